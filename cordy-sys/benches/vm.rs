@@ -30,10 +30,14 @@ fn bench_fib_for_range(c: &mut Criterion) { run("fn fib() for i in range()", "fn
 fn bench_fib_for_range_discard(c: &mut Criterion) { run("fn fib() for _ in range()", "fn fib(x) { let a = 1, b = 1 ; for _ in range(10) { a += b ; b = a - b } a } ; fib(10)", c) }
 fn bench_is_prime_any(c: &mut Criterion) { run("fn is_prime() with any()", "fn is_prime(n) -> range(2, 1 + sqrt(n)) . any(fn(p) -> n % p == 0) ; is_prime(53)", c); }
 fn bench_is_prime_for(c: &mut Criterion) { run("fn is_prime() with for", "fn is_prime(n) { for p in range(2, 1 + sqrt(n)) { if n % p == 0 { return true } } false } ; is_prime(53)", c); }
+fn bench_arithmetic_loop(c: &mut Criterion) { run("arithmetic in a while loop", "fn f(n) { let a = 1, b = 2, s = 0, i = 0 ; while i < n { s = s + a * b - i % 7 ; i += 1 } s } ; f(100)", c) }
+fn bench_arithmetic_nested_for(c: &mut Criterion) { run("arithmetic in a nested for loop", "fn f(n) { let s = 0 ; for i in range(n) { for j in range(n) { s += i * j + 1 } } s } ; f(12)", c) }
 fn bench_dict_default(c: &mut Criterion) { run("dict default()", "let d = dict().default(0) ; for c in 'hello the world this is mister skizzleface' { d[c] += 1 }", c) }
 fn bench_dict_manual_default(c: &mut Criterion) { run("dict manual default", "let d = dict() ; for c in 'hello the world this is mister skizzleface' { if c not in d { d[c] = 0 } d[c] += 1 }", c) }
 fn bench_dict_fn_default(c: &mut Criterion) { run("dict default(fn)", "let d = dict().default(list) ; for i, c in 'hello the world this is mister skizzleface'.enumerate { d[c] . push(i) }", c) }
 fn bench_dict_manual_fn_default(c: &mut Criterion) { run("dict manual default fn", "let d = dict() ; for i, c in 'hello the world this is mister skizzleface'.enumerate { if c not in d { d[c] = list() } d[c] . push(i) }", c) }
+fn bench_sum_tail_recursive_list(c: &mut Criterion) { run("sum tail recursive with list slices", "fn f(xs) -> if xs then xs[0] + f(xs[1:]) else 0 ; f(range(400).list)", c) }
+fn bench_sum_tail_recursive_view(c: &mut Criterion) { run("sum tail recursive with view slices", "fn f(xs) -> if xs then xs[0] + f(xs[1:]) else 0 ; f(range(400).view)", c) }
 
 
 criterion_group!(benches,
@@ -60,10 +64,14 @@ criterion_group!(benches,
     bench_fib_for_range_discard,
     bench_is_prime_any,
     bench_is_prime_for,
+    bench_arithmetic_loop,
+    bench_arithmetic_nested_for,
     bench_dict_default,
     bench_dict_manual_default,
     bench_dict_fn_default,
-    bench_dict_manual_fn_default
+    bench_dict_manual_fn_default,
+    bench_sum_tail_recursive_list,
+    bench_sum_tail_recursive_view
 );
 criterion_main!(benches);
 
@@ -76,9 +84,9 @@ fn run(name: &'static str, text: &'static str, criterion: &mut Criterion) {
     };
 
     // Run once initially and ensure that we don't error
-    let mut vm = VirtualMachine::new(compile.clone(), view, &b""[..], vec![], vec![]);
+    let mut vm = VirtualMachine::new(compile.clone(), view, &b""[..], vec![], vec![], vec![]);
     match vm.run_until_completion() {
-        ExitType::Exit => {},
+        ExitType::Exit(_) => {},
         ExitType::Error(e) => panic!("{}", vm.view().format(&e)),
         e => panic!("Abnormal exit: {:?}", e)
     };
@@ -87,7 +95,7 @@ fn run(name: &'static str, text: &'static str, criterion: &mut Criterion) {
     criterion.bench_function(name, |b| b.iter_batched(
         || {
             // For benchmarks, clone the compile result but use a new empty `SourceView`, since the runtime should not throw an error
-            VirtualMachine::new(compile.clone(), SourceView::empty(), &b""[..], vec![], vec![])
+            VirtualMachine::new(compile.clone(), SourceView::empty(), &b""[..], vec![], vec![], vec![])
         },
         |mut vm| {
             vm.run_until_completion()
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::time::Instant;
+
+use crate::vm::{ExitType, ValuePtr, VirtualMachine};
+
+
+/// The prefix of global functions which are run as benchmarks.
+pub const PREFIX: &str = "bench_";
+
+
+/// The timing statistics of a single benchmark, in nanoseconds per invocation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Benchmark {
+    pub name: String,
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+    pub samples: usize,
+}
+
+impl Benchmark {
+    fn new(name: String, mut samples: Vec<f64>) -> Benchmark {
+        samples.sort_by(f64::total_cmp);
+        let n: usize = samples.len();
+        let mean: f64 = samples.iter().sum::<f64>() / n as f64;
+        let median: f64 = match n % 2 {
+            0 => (samples[n / 2 - 1] + samples[n / 2]) / 2.0,
+            _ => samples[n / 2],
+        };
+        let stddev: f64 = match n {
+            1 => 0.0,
+            _ => (samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1) as f64).sqrt(),
+        };
+        Benchmark { name, mean, median, stddev, samples: n }
+    }
+}
+
+/// Saved benchmark results, used as a point of comparison, by the name of each benchmark.
+pub type Baseline = HashMap<String, Benchmark>;
+
+
+/// Runs the program in `vm`, which must be compiled with `compiler::compile_retaining_globals()`, and then each global function whose name starts with `bench_`, in the order they were declared.
+///
+/// Each function is invoked `warmup` times, which are not measured, and then `samples` times, each of which is timed.
+/// If the program, or any benchmark, raises an error then the formatted error is returned.
+pub fn run<R: BufRead, W: Write, E: Write>(vm: &mut VirtualMachine<R, W, E>, warmup: usize, samples: usize) -> Result<Vec<Benchmark>, String> {
+    let exit: ExitType = vm.run_until_completion();
+    check(vm, exit)?;
+
+    let functions: Vec<(String, ValuePtr)> = vm.globals()
+        .filter(|(name, value)| name.starts_with(PREFIX) && value.is_evaluable())
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect();
+
+    let mut benchmarks: Vec<Benchmark> = Vec::with_capacity(functions.len());
+    for (name, f) in functions {
+        for _ in 0..warmup {
+            let exit: ExitType = vm.run_function(f.clone());
+            check(vm, exit)?;
+        }
+        let mut times: Vec<f64> = Vec::with_capacity(samples.max(1));
+        for _ in 0..samples.max(1) {
+            let start: Instant = Instant::now();
+            let exit: ExitType = vm.run_function(f.clone());
+            let elapsed: f64 = start.elapsed().as_nanos() as f64;
+            check(vm, exit)?;
+            times.push(elapsed);
+        }
+        benchmarks.push(Benchmark::new(name, times));
+    }
+    Ok(benchmarks)
+}
+
+/// Returns a report of each benchmark, one per line. If a `baseline` is given, each benchmark which is present in the baseline is compared against it by its mean.
+pub fn report(benchmarks: &[Benchmark], baseline: Option<&Baseline>) -> Vec<String> {
+    let width: usize = benchmarks.iter().map(|b| b.name.len()).max().unwrap_or(0);
+    benchmarks.iter()
+        .map(|b| {
+            let mut line: String = format!("{:<width$}  mean {:>10}  median {:>10}  stddev {:>10}  ({} samples)", b.name, format_nanos(b.mean), format_nanos(b.median), format_nanos(b.stddev), b.samples, width = width);
+            if let Some(base) = baseline.and_then(|it| it.get(&b.name)) {
+                line.push_str(format!("  {:+.1}% vs. baseline", (b.mean - base.mean) / base.mean * 100.0).as_str());
+            }
+            line
+        })
+        .collect()
+}
+
+/// Serializes `benchmarks` to JSON, which can be read back with `parse_baseline()`.
+pub fn to_json(benchmarks: &[Benchmark]) -> String {
+    let entries: Vec<String> = benchmarks.iter()
+        .map(|b| format!("  \"{}\": {{\"mean\": {:.1}, \"median\": {:.1}, \"stddev\": {:.1}, \"samples\": {}}}", b.name, b.mean, b.median, b.stddev, b.samples))
+        .collect();
+    format!("{{\n{}\n}}\n", entries.join(",\n"))
+}
+
+/// Parses a baseline previously written by `to_json()`.
+pub fn parse_baseline(text: &str) -> Result<Baseline, String> {
+    let mut parser: Parser = Parser { chars: text.chars().collect(), pos: 0 };
+    let mut baseline: Baseline = Baseline::new();
+    for (name, value) in parser.parse_object()? {
+        let fields: Vec<(String, Json)> = match value {
+            Json::Object(it) => it,
+            _ => return Err(format!("expected an object for benchmark '{}'", name)),
+        };
+        let get = |key: &str| fields.iter()
+            .find_map(|(k, v)| match v {
+                Json::Number(n) if k == key => Some(*n),
+                _ => None,
+            })
+            .ok_or_else(|| format!("expected a number '{}' for benchmark '{}'", key, name));
+        let benchmark: Benchmark = Benchmark { name: name.clone(), mean: get("mean")?, median: get("median")?, stddev: get("stddev")?, samples: get("samples")? as usize };
+        baseline.insert(name, benchmark);
+    }
+    parser.skip_whitespace();
+    match parser.pos == parser.chars.len() {
+        true => Ok(baseline),
+        false => Err(parser.unexpected()),
+    }
+}
+
+/// Formats a duration in nanoseconds with the most appropriate unit, i.e. `1.234 ms`
+fn format_nanos(nanos: f64) -> String {
+    match nanos {
+        n if n < 1e3 => format!("{:.0} ns", n),
+        n if n < 1e6 => format!("{:.3} µs", n / 1e3),
+        n if n < 1e9 => format!("{:.3} ms", n / 1e6),
+        n => format!("{:.3} s", n / 1e9),
+    }
+}
+
+fn check<R: BufRead, W: Write, E: Write>(vm: &VirtualMachine<R, W, E>, exit: ExitType) -> Result<(), String> {
+    match exit {
+        ExitType::Error(error) => Err(vm.view().format(&error)),
+        ExitType::Exit(code) if code != 0 => Err(format!("Exited with code {}", code)),
+        _ => Ok(()),
+    }
+}
+
+
+/// The subset of JSON used by baseline files: objects, and numbers.
+enum Json {
+    Object(Vec<(String, Json)>),
+    Number(f64),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => Ok(Json::Object(self.parse_object()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start: usize = self.pos;
+                while matches!(self.peek(), Some(c) if c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' || c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                text.parse::<f64>().map(Json::Number).map_err(|_| format!("invalid number '{}'", text))
+            },
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Vec<(String, Json)>, String> {
+        self.expect('{')?;
+        let mut entries: Vec<(String, Json)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(entries)
+        }
+        loop {
+            self.skip_whitespace();
+            let key: String = self.parse_str()?;
+            self.expect(':')?;
+            entries.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(entries)
+                },
+                _ => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    fn parse_str(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text: String = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(text)
+                },
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c @ ('"' | '\\' | '/')) => text.push(c),
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        _ => return Err(self.unexpected()),
+                    }
+                    self.pos += 1;
+                },
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                },
+                None => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() == Some(c) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            },
+            false => Err(self.unexpected()),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn unexpected(&self) -> String {
+        match self.peek() {
+            Some(c) => format!("unexpected '{}' at offset {}", c, self.pos),
+            None => String::from("unexpected end of input"),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{bench, compiler, SourceView};
+    use crate::bench::Benchmark;
+    use crate::vm::VirtualMachine;
+
+    #[test]
+    fn test_run() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("let n = 0 ; fn bench_a() { n += 1 } ; fn helper() {} ; fn bench_b() -> helper() ; print('setup')"));
+        let compile = compiler::compile_retaining_globals(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]);
+
+        let benchmarks: Vec<Benchmark> = bench::run(&mut vm, 2, 3).unwrap();
+        drop(vm);
+
+        assert_eq!(benchmarks.iter().map(|b| (b.name.as_str(), b.samples)).collect::<Vec<(&str, usize)>>(), vec![("bench_a", 3), ("bench_b", 3)]);
+        assert_eq!(String::from_utf8(buf).unwrap().as_str(), "setup\n");
+    }
+
+    #[test]
+    fn test_run_error() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("let z = 0 ; fn bench_a() -> 1 / z"));
+        let compile = compiler::compile_retaining_globals(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]);
+
+        assert_eq!(bench::run(&mut vm, 1, 1), Err(String::from("ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn bench_a()` (line 1)\n\n1 | let z = 0 ; fn bench_a() -> 1 / z\n2 |                               ^\n")));
+    }
+
+    #[test]
+    fn test_statistics() {
+        let b: Benchmark = Benchmark::new(String::from("bench_a"), vec![4.0, 1.0, 3.0, 2.0]);
+        assert_eq!((b.mean, b.median, b.samples), (2.5, 2.5, 4));
+        assert!((b.stddev - 1.2909944).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_report() {
+        let benchmarks: Vec<Benchmark> = vec![
+            Benchmark { name: String::from("bench_a"), mean: 1500.0, median: 1400.0, stddev: 25.0, samples: 10 },
+            Benchmark { name: String::from("bench_long"), mean: 2.5e9, median: 2.5e6, stddev: 0.0, samples: 1 },
+        ];
+        let baseline = bench::parse_baseline("{\"bench_a\": {\"mean\": 1200.0, \"median\": 0, \"stddev\": 0, \"samples\": 5}}").unwrap();
+        assert_eq!(bench::report(&benchmarks, Some(&baseline)), vec![
+            "bench_a     mean   1.500 µs  median   1.400 µs  stddev      25 ns  (10 samples)  +25.0% vs. baseline",
+            "bench_long  mean    2.500 s  median   2.500 ms  stddev       0 ns  (1 samples)",
+        ]);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let benchmarks: Vec<Benchmark> = vec![
+            Benchmark { name: String::from("bench_a"), mean: 1500.0, median: 1400.0, stddev: 25.5, samples: 10 },
+            Benchmark { name: String::from("bench_b"), mean: 3.0, median: 2.0, stddev: 1.0, samples: 3 },
+        ];
+        let json: String = bench::to_json(&benchmarks);
+        assert_eq!(json.as_str(), "{\n  \"bench_a\": {\"mean\": 1500.0, \"median\": 1400.0, \"stddev\": 25.5, \"samples\": 10},\n  \"bench_b\": {\"mean\": 3.0, \"median\": 2.0, \"stddev\": 1.0, \"samples\": 3}\n}\n");
+
+        let baseline = bench::parse_baseline(json.as_str()).unwrap();
+        assert_eq!(baseline.get("bench_a"), Some(&benchmarks[0]));
+        assert_eq!(baseline.get("bench_b"), Some(&benchmarks[1]));
+    }
+
+    #[test] fn test_parse_baseline_empty() { assert_eq!(bench::parse_baseline(" {} ").map(|it| it.len()), Ok(0)); }
+    #[test] fn test_parse_baseline_missing_field() { assert_eq!(bench::parse_baseline("{\"bench_a\": {\"mean\": 1}}").err(), Some(String::from("expected a number 'median' for benchmark 'bench_a'"))); }
+    #[test] fn test_parse_baseline_trailing() { assert_eq!(bench::parse_baseline("{} x").err(), Some(String::from("unexpected 'x' at offset 3"))); }
+    #[test] fn test_parse_baseline_truncated() { assert_eq!(bench::parse_baseline("{\"bench_a\": ").err(), Some(String::from("unexpected end of input"))); }
+}
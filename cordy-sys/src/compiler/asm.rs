@@ -0,0 +1,626 @@
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::str::Chars;
+
+use crate::compiler::{CompileResult, Fields};
+use crate::core::{NativeFunction, Pattern};
+use crate::reporting::{Location, SourceView};
+use crate::vm::{C64, FunctionImpl, IntoValue, LiteralType, Opcode, StoreOp, StructKind, StructTypeImpl, Type, ValuePtr};
+use crate::vm::operator::{BinaryOp, UnaryOp};
+
+use Opcode::{*};
+
+
+const UNARY_OPS: [UnaryOp; 2] = [UnaryOp::Neg, UnaryOp::Not];
+const BINARY_OPS: [BinaryOp; 23] = [BinaryOp::Mul, BinaryOp::Div, BinaryOp::Pow, BinaryOp::Mod, BinaryOp::Is, BinaryOp::IsNot, BinaryOp::Add, BinaryOp::Sub, BinaryOp::LeftShift, BinaryOp::RightShift, BinaryOp::And, BinaryOp::Or, BinaryOp::Xor, BinaryOp::In, BinaryOp::NotIn, BinaryOp::LessThan, BinaryOp::GreaterThan, BinaryOp::LessThanEqual, BinaryOp::GreaterThanEqual, BinaryOp::Equal, BinaryOp::NotEqual, BinaryOp::Max, BinaryOp::Min];
+const LITERAL_TYPES: [LiteralType; 4] = [LiteralType::List, LiteralType::Vector, LiteralType::Set, LiteralType::Dict];
+
+
+impl CompileResult {
+
+    /// Returns the assembly form of this compiled program, which can be read back with `compiler::assemble()` to produce an equivalent program.
+    ///
+    /// Each line is either a directive, which declares an entry in one of the tables (`.constant`, `.global`, `.field`, `.property`, `.member`, or `.pattern`), or an instruction,
+    /// with an optional leading index. Anything following a `;` is a comment.
+    pub fn assembly(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for (id, constant) in self.constants.iter().enumerate() {
+            lines.push(format!(".constant {} ; {}", assemble_constant(constant), id));
+        }
+        for global in &self.globals {
+            lines.push(format!(".global {}", quote(global)));
+        }
+        for (field_index, name) in self.fields.get_field_names().into_iter().enumerate() {
+            for (type_index, offset) in self.fields.get_field_offsets(field_index as u32) {
+                lines.push(format!(".field {} {} {} ; {}", quote(name), type_index, offset, field_index));
+            }
+            for (type_index, getter, setter) in self.fields.get_properties(field_index as u32) {
+                lines.push(format!(".property {} {} {} {} ; {}", quote(name), type_index, getter, setter.map(|setter| setter.to_string()).unwrap_or_else(|| String::from("Nil")), field_index));
+            }
+            for (type_index, offset) in self.fields.get_member_offsets(field_index as u32) {
+                lines.push(format!(".member {} {} {} ; {}", quote(name), type_index, offset, field_index));
+            }
+        }
+        for (id, pattern) in self.patterns.iter().enumerate() {
+            lines.push(format!(".pattern {} ; {}", pattern.assembly(), id));
+        }
+        for (ip, opcode) in self.code.iter().enumerate() {
+            lines.push(format!("{:0>4} {:?}", ip, opcode));
+        }
+        lines
+    }
+}
+
+fn assemble_constant(constant: &ValuePtr) -> String {
+    match constant.ty() {
+        Type::Nil => String::from("Nil"),
+        Type::Bool => String::from(if constant.is_true() { "True" } else { "False" }),
+        Type::Int => format!("Int({})", constant.as_int()),
+        Type::Complex => {
+            let it = constant.as_precise_complex_ref().inner;
+            format!("Complex({}, {})", it.re, it.im)
+        },
+        Type::Str => format!("Str({})", quote(constant.as_str().borrow_const())),
+        Type::Function => {
+            let it = constant.as_function().borrow_const();
+            format!("{}({}, {}, {}, [{}], [{}], {}{})",
+                if it.is_async() { "AsyncFunction" } else { "Function" },
+                quote(it.name()),
+                it.head, it.tail,
+                it.args().iter().map(|arg| quote(arg)).collect::<Vec<String>>().join(", "),
+                it.default_args().iter().map(|offset| offset.to_string()).collect::<Vec<String>>().join(", "),
+                it.is_var_arg(),
+                it.doc().map(|doc| format!(", {}", quote(doc))).unwrap_or_default())
+        },
+        Type::StructType => {
+            let it = constant.as_struct_type().borrow_const();
+            format!("StructType({}, [{}], {}, [{}], {}, [{}])", quote(&it.name), it.field_names.iter().map(|field| quote(field)).collect::<Vec<String>>().join(", "), it.type_index, it.parents.iter().map(|parent| parent.to_string()).collect::<Vec<String>>().join(", "), match it.kind {
+                StructKind::Struct => String::from("Struct"),
+                StructKind::Enum => String::from("Enum"),
+                StructKind::Variant(discriminant) => format!("Variant({})", discriminant),
+            }, it.member_names.iter().map(|member| quote(member)).collect::<Vec<String>>().join(", "))
+        },
+        _ => panic!("Not a constant: {:?}", constant),
+    }
+}
+
+/// Quotes a string in the form used by assembly, with `\`, `'`, and any control characters escaped.
+fn quote(text: &str) -> String {
+    let mut quoted: String = String::from("'");
+    for c in text.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+
+/// Reads a program in the assembly form produced by `CompileResult::assembly()`, from the active entry in `view`.
+///
+/// The assembled program is checked with `CompileResult::verify()`, so any out of range index is reported as an error. Other invalid programs (for instance, ones that
+/// pop from an empty stack) are not detected, and may behave unpredictably, much like hand-written bytecode in any other VM.
+/// Each instruction takes the location of its line in `view`, so runtime errors report the line of assembly which raised them.
+pub fn assemble(view: &SourceView) -> Result<CompileResult, Vec<String>> {
+    let mut result: CompileResult = CompileResult {
+        code: Vec::new(),
+        errors: Vec::new(),
+        constants: Vec::new(),
+        patterns: Vec::new(),
+        globals: Vec::new(),
+        locations: Vec::new(),
+        fields: Fields::new(),
+        locals: Vec::new(),
+    };
+    let mut errors: Vec<String> = Vec::new();
+    let mut start: usize = 0;
+    let mut lines: Vec<(usize, &str)> = Vec::new(); // The line each instruction was assembled from
+    let error = |e: String, lineno: usize, line: &str| format!("{}\n  at: line {} ({})\n\n{} | {}", e, lineno + 1, view.name(), lineno + 1, line.trim_end());
+
+    for (lineno, line) in view.text().split('\n').enumerate() {
+        let width: usize = line.chars().count();
+        let loc: Location = Location::new(start, width as u32, view.index());
+        start += width + 1;
+
+        match assemble_line(&mut result, line, loc) {
+            Ok(()) => lines.resize(result.code.len(), (lineno, line)),
+            Err(e) => errors.push(error(e, lineno, line)),
+        }
+    }
+
+    // The assembled program is verified once all instructions are known, so the VM can trust that every index is valid
+    if errors.is_empty() {
+        if let Err(e) = result.verify() {
+            errors.extend(e.into_iter().map(|(ip, e)| {
+                let (lineno, line) = lines[ip];
+                error(e, lineno, line)
+            }));
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(result),
+        false => Err(errors),
+    }
+}
+
+fn assemble_line(result: &mut CompileResult, line: &str, loc: Location) -> Result<(), String> {
+    let mut input: Input = Input { chars: line.chars().peekable() };
+    input.skip_whitespace();
+    match input.peek() {
+        None => return Ok(()), // Empty line, or just a comment
+        Some('.') => {
+            input.next();
+            let directive: String = input.name()?;
+            match directive.as_str() {
+                "constant" => {
+                    let term: Term = input.term()?;
+                    result.constants.push(constant(&term)?);
+                },
+                "global" => {
+                    let name: String = input.term()?.as_str()?;
+                    result.globals.push(name);
+                },
+                "field" => {
+                    let name: String = input.term()?.as_str()?;
+                    let type_index: u32 = input.term()?.as_int()? as u32;
+                    let offset: usize = input.term()?.as_int()? as usize;
+                    result.fields.declare_field(type_index, offset, name);
+                },
+                "property" => {
+                    let name: String = input.term()?.as_str()?;
+                    let type_index: u32 = input.term()?.as_int()? as u32;
+                    let getter: usize = input.term()?.as_int()? as usize;
+                    let setter: Option<usize> = match input.term()? {
+                        Term::Int(setter) => Some(setter as usize),
+                        term => match term.as_apply()? {
+                            ("Nil", []) => None,
+                            _ => return Err(format!("Invalid setter '{}'", term)),
+                        },
+                    };
+                    result.fields.declare_property(type_index, getter, setter, name);
+                },
+                "member" => {
+                    let name: String = input.term()?.as_str()?;
+                    let type_index: u32 = input.term()?.as_int()? as u32;
+                    let offset: usize = input.term()?.as_int()? as usize;
+                    result.fields.declare_member(type_index, offset, name);
+                },
+                "pattern" => {
+                    let term: Term = input.term()?;
+                    result.patterns.push(Rc::new(pattern(&term)?));
+                },
+                _ => return Err(format!("Unknown directive '.{}'", directive)),
+            }
+        },
+        Some(_) => {
+            let mut term: Term = input.term()?;
+            if let Term::Int(_) = term { // Skip the optional leading index
+                term = input.term()?;
+            }
+            result.code.push(opcode(&term)?);
+            result.locations.push(loc);
+        },
+    }
+    input.end()
+}
+
+fn constant(term: &Term) -> Result<ValuePtr, String> {
+    Ok(match term.as_apply()? {
+        ("Nil", []) => ValuePtr::nil(),
+        ("True", []) => true.to_value(),
+        ("False", []) => false.to_value(),
+        ("Int", [it]) => it.as_int()?.to_value(),
+        ("Complex", [re, im]) => C64::new(re.as_int()?, im.as_int()?).to_value(),
+        ("Str", [it]) => it.as_str()?.to_value(),
+        (tag @ ("Function" | "AsyncFunction"), [name, head, tail, args, default_args, var_arg, doc @ ..]) if doc.len() <= 1 => FunctionImpl::new(
+            head.as_int()? as usize,
+            tail.as_int()? as usize,
+            name.as_str()?,
+            args.as_list()?.iter().map(|arg| arg.as_str()).collect::<Result<Vec<String>, String>>()?,
+            default_args.as_list()?.iter().map(|offset| offset.as_int().map(|u| u as usize)).collect::<Result<Vec<usize>, String>>()?,
+            var_arg.as_bool()?,
+            tag == "AsyncFunction",
+        ).with_doc(match doc {
+            [doc] => Some(doc.as_str()?),
+            _ => None,
+        }).to_value(),
+        ("StructType", [name, fields, type_index, parents, kind, members]) => StructTypeImpl::new(
+            name.as_str()?,
+            fields.as_list()?.iter().map(|field| field.as_str()).collect::<Result<Vec<String>, String>>()?,
+            type_index.as_int()? as u32,
+            parents.as_list()?.iter().map(|parent| parent.as_int().map(|u| u as u32)).collect::<Result<Vec<u32>, String>>()?,
+            match kind.as_apply()? {
+                ("Struct", []) => StructKind::Struct,
+                ("Enum", []) => StructKind::Enum,
+                ("Variant", [discriminant]) => StructKind::Variant(discriminant.as_int()? as u32),
+                _ => return Err(format!("Invalid struct kind '{}'", kind)),
+            },
+            members.as_list()?.iter().map(|member| member.as_str()).collect::<Result<Vec<String>, String>>()?,
+        ).to_value(),
+        _ => return Err(format!("Invalid constant '{}'", term)),
+    })
+}
+
+fn pattern(term: &Term) -> Result<Pattern, String> {
+    match term.as_apply()? {
+        ("Pattern", [len, variadic, terms]) => {
+            let mut pattern: Pattern = Pattern::new(len.as_int()? as usize, variadic.as_bool()?);
+            for term in terms.as_list()? {
+                match term.as_apply()? {
+                    ("Index", [index, op]) => pattern.push_index(index.as_int()?, store_op(op)?),
+                    ("Slice", [low, high, op]) => pattern.push_slice(low.as_int()?, high.as_int()?, store_op(op)?),
+                    ("Pattern", [index, next]) => pattern.push_pattern(index.as_int()?, self::pattern(next)?),
+                    _ => return Err(format!("Invalid pattern term '{}'", term)),
+                }
+            }
+            Ok(pattern)
+        },
+        _ => Err(format!("Invalid pattern '{}'", term)),
+    }
+}
+
+fn store_op(term: &Term) -> Result<StoreOp, String> {
+    match term.as_apply()? {
+        ("Local", [index]) => Ok(StoreOp::Local(index.as_int()? as u32)),
+        ("Global", [index]) => Ok(StoreOp::Global(index.as_int()? as u32)),
+        ("UpValue", [index]) => Ok(StoreOp::UpValue(index.as_int()? as u32)),
+        _ => Err(format!("Invalid store '{}'", term)),
+    }
+}
+
+fn opcode(term: &Term) -> Result<Opcode, String> {
+    let (name, args) = term.as_apply()?;
+    let int = |i: usize| -> Result<i64, String> { args[i].as_int() };
+    let index = |i: usize| -> Result<u32, String> { args[i].as_int().map(|u| u as u32) };
+
+    Ok(match (name, args.len()) {
+        ("Noop", 0) => Noop,
+        ("JumpIfFalse", 1) => JumpIfFalse(index(0)?),
+        ("JumpIfFalsePop", 1) => JumpIfFalsePop(index(0)?),
+        ("JumpIfTrue", 1) => JumpIfTrue(index(0)?),
+        ("JumpIfTruePop", 1) => JumpIfTruePop(index(0)?),
+        ("Jump", 1) => Jump(index(0)?),
+        ("Return", 0) => Return,
+        ("Pop", 0) => Pop,
+        ("PopN", 1) => PopN(index(0)?),
+        ("Swap", 0) => Swap,
+        ("PushLocal", 1) => PushLocal(index(0)?),
+        ("StoreLocal", 2) => StoreLocal(index(0)?, args[1].as_bool()?),
+        ("PushGlobal", 1) => PushGlobal(index(0)?),
+        ("StoreGlobal", 2) => StoreGlobal(index(0)?, args[1].as_bool()?),
+        ("PushUpValue", 1) => PushUpValue(index(0)?),
+        ("StoreUpValue", 1) => StoreUpValue(index(0)?),
+        ("StoreArray", 0) => StoreArray,
+        ("InitGlobal", 0) => InitGlobal,
+        ("Closure", 0) => Closure,
+        ("CloseLocal", 1) => CloseLocal(index(0)?),
+        ("CloseUpValue", 1) => CloseUpValue(index(0)?),
+        ("CloseValue", 0) => CloseValue,
+        ("LiftUpValue", 1) => LiftUpValue(index(0)?),
+        ("InitIterable", 0) => InitIterable,
+        ("TestIterable", 1) => TestIterable(index(0)?),
+        ("ExecPattern", 1) => ExecPattern(index(0)?),
+        ("Nil", 0) => Nil,
+        ("True", 0) => True,
+        ("False", 0) => False,
+        ("Constant", 1) => Constant(index(0)?),
+        ("NativeFunction", 1) => NativeFunction(find(NativeFunction::all(), args[0].as_name()?)?),
+        ("LiteralBegin", 2) => LiteralBegin(find(LITERAL_TYPES.into_iter(), args[0].as_name()?)?, index(1)?),
+        ("LiteralAcc", 1) => LiteralAcc(index(0)?),
+        ("LiteralUnroll", 0) => LiteralUnroll,
+        ("LiteralEnd", 0) => LiteralEnd,
+        ("Slice", 0) => Slice,
+        ("SliceWithStep", 0) => SliceWithStep,
+        ("Call", 2) => Call(int(0)? as u32, args[1].as_bool()?),
+        ("Unroll", 1) => Unroll(args[0].as_bool()?),
+        ("OpIndex", 0) => OpIndex,
+        ("OpIndexPeek", 0) => OpIndexPeek,
+        ("OpSlice", 0) => OpSlice,
+        ("OpSliceWithStep", 0) => OpSliceWithStep,
+        ("GetField", 1) => GetField(index(0)?),
+        ("GetFieldPeek", 1) => GetFieldPeek(index(0)?),
+        ("GetFieldFunction", 1) => GetFieldFunction(index(0)?),
+        ("SetField", 1) => SetField(index(0)?),
+        ("InitMember", 1) => InitMember(index(0)?),
+        ("InheritMembers", 0) => InheritMembers,
+        ("EnterContext", 0) => EnterContext,
+        ("ExitContext", 0) => ExitContext,
+        ("Await", 0) => Await,
+        ("Unary", 1) => Unary(find(UNARY_OPS.into_iter(), args[0].as_name()?)?),
+        ("Binary", 1) => Binary(find(BINARY_OPS.into_iter(), args[0].as_name()?)?),
+        ("BinaryLocal", 2) => BinaryLocal(find(BINARY_OPS.into_iter(), args[0].as_name()?)?, index(1)?),
+        ("BinaryConst", 2) => BinaryConst(find(BINARY_OPS.into_iter(), args[0].as_name()?)?, index(1)?),
+        ("Exit", 0) => Exit,
+        ("ExitWith", 0) => ExitWith,
+        ("ExitValue", 0) => ExitValue,
+        ("Yield", 0) => Yield,
+        ("AssertFailed", 0) => AssertFailed,
+        _ => return Err(format!("Invalid instruction '{}'", term)),
+    })
+}
+
+/// Finds the variant of an enum with the given name, which is the same as its `Debug` representation
+fn find<T : std::fmt::Debug>(mut variants: impl Iterator<Item=T>, name: &str) -> Result<T, String> {
+    variants.find(|variant| format!("{:?}", variant) == name)
+        .ok_or_else(|| format!("Unknown name '{}'", name))
+}
+
+
+/// A single term of assembly, which is either an integer, string, list, or a name with optional arguments, i.e. `Call(1, false)`
+#[derive(Debug)]
+enum Term {
+    Int(i64),
+    Str(String),
+    List(Vec<Term>),
+    Apply(String, Vec<Term>),
+}
+
+impl Term {
+    fn as_int(&self) -> Result<i64, String> {
+        match self {
+            Term::Int(it) => Ok(*it),
+            _ => Err(format!("Expected an integer, got '{}'", self)),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, String> {
+        match self.as_apply()? {
+            ("true", []) => Ok(true),
+            ("false", []) => Ok(false),
+            _ => Err(format!("Expected 'true' or 'false', got '{}'", self)),
+        }
+    }
+
+    fn as_str(&self) -> Result<String, String> {
+        match self {
+            Term::Str(it) => Ok(it.clone()),
+            _ => Err(format!("Expected a string, got '{}'", self)),
+        }
+    }
+
+    fn as_list(&self) -> Result<&[Term], String> {
+        match self {
+            Term::List(it) => Ok(it),
+            _ => Err(format!("Expected a list, got '{}'", self)),
+        }
+    }
+
+    fn as_name(&self) -> Result<&str, String> {
+        match self.as_apply()? {
+            (name, []) => Ok(name),
+            _ => Err(format!("Expected a name, got '{}'", self)),
+        }
+    }
+
+    fn as_apply(&self) -> Result<(&str, &[Term]), String> {
+        match self {
+            Term::Apply(name, args) => Ok((name.as_str(), args.as_slice())),
+            _ => Err(format!("Expected a name, got '{}'", self)),
+        }
+    }
+}
+
+impl std::fmt::Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |terms: &[Term]| terms.iter().map(|term| term.to_string()).collect::<Vec<String>>().join(", ");
+        match self {
+            Term::Int(it) => write!(f, "{}", it),
+            Term::Str(it) => write!(f, "{}", quote(it)),
+            Term::List(it) => write!(f, "[{}]", join(it)),
+            Term::Apply(name, args) if args.is_empty() => write!(f, "{}", name),
+            Term::Apply(name, args) => write!(f, "{}({})", name, join(args)),
+        }
+    }
+}
+
+
+struct Input<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Input<'a> {
+
+    fn term(&mut self) -> Result<Term, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('\'') => {
+                self.next();
+                self.str().map(Term::Str)
+            },
+            Some('[') => {
+                self.next();
+                self.list(']').map(Term::List)
+            },
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut text: String = String::new();
+                while let Some(c) = self.peek().filter(|c| *c == '-' || c.is_ascii_digit()) {
+                    text.push(c);
+                    self.next();
+                }
+                text.parse::<i64>().map(Term::Int).map_err(|_| format!("Invalid integer '{}'", text))
+            },
+            Some(_) => {
+                let name: String = self.name()?;
+                match self.peek() {
+                    Some('(') => {
+                        self.next();
+                        Ok(Term::Apply(name, self.list(')')?))
+                    },
+                    _ => Ok(Term::Apply(name, Vec::new())),
+                }
+            },
+            None => Err(String::from("Unexpected end of line")),
+        }
+    }
+
+    /// Parses a sequence of `,` separated terms, up to and including the closing character
+    fn list(&mut self, close: char) -> Result<Vec<Term>, String> {
+        let mut terms: Vec<Term> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(close) {
+                self.next();
+                return Ok(terms)
+            }
+            if !terms.is_empty() {
+                self.expect(',')?;
+            }
+            terms.push(self.term()?);
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let mut name: String = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+            name.push(c);
+            self.next();
+        }
+        match name.is_empty() {
+            true => Err(format!("Unexpected '{}'", self.peek().unwrap_or(' '))),
+            false => Ok(name),
+        }
+    }
+
+    /// Parses the rest of a string, after the opening `'`
+    fn str(&mut self) -> Result<String, String> {
+        let mut text: String = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(text),
+                Some('\\') => match self.next() {
+                    Some('n') => text.push('\n'),
+                    Some('r') => text.push('\r'),
+                    Some('t') => text.push('\t'),
+                    Some('u') => {
+                        self.expect('{')?;
+                        let mut hex: String = String::new();
+                        while let Some(c) = self.next().filter(|c| *c != '}') {
+                            hex.push(c);
+                        }
+                        text.push(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or_else(|| format!("Invalid escape '\\u{{{}}}'", hex))?);
+                    },
+                    Some(c @ ('\\' | '\'')) => text.push(c),
+                    c => return Err(format!("Invalid escape '\\{}'", c.unwrap_or(' '))),
+                },
+                Some(c) => text.push(c),
+                None => return Err(String::from("Unterminated string")),
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("Expected '{}', got '{}'", expected, c)),
+            None => Err(format!("Expected '{}', got end of line", expected)),
+        }
+    }
+
+    /// Checks that there is nothing left on the line, except for a comment
+    fn end(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(()),
+            Some(c) => Err(format!("Unexpected '{}'", c)),
+        }
+    }
+
+    /// Skips whitespace. A comment skips the remainder of the line.
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ';' => while self.next().is_some() {},
+                c if c.is_whitespace() => { self.next(); },
+                _ => break,
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> { self.chars.peek().copied() }
+    fn next(&mut self) -> Option<char> { self.chars.next() }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{compiler, SourceView};
+    use crate::compiler::CompileResult;
+    use crate::vm::{ExitType, VirtualMachine};
+
+    #[test] fn test_round_trip_expression() { run_round_trip("print(1 + 2 * 3, 'hello' + '\\n\\'world\\'', -(1 + 2j))") }
+    #[test] fn test_round_trip_function() { run_round_trip("fn f(a, b = 3, *c) -> [a, b, c]\nprint(f(1), f(1, 2), f(1, 2, 3, 4))") }
+    #[test] fn test_round_trip_closure() { run_round_trip("fn counter() { let x = 0 ; fn() -> x += 1 }\nlet c = counter()\nc() ; c()\nprint(c())") }
+    #[test] fn test_round_trip_closure_capture_by_value() { run_round_trip("let x = 1\nlet f = fn [x] () { x += 1 ; x }\nx = 5\nprint(f(), f(), x)") }
+    #[test] fn test_round_trip_struct() { run_round_trip("struct Point(x, y)\nstruct Other(y)\nlet p = Point(1, 2)\np->y += Other(3)->y\nprint(p, p->y)") }
+    #[test] fn test_round_trip_struct_inheritance() { run_round_trip("struct A(x)\nstruct B(y) : A\nlet b = B(1, 2)\nprint(b, b->x, b is A)") }
+    #[test] fn test_round_trip_struct_inheritance_members() { run_round_trip("struct A(x) { fn __enter__(self) -> self->x ; fn __exit__(self) -> print('exit A') }\nstruct B(y) : A { fn __exit__(self) -> print('exit B') }\nwith B(1, 2) as x { print(x) }") }
+    #[test] fn test_round_trip_enum() { run_round_trip("enum Color { Red, Rgb(r, g, b) }\nlet c = Rgb(1, 2, 3)\nprint(c, c->g, c is Color, int(Red()))") }
+    #[test] fn test_round_trip_struct_members() { run_round_trip("struct P(x, y) { const ORIGIN = P(0, 0) ; fn of(n) -> P(n, n) }\nprint(P->ORIGIN, P->of(3), P)") }
+    #[test] fn test_round_trip_struct_properties() { run_round_trip("struct A(x) { get y(self) -> self->x + 1 ; set y(self, value) -> self->x = value - 1 ; get z(self) -> 0 }\nlet a = A(1)\na->y = 5\nprint(a, a->y, a->z)") }
+    #[test] fn test_round_trip_with() { run_round_trip("struct R(x) { fn __enter__(self) -> self->x ; fn __exit__(self) -> print('exit') }\nfn f() { with R(1) as x { return x } }\nwith R(2) as y { print(y, f()) }") }
+    #[test] fn test_round_trip_doc() { run_round_trip("/// Doc for 'f'\n/// second line\nfn f() {}\nfn g() {}\nprint(repr(doc(f)), doc(g))") }
+    #[test] fn test_round_trip_async() { run_round_trip("async fn f(x) { await sleep_async(0) ; x + 1 }\nprint(await f(1), f)") }
+    #[test] fn test_round_trip_pattern() { run_round_trip("let a, (b, *c), _ = [1, [2, 3, 4], 5]\nprint(a, b, c)") }
+    #[test] fn test_round_trip_loops() { run_round_trip("for x in range(3) { if x == 1 { continue } print(x) }\nlet i = 0\nwhile i < 3 { i += 1 } print(i)") }
+    #[test] fn test_round_trip_literals() { run_round_trip("print([1, ...[2, 3]], {1: 2}, {3}, (4, 5), [1, 2, 3][1:], 'abc'[::-1])") }
+    #[test] fn test_assemble_by_hand() { run_asm(".constant Str('hello')\n.constant Int(3)\nNativeFunction(Print)\nConstant(0)\nConstant(1)\nCall(2, false) ; print('hello', 3)\nPop\nExit", "hello 3\n") }
+    #[test] fn test_assemble_runtime_error() { run_asm("True\nNativeFunction(Print)\nBinary(Add)\nExit", "TypeError: Cannot add 'true' of type 'bool' and 'print' of type 'native function'\n  at: line 3 (<test>)\n\n3 | Binary(Add)\n4 | ^^^^^^^^^^^\n") }
+    #[test] fn test_assemble_errors() { run_err(".constant Foo(1)\n.bar\n0001 Call(1)\nBinary(Nope)\nPushLocal('x')\nPop Pop", "Invalid constant 'Foo(1)'\n  at: line 1 (<test>)\n\n1 | .constant Foo(1)\nUnknown directive '.bar'\n  at: line 2 (<test>)\n\n2 | .bar\nInvalid instruction 'Call(1)'\n  at: line 3 (<test>)\n\n3 | 0001 Call(1)\nUnknown name 'Nope'\n  at: line 4 (<test>)\n\n4 | Binary(Nope)\nExpected an integer, got ''x''\n  at: line 5 (<test>)\n\n5 | PushLocal('x')\nUnexpected 'P'\n  at: line 6 (<test>)\n\n6 | Pop Pop") }
+    #[test] fn test_assemble_jumps() { run_asm("True\nJumpIfFalsePop(4)\nNativeFunction(Print)\nCall(0, false)\nExit", "\n") }
+    #[test] fn test_assemble_jump_out_of_range() { run_err("Jump(3)\nJump(0)\nExit", "Jump target 3 is out of range, there are only 3 instructions\n  at: line 1 (<test>)\n\n1 | Jump(3)") }
+    #[test] fn test_assemble_constant_out_of_range() { run_err(".constant Int(1)\nConstant(0)\nConstant(1)\nExit", "Constant 1 is out of range, there are only 1 constants\n  at: line 3 (<test>)\n\n3 | Constant(1)") }
+    #[test] fn test_assemble_binary_const_out_of_range() { run_err("True\nBinaryConst(Add, 0)\nExit", "Constant 0 is out of range, there are only 0 constants\n  at: line 2 (<test>)\n\n2 | BinaryConst(Add, 0)") }
+    #[test] fn test_assemble_global_out_of_range() { run_err(".global 'x'\nNil\nStoreGlobal(1, true)\nPushGlobal(0)\nExit", "Global 1 is out of range, there are only 1 globals\n  at: line 3 (<test>)\n\n3 | StoreGlobal(1, true)") }
+    #[test] fn test_assemble_field_out_of_range() { run_err("Nil\nGetField(0)\nExit", "Field 0 is out of range, there are only 0 fields\n  at: line 2 (<test>)\n\n2 | GetField(0)") }
+    #[test] fn test_assemble_pattern_out_of_range() { run_err("Nil\nExecPattern(0)\nExit", "Pattern 0 is out of range, there are only 0 patterns\n  at: line 2 (<test>)\n\n2 | ExecPattern(0)") }
+    #[test] fn test_assemble_pattern_global_out_of_range() { run_err(".pattern Pattern(1, false, [Index(0, Global(0))])\nNil\nExecPattern(0)\nExit", "Global 0 is out of range, there are only 0 globals\n  at: line 3 (<test>)\n\n3 | ExecPattern(0)") }
+    #[test] fn test_assemble_function_out_of_range() { run_err(".constant Function('f', 1, 5, [], [], false)\nConstant(0)\nExit", "Function fn f() has an invalid range L[1, 5], there are only 2 instructions\n  at: line 2 (<test>)\n\n2 | Constant(0)") }
+    #[test] fn test_assemble_unterminated_string() { run_err(".constant Str('abc", "Unterminated string\n  at: line 1 (<test>)\n\n1 | .constant Str('abc") }
+
+    /// Compiles `text`, and checks that the assembly can be read back, producing the same assembly, and the same output when run
+    fn run_round_trip(text: &'static str) {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let compiled: CompileResult = compiler::compile(true, &view).expect("Failed to compile");
+        let assembly: String = compiled.assembly().join("\n");
+
+        let asm_view: SourceView = SourceView::new(String::from("<test>"), assembly.clone());
+        let assembled: CompileResult = compiler::assemble(&asm_view).expect("Failed to assemble");
+
+        assert_eq!(assembled.assembly().join("\n"), assembly);
+        assert_eq!(run(assembled, asm_view), run(compiled, view));
+    }
+
+    fn run_asm(text: &'static str, expected: &'static str) {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let assembled: CompileResult = compiler::assemble(&view).expect("Failed to assemble");
+
+        assert_eq!(run(assembled, view), expected);
+    }
+
+    fn run_err(text: &'static str, expected: &'static str) {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let errors: Vec<String> = compiler::assemble(&view).err().expect("Expected an error");
+
+        assert_eq!(errors.join("\n"), expected);
+    }
+
+    fn run(compiled: CompileResult, view: SourceView) -> String {
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compiled, view, &b""[..], &mut buf, Vec::new(), vec![]);
+        let output: String = match vm.run_until_completion() {
+            ExitType::Error(error) => vm.view().format(&error),
+            _ => String::new(),
+        };
+        drop(vm);
+        String::from_utf8(buf).unwrap() + output.as_str()
+    }
+}
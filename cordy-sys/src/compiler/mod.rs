@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::compiler::parser::ParseRule;
@@ -6,20 +7,39 @@ use crate::reporting::{Location, SourceView};
 use crate::vm::{AnyResult, Opcode, RuntimeError, ValuePtr};
 use crate::core::Pattern;
 
+pub use crate::compiler::asm::assemble;
 pub use crate::compiler::parser::{default, Fields, Locals, ParserError, ParserErrorType};
 pub use crate::compiler::scanner::{ScanError, ScanErrorType, ScanToken, ScanTokenType};
 
+mod asm;
+mod verify;
 mod scanner;
 mod parser;
 
 pub fn scan(view: &SourceView) -> Vec<(Location, ScanTokenType)> {
     scanner::scan(view).tokens
         .into_iter()
+        .filter(|(_, t)| !matches!(t, ScanToken::DocComment(_))) // Doc comments are highlighted as comments
         .map(|(loc, t)| (loc, t.ty()))
         .collect()
 }
 
 pub fn compile(enable_optimization: bool, view: &SourceView) -> Result<CompileResult, Vec<String>> {
+    compile_with_flags(enable_optimization, CompileFlags::default(), view)
+}
+
+/// Like `compile()`, except global variables are not popped when the program reaches the end, so they can be inspected with `VirtualMachine::globals()` after it has terminated.
+pub fn compile_retaining_globals(enable_optimization: bool, view: &SourceView) -> Result<CompileResult, Vec<String>> {
+    compile_with_flags(enable_optimization, CompileFlags { retain_globals: true, ..CompileFlags::default() }, view)
+}
+
+/// Like `compile()`, except the value of a trailing top level expression, or `nil` if there is none, is reported as `ExitType::Value` when the program reaches the end.
+pub fn compile_print_last(enable_optimization: bool, view: &SourceView) -> Result<CompileResult, Vec<String>> {
+    compile_with_flags(enable_optimization, CompileFlags { print_last: true, ..CompileFlags::default() }, view)
+}
+
+/// Like `compile()`, with the given `CompileFlags`.
+pub fn compile_with_flags(enable_optimization: bool, flags: CompileFlags, view: &SourceView) -> Result<CompileResult, Vec<String>> {
     let mut errors: Vec<String> = Vec::new();
 
     // Scan
@@ -32,7 +52,7 @@ pub fn compile(enable_optimization: bool, view: &SourceView) -> Result<CompileRe
     }
 
     // Parse
-    let compile_result: CompileResult = parser::parse(enable_optimization, scan_result);
+    let compile_result: CompileResult = parser::parse(enable_optimization, flags, scan_result);
     if !compile_result.errors.is_empty() {
         for error in &compile_result.errors {
             errors.push(view.format(error));
@@ -40,6 +60,15 @@ pub fn compile(enable_optimization: bool, view: &SourceView) -> Result<CompileRe
         return Err(errors);
     }
 
+    // Verify
+    // The parser should never emit an invalid index, so any errors here are a bug in the compiler, and not the program
+    if let Err(e) = compile_result.verify() {
+        for (ip, error) in e {
+            errors.push(format!("Internal Compiler Error: {} at instruction {}\n  at: {}", error, ip, view.name()));
+        }
+        return Err(errors);
+    }
+
     // Compilation Successful
     Ok(compile_result)
 }
@@ -47,10 +76,21 @@ pub fn compile(enable_optimization: bool, view: &SourceView) -> Result<CompileRe
 /// Performs an incremental compile, given the following input parameters.
 ///
 /// This is used for incremental REPL structure. The result will have a `Print` instead of a delayed pop (if needed), and end with a `Yield` instruction instead of `Exit`.
-pub fn incremental_compile(mut params: CompileParameters) -> IncrementalCompileResult {
+pub fn incremental_compile(params: CompileParameters) -> IncrementalCompileResult {
+    staged_incremental_compile(params, |parser| parser.parse_incremental_repl())
+}
+
+/// Performs an incremental compile, like `incremental_compile()`, except the value of a trailing expression is left on the stack instead of printed, or `nil` if there is none.
+///
+/// This is used by `Session`, which returns the value to the caller.
+pub fn incremental_compile_value(params: CompileParameters) -> IncrementalCompileResult {
+    staged_incremental_compile(params, |parser| parser.parse_incremental_value())
+}
+
+fn staged_incremental_compile(mut params: CompileParameters, rule: ParseRule) -> IncrementalCompileResult {
     // Stage changes, so an error or aborted compile doesn't overwrite the current valid compile state
     let state: CompileState = params.save();
-    let ret: IncrementalCompileResult = try_incremental_compile(&mut params, |parser| parser.parse_incremental_repl(), true);
+    let ret: IncrementalCompileResult = try_incremental_compile(&mut params, rule, true);
 
     if !ret.is_success() { // Revert staged changes
         params.restore(state);
@@ -175,6 +215,38 @@ impl<'a> CompileParameters<'a> {
 }
 
 
+/// Flags which change how a program is compiled, passed to `compile_with_flags()`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct CompileFlags {
+    /// If `true`, global variables are not popped when the program reaches the end. See `compile_retaining_globals()`.
+    pub retain_globals: bool,
+    /// If `true`, the value of a trailing top level expression is reported when the program reaches the end. See `compile_print_last()`.
+    pub print_last: bool,
+    /// If `true`, the variables declared by a `for` loop are bound once per iteration, even for an iteration which ends in a `continue` or `break`.
+    /// Otherwise, a closure which captured one in such an iteration may see it change in later iterations, or see `nil` after the loop.
+    /// This is off by default during a deprecation period, after which it will always be enabled.
+    pub per_iteration_loops: bool,
+}
+
+
+/// Options which control the output of `CompileResult::disassemble()`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DisassemblyOptions {
+    /// If `true`, the `0001` style instruction indices are included in the output. Turning this off is useful when diffing two outputs.
+    pub line_numbers: bool,
+    /// If `true`, each instruction shows the source code it was emitted from, when it differs from the previous instruction.
+    pub annotate: bool,
+    /// If `true`, the constants and fields are listed before the code, each function is shown in its own block, and jumps are shown with labels instead of indices.
+    pub verbose: bool,
+}
+
+impl Default for DisassemblyOptions {
+    fn default() -> Self {
+        DisassemblyOptions { line_numbers: true, annotate: false, verbose: false }
+    }
+}
+
+
 #[derive(Debug, Clone)]
 pub struct CompileResult {
     pub code: Vec<Opcode>,
@@ -197,9 +269,9 @@ pub struct CompileResult {
 
 impl CompileResult {
 
-    /// `line_numbers` : If true, then the `0001` style line numbers will be included in the output. Turning this off is useful when diffing two outputs.
-    pub fn disassemble(&self, view: &SourceView, line_numbers: bool) -> Vec<String> {
+    pub fn disassemble(&self, view: &SourceView, options: &DisassemblyOptions) -> Vec<String> {
         let mut lines: Vec<String> = Vec::new();
+        let mut annotations: Vec<Option<String>> = Vec::new();
         let mut width: usize = 0;
         let mut longest: usize = view.len();
         while longest > 0 {
@@ -207,9 +279,42 @@ impl CompileResult {
             longest /= 10;
         }
 
+        // In verbose mode, each function is shown in its own block, and jumps refer to labels, which are numbered in order of their target
+        let mut functions: Vec<(usize, usize, String)> = Vec::new();
+        let mut labels: HashMap<usize, usize> = HashMap::new();
+        if options.verbose {
+            for constant in &self.constants {
+                if constant.is_function() {
+                    let func = constant.as_function().borrow_const();
+                    functions.push((func.head, func.tail, func.repr()));
+                }
+            }
+            functions.sort_unstable();
+
+            let mut targets: Vec<usize> = self.code.iter()
+                .filter_map(|opcode| opcode.jump_target())
+                .collect();
+            targets.sort_unstable();
+            targets.dedup();
+            labels.extend(targets.into_iter().enumerate().map(|(label, ip)| (ip, label)));
+        }
+        let mut functions = functions.into_iter().peekable();
+
         let mut last_line_no: usize = usize::MAX;
+        let mut last_loc: Option<Location> = None;
         let mut locals = self.locals.iter().cloned();
         for (ip, opcode) in self.code.iter().enumerate() {
+            if let Some((head, tail, repr)) = functions.next_if(|(head, _, _)| *head == ip) {
+                lines.push(String::new());
+                lines.push(format!("{} L[{}, {}]:", repr, head, tail));
+                annotations.extend([None, None]);
+                last_line_no = usize::MAX;
+            }
+            if let Some(label) = labels.get(&ip) {
+                lines.push(format!("@{}:", label));
+                annotations.push(None);
+            }
+
             let loc = self.locations[ip];
             let line_no = view.lineno(loc).unwrap_or(last_line_no);
             let label: String = if line_no != last_line_no {
@@ -218,8 +323,52 @@ impl CompileResult {
             } else {
                 " ".repeat(width + 3)
             };
-            let asm: String = opcode.disassembly(ip, &mut locals, &self.fields, &self.constants);
-            lines.push(format!("{}{} {}", label, if line_numbers { format!("{:0>4}", ip % 10_000) } else { String::new() }, asm));
+            let asm: String = match opcode.jump_target() {
+                Some(target) if options.verbose => format!("{} -> @{}", opcode.jump_name(), labels[&target]),
+                _ => opcode.disassembly(&mut locals, &self.fields, &self.constants),
+            };
+            lines.push(format!("{}{} {}", label, if options.line_numbers { format!("{:0>4}", ip % 10_000) } else { String::new() }, asm));
+
+            // Annotations show the source each instruction was emitted from, but only when it changes from the previous instruction
+            annotations.push(match last_loc.replace(loc) {
+                Some(last) if last == loc => None,
+                _ => view.text_of(loc),
+            });
+        }
+
+        if options.annotate {
+            let column: usize = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+            for (line, annotation) in lines.iter_mut().zip(annotations) {
+                if let Some(annotation) = annotation {
+                    *line = format!("{:<column$} ; {}", line, annotation, column = column);
+                }
+            }
+        }
+
+        if options.verbose {
+            let mut tables: Vec<String> = vec![String::from("Constants:")];
+            for (id, constant) in self.constants.iter().enumerate() {
+                let repr: String = match constant.is_function() {
+                    true => constant.as_function().borrow_const().repr(),
+                    false => Opcode::Constant(id as u32).disassembly(&mut std::iter::empty(), &self.fields, &self.constants),
+                };
+                tables.push(format!("  {:0>4} {}", id, repr));
+            }
+            tables.push(String::from("Fields:"));
+            for (field_index, name) in self.fields.get_field_names().into_iter().enumerate() {
+                let offsets: String = self.fields.get_field_offsets(field_index as u32).into_iter()
+                    .map(|(type_index, offset)| format!("type {} -> {}", type_index, offset))
+                    .chain(self.fields.get_properties(field_index as u32).into_iter()
+                        .map(|(type_index, getter, setter)| format!("type {} property -> {}, {:?}", type_index, getter, setter)))
+                    .chain(self.fields.get_member_offsets(field_index as u32).into_iter()
+                        .map(|(type_index, offset)| format!("type {} member -> {}", type_index, offset)))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                tables.push(format!("  {:0>4} {} ({})", field_index, name, offsets));
+            }
+            tables.push(String::from("Code:"));
+            tables.append(&mut lines);
+            lines = tables;
         }
         lines
     }
@@ -233,8 +382,7 @@ impl CompileResult {
         let mut locals = self.locals.iter().cloned();
         self.code
             .iter()
-            .enumerate()
-            .map(|(ip, op)| op.disassembly(ip, &mut locals, &self.fields, &self.constants)
+            .map(|op| op.disassembly(&mut locals, &self.fields, &self.constants)
                 .replace(" ", "")) // This replacement is the easiest solution to a test DSL problem where we split instructions by " "
             .join("\n")
     }
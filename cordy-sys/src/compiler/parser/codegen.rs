@@ -1,6 +1,7 @@
 use crate::compiler::parser::expr::{Expr, ExprType};
-use crate::compiler::parser::optimizer::Optimize;
+use crate::compiler::parser::optimizer::{literal_size_hint, Optimize};
 use crate::compiler::parser::Parser;
+use crate::compiler::parser::semantic::LValueReference;
 use crate::reporting::Location;
 use crate::vm::Opcode;
 use crate::vm::operator::BinaryOp;
@@ -15,199 +16,240 @@ impl<'a> Parser<'a> {
         if self.enable_optimization {
             expr = expr.optimize();
         }
-        self.emit_expr(expr);
+        let loc: Location = self.prev_location();
+        self.emit_expr(expr, loc);
     }
 
     /// Recursive version of the above.
     /// Does not call optimizations as the expression is already assumed to be optimized.
-    fn emit_expr(&mut self, expr: Expr) {
+    ///
+    /// Every opcode is emitted with the location of the expression it came from. Expressions which don't have a location of their own, such as constants,
+    /// or values produced by constant folding, use the location of the nearest enclosing expression which does. This keeps a best-effort mapping from each
+    /// opcode back to the source, even after optimizations have merged or eliminated expressions.
+    fn emit_expr(&mut self, expr: Expr, parent: Location) {
+        let Expr(loc, expr) = expr;
+        let loc: Location = if loc.is_empty() { parent } else { loc };
         match expr {
-            Expr(_, ExprType::Nil) => self.push(Nil),
-            Expr(_, ExprType::Exit) => self.push(Exit),
-            Expr(_, ExprType::Bool(true)) => self.push(True),
-            Expr(_, ExprType::Bool(false)) => self.push(False),
-            Expr(_, ExprType::Int(it)) => {
+            ExprType::Nil => self.push_with(Nil, loc),
+            ExprType::Exit => self.push_with(Exit, loc),
+            ExprType::ExitWith(code) => {
+                self.emit_expr(*code, loc);
+                self.push_with(ExitWith, loc);
+            },
+            ExprType::Bool(true) => self.push_with(True, loc),
+            ExprType::Bool(false) => self.push_with(False, loc),
+            ExprType::Int(it) => {
                 let id = self.declare_const(it);
-                self.push(Constant(id));
+                self.push_with(Constant(id), loc);
             },
-            Expr(_, ExprType::Complex(it)) => {
+            ExprType::Complex(it) => {
                 let id = self.declare_const(it);
-                self.push(Constant(id))
+                self.push_with(Constant(id), loc)
             }
-            Expr(_, ExprType::Str(it)) => {
+            ExprType::Str(it) => {
                 let id = self.declare_const(it);
-                self.push(Constant(id));
+                self.push_with(Constant(id), loc);
             },
-            Expr(loc, ExprType::LValue(lvalue)) => self.push_load_lvalue(loc, lvalue),
-            Expr(_, ExprType::NativeFunction(native)) => self.push(NativeFunction(native)),
-            Expr(_, ExprType::Function(id, closed_locals)) => {
-                self.push(Constant(id));
+            ExprType::LValue(lvalue) => self.push_load_lvalue(loc, lvalue),
+            ExprType::NativeFunction(native) => self.push_with(NativeFunction(native), loc),
+            ExprType::Function(id, closed_locals) => {
+                self.push_with(Constant(id), loc);
                 self.emit_closure_and_closed_locals(closed_locals)
             },
-            Expr(loc, ExprType::SliceLiteral(arg1, arg2, arg3)) => {
-                self.emit_expr(*arg1);
-                self.emit_expr(*arg2);
+            ExprType::SliceLiteral(arg1, arg2, arg3) => {
+                self.emit_expr(*arg1, loc);
+                self.emit_expr(*arg2, loc);
                 if let Some(arg3) = *arg3 {
-                    self.emit_expr(arg3);
+                    self.emit_expr(arg3, loc);
                     self.push_with(SliceWithStep, loc);
                 } else {
                     self.push_with(Slice, loc);
                 }
             },
-            Expr(loc, ExprType::Unary(op, arg)) => {
-                self.emit_expr(*arg);
+            ExprType::Unary(op, arg) => {
+                self.emit_expr(*arg, loc);
                 self.push_with(Unary(op), loc);
             },
-            Expr(loc, ExprType::Binary(op, lhs, rhs, swap)) => {
+            ExprType::Await(arg) => {
+                self.emit_expr(*arg, loc);
+                self.push_with(Await, loc);
+            },
+            ExprType::Binary(op, lhs, rhs, swap) => {
                 if swap {
-                    self.emit_expr(*rhs);
-                    self.emit_expr(*lhs);
-                    self.push(Swap);
+                    self.emit_expr(*rhs, loc);
+                    self.emit_expr(*lhs, loc);
+                    self.push_with(Swap, loc);
+                    self.push_with(Binary(op), loc);
                 } else {
-                    self.emit_expr(*lhs);
-                    self.emit_expr(*rhs);
+                    self.emit_expr(*lhs, loc);
+                    self.emit_binary_rhs(op, *rhs, loc);
                 }
-                self.push_with(Binary(op), loc);
             },
-            Expr(loc, ExprType::Literal(op, args)) => {
-                self.push(LiteralBegin(op, args.len() as u32));
+            ExprType::Literal(op, args) => {
+                self.push_with(LiteralBegin(op, literal_size_hint(op, &args)), loc);
 
                 let mut acc_args: u32 = 0;
                 for arg in args {
                     match arg {
                         Expr(arg_loc, ExprType::Unroll(unroll_arg, _)) => {
                             if acc_args > 0 {
-                                self.push(LiteralAcc(acc_args));
+                                self.push_with(LiteralAcc(acc_args), loc);
                                 acc_args = 0;
                             }
-                            self.emit_expr(*unroll_arg);
+                            self.emit_expr(*unroll_arg, loc);
                             self.push_with(LiteralUnroll, arg_loc);
                         },
                         _ => {
-                            self.emit_expr(arg);
+                            self.emit_expr(arg, loc);
                             acc_args += 1
                         },
                     }
                 }
 
                 if acc_args > 0 {
-                    self.push(LiteralAcc(acc_args));
+                    self.push_with(LiteralAcc(acc_args), loc);
                 }
 
                 self.push_with(LiteralEnd, loc);
             },
-            Expr(loc, ExprType::Unroll(arg, first)) => {
-                self.emit_expr(*arg);
+            ExprType::Unroll(arg, first) => {
+                self.emit_expr(*arg, loc);
                 self.push_with(Unroll(first), loc);
             },
-            Expr(loc, ExprType::Eval(f, args, any_unroll)) => {
+            ExprType::Eval(f, args, any_unroll) => {
                 let nargs: u32 = args.len() as u32;
-                self.emit_expr(*f);
+                self.emit_expr(*f, loc);
                 for arg in args {
-                    self.emit_expr(arg);
+                    self.emit_expr(arg, loc);
                 }
                 self.push_with(Call(nargs, any_unroll), loc);
             },
-            Expr(loc, ExprType::Compose(arg, f)) => {
-                self.emit_expr(*arg);
-                self.emit_expr(*f);
-                self.push(Swap);
+            ExprType::Compose(arg, f) => {
+                self.emit_expr(*arg, loc);
+                self.emit_expr(*f, loc);
+                self.push_with(Swap, loc);
                 self.push_with(Call(1, false), loc);
             },
-            Expr(_, ExprType::LogicalAnd(lhs, rhs)) => {
-                self.emit_expr(*lhs);
-                let jump_if_false = self.reserve();
-                self.push(Pop);
-                self.emit_expr(*rhs);
+            ExprType::MethodCall(receiver, f, args) => {
+                // The receiver must be evaluated first, but passed last, so compose it with a partial function awaiting just the receiver
+                let func: u32 = self.declare_placeholder_function(loc, &vec![false; args.len()], true);
+                let mut partial_args: Vec<Expr> = vec![*f];
+                partial_args.extend(args);
+                self.emit_expr(receiver.compose(loc, Expr::function(func, Vec::new()).eval(loc, partial_args, false)), loc);
+            },
+            ExprType::LogicalAnd(lhs, rhs) => {
+                self.emit_expr(*lhs, loc);
+                let jump_if_false = self.reserve_with(loc);
+                self.push_with(Pop, loc);
+                self.emit_expr(*rhs, loc);
                 self.fix_jump(jump_if_false, JumpIfFalse)
             },
-            Expr(_, ExprType::LogicalOr(lhs, rhs)) => {
-                self.emit_expr(*lhs);
-                let jump_if_true = self.reserve();
-                self.push(Pop);
-                self.emit_expr(*rhs);
+            ExprType::LogicalOr(lhs, rhs) => {
+                self.emit_expr(*lhs, loc);
+                let jump_if_true = self.reserve_with(loc);
+                self.push_with(Pop, loc);
+                self.emit_expr(*rhs, loc);
                 self.fix_jump(jump_if_true, JumpIfTrue);
             },
-            Expr(loc, ExprType::Index(array, index)) => {
-                self.emit_expr(*array);
-                self.emit_expr(*index);
+            ExprType::Index(array, index) => {
+                self.emit_expr(*array, loc);
+                self.emit_expr(*index, loc);
                 self.push_with(OpIndex, loc);
             },
-            Expr(loc, ExprType::Slice(array, arg1, arg2)) => {
-                self.emit_expr(*array);
-                self.emit_expr(*arg1);
-                self.emit_expr(*arg2);
+            ExprType::Slice(array, arg1, arg2) => {
+                self.emit_expr(*array, loc);
+                self.emit_expr(*arg1, loc);
+                self.emit_expr(*arg2, loc);
                 self.push_with(OpSlice, loc);
             },
-            Expr(loc, ExprType::SliceWithStep(array, arg1, arg2, arg3)) => {
-                self.emit_expr(*array);
-                self.emit_expr(*arg1);
-                self.emit_expr(*arg2);
-                self.emit_expr(*arg3);
+            ExprType::SliceWithStep(array, arg1, arg2, arg3) => {
+                self.emit_expr(*array, loc);
+                self.emit_expr(*arg1, loc);
+                self.emit_expr(*arg2, loc);
+                self.emit_expr(*arg3, loc);
                 self.push_with(OpSliceWithStep, loc);
             },
-            Expr(_, ExprType::IfThenElse(condition, if_true, if_false)) => {
-                self.emit_expr(*condition);
-                let jump_if_false_pop = self.reserve_with(Location::empty());
-                self.emit_expr(*if_true);
-                let jump = self.reserve_with(Location::empty());
+            ExprType::IfThenElse(condition, if_true, if_false) => {
+                self.emit_expr(*condition, loc);
+                let jump_if_false_pop = self.reserve_with(loc);
+                self.emit_expr(*if_true, loc);
+                let jump = self.reserve_with(loc);
                 self.fix_jump(jump_if_false_pop, JumpIfFalsePop);
-                self.emit_expr(*if_false);
+                self.emit_expr(*if_false, loc);
                 self.fix_jump(jump, Jump);
             },
-            Expr(loc, ExprType::GetField(lhs, field_index)) => {
-                self.emit_expr(*lhs);
+            ExprType::GetField(lhs, field_index) => {
+                self.emit_expr(*lhs, loc);
                 self.push_with(GetField(field_index), loc);
             },
-            Expr(loc, ExprType::SetField(lhs, field_index, rhs)) => {
-                self.emit_expr(*lhs);
-                self.emit_expr(*rhs);
+            ExprType::SetField(lhs, field_index, rhs) => {
+                self.emit_expr(*lhs, loc);
+                self.emit_expr(*rhs, loc);
                 self.push_with(SetField(field_index), loc)
             },
-            Expr(loc, ExprType::SwapField(lhs, field_index, rhs, op)) => {
-                self.emit_expr(*lhs);
+            ExprType::SwapField(lhs, field_index, rhs, op) => {
+                self.emit_expr(*lhs, loc);
                 self.push_with(GetFieldPeek(field_index), loc);
-                self.emit_expr(*rhs);
-                self.push_with(Binary(op), loc);
+                self.emit_binary_rhs(op, *rhs, loc);
                 self.push_with(SetField(field_index), loc);
             },
-            Expr(loc, ExprType::GetFieldFunction(field_index)) => {
+            ExprType::GetFieldFunction(field_index) => {
                 self.push_with(GetFieldFunction(field_index), loc);
             },
-            Expr(_, ExprType::Assignment(lvalue, rhs)) => {
-                self.emit_expr(*rhs);
+            ExprType::Assignment(lvalue, rhs) => {
+                self.emit_expr(*rhs, loc);
                 self.push_store_lvalue(lvalue);
             },
-            Expr(loc, ExprType::ArrayAssignment(array, index, rhs)) => {
-                self.emit_expr(*array);
-                self.emit_expr(*index);
-                self.emit_expr(*rhs);
+            ExprType::ArrayAssignment(array, index, rhs) => {
+                self.emit_expr(*array, loc);
+                self.emit_expr(*index, loc);
+                self.emit_expr(*rhs, loc);
                 self.push_with(StoreArray, loc);
             },
-            Expr(loc, ExprType::ArrayOpAssignment(array, index, op, rhs)) => {
-                self.emit_expr(*array);
-                self.emit_expr(*index);
+            ExprType::ArrayOpAssignment(array, index, op, rhs) => {
+                self.emit_expr(*array, loc);
+                self.emit_expr(*index, loc);
                 self.push_with(OpIndexPeek, loc);
-                self.emit_expr(*rhs);
                 match op {
                     BinaryOp::NotEqual => { // Marker to indicate this is a `array[index] .= rhs`
-                        self.push(Swap);
+                        self.emit_expr(*rhs, loc);
+                        self.push_with(Swap, loc);
                         self.push_with(Call(1, false), loc);
                     },
-                    op => self.push_with(Binary(op), loc),
+                    op => self.emit_binary_rhs(op, *rhs, loc),
                 }
                 self.push_with(StoreArray, loc);
             },
-            Expr(_, ExprType::PatternAssignment(lvalue, rhs)) => {
-                self.emit_expr(*rhs);
+            ExprType::PatternAssignment(lvalue, rhs) => {
+                self.emit_expr(*rhs, loc);
                 lvalue.emit_destructuring(self, false, true);
             },
-            Expr(loc, ExprType::RuntimeError(e)) => {
+            ExprType::RuntimeError(e) => {
                 self.runtime_error(loc, e);
             }
         }
     }
 
+    /// Emits the right hand side of a binary operator, and the operator itself. With optimizations enabled, if the right hand side is a local or a constant,
+    /// it is not pushed onto the stack, and instead a `BinaryLocal` or `BinaryConst` is emitted in place of the `Binary`.
+    fn emit_binary_rhs(&mut self, op: BinaryOp, rhs: Expr, loc: Location) {
+        match rhs {
+            Expr(_, ExprType::LValue(LValueReference::Local(index))) if self.enable_optimization => self.push_with(BinaryLocal(op, index), loc),
+            Expr(_, ExprType::Int(it)) if self.enable_optimization => {
+                let id = self.declare_const(it);
+                self.push_with(BinaryConst(op, id), loc);
+            },
+            Expr(_, ExprType::Str(it)) if self.enable_optimization => {
+                let id = self.declare_const(it);
+                self.push_with(BinaryConst(op, id), loc);
+            },
+            rhs => {
+                self.emit_expr(rhs, loc);
+                self.push_with(Binary(op), loc);
+            },
+        }
+    }
+
     pub fn emit_closure_and_closed_locals(&mut self, closed_locals: Vec<Opcode>) {
         if !closed_locals.is_empty() {
             self.push(Closure);
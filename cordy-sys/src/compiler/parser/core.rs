@@ -148,20 +148,38 @@ impl<'a> Parser<'a> {
     }
 
     /// Like `advance()`, but returns the boxed `Identifier` token.
-    /// **Important**: Must only be called once `peek()` has identified an `Identifier` token is present, as this will panic otherwise.
+    /// **Important**: Should only be called once `peek()` has identified an `Identifier` token is present. Otherwise, this raises an error, and returns an empty name.
     pub fn advance_identifier(&mut self) -> String {
         match self.advance() {
             Some(Identifier(name)) => name,
-            t => panic!("Token mismatch in advance_identifier() -> expected an Some(Identifier(String)), got a {:?} instead", t)
+            t => {
+                self.error(ExpectedToken(Identifier(String::new()), t));
+                String::new()
+            }
+        }
+    }
+
+    /// Like `advance()`, but returns the boxed `CustomOperator` token.
+    /// **Important**: Should only be called once `peek()` has identified a `CustomOperator` token is present. Otherwise, this raises an error, and returns an empty name.
+    pub fn advance_custom_operator(&mut self) -> String {
+        match self.advance() {
+            Some(CustomOperator(name)) => name,
+            t => {
+                self.error(ExpectedToken(CustomOperator(String::new()), t));
+                String::new()
+            }
         }
     }
 
     /// Like `advance()`, but returns the boxed `String` literal token.
-    /// **Important**: Must only be called once `peek()` has identified a `StringLiteral` token is present, as this will panic otherwise.
+    /// **Important**: Should only be called once `peek()` has identified a `StringLiteral` token is present. Otherwise, this raises an error, and returns an empty string.
     pub fn advance_str(&mut self) -> String {
         match self.advance() {
             Some(StringLiteral(s)) => s,
-            t => panic!("Token mismatch in advance_str() -> expected a Some(StringLiteral(String)), got a {:?} instead", t)
+            t => {
+                self.error(ExpectedToken(StringLiteral(String::new()), t));
+                String::new()
+            }
         }
     }
 
@@ -176,6 +194,17 @@ impl<'a> Parser<'a> {
         None
     }
 
+    /// Returns `true` if the next tokens are a method call, `.<name>(`, on the same line, with no whitespace between them or the previous token.
+    pub fn peek_method_call(&self) -> bool {
+        let mut tokens = self.input.iter().take(3);
+        match (self.last_location, tokens.next(), tokens.next(), tokens.next()) {
+            (Some(prev), Some((dot, Dot)), Some((name, Identifier(_))), Some((paren, OpenParen))) if !self.error_recovery => {
+                prev.end() + 1 == dot.start() && dot.end() + 1 == name.start() && name.end() + 1 == paren.start()
+            },
+            _ => false,
+        }
+    }
+
     /// Peeks at the next incoming token.
     /// Note that this function only returns a read-only reference to the underlying token, suitable for matching
     /// If the token data needs to be unboxed, i.e. as with `Identifier` tokens, it must be extracted only via `advance()`
@@ -187,7 +216,7 @@ impl<'a> Parser<'a> {
     fn peek_lookahead(&self, mut lookahead: u8) -> Option<&ScanToken> {
         if !self.error_recovery {
             for (_, token) in &self.input {
-                if token != &NewLine {
+                if !token.is_trivia() {
                     if lookahead == 0 {
                         return Some(token)
                     }
@@ -200,6 +229,20 @@ impl<'a> Parser<'a> {
         None
     }
 
+    /// Returns the text of any `///` doc comments directly before the next token, joined by newlines, or `None` if there are none.
+    /// Blank lines between doc comments are skipped, so all doc comments before the next token are attached to it.
+    pub fn peek_doc_comment(&self) -> Option<String> {
+        let mut lines: Vec<&str> = Vec::new();
+        for (_, token) in &self.input {
+            match token {
+                DocComment(line) => lines.push(line),
+                NewLine => {},
+                _ => break,
+            }
+        }
+        if lines.is_empty() { None } else { Some(lines.join("\n")) }
+    }
+
     /// Like `advance()` but discards the result.
     pub fn skip(&mut self) {
         self.advance_both();
@@ -220,7 +263,7 @@ impl<'a> Parser<'a> {
         if self.error_recovery {
             return None
         }
-        while let Some((_, NewLine)) = self.input.front() {
+        while let Some((_, NewLine | DocComment(_))) = self.input.front() {
             let token = self.input.pop_front().unwrap();
             if let Some(state) = &mut self.restore_state {
                 state.input.push(token);
@@ -264,15 +307,15 @@ impl<'a> Parser<'a> {
     }
 
     /// Given a `usize` index, pushes a jump instruction that jumps *back* to the target index.
-    pub fn push_jump<F: FnOnce(i32) -> Opcode>(&mut self, origin: usize, jump: F) {
-        let jump_opcode: Opcode = jump(origin as i32 - self.next_opcode() as i32 - 1);
+    pub fn push_jump<F: FnOnce(u32) -> Opcode>(&mut self, origin: usize, jump: F) {
+        let jump_opcode: Opcode = jump(origin as u32);
         trace::trace_parser!("push jump at {} -> {:?}", self.next_opcode(), jump_opcode);
         self.push(jump_opcode);
     }
 
     /// Given a `usize` index, which is obtained from `self.reserve()`, this fixes the jump instruction at that location to point to the next opcode.
-    pub fn fix_jump<F : FnOnce(i32) -> Opcode>(&mut self, reserved: usize, jump: F) {
-        let jump_opcode: Opcode = jump(self.next_opcode() as i32 - reserved as i32 - 1);
+    pub fn fix_jump<F : FnOnce(u32) -> Opcode>(&mut self, reserved: usize, jump: F) {
+        let jump_opcode: Opcode = jump(self.next_opcode() as u32);
         trace::trace_parser!("fixing jump at {} -> {:?}", reserved, jump_opcode);
         self.current_function_mut()[reserved].1 = jump_opcode;
     }
@@ -317,7 +360,7 @@ impl<'a> Parser<'a> {
             LValueReference::Local(index) => self.push_with(PushLocal(index), loc),
             LValueReference::Global(index) => self.push_with(PushGlobal(index), loc),
             LValueReference::LateBoundGlobal(global) => {
-                self.late_bound_globals.push(Reference::Load(global.update_opcode(self.functions.len() - 1, self.next_opcode())));
+                self.late_bound_globals.push(Reference::Load(global.update_opcode(self.current_function_ordinal(), self.next_opcode())));
                 self.push_with(Noop, loc); // Will be fixed when the global is declared, or caught at EoF as an error
             }
             LValueReference::UpValue(index) => self.push_with(PushUpValue(index), loc),
@@ -332,7 +375,7 @@ impl<'a> Parser<'a> {
             LValueReference::Local(index) => self.push(StoreLocal(index, false)),
             LValueReference::Global(index) => self.push(StoreGlobal(index, false)),
             LValueReference::LateBoundGlobal(global) => {
-                self.late_bound_globals.push(Reference::Store(global.update_opcode(self.functions.len() - 1, self.next_opcode())));
+                self.late_bound_globals.push(Reference::Store(global.update_opcode(self.current_function_ordinal(), self.next_opcode())));
                 self.push(Noop); // Will be fixed when the global is declared, or caught at EoF as an error
             },
             LValueReference::UpValue(index) => self.push(StoreUpValue(index)),
@@ -351,7 +394,7 @@ impl<'a> Parser<'a> {
         trace::trace_parser!("push {:?}", opcode);
         if let Some((depth, id)) = match &opcode {
             PushGlobal(id) | StoreGlobal(id, _) => Some((0, id)),
-            PushLocal(id) | StoreLocal(id, _) => Some((self.function_depth as usize, id)),
+            PushLocal(id) | StoreLocal(id, _) | BinaryLocal(_, id) => Some((self.function_depth as usize, id)),
             _ => None,
         } {
             let local = self.locals[depth].get_name(*id as usize);
@@ -411,6 +454,9 @@ impl<'a> Parser<'a> {
 
     /// Returns the source location of the next token, aka the one in `peek()`
     pub fn next_location(&self) -> Location {
-        self.input.front().map(|u| u.0).unwrap_or_else(Location::empty)
+        self.input.iter()
+            .find(|(_, token)| !token.is_trivia())
+            .map(|u| u.0)
+            .unwrap_or_else(Location::empty)
     }
 }
\ No newline at end of file
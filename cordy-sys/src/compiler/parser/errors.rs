@@ -42,24 +42,36 @@ impl ParserError {
             ExpectedUnderscoreOrVariableNameOrPattern(it) |
             ExpectedAnnotationOrNamedFunction(it) |
             ExpectedStructNameAfterStruct(it) |
-            ExpectedFieldNameAfterArrow(it) => it.is_none(),
+            ExpectedEnumNameAfterEnum(it) |
+            ExpectedFieldNameAfterArrow(it) |
+            ExpectedParentStructName(it) |
+            ExpectedStructMemberName(it) |
+            ExpectedNameAfterAs(it) => it.is_none(),
 
             LocalVariableConflict(_) |
             LocalVariableConflictWithNativeFunction(_) |
             UndeclaredIdentifier(_) |
             DuplicateFieldName(_) |
             InvalidFieldName(_) |
-            InvalidLValue(_) => false,
+            InvalidParentStruct(_) |
+            PropertyWithoutGetter(_) |
+            InvalidLValue(_) |
+            LateBoundGlobalInPattern(_) => false,
 
             InvalidAssignmentTarget |
             MultipleVariadicTermsInPattern |
             LetWithPatternBindingNoExpression |
             BreakOutsideOfLoop |
+            ReturnOutsideOfFunction |
             ContinueOutsideOfLoop |
             StructNotInGlobalScope |
+            EnumNotInGlobalScope |
             NonDefaultParameterAfterDefaultParameter |
             ParameterAfterVarParameter |
-            UnrollNotAllowedInSlice => false,
+            DefaultValueForVarParameter |
+            UnrollNotAllowedInSlice |
+            UnrollNotAllowedWithPlaceholder |
+            UnrollNotAllowedInMethodCall => false,
 
             Runtime(_) => false,
         }
@@ -97,24 +109,36 @@ pub enum ParserErrorType {
     ExpectedUnderscoreOrVariableNameOrPattern(Option<ScanToken>),
     ExpectedAnnotationOrNamedFunction(Option<ScanToken>),
     ExpectedStructNameAfterStruct(Option<ScanToken>),
+    ExpectedEnumNameAfterEnum(Option<ScanToken>),
     ExpectedFieldNameAfterArrow(Option<ScanToken>),
+    ExpectedParentStructName(Option<ScanToken>),
+    ExpectedStructMemberName(Option<ScanToken>),
+    ExpectedNameAfterAs(Option<ScanToken>),
 
     LocalVariableConflict(String),
     LocalVariableConflictWithNativeFunction(String),
     UndeclaredIdentifier(String),
     DuplicateFieldName(String),
     InvalidFieldName(String),
+    InvalidParentStruct(String),
+    PropertyWithoutGetter(String),
     InvalidLValue(String),
+    LateBoundGlobalInPattern(String),
 
     InvalidAssignmentTarget,
     MultipleVariadicTermsInPattern,
     LetWithPatternBindingNoExpression,
     BreakOutsideOfLoop,
+    ReturnOutsideOfFunction,
     ContinueOutsideOfLoop,
     StructNotInGlobalScope,
+    EnumNotInGlobalScope,
     NonDefaultParameterAfterDefaultParameter,
     ParameterAfterVarParameter,
+    DefaultValueForVarParameter,
     UnrollNotAllowedInSlice,
+    UnrollNotAllowedWithPlaceholder,
+    UnrollNotAllowedInMethodCall,
 
     Runtime(Box<RuntimeError>),
 }
\ No newline at end of file
@@ -15,6 +15,7 @@ pub enum ExprType {
     // Terminals
     Nil,
     Exit,
+    ExitWith(Arg),
     Bool(bool),
     Int(i64),
     Complex(C64),
@@ -26,6 +27,7 @@ pub enum ExprType {
 
     // Operators + Functions
     Unary(UnaryOp, Arg),
+    Await(Arg),
 
     /// Arguments are `op, lhs, rhs, swap`
     /// If `swap` is `true`, then **both** of the following effects will apply:
@@ -41,6 +43,8 @@ pub enum ExprType {
     Unroll(Arg, bool), // first: bool
     Eval(Arg, Vec<Expr>, bool), // any_unroll: bool
     Compose(Arg, Arg),
+    /// Arguments are `receiver, f, args`, for `receiver.f(args)`, which calls `f(args, receiver)` but evaluates `receiver` first.
+    MethodCall(Arg, Arg, Vec<Expr>),
     LogicalAnd(Arg, Arg),
     LogicalOr(Arg, Arg),
     Index(Arg, Arg),
@@ -75,6 +79,7 @@ impl Expr {
 
     pub fn nil() -> Expr { Expr(Location::empty(), ExprType::Nil) }
     pub fn exit() -> Expr { Expr(Location::empty(), ExprType::Exit) }
+    pub fn exit_with(loc: Location, code: Expr) -> Expr { Expr(loc, ExprType::ExitWith(Box::new(code))) }
     pub fn bool(it: bool) -> Expr { Expr(Location::empty(), ExprType::Bool(it)) }
     pub fn int(it: i64) -> Expr { Expr(Location::empty(), ExprType::Int(it)) }
     pub fn complex(it: i64) -> Expr { Expr::c64(C64::new(0, it)) }
@@ -102,10 +107,12 @@ impl Expr {
     pub fn raw_slice(loc: Location, arg1: Expr, arg2: Expr, arg3: Option<Expr>) -> Expr { Expr(loc, ExprType::SliceLiteral(Box::new(arg1), Box::new(arg2), Box::new(arg3))) }
 
     pub fn unary(self, loc: Location, op: UnaryOp) -> Expr { Expr(loc, ExprType::Unary(op, Box::new(self))) }
+    pub fn await_task(self, loc: Location) -> Expr { Expr(loc, ExprType::Await(Box::new(self))) }
     pub fn binary(self, loc: Location, op: BinaryOp, rhs: Expr, swap: bool) -> Expr { Expr(loc, ExprType::Binary(op, Box::new(self), Box::new(rhs), swap)) }
     pub fn unroll(self, loc: Location, first: bool) -> Expr { Expr(loc, ExprType::Unroll(Box::new(self), first)) }
     pub fn eval(self, loc: Location, args: Vec<Expr>, any_unroll: bool) -> Expr { Expr(loc, ExprType::Eval(Box::new(self), args, any_unroll)) }
     pub fn compose(self, loc: Location, f: Expr) -> Expr { Expr(loc, ExprType::Compose(Box::new(self), Box::new(f))) }
+    pub fn method_call(self, loc: Location, f: Expr, args: Vec<Expr>) -> Expr { Expr(loc, ExprType::MethodCall(Box::new(self), Box::new(f), args)) }
     pub fn index(self, loc: Location, index: Expr) -> Expr { Expr(loc, ExprType::Index(Box::new(self), Box::new(index))) }
     pub fn slice(self, loc: Location, arg1: Expr, arg2: Expr) -> Expr { Expr(loc, ExprType::Slice(Box::new(self), Box::new(arg1), Box::new(arg2))) }
     pub fn slice_step(self, loc: Location, arg1: Expr, arg2: Expr, arg3: Expr) -> Expr { Expr(loc, ExprType::SliceWithStep(Box::new(self), Box::new(arg1), Box::new(arg2), Box::new(arg3))) }
@@ -138,12 +145,18 @@ impl Expr {
         Expr(loc, ExprType::RuntimeError(error))
     }
 
-    pub fn value_result(loc: Location, value: ValueResult) -> Expr {
+    /// Returns the result of constant folding an expression. If it raised an error, that is reported at `loc`, otherwise the value retains the location of the whole expression, `span`
+    pub fn value_result(loc: Location, span: Location, value: ValueResult) -> Expr {
         match value.as_result() {
-            Ok(value) => Expr::value(value),
+            Ok(value) => Expr::value(value).at(span),
             Err(e) => Expr::error(loc, Box::new(e.value)),
         }
     }
 
+    /// Returns this expression, with the location replaced by `loc`
+    pub fn at(self, loc: Location) -> Expr {
+        Expr(loc, self.1)
+    }
+
     pub fn is_unroll(&self) -> bool { matches!(self, Expr(_, ExprType::Unroll(_, _))) }
 }
\ No newline at end of file
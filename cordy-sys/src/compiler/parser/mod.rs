@@ -1,7 +1,7 @@
 use std::collections::VecDeque;
 use std::rc::Rc;
 
-use crate::compiler::{CompileParameters, CompileResult};
+use crate::compiler::{CompileFlags, CompileParameters, CompileResult};
 use crate::compiler::parser::core::ParserState;
 use crate::compiler::parser::expr::{Expr, ExprType};
 use crate::compiler::parser::semantic::{LateBoundGlobal, LValue, LValueReference, ParserFunctionImpl, Reference};
@@ -9,7 +9,7 @@ use crate::compiler::scanner::{ScanResult, ScanToken};
 use crate::core::{NativeFunction, Pattern};
 use crate::reporting::Location;
 use crate::trace;
-use crate::vm::{Opcode, StructTypeImpl, ValuePtr};
+use crate::vm::{IntoValue, Opcode, StructKind, StructTypeImpl, ValuePtr};
 use crate::vm::operator::{BinaryOp, UnaryOp};
 
 pub use crate::compiler::parser::errors::{ParserError, ParserErrorType};
@@ -34,26 +34,31 @@ mod optimizer;
 
 /// Create a default empty `CompileResult`. This is semantically equivalent to parsing an empty program, but will output nothing.
 pub fn default() -> CompileResult {
-    parse_rule(true, vec![], |_| ())
+    parse_rule(true, CompileFlags::default(), vec![], |_| ())
 }
 
 
 /// Parse a complete `CompileResult` from the given `ScanResult`
-pub(super) fn parse(enable_optimization: bool, scan_result: ScanResult) -> CompileResult {
-    parse_rule(enable_optimization, scan_result.tokens, |parser| parser.parse())
+pub(super) fn parse(enable_optimization: bool, flags: CompileFlags, scan_result: ScanResult) -> CompileResult {
+    let rule: fn(&mut Parser) -> () = match flags {
+        CompileFlags { print_last: true, .. } => |parser| parser.parse_print_last(),
+        CompileFlags { retain_globals: true, .. } => |parser| parser.parse_retaining_globals(),
+        _ => |parser| parser.parse(),
+    };
+    parse_rule(enable_optimization, flags, scan_result.tokens, rule)
 }
 
 
 pub(super) fn parse_incremental(scan_result: ScanResult, params: &mut CompileParameters, rule: ParseRule) -> Vec<ParserError> {
     let mut errors: Vec<ParserError> = Vec::new();
 
-    rule(&mut Parser::new(params.enable_optimization, scan_result.tokens, params.code, &mut errors, params.constants, params.patterns, params.globals, params.locations, params.fields, params.locals, &mut Vec::new()));
+    rule(&mut Parser::new(params.enable_optimization, CompileFlags::default(), scan_result.tokens, params.code, &mut errors, params.constants, params.patterns, params.globals, params.locations, params.fields, params.locals, &mut Vec::new()));
 
     errors
 }
 
 
-fn parse_rule(enable_optimization: bool, tokens: Vec<(Location, ScanToken)>, rule: fn(&mut Parser) -> ()) -> CompileResult {
+fn parse_rule(enable_optimization: bool, flags: CompileFlags, tokens: Vec<(Location, ScanToken)>, rule: fn(&mut Parser) -> ()) -> CompileResult {
     let mut result = CompileResult {
         code: Vec::new(),
         errors: Vec::new(),
@@ -67,7 +72,7 @@ fn parse_rule(enable_optimization: bool, tokens: Vec<(Location, ScanToken)>, rul
         locals: Vec::new(),
     };
 
-    rule(&mut Parser::new(enable_optimization, tokens, &mut result.code, &mut result.errors, &mut result.constants, &mut result.patterns, &mut result.globals, &mut result.locations, &mut result.fields, &mut Locals::empty(), &mut result.locals));
+    rule(&mut Parser::new(enable_optimization, flags, tokens, &mut result.code, &mut result.errors, &mut result.constants, &mut result.patterns, &mut result.globals, &mut result.locations, &mut result.fields, &mut Locals::empty(), &mut result.locals));
 
     result
 }
@@ -75,6 +80,8 @@ fn parse_rule(enable_optimization: bool, tokens: Vec<(Location, ScanToken)>, rul
 
 pub(super) struct Parser<'a> {
     enable_optimization: bool,
+    /// If `true`, `continue` and `break` in a `for` loop also lift the loop's own variables, so they are bound per iteration. See `CompileFlags::per_iteration_loops`
+    per_iteration_loops: bool,
 
     input: VecDeque<(Location, ScanToken)>,
 
@@ -128,6 +135,7 @@ impl Parser<'_> {
 
     fn new<'a, 'b : 'a>(
         enable_optimization: bool,
+        flags: CompileFlags,
 
         tokens: Vec<(Location, ScanToken)>,
         output: &'b mut Vec<Opcode>,
@@ -144,6 +152,7 @@ impl Parser<'_> {
     ) -> Parser<'a> {
         Parser {
             enable_optimization,
+            per_iteration_loops: flags.per_iteration_loops,
 
             input: tokens.into_iter().collect::<VecDeque<(Location, ScanToken)>>(),
             raw_output: output,
@@ -183,6 +192,26 @@ impl Parser<'_> {
         self.teardown();
     }
 
+    fn parse_retaining_globals(&mut self) {
+        trace::trace_parser!("rule <root-retaining-globals>");
+        self.parse_statements();
+        self.push_delayed_pop();
+        self.push(Exit);
+        self.teardown();
+    }
+
+    fn parse_print_last(&mut self) {
+        trace::trace_parser!("rule <root-print-last>");
+        self.parse_statements();
+        if !self.delay_pop_from_expression_statement {
+            self.push(Nil); // No trailing expression, so the value is `nil`
+        }
+        self.delay_pop_from_expression_statement = false;
+        // Don't pop locals, as the value is on top of them, and the program is exiting anyway
+        self.push(ExitValue);
+        self.teardown();
+    }
+
     pub(super) fn parse_incremental_repl(&mut self) {
         trace::trace_parser!("rule <root-incremental>");
         self.parse_statements();
@@ -197,6 +226,18 @@ impl Parser<'_> {
         self.teardown();
     }
 
+    pub(super) fn parse_incremental_value(&mut self) {
+        trace::trace_parser!("rule <root-incremental-value>");
+        self.parse_statements();
+        if !self.delay_pop_from_expression_statement {
+            self.push(Nil); // No trailing expression, so the value is `nil`
+        }
+        self.delay_pop_from_expression_statement = false;
+        // Don't pop locals, or the value
+        self.push(Yield);
+        self.teardown();
+    }
+
     pub(super) fn parse_incremental_eval(&mut self) {
         self.parse_expression();
         self.push(Return); // Insert a `Return` at the end, to return out of `eval`'s frame
@@ -205,8 +246,10 @@ impl Parser<'_> {
 
     fn teardown(&mut self) {
         // Emit code from output -> (raw_output, locations)
+        // Jumps are relative to the start of the code being emitted, which may follow previously compiled code, so they are relocated to absolute indices here
+        let head: usize = self.raw_output.len();
         for (loc, op) in self.output.drain(..) {
-            self.raw_output.push(op);
+            self.raw_output.push(op.relocate(head));
             self.locations.push(loc);
         }
 
@@ -214,7 +257,7 @@ impl Parser<'_> {
         for mut func in self.functions.drain(..) {
             let head: usize = self.raw_output.len();
             for (loc, op) in func.emit_code() {
-                self.raw_output.push(op);
+                self.raw_output.push(op.relocate(head));
                 self.locations.push(loc);
             }
             for local in func.emit_locals() {
@@ -242,8 +285,8 @@ impl Parser<'_> {
         loop {
             trace::trace_parser!("rule <statement>");
             match self.peek() {
-                Some(At) => self.parse_annotated_named_function(),
-                Some(KeywordFn) => self.parse_named_function(),
+                Some(At) => self.parse_annotated_named_function(None),
+                Some(KeywordFn | KeywordAsync) => self.parse_named_function(None),
                 Some(KeywordReturn) => self.parse_return_statement(),
                 Some(KeywordLet) => self.parse_let_statement(),
                 Some(KeywordIf) => self.parse_if_statement(),
@@ -255,8 +298,10 @@ impl Parser<'_> {
                 Some(KeywordContinue) => self.parse_continue_statement(),
                 Some(KeywordAssert) => self.parse_assert_statement(),
                 Some(KeywordStruct) => self.parse_struct_statement(),
+                Some(KeywordEnum) => self.parse_enum_statement(),
+                Some(KeywordWith) => self.parse_with_statement(),
                 Some(CloseBrace) => break,
-                Some(KeywordExit) => {
+                Some(KeywordExit) if self.peek2() != Some(&OpenParen) => {
                     self.push_delayed_pop();
                     self.advance();
                     self.push(Exit);
@@ -307,55 +352,295 @@ impl Parser<'_> {
 
         // Declare a type index, as at this point we know we're in totally global scope, and the type name must be unique
         let type_index: u32 = self.declare_type();
-        let mut unique_fields: Vec<String> = Vec::new();
-
-        self.expect(OpenParen);
+        let own_fields: Vec<String> = self.parse_struct_fields();
 
-        loop {
+        // An optional parent struct, i.e. `struct Child(c) : Parent`
+        // The child inherits all fields of the parent, in order, before it's own fields, and is considered an instance of the parent with `is`
+        // It also inherits all members of the parent, which occupy the same member offsets in the child, and are copied from the parent when the child is declared.
+        let mut unique_fields: Vec<String> = Vec::new();
+        let mut parents: Vec<u32> = Vec::new();
+        let mut member_names: Vec<String> = Vec::new();
+        let mut parent_id: Option<u32> = None;
+        if let Some(Colon) = self.peek() {
+            self.skip(); // Consume `:`
             match self.peek() {
                 Some(Identifier(_)) => {
-                    let name: String = self.advance_identifier();
-
-                    if unique_fields.contains(&name) {
-                        self.semantic_error(DuplicateFieldName(name))
-                    } else {
-                        self.declare_field(type_index, unique_fields.len(), name.clone());
-                        unique_fields.push(name);
-                    }
-
-                    // Consume `,` and allow trailing comma
-                    if let Some(Comma) = self.peek() {
-                        self.skip();
+                    let parent_name: String = self.advance_identifier();
+                    match self.resolve_struct_type(&parent_name) {
+                        Some((id, parent)) if parent.kind == StructKind::Struct => {
+                            unique_fields = parent.field_names;
+                            parents.push(parent.type_index);
+                            parents.extend(parent.parents);
+                            member_names = parent.member_names;
+                            parent_id = Some(id);
+                            self.inherit_members(type_index, parent.type_index);
+                        },
+                        _ => self.semantic_error(InvalidParentStruct(parent_name)),
                     }
                 },
-                _ => break, // `CloseParen` also breaks the loop, and hits resync below
+                _ => self.error_with(ExpectedParentStructName),
+            }
+        }
+
+        for name in own_fields {
+            if unique_fields.contains(&name) {
+                self.semantic_error(DuplicateFieldName(name))
+            } else {
+                unique_fields.push(name);
             }
         }
 
-        let id: u32 = self.declare_const(StructTypeImpl::new(type_name, unique_fields, type_index));
+        for (offset, name) in unique_fields.iter().enumerate() {
+            self.declare_field(type_index, offset, name.clone());
+        }
+
+        let id: u32 = self.declare_const(StructTypeImpl::new(type_name.clone(), unique_fields.clone(), type_index, parents.clone(), StructKind::Struct, member_names.clone()));
         self.push(Constant(id));
 
+        if let Some(parent_id) = parent_id.filter(|_| !member_names.is_empty()) {
+            self.push(Constant(parent_id));
+            self.push(InheritMembers);
+        }
+
+        // An optional body, declaring static members of the struct, i.e. `struct P(x, y) { const ORIGIN = P(0, 0) ; fn from_str(s) -> ... }`
+        // Members are accessed on the struct type itself, i.e. `P->ORIGIN`, and each is initialized in order, directly after the struct is declared.
+        // The body may also declare computed fields (properties) via `get` and `set` accessors, which are stored as hidden members of the struct type.
+        // A member with the same name as an inherited member replaces it, so members are resolved on the child first, then on each parent in turn.
+        if let Some(OpenBrace) = self.peek() {
+            self.skip(); // Consume `{`
+            let mut inherited: Vec<String> = member_names.clone();
+            let mut properties: Vec<(String, Option<usize>, Option<usize>)> = Vec::new();
+            loop {
+                match self.peek() {
+                    Some(KeywordConst) => {
+                        self.advance(); // Consume `const`
+                        let name: String = match self.peek() {
+                            Some(Identifier(_)) => self.advance_identifier(),
+                            _ => {
+                                self.error_with(ExpectedStructMemberName);
+                                break
+                            },
+                        };
+                        self.expect(Equals);
+                        self.parse_expression();
+                        self.init_struct_member(type_index, &mut member_names, &mut inherited, name);
+                    },
+                    Some(KeywordFn | KeywordAsync) => {
+                        let doc: Option<String> = self.peek_doc_comment();
+                        let is_async: bool = self.parse_function_keyword(); // Consume `fn` or `async fn`
+                        let name: String = match self.peek() {
+                            Some(Identifier(_)) => self.advance_identifier(),
+                            _ => {
+                                self.error_with(ExpectedStructMemberName);
+                                break
+                            },
+                        };
+                        self.parse_struct_member_function(name.clone(), is_async, doc);
+                        self.init_struct_member(type_index, &mut member_names, &mut inherited, name);
+                    },
+                    Some(Identifier(it)) if (it == "get" || it == "set") && matches!(self.peek2(), Some(Identifier(_))) => {
+                        let is_getter: bool = it == "get";
+                        let doc: Option<String> = self.peek_doc_comment();
+                        self.advance(); // Consume `get` or `set`
+                        let name: String = self.advance_identifier();
+                        self.parse_struct_member_function(name.clone(), false, doc);
+
+                        // Accessors are initialized as members, but with names that cannot be referenced as a field
+                        let offset: usize = match struct_member_offset(&mut member_names, &mut inherited, format!("{} {}", if is_getter { "get" } else { "set" }, name)) {
+                            Some(offset) => offset,
+                            None => {
+                                self.semantic_error(DuplicateFieldName(name));
+                                self.push(Opcode::Pop);
+                                continue
+                            },
+                        };
+                        self.push(InitMember(offset as u32));
+
+                        // A property inherited from the parent starts with the parent's accessors, either of which may be replaced
+                        let index: usize = match properties.iter().position(|(prop, _, _)| prop == &name) {
+                            Some(index) => index,
+                            None => {
+                                let (getter, setter) = match self.resolve_field(&name).and_then(|field_index| self.fields.get_property_offsets(type_index, field_index)) {
+                                    Some((getter, setter)) => (Some(getter), setter),
+                                    None => (None, None),
+                                };
+                                properties.push((name.clone(), getter, setter));
+                                properties.len() - 1
+                            }
+                        };
+                        if is_getter {
+                            properties[index].1 = Some(offset);
+                        } else {
+                            properties[index].2 = Some(offset);
+                        }
+
+                        // Declare the property as soon as it has a getter, so it can be referenced by later members
+                        if unique_fields.contains(&name) {
+                            self.semantic_error(DuplicateFieldName(name));
+                        } else if let (_, Some(getter), setter) = properties[index] {
+                            self.declare_property(type_index, getter, setter, name);
+                        }
+                    },
+                    Some(Semicolon) => self.skip(),
+                    _ => break,
+                }
+            }
+            self.expect_resync(CloseBrace);
+
+            for (name, getter, _) in properties {
+                if getter.is_none() {
+                    self.semantic_error(PropertyWithoutGetter(name));
+                }
+            }
+
+            // `__cmp__` and `__lt__` both define the ordering of a struct, so a child which declares either one replaces both of the inherited members
+            for (declared, replaced) in [("__cmp__", "__lt__"), ("__lt__", "__cmp__")] {
+                if member_names.iter().any(|m| m == declared) && !inherited.iter().any(|m| m == declared) && inherited.iter().any(|m| m == replaced) {
+                    let offset: usize = member_names.iter().position(|m| m == replaced).unwrap();
+                    self.push(Opcode::Nil);
+                    self.push(InitMember(offset as u32));
+                }
+            }
+
+            // Now that all members are known, replace the struct type constant with one that knows it's members
+            self.constants[id as usize] = StructTypeImpl::new(type_name, unique_fields, type_index, parents, StructKind::Struct, member_names).to_value();
+        }
+    }
+
+    /// Parses the parameters and body of a function declared within a struct body, i.e. `fn name(<args>) <body>`, after the name.
+    /// The function is emitted onto the stack, but not stored anywhere.
+    fn parse_struct_member_function(&mut self, name: String, is_async: bool, doc: Option<String>) {
+        let func: u32 = self.declare_function(name, &[], false, is_async);
+        self.declare_function_doc(doc);
+        let closed_locals = self.parse_function_body();
+        self.emit_optimized_expr(Expr::function(func, closed_locals));
+    }
+
+    /// Initializes the value on top of the stack as a static member of the struct type below it, with the name `name`.
+    fn init_struct_member(&mut self, type_index: u32, member_names: &mut Vec<String>, inherited: &mut Vec<String>, name: String) {
+        match struct_member_offset(member_names, inherited, name.clone()) {
+            Some(offset) => {
+                self.declare_member(type_index, offset, name);
+                self.push(InitMember(offset as u32));
+            },
+            None => {
+                self.semantic_error(DuplicateFieldName(name));
+                self.push(Opcode::Pop);
+            },
+        }
+    }
+
+    /// Parses the field names of a struct, or enum variant, including the surrounding `(` parenthesis `)`, and returns the unique field names.
+    fn parse_struct_fields(&mut self) -> Vec<String> {
+        let mut fields: Vec<String> = Vec::new();
+
+        self.expect(OpenParen);
+
+        // `CloseParen` also ends the loop, and hits resync below
+        while let Some(Identifier(_)) = self.peek() {
+            let name: String = self.advance_identifier();
+
+            if fields.contains(&name) {
+                self.semantic_error(DuplicateFieldName(name))
+            } else {
+                fields.push(name);
+            }
+
+            // Consume `,` and allow trailing comma
+            if let Some(Comma) = self.peek() {
+                self.skip();
+            }
+        }
+
         self.expect_resync(CloseParen);
+        fields
     }
 
-    fn parse_annotated_named_function(&mut self) {
+    fn parse_enum_statement(&mut self) {
+        self.push_delayed_pop();
+        self.advance(); // Consume `enum`
+
+        // Like structs, enums (and their variants) can only be declared in global scope
+        if self.function_depth != 0 || self.scope_depth != 0 {
+            self.semantic_error(EnumNotInGlobalScope);
+            return;
+        }
+
+        let enum_name: String = match self.peek() {
+            Some(Identifier(_)) => self.advance_identifier(),
+            _ => {
+                self.error_with(ExpectedEnumNameAfterEnum);
+                return
+            },
+        };
+
+        // The enum itself is a struct type with no fields, which cannot be constructed, but is the parent of all variants
+        match self.declare_local(enum_name.clone()) {
+            Some(local) => self.init_local(local),
+            _ => return,
+        }
+
+        let enum_index: u32 = self.declare_type();
+        let id: u32 = self.declare_const(StructTypeImpl::new(enum_name, Vec::new(), enum_index, Vec::new(), StructKind::Enum, Vec::new()));
+        self.push(Constant(id));
+
+        self.expect(OpenBrace);
+
+        // Each variant is declared as a global struct type, inheriting from the enum, with a discriminant equal to it's index
+        let mut discriminant: u32 = 0;
+        while let Some(Identifier(_)) = self.peek() {
+            let variant_name: String = self.advance_identifier();
+            let local: Option<usize> = self.declare_local(variant_name.clone());
+            if let Some(local) = local {
+                self.init_local(local);
+            }
+
+            let type_index: u32 = self.declare_type();
+            let fields: Vec<String> = match self.peek() {
+                Some(OpenParen) => self.parse_struct_fields(),
+                _ => Vec::new(),
+            };
+
+            for (offset, name) in fields.iter().enumerate() {
+                self.declare_field(type_index, offset, name.clone());
+            }
+
+            if local.is_some() {
+                let id: u32 = self.declare_const(StructTypeImpl::new(variant_name, fields, type_index, vec![enum_index], StructKind::Variant(discriminant), Vec::new()));
+                self.push(Constant(id));
+            }
+            discriminant += 1;
+
+            // Consume `,` and allow trailing comma
+            if let Some(Comma) = self.peek() {
+                self.skip();
+            }
+        }
+
+        self.expect_resync(CloseBrace);
+    }
+
+    /// Parses a named function with annotations. A doc comment before the first annotation, i.e. `/// doc \n @memoize \n fn foo()`, is attached to the function.
+    fn parse_annotated_named_function(&mut self, doc: Option<String>) {
         trace::trace_parser!("rule <annotated-named-function");
 
+        let doc: Option<String> = doc.or_else(|| self.peek_doc_comment());
         self.push_delayed_pop();
         self.advance(); // Consume `@`
         self.parse_expression(); // The annotation body
         match self.peek() {
-            Some(At) => self.parse_annotated_named_function(),
-            Some(KeywordFn) => self.parse_named_function(),
+            Some(At) => self.parse_annotated_named_function(doc),
+            Some(KeywordFn | KeywordAsync) => self.parse_named_function(doc),
             _ => self.error_with(ExpectedAnnotationOrNamedFunction),
         }
         self.push(Call(1, false)) // Evaluate the annotation
     }
 
-    fn parse_named_function(&mut self) {
-        // Before we enter this rule, we instead check if we see `fn` `(`, which would imply this is actually part of an expression
+    fn parse_named_function(&mut self, doc: Option<String>) {
+        // Before we enter this rule, we instead check if we see `fn` `(`, or `async fn (`, which would imply this is actually part of an expression
         // If so, we shortcut into that
-        if let Some(OpenParen) = self.peek2() {
+        let next: Option<&ScanToken> = if let Some(KeywordAsync) = self.peek() { self.peek3() } else { self.peek2() };
+        if let Some(OpenParen) = next {
             self.parse_expression_statement();
             return
         }
@@ -363,12 +648,10 @@ impl Parser<'_> {
         trace::trace_parser!("rule <named-function>");
 
         // Function header - `fn <name> (<arg>, ...)
+        let doc: Option<String> = doc.or_else(|| self.peek_doc_comment());
         self.push_delayed_pop();
-        self.advance();
+        let is_async: bool = self.parse_function_keyword();
         let maybe_name: Option<String> = self.parse_function_name();
-        self.expect(OpenParen);
-        let (args, default_args, var_arg) = self.parse_function_parameters();
-        self.expect_resync(CloseParen);
 
         // Named functions are a complicated local variable, and needs to be declared as such
         // Note that we always declare the function here, to preserve parser operation in the event of a parse error
@@ -381,11 +664,12 @@ impl Parser<'_> {
             })
             .unwrap_or_else(|| String::from("<invalid>"));
 
-        let func: u32 = self.declare_function(name, &args, var_arg);
+        let func: u32 = self.declare_function(name, &[], false, is_async);
+        self.declare_function_doc(doc);
         self.push(Constant(func));
 
         // Emit the closed locals from the function body right away, because we are not in an expression context
-        let closed_locals = self.parse_function_body(args, default_args);
+        let closed_locals = self.parse_function_body();
         self.emit_closure_and_closed_locals(closed_locals);
     }
 
@@ -393,22 +677,31 @@ impl Parser<'_> {
         trace::trace_parser!("rule <expression-function>");
 
         // Function header - `fn` (<arg>, ...)
-        self.advance();
-        self.expect(OpenParen);
-        let (args, default_args, var_arg) = self.parse_function_parameters();
-        self.expect_resync(CloseParen);
+        let is_async: bool = self.parse_function_keyword();
 
         // Expression functions don't declare themselves as a local variable that can be referenced.
         // Instead, as they're part of an expression, they just push a single function instance onto the stack
-        let func: u32 = self.declare_function(String::from("_"), &args, var_arg);
-        let closed_locals = self.parse_function_body(args, default_args);
+        let func: u32 = self.declare_function(String::from("_"), &[], false, is_async);
+        let closed_locals = self.parse_function_body();
         Expr::function(func, closed_locals)
     }
 
+    /// Consumes the `fn` keyword at the start of a function, or `async fn` for an asynchronous function, and returns `true` if it was the latter.
+    fn parse_function_keyword(&mut self) -> bool {
+        match self.advance() {
+            Some(KeywordAsync) => {
+                self.expect(KeywordFn);
+                true
+            },
+            _ => false,
+        }
+    }
+
     fn parse_function_name(&mut self) -> Option<String> {
         trace::trace_parser!("rule <function-name>");
         match self.peek() {
             Some(Identifier(_)) => Some(self.advance_identifier()),
+            Some(CustomOperator(_)) => Some(self.advance_custom_operator()), // `fn <+> (a, b)` declares a custom operator
             _ => {
                 self.error_with(ExpectedFunctionNameAfterFn);
                 None
@@ -416,85 +709,152 @@ impl Parser<'_> {
         }
     }
 
-    /// Returns the pair of `lvalue` parameters, and `Expr` default values, if present.
-    fn parse_function_parameters(&mut self) -> (Vec<LValue>, Vec<Expr>, bool) {
+    /// Parses the parameters of the current function, `(<args>)`, and returns each `lvalue` parameter, along with its synthetic local if it is a pattern.
+    ///
+    /// This is called within the scope of the function, so each parameter is declared as it is parsed. Default values are parsed and emitted before the
+    /// parameter they belong to is declared, but after every parameter before it, so they can reference earlier parameters by name, i.e. `fn f(a, b = a + 1)`.
+    /// As the function is entered at the first default argument which was not provided, each default value is evaluated on every call where it is needed.
+    /// Parses an optional capture list before a function's parameters, i.e. `fn [x, y] () -> x + y`.
+    /// Each name is captured by value, so the function sees the value it had when the closure was created, instead of any later assignments.
+    fn parse_function_captures(&mut self, func: usize) -> Vec<(String, u32)> {
+        trace::trace_parser!("rule <function-captures>");
+
+        let mut captures: Vec<(String, u32)> = Vec::new();
+        if let Some(OpenSquareBracket) = self.peek() {
+            self.advance(); // Consume `[`
+            while let Some(Identifier(_)) = self.peek() {
+                let name: String = self.advance_identifier();
+                let index: u32 = self.declare_capture(func, name.clone());
+                captures.push((name, index));
+
+                match self.peek() {
+                    Some(Comma) => self.skip(),
+                    _ => break,
+                }
+            }
+            self.expect_resync(CloseSquareBracket);
+        }
+        captures
+    }
+
+    fn parse_function_parameters(&mut self) -> Vec<(LValue, Option<usize>)> {
         trace::trace_parser!("rule <function-parameters>");
 
+        let mut args: Vec<(LValue, Option<usize>)> = Vec::new();
         if let Some(CloseParen) = self.peek() {
-            return (Vec::new(), Vec::new(), false)
+            return args
         }
 
-        let mut args: Vec<LValue> = Vec::new();
-        let mut default_args: Vec<Expr> = Vec::new();
+        let mut has_default_args: bool = false;
         let mut var_arg: bool = false;
 
         loop {
-            match self.parse_lvalue() {
-                Some(lvalue @ (LValue::VarEmpty | LValue::Empty)) => self.semantic_error(InvalidLValue(lvalue.to_code_str())),
+            let start: Location = self.next_location();
+            let lvalue: Option<LValue> = self.parse_lvalue();
+            let var_param: bool = lvalue.as_ref().is_some_and(LValue::is_variadic_term);
+            let arg: Option<LValue> = match lvalue {
+                Some(lvalue @ (LValue::VarEmpty | LValue::Empty)) => {
+                    self.semantic_error(InvalidLValue(lvalue.to_code_str()));
+                    None
+                },
                 Some(LValue::VarNamed(reference)) => {
                     // A `*<name>` argument gets treated as a default argument value of an empty vector, and we set the `var_arg` flag
-                    args.push(LValue::Named(reference)); // Convert to a `Named()`
-                    default_args.push(Expr::vector(Location::empty(), Vec::new()));
+                    if var_arg {
+                        self.semantic_error(ParameterAfterVarParameter);
+                    }
+                    self.emit_optimized_expr(Expr::vector(Location::empty(), Vec::new()));
+                    self.current_function_impl().mark_default_arg();
+                    has_default_args = true;
                     var_arg = true;
+                    Some(LValue::Named(reference)) // Convert to a `Named()`
                 }
                 Some(lvalue) => {
                     if var_arg {
                         self.semantic_error(ParameterAfterVarParameter);
                     }
-                    args.push(lvalue)
+                    Some(lvalue)
                 },
-                _ => self.error_with(ExpectedParameterOrEndOfList),
-            }
+                _ => {
+                    self.error_with(ExpectedParameterOrEndOfList);
+                    None
+                },
+            };
 
             // Default Arguments
             match self.peek() {
+                // The variadic parameter already has a default value (an empty vector), so it cannot be given another
+                Some(QuestionMark | Equals) if var_param => {
+                    let token: Option<ScanToken> = self.advance();
+                    self.semantic_error(DefaultValueForVarParameter);
+                    if token == Some(Equals) {
+                        let _ = self.parse_expr_top_level(); // Parse and discard the expression, to continue parsing the parameters
+                    }
+                },
                 // Sugar for `= nil`, so mark this as a default argument
                 Some(QuestionMark) => {
                     self.skip(); // Consume `?`
-                    default_args.push(Expr(Location::empty(), ExprType::Nil));
+                    self.push(Nil);
+                    self.current_function_impl().mark_default_arg();
+                    has_default_args = true;
                 },
                 Some(Equals) => {
                     self.skip(); // Consume `=`
-                    default_args.push(self.parse_expr_top_level()); // Parse an expression
+                    // Parse an expression, which may reference any parameters declared so far
+                    // This excludes assignments, as otherwise `fn f(a, b = a, c = 1)` would parse `a, c = 1` as a pattern assignment
+                    let default: Expr = self.parse_expr_9();
+                    self.emit_optimized_expr(default);
+                    self.current_function_impl().mark_default_arg();
+                    has_default_args = true;
                 },
-                _ => if !var_arg && !default_args.is_empty() {
+                _ => if !var_arg && has_default_args {
                     self.semantic_error(NonDefaultParameterAfterDefaultParameter);
                 },
             }
 
+            // Only now is the parameter declared, so it cannot be referenced by its own default value
+            if let Some(mut arg) = arg {
+                let location: Location = start | self.prev_location();
+                self.current_function_impl().declare_arg(arg.to_code_str(), var_param, location);
+                let local: Option<usize> = arg.declare_single_local(self);
+                if local.is_none() {
+                    arg.initialize_locals(self);
+                }
+                args.push((arg, local));
+            }
+
             if self.parse_optional_trailing_comma(CloseParen, ExpectedCommaOrEndOfParameters) {
                 break
             }
         }
-        (args, default_args, var_arg)
+        args
     }
 
-    fn parse_function_body(&mut self, args: Vec<LValue>, default_args: Vec<Expr>) -> Vec<Opcode> {
+    /// Parses the parameters and body of the current function, i.e. `(<args>) <body>`, and returns the opcodes for any closed locals.
+    fn parse_function_body(&mut self) -> Vec<Opcode> {
         trace::trace_parser!("rule <function-body>");
         let prev_pop_status: bool = self.delay_pop_from_expression_statement; // Stack semantics for the delayed pop
+        let func: usize = self.functions.len() - 1;
+
+        // Any names captured by value are resolved in the enclosing function, before the function's own locals are declared
+        let captures: Vec<(String, u32)> = self.parse_function_captures(func);
 
         // Functions have their own depth tracking in addition to scope
         // In addition, we let parameters have their own scope depth one outside locals to the function
         // This lets us 1) declare parameters here, in the right scope,
         // and 2) avoid popping parameters at the end of a function call (as they're handled by the `Return` opcode instead)
-        self.locals.push(Locals::new(Some(self.functions.len() - 1)));
+        self.locals.push(Locals::new(Some(func)));
+        self.declare_function_captures(captures);
         self.function_depth += 1;
         self.scope_depth += 1;
 
         // After the locals have been pushed, we now can push function code
         // Before the body of the function, we emit code for each default argument, and mark it as such.
-        for arg in default_args {
-            self.emit_optimized_expr(arg);
-            self.current_function_impl().mark_default_arg();
-        }
-
-        // Collect arguments into pairs of the lvalue, and associated synthetic
-        let mut args_with_synthetics: Vec<(LValue, Option<usize>)> = args.into_iter()
-            .map(|mut arg| {
-                let local = arg.declare_single_local(self);
-                (arg, local)
-            })
-            .collect::<Vec<(LValue, Option<usize>)>>();
+        let start: Location = self.next_location();
+        self.expect(OpenParen);
+        let mut args_with_synthetics: Vec<(LValue, Option<usize>)> = self.parse_function_parameters();
+        self.expect_resync(CloseParen);
+        let location: Location = start | self.prev_location();
+        self.current_function_impl().declare_location(location);
 
         // Declare pattern locals as locals immediately after arguments.
         // Once declared, initialize all (referencable) locals - so not synthetics.
@@ -533,7 +893,14 @@ impl Parser<'_> {
             },
             Some(Arrow) => {
                 self.advance(); // Expression-based function
-                self.parse_expression(); // So parse an expression
+                match self.peek() {
+                    // `-> return <expr>` is identical to `-> <expr>`, as the expression is returned either way
+                    Some(KeywordReturn) => {
+                        self.advance();
+                        self.parse_return_value();
+                    },
+                    _ => self.parse_expression(), // So parse an expression
+                }
                 false
             },
             _ => {
@@ -562,7 +929,8 @@ impl Parser<'_> {
 
         // If this function has captured any upvalues, we need to emit the correct tokens for them now, including wrapping the function in a closure
         // We just collect and return the opcodes for it, as if this is part of an expression function, we need to hold them to be emitted later
-        let closed_locals: Vec<Opcode> = self.current_locals().closed_locals();
+        let closed_locals: Vec<Opcode> = self.current_locals().closed_locals(func);
+        self.declare_function_upvalue_names(func);
 
         self.delay_pop_from_expression_statement = prev_pop_status; // Exit the stack
 
@@ -573,8 +941,24 @@ impl Parser<'_> {
         trace::trace_parser!("rule <return-statement>");
         self.push_delayed_pop();
         self.advance(); // Consume `return`
-        match self.peek() {
-            Some(CloseBrace) => { // Allow a bare return, but only when followed by a `}` or `;`, which we can recognize and discard properly.
+        if self.function_depth == 0 {
+            self.semantic_error(ReturnOutsideOfFunction);
+        }
+        self.parse_return_value();
+        // Any `with` statements we are returning out of need to exit their contexts, after the return value is evaluated
+        self.push_exit_contexts(0);
+        // As the VM cleans up it's own call stack properly, by discarding everything above the function's frame when exiting,
+        // the only thing we need to do here is make sure we emit `LiftUpValue` opcodes.
+        self.pop_locals(None, false, false, true);
+        self.push(Return);
+    }
+
+    /// Parses the value following a `return` keyword, or pushes `nil` if there is none.
+    fn parse_return_value(&mut self) {
+        match self.peek_no_newline() {
+            // Allow a bare return, but only when followed by a `}`, `;`, or the end of the line, which we can recognize and discard properly.
+            // Without the end of the line, `return` followed by a statement on the next line would return the value of that statement.
+            Some(CloseBrace | NewLine | DocComment(_)) | None => {
                  self.push(Nil);
             },
             Some(Semicolon) => {
@@ -586,10 +970,6 @@ impl Parser<'_> {
                 self.parse_expression();
             }
         }
-        // As the VM cleans up it's own call stack properly, by discarding everything above the function's frame when exiting,
-        // the only thing we need to do here is make sure we emit `LiftUpValue` opcodes.
-        self.pop_locals(None, false, false, true);
-        self.push(Return);
     }
 
     // ===== Control Flow ===== //
@@ -762,7 +1142,7 @@ impl Parser<'_> {
         self.push(InitIterable);
 
         // Test
-        let jump: usize = self.begin_loop();
+        let jump: usize = self.begin_for_loop();
         let test_iterable = self.reserve();
 
         // Initialize locals
@@ -776,6 +1156,7 @@ impl Parser<'_> {
         // We want the variables declared in a `for` loop to be somewhat unique - if they get captured, we want them to be closed over each iteration of the loop
         // This effectively means there's a new heap-allocated variable for each iteration of the loop.
         // In order to do this, we just need to emit the proper `LiftUpValue` opcodes each iteration of the loop
+        // With `CompileFlags::per_iteration_loops`, any `break` or `continue` statements will also emit them, as they skip this point.
         self.pop_locals(Some(self.scope_depth), false, false, true);
 
         self.push_jump(jump, Jump);
@@ -803,7 +1184,10 @@ impl Parser<'_> {
         match self.current_locals_mut().top_loop() {
             Some(loop_stmt) => {
                 let depth: u32 = loop_stmt.scope_depth + 1;
-                self.pop_locals(Some(depth), false, true, true);
+                let lift_depth: u32 = loop_stmt.lift_depth();
+                self.push_exit_contexts(depth - 1);
+                self.pop_locals(Some(lift_depth), false, false, true);
+                self.pop_locals(Some(depth), false, true, false);
                 let jump = self.reserve();
                 self.current_locals_mut().top_loop().unwrap().break_statements.push(jump);
             },
@@ -819,13 +1203,74 @@ impl Parser<'_> {
             Some(loop_stmt) => {
                 let jump_to: usize = loop_stmt.start_index;
                 let depth: u32 = loop_stmt.scope_depth + 1;
-                self.pop_locals(Some(depth), false, true, true);
+                let lift_depth: u32 = loop_stmt.lift_depth();
+                self.push_exit_contexts(depth - 1);
+                self.pop_locals(Some(lift_depth), false, false, true);
+                self.pop_locals(Some(depth), false, true, false);
                 self.push_jump(jump_to, Jump);
             },
             None => self.semantic_error(ContinueOutsideOfLoop),
         }
     }
 
+    fn parse_with_statement(&mut self) {
+        trace::trace_parser!("rule <with-statement>");
+
+        // Translation:
+        // with <expr> as <name> {  | <expr> ; EnterContext -> <name>
+        //     <statements>         | <statements>
+        // }                        | ExitContext ; Pop
+
+        self.push_delayed_pop();
+        self.advance(); // Consume `with`
+
+        // The bound name, or the synthetic local holding the entered value, is within it's own scope
+        self.scope_depth += 1;
+
+        self.parse_expression();
+        self.push(EnterContext);
+
+        match self.peek() {
+            Some(KeywordAs) => {
+                self.advance(); // Consume `as`
+                match self.peek() {
+                    Some(Identifier(_)) => {
+                        let name: String = self.advance_identifier();
+                        match self.declare_local(name) {
+                            Some(local) => self.init_local(local),
+                            None => {
+                                self.declare_synthetic_local();
+                            },
+                        }
+                    },
+                    _ => {
+                        self.error_with(ExpectedNameAfterAs);
+                        self.declare_synthetic_local();
+                    },
+                }
+            },
+            _ => {
+                self.declare_synthetic_local();
+            },
+        }
+
+        self.begin_context();
+        self.parse_block_statement();
+        self.push_delayed_pop();
+        self.end_context();
+
+        self.push(ExitContext);
+        self.pop_locals(Some(self.scope_depth), true, true, true);
+        self.scope_depth -= 1;
+    }
+
+    /// Emits an `ExitContext` for each `with` statement which is nested deeper than the scope `depth`, i.e. which a `break`, `continue` or `return` would leave.
+    fn push_exit_contexts(&mut self, depth: u32) {
+        for _ in 0..self.current_locals().contexts_above(depth) {
+            self.push(ExitContext);
+        }
+    }
+
     fn parse_assert_statement(&mut self) {
         trace::trace_parser!("rule <assert-statement>");
         self.push_delayed_pop();
@@ -1018,13 +1463,28 @@ impl Parser<'_> {
     fn parse_expr_1_terminal(&mut self) -> Expr {
         trace::trace_parser!("rule <expr-1>");
         match self.peek() {
-            Some(KeywordNil) => { self.advance(); Expr::nil() },
-            Some(KeywordTrue) => { self.advance(); Expr::bool(true) },
-            Some(KeywordFalse) => { self.advance(); Expr::bool(false) },
-            Some(KeywordExit) => { self.advance(); Expr::exit() },
-            Some(IntLiteral(i)) => { let i = *i; self.advance(); Expr::int(i) },
-            Some(ComplexLiteral(i)) => { let i = *i; self.advance(); Expr::complex(i) },
-            Some(StringLiteral(_)) => Expr::str(self.advance_str()),
+            Some(KeywordNil) => Expr::nil().at(self.advance_with()),
+            Some(KeywordTrue) => Expr::bool(true).at(self.advance_with()),
+            Some(KeywordFalse) => Expr::bool(false).at(self.advance_with()),
+            Some(KeywordExit) => {
+                let loc_start = self.advance_with();
+                match self.peek_no_newline() {
+                    // `exit(code)` exits with a specific exit code, and must be on the same line, like a function call
+                    Some(OpenParen) => {
+                        self.advance();
+                        let code: Expr = self.parse_expr_top_level();
+                        self.expect(CloseParen);
+                        Expr::exit_with(loc_start | self.prev_location(), code)
+                    },
+                    _ => Expr::exit().at(loc_start),
+                }
+            },
+            Some(IntLiteral(i)) => { let i = *i; Expr::int(i).at(self.advance_with()) },
+            Some(ComplexLiteral(i)) => { let i = *i; Expr::complex(i).at(self.advance_with()) },
+            Some(StringLiteral(_)) => {
+                let it: String = self.advance_str();
+                Expr::str(it).at(self.prev_location())
+            },
             Some(Identifier(_)) => {
                 let name: String = self.advance_identifier();
                 let loc: Location = self.prev_location();
@@ -1056,7 +1516,7 @@ impl Parser<'_> {
             },
             Some(OpenSquareBracket) => self.parse_expr_1_list_or_slice_literal(),
             Some(OpenBrace) => self.parse_expr_1_dict_or_set_literal(),
-            Some(KeywordFn) => self.parse_expression_function(),
+            Some(KeywordFn | KeywordAsync) => self.parse_expression_function(),
             Some(KeywordIf) => self.parse_expr_1_inline_if_then_else(),
             _ => {
                 self.error_with(ExpectedExpressionTerminal);
@@ -1113,6 +1573,27 @@ impl Parser<'_> {
             Some(DoubleEquals) => binary = Some(OperatorEqual),
             Some(NotEquals) => binary = Some(OperatorNotEqual),
 
+            // Custom operators are resolved to the function declared with their name.
+            // `(<+>)` is the function itself, and `(<+> x)` is partially evaluated on the *second* argument, which is done with a placeholder, i.e. `(<+>)(_, x)`
+            Some(CustomOperator(_)) => {
+                let loc = self.next_location();
+                let name: String = self.advance_custom_operator();
+                let lvalue: LValueReference = self.resolve_identifier(name);
+                let op: Expr = Expr::lvalue(loc, lvalue);
+                return match self.peek() {
+                    Some(CloseParen) => {
+                        self.advance();
+                        Some(op)
+                    },
+                    _ => {
+                        let arg = self.parse_expr_top_level();
+                        self.expect(CloseParen);
+                        let func: u32 = self.declare_placeholder_function(loc, &[true, false], false);
+                        Some(Expr::function(func, Vec::new()).eval(loc, vec![op, arg], false))
+                    }
+                }
+            },
+
             // This is a unique case, as we can only partially evaluate this with an identifier, not an expression.
             // It also involves a unique operator, as it cannot be evaluated as a normal native operator (since again, it takes a field index, not a expression)
             // This operator also cannot stand alone: `(->)` is not valid, but `(->x)` is, presuming a field `x` exists.
@@ -1200,6 +1681,15 @@ impl Parser<'_> {
             Some(DoubleEquals) => OperatorEqual,
             Some(NotEquals) => OperatorNotEqual,
 
+            // `(x <+>)` is partially evaluated on the first argument, so it is just `(<+>)(x)`
+            Some(CustomOperator(_)) if self.peek2() == Some(&CloseParen) => {
+                let loc = self.next_location();
+                let name: String = self.advance_custom_operator();
+                let lvalue: LValueReference = self.resolve_identifier(name);
+                self.expect(CloseParen);
+                return Ok(Expr::lvalue(loc, lvalue).eval(loc, vec![expr], false))
+            },
+
             _ => return Err(expr),
         };
 
@@ -1386,9 +1876,17 @@ impl Parser<'_> {
         trace::trace_parser!("rule <expr-2>");
 
         let stack: Vec<(Location, UnaryOp)> = self.parse_expr_2_prefix_operators();
-        let mut expr: Expr = self.parse_expr_1_terminal();
-
-        expr = self.parse_expr_2_suffix_operators(expr);
+        let mut expr: Expr = match self.peek() {
+            // `await` is lower precedence than suffix operators, so `await f(x)` awaits the result of `f(x)`, but higher than other prefix operators
+            Some(KeywordAwait) => {
+                let loc = self.advance_with();
+                self.parse_expr_2_unary().await_task(loc)
+            },
+            _ => {
+                let expr: Expr = self.parse_expr_1_terminal();
+                self.parse_expr_2_suffix_operators(expr)
+            },
+        };
 
         // Prefix operators are lower precedence than suffix operators
         for (loc, op) in stack.into_iter().rev() {
@@ -1531,9 +2029,12 @@ impl Parser<'_> {
                 Some(Identifier(_)) if self.peek2() != Some(&Equals) => {
                     expr = self.parse_expr_2_bare_suffix(expr);
                 },
-                Some(KeywordFn) if self.peek2() == Some(&OpenParen) => {
+                Some(KeywordFn) if matches!(self.peek2(), Some(OpenParen | OpenSquareBracket)) => {
                     expr = self.parse_expr_2_bare_suffix(expr);
                 }
+                Some(Dot) if self.peek_method_call() => {
+                    expr = self.parse_expr_2_method_call(expr);
+                },
                 Some(KeywordNil | KeywordTrue | KeywordFalse | KeywordExit | IntLiteral(_) | ComplexLiteral(_) | StringLiteral(_) | At | KeywordIf) => {
                     expr = self.parse_expr_2_bare_suffix(expr);
                 },
@@ -1558,20 +2059,95 @@ impl Parser<'_> {
     }
 
     fn parse_expr_2_unary_function_call(&mut self, loc_start: Location, expr: Expr) -> Expr {
-        // First argument
+        let (args, any_unroll) = self.parse_expr_2_arguments();
+        let loc = loc_start | self.prev_location();
+        if args.iter().all(Option::is_some) {
+            return expr.eval(loc, args.into_iter().map(Option::unwrap).collect(), any_unroll)
+        }
+        if any_unroll {
+            self.semantic_error(UnrollNotAllowedWithPlaceholder);
+        }
+        self.parse_expr_2_placeholder_function_call(loc, expr, args)
+    }
+
+    /// Parses a method call, `<expr>.<name>(<args>)`, where there is no whitespace between the `.`, and either the preceding expression, or the name.
+    /// This calls `name` with the arguments, followed by the preceding expression, so `xs.map(f)` is the same as `map(f, xs)`.
+    ///
+    /// Unlike composition with `xs . map(f)`, this is high precedence, like a function call, and the function is called with all arguments at once, instead of by partially evaluating `map(f)`.
+    /// Placeholders may also be used, in which case the preceding expression is passed after the given arguments, and the result awaits the placeholders.
+    fn parse_expr_2_method_call(&mut self, expr: Expr) -> Expr {
+        trace::trace_parser!("rule <expr-2-method-call>");
+
+        let loc_start = self.advance_with(); // Consume `.`
+        let name: String = self.advance_identifier();
+        let f: Expr = {
+            let loc = self.prev_location();
+            let lvalue = self.resolve_identifier(name);
+            Expr::lvalue(loc, lvalue)
+        };
+        self.advance(); // Consume `(`
+
+        let (args, any_unroll) = match self.peek() {
+            Some(CloseParen) => {
+                self.advance();
+                (Vec::new(), false)
+            },
+            // Like a function call, a partially-evaluated operator can be written without the outer parenthesis, i.e. `xs.reduce(+)`
+            _ => match self.parse_expr_1_partial_operator_left() {
+                Some(partial_expr) => (vec![Some(partial_expr)], false),
+                None => self.parse_expr_2_arguments(),
+            },
+        };
+        let loc = loc_start | self.prev_location();
+        if any_unroll {
+            self.semantic_error(UnrollNotAllowedInMethodCall);
+        }
+        if args.iter().all(Option::is_some) {
+            return expr.method_call(loc, f, args.into_iter().map(Option::unwrap).collect())
+        }
+        let placeholders: Vec<bool> = args.iter().map(Option::is_none).collect();
+        let func: u32 = self.declare_placeholder_function(loc, &placeholders, true);
+
+        let mut partial_args: Vec<Expr> = vec![f];
+        partial_args.extend(args.into_iter().flatten());
+        expr.compose(loc, Expr::function(func, Vec::new()).eval(loc, partial_args, false))
+    }
+
+    /// Parses the arguments of a function call, up to and including the closing `)`. A `_` placeholder argument is returned as `None`.
+    /// Returns the arguments, and if any arguments were unrolled.
+    fn parse_expr_2_arguments(&mut self) -> (Vec<Option<Expr>>, bool) {
         let mut any_unroll: bool = false;
-        let mut args: Vec<Expr> = Vec::new();
+        let mut args: Vec<Option<Expr>> = Vec::new();
 
         loop {
-            args.push(self.parse_expr_top_level_or_unrolled(&mut any_unroll));
+            // A bare `_` as an argument is a placeholder, i.e. `f(_, 2)`, which is left unevaluated
+            match (self.peek(), self.peek2()) {
+                (Some(Underscore), Some(Comma | CloseParen)) => {
+                    self.skip();
+                    args.push(None);
+                },
+                _ => args.push(Some(self.parse_expr_top_level_or_unrolled(&mut any_unroll))),
+            }
             if self.parse_optional_trailing_comma(CloseParen, ExpectedCommaOrEndOfArguments) {
                 break;
             }
         }
 
         self.expect_resync(CloseParen);
+        (args, any_unroll)
+    }
+
+    /// Creates a partially evaluated function call from a call with placeholder (`_`) arguments, i.e. `f(_, 2)`.
+    /// See `declare_placeholder_function()` for how the partial function is created.
+    fn parse_expr_2_placeholder_function_call(&mut self, loc: Location, expr: Expr, args: Vec<Option<Expr>>) -> Expr {
+        trace::trace_parser!("rule <expr-2-placeholder-function-call>");
 
-        expr.eval(loc_start | self.prev_location(), args, any_unroll)
+        let placeholders: Vec<bool> = args.iter().map(Option::is_none).collect();
+        let func: u32 = self.declare_placeholder_function(loc, &placeholders, false);
+
+        let mut partial_args: Vec<Expr> = vec![expr];
+        partial_args.extend(args.into_iter().flatten());
+        Expr::function(func, Vec::new()).eval(loc, partial_args, false)
     }
 
     /// Parses a `-> <field>` - either returns a `(Location, field_index)` pairing, or `None` and raises a parse error.
@@ -1694,6 +2270,18 @@ impl Parser<'_> {
                 Some(BitwiseAnd) => Some(BinaryOp::And),
                 Some(BitwiseOr) => Some(BinaryOp::Or),
                 Some(BitwiseXor) => Some(BinaryOp::Xor),
+                Some(CustomOperator(_)) => {
+                    // Custom operators share the same precedence as bitwise operators, and are resolved to a call of the function declared with their name
+                    if self.peek2() == Some(&CloseParen) {
+                        break
+                    }
+                    let loc = self.next_location();
+                    let name: String = self.advance_custom_operator();
+                    let lvalue: LValueReference = self.resolve_identifier(name);
+                    let rhs: Expr = self.parse_expr_5();
+                    expr = Expr::lvalue(loc, lvalue).eval(loc, vec![expr, rhs], false);
+                    continue
+                },
                 _ => None
             };
             if maybe_op.is_some() && self.peek2() == Some(&CloseParen) {
@@ -1753,7 +2341,7 @@ impl Parser<'_> {
 
     fn parse_expr_9(&mut self) -> Expr {
         trace::trace_parser!("rule <expr-9>");
-        let mut expr: Expr = self.parse_expr_8();
+        let mut expr: Expr = self.parse_expr_9_not();
         loop {
             let maybe_op: Option<BinaryOp> = match self.peek() {
                 Some(LogicalAnd) => Some(BinaryOp::And), // Just markers
@@ -1766,7 +2354,7 @@ impl Parser<'_> {
             match maybe_op {
                 Some(op) => {
                     let loc = self.advance_with();
-                    expr = expr.logical(loc, op, self.parse_expr_8());
+                    expr = expr.logical(loc, op, self.parse_expr_9_not());
                 },
                 _ => break
             }
@@ -1774,6 +2362,20 @@ impl Parser<'_> {
         expr
     }
 
+    /// Parses a prefix `not`, which is lower precedence than comparisons, but higher than `and` and `or`, so `not a == b and c` is `(not (a == b)) and c`
+    /// Unlike `!`, this always takes the truthy value of its argument, so it is emitted as `if <expr> then false else true`
+    fn parse_expr_9_not(&mut self) -> Expr {
+        trace::trace_parser!("rule <expr-9-not>");
+        match self.peek() {
+            Some(KeywordNot) => {
+                let loc = self.advance_with();
+                let arg: Expr = self.parse_expr_9_not();
+                arg.if_then_else(loc, Expr::bool(false).at(loc), Expr::bool(true).at(loc))
+            },
+            _ => self.parse_expr_8()
+        }
+    }
+
     fn parse_expr_10(&mut self) -> Expr {
         trace::trace_parser!("rule <expr-10>");
         let mut expr: Expr = self.parse_expr_10_pattern_lvalue();
@@ -1908,6 +2510,22 @@ impl Parser<'_> {
     }
 }
 
+/// Returns the member offset for a member named `name`, declared in the body of a struct. If the member is inherited, and not yet replaced, it replaces the inherited member at the same offset.
+/// Otherwise, it is appended to `member_names`. Returns `None` if the struct has already declared a member with this name.
+fn struct_member_offset(member_names: &mut Vec<String>, inherited: &mut Vec<String>, name: String) -> Option<usize> {
+    match inherited.iter().position(|member| member == &name) {
+        Some(index) => {
+            inherited.swap_remove(index);
+            member_names.iter().position(|member| member == &name)
+        },
+        None if member_names.contains(&name) => None,
+        None => {
+            member_names.push(name);
+            Some(member_names.len() - 1)
+        },
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -1919,6 +2537,7 @@ mod tests {
     #[test] fn test_false() { run_expr("false", "False") }
     #[test] fn test_int() { run_expr("123", "Int(123)") }
     #[test] fn test_imaginary() { run_expr("123i", "Complex(123i)") }
+    #[test] fn test_exit_with_code() { run_expr("exit(1 + 2)", "Int(1) Int(2) Add ExitWith") }
     #[test] fn test_complex() { run_expr("123 + 456i", "Int(123) Complex(456i) Add") }
     #[test] fn test_str() { run_expr("'abc'", "Str('abc')") }
     #[test] fn test_print() { run_expr("print", "Print") }
@@ -1944,6 +2563,11 @@ mod tests {
     #[test] fn test_binary_and() { run_expr("1 and 2", "Int(1) JumpIfFalse(4) Pop Int(2)"); }
     #[test] fn test_binary_and_or() { run_expr("1 and (2 or 3)", "Int(1) JumpIfFalse(7) Pop Int(2) JumpIfTrue(7) Pop Int(3)"); }
     #[test] fn test_binary_or() { run_expr("1 or 2", "Int(1) JumpIfTrue(4) Pop Int(2)"); }
+    #[test] fn test_keyword_not() { run_expr("not 1", "Int(1) JumpIfFalsePop(4) False Jump(5) True") }
+    #[test] fn test_keyword_not_not() { run_expr("not not 1", "Int(1) JumpIfFalsePop(4) False Jump(5) True JumpIfFalsePop(8) False Jump(9) True") }
+    #[test] fn test_keyword_not_lower_than_comparison() { run_expr("not 1 < 2", "Int(1) Int(2) LessThan JumpIfFalsePop(6) False Jump(7) True") }
+    #[test] fn test_keyword_not_higher_than_and() { run_expr("not 1 and 2", "Int(1) JumpIfFalsePop(4) False Jump(5) True JumpIfFalse(8) Pop Int(2)") }
+    #[test] fn test_keyword_not_in_rhs_of_or() { run_expr("1 or not 2", "Int(1) JumpIfTrue(8) Pop Int(2) JumpIfFalsePop(7) False Jump(8) True") }
     #[test] fn test_binary_or_and() { run_expr("1 or (2 and 3)", "Int(1) JumpIfTrue(7) Pop Int(2) JumpIfFalse(7) Pop Int(3)"); }
     #[test] fn test_binary_equal() { run_expr("1 == 2", "Int(1) Int(2) Equal") }
     #[test] fn test_binary_equal_add() { run_expr("1 == 2 + 3", "Int(1) Int(2) Int(3) Add Equal") }
@@ -1985,15 +2609,23 @@ mod tests {
 
     #[test] fn test_let_eof() { run_err("let", "Expected a variable binding, either a name, or '_', or pattern (i.e. 'x, (_, y), *z'), got end of input instead\n  at: line 1 (<test>)\n\n1 | let\n2 |     ^^^\n"); }
     #[test] fn test_let_no_identifier() { run_err("let =", "Expected a variable binding, either a name, or '_', or pattern (i.e. 'x, (_, y), *z'), got '=' token instead\n  at: line 1 (<test>)\n\n1 | let =\n2 |     ^\n"); }
+    #[test] fn test_str_invalid_escape() { run_err(r"'\q'", "Invalid escape sequence: '\\q'\n  at: line 1 (<test>)\n\n1 | '\\q'\n2 |  ^^\n"); }
+    #[test] fn test_str_invalid_escape_in_middle() { run_err(r"'a\zb'", "Invalid escape sequence: '\\z'\n  at: line 1 (<test>)\n\n1 | 'a\\zb'\n2 |   ^^\n"); }
+    #[test] fn test_int_literal_too_large() { run_err("let x = 4611686018427387904", "Invalid numeric value: number too large to fit in target type\n  at: line 1 (<test>)\n\n1 | let x = 4611686018427387904\n2 |         ^^^^^^^^^^^^^^^^^^^\n"); }
     #[test] fn test_let_expression_eof() { run_err("let x =", "Expected an expression terminal, got end of input instead\n  at: line 1 (<test>)\n\n1 | let x =\n2 |         ^^^\n"); }
     #[test] fn test_let_no_expression() { run_err("let x = &", "Expected an expression terminal, got '&' token instead\n  at: line 1 (<test>)\n\n1 | let x = &\n2 |         ^\n"); }
+    #[test] fn test_parameter_after_var_parameter() { run_err("fn f(*a, *b) {}", "Variadic parameter must be the last one in the function.\n  at: line 1 (<test>)\n\n1 | fn f(*a, *b) {}\n2 |           ^\n"); }
+    #[test] fn test_default_value_for_var_parameter() { run_err("fn f(*a = 3) {}", "Variadic parameter cannot have a default value.\n  at: line 1 (<test>)\n\n1 | fn f(*a = 3) {}\n2 |         ^\n"); }
+    #[test] fn test_nil_default_value_for_var_parameter() { run_err("fn f(a, *b?) {}", "Variadic parameter cannot have a default value.\n  at: line 1 (<test>)\n\n1 | fn f(a, *b?) {}\n2 |           ^\n"); }
     #[test] fn test_expression_function_with_name() { run_err("(fn hello() {})", "Expected a '(' token, got identifier 'hello' instead\n  at: line 1 (<test>)\n\n1 | (fn hello() {})\n2 |     ^^^^^\n"); }
     #[test] fn test_top_level_function_in_error_recovery_mode() { run_err("+ fn hello() {}", "Expected an expression terminal, got '+' token instead\n  at: line 1 (<test>)\n\n1 | + fn hello() {}\n2 | ^\n"); }
 
     #[test] fn test_array_access_after_newline() { run("array_access_after_newline"); }
     #[test] fn test_array_access_no_newline() { run("array_access_no_newline"); }
+    #[test] fn test_async_await() { run("async_await"); }
     #[test] fn test_bare_eval() { run("bare_eval"); }
     #[test] fn test_break_past_locals() { run("break_past_locals"); }
+    #[test] fn test_closure_capture_by_value() { run("closure_capture_by_value"); }
     #[test] fn test_constants() { run("constants"); }
     #[test] fn test_continue_past_locals() { run("continue_past_locals"); }
     #[test] fn test_empty() { run("empty"); }
@@ -2042,8 +2674,11 @@ mod tests {
     #[test] fn test_while_2() { run("while_2"); }
     #[test] fn test_while_3() { run("while_3"); }
     #[test] fn test_while_4() { run("while_4"); }
+    #[test] fn test_verbose_disassembly() { run_with("verbose_disassembly", compiler::DisassemblyOptions { verbose: true, ..Default::default() }); }
     #[test] fn test_while_else() { run("while_else"); }
     #[test] fn test_while_false_if_false() { run("while_false_if_false"); }
+    #[test] fn test_with() { run("with"); }
+    #[test] fn test_with_break_return() { run("with_break_return"); }
 
 
     fn run_expr(text: &'static str, expected: &'static str) {
@@ -2063,10 +2698,14 @@ mod tests {
     }
 
     fn run(path: &'static str) {
+        run_with(path, compiler::DisassemblyOptions::default())
+    }
+
+    fn run_with(path: &'static str, options: compiler::DisassemblyOptions) {
         let resource = test_util::get_resource("parser", path);
         let view: SourceView = resource.view();
         let actual: Vec<String> = match compiler::compile(false, &view) {
-            Ok(compile) => compile.disassemble(&view, true),
+            Ok(compile) => compile.disassemble(&view, &options),
             Err(err) => err
         };
 
@@ -1,6 +1,8 @@
 use crate::compiler::parser::expr::{Expr, ExprType};
+use crate::core;
 use crate::core::NativeFunction;
-use crate::vm::{IntoValue, LiteralType, MAX_INT, MIN_INT, RuntimeError, ValuePtr};
+use crate::reporting::Location;
+use crate::vm::{IntoValue, LiteralType, MAX_INT, MIN_INT, Opcode, RuntimeError, Type, ValuePtr};
 use crate::vm::operator::BinaryOp;
 
 /// A trait for objects which are able to be optimized via a recursive self-transformation
@@ -22,21 +24,29 @@ impl Optimize for Expr {
     /// with full view of the expression tree. It includes the following passes:
     ///
     /// - Constant Folding + Dead Code Elimination (`1 + 2` -> `3`)
+    /// - Constant Folding of pure native functions (`ord('a')` -> `97`)
     /// - Compose/List Folding (`a . [b]` -> `a[b]`)
+    /// - Index/Slice Section Inlining (`[b](a)` -> `a[b]` where legal)
     /// - Compose/Eval Reordering (`a . b` -> `b(a)` where legal)
+    /// - Method Call/Eval Reordering (`a.b(c)` -> `b(c, a)` where legal)
     /// - Consistent Function Eval Merging (`a(b1, b2, ...)(c1, c2, ...)` -> `a(b1, b2, ... c1, c2, ...)` where legal)
     /// - Inlining of partially evaluated operators (`(==)(a, b)` -> `a == b`)
+    /// - Unrolled Literal Flattening (`[a, ...[b, c]]` -> `[a, b, c]`)
     ///
     fn optimize(self) -> Self {
         match self {
             // Terminals
             e @ Expr(_, ExprType::Nil | ExprType::Exit | ExprType::Bool(_) | ExprType::Int(_) | ExprType::Str(_) | ExprType::LValue(_) | ExprType::Function(_, _) | ExprType::NativeFunction(_)) => e,
 
+            Expr(loc, ExprType::ExitWith(code)) => Expr::exit_with(loc, code.optimize()),
+            Expr(loc, ExprType::Await(arg)) => arg.optimize().await_task(loc),
+
             // Unary Operators
             Expr(loc, ExprType::Unary(op, arg)) => {
                 let arg: Expr = arg.optimize();
+                let span: Location = span(&[loc, arg.0]);
                 match arg.into_const() {
-                    Ok(arg) => Expr::value_result(loc, op.apply(arg)),
+                    Ok(arg) => Expr::value_result(loc, span, op.apply(arg)),
                     Err(arg) => arg.unary(loc, op)
                 }
             },
@@ -45,16 +55,17 @@ impl Optimize for Expr {
             Expr(loc, ExprType::Binary(op, lhs, rhs, swap)) => {
                 let lhs: Expr = lhs.optimize();
                 let rhs: Expr = rhs.optimize();
+                let (lhs_loc, span) = (lhs.0, span(&[lhs.0, loc, rhs.0]));
                 match lhs.into_const() {
                     Ok(lhs) => match rhs.into_const() {
-                        Ok(rhs) => Expr::value_result(loc, if swap { op.apply(rhs, lhs) } else { op.apply(lhs, rhs) }),
-                        Err(rhs) => Expr::value(lhs).binary(loc, op, rhs, swap),
+                        Ok(rhs) => Expr::value_result(loc, span, if swap { op.apply(rhs, lhs) } else { op.apply(lhs, rhs) }),
+                        Err(rhs) => Expr::value(lhs).at(lhs_loc).binary(loc, op, rhs, swap),
                     },
                     Err(lhs) => lhs.binary(loc, op, rhs, swap),
                 }
             },
 
-            Expr(loc, ExprType::Literal(op, args)) => Expr(loc, ExprType::Literal(op, args.optimize())),
+            Expr(loc, ExprType::Literal(op, args)) => Expr(loc, ExprType::Literal(op, flatten_unrolled_literals(op, args.optimize()))),
             Expr(loc, ExprType::Unroll(arg, first)) => arg.optimize().unroll(loc, first),
 
             Expr(loc, ExprType::Eval(f, args, any_unroll)) => {
@@ -87,12 +98,52 @@ impl Optimize for Expr {
                     // This is a special case, for `min(int)` and `max(int)`, we can replace this with a compile time constant
                     Expr(_, ExprType::NativeFunction(native_f @ (NativeFunction::Min | NativeFunction::Max))) if nargs == Some(1) => {
                         if let Expr(_, ExprType::NativeFunction(NativeFunction::Int)) = args[0] {
-                            Expr::int(if native_f == NativeFunction::Min { MIN_INT } else { MAX_INT })
+                            Expr::int(if native_f == NativeFunction::Min { MIN_INT } else { MAX_INT }).at(loc)
                         } else {
                             f.eval(loc, args, any_unroll)
                         }
                     },
 
+                    // Found a call to a pure native function, which may be evaluated at compile time if all arguments are constant
+                    // If evaluating it would raise an error, or does not produce a constant, then the call is kept, and evaluated at runtime as normal
+                    Expr(_, ExprType::NativeFunction(native_f)) if native_f.is_pure() && nargs.is_some() => {
+                        let value: Option<ValuePtr> = args.iter()
+                            .map(|arg| arg.as_const())
+                            .collect::<Option<Vec<ValuePtr>>>()
+                            .and_then(|args| core::invoke_const(native_f, &args))
+                            .and_then(|value| value.as_result().ok())
+                            .filter(|value| matches!(value.ty(), Type::Nil | Type::Bool | Type::Int | Type::Complex | Type::Str));
+                        match value {
+                            Some(value) => {
+                                let locs: Vec<Location> = args.iter().map(|arg| arg.0).chain([f.0, loc]).collect();
+                                Expr::value(value).at(span(&locs))
+                            },
+                            None => f.eval(loc, args, any_unroll),
+                        }
+                    },
+
+                    // Found `[b](arg)`, i.e. an index section. If we can evaluate `arg` before `b`, then inline as `arg[b]`
+                    // A list with any other length is left as-is, so the error is raised at runtime
+                    Expr(_, ExprType::Literal(LiteralType::List, ref index)) if nargs == Some(1) && index.len() == 1 && !index[0].is_unroll() && f.can_reorder(&args[0]) => {
+                        let index: Expr = match f {
+                            Expr(_, ExprType::Literal(_, mut index)) => index.swap_remove(0),
+                            _ => panic!(),
+                        };
+                        args.swap_remove(0).index(loc, index).optimize()
+                    },
+
+                    // Found `[a:b:c](arg)`, i.e. a slice section. Inline as `arg[a:b:c]`, under the same condition as above
+                    Expr(_, ExprType::SliceLiteral(..)) if nargs == Some(1) && f.can_reorder(&args[0]) => {
+                        let arg: Expr = args.swap_remove(0);
+                        match f {
+                            Expr(_, ExprType::SliceLiteral(a, b, c)) => match *c {
+                                Some(c) => arg.slice_step(loc, *a, *b, c),
+                                None => arg.slice(loc, *a, *b),
+                            },
+                            _ => panic!(),
+                        }
+                    },
+
                     // If we can assert the inner function is partial, then we can merge the two calls:
                     // - We know the function being called is partial with the given number of arguments, and
                     // - The call is not unrolled (because then we can never prove it is partial
@@ -157,6 +208,21 @@ impl Optimize for Expr {
                 }
             },
 
+            Expr(loc, ExprType::MethodCall(receiver, f, args)) => {
+                let receiver: Expr = receiver.optimize();
+                let f: Expr = f.optimize();
+                let mut args: Vec<Expr> = args.optimize();
+
+                // If the function and all arguments can be evaluated before the receiver, then replace `receiver.f(args)` with `f(args, receiver)`
+                // Then re-optimize the new `eval` expression
+                if f.can_reorder(&receiver) && args.iter().all(|arg| arg.can_reorder(&receiver)) {
+                    args.push(receiver);
+                    f.eval(loc, args, false).optimize()
+                } else {
+                    receiver.method_call(loc, f, args)
+                }
+            },
+
             Expr(loc, ExprType::LogicalAnd(lhs, rhs)) => lhs.optimize().logical(loc, BinaryOp::And, rhs.optimize()),
             Expr(loc, ExprType::LogicalOr(lhs, rhs)) => lhs.optimize().logical(loc, BinaryOp::Or, rhs.optimize()),
             Expr(loc, ExprType::Index(array, index)) => array.optimize().index(loc, index.optimize()),
@@ -203,6 +269,18 @@ impl Expr {
         }
     }
 
+    /// Returns the constant value of this expression, if it has one, as with `into_const()`
+    fn as_const(&self) -> Option<ValuePtr> {
+        match &self.1 {
+            ExprType::Nil => Some(ValuePtr::nil()),
+            ExprType::Bool(it) => Some(it.to_value()),
+            ExprType::Int(it) => Some(it.to_value()),
+            ExprType::Complex(it) => Some(it.to_value()),
+            ExprType::Str(it) => Some(it.clone().to_value()),
+            _ => None
+        }
+    }
+
     fn can_reorder(&self, other: &Self) -> bool {
         match self.purity() {
             Purity::Strong => true,
@@ -213,21 +291,26 @@ impl Expr {
 
     fn purity(&self) -> Purity {
         match &self.1 {
-            ExprType::Nil | ExprType::Exit | ExprType::Bool(_) | ExprType::Int(_) | ExprType::Str(_) | ExprType::NativeFunction(_) | ExprType::Function(_, _) => Purity::Strong,
+            ExprType::Nil | ExprType::Exit | ExprType::Bool(_) | ExprType::Int(_) | ExprType::Str(_) | ExprType::NativeFunction(_) => Purity::Strong,
+            ExprType::Function(_, closed_locals) => match closed_locals.contains(&Opcode::CloseValue) {
+                true => Purity::Weak, // Reads the values it captures when created
+                false => Purity::Strong,
+            },
             ExprType::LValue(_) => Purity::Weak,
 
             ExprType::Unary(_, arg) => arg.purity(),
             ExprType::Binary(_, lhs, rhs, _) | ExprType::LogicalOr(lhs, rhs) | ExprType::LogicalAnd(lhs, rhs) => lhs.purity().min(rhs.purity()),
             ExprType::Literal(_, args) => args.iter().map(|u| u.purity()).min().unwrap_or(Purity::Strong),
             ExprType::Unroll(arg, _) => arg.purity(),
+            ExprType::SliceLiteral(a, b, c) => a.purity().min(b.purity()).min(c.as_ref().as_ref().map(|c| c.purity()).unwrap_or(Purity::Strong)),
             ExprType::IfThenElse(condition, if_true, if_false) => condition.purity().min(if_true.purity()).min(if_false.purity()),
 
-            ExprType::Eval(f, args, any_unroll) => match !*any_unroll && f.is_partial(args.len()) {
+            ExprType::Eval(f, args, any_unroll) => match !*any_unroll && (f.is_partial(args.len()) || f.is_pure(args.len())) {
                 true => f.purity().min(args.iter().map(|u| u.purity()).min().unwrap_or(Purity::Strong)),
                 false => Purity::None,
             },
 
-            ExprType::Compose(arg, f) => match f.is_partial(1) {
+            ExprType::Compose(arg, f) => match f.is_partial(1) || f.is_pure(1) {
                 true => f.purity().min(arg.purity()),
                 false => Purity::None,
             }
@@ -242,6 +325,70 @@ impl Expr {
             _ => false
         }
     }
+
+    /// Returns `true` if this is a pure native function, which would be fully evaluated with `nargs` arguments.
+    /// Note that like operators, the evaluation may still raise an error, but it has no other side effects.
+    fn is_pure(&self, nargs: usize) -> bool {
+        match &self.1 {
+            ExprType::NativeFunction(f) => f.is_pure() && f.min_nargs() as usize <= nargs && f.max_nargs().is_some_and(|max| nargs <= max as usize),
+            _ => false
+        }
+    }
+}
+
+/// Returns the smallest location containing each of `locs`, ignoring any which are empty
+fn span(locs: &[Location]) -> Location {
+    locs.iter()
+        .filter(|loc| !loc.is_empty())
+        .copied()
+        .reduce(|lhs, rhs| lhs | rhs)
+        .unwrap_or_else(Location::empty)
+}
+
+/// Returns the capacity to pre-size a literal with, given its (optimized) arguments.
+///
+/// This is exact where the final length can be proven at compile time: each plain argument contributes one element (or one half of an entry, for a `dict`),
+/// and unrolled string constants and literals contribute their length. Other unrolled arguments contribute nothing, as they are instead reserved for when unrolled at runtime.
+pub fn literal_size_hint(op: LiteralType, args: &[Expr]) -> u32 {
+    let size: usize = args.iter()
+        .map(|arg| match &arg.1 {
+            ExprType::Unroll(arg, _) => known_len(arg).unwrap_or(0),
+            _ => 1,
+        })
+        .sum();
+    (if op == LiteralType::Dict { size / 2 } else { size }) as u32
+}
+
+/// Flattens any unrolled `list` or `vector` literals directly within a literal, which do not contain unrolls themselves, into their arguments.
+/// This is not done for `dict` literals, where an unrolled argument contributes key-value pairs, rather than keys and values.
+fn flatten_unrolled_literals(op: LiteralType, args: Vec<Expr>) -> Vec<Expr> {
+    if op == LiteralType::Dict || !args.iter().any(|arg| matches!(arg, Expr(_, ExprType::Unroll(inner, _)) if is_flat_sequence(inner))) {
+        return args
+    }
+    let mut flat: Vec<Expr> = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg {
+            Expr(_, ExprType::Unroll(inner, _)) if is_flat_sequence(&inner) => match *inner {
+                Expr(_, ExprType::Literal(_, inner_args)) => flat.extend(inner_args),
+                _ => unreachable!(),
+            },
+            arg => flat.push(arg),
+        }
+    }
+    flat
+}
+
+/// Returns the number of elements `expr` would produce when unrolled, if it is known at compile time.
+fn known_len(expr: &Expr) -> Option<usize> {
+    match &expr.1 {
+        ExprType::Str(it) => Some(it.chars().count()),
+        ExprType::Literal(LiteralType::List | LiteralType::Vector, args) if !any_unroll(args) => Some(args.len()),
+        _ => None,
+    }
+}
+
+fn is_flat_sequence(expr: &Expr) -> bool {
+    matches!(expr, Expr(_, ExprType::Literal(LiteralType::List | LiteralType::Vector, args)) if !any_unroll(args))
 }
 
 fn any_unroll(args: &[Expr]) -> bool {
@@ -273,25 +420,49 @@ mod tests {
     #[test] fn test_constant_folding_constant_ternary_if_false() { run_expr("(if 1 + 1 == 3 then 'yes' else 'no')", "Str('no') Pop") }
     #[test] fn test_constant_folding_constant_ternary_top_level_if_true() { run_expr("if 1 + 1 == 3 then 'yes' else 'no'", "Str('no') Pop") }
     #[test] fn test_constant_folding_constant_ternary_top_level_if_false() { run_expr("if 1 + 1 == 3 then 'yes' else 'no'", "Str('no') Pop") }
+    #[test] fn test_constant_folding_native_ord() { run_expr("ord('a')", "Int(97) Pop") }
+    #[test] fn test_constant_folding_native_abs() { run_expr("abs(-3)", "Int(3) Pop") }
+    #[test] fn test_constant_folding_native_abs_out_of_range() { run_expr("abs(-4611686018427387903 - 1)", "Abs Int(-4611686018427387904) Call(1) Pop") }
+    #[test] fn test_constant_folding_native_compose() { run_expr("'abc' . len", "Int(3) Pop") }
+    #[test] fn test_constant_folding_native_method_call() { run_expr("'abc'.len()", "Int(3) Pop") }
+    #[test] fn test_constant_folding_native_nested() { run_expr("ord('z') . hex . to_upper", "Str('7A') Pop") }
+    #[test] fn test_constant_folding_native_two_args() { run_expr("int('x', -1) + parse_int('12', 0)", "Int(11) Pop") }
+    #[test] fn test_constant_folding_native_partial() { run_expr("const_(1)", "Const Int(1) Call(1) Pop") }
+    #[test] fn test_constant_folding_native_error() { run_expr("ord('ab')", "Ord Str('ab') Call(1) Pop") }
+    #[test] fn test_constant_folding_native_non_constant_result() { run_expr("fraction(1, 2)", "Fraction Int(1) Int(2) Call(2) Pop") }
+    #[test] fn test_constant_folding_native_non_constant_arg() { run_expr("do { let x ; ord(x) }", "Nil Ord PushLocal(0)->x Call(1) PopN(2)") }
+    #[test] fn test_constant_folding_native_impure() { run_expr("read_text('abc')", "ReadText Str('abc') Call(1) Pop") }
     #[test] fn test_compose_list_inlining() { run_expr("1 . [2]", "Int(1) Int(2) OpIndex Pop") }
     #[test] fn test_compose_slice_inlining_1() { run_expr("1 . [2:3]", "Int(1) Int(2) Int(3) OpSlice Pop") }
     #[test] fn test_compose_slice_inlining_2() { run_expr("1 . [2:3:4]", "Int(1) Int(2) Int(3) Int(4) OpSliceWithStep Pop") }
+    #[test] fn test_eval_list_inlining() { run_expr("[2](1)", "Int(1) Int(2) OpIndex Pop") }
+    #[test] fn test_eval_list_inlining_local() { run_expr("do { let x ; [0](x) }", "Nil PushLocal(0)->x Int(0) OpIndex PopN(2)") }
+    #[test] fn test_eval_list_inlining_impure() { run_expr("do { let x, y ; [x](y = 2) }", "Nil Nil LiteralBegin(List,1) PushLocal(0)->x LiteralAcc(1) LiteralEnd Int(2) StoreLocal(1)->y Call(1) PopN(3)") }
+    #[test] fn test_eval_list_no_inlining_wrong_length() { run_expr("[1, 2](3)", "LiteralBegin(List,2) Int(1) Int(2) LiteralAcc(2) LiteralEnd Int(3) Call(1) Pop") }
+    #[test] fn test_eval_slice_inlining_1() { run_expr("[2:3](1)", "Int(1) Int(2) Int(3) OpSlice Pop") }
+    #[test] fn test_eval_slice_inlining_2() { run_expr("[2:3:4](1)", "Int(1) Int(2) Int(3) Int(4) OpSliceWithStep Pop") }
     #[test] fn test_compose_reordering_pure_strong_strong() { run_expr("1 . 2", "Int(2) Int(1) Call(1) Pop") }
     #[test] fn test_compose_reordering_both_strong_weak() { run_expr("do { let x ; 1 . x }", "Nil PushLocal(0)->x Int(1) Call(1) PopN(2)") }
     #[test] fn test_compose_reordering_both_strong_impure() { run_expr("do { let x ; 1 . (x = 2) }", "Nil Int(2) StoreLocal(0)->x Int(1) Call(1) PopN(2)") }
     #[test] fn test_compose_reordering_both_weak_weak() { run_expr("do { let x, y ; x . y }", "Nil Nil PushLocal(1)->y PushLocal(0)->x Call(1) PopN(3)") }
     #[test] fn test_compose_reordering_both_weak_impure() { run_expr("do { let x, y ; x . (y = 2) }", "Nil Nil PushLocal(0)->x Int(2) StoreLocal(1)->y Swap Call(1) PopN(3)") }
     #[test] fn test_compose_reordering_both_impure_impure() { run_expr("do { let x, y ; (x = 1) . (y = 2) }", "Nil Nil Int(1) StoreLocal(0)->x Int(2) StoreLocal(1)->y Swap Call(1) PopN(3)") }
+    #[test] fn test_method_call_reordering_weak_strong() { run_expr("do { let x ; x.len() }", "Nil Len PushLocal(0)->x Call(1) PopN(2)") }
+    #[test] fn test_method_call_reordering_weak_weak() { run_expr("do { let x, y ; x.y(x) }", "Nil Nil PushLocal(1)->y PushLocal(0)->x PushLocal(0)->x Call(2) PopN(3)") }
+    #[test] fn test_method_call_reordering_weak_pure_native() { run_expr("do { let x, y ; x.split(trim(y)) }", "Nil Nil Split Trim PushLocal(1)->y Call(1) PushLocal(0)->x Call(2) PopN(3)") }
+    #[test] fn test_compose_no_reordering_weak_impure_native() { run_expr("do { let x, y ; x . hex(read_text(y)) }", "Nil Nil PushLocal(0)->x Hex ReadText PushLocal(1)->y Call(1) Call(1) Swap Call(1) PopN(3)") }
+    #[test] fn test_compose_reordering_weak_pure_native() { run_expr("do { let x, y ; x . hex(y) }", "Nil Nil Hex PushLocal(1)->y Call(1) PushLocal(0)->x Call(1) PopN(3)") }
+    #[test] fn test_compose_no_reordering_pure_native_wrong_nargs() { run_expr("do { let x, y ; x . hex(y, y) }", "Nil Nil PushLocal(0)->x Hex PushLocal(1)->y PushLocal(1)->y Call(2) Swap Call(1) PopN(3)") }
     #[test] fn test_operator_function_inlining_constant_1() { run_expr("(+)(1)(2)", "Int(3) Pop") }
     #[test] fn test_operator_function_inlining_constant_2() { run_expr("1 . (+2)", "Int(3) Pop") }
     #[test] fn test_operator_function_inlining_constant_3() { run_expr("1 . (2+)", "Int(3) Pop") }
     #[test] fn test_operator_function_inlining_constant_4() { run_expr("(+1)(2)", "Int(3) Pop") }
     #[test] fn test_operator_function_inlining_constant_5() { run_expr("(1+)(2)", "Int(3) Pop") }
-    #[test] fn test_operator_function_inlining_non_constant_1() { run_expr("do { let x ; (+)(x)(2) }", "Nil PushLocal(0)->x Int(2) Add PopN(2)") }
-    #[test] fn test_operator_function_inlining_non_constant_2() { run_expr("do { let x ; x . (+2) }", "Nil PushLocal(0)->x Int(2) Add PopN(2)") }
-    #[test] fn test_operator_function_inlining_non_constant_3() { run_expr("do { let x ; x . (2+) }", "Nil Int(2) PushLocal(0)->x Add PopN(2)") }
-    #[test] fn test_operator_function_inlining_non_constant_4() { run_expr("do { let x ; (+x)(2) }", "Nil Int(2) PushLocal(0)->x Add PopN(2)") }
-    #[test] fn test_operator_function_inlining_non_constant_5() { run_expr("do { let x ; (x+)(2) }", "Nil PushLocal(0)->x Int(2) Add PopN(2)") }
+    #[test] fn test_operator_function_inlining_non_constant_1() { run_expr("do { let x ; (+)(x)(2) }", "Nil PushLocal(0)->x AddConst(Int(2)) PopN(2)") }
+    #[test] fn test_operator_function_inlining_non_constant_2() { run_expr("do { let x ; x . (+2) }", "Nil PushLocal(0)->x AddConst(Int(2)) PopN(2)") }
+    #[test] fn test_operator_function_inlining_non_constant_3() { run_expr("do { let x ; x . (2+) }", "Nil Int(2) AddLocal(0)->x PopN(2)") }
+    #[test] fn test_operator_function_inlining_non_constant_4() { run_expr("do { let x ; (+x)(2) }", "Nil Int(2) AddLocal(0)->x PopN(2)") }
+    #[test] fn test_operator_function_inlining_non_constant_5() { run_expr("do { let x ; (x+)(2) }", "Nil PushLocal(0)->x AddConst(Int(2)) PopN(2)") }
     #[test] fn test_operator_function_inlining_asymmetric_1() { run_expr("(/)(2)(5)", "Int(0) Pop") }
     #[test] fn test_operator_function_inlining_asymmetric_2() { run_expr("2 . (/5)", "Int(0) Pop") }
     #[test] fn test_operator_function_inlining_asymmetric_3() { run_expr("2 . (5/)", "Int(2) Pop") }
@@ -303,15 +474,21 @@ mod tests {
     #[test] fn test_operator_function_inlining_impure_4() { run_expr("do { let x, y ; (/x)(y = 2) }", "Nil Nil PushLocal(0)->x Int(2) StoreLocal(1)->y Swap Div PopN(3)") }
     #[test] fn test_operator_function_inlining_impure_5() { run_expr("do { let x, y ; (x/)(y = 2) }", "Nil Nil PushLocal(0)->x Int(2) StoreLocal(1)->y Div PopN(3)") }
     #[test] fn test_operator_function_inlining_with_unroll() { run_expr("(/)(...1, 2)", "OperatorDiv Int(1) Unroll Int(2) Call...(2) Pop") }
+    #[test] fn test_binary_with_local_rhs() { run_expr("do { let a, b, c ; a + b * c }", "Nil Nil Nil PushLocal(0)->a PushLocal(1)->b MulLocal(2)->c Add PopN(4)") }
+    #[test] fn test_binary_with_const_rhs() { run_expr("do { let i ; i += 1 ; i < 'x' }", "Nil PushLocal(0)->i AddConst(Int(1)) StoreLocalPop(0)->i PushLocal(0)->i LessThanConst(Str('x')) PopN(2)") }
+    #[test] fn test_binary_with_index_assignment() { run_expr("do { let x, i ; x[i] -= 2 }", "Nil Nil PushLocal(0)->x PushLocal(1)->i OpIndexPeek SubConst(Int(2)) StoreArray PopN(3)") }
     #[test] fn test_inline_int_min() { run_expr("min(int)", "Int(-4611686018427387904) Pop") }
     #[test] fn test_inline_int_max() { run_expr("int.max", "Int(4611686018427387903) Pop") }
     #[test] fn test_partial_function_call_merge_no_args_1() { run_expr("vector()()", "Vector Call(0) Call(0) Pop"); }
     #[test] fn test_partial_function_call_merge_no_args_2() { run_expr("vector()(1)", "Vector Call(0) Int(1) Call(1) Pop"); }
-    #[test] fn test_partial_function_call_merge_one_arg_1() { run_expr("int()()", "Int Call(0) Pop") }
-    #[test] fn test_partial_function_call_merge_one_arg_2() { run_expr("int()(1)", "Int Int(1) Call(1) Pop") }
-    #[test] fn test_partial_function_call_merge_one_arg_3() { run_expr("int()(1)(2)", "Int Int(1) Call(1) Int(2) Call(1) Pop") }
-    #[test] fn test_partial_function_call_merge_one_arg_4() { run_expr("int()(1)()(2)", "Int Int(1) Call(1) Call(0) Int(2) Call(1) Pop") }
-    #[test] fn test_partial_function_call_merge_one_arg_5() { run_expr("int()()(1)()()", "Int Int(1) Call(1) Call(0) Call(0) Pop") }
+    #[test] fn test_partial_function_call_merge_one_arg_1() { run_expr("read_text()()", "ReadText Call(0) Pop") }
+    #[test] fn test_partial_function_call_merge_one_arg_2() { run_expr("read_text()(1)", "ReadText Int(1) Call(1) Pop") }
+    #[test] fn test_partial_function_call_merge_one_arg_3() { run_expr("read_text()(1)(2)", "ReadText Int(1) Call(1) Int(2) Call(1) Pop") }
+    #[test] fn test_partial_function_call_merge_one_arg_4() { run_expr("read_text()(1)()(2)", "ReadText Int(1) Call(1) Call(0) Int(2) Call(1) Pop") }
+    #[test] fn test_partial_function_call_merge_one_arg_5() { run_expr("read_text()()(1)()()", "ReadText Int(1) Call(1) Call(0) Call(0) Pop") }
+    #[test] fn test_partial_function_call_merge_then_constant_folding_1() { run_expr("int()(1)", "Int(1) Pop") }
+    #[test] fn test_partial_function_call_merge_then_constant_folding_2() { run_expr("int()(1)(2)", "Int(1) Int(2) Call(1) Pop") }
+    #[test] fn test_partial_function_call_merge_then_constant_folding_3() { run_expr("int()()('12')()", "Int(12) Call(0) Pop") }
     #[test] fn test_partial_function_call_merge_two_arg_1() { run_expr("map(1)(2)", "Map Int(1) Int(2) Call(2) Pop"); }
     #[test] fn test_partial_function_call_merge_two_arg_2() { run_expr("map()(1)(2)", "Map Int(1) Int(2) Call(2) Pop"); }
     #[test] fn test_partial_function_call_merge_two_arg_3() { run_expr("map()(1)()(2)", "Map Int(1) Int(2) Call(2) Pop"); }
@@ -322,6 +499,19 @@ mod tests {
     #[test] fn test_partial_function_call_merge_two_arg_unroll_1() { run_expr("map()(...1)", "Map Int(1) Unroll Call...(1) Pop"); }
     #[test] fn test_partial_function_call_merge_two_arg_unroll_2() { run_expr("map(1)(...2)", "Map Int(1) Int(2) Unroll Call...(2) Pop"); }
     #[test] fn test_partial_function_call_merge_two_arg_unroll_3() { run_expr("map(...1)()", "Map Int(1) Unroll Call...(1) Call(0) Pop"); }
+    #[test] fn test_literal_size_hint_list() { run_expr("[1, 2, 3]", "LiteralBegin(List,3) Int(1) Int(2) Int(3) LiteralAcc(3) LiteralEnd Pop") }
+    #[test] fn test_literal_size_hint_dict() { run_expr("{1: 2, 3: 4}", "LiteralBegin(Dict,2) Int(1) Int(2) Int(3) Int(4) LiteralAcc(4) LiteralEnd Pop") }
+    #[test] fn test_literal_size_hint_unroll_str() { run_expr("[1, ...'abc']", "LiteralBegin(List,4) Int(1) LiteralAcc(1) Str('abc') LiteralUnroll LiteralEnd Pop") }
+    #[test] fn test_literal_size_hint_unroll_unknown() { run_expr("do { let x ; [1, ...x] }", "Nil LiteralBegin(List,1) Int(1) LiteralAcc(1) PushLocal(0)->x LiteralUnroll LiteralEnd PopN(2)") }
+    #[test] fn test_literal_flatten_unrolled_list() { run_expr("[1, ...[2, 3], ...(4, 5)]", "LiteralBegin(List,5) Int(1) Int(2) Int(3) Int(4) Int(5) LiteralAcc(5) LiteralEnd Pop") }
+    #[test] fn test_literal_flatten_unrolled_list_in_set() { run_expr("{1, ...[1, 2]}", "LiteralBegin(Set,3) Int(1) Int(1) Int(2) LiteralAcc(3) LiteralEnd Pop") }
+    #[test] fn test_literal_no_flatten_unrolled_set() { run_expr("[...{1, 2}]", "LiteralBegin(List,0) LiteralBegin(Set,2) Int(1) Int(2) LiteralAcc(2) LiteralEnd LiteralUnroll LiteralEnd Pop") }
+    #[test] fn test_literal_no_flatten_unrolled_list_in_dict() { run_expr("{1: 2, ...[(3, 4)]}", "LiteralBegin(Dict,1) Int(1) Int(2) LiteralAcc(2) LiteralBegin(List,1) LiteralBegin(Vector,2) Int(3) Int(4) LiteralAcc(2) LiteralEnd LiteralAcc(1) LiteralEnd LiteralUnroll LiteralEnd Pop") }
+
+    #[test] fn test_constant_folding_retains_location() { run_annotated("let x = 1 + 2 * 3", "L1: Int(7) ; 1 + 2 * 3\nInitGlobal ; 3\nPop\nExit") }
+    #[test] fn test_constant_folding_retains_location_of_partial_fold() { run_annotated("let x ; x = 1 + 2 + x", "L1: InitGlobal ; x\nNil\nInt(3) ; 1 + 2\nPushGlobal(0) -> x ; x\nAdd ; +\nStoreGlobalPop(0) -> x ; x\nPop\nExit") }
+    #[test] fn test_constant_folding_retains_location_across_lines() { run_annotated("let x = 1 +\n  2", "L1: Int(3) ; 1 + ...\nL2: InitGlobal ; 2\nPop\nExit") }
+    #[test] fn test_dead_code_elimination_retains_location() { run_annotated("let x = if 1 > 0 then 'yes' else 'no'", "L1: Str('yes') ; 'yes'\nInitGlobal ; 'no'\nPop\nExit") }
 
     fn run_expr(text: &'static str, expected: &'static str) {
         let expected: String = format!("{}\nExit", expected.replace(" ", "\n"));
@@ -331,4 +521,18 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    fn run_annotated(text: &'static str, expected: &'static str) {
+        use itertools::Itertools;
+
+        let view: SourceView = SourceView::new(String::new(), String::from(text));
+        let actual: String = compiler::compile(true, &view)
+            .expect("Failed to compile")
+            .disassemble(&view, &compiler::DisassemblyOptions { line_numbers: false, annotate: true, verbose: false })
+            .into_iter()
+            .map(|line| line.split_whitespace().join(" ")) // Ignore the indentation, and alignment of annotations
+            .join("\n");
+
+        assert_eq!(actual, expected);
+    }
 }
\ No newline at end of file
@@ -12,7 +12,7 @@ use itertools::Itertools;
 use crate::compiler::parser::{Parser, ParserError, ParserErrorType};
 use crate::core;
 use crate::reporting::Location;
-use crate::vm::{FunctionImpl, IntoValue, Opcode, StoreOp, ValuePtr};
+use crate::vm::{FunctionImpl, IntoValue, Opcode, StoreOp, StructTypeImpl, ValuePtr};
 
 use Opcode::{*};
 use ParserErrorType::{*};
@@ -23,12 +23,19 @@ use crate::core::Pattern;
 pub struct Loop {
     pub(super) start_index: usize,
     pub(super) scope_depth: u32,
-    pub(super) break_statements: Vec<usize>
+    pub(super) break_statements: Vec<usize>,
+    /// If `true`, the locals declared in the loop's own scope (the variables of a `for` loop) are bound per iteration, so `break` and `continue` need to lift them as well.
+    pub(super) per_iteration: bool,
 }
 
 impl Loop {
-    fn new(start_index: usize, depth: u32) -> Loop {
-        Loop { start_index, scope_depth: depth, break_statements: Vec::new() }
+    fn new(start_index: usize, depth: u32, per_iteration: bool) -> Loop {
+        Loop { start_index, scope_depth: depth, break_statements: Vec::new(), per_iteration }
+    }
+
+    /// Returns the minimum scope depth of locals which need to be lifted when exiting an iteration of this loop via `break` or `continue`.
+    pub(super) fn lift_depth(&self) -> u32 {
+        if self.per_iteration { self.scope_depth } else { self.scope_depth + 1 }
     }
 }
 
@@ -41,11 +48,16 @@ pub struct Locals {
     locals: Vec<Local>,
     /// An array of captured upvalues for this function, either due to an inner function requiring them, or this function needing to capture locals from it's enclosing function
     upvalues: Vec<UpValue>,
+    /// The names captured by value in this function's capture list, i.e. `fn [x] () -> x`, and the index of the upvalue each one is stored in.
+    captures: Vec<(String, u32)>,
     /// Loop stack
     /// Each frame represents a single loop, which `break` and `continue` statements refer to
     /// `continue` jumps back to the beginning of the loop, aka the first `usize` (loop start)
     /// `break` statements jump back to the end of the loop, which needs to be patched later. The values to be patched record themselves in the stack at the current loop level
     loops: Vec<Loop>,
+    /// The scope depth of each enclosing `with` statement, innermost last.
+    /// Any `break`, `continue` or `return` which leaves a `with` statement needs to exit its context first.
+    contexts: Vec<u32>,
 
     /// Ordinal into `self.functions` to access `self.functions[func].code`
     /// If not present, it is assumed to be global code.
@@ -61,7 +73,7 @@ impl Locals {
 
     /// Returns a new empty `Locals` instance, corresponding to the given function, if present.
     pub(super) fn new(func: Option<usize>) -> Locals {
-        Locals { locals: Vec::new(), upvalues: Vec::new(), loops: Vec::new(), func }
+        Locals { locals: Vec::new(), upvalues: Vec::new(), captures: Vec::new(), loops: Vec::new(), contexts: Vec::new(), func }
     }
 
     /// Returns the length of the locals, effectively the number of variables declared in this frame.
@@ -71,8 +83,9 @@ impl Locals {
     }
 
     /// Returns the name of a local with the given `index`.
+    /// If there is no such local, which may happen when recovering from an error, returns an empty name.
     pub(super) fn get_name(&self, index: usize) -> String {
-        self.locals[index].name.clone()
+        self.locals.get(index).map(|local| local.name.clone()).unwrap_or_default()
     }
 
     /// Returns the topmost `Loop` statement on the stack, or `None` if the stack is empty.
@@ -80,10 +93,22 @@ impl Locals {
         self.loops.last_mut()
     }
 
-    /// Enumerates the current locals' `upvalues`, and emits the correct `CloseLocal` or `CloseUpValue` tokens for each.
-    pub(super) fn closed_locals(&self) -> Vec<Opcode> {
+    /// Returns the number of enclosing `with` statements which are nested deeper than the scope `depth`.
+    pub(super) fn contexts_above(&self, depth: u32) -> usize {
+        self.contexts.iter().filter(|context| **context > depth).count()
+    }
+
+    /// Enumerates the current locals' `upvalues`, and emits the correct `CloseLocal` or `CloseUpValue` tokens for each, for the closure of the function `func`.
+    ///
+    /// Upvalues captured by value are loaded and closed with `CloseValue`. As all functions declared in the same enclosing function share these upvalues,
+    /// any captured by value by a different function are filled with `nil` instead, so the indices of the remaining upvalues are unchanged.
+    pub(super) fn closed_locals(&self, func: usize) -> Vec<Opcode> {
         self.upvalues.iter()
-            .map(|upvalue| if upvalue.is_local { CloseLocal(upvalue.index) } else { CloseUpValue(upvalue.index) })
+            .flat_map(|upvalue| match upvalue.by_value {
+                Some((owner, load)) => vec![if owner == func { load } else { Nil }, CloseValue],
+                None if upvalue.is_local => vec![CloseLocal(upvalue.index)],
+                None => vec![CloseUpValue(upvalue.index)],
+            })
             .collect::<Vec<Opcode>>()
     }
 }
@@ -97,24 +122,119 @@ pub struct Fields {
     /// A table which maps pairs of `(type index, field index)` to a `field offset`
     /// The `type index` is known at runtime, based on the runtime type of the struct in use.
     /// The `field index` is known at compile time, based on the identifier that it resolves to.
-    /// The resultant `field offset` is a index into a specific struct object's `Vec<Value>` of fields.
-    lookup: HashMap<(u32, u32), usize, FxBuildHasher>,
+    /// The resultant `field offset` is a index into a specific struct object's `Vec<Value>` of fields, or for computed fields (properties), the offsets of the getter and setter members.
+    lookup: HashMap<(u32, u32), FieldOffset, FxBuildHasher>,
+
+    /// A table which maps pairs of `(type index, field index)` to a `member offset`, for the static members (functions and constants) of a struct.
+    /// Unlike fields, these are accessed on the struct type itself, and the `member offset` is an index into the struct type's members.
+    members: HashMap<(u32, u32), usize, FxBuildHasher>,
 
     /// The next available `type_index`
     types: u32,
 }
 
+/// An entry in the field lookup table, which distinguishes fields stored on each instance from computed fields (properties).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum FieldOffset {
+    /// A field stored on each struct instance, at the given field offset.
+    Stored(usize),
+    /// A computed field, implemented by a getter, and optionally a setter, which are stored as members of the struct type at the given member offsets.
+    Computed(usize, Option<usize>),
+}
+
 impl Fields {
-    pub(super) fn new() -> Fields {
+    pub(crate) fn new() -> Fields {
         Fields {
             fields: HashMap::with_hasher(FxBuildHasher::default()),
             lookup: HashMap::with_hasher(FxBuildHasher::default()),
+            members: HashMap::with_hasher(FxBuildHasher::default()),
             types: 0,
         }
     }
 
+    /// Declares a static member of a struct type, and returns the corresponding `field index`. Members share field indices with fields of the same name.
+    /// It will also insert the member lookup entry for the member and type pair, to the desired member offset
+    pub(crate) fn declare_member(&mut self, type_index: u32, member_offset: usize, name: String) -> u32 {
+        let next_field_index: u32 = self.fields.len() as u32;
+        let field_index: u32 = *self.fields
+            .entry(name)
+            .or_insert(next_field_index);
+
+        self.members.insert((type_index, field_index), member_offset);
+        self.types = self.types.max(type_index + 1);
+
+        field_index
+    }
+
+    /// Declares a field, and returns the corresponding `field index`. If the field has not been seen before, this will assign a new `field index` for it.
+    /// It will also insert the lookup entry for the field and type pair, to the desired field offset
+    pub(crate) fn declare_field(&mut self, type_index: u32, field_offset: usize, name: String) -> u32 {
+        let next_field_index: u32 = self.fields.len() as u32;
+        let field_index: u32 = *self.fields
+            .entry(name)
+            .or_insert(next_field_index);
+
+        self.lookup.insert((type_index, field_index), FieldOffset::Stored(field_offset));
+        self.types = self.types.max(type_index + 1);
+
+        field_index
+    }
+
+    /// Declares a computed field (property), and returns the corresponding `field index`. Like fields, this will assign a new `field index` if the name has not been seen before.
+    /// The getter and setter are members of the struct type, at the given member offsets.
+    pub(crate) fn declare_property(&mut self, type_index: u32, getter_offset: usize, setter_offset: Option<usize>, name: String) -> u32 {
+        let next_field_index: u32 = self.fields.len() as u32;
+        let field_index: u32 = *self.fields
+            .entry(name)
+            .or_insert(next_field_index);
+
+        self.lookup.insert((type_index, field_index), FieldOffset::Computed(getter_offset, setter_offset));
+        self.types = self.types.max(type_index + 1);
+
+        field_index
+    }
+
+    /// Declares every static member and computed field (property) of the type `parent_index` as a member or property of the type `type_index`, at the same member offsets.
+    pub(crate) fn inherit_members(&mut self, type_index: u32, parent_index: u32) {
+        let inherited: Vec<(u32, usize)> = self.members.iter()
+            .filter(|((index, _), _)| *index == parent_index)
+            .map(|((_, field_index), offset)| (*field_index, *offset))
+            .collect();
+        for (field_index, offset) in inherited {
+            self.members.insert((type_index, field_index), offset);
+        }
+
+        let properties: Vec<(u32, FieldOffset)> = self.lookup.iter()
+            .filter(|((index, _), offset)| *index == parent_index && matches!(offset, FieldOffset::Computed(_, _)))
+            .map(|((_, field_index), offset)| (*field_index, *offset))
+            .collect();
+        for (field_index, offset) in properties {
+            self.lookup.insert((type_index, field_index), offset);
+        }
+    }
+
+    /// Returns the number of unique fields, where every `field index` is less than this count
+    pub fn field_count(&self) -> usize {
+        self.fields.len()
+    }
+
     pub fn get_field_offset(&self, type_index: u32, field_index: u32) -> Option<usize> {
-        self.lookup.get(&(type_index, field_index)).copied()
+        match self.lookup.get(&(type_index, field_index)) {
+            Some(FieldOffset::Stored(offset)) => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Returns the member offsets of the `(getter, setter)` of a computed field, if the field is computed for the given type.
+    pub fn get_property_offsets(&self, type_index: u32, field_index: u32) -> Option<(usize, Option<usize>)> {
+        match self.lookup.get(&(type_index, field_index)) {
+            Some(FieldOffset::Computed(getter, setter)) => Some((*getter, *setter)),
+            _ => None,
+        }
+    }
+
+    pub fn get_member_offset(&self, type_index: u32, field_index: u32) -> Option<usize> {
+        self.members.get(&(type_index, field_index)).copied()
     }
 
     pub fn get_field_name(&self, field_index: u32) -> String {
@@ -124,6 +244,47 @@ impl Fields {
             .0
             .clone()
     }
+
+    /// Returns the name of each field, ordered by field index
+    pub fn get_field_names(&self) -> Vec<&String> {
+        let mut fields: Vec<(&String, &u32)> = self.fields.iter().collect();
+        fields.sort_by_key(|(_, field_index)| **field_index);
+        fields.into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// Returns each pair of `(type index, field offset)` for the stored field with index `field_index`, ordered by type index
+    pub fn get_field_offsets(&self, field_index: u32) -> Vec<(u32, usize)> {
+        let mut offsets: Vec<(u32, usize)> = self.lookup.iter()
+            .filter_map(|((type_index, index), offset)| match offset {
+                FieldOffset::Stored(offset) if *index == field_index => Some((*type_index, *offset)),
+                _ => None,
+            })
+            .collect();
+        offsets.sort_unstable();
+        offsets
+    }
+
+    /// Returns each triple of `(type index, getter offset, setter offset)` for the computed field with index `field_index`, ordered by type index
+    pub fn get_properties(&self, field_index: u32) -> Vec<(u32, usize, Option<usize>)> {
+        let mut offsets: Vec<(u32, usize, Option<usize>)> = self.lookup.iter()
+            .filter_map(|((type_index, index), offset)| match offset {
+                FieldOffset::Computed(getter, setter) if *index == field_index => Some((*type_index, *getter, *setter)),
+                _ => None,
+            })
+            .collect();
+        offsets.sort_unstable();
+        offsets
+    }
+
+    /// Returns each pair of `(type index, member offset)` for the member with index `field_index`, ordered by type index
+    pub fn get_member_offsets(&self, field_index: u32) -> Vec<(u32, usize)> {
+        let mut offsets: Vec<(u32, usize)> = self.members.iter()
+            .filter(|((_, index), _)| *index == field_index)
+            .map(|((type_index, _), offset)| (*type_index, *offset))
+            .collect();
+        offsets.sort_unstable();
+        offsets
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -134,11 +295,22 @@ struct UpValue {
     /// Either a reference to an index in the enclosing function's `locals` (which are stack offset),
     /// or a reference to the enclosing function's `upvalues` (which can be accessed via stack offset 0 -> upvalues, if it is a closure
     index: u32,
+
+    /// The name of the captured local variable, used for debugging
+    name: String,
+
+    /// If present, this upvalue is captured by value by the function with this ordinal, and is initialized by this opcode, which loads the value in the enclosing function.
+    /// It is not shared with any other function, and so is never matched when resolving other upvalues.
+    by_value: Option<(usize, Opcode)>,
 }
 
 impl UpValue {
-    fn new(is_local: bool, index: u32) -> UpValue {
-        UpValue { is_local, index }
+    fn new(is_local: bool, index: u32, name: String) -> UpValue {
+        UpValue { is_local, index, name, by_value: None }
+    }
+
+    fn by_value(func: usize, load: Opcode, name: String) -> UpValue {
+        UpValue { is_local: false, index: 0, name, by_value: Some((func, load)) }
     }
 }
 
@@ -267,12 +439,22 @@ impl LValue {
     }
 
     /// Resolves each identifier as a local variable that is currently declared.
-    /// This will raise semantic errors for undeclared variables.
+    /// This will raise semantic errors for undeclared variables, or variables which cannot be assigned to in a pattern.
     pub(super) fn resolve_locals(&mut self, parser: &mut Parser) {
         match self {
             LValue::Named(it) | LValue::VarNamed(it) => {
                 let name: String = it.as_named();
-                *it = parser.resolve_identifier(name);
+                *it = match parser.resolve_identifier(name) {
+                    LValueReference::NativeFunction(_) => {
+                        parser.semantic_error(InvalidAssignmentTarget);
+                        LValueReference::Invalid
+                    },
+                    LValueReference::LateBoundGlobal(global) => {
+                        parser.semantic_error(LateBoundGlobalInPattern(global.name));
+                        LValueReference::Invalid
+                    },
+                    it => it,
+                };
             },
             LValue::Terms(lvalue) => {
                 for term in lvalue {
@@ -443,11 +625,8 @@ impl LValueReference {
         match self {
             LValueReference::Local(index) => StoreOp::Local(index),
             LValueReference::Global(index) => StoreOp::Global(index),
-            LValueReference::LateBoundGlobal(_global) => {
-                // todo: support this
-                panic!("can't support late bound globals in pattern destructuring yet");
-            },
             LValueReference::UpValue(index) => StoreOp::UpValue(index),
+            LValueReference::Invalid => StoreOp::Local(0), // A semantic error will have already been raised, so this will not be run
             _ => panic!("Invalid store: {:?}", self),
         }
     }
@@ -480,6 +659,19 @@ pub struct ParserFunctionImpl {
     /// If the last argument in this function is a variadic argument, meaning it needs special behavior when invoked with >= `max_args()`
     var_arg: bool,
 
+    /// If this function was declared with `async fn`
+    is_async: bool,
+
+    /// The `///` doc comment before this function's declaration, if present
+    doc: Option<String>,
+
+    /// The name of each upvalue in this function's closure environment, in order, if it captures any.
+    upvalue_names: Vec<String>,
+
+    /// The location of the parameter list, and of each parameter as written, for functions declared with one
+    location: Location,
+    arg_locations: Vec<Location>,
+
     /// Bytecode for the function body itself
     code: Vec<(Location, Opcode)>,
 
@@ -504,13 +696,25 @@ impl ParserFunctionImpl {
     /// Bakes this parser function into an immutable `FunctionImpl`.
     /// The `head` and `tail` pointers are computed based on the surrounding code.
     pub(super) fn bake(self, constants: &mut [ValuePtr], head: usize, tail: usize) {
-        constants[self.constant_id as usize] = FunctionImpl::new(head, tail, self.name, self.args, self.default_args, self.var_arg).to_value();
+        constants[self.constant_id as usize] = FunctionImpl::new(head, tail, self.name, self.args, self.default_args, self.var_arg, self.is_async).with_doc(self.doc).with_upvalue_names(self.upvalue_names).with_signature(self.location, self.arg_locations).to_value();
     }
 
     /// Marks a default argument as finished.
     pub(super) fn mark_default_arg(&mut self) {
         self.default_args.push(self.code.len());
     }
+
+    /// Declares the next parameter of this function, with `name` as written in the function's signature, and `location` spanning the parameter and any default value.
+    pub(super) fn declare_arg(&mut self, name: String, var_arg: bool, location: Location) {
+        self.args.push(name);
+        self.arg_locations.push(location);
+        self.var_arg |= var_arg;
+    }
+
+    /// Declares the location of this function's parameter list, once it has been parsed.
+    pub(super) fn declare_location(&mut self, location: Location) {
+        self.location = location;
+    }
 }
 
 
@@ -522,7 +726,16 @@ impl<'a> Parser<'a> {
     pub fn begin_loop(&mut self) -> usize {
         let loop_start: usize = self.next_opcode(); // Top of the loop, push onto the loop stack
         let loop_depth: u32 = self.scope_depth;
-        self.current_locals_mut().loops.push(Loop::new(loop_start, loop_depth));
+        self.current_locals_mut().loops.push(Loop::new(loop_start, loop_depth, false));
+        loop_start
+    }
+
+    /// Like `begin_loop()`, but for a `for` loop, where the variables declared in the current scope are bound per iteration, if enabled by `CompileFlags::per_iteration_loops`.
+    pub fn begin_for_loop(&mut self) -> usize {
+        let loop_start: usize = self.next_opcode();
+        let loop_depth: u32 = self.scope_depth;
+        let per_iteration: bool = self.per_iteration_loops;
+        self.current_locals_mut().loops.push(Loop::new(loop_start, loop_depth, per_iteration));
         loop_start
     }
 
@@ -534,6 +747,16 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Marks the beginning of a `with` statement, for the purposes of exiting its context on `break`, `continue` and `return` statements.
+    pub fn begin_context(&mut self) {
+        let depth: u32 = self.scope_depth;
+        self.current_locals_mut().contexts.push(depth);
+    }
+
+    pub fn end_context(&mut self) {
+        self.current_locals_mut().contexts.pop();
+    }
+
     pub fn declare_const<T : IntoValue>(&mut self, value: T) -> u32 {
         let value = value.to_value();
         if let Some(id) = self.constants.iter().position(|i| i == &value) {
@@ -546,7 +769,7 @@ impl<'a> Parser<'a> {
     /// Declares a function with a given name and arguments.
     /// Returns the constant identifier for this function, however the function itself is currently located in `self.functions`, not `self.constants`.
     /// Instead, we push a dummy `Nil` into the constants array, and store the constant index on our parser function. During teardown, we inject these into the right spots.
-    pub fn declare_function(&mut self, name: String, args: &[LValue], var_arg: bool) -> u32 {
+    pub fn declare_function(&mut self, name: String, args: &[LValue], var_arg: bool, is_async: bool) -> u32 {
         let constant_id: u32 = self.constants.len() as u32;
 
         self.constants.push(ValuePtr::nil());
@@ -555,6 +778,11 @@ impl<'a> Parser<'a> {
             args: args.iter().map(|u| u.to_code_str()).collect(),
             default_args: Vec::new(),
             var_arg,
+            is_async,
+            doc: None,
+            upvalue_names: Vec::new(),
+            location: Location::empty(),
+            arg_locations: Vec::new(),
             code: Vec::new(),
             locals_reference: Vec::new(),
             constant_id,
@@ -562,6 +790,94 @@ impl<'a> Parser<'a> {
         constant_id
     }
 
+    /// Attaches a `///` doc comment to the most recently declared function.
+    pub fn declare_function_doc(&mut self, doc: Option<String>) {
+        self.functions.last_mut().unwrap().doc = doc;
+    }
+
+    /// Declares `name` as captured by value by the function `func`, which is about to be parsed, and returns the index of the upvalue it is stored in.
+    /// The name is resolved in the enclosing function, and the value is loaded from there when the closure is created.
+    pub fn declare_capture(&mut self, func: usize, name: String) -> u32 {
+        let load: Opcode = match self.resolve_identifier(name.clone()) {
+            LValueReference::Local(index) => PushLocal(index),
+            LValueReference::Global(index) => PushGlobal(index),
+            LValueReference::UpValue(index) => PushUpValue(index),
+            LValueReference::NativeFunction(native) => NativeFunction(native),
+            LValueReference::LateBoundGlobal(_) => {
+                // A late bound global cannot be loaded by value, as it may not be declared when the closure is created
+                self.semantic_error(UndeclaredIdentifier(name.clone()));
+                Nil
+            },
+            _ => Nil, // An error will have already been raised
+        };
+        self.current_locals_mut().upvalues.push(UpValue::by_value(func, load, name));
+        (self.current_locals().upvalues.len() - 1) as u32
+    }
+
+    /// Declares the names captured by value by the current function, once its locals have been pushed, so they can be resolved within the function.
+    pub fn declare_function_captures(&mut self, captures: Vec<(String, u32)>) {
+        self.current_locals_mut().captures = captures;
+    }
+
+    /// Records the names of the upvalues captured by the function at `func`, once it has been parsed. These are the upvalues of the enclosing function, which the closure's environment is created from.
+    pub fn declare_function_upvalue_names(&mut self, func: usize) {
+        self.functions[func].upvalue_names = self.current_locals().upvalues.iter()
+            .map(|upvalue| match upvalue.by_value {
+                Some((owner, _)) if owner != func => String::from("_"), // Captured by value by a different function
+                _ => upvalue.name.clone(),
+            })
+            .collect();
+    }
+
+    /// Declares a synthetic function used to implement calls with placeholder (`_`) arguments, i.e. `f(_, 2)`, and method calls, i.e. `x.f(2)`.
+    /// `placeholders` has an entry for each argument of the call, which is `true` if that argument is a placeholder. Returns the constant identifier for the function.
+    ///
+    /// The function takes the function being called, and each argument that was given, followed by one parameter for each placeholder.
+    /// The body calls the function with the given and placeholder arguments in their original order. Evaluating it with only the function and given arguments results in a partial function awaiting the placeholders.
+    ///
+    /// `f(_, 2)` is then equivalent to `(fn($0, $1, $2) -> $0($2, $1))(f, 2)`, and the function and given arguments are evaluated when the partial function is created, not when it is called.
+    ///
+    /// If `receiver` is `true`, this is a method call, and the function takes one additional parameter before the placeholders, which is passed as the last argument.
+    pub fn declare_placeholder_function(&mut self, loc: Location, placeholders: &[bool], receiver: bool) -> u32 {
+        let nargs: usize = placeholders.len() + receiver as usize;
+        let ngiven: usize = placeholders.iter().filter(|u| !**u).count();
+
+        self.locals.push(Locals::new(Some(self.functions.len())));
+        self.function_depth += 1;
+        self.scope_depth += 1;
+
+        let params: Vec<usize> = (0..=nargs).map(|_| self.declare_synthetic_local()).collect();
+        let names: Vec<LValue> = params.iter()
+            .map(|index| LValue::Named(LValueReference::Named(self.current_locals().get_name(*index))))
+            .collect();
+
+        let func: u32 = self.declare_function(String::from("_"), &names, false, false);
+
+        // The function being called, followed by each argument in order, which is either a given argument, or the next placeholder
+        // The receiver of a method call, if present, is passed last
+        let mut next_given: usize = 1;
+        let mut next_placeholder: usize = 1 + ngiven + receiver as usize;
+        self.push_with(PushLocal(params[0] as u32), loc);
+        for placeholder in placeholders {
+            let index = if *placeholder { &mut next_placeholder } else { &mut next_given };
+            self.push_with(PushLocal(params[*index] as u32), loc);
+            *index += 1;
+        }
+        if receiver {
+            self.push_with(PushLocal(params[next_given] as u32), loc);
+        }
+        self.push_with(Call(nargs as u32, false), loc);
+
+        self.pop_locals(Some(self.scope_depth), true, false, true);
+        self.push_with(Return, loc);
+
+        self.locals.pop().unwrap();
+        self.function_depth -= 1;
+        self.scope_depth -= 1;
+
+        func
+    }
+
     /// Declares a `Pattern`, stores the pattern, and then emits a `ExecPattern` opcode for it
     fn declare_pattern(&mut self, pattern: Pattern) {
         let pattern_id: u32 = self.patterns.len() as u32;
@@ -677,10 +993,16 @@ impl<'a> Parser<'a> {
                     self.current_locals_mut().locals.pop().unwrap();
 
                     // And pop any matching upvalues
-                    if let Some(upvalue) = self.current_locals_mut().upvalues.last() {
+                    // Any upvalues captured by value can also be popped, as the functions which captured them have already been declared
+                    while let Some(upvalue) = self.current_locals_mut().upvalues.last() {
+                        if upvalue.by_value.is_some() {
+                            self.current_locals_mut().upvalues.pop().unwrap();
+                            continue
+                        }
                         if upvalue.index == local_index && upvalue.is_local {
                             self.current_locals_mut().upvalues.pop().unwrap();
                         }
+                        break
                     }
                 }
             }
@@ -720,10 +1042,17 @@ impl<'a> Parser<'a> {
 
     /// Returns a mutable reference to the current `ParserFunctionImpl`. Will panic if a function is currently not being parsed.
     pub fn current_function_impl(&mut self) -> &mut ParserFunctionImpl {
-        let func: usize = self.current_locals().func.unwrap();
+        let func: usize = self.current_function_ordinal();
         &mut self.functions[func]
     }
 
+    /// Returns the ordinal into `self.functions` of the current function. Will panic if a function is currently not being parsed.
+    ///
+    /// Note this is not the same as `self.functions.len() - 1`, as that refers to the most recently declared function, which may be nested within the current one.
+    pub fn current_function_ordinal(&self) -> usize {
+        self.current_locals().func.unwrap()
+    }
+
     /// Returns the locals reference of the current function, like `current_function_mut()`
     pub fn current_locals_reference_mut(&mut self) -> &mut Vec<String> {
         match self.current_locals().func {
@@ -758,6 +1087,9 @@ impl<'a> Parser<'a> {
                 }
             }
         }
+        if let Some((_, index)) = self.current_locals().captures.iter().find(|(it, _)| *it == name) {
+            return LValueReference::UpValue(*index)
+        }
 
         // 2. If we are in function depth > 0, we search in enclosing functions (and global scope), for values that can be captured by this function.
         //   - Globals that are not true globals can be captured in the same manner as upvalues (these are fairly uncommon in practice)
@@ -771,6 +1103,10 @@ impl<'a> Parser<'a> {
                         return self.resolve_upvalue(depth, index);
                     }
                 }
+                if let Some((_, index)) = self.locals[depth as usize].captures.iter().find(|(it, _)| *it == name) {
+                    let index: u32 = *index;
+                    return self.resolve_captured_upvalue(depth, index, name);
+                }
             }
         }
 
@@ -789,7 +1125,7 @@ impl<'a> Parser<'a> {
         if self.function_depth > 0 {
             // Assume a late bound global
             let error = self.deferred_error(UndeclaredIdentifier(name.clone()));
-            let global = LateBoundGlobal::new(name, self.functions.len() - 1, self.next_opcode(), error);
+            let global = LateBoundGlobal::new(name, self.current_function_ordinal(), self.next_opcode(), error);
             return LValueReference::LateBoundGlobal(global);
         }
 
@@ -798,6 +1134,22 @@ impl<'a> Parser<'a> {
         LValueReference::Invalid
     }
 
+    /// Resolves an `UpValue` reference to a name captured by value by the function at `capture_depth`, which is stored in its upvalue `index`.
+    /// Like `resolve_upvalue()`, this bubbles up the upvalue through each of the functions between there and `self.function_depth`.
+    fn resolve_captured_upvalue(&mut self, capture_depth: u32, mut index: u32, name: String) -> LValueReference {
+        for depth in capture_depth..self.function_depth {
+            let upvalues: &mut Vec<UpValue> = &mut self.locals[depth as usize].upvalues;
+            index = match upvalues.iter().position(|upvalue| upvalue.index == index && !upvalue.is_local && upvalue.by_value.is_none()) {
+                Some(position) => position as u32,
+                None => {
+                    upvalues.push(UpValue::new(false, index, name.clone()));
+                    (upvalues.len() - 1) as u32
+                },
+            };
+        }
+        LValueReference::UpValue(index)
+    }
+
     /// Resolves an `UpValue` reference.
     /// For a given reference to a local, defined at a function depth `local_depth` at index `local_index`, this will
     /// bubble up the upvalue through each of the enclosing functions between here and `self.function_depth`, and ensure the variable is added as an `UpValue`.
@@ -807,17 +1159,18 @@ impl<'a> Parser<'a> {
         // If it already exists (is `is_local` and has the same `index` as the target), just grab the upvalue index, otherwise add it and bubble up
         let mut maybe_index: Option<usize> = None;
         for (i, upvalue) in self.locals[local_depth as usize].upvalues.iter().enumerate() {
-            if upvalue.index == local_index && upvalue.is_local {
+            if upvalue.index == local_index && upvalue.is_local && upvalue.by_value.is_none() {
                 maybe_index = Some(i);
                 break
             }
         }
 
         // If we did not find it, then capture the local - add this as an upvalue to the function at this depth
+        let name: String = self.locals[local_depth as usize].get_name(local_index as usize);
         let mut index = if let Some(index) = maybe_index {
             index as u32
         } else {
-            self.locals[local_depth as usize].upvalues.push(UpValue::new(true, local_index));
+            self.locals[local_depth as usize].upvalues.push(UpValue::new(true, local_index, name.clone()));
             (self.locals[local_depth as usize].upvalues.len() - 1) as u32
         };
 
@@ -828,7 +1181,7 @@ impl<'a> Parser<'a> {
             // Only add it if we haven't found an upvalue with the same index and `!is_local` (which is unique).
             let mut found: bool = false;
             for upvalue in &self.locals[depth as usize].upvalues {
-                if upvalue.index == index && !upvalue.is_local {
+                if upvalue.index == index && !upvalue.is_local && upvalue.by_value.is_none() {
                     index = upvalue.index; // Update the index
                     found = true; // And mark that we found an existing one
                     break
@@ -837,7 +1190,7 @@ impl<'a> Parser<'a> {
 
             // If we did not find an upvalue, then we must add one, referencing the upvalue from the outer function
             if !found {
-                self.locals[depth as usize].upvalues.push(UpValue::new(false, index));
+                self.locals[depth as usize].upvalues.push(UpValue::new(false, index, name.clone()));
                 index = (self.locals[depth as usize].upvalues.len() - 1) as u32
             }
         }
@@ -862,14 +1215,23 @@ impl<'a> Parser<'a> {
     /// If the field has not been seen before, this will declare the field (assign a `field index` for it).
     /// It will also insert the lookup entry for the field and type pair, to the desired field offset
     pub fn declare_field(&mut self, type_index: u32, field_offset: usize, name: String) -> u32 {
-        let next_field_index: u32 = self.fields.fields.len() as u32;
-        let field_index: u32 = *self.fields.fields
-            .entry(name)
-            .or_insert(next_field_index);
+        self.fields.declare_field(type_index, field_offset, name)
+    }
 
-        self.fields.lookup.insert((type_index, field_index), field_offset);
+    /// Declares a static member of a struct, and returns the corresponding `field index`.
+    /// Like `declare_field()`, the member must be unique within this struct, which this method does not check.
+    pub fn declare_member(&mut self, type_index: u32, member_offset: usize, name: String) -> u32 {
+        self.fields.declare_member(type_index, member_offset, name)
+    }
 
-        field_index
+    /// Declares a computed field (property) of a struct, and returns the corresponding `field index`.
+    pub fn declare_property(&mut self, type_index: u32, getter_offset: usize, setter_offset: Option<usize>, name: String) -> u32 {
+        self.fields.declare_property(type_index, getter_offset, setter_offset, name)
+    }
+
+    /// Declares each static member and computed field of the struct `parent_index` on the struct `type_index`, which may later be replaced with `declare_member()` or `declare_property()`.
+    pub fn inherit_members(&mut self, type_index: u32, parent_index: u32) {
+        self.fields.inherit_members(type_index, parent_index)
     }
 
     /// Declares a new type, and returns the corresponding `type index`.
@@ -878,6 +1240,16 @@ impl<'a> Parser<'a> {
         self.fields.types - 1
     }
 
+    /// Resolves the name of a previously declared struct type, by finding the most recent struct type constant with that name.
+    /// Returns the constant id and the struct type, or `None` if no such struct has been declared.
+    pub fn resolve_struct_type(&self, name: &String) -> Option<(u32, StructTypeImpl)> {
+        self.constants.iter()
+            .enumerate()
+            .rev()
+            .find(|(_, c)| c.is_struct_type() && &c.as_struct_type().borrow_const().name == name)
+            .map(|(id, c)| (id as u32, c.as_struct_type().borrow_const().clone()))
+    }
+
     /// Resolves a field name to a specific field index. If the field is not present, raises a parse error.
     /// Returns the `field_index`, if one was found, or `None` if not.
     pub fn resolve_field(&self, name: &String) -> Option<u32> {
@@ -6,6 +6,7 @@ use std::str::Chars;
 use crate::core::NativeFunction;
 use crate::reporting::{AsErrorWithContext, Location};
 use crate::SourceView;
+use crate::vm::MAX_INT;
 
 use self::ScanErrorType::{*};
 use self::ScanToken::{*};
@@ -57,6 +58,12 @@ impl AsErrorWithContext for ScanError {
 pub enum ScanErrorType {
     InvalidNumericPrefix(char),
     InvalidNumericValue(ParseIntError),
+    InvalidNumericDigit(char, u32),
+    NumericValueTooLarge,
+    InvalidCharLiteral(usize),
+    InvalidEscape(char),
+    InvalidUnicodeEscape(String),
+    InvalidUnicodeCodePoint(u32),
     InvalidCharacter(char),
     UnterminatedStringLiteral,
     UnterminatedBlockComment,
@@ -81,6 +88,8 @@ pub enum ScanToken {
     StringLiteral(String),
     IntLiteral(i64),
     ComplexLiteral(i64),
+    /// A user-defined infix operator, i.e. `<+>`, which is one to three symbols enclosed in `<` and `>`
+    CustomOperator(String),
 
     // Keywords
     KeywordLet,
@@ -103,8 +112,14 @@ pub enum ScanToken {
     KeywordFalse,
     KeywordNil,
     KeywordStruct,
+    KeywordEnum,
+    KeywordConst,
     KeywordExit,
     KeywordAssert,
+    KeywordWith,
+    KeywordAs,
+    KeywordAsync,
+    KeywordAwait,
 
     // Syntax
     Equals,
@@ -163,15 +178,23 @@ pub enum ScanToken {
     QuestionMark,
 
     NewLine,
+
+    /// A `///` doc comment, which contains the text of the comment. This is trivia, which is skipped over by the parser, except when looking for the doc comment of a declaration.
+    DocComment(String),
 }
 
 impl ScanToken {
+    /// Returns `true` if this token is skipped by the parser, i.e. newlines and doc comments.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, NewLine | DocComment(_))
+    }
+
     pub(super) fn ty(self) -> ScanTokenType {
         match self {
             StringLiteral(_) => ScanTokenType::String,
             IntLiteral(_) | ComplexLiteral(_) => ScanTokenType::Number,
             KeywordTrue | KeywordFalse | KeywordNil => ScanTokenType::Constant,
-            KeywordLet | KeywordFn | KeywordReturn | KeywordIf | KeywordElif | KeywordElse | KeywordThen | KeywordLoop | KeywordWhile | KeywordFor | KeywordIn | KeywordIs | KeywordNot | KeywordBreak | KeywordContinue | KeywordDo | KeywordStruct | KeywordExit | KeywordAssert => ScanTokenType::Keyword,
+            KeywordLet | KeywordFn | KeywordReturn | KeywordIf | KeywordElif | KeywordElse | KeywordThen | KeywordLoop | KeywordWhile | KeywordFor | KeywordIn | KeywordIs | KeywordNot | KeywordBreak | KeywordContinue | KeywordDo | KeywordStruct | KeywordEnum | KeywordConst | KeywordExit | KeywordAssert | KeywordWith | KeywordAs | KeywordAsync | KeywordAwait => ScanTokenType::Keyword,
             Identifier(it)  => match NativeFunction::find(it.as_str()) {
                 Some(NativeFunction::Int | NativeFunction::Str | NativeFunction::Function | NativeFunction::List | NativeFunction::Heap | NativeFunction::Dict | NativeFunction::Set | NativeFunction::Vector | NativeFunction::Any | NativeFunction::Bool | NativeFunction::Iterable | NativeFunction::Complex) => ScanTokenType::Type,
                 Some(_) => ScanTokenType::Native,
@@ -201,17 +224,12 @@ impl<'a> Scanner<'a> {
                    match c {
                        ' ' | '\t' | '\r' | '\n' => {},
 
-                       'a'..='z' | 'A'..='Z' => {
-                           let mut buffer: Vec<char> = Vec::new();
-                           buffer.push(c);
-                           loop {
-                               match self.peek() {
-                                   Some('a'..='z' | 'A'..='Z' | '0'..='9' | '_') => self.push_advance(&mut buffer),
-                                   _ => break
-                               }
-                           }
-                           self.screen_identifier(buffer);
+                       'r' if matches!(self.peek(), Some('\'' | '"')) => { // Raw string literal, i.e. `r'...'`
+                           let open: char = self.advance().unwrap();
+                           self.scan_string(open, true)
                        },
+                       'a'..='z' | 'A'..='Z' => self.scan_identifier(vec![c]),
+                       '_' if self.peek_dunder_identifier() => self.scan_identifier(vec![c]),
                        '0' => {
                             match self.peek() {
                                 Some('x') => {
@@ -224,19 +242,31 @@ impl<'a> Scanner<'a> {
                                             _ => break
                                         };
                                     }
-                                    self.screen_int(buffer, 16);
+                                    self.screen_int(buffer, 16, None);
                                 },
                                 Some('b') => {
                                     self.advance();
                                     let mut buffer: Vec<char> = Vec::new();
                                     loop {
                                         match self.peek() {
-                                            Some('1' | '0') => self.push_advance(&mut buffer),
+                                            Some('0'..='9') => self.push_advance(&mut buffer), // Invalid digits are consumed, and reported in `screen_int()`
                                             Some('_') => self.skip(),
                                             _ => break
                                         }
                                     }
-                                    self.screen_int(buffer, 2);
+                                    self.screen_int(buffer, 2, None);
+                                },
+                                Some('o') => {
+                                    self.advance();
+                                    let mut buffer: Vec<char> = Vec::new();
+                                    loop {
+                                        match self.peek() {
+                                            Some('0'..='9') => self.push_advance(&mut buffer),
+                                            Some('_') => self.skip(),
+                                            _ => break
+                                        }
+                                    }
+                                    self.screen_int(buffer, 8, None);
                                 },
                                 Some('i' | 'j') => { // Complex literal `0i` which is equal to `0`
                                     self.advance();
@@ -264,66 +294,28 @@ impl<'a> Scanner<'a> {
                                    _ => break
                                }
                            }
-                           self.screen_int(buffer, 10);
-                       },
-
-                       open @ ('\'' | '"') => {
-                           let mut buffer: Vec<char> = Vec::new();
-                           let mut escaped: bool = false;
-                           let start: usize = self.cursor;
-                           loop {
-                               match self.advance() {
-                                   // Escaped quote always emits the single character
-                                   // Un-escaped will emit if it's not the same as the open
-                                   Some(quote @ ('\'' | '"')) => {
-                                       if escaped {
-                                           buffer.push(quote);
-                                           escaped = false;
-                                       } else if open != quote {
-                                            buffer.push(quote);
-                                       } else {
-                                           break
-                                       }
-                                   },
-                                   Some('\\') => { // Escaped backslash will emit a backslash, un-escaped will begin an escape sequence (skipping the backslash)
-                                       if escaped {
-                                           buffer.push('\\');
-                                           escaped = false;
-                                       } else {
-                                           escaped = true;
+                           // Scientific notation, i.e. `1e9`, which is only recognized if the `e` is followed by a digit
+                           // Otherwise, `1else` would be scanned as an exponent
+                           let exponent: Option<Vec<char>> = match (self.peek(), self.peek2()) {
+                               (Some('e'), Some('0'..='9')) => {
+                                   self.skip();
+                                   let mut exponent: Vec<char> = Vec::new();
+                                   loop {
+                                       match self.peek() {
+                                           Some('0'..='9') => self.push_advance(&mut exponent),
+                                           Some('_') => self.skip(),
+                                           _ => break
                                        }
-                                   },
-                                   Some('\r') => {}, // A natural `\r` never gets included in a string, when present in source
-                                   Some('n') if escaped => { // `\n` escape sequence -> emit a single `\n`
-                                       buffer.push('\n');
-                                       escaped = false;
-                                   },
-                                   Some('r') if escaped => { // `\r` escape sequence -> emit a single `\r`
-                                       buffer.push('\r');
-                                       escaped = false;
                                    }
-                                   Some('t') if escaped => {
-                                       buffer.push('\t'); // `\t` escape sequence -> emit a single `\t`
-                                       escaped = false;
-                                   },
-                                   Some(c0) => { // Any other character, emits itself. If escaped, the backslash is also included as part of the string
-                                       if escaped {
-                                           buffer.push('\\');
-                                       }
-                                       buffer.push(c0);
-                                       escaped = false;
-                                   }
-                                   None => {
-                                       // Manually report this error at the source point, not at the destination point of the string
-                                       // It makes it much easier to read.
-                                       self.push_err_at(start, 1, UnterminatedStringLiteral);
-                                       break
-                                   }
-                               }
-                           }
-                           self.push(self.cursor - start + 1, StringLiteral(buffer.iter().collect()))
+                                   Some(exponent)
+                               },
+                               _ => None,
+                           };
+                           self.screen_int(buffer, 10, exponent);
                        },
 
+                       open @ ('\'' | '"') => self.scan_string(open, false),
+
                        '!' => match self.peek() {
                            Some('=') => self.push_skip(2, NotEquals),
                            _ => self.push(1, Not)
@@ -341,6 +333,7 @@ impl<'a> Scanner<'a> {
                            Some('=') => self.push_skip(2, GreaterThanEquals),
                            _ => self.push(1, GreaterThan)
                        },
+                       '<' if self.peek_custom_operator() => self.screen_custom_operator(),
                        '<' => match self.peek() {
                            Some('<') => match self.advance_peek() {
                                Some('=') => self.push_skip(3, LeftShiftEquals),
@@ -369,29 +362,46 @@ impl<'a> Scanner<'a> {
                        },
                        '/' => match self.peek() {
                            Some('/') => {
-                               // Single-line comment
+                               // Single-line comment, or a doc comment if it starts with exactly three slashes, i.e. `/// ...`
+                               let start: usize = self.cursor - 1;
+                               self.skip();
+                               let is_doc: bool = self.peek() == Some('/') && self.peek2() != Some('/');
+                               if is_doc {
+                                   self.skip();
+                               }
+                               let mut buffer: Vec<char> = Vec::new();
                                loop {
-                                   match self.advance() {
-                                       Some('\n') => break,
-                                       Some(_) => {},
-                                       None => break
+                                   match self.peek() {
+                                       Some('\n') | None => break,
+                                       Some('\r') => self.skip(),
+                                       Some(_) => self.push_advance(&mut buffer),
                                    }
                                }
+                               if is_doc {
+                                   // A single leading space is not included in the text of the comment
+                                   let text: String = buffer.iter().collect();
+                                   let text: String = text.strip_prefix(' ').map(String::from).unwrap_or(text);
+                                   self.push(self.cursor - start, DocComment(text));
+                               }
                            }
                            Some('*') => {
+                               // Block comments can be nested, so `/* a /* b */ c */` is a single comment
                                let start: usize = self.cursor;
+                               let mut depth: usize = 1;
+                               self.skip();
                                loop {
                                    match self.advance() {
-                                       Some('*') => {
-                                           match self.advance() {
-                                               Some('/') => break,
-                                               Some(_) => {},
-                                               None => {
-                                                   self.push_err_at(start, 2, UnterminatedBlockComment);
-                                                   break
-                                               }
+                                       Some('*') if self.peek() == Some('/') => {
+                                           self.skip();
+                                           depth -= 1;
+                                           if depth == 0 {
+                                               break
                                            }
                                        },
+                                       Some('/') if self.peek() == Some('*') => {
+                                           self.skip();
+                                           depth += 1;
+                                       },
                                        Some(_) => {},
                                        None => {
                                            self.push_err_at(start, 2, UnterminatedBlockComment);
@@ -455,6 +465,183 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    /// Custom operators consist of between one and three symbols, enclosed in `<` and `>`. This must be checked before consuming any of the symbols.
+    /// None of these can form a valid sequence of other tokens, as no expression can begin or end with just these symbols, so they are reserved for custom operators.
+    fn peek_custom_operator(&mut self) -> bool {
+        let mut chars = self.chars.clone();
+        let mut len: usize = 0;
+        loop {
+            match chars.next() {
+                Some(c) if is_custom_operator_symbol(c) && len < 3 => len += 1,
+                Some('>') => return len > 0,
+                _ => return false,
+            }
+        }
+    }
+
+    fn screen_custom_operator(&mut self) {
+        let mut buffer: Vec<char> = vec!['<'];
+        loop {
+            match self.advance() {
+                Some('>') => break,
+                Some(c) => buffer.push(c),
+                None => break,
+            }
+        }
+        buffer.push('>');
+        self.push(buffer.len(), CustomOperator(buffer.iter().collect()));
+    }
+
+    /// Returns `true` if the next characters, following a `_`, are a `_` and then a letter, i.e. `__cmp__`.
+    /// Identifiers cannot start with `_` in general, but these (dunder) identifiers are used for special static members of structs.
+    fn peek_dunder_identifier(&mut self) -> bool {
+        let mut chars = self.chars.clone();
+        matches!((chars.next(), chars.next()), (Some('_'), Some('a'..='z' | 'A'..='Z')))
+    }
+
+    /// Scans a string literal, after the opening quote (and `r` prefix, if `raw`) has been consumed.
+    ///
+    /// - A string opened with three quotes, i.e. `'''...'''`, is only closed by three of the same quote, and so can contain unescaped quotes.
+    /// - A `raw` string does not process any escape sequences. A backslash still prevents the following quote from closing the string, but both are kept.
+    fn scan_string(&mut self, open: char, raw: bool) {
+        let begin: usize = self.cursor - if raw { 2 } else { 1 }; // The start of the token, including the `r` prefix
+        let triple: bool = self.peek() == Some(open) && self.peek2() == Some(open);
+        if triple {
+            self.skip();
+            self.skip();
+        }
+
+        let mut buffer: Vec<char> = Vec::new();
+        let mut escaped: bool = false;
+        let start: usize = self.cursor;
+        loop {
+            match self.advance() {
+                // Escaped quote always emits the single character
+                // Un-escaped will emit if it's not the same as the open, or if it is not followed by two more, in a triple quoted string
+                Some(quote @ ('\'' | '"')) => {
+                    if escaped {
+                        buffer.push(quote);
+                        escaped = false;
+                    } else if open != quote || (triple && (self.peek() != Some(open) || self.peek2() != Some(open))) {
+                        buffer.push(quote);
+                    } else {
+                        if triple {
+                            self.skip();
+                            self.skip();
+                        }
+                        break
+                    }
+                },
+                Some('\\') if raw => { // In a raw string, a backslash and the following character are both emitted as-is
+                    buffer.push('\\');
+                    match self.peek() {
+                        Some('\r') | None => {},
+                        Some(_) => self.push_advance(&mut buffer),
+                    }
+                },
+                Some('\\') => { // Escaped backslash will emit a backslash, un-escaped will begin an escape sequence (skipping the backslash)
+                    if escaped {
+                        buffer.push('\\');
+                        escaped = false;
+                    } else {
+                        escaped = true;
+                    }
+                },
+                Some('\r') => {}, // A natural `\r` never gets included in a string, when present in source
+                Some('n') if escaped => { // `\n` escape sequence -> emit a single `\n`
+                    buffer.push('\n');
+                    escaped = false;
+                },
+                Some('r') if escaped => { // `\r` escape sequence -> emit a single `\r`
+                    buffer.push('\r');
+                    escaped = false;
+                }
+                Some('t') if escaped => {
+                    buffer.push('\t'); // `\t` escape sequence -> emit a single `\t`
+                    escaped = false;
+                },
+                Some('0') if escaped => { // `\0` escape sequence -> emit a single `\0`
+                    buffer.push('\0');
+                    escaped = false;
+                },
+                Some('u') if escaped => { // `\u{...}` escape sequence -> emit a single unicode character
+                    if let Some(c0) = self.scan_unicode_escape() {
+                        buffer.push(c0);
+                    }
+                    escaped = false;
+                },
+                Some(c0) if escaped => { // Any other escaped character is not a valid escape sequence
+                    self.push_err(0, 2, InvalidEscape(c0));
+                    escaped = false;
+                },
+                Some(c0) => buffer.push(c0), // Any other character, emits itself
+                None => {
+                    // Manually report this error at the source point, not at the destination point of the string
+                    // It makes it much easier to read.
+                    self.push_err_at(start, 1, UnterminatedStringLiteral);
+                    break
+                }
+            }
+        }
+        match (self.peek(), self.peek2()) {
+            // A `c` suffix directly after a string literal, i.e. `'a'c`, is a char literal, which is the ordinal of a single character
+            (Some('c'), next) if !matches!(next, Some('a'..='z' | 'A'..='Z' | '0'..='9' | '_')) => {
+                self.skip();
+                match buffer.len() {
+                    1 => self.push(self.cursor - begin, IntLiteral(buffer[0] as i64)),
+                    n => self.push_err(0, self.cursor - begin, InvalidCharLiteral(n)),
+                }
+            },
+            _ => self.push(self.cursor - begin, StringLiteral(buffer.iter().collect()))
+        }
+    }
+
+    /// Scans a `\u{...}` escape sequence, after the `\u` has been consumed, containing between one and six hex digits.
+    /// If the escape sequence is malformed, this raises an error and returns `None`.
+    fn scan_unicode_escape(&mut self) -> Option<char> {
+        let mut text: String = String::from("\\u");
+        let mut digits: String = String::new();
+        if let Some('{') = self.peek() {
+            text.push(self.advance().unwrap());
+            loop {
+                match self.peek() {
+                    Some('}') if !digits.is_empty() => {
+                        text.push(self.advance().unwrap());
+                        break
+                    },
+                    Some(c @ ('0'..='9' | 'a'..='f' | 'A'..='F')) if digits.len() < 6 => {
+                        self.advance();
+                        text.push(c);
+                        digits.push(c);
+                    },
+                    _ => {
+                        self.push_err(0, text.len(), InvalidUnicodeEscape(text));
+                        return None
+                    }
+                }
+            }
+        } else {
+            self.push_err(0, text.len(), InvalidUnicodeEscape(text));
+            return None
+        }
+
+        let value: u32 = u32::from_str_radix(digits.as_str(), 16).unwrap(); // At most six hex digits, so this cannot fail
+        match char::from_u32(value) {
+            Some(c) => Some(c),
+            None => {
+                self.push_err(0, text.len(), InvalidUnicodeCodePoint(value));
+                None
+            }
+        }
+    }
+
+    fn scan_identifier(&mut self, mut buffer: Vec<char>) {
+        while let Some('a'..='z' | 'A'..='Z' | '0'..='9' | '_') = self.peek() {
+            self.push_advance(&mut buffer);
+        }
+        self.screen_identifier(buffer);
+    }
+
     fn screen_identifier(&mut self, buffer: Vec<char>) {
         let string: String = buffer.iter().collect();
         let len: usize = string.len();
@@ -479,8 +666,14 @@ impl<'a> Scanner<'a> {
             "false" => KeywordFalse,
             "nil" => KeywordNil,
             "struct" => KeywordStruct,
+            "enum" => KeywordEnum,
+            "const" => KeywordConst,
             "exit" => KeywordExit,
             "assert" => KeywordAssert,
+            "with" => KeywordWith,
+            "as" => KeywordAs,
+            "async" => KeywordAsync,
+            "await" => KeywordAwait,
             "and" => LogicalAnd,
             "or" => LogicalOr,
              _ => Identifier(string)
@@ -488,9 +681,12 @@ impl<'a> Scanner<'a> {
         self.push(len, token);
     }
 
-    fn screen_int(&mut self, buffer: Vec<char>, radix: u32) {
+    fn screen_int(&mut self, buffer: Vec<char>, radix: u32, exponent: Option<Vec<char>>) {
         let string: String = buffer.iter().collect();
         let mut len: usize = string.len();
+        if let Some(exponent) = &exponent {
+            len += 1 + exponent.len(); // To account for the `e`
+        }
         let is_complex: bool = match self.peek() {
             Some('i' | 'j') => {
                 self.advance();
@@ -503,8 +699,19 @@ impl<'a> Scanner<'a> {
             len += 2; // To account for the numeric prefix
         }
 
+        if let Some(c) = buffer.iter().find(|c| !c.is_digit(radix)) {
+            self.push_err(0, len, InvalidNumericDigit(*c, radix));
+            return
+        }
+
         match i64::from_str_radix(string.as_str(), radix) {
-            Ok(value) => self.push(len, if is_complex { ComplexLiteral(value) } else { IntLiteral(value) }),
+            Ok(value) => match exponent {
+                Some(exponent) => match exponent.iter().collect::<String>().parse::<u32>().ok().and_then(|e| 10i64.checked_pow(e)).and_then(|e| value.checked_mul(e)) {
+                    Some(value) => self.push_numeric_literal(len, value, is_complex),
+                    None => self.push_err(0, len, NumericValueTooLarge),
+                },
+                None => self.push_numeric_literal(len, value, is_complex),
+            },
             Err(e) => self.push_err(0, len, InvalidNumericValue(e))
         }
     }
@@ -519,6 +726,15 @@ impl<'a> Scanner<'a> {
         self.push(width, token);
     }
 
+    /// Pushes an `int` or complex literal. Unlike the imaginary part of a complex number, an `int` must fit in the range of a `ValuePtr`, so anything larger is an error.
+    fn push_numeric_literal(&mut self, width: usize, value: i64, is_complex: bool) {
+        match is_complex {
+            true => self.push(width, ComplexLiteral(value)),
+            false if value <= MAX_INT => self.push(width, IntLiteral(value)),
+            false => self.push_err(0, width, NumericValueTooLarge),
+        }
+    }
+
     fn push_err(&mut self, offset: usize, width: usize, error: ScanErrorType) {
         self.errors.push(ScanError {
             error,
@@ -573,6 +789,16 @@ impl<'a> Scanner<'a> {
     fn peek(&mut self) -> Option<char> {
         self.chars.peek().copied()
     }
+
+    /// Inspects the character after the next one, without consuming either
+    fn peek2(&mut self) -> Option<char> {
+        self.chars.clone().nth(1)
+    }
+}
+
+
+fn is_custom_operator_symbol(c: char) -> bool {
+    matches!(c, '+' | '-' | '*' | '/' | '%' | '^' | '&' | '|' | '$' | '~' | '!' | '?' | ':' | '=' | '@' | '.')
 }
 
 
@@ -587,15 +813,33 @@ mod tests {
 
 
     #[test] fn test_empty() { run_str("", vec![]); }
-    #[test] fn test_keywords() { run_str("let fn return if elif else then loop while for in is not break continue do true false nil struct exit assert", vec![KeywordLet, KeywordFn, KeywordReturn, KeywordIf, KeywordElif, KeywordElse, KeywordThen, KeywordLoop, KeywordWhile, KeywordFor, KeywordIn, KeywordIs, KeywordNot, KeywordBreak, KeywordContinue, KeywordDo, KeywordTrue, KeywordFalse, KeywordNil, KeywordStruct, KeywordExit, KeywordAssert]); }
+    #[test] fn test_keywords() { run_str("let fn return if elif else then loop while for in is not break continue do true false nil struct enum const exit assert with as async await", vec![KeywordLet, KeywordFn, KeywordReturn, KeywordIf, KeywordElif, KeywordElse, KeywordThen, KeywordLoop, KeywordWhile, KeywordFor, KeywordIn, KeywordIs, KeywordNot, KeywordBreak, KeywordContinue, KeywordDo, KeywordTrue, KeywordFalse, KeywordNil, KeywordStruct, KeywordEnum, KeywordConst, KeywordExit, KeywordAssert, KeywordWith, KeywordAs, KeywordAsync, KeywordAwait]); }
+    #[test] fn test_dunder_identifiers() { run_str("__cmp__ __lt__ _ __ _a", vec![Identifier(String::from("__cmp__")), Identifier(String::from("__lt__")), Underscore, Underscore, Underscore, Underscore, Identifier(String::from("a"))]); }
     #[test] fn test_identifiers() { run_str("foobar big_bad_wolf ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz", vec![Identifier(String::from("foobar")), Identifier(String::from("big_bad_wolf")), Identifier(String::from("ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz"))]); }
     #[test] fn test_str_literals() { run_str("'abc' 'a \n 3' '\\''", vec![StringLiteral(String::from("abc")), NewLine, StringLiteral(String::from("a \n 3")), StringLiteral(String::from("'"))]); }
     #[test] fn test_double_quote_str_literals() { run_str("\"abc\" '\"' \"'\"", vec![StringLiteral(String::from("abc")), StringLiteral(String::from("\"")), StringLiteral(String::from("'"))]); }
-    #[test] fn test_str_escaping() { run_str("'\\.' '\\\\.' '\\n' '\\\\n'", vec![StringLiteral(String::from("\\.")), StringLiteral(String::from("\\.")), StringLiteral(String::from("\n")), StringLiteral(String::from("\\n"))]); }
+    #[test] fn test_str_escaping() { run_str("'\\\\.' '\\n' '\\\\n'", vec![StringLiteral(String::from("\\.")), StringLiteral(String::from("\n")), StringLiteral(String::from("\\n"))]); }
+    #[test] fn test_str_unicode_escapes() { run_str(r"'\u{41}' '\u{1f600}' '\u{0}' '\0' '\\u{41}'", vec![StringLiteral(String::from("A")), StringLiteral(String::from("\u{1f600}")), StringLiteral(String::from("\0")), StringLiteral(String::from("\0")), StringLiteral(String::from(r"\u{41}"))]); }
+    #[test] fn test_block_comments() { run_str("1 /* 2 */ 3 /**/ 4 /* 5 **/ 6", vec![IntLiteral(1), IntLiteral(3), IntLiteral(4), IntLiteral(6)]); }
+    #[test] fn test_nested_block_comments() { run_str("1 /* 2 /* 3 */ 4 */ 5 /*/* */*/ 6", vec![IntLiteral(1), IntLiteral(5), IntLiteral(6)]); }
+    #[test] fn test_line_comments() { run_str("1 // 2\n3 //// 4\n5", vec![IntLiteral(1), NewLine, IntLiteral(3), NewLine, IntLiteral(5)]); }
+    #[test] fn test_doc_comments() { run_str("/// one\n///two\n///\nfn", vec![DocComment(String::from("one")), NewLine, DocComment(String::from("two")), NewLine, DocComment(String::new()), NewLine, KeywordFn]); }
+    #[test] fn test_raw_str_unicode_escapes() { run_str(r"r'\u{41}' r'\u'", vec![StringLiteral(String::from(r"\u{41}")), StringLiteral(String::from(r"\u"))]); }
+    #[test] fn test_raw_str_literals() { run_str(r#"r'\d+\.' r"\n" r'\'' r'\\'"#, vec![StringLiteral(String::from(r"\d+\.")), StringLiteral(String::from(r"\n")), StringLiteral(String::from(r"\'")), StringLiteral(String::from(r"\\"))]); }
+    #[test] fn test_raw_str_literals_not_a_prefix() { run_str("r 'a' rr'a' r", vec![Identifier(String::from("r")), StringLiteral(String::from("a")), Identifier(String::from("rr")), StringLiteral(String::from("a")), Identifier(String::from("r"))]); }
+    #[test] fn test_triple_quoted_str_literals() { run_str(r#"'''a 'b' "c"''' """it's""" '''\n'''"#, vec![StringLiteral(String::from("a 'b' \"c\"")), StringLiteral(String::from("it's")), StringLiteral(String::from("\n"))]); }
+    #[test] fn test_triple_quoted_str_literals_with_newlines() { run_str("'''a\n\nb'''", vec![NewLine, NewLine, StringLiteral(String::from("a\n\nb"))]); }
+    #[test] fn test_empty_str_literals() { run_str("'' \"\" ''.", vec![StringLiteral(String::new()), StringLiteral(String::new()), StringLiteral(String::new()), Dot]); }
+    #[test] fn test_raw_triple_quoted_str_literals() { run_str(r"r'''\d'\s'''", vec![StringLiteral(String::from(r"\d'\s"))]); }
     #[test] fn test_ints() { run_str("1234 654 10_00_00 0 1", vec![IntLiteral(1234), IntLiteral(654), IntLiteral(100000), IntLiteral(0), IntLiteral(1)]); }
     #[test] fn test_binary_ints() { run_str("0b11011011 0b0 0b1 0b1_01", vec![IntLiteral(0b11011011), IntLiteral(0b0), IntLiteral(0b1), IntLiteral(0b101)]); }
     #[test] fn test_hex_ints() { run_str("0x12345678 0xabcdef90 0xABCDEF 0xF_f", vec![IntLiteral(0x12345678), IntLiteral(0xabcdef90), IntLiteral(0xABCDEF), IntLiteral(0xFF)])}
     #[test] fn test_complex_ints() { run_str("0i 0j 1i 1j 0b101i 0xfi 123i", vec![IntLiteral(0), IntLiteral(0), ComplexLiteral(1), ComplexLiteral(1), ComplexLiteral(5), ComplexLiteral(0xf), ComplexLiteral(123)]); }
+    #[test] fn test_octal_ints() { run_str("0o755 0o0 0o7_7 0o17i", vec![IntLiteral(0o755), IntLiteral(0), IntLiteral(0o77), ComplexLiteral(0o17)]); }
+    #[test] fn test_scientific_ints() { run_str("1e9 2e0 15e2 1e1_0 3e2i", vec![IntLiteral(1_000_000_000), IntLiteral(2), IntLiteral(1500), IntLiteral(10_000_000_000), ComplexLiteral(300)]); }
+    #[test] fn test_scientific_ints_not_an_exponent() { run_str("1else 2e", vec![IntLiteral(1), KeywordElse, IntLiteral(2), Identifier(String::from("e"))]); }
+    #[test] fn test_char_literals() { run_str("'a'c \"0\"c '\\n'c ' 'c", vec![IntLiteral(97), IntLiteral(48), IntLiteral(10), IntLiteral(32)]); }
+    #[test] fn test_char_literals_not_a_suffix() { run_str("'a'count 'a' c", vec![StringLiteral(String::from("a")), Identifier(String::from("count")), StringLiteral(String::from("a")), Identifier(String::from("c"))]); }
     #[test] fn test_unary_operators() { run_str("- !", vec![Minus, Not]); }
     #[test] fn test_comparison_operators() { run_str("> < >= > = <= < =", vec![GreaterThan, LessThan, GreaterThanEquals, GreaterThan, Equals, LessThanEquals, LessThan, Equals]); }
     #[test] fn test_equality_operators() { run_str("!= ! = == =", vec![NotEquals, Not, Equals, DoubleEquals, Equals]); }
@@ -604,6 +848,8 @@ mod tests {
     #[test] fn test_other_arithmetic_operators() { run_str("% %= ** *= **= * *=", vec![Mod, ModEquals, Pow, MulEquals, PowEquals, Mul, MulEquals]); }
     #[test] fn test_bitwise_operators() { run_str("| ^ & &= |= ^=", vec![BitwiseOr, BitwiseXor, BitwiseAnd, AndEquals, OrEquals, XorEquals]); }
     #[test] fn test_groupings() { run_str("( [ { } ] )", vec![OpenParen, OpenSquareBracket, OpenBrace, CloseBrace, CloseSquareBracket, CloseParen]); }
+    #[test] fn test_custom_operators() { run_str("<+> <$> <|> <=> <.:.> <= > < + >", vec![CustomOperator(String::from("<+>")), CustomOperator(String::from("<$>")), CustomOperator(String::from("<|>")), CustomOperator(String::from("<=>")), CustomOperator(String::from("<.:.>")), LessThanEquals, GreaterThan, LessThan, Plus, GreaterThan]); }
+    #[test] fn test_not_custom_operators() { run_str("<<> <-x> <****> <>", vec![LeftShift, GreaterThan, LessThan, Minus, Identifier(String::from("x")), GreaterThan, LessThan, Pow, Pow, GreaterThan, LessThan, GreaterThan]); }
     #[test] fn test_syntax() { run_str(". .. ... .= , -> - > : @", vec![Dot, Dot, Dot, Ellipsis, DotEquals, Comma, Arrow, Minus, GreaterThan, Colon, At]); }
 
 
@@ -611,6 +857,9 @@ mod tests {
     #[test] fn test_invalid_character() { run("invalid_character"); }
     #[test] fn test_invalid_numeric_prefix() { run("invalid_numeric_prefix"); }
     #[test] fn test_invalid_numeric_value() { run("invalid_numeric_value"); }
+    #[test] fn test_invalid_numeric_digit() { run("invalid_numeric_digit"); }
+    #[test] fn test_invalid_char_literal() { run("invalid_char_literal"); }
+    #[test] fn test_invalid_unicode_escape() { run("invalid_unicode_escape"); }
     #[test] fn test_string_with_newlines() { run("string_with_newlines"); }
     #[test] fn test_unterminated_block_comment() { run("unterminated_block_comment"); }
     #[test] fn test_unterminated_string_literal() { run("unterminated_string_literal"); }
@@ -0,0 +1,64 @@
+use crate::compiler::CompileResult;
+use crate::vm::{Opcode, StoreOp};
+
+use Opcode::{*};
+
+
+impl CompileResult {
+
+    /// Checks that every index referenced by the compiled code is in range, so the VM is able to trust them without checking each one at runtime.
+    ///
+    /// This includes the targets of jumps, the indices of constants, globals, patterns and fields, and the head, tail, and default argument offsets of every function.
+    /// Local variables and upvalues are not checked, as they are relative to the current call frame, and so can only be checked at runtime.
+    ///
+    /// Returns each error found, along with the index of the instruction which caused it.
+    pub fn verify(&self) -> Result<(), Vec<(usize, String)>> {
+        let mut errors: Vec<(usize, String)> = Vec::new();
+        for (ip, op) in self.code.iter().enumerate() {
+            if let Err(e) = self.verify_opcode(*op) {
+                errors.push((ip, e));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    fn verify_opcode(&self, op: Opcode) -> Result<(), String> {
+        if let Some(target) = op.jump_target() {
+            check("Jump target", target, self.code.len(), "instructions")?;
+        }
+        match op {
+            Constant(id) | BinaryConst(_, id) => {
+                check("Constant", id as usize, self.constants.len(), "constants")?;
+                let constant = &self.constants[id as usize];
+                if constant.is_function() {
+                    let func = constant.as_function().borrow_const();
+                    if func.head > func.tail || func.tail >= self.code.len() || func.default_args().iter().any(|offset| func.head + offset > func.tail) {
+                        return Err(format!("Function {} has an invalid range L[{}, {}], there are only {} instructions", func.repr(), func.head, func.tail, self.code.len()))
+                    }
+                }
+            },
+            PushGlobal(id) | StoreGlobal(id, _) => check("Global", id as usize, self.globals.len(), "globals")?,
+            ExecPattern(id) => {
+                check("Pattern", id as usize, self.patterns.len(), "patterns")?;
+                for store in self.patterns[id as usize].stores() {
+                    if let StoreOp::Global(id) = store {
+                        check("Global", id as usize, self.globals.len(), "globals")?;
+                    }
+                }
+            },
+            GetField(id) | GetFieldPeek(id) | GetFieldFunction(id) | SetField(id) => check("Field", id as usize, self.fields.field_count(), "fields")?,
+            _ => {},
+        }
+        Ok(())
+    }
+}
+
+fn check(kind: &str, index: usize, len: usize, plural: &str) -> Result<(), String> {
+    match index < len {
+        true => Ok(()),
+        false => Err(format!("{} {} is out of range, there are only {} {}", kind, index, len, plural)),
+    }
+}
@@ -0,0 +1,228 @@
+use crate::vm::{ErrorResult, IntoDictValue, IntoValue, RuntimeError, Type, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// The exit code used when the program arguments do not match the spec, following the convention of most command line tools.
+const USAGE_EXIT_CODE: i32 = 2;
+
+
+/// Parses the program arguments according to `spec`, and returns a dictionary of each argument's name to its value.
+///
+/// Each key of `spec` is the name of an argument, and each value is either the default value of that argument, or a dictionary with the keys `'default'` and `'help'`.
+/// - Names starting with `--` are options. An option whose default is a `bool` is a flag, which takes no value and is `true` if present.
+/// - All other names are positional arguments, which are matched in the order they appear in `spec`. A positional argument with a default of `nil` is required.
+///
+/// If the default of an argument is an `int`, the argument is converted to an `int`, otherwise it is a `str`.
+/// If `--help` or `-h` is present, a usage message is printed and the program exits. If the arguments are invalid, a usage message and the error are printed to standard error, and the program exits with code `2`.
+pub fn argparse<VM : VirtualInterface>(vm: &mut VM, spec: ValuePtr) -> ValueResult {
+    let args: Vec<Arg> = parse_spec(spec)?;
+    let program_args: Vec<String> = vm.get_args().to_iter()?.map(|arg| arg.to_str()).collect();
+    match parse_args(&args, program_args) {
+        Ok(Some(values)) => args.into_iter()
+            .zip(values)
+            .map(|(arg, value)| (arg.key.to_value(), value))
+            .to_dict()
+            .ok(),
+        Ok(None) => {
+            vm.print(help(&args));
+            RuntimeExit(0).err()
+        },
+        Err(message) => {
+            vm.eprintln(format!("{}error: {}", usage(&args), message));
+            RuntimeExit(USAGE_EXIT_CODE).err()
+        },
+    }
+}
+
+
+#[derive(Debug)]
+struct Arg {
+    /// The name of the argument, as given in the spec, e.g. `--verbose` or `file`
+    name: String,
+    /// The key of the argument in the returned dictionary, e.g. `verbose` or `file`
+    key: String,
+    kind: ArgKind,
+    default: ValuePtr,
+    help: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ArgKind {
+    Flag, Option, Positional
+}
+
+impl Arg {
+    /// The placeholder shown for the value of this argument in help messages, e.g. `COUNT`
+    fn metavar(&self) -> String {
+        self.key.to_uppercase()
+    }
+
+    fn is_required(&self) -> bool {
+        self.kind == ArgKind::Positional && self.default.is_nil()
+    }
+
+    /// Converts `value` to the type of this argument's default value.
+    fn convert(&self, value: String) -> Result<ValuePtr, String> {
+        match self.default.ty() {
+            Type::Int => value.parse::<i64>()
+                .map(|i| i.to_value())
+                .map_err(|_| format!("argument {}: expected an int, got '{}'", self.name, value)),
+            _ => Ok(value.to_value()),
+        }
+    }
+}
+
+
+fn parse_spec(spec: ValuePtr) -> ErrorResult<Vec<Arg>> {
+    let spec = spec.check_dict()?;
+    let it = spec.as_dict().borrow();
+    let mut args: Vec<Arg> = Vec::with_capacity(it.dict.len());
+    for (name, value) in &it.dict {
+        let name: String = name.clone().check_str()?.as_str().borrow_const().clone();
+        let (is_option, key) = match name.strip_prefix("--") {
+            Some(key) => (true, key.replace('-', "_")),
+            None => (false, name.clone()),
+        };
+        if key.is_empty() || key.starts_with('-') || key == "help" {
+            return ValueErrorInvalidArgumentSpec(name.to_value()).err()
+        }
+        let (default, help) = match value.is_dict() {
+            true => {
+                let desc = value.as_dict().borrow();
+                let default: ValuePtr = desc.dict.get(&"default".to_value()).cloned().unwrap_or_else(ValuePtr::nil);
+                let help: Option<String> = desc.dict.get(&"help".to_value()).map(|help| help.to_str());
+                (default, help)
+            },
+            false => (value.clone(), None),
+        };
+        let kind: ArgKind = match (is_option, default.is_bool()) {
+            (true, true) => ArgKind::Flag,
+            (true, false) => ArgKind::Option,
+            (false, _) => ArgKind::Positional,
+        };
+        args.push(Arg { name, key, kind, default, help });
+    }
+    Ok(args)
+}
+
+/// Matches `program_args` against `args`, returning the value of each argument in the same order as `args`, or `None` if help was requested.
+/// If the program arguments do not match the spec, returns a message describing why.
+fn parse_args(args: &[Arg], program_args: Vec<String>) -> Result<Option<Vec<ValuePtr>>, String> {
+    let mut values: Vec<Option<ValuePtr>> = vec![None; args.len()];
+    let mut positionals = args.iter().enumerate().filter(|(_, arg)| arg.kind == ArgKind::Positional);
+    let mut iter = program_args.into_iter();
+    let mut only_positional: bool = false;
+
+    while let Some(program_arg) = iter.next() {
+        if !only_positional && program_arg.starts_with('-') && program_arg != "-" {
+            if program_arg == "--" {
+                only_positional = true;
+                continue
+            }
+            if program_arg == "--help" || program_arg == "-h" {
+                return Ok(None)
+            }
+            let (name, inline) = match program_arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (program_arg.as_str(), None),
+            };
+            let (index, arg) = match args.iter().enumerate().find(|(_, arg)| arg.kind != ArgKind::Positional && arg.name == name) {
+                Some(it) => it,
+                None => return Err(format!("unrecognized argument '{}'", name)),
+            };
+            values[index] = Some(match arg.kind {
+                ArgKind::Flag => match inline {
+                    Some(_) => return Err(format!("argument {}: does not take a value", arg.name)),
+                    None => true.to_value(),
+                },
+                _ => match inline.or_else(|| iter.next()) {
+                    Some(value) => arg.convert(value)?,
+                    None => return Err(format!("argument {}: expected a value", arg.name)),
+                },
+            });
+        } else {
+            match positionals.next() {
+                Some((index, arg)) => values[index] = Some(arg.convert(program_arg)?),
+                None => return Err(format!("unrecognized argument '{}'", program_arg)),
+            }
+        }
+    }
+
+    let missing: Vec<&str> = args.iter()
+        .zip(values.iter())
+        .filter(|(arg, value)| value.is_none() && arg.is_required())
+        .map(|(arg, _)| arg.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("missing required argument{} {}", if missing.len() == 1 { "" } else { "s" }, missing.join(", ")))
+    }
+
+    Ok(Some(args.iter()
+        .zip(values)
+        .map(|(arg, value)| value.unwrap_or_else(|| arg.default.clone()))
+        .collect()))
+}
+
+
+/// Returns the single line usage message, i.e. `usage: [-h] [--verbose] [--count COUNT] file`, followed by a newline.
+fn usage(args: &[Arg]) -> String {
+    let mut text: String = String::from("usage: [-h]");
+    for arg in args {
+        text.push(' ');
+        text.push_str(match arg.kind {
+            ArgKind::Flag => format!("[{}]", arg.name),
+            ArgKind::Option => format!("[{} {}]", arg.name, arg.metavar()),
+            ArgKind::Positional if arg.is_required() => arg.name.clone(),
+            ArgKind::Positional => format!("[{}]", arg.name),
+        }.as_str());
+    }
+    text.push('\n');
+    text
+}
+
+/// Returns the full help message, which lists each argument along with its help text and default value.
+fn help(args: &[Arg]) -> String {
+    let positionals: Vec<(String, String)> = args.iter()
+        .filter(|arg| arg.kind == ArgKind::Positional)
+        .map(|arg| (arg.name.clone(), describe(arg)))
+        .collect();
+    let options: Vec<(String, String)> = std::iter::once((String::from("-h, --help"), String::from("show this help message and exit")))
+        .chain(args.iter()
+            .filter(|arg| arg.kind != ArgKind::Positional)
+            .map(|arg| (match arg.kind {
+                ArgKind::Flag => arg.name.clone(),
+                _ => format!("{} {}", arg.name, arg.metavar()),
+            }, describe(arg))))
+        .collect();
+
+    let width: usize = positionals.iter().chain(options.iter()).map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut text: String = usage(args);
+    for (title, section) in [("positional arguments", positionals), ("options", options)] {
+        if section.is_empty() {
+            continue
+        }
+        text.push_str(format!("\n{}:\n", title).as_str());
+        for (name, desc) in section {
+            let line: String = format!("  {:<width$}  {}", name, desc, width = width);
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Describes an argument in the help message, by its help text and its default value, if it has one that is worth showing.
+fn describe(arg: &Arg) -> String {
+    let default: Option<String> = match arg.kind {
+        ArgKind::Flag => None,
+        _ if arg.default.is_nil() => None,
+        _ => Some(format!("(default: {})", arg.default.to_repr_str())),
+    };
+    match (&arg.help, default) {
+        (Some(help), Some(default)) => format!("{} {}", help, default),
+        (Some(help), None) => help.clone(),
+        (None, Some(default)) => default,
+        (None, None) => String::new(),
+    }
+}
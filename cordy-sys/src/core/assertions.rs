@@ -0,0 +1,93 @@
+use num_traits::CheckedSub;
+
+use crate::reporting::AsError;
+use crate::vm::{IntoValue, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// Raises an assertion error if `a` is not equal to `b`. The message shows both values, and for collections, where they first differ.
+pub fn assert_eq(a: ValuePtr, b: ValuePtr) -> ValueResult {
+    if a == b {
+        return ValuePtr::nil().ok()
+    }
+    let message: String = match difference(&a, &b) {
+        Some(difference) => format!("{} != {}, {}", a.to_repr_str(), b.to_repr_str(), difference),
+        None => format!("{} != {}", a.to_repr_str(), b.to_repr_str()),
+    };
+    RuntimeAssertFailed(message).err()
+}
+
+/// Invokes `f` with no arguments, and raises an assertion error if it does not raise an error. Returns the error message.
+pub fn assert_err<VM : VirtualInterface>(vm: &mut VM, f: ValuePtr) -> ValueResult {
+    if !f.is_evaluable() {
+        return TypeErrorArgMustBeFunction(f).err()
+    }
+    match vm.try_call(f.clone(), []).as_result() {
+        Ok(ret) => RuntimeAssertFailed(format!("Expected {} to raise an error, but it returned {}", f.to_repr_str(), ret.to_repr_str())).err(),
+        Err(err) => match err.value {
+            // Exiting the program is not an error, and so is not caught
+            error @ (RuntimeExit(_) | RuntimeYield) => error.err(),
+            error => error.as_error().to_value().ok(),
+        },
+    }
+}
+
+/// Returns `true` if `a` and `b` differ by at most `eps`.
+pub fn approx_eq(a: ValuePtr, b: ValuePtr, eps: ValuePtr) -> ValueResult {
+    let a = a.check_fraction()?.as_fraction();
+    let b = b.check_fraction()?.as_fraction();
+    let eps = eps.check_fraction()?.as_fraction();
+    match if a > b { a.checked_sub(&b) } else { b.checked_sub(&a) } {
+        Some(diff) => (diff <= eps).to_value().ok(),
+        None => ValueErrorFractionOverflow.err(),
+    }
+}
+
+
+/// For two unequal collections of the same type, returns a description of where they first differ.
+fn difference(a: &ValuePtr, b: &ValuePtr) -> Option<String> {
+    if a.ty() != b.ty() {
+        return None
+    }
+    if a.is_list() {
+        sequence_difference(a.as_list().borrow().list.iter().cloned(), b.as_list().borrow().list.iter().cloned())
+    } else if a.is_vector() {
+        sequence_difference(a.as_vector().borrow().vector.iter().cloned(), b.as_vector().borrow().vector.iter().cloned())
+    } else if a.is_str() {
+        sequence_difference(a.as_str().borrow_const().chars().map(|c| c.to_string().to_value()), b.as_str().borrow_const().chars().map(|c| c.to_string().to_value()))
+    } else if a.is_dict() {
+        let a = a.as_dict().borrow();
+        let b = b.as_dict().borrow();
+        for (key, x) in &a.dict {
+            match b.dict.get(key) {
+                Some(y) if x == y => {},
+                Some(y) => return Some(format!("first difference at key {}: {} != {}", key.to_repr_str(), x.to_repr_str(), y.to_repr_str())),
+                None => return Some(format!("key {} is missing on the right", key.to_repr_str())),
+            }
+        }
+        b.dict.keys()
+            .find(|key| !a.dict.contains_key(*key))
+            .map(|key| format!("key {} is missing on the left", key.to_repr_str()))
+    } else if a.is_set() {
+        let a = a.as_set().borrow();
+        let b = b.as_set().borrow();
+        match a.set.iter().find(|x| !b.set.contains(*x)) {
+            Some(x) => Some(format!("element {} is missing on the right", x.to_repr_str())),
+            None => b.set.iter()
+                .find(|y| !a.set.contains(*y))
+                .map(|y| format!("element {} is missing on the left", y.to_repr_str())),
+        }
+    } else {
+        None
+    }
+}
+
+fn sequence_difference(a: impl Iterator<Item=ValuePtr>, b: impl Iterator<Item=ValuePtr>) -> Option<String> {
+    let a: Vec<ValuePtr> = a.collect();
+    let b: Vec<ValuePtr> = b.collect();
+    match a.iter().zip(b.iter()).position(|(x, y)| x != y) {
+        Some(index) => Some(format!("first difference at index {}: {} != {}", index, a[index].to_repr_str(), b[index].to_repr_str())),
+        None => Some(format!("lengths differ: {} != {}", a.len(), b.len())),
+    }
+}
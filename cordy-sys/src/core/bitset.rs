@@ -0,0 +1,62 @@
+use crate::core::collections;
+use crate::vm::{AnyResult, BitSetImpl, IntoValue, RuntimeError, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// Constructs a new bitset of the given size, optionally with the indices in `bits` set.
+pub fn create(size: ValuePtr, bits: Option<ValuePtr>) -> ValueResult {
+    let size: i64 = size.check_int()?.as_int();
+    if size < 0 {
+        return ValueErrorValueMustBeNonNegative(size).err()
+    }
+    let mut it = match BitSetImpl::try_new(size as usize) {
+        Some(it) => it,
+        None => return ValueErrorBitSetTooLarge(size).err(),
+    };
+    if let Some(bits) = bits {
+        for bit in bits.to_iter()? {
+            let index: usize = collections::check_index(bit, it.size)?;
+            it.set(index, true);
+        }
+    }
+    it.to_value().ok()
+}
+
+pub fn get_index(bitset: &ValuePtr, index: ValuePtr) -> ValueResult {
+    let it = bitset.as_bitset().borrow();
+    let index: usize = collections::check_index(index, it.size)?;
+    it.get(index).to_value().ok()
+}
+
+pub fn set_index(bitset: &ValuePtr, index: ValuePtr, value: ValuePtr) -> AnyResult {
+    let mut it = bitset.as_bitset().borrow_mut();
+    let index: usize = collections::check_index(index, it.size)?;
+    it.set(index, value.to_bool());
+    Ok(())
+}
+
+/// Returns `true` if the bit at `index` is set.
+pub fn get_bit(index: ValuePtr, bitset: ValuePtr) -> ValueResult {
+    let bitset = bitset.check_bitset()?;
+    get_index(&bitset, index)
+}
+
+/// Sets the bit at `index`, returning the bitset.
+pub fn set_bit(index: ValuePtr, bitset: ValuePtr) -> ValueResult {
+    let bitset = bitset.check_bitset()?;
+    set_index(&bitset, index, true.to_value())?;
+    bitset.ok()
+}
+
+/// Flips the bit at `index`, returning the bitset.
+pub fn flip_bit(index: ValuePtr, bitset: ValuePtr) -> ValueResult {
+    let bitset = bitset.check_bitset()?;
+    {
+        let mut it = bitset.as_bitset().borrow_mut();
+        let index: usize = collections::check_index(index, it.size)?;
+        let value: bool = it.get(index);
+        it.set(index, !value);
+    }
+    bitset.ok()
+}
@@ -1,12 +1,14 @@
 use std::cmp::{Ordering, Reverse};
 use std::collections::VecDeque;
+use std::ops::ControlFlow;
 use fxhash::FxBuildHasher;
 use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
 
 use crate::{util, vm};
-use crate::core::{InvokeArg0, InvokeArg1, InvokeArg2};
-use crate::vm::{AnyResult, ErrorResult, IntoDictValue, IntoIterableValue, IntoValue, Iterable, RuntimeError, Type, ValuePtr, ValueResult, VirtualInterface};
+use crate::core::{bitset, InvokeArg0, InvokeArg1, InvokeArg2, matrix, persistent, sorted, strings};
+use crate::vm::operator;
+use crate::vm::{AnyResult, ErrorResult, IntoDictValue, IntoIterableValue, IntoValue, Iterable, LazyIterImpl, RuntimeError, Type, ValuePtr, ValueResult, ViewImpl, VirtualInterface};
 
 use RuntimeError::{*};
 
@@ -15,6 +17,18 @@ pub fn get_index<VM : VirtualInterface>(vm: &mut VM, target: &ValuePtr, index: V
     if target.is_dict() {
         return get_dict_index(vm, target, index);
     }
+    if target.is_matrix() {
+        return matrix::get_index(target, index);
+    }
+    if target.is_bitset() {
+        return bitset::get_index(target, index);
+    }
+    if target.is_sorted_dict() {
+        return sorted::get_index(target, index);
+    }
+    if target.is_pdict() {
+        return persistent::get_index(target, index);
+    }
 
     let indexable = target.to_index()?;
     let index: usize = indexable.check_index(index)?;
@@ -29,6 +43,7 @@ fn get_dict_index<VM : VirtualInterface>(vm: &mut VM, dict: &ValuePtr, key: Valu
     // Initially unbox (non mutable) to clone out the default value.
     // If the default is a function, we can't have a reference out of the dict while we're accessing the default.
 
+    let ptr: &ValuePtr = dict;
     let dict = dict.as_dict();
     let default_factory: InvokeArg0;
     {
@@ -51,6 +66,8 @@ fn get_dict_index<VM : VirtualInterface>(vm: &mut VM, dict: &ValuePtr, key: Valu
     // Invoke the new value supplier - this might modify the dict
     // We go through the `.entry()` API again in this case
     let new_value: ValuePtr = default_factory.invoke(vm)?;
+    ptr.check_mutable()?;
+    key.freeze();
     let mut dict = dict.borrow_mut();
 
     dict.dict.entry(key)
@@ -60,11 +77,20 @@ fn get_dict_index<VM : VirtualInterface>(vm: &mut VM, dict: &ValuePtr, key: Valu
 }
 
 pub fn set_index(target: &ValuePtr, index: ValuePtr, value: ValuePtr) -> AnyResult {
+    if target.is_sorted_dict() {
+        return sorted::set_index(target, index, value);
+    }
+    target.check_mutable()?;
     if target.is_dict() {
+        index.freeze();
         match vm::guard_recursive_hash(|| target.as_dict().borrow_mut().dict.insert(index, value)) {
             Err(_) => ValueErrorRecursiveHash(target.clone()).err(),
             Ok(_) => Ok(())
         }
+    } else if target.is_matrix() {
+        matrix::set_index(target, index, value)
+    } else if target.is_bitset() {
+        bitset::set_index(target, index, value)
     } else {
         let mut indexable = target.to_index()?;
         let index: usize = indexable.check_index(index)?;
@@ -105,6 +131,13 @@ pub fn get_slice(target: &ValuePtr, low: ValuePtr, high: ValuePtr, step: ValuePt
     let abs_stop: i64 = to_index(length, high);
     let abs_step: usize = step.unsigned_abs() as usize;
 
+    // Views share their backing storage, so a contiguous slice of a view does not need to copy any elements
+    if step == 1 && target.is_view() {
+        let start: i64 = abs_start.clamp(0, length);
+        let stop: i64 = abs_stop.clamp(start, length);
+        return target.as_view().borrow_const().sub_view(start as usize, stop as usize).to_value().ok()
+    }
+
     if step > 0 {
         for i in (abs_start..abs_stop).step_by(abs_step) {
             slice.accept(i)
@@ -128,6 +161,17 @@ pub fn to_index(len: i64, pos_or_neg: i64) -> i64 {
     }
 }
 
+/// Takes an `int` index in `[-len, len)`, and converts it to a real index in `[0, len)`, or raises an error.
+pub fn check_index(value: ValuePtr, len: usize) -> ErrorResult<usize> {
+    let index: i64 = value.check_int()?.as_int();
+    let raw: usize = to_index(len as i64, index) as usize;
+    if raw < len {
+        Ok(raw)
+    } else {
+        ValueErrorIndexOutOfBounds(index, len).err()
+    }
+}
+
 #[inline(always)]
 fn rev_range(start_high_inclusive: i64, stop_low_exclusive: i64) -> impl Iterator<Item = i64> {
     let mut start: i64 = start_high_inclusive;
@@ -146,6 +190,22 @@ fn rev_range(start_high_inclusive: i64, stop_low_exclusive: i64) -> impl Iterato
 // ===== Library Functions ===== //
 
 
+/// Returns if the iterable `args` has no elements. Infinite iterables are never empty, even though they do not have a length.
+pub fn is_empty(args: ValuePtr) -> ValueResult {
+    match args.is_infinite() {
+        true => false,
+        false => args.len()? == 0,
+    }.to_value().ok()
+}
+
+/// Counts the elements of `args` by iterating through it, as opposed to `len()`.
+pub fn count_all(args: ValuePtr) -> ValueResult {
+    if args.is_infinite() {
+        return ValueErrorIterableIsInfinite(args).err()
+    }
+    (args.to_iter()?.count() as i64).to_value().ok()
+}
+
 pub fn sum(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
     let mut sum: i64 = 0;
     for v in args {
@@ -154,95 +214,128 @@ pub fn sum(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
     sum.to_value().ok()
 }
 
-pub fn min(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
-    non_empty(args.min())
+pub fn prod(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    let mut prod: i64 = 1;
+    for v in args {
+        prod *= v.check_int()?.as_int();
+    }
+    prod.to_value().ok()
 }
 
-pub fn min_by<VM: VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr) -> ValueResult {
-    let iter = args.to_iter()?;
-    match by.min_nargs() {
-        Some(2) => {
-            let by: InvokeArg2 = InvokeArg2::from(by)?;
-            let mut err = None;
-            let ret = iter.min_by(|a, b|
-                util::catch(&mut err, ||
-                    Ok(by.invoke((*a).clone(), (*b).clone(), vm)?.check_int()?.as_int().cmp(&0)), Ordering::Equal));
-            util::join(non_empty(ret)?, err)
-        },
-        Some(1) => {
-            let by: InvokeArg1 = InvokeArg1::from(by)?;
-            let mut err = None;
-            let ret = iter.min_by_key(|u|
-                util::catch(&mut err, ||
-                    by.invoke((*u).clone(), vm).as_result(), ValuePtr::nil()));
-            util::join(non_empty(ret)?, err)
-        },
-        Some(_) => TypeErrorArgMustBeCmpOrKeyFunction(by).err(),
-        None => TypeErrorArgMustBeFunction(by).err(),
+/// Returns a list of the running sums of `args`, so the last element is equal to `sum(args)`.
+pub fn cumsum(args: ValuePtr) -> ValueResult {
+    let mut acc: VecDeque<ValuePtr> = VecDeque::with_capacity(args.len().unwrap_or(0));
+    let mut sum: i64 = 0;
+    for v in args.to_iter()? {
+        sum += v.check_int()?.as_int();
+        acc.push_back(sum.to_value());
     }
+    acc.to_value().ok()
+}
+
+/// Returns both the minimum and maximum of `args`, in a single pass.
+pub fn minmax<VM: VirtualInterface>(vm: &mut VM, mut args: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    let first: ValuePtr = non_empty(args.next())?;
+    let (mut min, mut max) = (first.clone(), first);
+    for v in args {
+        if operator::compare(vm, &min, &v)? == Ordering::Greater {
+            min = v.clone();
+        }
+        if operator::compare(vm, &max, &v)? != Ordering::Greater {
+            max = v;
+        }
+    }
+    (min, max).to_value().ok()
+}
+
+pub fn min<VM: VirtualInterface>(vm: &mut VM, args: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    non_empty(extremum_by(args, false, |a, b| operator::compare(vm, a, b))?)
+}
+
+pub fn min_by<VM: VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr) -> ValueResult {
+    extremum_by_key_or_cmp(vm, by, args, false)
 }
 
-pub fn max(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
-    non_empty(args.max())
+pub fn max<VM: VirtualInterface>(vm: &mut VM, args: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    non_empty(extremum_by(args, true, |a, b| operator::compare(vm, a, b))?)
 }
 
 pub fn max_by<VM: VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr) -> ValueResult {
+    extremum_by_key_or_cmp(vm, by, args, true)
+}
+
+/// Implements `min_by()` and `max_by()`, where `by` is either a comparator function of two arguments, or a key function of one argument.
+fn extremum_by_key_or_cmp<VM: VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr, max: bool) -> ValueResult {
     let iter = args.to_iter()?;
     match by.min_nargs() {
         Some(2) => {
             let by: InvokeArg2 = InvokeArg2::from(by)?;
-            let mut err = None;
-            let ret = iter.max_by(|a, b|
-                util::catch(&mut err, ||
-                    Ok(by.invoke((*a).clone(), (*b).clone(), vm)?.check_int()?.as_int().cmp(&0)), Ordering::Equal));
-            util::join(non_empty(ret)?, err)
+            non_empty(extremum_by(iter, max, |a, b| by.compare(a, b, vm))?)
         },
         Some(1) => {
             let by: InvokeArg1 = InvokeArg1::from(by)?;
-            let mut err = None;
-            let ret = iter.max_by_key(|u|
-                util::catch(&mut err, ||
-                    by.invoke((*u).clone(), vm).as_result(), ValuePtr::nil()));
-            util::join(non_empty(ret)?, err)
+            let keyed: Vec<(ValuePtr, ValuePtr)> = with_keys(vm, &by, iter)?;
+            non_empty(extremum_by(keyed.into_iter(), max, |(a, _), (b, _)| Ok(a.cmp(b)))?.map(|(_, v)| v))
         },
         Some(_) => TypeErrorArgMustBeCmpOrKeyFunction(by).err(),
         None => TypeErrorArgMustBeFunction(by).err(),
     }
 }
 
+/// Returns the minimum element of `iter`, or the maximum if `max` is `true`, according to `cmp`. Like `Iterator::min_by()` and `Iterator::max_by()`,
+/// this returns the first of multiple equal minimums, and the last of multiple equal maximums.
+///
+/// If `cmp` raises an error, it is returned immediately, without making any further comparisons.
+fn extremum_by<T>(mut iter: impl Iterator<Item=T>, max: bool, mut cmp: impl FnMut(&T, &T) -> ErrorResult<Ordering>) -> ErrorResult<Option<T>> {
+    let mut acc: T = match iter.next() {
+        Some(it) => it,
+        None => return Ok(None),
+    };
+    for v in iter {
+        let ord: Ordering = cmp(&acc, &v)?;
+        let replace: bool = match max {
+            false => ord == Ordering::Greater,
+            true => ord != Ordering::Greater, // Equal elements replace the maximum, so the last is kept
+        };
+        if replace {
+            acc = v;
+        }
+    }
+    Ok(Some(acc))
+}
 
-pub fn sort(args: impl Iterator<Item=ValuePtr>) -> ValuePtr {
-    let mut sorted: Vec<ValuePtr> = args.collect::<Vec<ValuePtr>>();
-    sorted.sort_unstable();
-    sorted.into_iter().to_list()
+/// Pairs each element of `iter` with its key, computed by invoking `by`. This invokes `by` exactly once per element, and returns any error it raises immediately.
+fn with_keys<VM: VirtualInterface>(vm: &mut VM, by: &InvokeArg1, iter: impl Iterator<Item=ValuePtr>) -> ErrorResult<Vec<(ValuePtr, ValuePtr)>> {
+    let mut keyed: Vec<(ValuePtr, ValuePtr)> = Vec::new();
+    by.for_each(iter, vm, |value, key| {
+        keyed.push((key, value));
+        ControlFlow::<()>::Continue(())
+    })?;
+    Ok(keyed)
+}
+
+
+pub fn sort<VM: VirtualInterface>(vm: &mut VM, args: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    let sorted: Vec<ValuePtr> = util::try_sort_by(args.collect::<Vec<ValuePtr>>(), &mut |a, b| operator::compare(vm, a, b))?;
+    sorted.into_iter().to_list().ok()
 }
 
 pub fn sort_by<VM : VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr) -> ValueResult {
-    let mut sorted: Vec<ValuePtr> = args.to_iter()?.collect::<Vec<ValuePtr>>();
-    match by.min_nargs() {
+    let iter = args.to_iter()?;
+    let sorted: Vec<ValuePtr> = match by.min_nargs() {
         Some(2) => {
             let by: InvokeArg2 = InvokeArg2::from(by)?;
-            let mut err = None;
-            sorted.sort_unstable_by(|a, b|
-                util::catch(&mut err, ||
-                    Ok(by.invoke(a.clone(), b.clone(), vm)?.check_int()?.as_int().cmp(&0)), Ordering::Equal));
-            if let Some(err) = err {
-                return err.value.err();
-            }
+            util::try_sort_by(iter.collect::<Vec<ValuePtr>>(), &mut |a, b| by.compare(a, b, vm))?
         },
         Some(1) => {
             let by: InvokeArg1 = InvokeArg1::from(by)?;
-            let mut err = None;
-            sorted.sort_unstable_by_key(|a|
-                util::catch(&mut err, ||
-                    by.invoke(a.clone(), vm).as_result(), ValuePtr::nil()));
-            if let Some(err) = err {
-                return err.value.err();
-            }
+            let mut keyed: Vec<(ValuePtr, ValuePtr)> = with_keys(vm, &by, iter)?;
+            keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+            keyed.into_iter().map(|(_, v)| v).collect()
         },
         Some(_) => return TypeErrorArgMustBeCmpOrKeyFunction(by).err(),
         None => return TypeErrorArgMustBeFunction(by).err(),
-    }
+    };
     sorted.into_iter().to_list().ok()
 }
 
@@ -265,13 +358,14 @@ pub fn group_by<VM : VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr
                 return ValueErrorValueMustBePositive(i).err()
             }
             let size: usize = i as usize;
+            let capacity: usize = size.min(iter.len()); // Groups are never larger than the iterable, so `size` may be arbitrarily large
             let mut groups: VecDeque<ValuePtr> = VecDeque::with_capacity(1 + iter.len() / size); // Accurate guess
-            let mut group: Vec<ValuePtr> = Vec::with_capacity(size);
+            let mut group: Vec<ValuePtr> = Vec::with_capacity(capacity);
             for value in iter {
                 group.push(value);
                 if group.len() == size {
                     groups.push_back(group.to_value());
-                    group = Vec::with_capacity(size);
+                    group = Vec::with_capacity(capacity);
                 }
             }
             if !group.is_empty() {
@@ -299,6 +393,40 @@ pub fn group_by<VM : VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr
     }
 }
 
+/// Creates a dictionary of `by(value) -> value`. If `strict`, then duplicate keys raise an error, otherwise the last value with each key is kept.
+pub fn index_by<VM : VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr, strict: bool) -> ValueResult {
+    let iter = args.to_iter()?;
+    let mut dict: IndexMap<ValuePtr, ValuePtr, FxBuildHasher> = IndexMap::with_capacity_and_hasher(iter.len(), FxBuildHasher::default());
+    let by: InvokeArg1 = InvokeArg1::from(by)?;
+    for value in iter {
+        let key = by.invoke(value.clone(), vm)?;
+        insert_unique(&mut dict, key, value, strict)?;
+    }
+    dict.to_value().ok()
+}
+
+/// Swaps the keys and values of a dictionary. If `strict`, then duplicate values raise an error, otherwise the last key with each value is kept.
+pub fn invert(dict: ValuePtr, strict: bool) -> ValueResult {
+    let dict = dict.check_dict()?;
+    let it = dict.as_dict().borrow();
+    let mut inverse: IndexMap<ValuePtr, ValuePtr, FxBuildHasher> = IndexMap::with_capacity_and_hasher(it.dict.len(), FxBuildHasher::default());
+    for (key, value) in it.dict.iter() {
+        insert_unique(&mut inverse, value.clone(), key.clone(), strict)?;
+    }
+    inverse.to_value().ok()
+}
+
+fn insert_unique(dict: &mut IndexMap<ValuePtr, ValuePtr, FxBuildHasher>, key: ValuePtr, value: ValuePtr, strict: bool) -> AnyResult {
+    if key.is_recursive() {
+        return ValueErrorRecursiveHash(key).err()
+    }
+    if strict && dict.contains_key(&key) {
+        return ValueErrorDuplicateKey(key).err()
+    }
+    dict.insert(key, value);
+    Ok(())
+}
+
 pub fn reverse(args: impl Iterator<Item=ValuePtr>) -> ValuePtr {
     let mut vec = args.collect::<Vec<ValuePtr>>();
     vec.reverse();
@@ -310,7 +438,11 @@ pub fn permutations(n: ValuePtr, args: ValuePtr) -> ValueResult {
     if n <= 0 {
         return ValueErrorValueMustBeNonNegative(n).err();
     }
-    args.to_iter()?
+    let values: Vec<ValuePtr> = args.to_iter()?.collect();
+    if n as usize > values.len() {
+        return VecDeque::new().to_value().ok() // Avoids allocating `n` indices, when there are no permutations
+    }
+    values.into_iter()
         .permutations(n as usize)
         .map(|u| u.to_value())
         .to_list()
@@ -322,7 +454,11 @@ pub fn combinations(n: ValuePtr, args: ValuePtr) -> ValueResult {
     if n <= 0 {
         return ValueErrorValueMustBeNonNegative(n).err();
     }
-    args.to_iter()?
+    let values: Vec<ValuePtr> = args.to_iter()?.collect();
+    if n as usize > values.len() {
+        return VecDeque::new().to_value().ok() // Avoids allocating `n` indices, when there are no combinations
+    }
+    values.into_iter()
         .combinations(n as usize)
         .map(|u| u.to_value())
         .to_list()
@@ -342,12 +478,11 @@ pub fn all<VM: VirtualInterface>(vm: &mut VM, f: ValuePtr, args: ValuePtr) -> Va
 /// With `is_any = true`, this behaves like `any()`, with it `false`, it behaves like `all()`
 fn predicate<VM : VirtualInterface>(vm: &mut VM, f: ValuePtr, args: ValuePtr, is_any: bool) -> ValueResult {
     let f: InvokeArg1 = InvokeArg1::from(f)?;
-    for r in args.to_iter()? {
-        if f.invoke(r, vm)?.to_bool() == is_any {
-            return is_any.to_value().ok()
-        }
-    }
-    (!is_any).to_value().ok()
+    let found: Option<()> = f.for_each(args.to_iter()?, vm, |_, ret| match ret.to_bool() == is_any {
+        true => ControlFlow::Break(()),
+        false => ControlFlow::Continue(()),
+    })?;
+    (found.is_some() == is_any).to_value().ok()
 }
 
 
@@ -374,6 +509,88 @@ pub fn filter<VM: VirtualInterface>(vm: &mut VM, f: ValuePtr, args: ValuePtr) ->
     acc.to_value().ok()
 }
 
+/// Splits `args` into a pair of the elements which match `f`, and those which do not, in a single pass.
+pub fn partition<VM: VirtualInterface>(vm: &mut VM, f: ValuePtr, args: ValuePtr) -> ValueResult {
+    let mut matches: VecDeque<ValuePtr> = VecDeque::new();
+    let mut rest: VecDeque<ValuePtr> = VecDeque::new();
+    let f: InvokeArg1 = InvokeArg1::from(f)?;
+    for r in args.to_iter()? {
+        let ret = f.invoke(r.clone(), vm)?;
+        if ret.to_bool() {
+            matches.push_back(r);
+        } else {
+            rest.push_back(r);
+        }
+    }
+    (matches.to_value(), rest.to_value()).to_value().ok()
+}
+
+/// Splits `args` into a pair of the longest prefix of elements which match `f`, and all remaining elements.
+pub fn span<VM: VirtualInterface>(vm: &mut VM, f: ValuePtr, args: ValuePtr) -> ValueResult {
+    let mut iter = args.to_iter()?;
+    let (prefix, first) = split_while(vm, f, &mut iter)?;
+    let rest: VecDeque<ValuePtr> = first.into_iter().chain(iter).collect();
+    (prefix.to_value(), rest.to_value()).to_value().ok()
+}
+
+/// Returns the longest prefix of `args` whose elements match `f`. Once an element does not match, `f` is not invoked on any further elements.
+pub fn take_while<VM: VirtualInterface>(vm: &mut VM, f: ValuePtr, args: ValuePtr) -> ValueResult {
+    let (prefix, _) = split_while(vm, f, &mut args.to_iter()?)?;
+    prefix.to_value().ok()
+}
+
+/// Returns all elements of `args`, starting with the first which does not match `f`. Once an element does not match, `f` is not invoked on any further elements.
+pub fn drop_while<VM: VirtualInterface>(vm: &mut VM, f: ValuePtr, args: ValuePtr) -> ValueResult {
+    let mut iter = args.to_iter()?;
+    let (_, first) = split_while(vm, f, &mut iter)?;
+    first.into_iter().chain(iter).to_list().ok()
+}
+
+/// Takes elements from `iter` while they match `f`, and returns them, along with the first element which did not match, if any. Any remaining elements are left in `iter`.
+fn split_while<VM: VirtualInterface>(vm: &mut VM, f: ValuePtr, iter: &mut impl Iterator<Item=ValuePtr>) -> ErrorResult<(VecDeque<ValuePtr>, Option<ValuePtr>)> {
+    let f: InvokeArg1 = InvokeArg1::from(f)?;
+    let mut prefix: VecDeque<ValuePtr> = VecDeque::new();
+    let first: Option<ValuePtr> = f.for_each(iter, vm, |r, ret| match ret.to_bool() {
+        true => {
+            prefix.push_back(r);
+            ControlFlow::Continue(())
+        },
+        false => ControlFlow::Break(r),
+    })?;
+    Ok((prefix, first))
+}
+
+/// Counts the elements of `args` which either match the predicate `finder`, or are equal to `finder`, without collecting them.
+pub fn count<VM: VirtualInterface>(vm: &mut VM, finder: ValuePtr, args: ValuePtr) -> ValueResult {
+    let iter = args.to_iter()?;
+    let mut count: i64 = 0;
+    if finder.is_evaluable() {
+        let finder: InvokeArg1 = InvokeArg1::from(finder)?;
+        for v in iter {
+            if finder.invoke(v, vm)?.to_bool() {
+                count += 1;
+            }
+        }
+    } else {
+        count = iter.filter(|v| v == &finder).count() as i64;
+    }
+    count.to_value().ok()
+}
+
+/// Counts the elements of `args` which match, and do not match, the predicate `f`, as a pair.
+pub fn tally<VM: VirtualInterface>(vm: &mut VM, f: ValuePtr, args: ValuePtr) -> ValueResult {
+    let (mut matches, mut rest): (i64, i64) = (0, 0);
+    let f: InvokeArg1 = InvokeArg1::from(f)?;
+    for r in args.to_iter()? {
+        if f.invoke(r, vm)?.to_bool() {
+            matches += 1;
+        } else {
+            rest += 1;
+        }
+    }
+    (matches.to_value(), rest.to_value()).to_value().ok()
+}
+
 pub fn flat_map<VM>(vm: &mut VM, f: Option<ValuePtr>, args: ValuePtr) -> ValueResult where VM : VirtualInterface {
     let len: usize = args.len().unwrap_or(0);
     let mut acc: VecDeque<ValuePtr> = VecDeque::with_capacity(len);
@@ -393,6 +610,180 @@ pub fn flat_map<VM>(vm: &mut VM, f: Option<ValuePtr>, args: ValuePtr) -> ValueRe
     acc.to_value().ok()
 }
 
+/// Flattens nested `list`s and `vector`s by up to `depth` levels, or fully if `depth` is `nil`. All other values, including strings, are treated as scalars.
+pub fn flatten(args: ValuePtr, depth: ValuePtr) -> ValueResult {
+    let depth: Option<usize> = match depth.is_nil() {
+        true => None,
+        false => {
+            let depth: i64 = depth.check_int()?.as_int();
+            if depth < 0 {
+                return ValueErrorValueMustBeNonNegative(depth).err()
+            }
+            Some(depth as usize)
+        }
+    };
+    if depth.is_none() && args.is_recursive() {
+        return ValueErrorCannotFlattenRecursive(args).err()
+    }
+
+    let mut acc: VecDeque<ValuePtr> = VecDeque::with_capacity(args.len().unwrap_or(0));
+    for value in args.to_iter()? {
+        flatten_into(value, depth, &mut acc);
+    }
+    acc.to_value().ok()
+}
+
+fn flatten_into(value: ValuePtr, depth: Option<usize>, acc: &mut VecDeque<ValuePtr>) {
+    if depth != Some(0) && (value.is_list() || value.is_vector()) {
+        for inner in value.as_iter_or_unit() {
+            flatten_into(inner, depth.map(|d| d - 1), acc);
+        }
+    } else {
+        acc.push_back(value);
+    }
+}
+
+/// Removes duplicate elements, keeping the first occurrence of each, in order.
+pub fn unique(args: ValuePtr) -> ValueResult {
+    let mut seen: IndexSet<ValuePtr, FxBuildHasher> = IndexSet::with_capacity_and_hasher(args.len().unwrap_or(0), FxBuildHasher::default());
+    for value in args.to_iter()? {
+        if value.is_recursive() {
+            return ValueErrorRecursiveHash(value).err()
+        }
+        seen.insert(value);
+    }
+    seen.into_iter().to_list().ok()
+}
+
+/// Removes elements which have a duplicate key, as computed by `by`, keeping the first element with each key, in order.
+pub fn unique_by<VM : VirtualInterface>(vm: &mut VM, by: ValuePtr, args: ValuePtr) -> ValueResult {
+    let by: InvokeArg1 = InvokeArg1::from(by)?;
+    let mut seen: IndexSet<ValuePtr, FxBuildHasher> = IndexSet::with_hasher(FxBuildHasher::default());
+    let mut acc: VecDeque<ValuePtr> = VecDeque::new();
+    for value in args.to_iter()? {
+        let key = by.invoke(value.clone(), vm)?;
+        if key.is_recursive() {
+            return ValueErrorRecursiveHash(key).err()
+        }
+        if seen.insert(key) {
+            acc.push_back(value);
+        }
+    }
+    acc.to_value().ok()
+}
+
+/// Removes consecutive duplicate elements. Unlike `unique()`, this does not require elements to be hashable.
+pub fn dedup(args: ValuePtr) -> ValueResult {
+    args.to_iter()?.dedup().to_list().ok()
+}
+
+pub fn cycle(it: ValuePtr) -> ValueResult {
+    LazyIterImpl::Cycle(it.check_iter()?).to_value().ok()
+}
+
+pub fn repeat(value: ValuePtr, n: Option<ValuePtr>) -> ValueResult {
+    let n: Option<usize> = match n {
+        Some(n) => {
+            let n: i64 = n.check_int()?.as_int();
+            if n < 0 {
+                return ValueErrorValueMustBeNonNegative(n).err()
+            }
+            Some(n as usize)
+        },
+        None => None,
+    };
+    LazyIterImpl::Repeat(value, n).to_value().ok()
+}
+
+pub fn chain(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    LazyIterImpl::Chain(args
+        .map(|u| u.check_iter().as_result())
+        .collect::<ErrorResult<Vec<ValuePtr>>>()?
+    ).to_value().ok()
+}
+
+/// Takes at most the first `n` elements of `args` into a list, which stops iterating once `n` elements are reached, so it can be used on infinite iterables.
+pub fn take(n: ValuePtr, args: ValuePtr) -> ValueResult {
+    let n: i64 = n.check_int()?.as_int();
+    if n < 0 {
+        return ValueErrorValueMustBeNonNegative(n).err()
+    }
+    args.to_iter()?.take(n as usize).to_list().ok()
+}
+
+/// Transposes an iterable of rows into a vector of columns, each of which is a list. Matrices are instead transposed into a matrix.
+/// All rows must have the same length.
+pub fn transpose(rows: ValuePtr) -> ValueResult {
+    if rows.is_matrix() {
+        return matrix::transpose(rows);
+    }
+    let mut columns: Vec<VecDeque<ValuePtr>> = Vec::new();
+    for (n, row) in rows.to_iter()?.enumerate() {
+        let row: Vec<ValuePtr> = row.to_iter()?.collect();
+        if n == 0 {
+            columns.resize_with(row.len(), VecDeque::new);
+        } else if row.len() != columns.len() {
+            return ValueErrorRowLengthMismatch(columns.len(), row.len()).err()
+        }
+        for (column, value) in columns.iter_mut().zip(row) {
+            column.push_back(value);
+        }
+    }
+    columns.into_iter().map(|u| u.to_value()).collect::<Vec<ValuePtr>>().to_value().ok()
+}
+
+pub fn first(args: ValuePtr) -> ValueResult {
+    let first: Option<ValuePtr> = match args.ty() {
+        // Sorted collections can find their first element without unboxing every element to iterate
+        Type::SortedSet => args.as_sorted_set().borrow().first(),
+        Type::SortedDict => args.as_sorted_dict().borrow().first().map(|u| u.to_value()),
+        _ => args.to_iter()?.next(),
+    };
+    match first {
+        Some(v) => v.ok(),
+        None => ValueErrorValueMustBeNonEmpty.err(),
+    }
+}
+
+pub fn last(args: ValuePtr) -> ValueResult {
+    // Reverse iteration finds the last element without iterating through everything, but that only makes sense for finite iterables
+    if args.is_infinite() {
+        return ValueErrorIterableIsInfinite(args).err()
+    }
+    let last: Option<ValuePtr> = match args.ty() {
+        Type::SortedSet => args.as_sorted_set().borrow().last(),
+        Type::SortedDict => args.as_sorted_dict().borrow().last().map(|u| u.to_value()),
+        _ => args.to_iter()?.reverse().next(),
+    };
+    match last {
+        Some(v) => v.ok(),
+        None => ValueErrorValueMustBeNonEmpty.err(),
+    }
+}
+
+pub fn nth(n: ValuePtr, args: ValuePtr) -> ValueResult {
+    let n: i64 = n.check_int()?.as_int();
+    if n < 0 {
+        return ValueErrorValueMustBeNonNegative(n).err()
+    }
+    let mut len: usize = 0;
+    for value in args.to_iter()? {
+        if len == n as usize {
+            return value.ok()
+        }
+        len += 1;
+    }
+    ValueErrorIndexOutOfBounds(n, len).err()
+}
+
+pub fn single(args: ValuePtr) -> ValueResult {
+    let mut iter = args.clone().to_iter()?;
+    match (iter.next(), iter.next()) {
+        (Some(v), None) => v.ok(),
+        _ => ValueErrorValueMustBeSingle(args).err(),
+    }
+}
+
 pub fn zip(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
     let mut iters = args
         .map(|v| v.to_iter())
@@ -446,11 +837,14 @@ pub fn peek(target: ValuePtr) -> ValueResult {
 }
 
 pub fn pop(target: ValuePtr) -> ValueResult {
+    target.check_mutable()?;
     match match target.ty() {
         Type::List => target.as_list().borrow_mut().list.pop_back(),
         Type::Set => target.as_set().borrow_mut().set.pop(),
         Type::Dict => target.as_dict().borrow_mut().dict.pop().map(|u| u.to_value()),
         Type::Heap => target.as_heap().borrow_mut().heap.pop().map(|t| t.0),
+        Type::SortedSet => target.as_sorted_set().borrow_mut().pop_last(),
+        Type::SortedDict => target.as_sorted_dict().borrow_mut().pop_last().map(|u| u.to_value()),
         _ => return TypeErrorArgMustBeIterable(target).err()
     } {
         Some(v) => v.ok(),
@@ -460,6 +854,7 @@ pub fn pop(target: ValuePtr) -> ValueResult {
 
 pub fn pop_front(target: ValuePtr) -> ValueResult {
     let target = target.check_list()?;
+    target.check_mutable()?;
     let ret = match target.as_list().borrow_mut().list.pop_front() {
         Some(v) => v.ok(),
         None => ValueErrorValueMustBeNonEmpty.err()
@@ -468,14 +863,24 @@ pub fn pop_front(target: ValuePtr) -> ValueResult {
 }
 
 pub fn push(value: ValuePtr, target: ValuePtr) -> ValueResult {
+    if target.is_sorted_set() {
+        value.freeze();
+        target.check_mutable()?; // Checked after freezing the value, as if `target` is reachable from `value`, it cannot be compared while it is being mutated
+        target.as_sorted_set().borrow_mut().insert(value);
+        return target.ok()
+    }
+    target.check_mutable()?;
     match target.ty() {
         Type::List => {
             target.as_list().borrow_mut().list.push_back(value);
             target.ok()
         }
-        Type::Set => match vm::guard_recursive_hash(|| target.as_set().borrow_mut().set.insert(value)) {
-            Err(_) => ValueErrorRecursiveHash(target).err(),
-            Ok(_) => target.ok()
+        Type::Set => {
+            value.freeze();
+            match vm::guard_recursive_hash(|| target.as_set().borrow_mut().set.insert(value)) {
+                Err(_) => ValueErrorRecursiveHash(target).err(),
+                Ok(_) => target.ok()
+            }
         }
         Type::Heap => {
             target.as_heap().borrow_mut().heap.push(Reverse(value));
@@ -487,6 +892,7 @@ pub fn push(value: ValuePtr, target: ValuePtr) -> ValueResult {
 
 pub fn push_front(value: ValuePtr, target: ValuePtr) -> ValueResult {
     let target = target.check_list()?;
+    target.check_mutable()?;
     target.as_list()
         .borrow_mut()
         .list
@@ -494,7 +900,27 @@ pub fn push_front(value: ValuePtr, target: ValuePtr) -> ValueResult {
     target.ok()
 }
 
+/// Pushes each value of `values` onto the front of a list, so they appear in the same order as in `values`. This takes `O(n)` time in the number of values pushed.
+pub fn extend_front(values: ValuePtr, target: ValuePtr) -> ValueResult {
+    let target = target.check_list()?;
+    target.check_mutable()?;
+    let values: Vec<ValuePtr> = values.to_iter()?.collect(); // Collect first, in case `values` is `target`
+    {
+        let mut it = target.as_list().borrow_mut();
+        it.list.reserve(values.len());
+        for value in values.into_iter().rev() {
+            it.list.push_front(value);
+        }
+    }
+    target.ok()
+}
+
 pub fn insert(index: ValuePtr, value: ValuePtr, target: ValuePtr) -> ValueResult {
+    if target.is_sorted_dict() {
+        sorted::set_index(&target, index, value)?;
+        return target.ok()
+    }
+    target.check_mutable()?;
     match target.ty() {
         Type::List => {
             {
@@ -511,15 +937,19 @@ pub fn insert(index: ValuePtr, value: ValuePtr, target: ValuePtr) -> ValueResult
             }
             target.ok()
         },
-        Type::Dict => match vm::guard_recursive_hash(|| target.as_dict().borrow_mut().dict.insert(index, value)) {
-            Err(_) => ValueErrorRecursiveHash(target).err(),
-            Ok(_) => target.ok()
+        Type::Dict => {
+            index.freeze();
+            match vm::guard_recursive_hash(|| target.as_dict().borrow_mut().dict.insert(index, value)) {
+                Err(_) => ValueErrorRecursiveHash(target).err(),
+                Ok(_) => target.ok()
+            }
         },
         _ => TypeErrorArgMustBeIndexable(target).err()
     }
 }
 
 pub fn remove(needle: ValuePtr, target: ValuePtr) -> ValueResult {
+    target.check_mutable()?;
     match target.ty() {
         Type::List => {
             let mut it = target.as_list().borrow_mut();
@@ -535,11 +965,46 @@ pub fn remove(needle: ValuePtr, target: ValuePtr) -> ValueResult {
         },
         Type::Set => target.as_set().borrow_mut().set.remove(&needle).to_value().ok(),
         Type::Dict => target.as_dict().borrow_mut().dict.remove(&needle).is_some().to_value().ok(),
+        Type::SortedSet => target.as_sorted_set().borrow_mut().remove(&needle).to_value().ok(),
+        Type::SortedDict => target.as_sorted_dict().borrow_mut().remove(&needle).to_value().ok(),
         _ => TypeErrorArgMustBeIterable(target).err(),
     }
 }
 
+/// Removes the value at `index` from a list in `O(1)` time, by replacing it with the last value of the list. This does not preserve the order of the list.
+pub fn swap_remove(index: ValuePtr, target: ValuePtr) -> ValueResult {
+    let target = target.check_list()?;
+    target.check_mutable()?;
+    let mut it = target.as_list().borrow_mut();
+    let index = index.check_int()?.as_int();
+    let len = it.list.len();
+    if 0 <= index && index < len as i64 {
+        it.list.swap_remove_back(index as usize)
+            .unwrap() // .unwrap() is safe, as we pre-checked the index
+            .ok()
+    } else {
+        ValueErrorIndexOutOfBounds(index, len).err()
+    }
+}
+
+/// Rotates a list `n` places to the right, so the last value moves to the front. A negative `n` rotates to the left instead.
+/// This takes `O(min(n, len - n))` time, where `n` is taken modulo the length of the list.
+pub fn rotate(n: ValuePtr, target: ValuePtr) -> ValueResult {
+    let target = target.check_list()?;
+    target.check_mutable()?;
+    let n = n.check_int()?.as_int();
+    {
+        let mut it = target.as_list().borrow_mut();
+        let len = it.list.len();
+        if len > 0 {
+            it.list.rotate_right(n.rem_euclid(len as i64) as usize);
+        }
+    }
+    target.ok()
+}
+
 pub fn clear(target: ValuePtr) -> ValueResult {
+    target.check_mutable()?;
     match target.ty() {
         Type::List => {
             target.as_list().borrow_mut().list.clear();
@@ -557,10 +1022,64 @@ pub fn clear(target: ValuePtr) -> ValueResult {
             target.as_heap().borrow_mut().heap.clear();
             target.ok()
         },
+        Type::SortedSet => {
+            target.as_sorted_set().borrow_mut().clear();
+            target.ok()
+        },
+        Type::SortedDict => {
+            target.as_sorted_dict().borrow_mut().clear();
+            target.ok()
+        },
         _ => TypeErrorArgMustBeIterable(target).err(),
     }
 }
 
+/// Creates a view of `args[start:stop]`. If `args` is already a view, this shares its backing storage, otherwise its elements are copied once.
+pub fn view(args: ValuePtr, start: ValuePtr, stop: ValuePtr) -> ValueResult {
+    let args: ValuePtr = match args.is_view() {
+        true => args,
+        false => ViewImpl::new(args.to_iter()?.collect()).to_value(),
+    };
+    get_slice(&args, start, stop, ValuePtr::nil())
+}
+
+pub fn list_with_capacity(n: ValuePtr) -> ValueResult {
+    let mut list: VecDeque<ValuePtr> = VecDeque::new();
+    try_reserve(n, |n| list.try_reserve(n).is_ok())?;
+    list.to_value().ok()
+}
+
+pub fn dict_with_capacity(n: ValuePtr) -> ValueResult {
+    let mut dict: IndexMap<ValuePtr, ValuePtr, FxBuildHasher> = IndexMap::with_hasher(FxBuildHasher::default());
+    try_reserve(n, |n| dict.try_reserve(n).is_ok())?;
+    dict.to_value().ok()
+}
+
+/// Reserves capacity for at least `n` more elements in `target`, without changing its contents.
+pub fn reserve(target: ValuePtr, n: ValuePtr) -> ValueResult {
+    match target.ty() {
+        Type::List => try_reserve(n, |n| target.as_list().borrow_mut().list.try_reserve(n).is_ok())?,
+        Type::Vector => try_reserve(n, |n| target.as_vector().borrow_mut().vector.try_reserve(n).is_ok())?,
+        Type::Set => try_reserve(n, |n| target.as_set().borrow_mut().set.try_reserve(n).is_ok())?,
+        Type::Dict => try_reserve(n, |n| target.as_dict().borrow_mut().dict.try_reserve(n).is_ok())?,
+        Type::Heap => try_reserve(n, |n| target.as_heap().borrow_mut().heap.try_reserve(n).is_ok())?,
+        _ => return TypeErrorArgMustBeIterable(target).err(),
+    }
+    target.ok()
+}
+
+/// Checks that `n` is a non-negative int, and calls `reserve` with it. Any failure to allocate, including requesting more than the maximum capacity, raises an error rather than aborting.
+fn try_reserve(n: ValuePtr, reserve: impl FnOnce(usize) -> bool) -> AnyResult {
+    let n: i64 = n.check_int()?.as_int();
+    if n < 0 {
+        return ValueErrorValueMustBeNonNegative(n).err()
+    }
+    match usize::try_from(n).is_ok_and(reserve) {
+        true => Ok(()),
+        false => ValueErrorCannotReserveCapacity(n).err(),
+    }
+}
+
 
 pub fn collect_into_dict(iter: impl Iterator<Item=ValuePtr>) -> ValueResult {
     iter.map(|t| t.to_pair())
@@ -600,24 +1119,43 @@ pub fn dict_values(target: ValuePtr) -> ValueResult {
         .ok()
 }
 
-pub fn left_find<VM: VirtualInterface>(vm: &mut VM, finder: ValuePtr, args: ValuePtr, return_index: bool) -> ValueResult {
+/// `index_of`, which is a `left_find()` with an optional `start` index. When searching a `str` for a `str`, this searches for a substring, rather than a single character.
+pub fn index_of<VM: VirtualInterface>(vm: &mut VM, finder: ValuePtr, args: ValuePtr, start: Option<ValuePtr>) -> ValueResult {
+    let start: usize = match start {
+        Some(start) => {
+            let start: i64 = start.check_int()?.as_int();
+            if start < 0 {
+                return ValueErrorValueMustBeNonNegative(start).err()
+            }
+            start as usize
+        },
+        None => 0,
+    };
+    if finder.is_str() && args.is_str() {
+        return strings::index_of(finder, args, start).ok()
+    }
+    left_find(vm, finder, args, start, true)
+}
+
+pub fn left_find<VM: VirtualInterface>(vm: &mut VM, finder: ValuePtr, args: ValuePtr, start: usize, return_index: bool) -> ValueResult {
     // Supports both find index (`index_of`), and find position (`find`)
     // For predicates, we use the same `enumerate()`, but then either return index, or value
     // For index with value, we use `.position()`
     // For value with value, we just use `.find()`
-    let mut iter = args.to_iter()?;
+    // Indices are offset by `start`, as we skip the first `start` elements
+    let mut iter = args.to_iter()?.skip(start);
     if finder.is_evaluable() {
         let finder: InvokeArg1 = InvokeArg1::from(finder)?;
         for (i, v) in iter.enumerate() {
             let ret = finder.invoke(v.clone(), vm)?;
             if ret.to_bool() {
-                return if return_index { (i as i64).to_value() } else { v }.ok()
+                return if return_index { ((start + i) as i64).to_value() } else { v }.ok()
             }
         }
         if return_index { (-1i64).to_value() } else { ValuePtr::nil() }.ok()
     } else if return_index {
         match iter.position(|v| v == finder) {
-            Some(i) => i as i64,
+            Some(i) => (start + i) as i64,
             None => -1
         }.to_value().ok()
     } else {
@@ -627,9 +1165,11 @@ pub fn left_find<VM: VirtualInterface>(vm: &mut VM, finder: ValuePtr, args: Valu
 
 pub fn right_find<VM: VirtualInterface>(vm: &mut VM, finder: ValuePtr, args: ValuePtr, return_index: bool) -> ValueResult {
     // Identical to the above except we use `.reverse()`, and subtract the index from `len`
+    if return_index && finder.is_str() && args.is_str() {
+        return strings::right_index_of(finder, args).ok()
+    }
     let mut iter = args.to_iter()?.reverse();
     let len = iter.len();
-    dbg!(len, &iter);
     if finder.is_evaluable() {
         let finder: InvokeArg1 = InvokeArg1::from(finder)?;
         for (i, v) in iter.enumerate() {
@@ -649,19 +1189,50 @@ pub fn right_find<VM: VirtualInterface>(vm: &mut VM, finder: ValuePtr, args: Val
     }
 }
 
-pub fn create_memoized(f: ValuePtr) -> ValueResult {
-    match f.is_evaluable() {
-        true => ValuePtr::memoized(f).ok(),
-        false => TypeErrorArgMustBeFunction(f).err()
+pub fn create_memoized(f: ValuePtr, max_size: Option<ValuePtr>) -> ValueResult {
+    if !f.is_evaluable() {
+        return TypeErrorArgMustBeFunction(f).err()
+    }
+    let max_size: Option<usize> = match max_size {
+        Some(it) => match it.check_int()?.as_int() {
+            i if i > 0 => Some(i as usize),
+            i => return ValueErrorValueMustBePositive(i).err(),
+        },
+        None => None,
+    };
+    ValuePtr::memoized(f, max_size).ok()
+}
+
+/// Returns a `dict` of statistics about a memoized function's cache: the number of cache hits and misses, the current size, and maximum size (or `nil` if unbounded).
+pub fn cache_stats(f: ValuePtr) -> ValueResult {
+    if !f.is_memoized() {
+        return TypeErrorArgMustBeMemoized(f).err()
+    }
+    let it = f.as_memoized().borrow();
+    let mut stats: IndexMap<ValuePtr, ValuePtr, FxBuildHasher> = IndexMap::with_hasher(FxBuildHasher::default());
+    stats.insert("hits".to_value(), it.hits.to_value());
+    stats.insert("misses".to_value(), it.misses.to_value());
+    stats.insert("size".to_value(), it.len().to_value());
+    stats.insert("max_size".to_value(), it.max_size.map_or(ValuePtr::nil(), |u| u.to_value()));
+    stats.to_value().ok()
+}
+
+pub fn cache_clear(f: ValuePtr) -> ValueResult {
+    if !f.is_memoized() {
+        return TypeErrorArgMustBeMemoized(f).err()
     }
+    f.as_memoized().borrow_mut().clear();
+    ValuePtr::nil().ok()
 }
 
 pub fn set_union(other: ValuePtr, this: ValuePtr) -> ValueResult {
     match this.ty() {
         Type::Set => {
             // this.union(other) := add everything from other to this
+            this.check_mutable()?;
             let mut set = this.as_set().borrow_mut();
             for e in other.to_iter()? {
+                e.freeze();
                 set.set.insert(e);
             }
             drop(set);
@@ -676,6 +1247,7 @@ pub fn set_intersect(other: ValuePtr, this: ValuePtr) -> ValueResult {
         Type::Set => {
             // this.intersect(other) := only keep elements of this that are also in other
             // Since we have just an iterator, we need to compute a set of `other`, then repeatedly check `contains()` for each element in `this`
+            this.check_mutable()?;
             let mut set = this.as_set().borrow_mut();
             let other = other.to_iter()?.collect::<IndexSet<ValuePtr, FxBuildHasher>>();
             set.set.retain(|e| other.contains(e));
@@ -690,6 +1262,7 @@ pub fn set_difference(other: ValuePtr, this: ValuePtr) -> ValueResult {
     match this.ty() {
         Type::Set => {
             // this.difference(other) := remove everything from this that is in other
+            this.check_mutable()?;
             let mut set = this.as_set().borrow_mut();
             for e in other.to_iter()? {
                 set.set.remove(&e);
@@ -0,0 +1,97 @@
+use crate::vm::{ErrorResult, IntoIterableValue, IntoValue, RuntimeError, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// Parses `text` as CSV, returning a list of rows, where each row is a vector of string fields.
+///
+/// Fields are separated by `delim`, which defaults to `,`, and rows by either `\n` or `\r\n`. A trailing newline does not produce an empty row.
+/// Fields may be quoted with `"`, in which case they may contain the delimiter, newlines, or a literal `"` written as `""`.
+pub fn decode(text: ValuePtr, delim: Option<ValuePtr>) -> ValueResult {
+    let text = text.check_str()?;
+    let delim: char = check_delim(delim)?;
+    let text: &str = text.as_str().borrow_const();
+
+    let mut rows: Vec<ValuePtr> = Vec::new();
+    let mut row: Vec<ValuePtr> = Vec::new();
+    let mut field: String = String::new();
+    let mut quoted: bool = false; // If the current field was quoted, in which case it is present even if empty
+    let mut chars = text.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('"') if field.is_empty() && !quoted => {
+                // Quoted field, which continues until a closing quote that is not part of an escaped `""`
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        },
+                        Some('"') => break,
+                        Some(c) => field.push(c),
+                        None => return ValueErrorUnterminatedQuoteInCsv.err(),
+                    }
+                }
+            },
+            Some(c) if c == delim => {
+                row.push(std::mem::take(&mut field).to_value());
+                quoted = false;
+            },
+            Some('\r') if chars.peek() == Some(&'\n') => {},
+            Some('\n') => {
+                row.push(std::mem::take(&mut field).to_value());
+                rows.push(std::mem::take(&mut row).to_value());
+                quoted = false;
+            },
+            Some(c) => field.push(c),
+            None => {
+                // Only emit a final row if there is any content after the last newline
+                if !field.is_empty() || quoted || !row.is_empty() {
+                    row.push(field.to_value());
+                    rows.push(row.to_value());
+                }
+                break
+            },
+        }
+    }
+    rows.into_iter().to_list().ok()
+}
+
+/// Converts `rows`, an iterable of iterables, into CSV text. Each field is converted with `str()`, and is quoted if it contains the delimiter, a `"`, or a newline.
+/// Every row, including the last, is terminated with a `\n`.
+pub fn encode(rows: ValuePtr, delim: Option<ValuePtr>) -> ValueResult {
+    let delim: char = check_delim(delim)?;
+    let mut output: String = String::new();
+    for row in rows.to_iter()? {
+        for (i, field) in row.to_iter()?.enumerate() {
+            if i > 0 {
+                output.push(delim);
+            }
+            let field: String = field.to_str();
+            if field.contains([delim, '"', '\n', '\r']) {
+                output.push('"');
+                output.push_str(field.replace('"', "\"\"").as_str());
+                output.push('"');
+            } else {
+                output.push_str(field.as_str());
+            }
+        }
+        output.push('\n');
+    }
+    output.to_value().ok()
+}
+
+fn check_delim(delim: Option<ValuePtr>) -> ErrorResult<char> {
+    match delim {
+        None => Ok(','),
+        Some(delim) => {
+            let delim = delim.check_str()?;
+            let mut chars = delim.as_str().borrow_const().chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c != '"' && c != '\n' && c != '\r' => Ok(c),
+                _ => ValueErrorInvalidCsvDelimiter(delim.clone()).err(),
+            }
+        }
+    }
+}
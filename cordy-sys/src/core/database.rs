@@ -0,0 +1,175 @@
+#[cfg(feature = "sqlite")]
+use rusqlite::{Connection, Rows, Statement, ToSql};
+#[cfg(feature = "sqlite")]
+use rusqlite::types::{Value, ValueRef};
+
+#[cfg(feature = "sqlite")]
+use crate::vm::{DatabaseImpl, ErrorResult, Handle, IntoDictValue, IntoIterableValue, IntoValue, MAX_INT, MIN_INT, R64, Type};
+use crate::vm::{RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// Opens the SQLite database at `path`, creating it if it does not exist. The path `:memory:` opens a new in-memory database.
+#[cfg(feature = "sqlite")]
+pub fn open<VM : VirtualInterface>(vm: &mut VM, path: ValuePtr) -> ValueResult {
+    let path = path.check_str()?;
+    let path: String = path.as_str().borrow_const().clone();
+    match Connection::open(&path) {
+        Ok(conn) => vm.register_handle(DatabaseImpl { path, conn: Handle::new(conn) }.to_value()).ok(),
+        Err(err) => ValueErrorDatabase(err.to_string()).err(),
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn open<VM : VirtualInterface>(_: &mut VM, _: ValuePtr) -> ValueResult {
+    ValueErrorFeatureNotEnabled("sqlite").err()
+}
+
+/// Executes a single SQL statement, which does not return rows, with the given parameters. Returns the number of rows that were changed.
+///
+/// `params` may be a `list` or `vector` of positional parameters, or a `dict` of named parameters.
+#[cfg(feature = "sqlite")]
+pub fn exec(db: ValuePtr, sql: ValuePtr, params: Option<ValuePtr>) -> ValueResult {
+    with_statement(db, sql, params, |stmt, params| {
+        let changed: usize = match params {
+            Params::Positional(it) => stmt.execute(rusqlite::params_from_iter(it)),
+            Params::Named(it) => stmt.execute(&*named(&it)),
+        }.map_err(|err| err.to_string())?;
+        Ok((changed as i64).to_value())
+    })
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn exec(_: ValuePtr, _: ValuePtr, _: Option<ValuePtr>) -> ValueResult {
+    ValueErrorFeatureNotEnabled("sqlite").err()
+}
+
+/// Executes a single SQL query with the given parameters, and returns the result as a `list` of `dict`s, each mapping column names to values.
+#[cfg(feature = "sqlite")]
+pub fn query(db: ValuePtr, sql: ValuePtr, params: Option<ValuePtr>) -> ValueResult {
+    with_statement(db, sql, params, |stmt, params| {
+        let columns: Vec<ValuePtr> = stmt.column_names().into_iter().map(|name| name.to_value()).collect();
+        let mut rows: Rows = match params {
+            Params::Positional(it) => stmt.query(rusqlite::params_from_iter(it)),
+            Params::Named(it) => stmt.query(&*named(&it)),
+        }.map_err(|err| err.to_string())?;
+        let mut results: Vec<ValuePtr> = Vec::new();
+        while let Some(row) = rows.next().map_err(|err| err.to_string())? {
+            let values: Vec<ValuePtr> = (0..columns.len())
+                .map(|i| from_sql(row.get_ref(i).map_err(|err| err.to_string())?))
+                .collect::<Result<Vec<ValuePtr>, String>>()?;
+            results.push(columns.iter().cloned().zip(values).to_dict());
+        }
+        Ok(results.into_iter().to_list())
+    })
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn query(_: ValuePtr, _: ValuePtr, _: Option<ValuePtr>) -> ValueResult {
+    ValueErrorFeatureNotEnabled("sqlite").err()
+}
+
+/// Closes `db`. Closing a database which is already closed does nothing.
+#[cfg(feature = "sqlite")]
+pub fn close(db: ValuePtr) -> ValueResult {
+    let conn: Option<Connection> = db.as_database().borrow_mut().conn.close();
+    if let Some(conn) = conn {
+        if let Err((_, err)) = conn.close() {
+            return ValueErrorDatabase(err.to_string()).err()
+        }
+    }
+    ValuePtr::nil().ok()
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn close(_: ValuePtr) -> ValueResult {
+    ValuePtr::nil().ok()
+}
+
+
+/// Either positional or named parameters, bound to a statement.
+#[cfg(feature = "sqlite")]
+enum Params {
+    Positional(Vec<Value>),
+    Named(Vec<(String, Value)>),
+}
+
+/// Prepares `sql` on `db`, converts `params`, and then invokes `f` with the prepared statement and parameters.
+#[cfg(feature = "sqlite")]
+fn with_statement<F : FnOnce(&mut Statement, Params) -> Result<ValuePtr, String>>(db: ValuePtr, sql: ValuePtr, params: Option<ValuePtr>, f: F) -> ValueResult {
+    let db = db.check_database()?;
+    let sql = sql.check_str()?;
+    let params: Params = to_params(params)?;
+    let it = db.as_database().borrow();
+    let conn: &Connection = it.conn.get(&db)?;
+    let result = match conn.prepare(sql.as_str().borrow_const()) {
+        Ok(mut stmt) => f(&mut stmt, params),
+        Err(err) => Err(err.to_string()),
+    };
+    match result {
+        Ok(value) => value.ok(),
+        Err(err) => ValueErrorDatabase(err).err(),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn named(params: &[(String, Value)]) -> Vec<(&str, &dyn ToSql)> {
+    params.iter().map(|(k, v)| (k.as_str(), v as &dyn ToSql)).collect()
+}
+
+#[cfg(feature = "sqlite")]
+fn to_params(params: Option<ValuePtr>) -> ErrorResult<Params> {
+    let params: ValuePtr = match params {
+        Some(params) => params,
+        None => return Ok(Params::Positional(Vec::new())),
+    };
+    match params.is_dict() {
+        true => {
+            let it = params.as_dict().borrow();
+            it.dict.iter()
+                .map(|(k, v)| {
+                    // Named parameters may be given with or without their prefix, i.e. `{'name': 1}` binds to `:name`
+                    let name: String = k.to_str();
+                    let name: String = if name.starts_with([':', '@', '$']) { name } else { format!(":{}", name) };
+                    Ok((name, to_sql(v)?))
+                })
+                .collect::<ErrorResult<Vec<(String, Value)>>>()
+                .map(Params::Named)
+        },
+        false => params.to_iter()?
+            .map(|v| to_sql(&v))
+            .collect::<ErrorResult<Vec<Value>>>()
+            .map(Params::Positional),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn to_sql(value: &ValuePtr) -> ErrorResult<Value> {
+    Ok(match value.ty() {
+        Type::Nil => Value::Null,
+        Type::Bool | Type::Int => Value::Integer(value.as_int()),
+        Type::Fraction => {
+            let it: R64 = value.clone().as_fraction();
+            Value::Real(*it.numer() as f64 / *it.denom() as f64)
+        },
+        Type::Str => Value::Text(value.as_str().borrow_const().clone()),
+        _ => return TypeErrorCannotBindSqlParameter(value.clone()).err(),
+    })
+}
+
+/// Converts a SQL value to a Cordy value. Like `toml_decode()`, as Cordy has no floating point type, reals are converted to the nearest `fraction`, and blobs are converted to a `list` of bytes.
+#[cfg(feature = "sqlite")]
+fn from_sql(value: ValueRef) -> Result<ValuePtr, String> {
+    match value {
+        ValueRef::Null => Ok(ValuePtr::nil()),
+        ValueRef::Integer(it) if (MIN_INT..=MAX_INT).contains(&it) => Ok(it.to_value()),
+        ValueRef::Integer(it) => Err(format!("integer {} is out of range", it)),
+        ValueRef::Real(it) => match R64::approximate_float(it) {
+            Some(f) if !f.is_integer() || (MIN_INT..=MAX_INT).contains(&f.to_integer()) => Ok(f.to_value()),
+            _ => Err(format!("real {} cannot be represented as a fraction", it)),
+        },
+        ValueRef::Text(it) => Ok(String::from_utf8_lossy(it).into_owned().to_value()),
+        ValueRef::Blob(it) => Ok(it.iter().map(|b| (*b as i64).to_value()).to_list()),
+    }
+}
@@ -0,0 +1,59 @@
+use crate::vm::{DsuImpl, ErrorResult, IntoIterableValue, IntoValue, RuntimeError, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// Constructs a new disjoint set union of the given size, where each element is initially in its own set.
+pub fn create(size: ValuePtr) -> ValueResult {
+    let size: i64 = size.check_int()?.as_int();
+    if size < 0 {
+        return ValueErrorValueMustBeNonNegative(size).err()
+    }
+    match DsuImpl::try_new(size as usize) {
+        Some(it) => it.to_value().ok(),
+        None => ValueErrorDsuTooLarge(size).err(),
+    }
+}
+
+/// Returns the representative element of the set containing `x`. Two elements are in the same set if and only if they have the same representative.
+pub fn find(x: ValuePtr, dsu: ValuePtr) -> ValueResult {
+    let mut it = dsu.as_dsu().borrow_mut();
+    let x: usize = check_element(x, it.size())?;
+    it.find(x).to_value().ok()
+}
+
+/// Merges the sets containing `a` and `b`, returning `true` if they were previously in different sets.
+pub fn union(a: ValuePtr, b: ValuePtr, dsu: ValuePtr) -> ValueResult {
+    let dsu = dsu.check_dsu()?;
+    let mut it = dsu.as_dsu().borrow_mut();
+    let a: usize = check_element(a, it.size())?;
+    let b: usize = check_element(b, it.size())?;
+    it.union(a, b).to_value().ok()
+}
+
+pub fn connected(a: ValuePtr, b: ValuePtr, dsu: ValuePtr) -> ValueResult {
+    let dsu = dsu.check_dsu()?;
+    let mut it = dsu.as_dsu().borrow_mut();
+    let a: usize = check_element(a, it.size())?;
+    let b: usize = check_element(b, it.size())?;
+    (it.find(a) == it.find(b)).to_value().ok()
+}
+
+/// Checks that `x` is an element of a dsu of the given size, in the range `[0, size)`. Unlike an index, `x` cannot be negative.
+fn check_element(x: ValuePtr, size: usize) -> ErrorResult<usize> {
+    let x: i64 = x.check_int()?.as_int();
+    match x >= 0 && (x as usize) < size {
+        true => Ok(x as usize),
+        false => ValueErrorDsuElementOutOfRange(x, size).err(),
+    }
+}
+
+/// Returns a list of each set, as a list of its elements in increasing order, ordered by their smallest element.
+pub fn groups(dsu: ValuePtr) -> ValueResult {
+    let dsu = dsu.check_dsu()?;
+    let groups = dsu.as_dsu().borrow_mut().groups();
+    groups.into_iter()
+        .map(|group| group.into_iter().map(|u| u.to_value()).to_list())
+        .to_list()
+        .ok()
+}
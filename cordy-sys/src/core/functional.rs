@@ -0,0 +1,37 @@
+use crate::core::{NativeFunction, PartialArgument};
+use crate::vm::{IntoValue, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// Returns a function which calls each of `fs` in turn, from right to left, so `compose(f, g)(x)` is `f(g(x))`.
+///
+/// Each pair of functions is represented as a partial `compose(f, g, x)`, so the result has a meaningful `repr()`, and reports the expected number of arguments when called incorrectly.
+/// With no functions, this returns `identity`, and with one function, it returns that function.
+pub fn compose(fs: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    let fs: Vec<ValuePtr> = fs.collect();
+    if let Some(f) = fs.iter().find(|f| !f.is_evaluable()) {
+        return TypeErrorArgMustBeFunction(f.clone()).err()
+    }
+    fs.into_iter()
+        .rev()
+        .reduce(|g, f| ValuePtr::partial_native(NativeFunction::Composed, PartialArgument::Arg3Par2(f, g)))
+        .unwrap_or_else(|| NativeFunction::Identity.to_value())
+        .ok()
+}
+
+/// Invokes a pair of functions created by `compose()`, as `f(g(x))`
+pub fn composed<VM : VirtualInterface>(vm: &mut VM, f: ValuePtr, g: ValuePtr, x: ValuePtr) -> ValueResult {
+    let y: ValuePtr = vm.call(g, [x])?;
+    vm.call(f, [y])
+}
+
+/// Invokes `f` with the arguments `a` and `b` in the opposite order, as `f(b, a)`
+pub fn flip<VM : VirtualInterface>(vm: &mut VM, f: ValuePtr, a: ValuePtr, b: ValuePtr) -> ValueResult {
+    vm.call(f, [b, a])
+}
+
+/// Invokes `f` with the single argument `(a, b)`, which converts a function of a pair into a function of two arguments.
+pub fn curry<VM : VirtualInterface>(vm: &mut VM, f: ValuePtr, a: ValuePtr, b: ValuePtr) -> ValueResult {
+    vm.call(f, [(a, b).to_value()])
+}
@@ -0,0 +1,77 @@
+use std::time::UNIX_EPOCH;
+
+use crate::vm::{IntoValue, LogLevel, LogRecord, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// Logs `message` at the given level, with optional structured fields given by the dictionary `fields`.
+pub fn log<VM : VirtualInterface>(vm: &mut VM, level: LogLevel, message: ValuePtr, fields: Option<ValuePtr>) -> ValueResult {
+    let fields: Vec<(String, String)> = match fields {
+        Some(fields) => {
+            let fields = fields.check_dict()?;
+            let it = fields.as_dict().borrow();
+            it.dict.iter()
+                .map(|(k, v)| (k.to_str(), v.to_repr_str()))
+                .collect()
+        },
+        None => Vec::new(),
+    };
+    vm.log(level, message.to_str(), fields);
+    ValuePtr::nil().ok()
+}
+
+/// Returns the name of the current log level.
+pub fn log_level<VM : VirtualInterface>(vm: &mut VM) -> ValueResult {
+    vm.log_level().name().to_value().ok()
+}
+
+/// Sets the current log level to `level`, and returns the name of the previous level.
+pub fn set_log_level<VM : VirtualInterface>(vm: &mut VM, level: ValuePtr) -> ValueResult {
+    let level = level.check_str()?;
+    match LogLevel::parse(level.as_str().borrow_const()) {
+        Some(new) => {
+            let prev: LogLevel = vm.log_level();
+            vm.set_log_level(new);
+            prev.name().to_value().ok()
+        },
+        None => ValueErrorInvalidLogLevel(level.clone()).err(),
+    }
+}
+
+/// Formats a log record as a single line, with a UTC timestamp, the level, the source location, the message, and then each field as `key=value`.
+pub fn format_log_record(record: &LogRecord) -> String {
+    let mut text: String = format!("{} {:<5} ", format_timestamp(record), record.level.name().to_uppercase());
+    if !record.source.is_empty() {
+        text.push_str(record.source.as_str());
+        if let Some(lineno) = record.lineno {
+            text.push_str(format!(":{}", lineno).as_str());
+        }
+        text.push(' ');
+    }
+    text.push_str(record.message.as_str());
+    for (key, value) in &record.fields {
+        text.push_str(format!(" {}={}", key, value).as_str());
+    }
+    text
+}
+
+/// Formats the timestamp of `record` in the form `YYYY-MM-DDTHH:MM:SS.mmmZ`.
+fn format_timestamp(record: &LogRecord) -> String {
+    let since_epoch = record.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs: u64 = since_epoch.as_secs();
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+
+    // Converts days since the epoch to a civil (year, month, day), see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z: i64 = days + 719468;
+    let era: i64 = z.div_euclid(146097);
+    let doe: i64 = z - era * 146097;
+    let yoe: i64 = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: i64 = (5 * doy + 2) / 153;
+    let day: i64 = doy - (153 * mp + 2) / 5 + 1;
+    let month: i64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: i64 = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day, secs / 3600, (secs / 60) % 60, secs % 60, since_epoch.subsec_millis())
+}
@@ -0,0 +1,257 @@
+use crate::vm::{operator, AnyResult, ErrorResult, IntoDictValue, IntoIterableValue, IntoValue, MAX_INT, MIN_INT, RuntimeError, Type, ValuePtr, ValueResult, ValueStructType, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// The first bytes of every marshalled value, which identify the format and its version.
+const HEADER: [u8; 3] = [b'C', b'M', 1];
+
+const TAG_NIL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_STR: u8 = 4;
+const TAG_LIST: u8 = 5;
+const TAG_VECTOR: u8 = 6;
+const TAG_SET: u8 = 7;
+const TAG_DICT: u8 = 8;
+const TAG_HEAP: u8 = 9;
+const TAG_STRUCT: u8 = 10;
+
+
+/// Serializes `value` into a list of bytes, which can be converted back into an equal value with `unmarshal()`.
+///
+/// Supports `nil`, `bool`, `int`, `str`, `list`, `vector`, `set`, `dict`, `heap` and structs, nested arbitrarily. The default function of a `dict` is not preserved.
+pub fn marshal(value: ValuePtr) -> ValueResult {
+    if value.is_recursive() {
+        return ValueErrorCannotMarshalRecursive(value).err()
+    }
+    let mut buf: Vec<u8> = HEADER.to_vec();
+    encode(&mut buf, &value)?;
+    buf.into_iter()
+        .map(|b| (b as i64).to_value())
+        .to_list()
+        .ok()
+}
+
+/// Deserializes a list of bytes produced by `marshal()` back into a value.
+///
+/// Structs are matched by name and field names against the struct types declared in the current program.
+pub fn unmarshal<VM : VirtualInterface>(vm: &mut VM, bytes: ValuePtr) -> ValueResult {
+    let bytes: Vec<u8> = bytes.to_iter()?
+        .map(|b| {
+            let b: i64 = b.check_int()?.as_int();
+            u8::try_from(b).or_else(|_| ValueErrorInvalidByte(b).err())
+        })
+        .collect::<ErrorResult<Vec<u8>>>()?;
+
+    if !bytes.starts_with(&HEADER) {
+        return ValueErrorCannotUnmarshal(String::from("not a marshalled value")).err()
+    }
+    let mut decoder: Decoder<VM> = Decoder { vm, bytes: &bytes, pos: HEADER.len() };
+    let value: ValuePtr = decoder.decode()?;
+    match decoder.pos == bytes.len() {
+        true => value.ok(),
+        false => ValueErrorCannotUnmarshal(format!("unexpected trailing bytes at offset {}", decoder.pos)).err(),
+    }
+}
+
+
+fn encode(buf: &mut Vec<u8>, value: &ValuePtr) -> AnyResult {
+    match value.ty() {
+        Type::Nil => buf.push(TAG_NIL),
+        Type::Bool => buf.push(if value.as_bool() { TAG_TRUE } else { TAG_FALSE }),
+        Type::Int => {
+            buf.push(TAG_INT);
+            encode_int(buf, value.as_int());
+        },
+        Type::Str => {
+            buf.push(TAG_STR);
+            encode_str(buf, value.as_str().borrow_const());
+        },
+        Type::List => encode_all(buf, TAG_LIST, value.as_list().borrow().list.iter())?,
+        Type::Vector => encode_all(buf, TAG_VECTOR, value.as_vector().borrow().vector.iter())?,
+        Type::Set => encode_all(buf, TAG_SET, value.as_set().borrow().set.iter())?,
+        Type::Heap => encode_all(buf, TAG_HEAP, value.as_heap().borrow().heap.iter().map(|it| &it.0))?,
+        Type::Dict => {
+            let it = value.as_dict().borrow();
+            buf.push(TAG_DICT);
+            encode_len(buf, it.dict.len());
+            for (k, v) in &it.dict {
+                encode(buf, k)?;
+                encode(buf, v)?;
+            }
+        },
+        Type::Struct => {
+            let it = value.as_struct().borrow();
+            let type_impl = it.type_impl.get();
+            buf.push(TAG_STRUCT);
+            encode_str(buf, &type_impl.name);
+            encode_len(buf, type_impl.field_names.len());
+            for (name, field) in type_impl.field_names.iter().zip(it.values()) {
+                encode_str(buf, name);
+                encode(buf, field)?;
+            }
+        },
+        _ => return TypeErrorCannotMarshal(value.clone()).err(),
+    }
+    Ok(())
+}
+
+fn encode_all<'a, I : ExactSizeIterator<Item=&'a ValuePtr>>(buf: &mut Vec<u8>, tag: u8, values: I) -> AnyResult {
+    buf.push(tag);
+    encode_len(buf, values.len());
+    for value in values {
+        encode(buf, value)?;
+    }
+    Ok(())
+}
+
+fn encode_str(buf: &mut Vec<u8>, value: &str) {
+    encode_len(buf, value.len());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn encode_len(buf: &mut Vec<u8>, len: usize) {
+    encode_varint(buf, len as u64);
+}
+
+/// Encodes a signed integer with zig-zag encoding, so small negative numbers are also encoded in a small number of bytes.
+fn encode_int(buf: &mut Vec<u8>, value: i64) {
+    encode_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+/// Encodes an unsigned integer in little-endian base 128, where the high bit of each byte indicates if more bytes follow.
+fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+
+struct Decoder<'a, VM : VirtualInterface> {
+    vm: &'a mut VM,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a, VM : VirtualInterface> Decoder<'a, VM> {
+
+    fn decode(&mut self) -> ValueResult {
+        let tag: u8 = self.next()?;
+        match tag {
+            TAG_NIL => ValuePtr::nil().ok(),
+            TAG_FALSE => false.to_value().ok(),
+            TAG_TRUE => true.to_value().ok(),
+            TAG_INT => self.decode_int()?.to_value().ok(),
+            TAG_STR => self.decode_str()?.to_value().ok(),
+            TAG_LIST => self.decode_all()?.into_iter().to_list().ok(),
+            TAG_VECTOR => self.decode_all()?.into_iter().to_vector().ok(),
+            TAG_SET => self.decode_all()?.into_iter().to_set().ok(),
+            TAG_HEAP => self.decode_all()?.into_iter().to_heap().ok(),
+            TAG_DICT => {
+                let len: usize = self.decode_len()?;
+                let mut entries: Vec<(ValuePtr, ValuePtr)> = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let key: ValuePtr = self.decode()?;
+                    let value: ValuePtr = self.decode()?;
+                    entries.push((key, value));
+                }
+                entries.into_iter().to_dict().ok()
+            },
+            TAG_STRUCT => {
+                let name: String = self.decode_str()?;
+                let len: usize = self.decode_len()?;
+                let mut field_names: Vec<String> = Vec::with_capacity(len);
+                let mut values: Vec<ValuePtr> = Vec::with_capacity(len);
+                for _ in 0..len {
+                    field_names.push(self.decode_str()?);
+                    values.push(self.decode()?);
+                }
+                match self.vm.struct_type(&name, &field_names) {
+                    Some(struct_type) => {
+                        let instance: ValuePtr = ValuePtr::instance(ValueStructType::new(struct_type), values);
+                        operator::update_hash(self.vm, &instance)?;
+                        instance.ok()
+                    },
+                    None => ValueErrorCannotUnmarshal(format!("no struct {}({}) is declared", name, field_names.join(", "))).err(),
+                }
+            },
+            _ => ValueErrorCannotUnmarshal(format!("unknown tag {} at offset {}", tag, self.pos - 1)).err(),
+        }
+    }
+
+    fn decode_all(&mut self) -> ErrorResult<Vec<ValuePtr>> {
+        let len: usize = self.decode_len()?;
+        let mut values: Vec<ValuePtr> = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(self.decode()?);
+        }
+        Ok(values)
+    }
+
+    fn decode_str(&mut self) -> ErrorResult<String> {
+        let len: usize = self.decode_len()?;
+        let start: usize = self.pos;
+        let end: usize = match start.checked_add(len) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => return self.unexpected_end(),
+        };
+        self.pos = end;
+        match String::from_utf8(self.bytes[start..end].to_vec()) {
+            Ok(value) => Ok(value),
+            Err(_) => ValueErrorCannotUnmarshal(format!("invalid utf-8 string at offset {}", start)).err(),
+        }
+    }
+
+    /// Decodes a length, which is bounded by the number of remaining bytes, so malformed input cannot cause a huge allocation.
+    fn decode_len(&mut self) -> ErrorResult<usize> {
+        let len: u64 = self.decode_varint()?;
+        match usize::try_from(len) {
+            Ok(len) if len <= self.bytes.len() - self.pos => Ok(len),
+            _ => self.unexpected_end(),
+        }
+    }
+
+    fn decode_int(&mut self) -> ErrorResult<i64> {
+        let start: usize = self.pos;
+        let value: u64 = self.decode_varint()?;
+        let value: i64 = ((value >> 1) as i64) ^ -((value & 1) as i64);
+        match (MIN_INT..=MAX_INT).contains(&value) {
+            true => Ok(value),
+            false => ValueErrorCannotUnmarshal(format!("integer out of range at offset {}", start)).err(),
+        }
+    }
+
+    fn decode_varint(&mut self) -> ErrorResult<u64> {
+        let mut value: u64 = 0;
+        let mut shift: u32 = 0;
+        loop {
+            let b: u8 = self.next()?;
+            if shift >= 64 {
+                return ValueErrorCannotUnmarshal(format!("integer too large at offset {}", self.pos - 1)).err()
+            }
+            value |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(value)
+            }
+            shift += 7;
+        }
+    }
+
+    fn next(&mut self) -> ErrorResult<u8> {
+        match self.bytes.get(self.pos) {
+            Some(b) => {
+                self.pos += 1;
+                Ok(*b)
+            },
+            None => self.unexpected_end(),
+        }
+    }
+
+    fn unexpected_end<T>(&self) -> ErrorResult<T> {
+        ValueErrorCannotUnmarshal(String::from("unexpected end of input")).err()
+    }
+}
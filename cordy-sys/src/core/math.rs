@@ -1,32 +1,61 @@
 use num_integer::Roots;
+use num_traits::CheckedDiv;
 
-use crate::vm::{ErrorResult, IntoValue, RuntimeError, Type, ValueOption, ValuePtr, ValueResult};
+use crate::vm::{ErrorResult, IntoValue, MIN_INT, operator, R64, RuntimeError, Type, ValueOption, ValuePtr, ValueResult};
 
 use RuntimeError::{*};
 
 
 pub fn convert_to_int(target: ValuePtr, default: ValueOption) -> ValueResult {
-    match target.ty() {
-        Type::Nil => 0i64.to_value().ok(),
-        Type::Bool => target.as_int().to_value().ok(),
-        Type::Int => target.ok(),
-        Type::Str => match target.as_str().borrow_const().parse::<i64>() {
-            Ok(i) => i.to_value().ok(),
-            Err(_) => match default.as_option() {
-                Some(a2) => a2.ok(),
-                None => TypeErrorCannotConvertToInt(target).err(),
-            },
+    match to_int(&target) {
+        Some(i) => i.to_value().ok(),
+        None => match (target.is_str(), default.as_option()) {
+            (true, Some(a2)) => a2.ok(),
+            _ => TypeErrorCannotConvertToInt(target).err(),
         },
-        _ => TypeErrorCannotConvertToInt(target).err(),
+    }
+}
+
+/// Like `convert_to_int()`, but returns `nil` instead of raising an error, for any value which cannot be converted to an int.
+pub fn try_convert_to_int(target: ValuePtr) -> ValueResult {
+    match to_int(&target) {
+        Some(i) => i.to_value().ok(),
+        None => ValuePtr::nil().ok(),
+    }
+}
+
+fn to_int(target: &ValuePtr) -> Option<i64> {
+    match target.ty() {
+        Type::Nil => Some(0),
+        Type::Bool | Type::Int => Some(target.as_int()),
+        Type::Fraction => Some(target.clone().as_fraction().floor().to_integer()),
+        Type::Str => target.as_str().borrow_const().parse::<i64>().ok(),
+        Type::Struct => target.as_struct().borrow().type_impl.get().discriminant().map(|d| d as i64),
+        _ => None,
+    }
+}
+
+/// Constructs a fraction `x / denominator`, where both arguments may be either an `int` or `fraction`.
+pub fn convert_to_fraction(x: ValuePtr, denominator: ValuePtr) -> ValueResult {
+    let x: R64 = x.check_fraction()?.as_fraction();
+    let denominator: R64 = denominator.check_fraction()?.as_fraction();
+    if denominator == R64::from_integer(0) {
+        ValueErrorValueMustBeNonZero.err()
+    } else {
+        operator::checked_fraction(x.checked_div(&denominator))
     }
 }
 
 pub fn abs(value: ValuePtr) -> ValueResult {
-    value.check_int()?
-        .as_int()
-        .abs()
-        .to_value()
-        .ok()
+    if value.is_precise_fraction() {
+        let it: R64 = value.as_fraction();
+        return operator::checked_fraction(Some(if *it.numer() < 0 { -it } else { it }))
+    }
+    // The absolute value of the minimum `int` is one larger than the maximum, so cannot be represented
+    match value.check_int()?.as_int() {
+        MIN_INT => ValueErrorIntOverflow.err(),
+        it => it.abs().to_value().ok(),
+    }
 }
 
 pub fn sqrt(value: ValuePtr) -> ValueResult {
@@ -57,6 +86,9 @@ pub fn lcm(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
 }
 
 pub fn count_ones(value: ValuePtr) -> ValueResult {
+    if value.is_bitset() {
+        return value.as_bitset().borrow().count_ones().to_value().ok()
+    }
     (value.check_int()?
         .as_int()
         .count_ones() as i64)
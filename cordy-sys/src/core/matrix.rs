@@ -0,0 +1,145 @@
+use crate::core::collections;
+use crate::vm::{AnyResult, ErrorResult, IntoValue, MatrixImpl, RuntimeError, Type, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// Constructs a matrix from a nested iterable, where each element is a row of `int`s.
+pub fn from_rows(rows: ValuePtr) -> ValueResult {
+    let mut data: Vec<i64> = Vec::new();
+    let mut n_rows: usize = 0;
+    let mut n_cols: Option<usize> = None;
+    for row in rows.to_iter()? {
+        let start: usize = data.len();
+        for value in row.to_iter()? {
+            data.push(value.check_int()?.as_int());
+        }
+        let len: usize = data.len() - start;
+        match n_cols {
+            Some(cols) if cols != len => return ValueErrorMatrixRowLength(cols, len).err(),
+            _ => n_cols = Some(len),
+        }
+        n_rows += 1;
+    }
+    MatrixImpl::new(n_rows, n_cols.unwrap_or(0), data).to_value().ok()
+}
+
+/// Constructs a `rows x cols` matrix, with every element set to `fill`.
+pub fn from_fill(rows: ValuePtr, cols: ValuePtr, fill: ValuePtr) -> ValueResult {
+    let rows: usize = check_dimension(rows)?;
+    let cols: usize = check_dimension(cols)?;
+    let fill: i64 = fill.check_int()?.as_int();
+    MatrixImpl::new(rows, cols, alloc(rows, cols, fill)?).to_value().ok()
+}
+
+/// Allocates the elements of a `rows x cols` matrix, each set to `fill`. If the number of elements overflows, or cannot be allocated, this raises an error rather than panicking or aborting.
+fn alloc(rows: usize, cols: usize, fill: i64) -> ErrorResult<Vec<i64>> {
+    let mut data: Vec<i64> = Vec::new();
+    match rows.checked_mul(cols) {
+        Some(len) if data.try_reserve_exact(len).is_ok() => {
+            data.resize(len, fill);
+            Ok(data)
+        },
+        _ => ValueErrorMatrixTooLarge(rows, cols).err(),
+    }
+}
+
+fn check_dimension(value: ValuePtr) -> ErrorResult<usize> {
+    let i: i64 = value.check_int()?.as_int();
+    if i < 0 {
+        ValueErrorValueMustBeNonNegative(i).err()
+    } else {
+        Ok(i as usize)
+    }
+}
+
+/// Indexing a matrix either takes a single `int`, which returns a row as a `vector`, or a pair of `(row, col)`, which returns a single element.
+pub fn get_index(matrix: &ValuePtr, index: ValuePtr) -> ValueResult {
+    let it = matrix.as_matrix().borrow();
+    match to_matrix_index(&it, index)? {
+        MatrixIndex::Row(row) => it.row(row).ok(),
+        MatrixIndex::Element(offset) => it.data[offset].to_value().ok(),
+    }
+}
+
+/// Assigns either a single element, when indexed by `(row, col)`, or an entire row, when indexed by an `int`.
+pub fn set_index(matrix: &ValuePtr, index: ValuePtr, value: ValuePtr) -> AnyResult {
+    let (index, cols) = {
+        let it = matrix.as_matrix().borrow();
+        (to_matrix_index(&it, index)?, it.cols)
+    };
+    match index {
+        MatrixIndex::Row(row) => {
+            // Collect the row before borrowing the matrix mutably, as `value` may be derived from the matrix itself
+            let values: Vec<i64> = value.to_iter()?
+                .map(|v| v.check_int().as_result().map(|v| v.as_int()))
+                .collect::<ErrorResult<Vec<i64>>>()?;
+            if values.len() != cols {
+                return ValueErrorMatrixRowLength(cols, values.len()).err()
+            }
+            matrix.as_matrix().borrow_mut().data[row * cols..(row + 1) * cols].copy_from_slice(&values);
+        },
+        MatrixIndex::Element(offset) => {
+            let value: i64 = value.check_int()?.as_int();
+            matrix.as_matrix().borrow_mut().data[offset] = value;
+        },
+    }
+    Ok(())
+}
+
+enum MatrixIndex {
+    Row(usize),
+    Element(usize),
+}
+
+fn to_matrix_index(it: &MatrixImpl, index: ValuePtr) -> ErrorResult<MatrixIndex> {
+    match index.ty() {
+        Type::List | Type::Vector => {
+            let len: usize = index.len()?;
+            let mut iter = index.clone().to_iter()?;
+            match (iter.next(), iter.next(), iter.next()) {
+                (Some(row), Some(col), None) => {
+                    let row: usize = collections::check_index(row, it.rows)?;
+                    let col: usize = collections::check_index(col, it.cols)?;
+                    Ok(MatrixIndex::Element(row * it.cols + col))
+                },
+                _ => ValueErrorCannotUnpackLengthMustBeEqual(2, len, index).err(),
+            }
+        },
+        _ => Ok(MatrixIndex::Row(collections::check_index(index, it.rows)?)),
+    }
+}
+
+pub fn transpose(matrix: ValuePtr) -> ValueResult {
+    let it = matrix.check_matrix()?;
+    let it = it.as_matrix().borrow();
+    let mut data: Vec<i64> = Vec::with_capacity(it.data.len());
+    for col in 0..it.cols {
+        for row in 0..it.rows {
+            data.push(it.data[row * it.cols + col]);
+        }
+    }
+    MatrixImpl::new(it.cols, it.rows, data).to_value().ok()
+}
+
+pub fn matmul(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
+    let lhs = lhs.check_matrix()?;
+    let rhs = rhs.check_matrix()?;
+    let lhs = lhs.as_matrix().borrow();
+    let rhs = rhs.as_matrix().borrow();
+    if lhs.cols != rhs.rows {
+        return ValueErrorMatrixDimensionMismatch(lhs.rows, lhs.cols, rhs.rows, rhs.cols).err()
+    }
+
+    // Iterate in `i, k, j` order, so the innermost loop accesses both `rhs` and `data` sequentially
+    let mut data: Vec<i64> = alloc(lhs.rows, rhs.cols, 0)?;
+    for i in 0..lhs.rows {
+        for k in 0..lhs.cols {
+            let l: i64 = lhs.data[i * lhs.cols + k];
+            for j in 0..rhs.cols {
+                data[i * rhs.cols + j] += l * rhs.data[k * rhs.cols + j];
+            }
+        }
+    }
+    MatrixImpl::new(lhs.rows, rhs.cols, data).to_value().ok()
+}
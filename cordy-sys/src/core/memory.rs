@@ -0,0 +1,51 @@
+use crate::vm::{IntoDictValue, IntoValue, MemStat, RuntimeError, ValuePtr, ValueResult, VirtualInterface, WeakImpl};
+
+use RuntimeError::{*};
+
+
+pub fn weak(value: ValuePtr) -> ValueResult {
+    let value = value.check_shared()?;
+    WeakImpl::new(&value).to_value().ok()
+}
+
+/// Returns the value referenced by `weak`, or `nil` if it has since been freed.
+pub fn get(weak: ValuePtr) -> ValueResult {
+    let weak = weak.check_weak()?;
+    let value: Option<ValuePtr> = weak.as_weak().borrow_const().get();
+    value.unwrap_or_else(ValuePtr::nil).ok()
+}
+
+/// Registers `f` to be invoked with no arguments, once `value` has been freed.
+pub fn on_drop<VM : VirtualInterface>(vm: &mut VM, value: ValuePtr, f: ValuePtr) -> ValueResult {
+    let value = value.check_shared()?;
+    if !f.is_evaluable() {
+        return TypeErrorArgMustBeFunction(f).err()
+    }
+    vm.add_finalizer(&value, f);
+    ValuePtr::nil().ok()
+}
+
+/// Returns the number of live values, and approximate bytes used, in total and of each type, along with the depth of the VM's stack.
+pub fn memstats<VM : VirtualInterface>(vm: &VM) -> ValueResult {
+    let stats: Vec<MemStat> = crate::vm::live_stats();
+    let (stack, frames) = vm.stack_depth();
+    let count: u64 = stats.iter().map(|stat| stat.count).sum();
+    let bytes: u64 = stats.iter().map(|stat| stat.bytes).sum();
+    let types: ValuePtr = stats.into_iter()
+        .map(|stat| (stat.name.to_value(), to_stat(stat.count, stat.bytes)))
+        .to_dict();
+    vec![
+        ("count".to_value(), (count as i64).to_value()),
+        ("bytes".to_value(), (bytes as i64).to_value()),
+        ("types".to_value(), types),
+        ("stack".to_value(), (stack as i64).to_value()),
+        ("frames".to_value(), (frames as i64).to_value()),
+    ].into_iter().to_dict().ok()
+}
+
+fn to_stat(count: u64, bytes: u64) -> ValuePtr {
+    vec![
+        ("count".to_value(), (count as i64).to_value()),
+        ("bytes".to_value(), (bytes as i64).to_value()),
+    ].into_iter().to_dict()
+}
@@ -1,54 +1,159 @@
+use std::cmp::Ordering;
 use std::collections::{BinaryHeap, VecDeque};
 use std::default::Default;
 use std::fs;
 use std::hash::Hash;
+use std::ops::ControlFlow;
 use fxhash::FxBuildHasher;
 use indexmap::{IndexMap, IndexSet};
 
-use crate::trace;
-use crate::vm::{ErrorResult, IntoIterableValue, IntoValue, MAX_INT, MIN_INT, operator, RuntimeError, Type, ValueOption, ValuePtr, ValueResult, VirtualInterface};
+use crate::vm::{AnyResult, Capability, Entropy, ErrorResult, FunctionImpl, IntoIterableValue, IntoValue, LazyIterImpl, MAX_INT, MIN_INT, operator, RuntimeError, PDictImpl, PListImpl, SocketImpl, SortedDictImpl, SortedSetImpl, Signal, StoreOp, Terminal, Type, ValueOption, ValuePtr, ValueResult, VirtualInterface};
 use crate::vm::operator::BinaryOp;
 
 pub use crate::core::collections::{get_index, get_slice, set_index, to_index};
 pub use crate::core::strings::format_string;
 pub use crate::core::pattern::Pattern;
+pub use crate::core::paths::read_dir;
+pub use crate::core::timing::ProgressBar;
+pub use crate::core::logging::format_log_record;
 
 use Argument::{*};
 use NativeFunction::{*};
 use RuntimeError::{*};
 
 
+mod argparse;
+mod assertions;
+mod bitset;
+mod csv;
+mod dsu;
+mod database;
+mod logging;
+mod marshal;
 mod math;
+mod matrix;
+mod memory;
+mod net;
+mod pack;
 mod pattern;
+mod process;
+mod property;
+mod random;
+mod paths;
+mod sorted;
+mod persistent;
+mod functional;
 mod strings;
+mod terminal;
+mod timing;
 mod collections;
+mod toml;
 
 
 /// An enum representing all possible native functions implemented in Cordy
-#[repr(u8)]
+#[repr(u16)]
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
 pub enum NativeFunction {
     Read,
     ReadLine,
+    ReadLines,
+    Input,
     Print,
+    EPrint,
+    EPrintLn,
+    WriteBytes,
     ReadText,
     WriteText,
+    PathJoin,
+    DirName,
+    BaseName,
+    Extension,
+    Absolute,
+    Normalize,
+    HomeDir,
+    TempDir,
+    ListDir,
+    Walk,
+    Glob,
     Env,
     Argv,
+    Argparse,
+    Marshal,
+    Unmarshal,
+    Pack,
+    Unpack,
+    AtExit,
+    OnSignal,
+    TermSize,
+    ClearScreen,
+    ReadKey,
+    Color,
+    TimeIt,
+    Stopwatch,
+    Lap,
+    Elapsed,
+    Progress,
+    Task,
+    SleepAsync,
+    LogDebug,
+    LogInfo,
+    LogWarn,
+    LogError,
+    LogLevel,
+    Uuid4,
+    RandBytes,
+    RandHex,
+    TcpConnect,
+    TcpListen,
+    Accept,
+    Send,
+    RecvLine,
+    Close,
+    LocalAddr,
+    HttpGetAsync,
+    Coprocess,
+    DbOpen,
+    DbExec,
+    DbQuery,
+    GenInt,
+    GenList,
+    GenStr,
+    Forall,
+    AssertEq,
+    AssertErr,
+    ApproxEq,
+    Weak,
+    Get,
+    OnDrop,
+    MemStats,
+    SendLine,
+    TryInt,
+    ParseInt,
     Bool,
     Int,
     Complex,
+    Fraction,
     Str,
     List,
     Set,
     Dict,
     Heap,
+    SortedSet,
+    SortedDict,
     Vector,
+    View,
+    PList,
+    PDict,
+    Matrix,
+    BitSet,
+    Dsu,
     Function,
     Iterable,
     Repr,
     Eval,
     TypeOf,
+    Doc,
+    Help,
 
     // Native Operators
     OperatorSub,
@@ -94,54 +199,117 @@ pub enum NativeFunction {
     ToUpper,
     Replace,
     Search,
+    Scan,
     Trim,
     Split,
     Join,
+    Template,
     Char,
     Ord,
     Hex,
     Bin,
+    IsDigit,
+    IsAlpha,
+    IsAlnum,
+    IsSpace,
+    IsUpper,
+    IsLower,
+    CsvDecode,
+    CsvEncode,
+    TomlDecode,
 
     // collections
     Len,
+    IsEmpty,
+    CountAll,
     Range,
     Enumerate,
+    Cycle,
+    Repeat,
+    Chain,
+    Take,
+    First,
+    Last,
+    Nth,
+    Single,
     Sum,
     Min,
     Max,
+    Prod,
+    CumSum,
+    MinMax,
     MinBy,
     MaxBy,
     Map,
     Filter,
+    Partition,
+    Span,
+    TakeWhile,
+    DropWhile,
+    Count,
+    Tally,
     FlatMap,
     Concat, // Native optimized version of flatMap(fn(x) -> x)
+    Flatten,
+    Distinct, // `unique`, as `Unique` is an `Argument`
+    UniqueBy,
+    Dedup,
     Zip,
+    Unzip,
     Reduce,
     Sort,
     SortBy,
     GroupBy,
+    IndexBy,
+    Invert,
     Reverse,
     Permutations,
     Combinations,
     Any,
     All,
     Memoize,
+    CacheStats,
+    CacheClear,
+    Compose,
+    Composed, // The partial function created by `compose()`
+    Identity,
+    Const,
+    Flip,
+    Curry,
     Union,
     Intersect,
     Difference,
+    Connected,
+    Groups,
+    GetBit,
+    SetBit,
+    FlipBit,
 
     Peek, // Peek first value
     Pop, // Remove value at end
     PopFront, // Remove value at front
     Push, // Insert value at end
     PushFront, // Insert value at front
+    ExtendFront, // Insert each value of an iterable at front, in order
     Insert, // Insert value at index
     Remove, // Remove (list: by index, set: by value, dict: by key)
+    SwapRemove, // Remove by index, replacing it with the last value
+    Rotate, // Rotate a list to the right by n
     Clear, // Remove all values - shortcut for `retain(fn(_) -> false)`
+    ListWithCapacity,
+    DictWithCapacity,
+    Reserve,
+    Between,
+    Floor,
+    Ceiling,
+    Conj,
+    Assoc,
+    Dissoc,
     Find, // Find first value (list, set) or key (dict) by predicate
     RightFind, // Find last index of value (list, set), or key (dict) by predicate
     IndexOf, // Find first index of value, or index by predicate
     RightIndexOf, // Find last index of a value, or index by predicate
+    Contains, // Equivalent to the `in` operator
     Default, // For a `Dict`, sets the default value
     Keys, // `Dict.keys` -> returns a set of all keys
     Values, // `Dict.values` -> returns a list of all values
@@ -155,6 +323,8 @@ pub enum NativeFunction {
     CountZeros,
     Real,
     Imag,
+    Transpose,
+    MatMul,
 }
 
 
@@ -167,6 +337,11 @@ impl NativeFunction {
         std::mem::variant_count::<NativeFunction>()
     }
 
+    /// Returns every native function, including hidden ones.
+    pub fn all() -> impl Iterator<Item=NativeFunction> {
+        NATIVE_FUNCTIONS.iter().map(|info| info.native)
+    }
+
     /// Find a native function with the given name, that is not hidden.
     pub fn find(name: &str) -> Option<NativeFunction> {
         NATIVE_FUNCTIONS.iter()
@@ -183,6 +358,37 @@ impl NativeFunction {
     /// Returns the `repr` string of the function, which is the form `fn <name>(<args> ...)`
     pub fn repr(&self) -> String { let info = self.info(); format!("fn {}({})", info.name, info.args) }
 
+    /// Returns `true` if the function cannot be referenced by name, i.e. operators.
+    pub fn is_hidden(&self) -> bool { self.info().hidden }
+
+    /// Returns the category this function is listed under in `help()`
+    pub fn category(&self) -> Category { self.info().category }
+
+    /// Returns a one-line summary of the function, or an empty string if the function is hidden.
+    pub fn summary(&self) -> &'static str { self.info().summary }
+
+    /// Returns the maximum amount of arguments this function can be evaluated with, or `None` if it accepts any number of arguments
+    pub fn max_nargs(&self) -> Option<u32> { self.info().arg.max_nargs() }
+
+    /// Returns `true` if evaluating this function has no side effects, and does not invoke any user code.
+    /// It may still raise an error, for instance if its arguments are of the wrong type.
+    pub fn is_pure(&self) -> bool { self.info().pure }
+
+    /// Returns the capability which the host must allow, for this function to be evaluated.
+    pub fn capability(&self) -> Option<Capability> { self.info().capability }
+
+    /// Returns the index printed by `help()`, of every function which is not hidden, grouped by category.
+    pub fn help_index() -> String {
+        let mut lines: Vec<String> = Vec::new();
+        for category in Category::all() {
+            lines.push(format!("{}:", category.name()));
+            for info in NATIVE_FUNCTIONS.iter().filter(|info| info.category == category && !info.hidden) {
+                lines.push(format!("  {:<20}{}", info.name, info.summary));
+            }
+        }
+        lines.join("\n")
+    }
+
     pub fn is_operator(&self) -> bool { self.swap() != *self }
 
     /// An `operator` refers to an operator which has a direct opcode representation.
@@ -264,11 +470,65 @@ struct NativeFunctionInfo {
     args: &'static str,
     arg: Argument,
     hidden: bool,
+    category: Category,
+    summary: &'static str,
+    pure: bool,
+    capability: Option<Capability>,
 }
 
 impl NativeFunctionInfo {
-    const fn new(native: NativeFunction, name: &'static str, args: &'static str, arg: Argument, hidden: bool) -> NativeFunctionInfo {
-        NativeFunctionInfo { native, name, args, arg, hidden }
+    const fn new(native: NativeFunction, name: &'static str, args: &'static str, arg: Argument, hidden: bool, category: Category, summary: &'static str) -> NativeFunctionInfo {
+        NativeFunctionInfo { native, name, args, arg, hidden, category, summary, pure: false, capability: None }
+    }
+
+    /// Marks this function as pure: when evaluated, it has no side effects, and does not invoke any user code.
+    ///
+    /// Every function which meets this definition must be marked, which is checked by a test. That is, a function which takes a fixed number of arguments, does not require a capability, is not in the `Io`, `System`, `Net` or `Testing` categories, does not use the VM for anything but its arguments, does not mutate its arguments, and returns the same result for the same arguments.
+    const fn pure(self) -> NativeFunctionInfo {
+        NativeFunctionInfo { pure: true, ..self }
+    }
+
+    /// Marks this function as requiring `capability`, so it raises an error if the host has denied it.
+    const fn requires(self, capability: Capability) -> NativeFunctionInfo {
+        NativeFunctionInfo { capability: Some(capability), ..self }
+    }
+}
+
+/// The category of a native function, which groups related functions together in `help()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Category {
+    Io,
+    System,
+    Net,
+    Testing,
+    Types,
+    Strings,
+    Collections,
+    Functions,
+    Math,
+    Operators,
+}
+
+impl Category {
+    /// Returns every category which contains functions that are not hidden, in the order they are listed.
+    pub fn all() -> impl Iterator<Item=Category> {
+        use Category::{*};
+        [Io, System, Net, Testing, Types, Strings, Collections, Functions, Math].into_iter()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Io => "io",
+            Category::System => "system",
+            Category::Net => "net",
+            Category::Testing => "testing",
+            Category::Types => "types",
+            Category::Strings => "strings",
+            Category::Collections => "collections",
+            Category::Functions => "functions",
+            Category::Math => "math",
+            Category::Operators => "operators",
+        }
     }
 }
 
@@ -277,32 +537,114 @@ static NATIVE_FUNCTIONS: [NativeFunctionInfo; NativeFunction::total()] = load_na
 
 const fn load_native_functions() -> [NativeFunctionInfo; NativeFunction::total()] {
 
-    const fn op1(f: NativeFunction, name: &'static str, args: &'static str, arg: Argument) -> NativeFunctionInfo { NativeFunctionInfo::new(f, name, args, arg, true) }
-    const fn op2(f: NativeFunction, name: &'static str) -> NativeFunctionInfo { NativeFunctionInfo::new(f, name, "lhs, rhs", Arg2, true) }
-    const fn new(f: NativeFunction, name: &'static str, args: &'static str, arg: Argument) -> NativeFunctionInfo { NativeFunctionInfo::new(f, name, args, arg, false) }
+    use Category::{*};
+
+    const fn op1(f: NativeFunction, name: &'static str, args: &'static str, arg: Argument) -> NativeFunctionInfo { NativeFunctionInfo::new(f, name, args, arg, true, Operators, "").pure() }
+    const fn op2(f: NativeFunction, name: &'static str) -> NativeFunctionInfo { NativeFunctionInfo::new(f, name, "lhs, rhs", Arg2, true, Operators, "").pure() }
+    const fn new(f: NativeFunction, name: &'static str, args: &'static str, arg: Argument, category: Category, summary: &'static str) -> NativeFunctionInfo { NativeFunctionInfo::new(f, name, args, arg, false, category, summary) }
+    const fn hidden(f: NativeFunction, name: &'static str, args: &'static str, arg: Argument, category: Category) -> NativeFunctionInfo { NativeFunctionInfo::new(f, name, args, arg, true, category, "") }
 
     [
-        new(Read, "read", "", Arg0),
-        new(ReadLine, "read_line", "", Arg0),
-        new(Print, "print", "...", Unique),
-        new(ReadText, "read_text", "file", Arg1),
-        new(WriteText, "write_text", "file, text", Arg2),
-        new(Env, "env", "...", Arg0To1),
-        new(Argv, "argv", "", Arg0),
-        new(Bool, "bool", "x", Arg1),
-        new(Int, "int", "x, default?", Arg1To2),
-        new(Complex, "complex", "", Invalid),
-        new(Str, "str", "x", Arg1),
-        new(List, "list", "...", Iter),
-        new(Set, "set", "...", Iter),
-        new(Dict, "dict", "...", Iter),
-        new(Heap, "heap", "...", Iter),
-        new(Vector, "vector", "...", Unique),
-        new(Function, "function", "", Invalid),
-        new(Iterable, "iterable", "", Invalid),
-        new(Repr, "repr", "x", Arg1),
-        new(Eval, "eval", "expr", Arg1),
-        new(TypeOf, "typeof", "x", Arg1),
+        new(Read, "read", "", Arg0, Io, "Reads all of stdin as a string"),
+        new(ReadLine, "read_line", "", Arg0, Io, "Reads a single line from stdin"),
+        new(ReadLines, "read_lines", "", Arg0, Io, "Reads all of stdin as a list of lines"),
+        new(Input, "input", "prompt", Arg1, Io, "Prints a prompt, and reads a line from stdin"),
+        new(Print, "print", "...", Unique, Io, "Prints each argument to stdout, followed by a newline"),
+        new(EPrint, "eprint", "...", Unique, Io, "Prints each argument to stderr, without a trailing newline"),
+        new(EPrintLn, "eprintln", "...", Unique, Io, "Prints each argument to stderr, followed by a newline"),
+        new(WriteBytes, "write_bytes", "bytes", Arg1, Io, "Writes raw bytes to stdout"),
+        new(ReadText, "read_text", "file", Arg1, Io, "Reads the contents of a file as a string").requires(Capability::Fs),
+        new(WriteText, "write_text", "file, text", Arg2, Io, "Writes a string to a file, replacing its contents").requires(Capability::Fs),
+        new(PathJoin, "path_join", "...", IterNonEmpty, Io, "Joins path components with the platform separator"),
+        new(DirName, "dirname", "path", Arg1, Io, "Returns the parent directory of a path"),
+        new(BaseName, "basename", "path", Arg1, Io, "Returns the final component of a path"),
+        new(Extension, "extension", "path", Arg1, Io, "Returns the extension of a path"),
+        new(Absolute, "absolute", "path", Arg1, Io, "Returns the absolute form of a path").requires(Capability::Fs),
+        new(Normalize, "normalize", "path", Arg1, Io, "Removes redundant components from a path"),
+        new(HomeDir, "home_dir", "", Arg0, Io, "Returns the current user's home directory").requires(Capability::Fs),
+        new(TempDir, "temp_dir", "", Arg0, Io, "Returns the system temporary directory").requires(Capability::Fs),
+        new(ListDir, "list_dir", "path", Arg1, Io, "Lists the entries of a directory").requires(Capability::Fs),
+        new(Walk, "walk", "path", Arg1, Io, "Recursively iterates all files under a directory").requires(Capability::Fs),
+        new(Glob, "glob", "pattern", Arg1, Io, "Returns all paths matching a glob pattern").requires(Capability::Fs),
+        new(Env, "env", "...", Arg0To1, System, "Returns all environment variables, or a single one by name"),
+        new(Argv, "argv", "", Arg0, System, "Returns the command line arguments"),
+        new(Argparse, "argparse", "spec", Arg1, System, "Parses the command line arguments according to a spec"),
+        new(Marshal, "marshal", "value", Arg1, Io, "Serializes a value to a list of bytes"),
+        new(Unmarshal, "unmarshal", "bytes", Arg1, Io, "Deserializes a value from a list of bytes"),
+        new(Pack, "pack", "format, values", Arg2, Io, "Packs values into bytes according to a format"),
+        new(Unpack, "unpack", "format, bytes", Arg2, Io, "Unpacks bytes into values according to a format"),
+        new(AtExit, "at_exit", "f", Arg1, System, "Registers a function to be called when the program exits"),
+        new(OnSignal, "on_signal", "signal, f", Arg2, System, "Registers a function to be called when a signal is received"),
+        new(TermSize, "term_size", "", Arg0, Io, "Returns the terminal size as (columns, rows)"),
+        new(ClearScreen, "clear_screen", "", Arg0, Io, "Clears the terminal"),
+        new(ReadKey, "read_key", "", Arg0, Io, "Reads a single key press from the terminal"),
+        new(Color, "color", "name, x", Arg2, Io, "Wraps a value in a named terminal color"),
+        new(TimeIt, "timeit", "f, n?", Arg1To2, System, "Returns the time taken to call a function, in nanoseconds"),
+        new(Stopwatch, "stopwatch", "", Arg0, System, "Creates a new, running stopwatch"),
+        new(Lap, "lap", "stopwatch", Arg1, System, "Returns the time since the last lap of a stopwatch"),
+        new(Elapsed, "elapsed", "stopwatch", Arg1, System, "Returns the time since a stopwatch was started"),
+        new(Progress, "progress", "it, total?", Arg1To2, System, "Wraps an iterable with a progress bar"),
+        new(Task, "task", "x", Arg1, System, "Wraps a value in a completed task"),
+        new(SleepAsync, "sleep_async", "ms", Arg1, System, "Returns a task which completes after a delay"),
+        new(LogDebug, "log_debug", "msg, fields?", Arg1To2, System, "Logs a message at the debug level"),
+        new(LogInfo, "log_info", "msg, fields?", Arg1To2, System, "Logs a message at the info level"),
+        new(LogWarn, "log_warn", "msg, fields?", Arg1To2, System, "Logs a message at the warn level"),
+        new(LogError, "log_error", "msg, fields?", Arg1To2, System, "Logs a message at the error level"),
+        new(LogLevel, "log_level", "level?", Arg0To1, System, "Returns, or sets, the minimum log level"),
+        new(Uuid4, "uuid4", "", Arg0, System, "Returns a random version 4 UUID"),
+        new(RandBytes, "rand_bytes", "n", Arg1, System, "Returns a list of random bytes"),
+        new(RandHex, "rand_hex", "n", Arg1, System, "Returns a random hexadecimal string"),
+        new(TcpConnect, "tcp_connect", "host, port", Arg2, Net, "Opens a TCP connection to a host and port").requires(Capability::Net),
+        new(TcpListen, "tcp_listen", "port", Arg1, Net, "Listens for TCP connections on a port").requires(Capability::Net),
+        new(Accept, "accept", "listener", Arg1, Net, "Accepts a connection from a TCP listener"),
+        new(Send, "send", "stream, text", Arg2, Net, "Sends text over a TCP stream"),
+        new(RecvLine, "recv_line", "stream", Arg1, Net, "Receives a single line from a TCP stream or coprocess"),
+        new(Close, "close", "handle", Arg1, Net, "Closes a stream, listener, coprocess or database"),
+        new(LocalAddr, "local_addr", "socket", Arg1, Net, "Returns the local address of a socket"),
+        new(HttpGetAsync, "http_get_async", "url", Arg1, Net, "Returns a task which performs a HTTP GET request").requires(Capability::Net),
+        new(Coprocess, "coprocess", "cmd, timeout?", Arg1To2, System, "Spawns a process which can be written to and read from").requires(Capability::Process),
+        new(DbOpen, "db_open", "path", Arg1, Io, "Opens a SQLite database").requires(Capability::Fs),
+        new(DbExec, "db_exec", "db, sql, params?", Arg2To3, Io, "Executes a SQL statement, returning the number of changed rows"),
+        new(DbQuery, "db_query", "db, sql, params?", Arg2To3, Io, "Executes a SQL query, returning a list of rows"),
+        new(GenInt, "gen_int", "lo, hi", Arg2, Testing, "Creates a generator of integers in a range"),
+        new(GenList, "gen_list", "g, n", Arg2, Testing, "Creates a generator of lists of generated values"),
+        new(GenStr, "gen_str", "alphabet, n", Arg2, Testing, "Creates a generator of strings from an alphabet"),
+        new(Forall, "forall", "g, f, cases?", Arg2To3, Testing, "Checks a property holds for many generated values"),
+        new(AssertEq, "assert_eq", "a, b", Arg2, Testing, "Raises an error if two values are not equal"),
+        new(AssertErr, "assert_err", "f", Arg1, Testing, "Raises an error if calling a function does not raise an error"),
+        new(ApproxEq, "approx_eq", "a, b, eps", Arg3, Testing, "Returns true if two numbers are within a tolerance"),
+        new(Weak, "weak", "x", Arg1, System, "Creates a weak reference to a value"),
+        new(Get, "get", "weak", Arg1, System, "Returns the value of a weak reference, or nil if it was dropped"),
+        new(OnDrop, "on_drop", "x, f", Arg2, System, "Registers a function to be called when a value is dropped"),
+        new(MemStats, "memstats", "", Arg0, System, "Returns statistics about memory usage"),
+        new(SendLine, "send_line", "stream, text", Arg2, Net, "Sends text followed by a newline over a stream or coprocess"),
+        new(TryInt, "try_int", "x", Arg1, Types, "Converts a value to an int, or returns nil").pure(),
+        new(ParseInt, "parse_int", "s, default", Arg2, Types, "Converts a string to an int, or returns a default").pure(),
+        new(Bool, "bool", "x", Arg1, Types, "Converts a value to a bool").pure(),
+        new(Int, "int", "x, default?", Arg1To2, Types, "Converts a value to an int").pure(),
+        new(Complex, "complex", "", Invalid, Types, "The type of complex numbers"),
+        new(Fraction, "fraction", "x, denominator?", Arg1To2, Types, "Converts a value to a fraction").pure(),
+        new(Str, "str", "x", Arg1, Types, "Converts a value to a string").pure(),
+        new(List, "list", "...", Iter, Types, "Creates a list"),
+        new(Set, "set", "...", Iter, Types, "Creates a set"),
+        new(Dict, "dict", "...", Iter, Types, "Creates a dict"),
+        new(Heap, "heap", "...", Iter, Types, "Creates a min-heap"),
+        new(SortedSet, "sorted_set", "...", Iter, Types, "Creates a sorted set"),
+        new(SortedDict, "sorted_dict", "...", Iter, Types, "Creates a sorted dict"),
+        new(Vector, "vector", "...", Unique, Types, "Creates a vector"),
+        new(View, "view", "x, start?, stop?", Arg1To3, Types, "Creates a view over a slice of an iterable").pure(),
+        new(PList, "plist", "...", Iter, Types, "Creates a persistent list"),
+        new(PDict, "pdict", "...", Iter, Types, "Creates a persistent dict"),
+        new(Matrix, "matrix", "rows, cols?, fill?", Arg1To3, Types, "Creates a matrix").pure(),
+        new(BitSet, "bitset", "size, bits?", Arg1To2, Types, "Creates a fixed size set of bits").pure(),
+        new(Dsu, "dsu", "n", Arg1, Types, "Creates a disjoint set union").pure(),
+        new(Function, "function", "", Invalid, Types, "The type of functions"),
+        new(Iterable, "iterable", "", Invalid, Types, "The type of iterables"),
+        new(Repr, "repr", "x", Arg1, Types, "Returns the full representation of a value").pure(),
+        new(Eval, "eval", "expr", Arg1, Types, "Compiles and evaluates a Cordy expression"),
+        new(TypeOf, "typeof", "x", Arg1, Types, "Returns the type of a value").pure(),
+        new(Doc, "doc", "f", Arg1, Types, "Returns the doc comment of a function").pure(),
+        new(Help, "help", "f?", Arg0To1, Types, "Prints this index, or the signature and summary of a function"),
 
         // operator
         op1(OperatorSub, "(-)", "x", Arg1To2),
@@ -344,70 +686,135 @@ const fn load_native_functions() -> [NativeFunctionInfo; NativeFunction::total()
         op2(OperatorNotEqual, "(!=)"),
 
         // strings
-        new(ToLower, "to_lower", "x", Arg1),
-        new(ToUpper, "to_upper", "x", Arg1),
-        new(Replace, "replace", "pattern, replacer, x", Arg3),
-        new(Search, "search", "pattern, x", Arg2),
-        new(Trim, "trim", "x", Arg1),
-        new(Split, "split", "pattern, x", Arg2),
-        new(Join, "join", "joiner, iter", Arg2),
-        new(Char, "char", "x", Arg1),
-        new(Ord, "ord", "x", Arg1),
-        new(Hex, "hex", "x", Arg1),
-        new(Bin, "bin", "x", Arg1),
-
-        new(Len, "len", "x", Arg1),
-        new(Range, "range", "start, stop, step", Arg1To3),
-        new(Enumerate, "enumerate", "iter", Arg1),
-        new(Sum, "sum", "...", IterNonEmpty),
-        new(Min, "min", "...", IterNonEmpty),
-        new(Max, "max", "...", IterNonEmpty),
-        new(MinBy, "min_by", "key_or_cmp, iter", Arg2),
-        new(MaxBy, "max_by", "key_or_cmp, iter", Arg2),
-        new(Map, "map", "f, iter", Arg2),
-        new(Filter, "filter", "f, iter", Arg2),
-        new(FlatMap, "flat_map", "f, iter", Arg2),
-        new(Concat, "concat", "iter", Arg1),
-        new(Zip, "zip", "...", IterNonEmpty),
-        new(Reduce, "reduce", "f, iter", Arg2),
-        new(Sort, "sort", "...", IterNonEmpty),
-        new(SortBy, "sort_by", "f, iter", Arg2),
-        new(GroupBy, "group_by", "f, iter", Arg2),
-        new(Reverse, "reverse", "...", IterNonEmpty),
-        new(Permutations, "permutations", "n, iter", Arg2),
-        new(Combinations, "combinations", "n, iter", Arg2),
-        new(Any, "any", "f, it", Arg2),
-        new(All, "all", "f, it", Arg2),
-        new(Memoize, "memoize", "f", Arg1),
-        new(Union, "union", "other, self", Arg2),
-        new(Intersect, "intersect", "other, self", Arg2),
-        new(Difference, "difference", "other, self", Arg2),
-
-        new(Peek, "peek", "collection", Arg1),
-        new(Pop, "pop", "collection", Arg1),
-        new(PopFront, "pop_front", "collection", Arg1),
-        new(Push, "push", "value, collection", Arg2),
-        new(PushFront, "push_front", "value, collection", Arg2),
-        new(Insert, "insert", "index, value, collection", Arg3),
-        new(Remove, "remove", "param, collection", Arg2),
-        new(Clear, "clear", "collection", Arg1),
-        new(Find, "find", "predicate, collection", Arg2),
-        new(RightFind, "rfind", "predicate, collection", Arg2),
-        new(IndexOf, "index_of", "value_or_predicate, collection", Arg2),
-        new(RightIndexOf, "rindex_of", "value_or_predicate, collection", Arg2),
-        new(Default, "default", "value, dictionary", Arg2),
-        new(Keys, "keys", "dictionary", Arg1),
-        new(Values, "values", "dictionary", Arg1),
+        new(ToLower, "to_lower", "x", Arg1, Strings, "Converts a string to lowercase").pure(),
+        new(ToUpper, "to_upper", "x", Arg1, Strings, "Converts a string to uppercase").pure(),
+        new(Replace, "replace", "pattern, replacer, x", Arg3, Strings, "Replaces all matches of a pattern in a string"),
+        new(Search, "search", "pattern, x", Arg2, Strings, "Returns all matches of a pattern in a string").pure(),
+        new(Scan, "scan", "format, x", Arg2, Strings, "Matches a string against a format, returning each token").pure(),
+        new(Trim, "trim", "x", Arg1, Strings, "Removes leading and trailing whitespace").pure(),
+        new(Split, "split", "pattern, x", Arg2, Strings, "Splits a string by a pattern").pure(),
+        new(Join, "join", "joiner, iter", Arg2, Strings, "Joins an iterable into a string with a separator").pure(),
+        new(Template, "template", "x, dict", Arg2, Strings, "Substitutes named values from a dict into a string").pure(),
+        new(Char, "char", "x", Arg1, Strings, "Converts an int to a character").pure(),
+        new(Ord, "ord", "x", Arg1, Strings, "Converts a character to an int").pure(),
+        new(Hex, "hex", "x", Arg1, Strings, "Converts an int to a hexadecimal string").pure(),
+        new(Bin, "bin", "x", Arg1, Strings, "Converts an int to a binary string").pure(),
+        new(IsDigit, "is_digit", "x", Arg1, Strings, "Returns true if every character is a digit").pure(),
+        new(IsAlpha, "is_alpha", "x", Arg1, Strings, "Returns true if every character is alphabetic").pure(),
+        new(IsAlnum, "is_alnum", "x", Arg1, Strings, "Returns true if every character is alphanumeric").pure(),
+        new(IsSpace, "is_space", "x", Arg1, Strings, "Returns true if every character is whitespace").pure(),
+        new(IsUpper, "is_upper", "x", Arg1, Strings, "Returns true if every character is uppercase").pure(),
+        new(IsLower, "is_lower", "x", Arg1, Strings, "Returns true if every character is lowercase").pure(),
+        new(CsvDecode, "csv_decode", "x, delim?", Arg1To2, Strings, "Parses CSV text into a list of rows").pure(),
+        new(CsvEncode, "csv_encode", "rows, delim?", Arg1To2, Strings, "Formats a list of rows as CSV text").pure(),
+        new(TomlDecode, "toml_decode", "x", Arg1, Strings, "Parses TOML text into a dict").pure(),
+
+        new(Len, "len", "x", Arg1, Collections, "Returns the length of an iterable").pure(),
+        new(IsEmpty, "is_empty", "x", Arg1, Collections, "Returns true if an iterable has no elements").pure(),
+        new(CountAll, "count_all", "iter", Arg1, Collections, "Counts the elements of an iterable by iterating it").pure(),
+        new(Range, "range", "start, stop, step", Arg1To3, Collections, "Returns a range of ints").pure(),
+        new(Enumerate, "enumerate", "iter", Arg1, Collections, "Pairs each element of an iterable with its index").pure(),
+        new(Cycle, "cycle", "iter", Arg1, Collections, "Repeats an iterable forever").pure(),
+        new(Repeat, "repeat", "x, n?", Arg1To2, Collections, "Repeats a value, forever or a number of times").pure(),
+        new(Chain, "chain", "...", Iter, Collections, "Chains iterables together, lazily"),
+        new(Take, "take", "n, iter", Arg2, Collections, "Returns the first n elements of an iterable").pure(),
+        new(First, "first", "iter", Arg1, Collections, "Returns the first element of an iterable").pure(),
+        new(Last, "last", "iter", Arg1, Collections, "Returns the last element of an iterable").pure(),
+        new(Nth, "nth", "n, iter", Arg2, Collections, "Returns the nth element of an iterable").pure(),
+        new(Single, "single", "iter", Arg1, Collections, "Returns the only element of an iterable").pure(),
+        new(Sum, "sum", "...", IterNonEmpty, Collections, "Returns the sum of the elements"),
+        new(Min, "min", "...", IterNonEmpty, Collections, "Returns the minimum of the elements"),
+        new(Max, "max", "...", IterNonEmpty, Collections, "Returns the maximum of the elements"),
+        new(Prod, "prod", "...", IterNonEmpty, Collections, "Returns the product of the elements"),
+        new(CumSum, "cumsum", "iter", Arg1, Collections, "Returns the running sums of an iterable").pure(),
+        new(MinMax, "minmax", "...", IterNonEmpty, Collections, "Returns the minimum and maximum of the elements"),
+        new(MinBy, "min_by", "key_or_cmp, iter", Arg2, Collections, "Returns the minimum element by a key or comparator"),
+        new(MaxBy, "max_by", "key_or_cmp, iter", Arg2, Collections, "Returns the maximum element by a key or comparator"),
+        new(Map, "map", "f, iter", Arg2, Collections, "Applies a function to each element"),
+        new(Filter, "filter", "f, iter", Arg2, Collections, "Keeps the elements which match a predicate"),
+        new(Partition, "partition", "f, iter", Arg2, Collections, "Splits elements by whether they match a predicate"),
+        new(Span, "span", "f, iter", Arg2, Collections, "Splits an iterable at the first element not matching a predicate"),
+        new(TakeWhile, "take_while", "f, iter", Arg2, Collections, "Takes elements while they match a predicate"),
+        new(DropWhile, "drop_while", "f, iter", Arg2, Collections, "Drops elements while they match a predicate"),
+        new(Count, "count", "value_or_predicate, iter", Arg2, Collections, "Counts the elements equal to a value, or matching a predicate"),
+        new(Tally, "tally", "f, iter", Arg2, Collections, "Counts the elements which do, and do not, match a predicate"),
+        new(FlatMap, "flat_map", "f, iter", Arg2, Collections, "Maps each element to an iterable, and flattens the result"),
+        new(Concat, "concat", "iter", Arg1, Collections, "Flattens an iterable of iterables by one level").pure(),
+        new(Flatten, "flatten", "iter, depth?", Arg1To2, Collections, "Flattens nested iterables, up to an optional depth").pure(),
+        new(Distinct, "unique", "iter", Arg1, Collections, "Removes duplicate elements").pure(),
+        new(UniqueBy, "unique_by", "f, iter", Arg2, Collections, "Removes elements with duplicate keys"),
+        new(Dedup, "dedup", "iter", Arg1, Collections, "Removes consecutive duplicate elements").pure(),
+        new(Zip, "zip", "...", IterNonEmpty, Collections, "Pairs up the elements of multiple iterables"),
+        new(Unzip, "unzip", "iter", Arg1, Collections, "Splits an iterable of rows into a vector of columns").pure(),
+        new(Reduce, "reduce", "f, iter", Arg2, Collections, "Combines the elements with a binary function"),
+        new(Sort, "sort", "...", IterNonEmpty, Collections, "Returns the elements in sorted order"),
+        new(SortBy, "sort_by", "f, iter", Arg2, Collections, "Sorts elements by a key or comparator"),
+        new(GroupBy, "group_by", "f, iter", Arg2, Collections, "Groups elements by a key function, or into chunks"),
+        new(IndexBy, "index_by", "f, iter, strict?", Arg2To3, Collections, "Builds a dict of elements keyed by a function"),
+        new(Invert, "invert", "dict, strict?", Arg1To2, Collections, "Swaps the keys and values of a dict").pure(),
+        new(Reverse, "reverse", "...", IterNonEmpty, Collections, "Returns the elements in reverse order"),
+        new(Permutations, "permutations", "n, iter", Arg2, Collections, "Returns all permutations of length n").pure(),
+        new(Combinations, "combinations", "n, iter", Arg2, Collections, "Returns all combinations of length n").pure(),
+        new(Any, "any", "f, it", Arg2, Collections, "Returns true if any element matches a predicate"),
+        new(All, "all", "f, it", Arg2, Collections, "Returns true if all elements match a predicate"),
+        new(Memoize, "memoize", "f, max_size?", Arg1To2, Functions, "Caches the results of a function").pure(),
+        new(CacheStats, "cache_stats", "f", Arg1, Functions, "Returns the cache statistics of a memoized function").pure(),
+        new(CacheClear, "cache_clear", "f", Arg1, Functions, "Clears the cache of a memoized function"),
+        new(Compose, "compose", "...", Unique, Functions, "Composes functions, right to left"),
+        hidden(Composed, "compose", "f, g, x", Arg3, Functions),
+        new(Identity, "identity", "x", Arg1, Functions, "Returns its argument").pure(),
+        new(Const, "const_", "value, x", Arg2, Functions, "Returns a function which always returns a value").pure(),
+        new(Flip, "flip", "f, a, b", Arg3, Functions, "Calls a function with its two arguments swapped"),
+        new(Curry, "curry", "f, a, b", Arg3, Functions, "Calls a function with a vector of two arguments"),
+        new(Union, "union", "other, self, dsu?", Arg2To3, Collections, "Returns the union of two sets, or joins two groups of a dsu"),
+        new(Intersect, "intersect", "other, self", Arg2, Collections, "Returns the intersection of two sets"),
+        new(Difference, "difference", "other, self", Arg2, Collections, "Returns the difference of two sets"),
+        new(Connected, "connected", "a, b, dsu", Arg3, Collections, "Returns true if two elements of a dsu are in the same group").pure(),
+        new(Groups, "groups", "dsu", Arg1, Collections, "Returns the groups of a dsu").pure(),
+        new(GetBit, "get_bit", "index, bitset", Arg2, Collections, "Returns true if a bit of a bitset is set").pure(),
+        new(SetBit, "set_bit", "index, bitset", Arg2, Collections, "Sets a bit of a bitset"),
+        new(FlipBit, "flip_bit", "index, bitset", Arg2, Collections, "Flips a bit of a bitset"),
+
+        new(Peek, "peek", "collection", Arg1, Collections, "Returns the first value of a collection").pure(),
+        new(Pop, "pop", "collection", Arg1, Collections, "Removes and returns the last value of a collection"),
+        new(PopFront, "pop_front", "collection", Arg1, Collections, "Removes and returns the first value of a collection"),
+        new(Push, "push", "value, collection", Arg2, Collections, "Inserts a value at the end of a collection"),
+        new(PushFront, "push_front", "value, collection", Arg2, Collections, "Inserts a value at the front of a collection"),
+        new(ExtendFront, "extend_front", "values, collection", Arg2, Collections, "Inserts each value at the front of a collection, in order"),
+        new(Insert, "insert", "index, value, collection", Arg3, Collections, "Inserts a value at an index, or a key into a dict"),
+        new(Remove, "remove", "param, collection", Arg2, Collections, "Removes an index, value, or key from a collection"),
+        new(SwapRemove, "swap_remove", "index, collection", Arg2, Collections, "Removes an index, replacing it with the last value"),
+        new(Rotate, "rotate", "n, collection", Arg2, Collections, "Rotates a list to the right by n"),
+        new(Clear, "clear", "collection", Arg1, Collections, "Removes all values from a collection"),
+        new(ListWithCapacity, "list_with_capacity", "n", Arg1, Collections, "Creates an empty list with reserved capacity").pure(),
+        new(DictWithCapacity, "dict_with_capacity", "n", Arg1, Collections, "Creates an empty dict with reserved capacity").pure(),
+        new(Reserve, "reserve", "collection, n", Arg2, Collections, "Reserves capacity in a collection"),
+        new(Between, "between", "lo, hi, collection", Arg3, Collections, "Returns the values of a sorted collection within a range").pure(),
+        new(Floor, "floor", "value, collection", Arg2, Collections, "Returns the largest value of a sorted collection not above a value").pure(),
+        new(Ceiling, "ceiling", "value, collection", Arg2, Collections, "Returns the smallest value of a sorted collection not below a value").pure(),
+        new(Conj, "conj", "value, collection", Arg2, Collections, "Returns a persistent collection with a value appended").pure(),
+        new(Assoc, "assoc", "key, value, collection", Arg3, Collections, "Returns a persistent collection with a key set").pure(),
+        new(Dissoc, "dissoc", "key, collection", Arg2, Collections, "Returns a persistent dict with a key removed").pure(),
+        new(Find, "find", "predicate, collection", Arg2, Collections, "Returns the first value equal to a value, or matching a predicate"),
+        new(RightFind, "rfind", "predicate, collection", Arg2, Collections, "Returns the last value equal to a value, or matching a predicate"),
+        new(IndexOf, "index_of", "value_or_predicate, collection, start?", Arg2To3, Collections, "Returns the first index of a value, or matching a predicate"),
+        new(RightIndexOf, "rindex_of", "value_or_predicate, collection", Arg2, Collections, "Returns the last index of a value, or matching a predicate"),
+        new(Contains, "contains", "value, collection", Arg2, Collections, "Returns true if a collection contains a value").pure(),
+        new(Default, "default", "value, dictionary", Arg2, Collections, "Sets the default value of a dict"),
+        new(Keys, "keys", "dictionary", Arg1, Collections, "Returns the keys of a dict").pure(),
+        new(Values, "values", "dictionary", Arg1, Collections, "Returns the values of a dict").pure(),
 
         // math
-        new(Abs, "abs", "x", Arg1),
-        new(Sqrt, "sqrt", "x", Arg1),
-        new(Gcd, "gcd", "...", IterNonEmpty),
-        new(Lcm, "lcm", "...", IterNonEmpty),
-        new(CountOnes, "count_ones", "x", Arg1),
-        new(CountZeros, "count_zeros", "x", Arg1),
-        new(Real, "real", "x", Arg1),
-        new(Imag, "imag", "x", Arg1),
+        new(Abs, "abs", "x", Arg1, Math, "Returns the absolute value of a number").pure(),
+        new(Sqrt, "sqrt", "x", Arg1, Math, "Returns the integer square root of an int").pure(),
+        new(Gcd, "gcd", "...", IterNonEmpty, Math, "Returns the greatest common divisor"),
+        new(Lcm, "lcm", "...", IterNonEmpty, Math, "Returns the lowest common multiple"),
+        new(CountOnes, "count_ones", "x", Arg1, Math, "Counts the one bits of an int").pure(),
+        new(CountZeros, "count_zeros", "x", Arg1, Math, "Counts the zero bits of an int").pure(),
+        new(Real, "real", "x", Arg1, Math, "Returns the real part of a complex number").pure(),
+        new(Imag, "imag", "x", Arg1, Math, "Returns the imaginary part of a complex number").pure(),
+        new(Transpose, "transpose", "x", Arg1, Math, "Transposes a matrix").pure(),
+        new(MatMul, "matmul", "lhs, rhs", Arg2, Math, "Multiplies two matrices").pure(),
     ]
 }
 
@@ -424,6 +831,7 @@ const fn load_native_functions() -> [NativeFunctionInfo; NativeFunction::total()
 ///
 /// - `Arg<N>` is partial on `[0, N)` arguments, evaluated on `N` arguments, and errors on `> N` arguments.
 /// - `Arg<N>To<M>` is partial on `[0, N)` arguments, evaluated on `[N, M)` arguments, and errors on `> M` arguments.
+///   Note that a partial `Arg2To3` function, like `Arg2`, can only be completed with exactly one more argument.
 ///
 /// We also define a few unique types which are never partial, but have unique invocation patterns:
 ///
@@ -441,6 +849,7 @@ pub enum Argument {
     Arg1To2,
     Arg1To3,
     Arg2,
+    Arg2To3,
     Arg3,
     Unique,
     Iter,
@@ -453,11 +862,22 @@ impl Argument {
     fn min_nargs(self) -> u32 {
         match self {
             Arg1 | Arg1To2 | Arg1To3 => 1,
-            Arg2 => 2,
+            Arg2 | Arg2To3 => 2,
             Arg3 => 3,
             _ => 0,
         }
     }
+
+    /// Returns the maximum amount of arguments this function can be evaluated with, or `None` if there is no maximum
+    fn max_nargs(self) -> Option<u32> {
+        match self {
+            Arg0 | Invalid => Some(0),
+            Arg0To1 | Arg1 => Some(1),
+            Arg1To2 | Arg2 => Some(2),
+            Arg1To3 | Arg2To3 | Arg3 => Some(3),
+            Unique | Iter | IterNonEmpty => None,
+        }
+    }
 }
 
 /// The data structure representing a partially evaluated function.
@@ -507,6 +927,9 @@ impl PartialArgument {
 ///
 /// Note that `InvokeArg0` can not, definitionally, have partially evaluated arguments, as it would no-op.
 /// We do still have to handle that case, however, since it is technically legal code (if contrived).
+///
+/// Any error raised by invoking the function is returned directly from `invoke()`, so native functions should propagate it with `?` as soon as it is raised,
+/// rather than continuing to invoke the function. `InvokeArg1::for_each()` handles this when invoking a function over an iterable, and additionally allows stopping early.
 #[derive(Debug, Clone)]
 pub enum InvokeArg0 {
     Noop(ValuePtr),
@@ -541,7 +964,7 @@ impl InvokeArg0 {
             Type::Function | Type::Closure | Type::PartialFunction | Type::StructType | Type::Memoized => Ok(InvokeArg0::User(f)),
             Type::NativeFunction => match f.as_native().info().arg {
                 Arg0 | Arg0To1 | Unique | Iter => Ok(InvokeArg0::Native(f.as_native())),
-                Arg1 | Arg1To2 | Arg1To3 | Arg2 | Arg3 => Ok(InvokeArg0::Noop(f)), // Partial with zero arg = no-op
+                Arg1 | Arg1To2 | Arg1To3 | Arg2 | Arg2To3 | Arg3 => Ok(InvokeArg0::Noop(f)), // Partial with zero arg = no-op
                 IterNonEmpty => IncorrectArgumentsNativeFunction(f.as_native(), 0).err(),
                 Invalid => ValueIsNotFunctionEvaluable(f).err(),
             },
@@ -553,7 +976,7 @@ impl InvokeArg0 {
     fn invoke<VM : VirtualInterface>(self, vm: &mut VM) -> ValueResult {
         match self {
             InvokeArg0::Noop(f) => f.ok(),
-            InvokeArg0::User(f) => vm.invoke_func0(f),
+            InvokeArg0::User(f) => vm.call(f, []),
             InvokeArg0::Native(f) => invoke_arg0(f, vm),
         }
     }
@@ -566,7 +989,7 @@ impl InvokeArg1 {
             Type::NativeFunction => match f.as_native().info().arg {
                 Arg0To1 | Arg1 | Arg1To2 | Arg1To3 | Unique => Ok(InvokeArg1::Native(f.as_native())),
                 Iter | IterNonEmpty => Ok(InvokeArg1::NativeVar(f.as_native())),
-                Arg2 => Ok(InvokeArg1::Arg2Par1(f.as_native())),
+                Arg2 | Arg2To3 => Ok(InvokeArg1::Arg2Par1(f.as_native())),
                 Arg3 => Ok(InvokeArg1::Arg3Par1(f.as_native())),
                 Arg0 => IncorrectArgumentsNativeFunction(f.as_native(), 1).err(),
                 Invalid => ValueIsNotFunctionEvaluable(f).err(),
@@ -585,7 +1008,7 @@ impl InvokeArg1 {
 
     fn invoke<VM: VirtualInterface>(&self, arg: ValuePtr, vm: &mut VM) -> ValueResult {
         match self {
-            InvokeArg1::User(f) => vm.invoke_func1(f.clone(), arg),
+            InvokeArg1::User(f) => vm.call(f.clone(), [arg]),
             InvokeArg1::Native(f) => invoke_arg1(*f, arg, vm),
             InvokeArg1::NativePar1(f, a1) => invoke_arg2(*f, a1.clone(), arg, vm),
             InvokeArg1::NativePar2(f, a1, a2) => invoke_arg3(*f, a1.clone(), a2.clone(), arg, vm),
@@ -595,6 +1018,20 @@ impl InvokeArg1 {
             InvokeArg1::Arg3Par2(f, a1) => PartialArgument::Arg3Par2(a1.clone(), arg).to_value(*f),
         }
     }
+
+    /// Invokes this function with each element of `iter` in turn, and passes both the element and the result to `f`.
+    ///
+    /// This stops as soon as `f` returns `ControlFlow::Break`, without invoking the function on any further elements, and returns the value it broke with.
+    /// If invoking the function raises an error, it is returned immediately. Otherwise, this returns `None` once `iter` is exhausted.
+    fn for_each<VM : VirtualInterface, B>(&self, iter: impl Iterator<Item=ValuePtr>, vm: &mut VM, mut f: impl FnMut(ValuePtr, ValuePtr) -> ControlFlow<B>) -> ErrorResult<Option<B>> {
+        for arg in iter {
+            let ret: ValuePtr = self.invoke(arg.clone(), vm)?;
+            if let ControlFlow::Break(b) = f(arg, ret) {
+                return Ok(Some(b))
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl InvokeArg2 {
@@ -602,7 +1039,7 @@ impl InvokeArg2 {
         match f.ty() {
             Type::Function | Type::Closure | Type::PartialFunction | Type::List | Type::Slice | Type::StructType | Type::GetField | Type::Memoized => Ok(InvokeArg2::User(f)),
             Type::NativeFunction => match f.as_native().info().arg {
-                Arg1To2 | Arg1To3 | Arg2 | Unique => Ok(InvokeArg2::Native(f.as_native())),
+                Arg1To2 | Arg1To3 | Arg2 | Arg2To3 | Unique => Ok(InvokeArg2::Native(f.as_native())),
                 Iter | IterNonEmpty => Ok(InvokeArg2::NativeVar(f.as_native())),
                 Arg3 => Ok(InvokeArg2::Arg3Par1(f.as_native())),
                 Arg0 | Arg0To1 | Arg1 => IncorrectArgumentsNativeFunction(f.as_native(), 2).err(),
@@ -622,20 +1059,24 @@ impl InvokeArg2 {
 
     fn invoke<VM : VirtualInterface>(&self, arg1: ValuePtr, arg2: ValuePtr, vm: &mut VM) -> ValueResult {
         match self {
-            InvokeArg2::User(f) => vm.invoke_func2(f.clone(), arg1, arg2),
+            InvokeArg2::User(f) => vm.call(f.clone(), [arg1, arg2]),
             InvokeArg2::Native(f) => invoke_arg2(*f, arg1, arg2, vm),
             InvokeArg2::NativePar1(f, a1) => invoke_arg3(*f, a1.clone(), arg1, arg2, vm),
             InvokeArg2::NativeVar(f) => invoke_var(*f, vec![arg1, arg2].into_iter(), vm),
             InvokeArg2::Arg3Par1(f) => PartialArgument::Arg3Par2(arg1, arg2).to_value(*f),
         }
     }
+
+    /// Invokes this function as a comparator, which must return an `int` that is negative, zero, or positive if `a` is less than, equal to, or greater than `b`.
+    fn compare<VM : VirtualInterface>(&self, a: &ValuePtr, b: &ValuePtr, vm: &mut VM) -> ErrorResult<Ordering> {
+        Ok(self.invoke(a.clone(), b.clone(), vm)?.check_int()?.as_int().cmp(&0))
+    }
 }
 
 
 
 /// Invokes a function with arguments laid out on the stack.
 pub fn invoke_stack<VM : VirtualInterface>(f: NativeFunction, nargs: u32, vm: &mut VM) -> ValueResult {
-    trace::trace_interpreter!("core::invoke_stack f={}, nargs={}", f.name(), nargs);
     match f.info().arg {
         Arg0 => match nargs {
             0 => invoke_arg0(f, vm),
@@ -702,6 +1143,25 @@ pub fn invoke_stack<VM : VirtualInterface>(f: NativeFunction, nargs: u32, vm: &m
             },
             _ => IncorrectArgumentsNativeFunction(f, nargs).err()
         },
+        Arg2To3 => match nargs {
+            0 => f.to_value().ok(),
+            1 => {
+                let a1: ValuePtr = vm.pop();
+                PartialArgument::Arg2Par1(a1).to_value(f)
+            }
+            2 => {
+                let a2: ValuePtr = vm.pop();
+                let a1: ValuePtr = vm.pop();
+                invoke_arg2(f, a1, a2, vm)
+            },
+            3 => {
+                let a3: ValuePtr = vm.pop();
+                let a2: ValuePtr = vm.pop();
+                let a1: ValuePtr = vm.pop();
+                invoke_arg3(f, a1, a2, a3, vm)
+            },
+            _ => IncorrectArgumentsNativeFunction(f, nargs).err()
+        },
         Arg3 => match nargs {
             0 => f.to_value().ok(),
             1 => {
@@ -761,7 +1221,6 @@ pub fn invoke_stack<VM : VirtualInterface>(f: NativeFunction, nargs: u32, vm: &m
 
 /// Invokes a partial native function with partial arguments held in the unique `PartialArgument` structure, and additional arguments present on the stack.
 pub fn invoke_partial<VM : VirtualInterface>(f: NativeFunction, partial: PartialArgument, nargs: u32, vm: &mut VM) -> ValueResult {
-    trace::trace_interpreter!("core::invoke_partial f={}, nargs={}", f.name(), nargs);
     match partial {
         PartialArgument::Arg2Par1(a1) => match nargs {
             0 => PartialArgument::Arg2Par1(a1).to_value(f),
@@ -797,32 +1256,77 @@ pub fn invoke_partial<VM : VirtualInterface>(f: NativeFunction, partial: Partial
 
 
 fn invoke_arg0<VM : VirtualInterface>(f: NativeFunction, vm: &mut VM) -> ValueResult {
+    check_capability(vm, f)?;
     match f {
         Read => vm.read().to_value().ok(),
         ReadLine => vm.read_line().to_value().ok(),
+        ReadLines => vm.read().lines().map(|line| line.to_value()).to_list().ok(),
         Print => {
             vm.println0();
             ValuePtr::nil().ok()
         },
+        EPrint => ValuePtr::nil().ok(),
+        EPrintLn => {
+            vm.eprintln0();
+            ValuePtr::nil().ok()
+        },
         Env => vm.get_envs().ok(),
         Argv => vm.get_args().ok(),
+        TermSize => terminal::term_size(vm),
+        ClearScreen => terminal::clear_screen(vm),
+        ReadKey => terminal::read_key(vm),
+        Stopwatch => timing::stopwatch(),
+        LogLevel => logging::log_level(vm),
+        Uuid4 => random::uuid4(vm),
+        HomeDir => paths::home_dir(),
+        TempDir => paths::temp_dir(),
+        MemStats => memory::memstats(vm),
+        Help => {
+            vm.println(NativeFunction::help_index());
+            ValuePtr::nil().ok()
+        },
 
         List => VecDeque::new().to_value().ok(),
         Set => IndexSet::with_hasher(FxBuildHasher::default()).to_value().ok(),
         Dict => IndexMap::with_hasher(FxBuildHasher::default()).to_value().ok(),
         Heap => BinaryHeap::new().to_value().ok(),
+        SortedSet => SortedSetImpl::default().to_value().ok(),
+        SortedDict => SortedDictImpl::default().to_value().ok(),
+        PList => PListImpl::default().to_value().ok(),
+        PDict => PDictImpl::default().to_value().ok(),
         Vector => Vec::new().to_value().ok(),
+        Compose => functional::compose(std::iter::empty()),
+        Chain => collections::chain(std::iter::empty()),
 
         _ => panic!("core::invoke_arg0() not supported for {:?}", f),
     }
 }
 
 fn invoke_arg1<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, vm: &mut VM) -> ValueResult {
+    check_capability(vm, f)?;
     match f {
         Print => {
             vm.println(a1.to_str());
             ValuePtr::nil().ok()
         },
+        EPrint => {
+            vm.eprint(a1.to_str());
+            ValuePtr::nil().ok()
+        },
+        EPrintLn => {
+            vm.eprintln(a1.to_str());
+            ValuePtr::nil().ok()
+        },
+        WriteBytes => {
+            let bytes: Vec<u8> = a1.to_iter()?
+                .map(|b| {
+                    let b: i64 = b.check_int()?.as_int();
+                    u8::try_from(b).or_else(|_| ValueErrorInvalidByte(b).err())
+                })
+                .collect::<ErrorResult<Vec<u8>>>()?;
+            vm.write_bytes(&bytes);
+            ValuePtr::nil().ok()
+        },
         ReadText => {
             let path = a1.check_str()?;
             match fs::read_to_string::<&str>(path.as_str().borrow_const().as_ref()) {
@@ -831,19 +1335,84 @@ fn invoke_arg1<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, vm: &mut
             }
         },
         Env => vm.get_env(a1.check_str()?.as_str().borrow_const()).ok(),
+        Argparse => argparse::argparse(vm, a1),
+        Marshal => marshal::marshal(a1),
+        Unmarshal => marshal::unmarshal(vm, a1),
+        AtExit => {
+            if !a1.is_evaluable() {
+                return TypeErrorArgMustBeFunction(a1).err()
+            }
+            vm.at_exit(a1);
+            ValuePtr::nil().ok()
+        },
+        Input => {
+            vm.print(a1.to_str());
+            vm.flush();
+            vm.read_line().to_value().ok()
+        },
+        DirName => paths::dirname(a1),
+        BaseName => paths::basename(a1),
+        Extension => paths::extension(a1),
+        Absolute => paths::absolute(a1),
+        Normalize => paths::normalize(a1),
+        ListDir => paths::list_dir(a1),
+        Walk => paths::walk(a1),
+        TimeIt => timing::timeit(vm, a1, None),
+        Lap => timing::lap(a1),
+        Elapsed => timing::elapsed(a1),
+        Progress => timing::progress(a1, None),
+        Task => timing::task(a1),
+        SleepAsync => timing::sleep_async(vm, a1),
+        LogDebug => logging::log(vm, crate::vm::LogLevel::Debug, a1, None),
+        LogInfo => logging::log(vm, crate::vm::LogLevel::Info, a1, None),
+        LogWarn => logging::log(vm, crate::vm::LogLevel::Warn, a1, None),
+        LogError => logging::log(vm, crate::vm::LogLevel::Error, a1, None),
+        LogLevel => logging::set_log_level(vm, a1),
+        RandBytes => random::rand_bytes(vm, a1),
+        RandHex => random::rand_hex(vm, a1),
+        TcpListen => net::tcp_listen(vm, a1),
+        Accept => net::accept(vm, a1),
+        RecvLine => match a1.is_coprocess() {
+            true => process::recv_line(a1),
+            false => net::recv_line(a1),
+        },
+        Close => close(a1),
+        LocalAddr => net::local_addr(a1),
+        HttpGetAsync => net::http_get_async(vm, a1),
+        Coprocess => process::coprocess(vm, a1, None),
+        DbOpen => database::open(vm, a1),
+        Weak => memory::weak(a1),
+        Get => memory::get(a1),
+        Glob => paths::glob(a1),
+        TryInt => math::try_convert_to_int(a1),
+        AssertErr => assertions::assert_err(vm, a1),
 
         Bool => a1.to_bool().to_value().ok(),
         Int => math::convert_to_int(a1, ValueOption::none()),
+        Fraction => math::convert_to_fraction(a1, 1i64.to_value()),
         Str => a1.to_str().to_value().ok(),
         Vector => if a1.is_precise_complex() {  // Handle `a + bi . vector` as a special case here
             let it = a1.as_precise_complex().value.inner;
             (it.re.to_value(), it.im.to_value()).to_value().ok()
+        } else if a1.is_precise_fraction() { // And `fraction(a, b) . vector` as `(a, b)`
+            let it = a1.as_precise_fraction().value.inner;
+            (it.numer().to_value(), it.denom().to_value()).to_value().ok()
         } else {
             a1.to_iter()?.to_vector().ok()
         },
+        View => collections::view(a1, ValuePtr::nil(), ValuePtr::nil()),
+        Matrix => matrix::from_rows(a1),
+        BitSet => bitset::create(a1, None),
+        Dsu => dsu::create(a1),
+        Groups => dsu::groups(a1),
         Repr => a1.to_repr_str().to_value().ok(),
         Eval => vm.invoke_eval(a1.check_str()?.as_str().borrow_const()),
         TypeOf => type_of(a1).ok(),
+        Doc => doc_of(a1).ok(),
+        Help => {
+            vm.println(help_of(a1));
+            ValuePtr::nil().ok()
+        },
 
         OperatorSub => operator::unary_sub(a1),
         OperatorUnaryNot => operator::unary_not(a1),
@@ -855,25 +1424,53 @@ fn invoke_arg1<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, vm: &mut
         Ord => strings::to_ord(a1),
         Hex => strings::to_hex(a1),
         Bin => strings::to_bin(a1),
-
-        Len => a1.len()?.to_value().ok(),
+        IsDigit => strings::is_all(a1, |c| c.is_ascii_digit()),
+        IsAlpha => strings::is_all(a1, char::is_alphabetic),
+        IsAlnum => strings::is_all(a1, char::is_alphanumeric),
+        IsSpace => strings::is_all(a1, char::is_whitespace),
+        IsUpper => strings::is_all(a1, char::is_uppercase),
+        IsLower => strings::is_all(a1, char::is_lowercase),
+        CsvDecode => csv::decode(a1, None),
+        CsvEncode => csv::encode(a1, None),
+        TomlDecode => toml::decode(a1),
+
+        Len => len_of(a1),
+        IsEmpty => collections::is_empty(a1),
+        CountAll => collections::count_all(a1),
         Range => ValuePtr::range(0, a1.check_int()?.as_int(), 1),
         Enumerate => ValuePtr::enumerate(a1).ok(),
+        Cycle => collections::cycle(a1),
+        Repeat => collections::repeat(a1, None),
+        First => collections::first(a1),
+        Last => collections::last(a1),
+        Single => collections::single(a1),
         Min => match a1.is_native() {
             true if a1.as_native() == Int => MIN_INT.to_value().ok(),
-            _ => collections::min(a1.to_iter()?),
+            _ => collections::min(vm, a1.to_iter()?),
         },
         Max => match a1.is_native() {
             true if a1.as_native() == Int => MAX_INT.to_value().ok(),
-            _ => collections::max(a1.to_iter()?),
+            _ => collections::max(vm, a1.to_iter()?),
         },
+        CumSum => collections::cumsum(a1),
         Concat => collections::flat_map(vm, None, a1),
-        Memoize => collections::create_memoized(a1),
+        Flatten => collections::flatten(a1, 1i64.to_value()),
+        Distinct => collections::unique(a1),
+        Dedup => collections::dedup(a1),
+        Invert => collections::invert(a1, true),
+        Unzip => collections::transpose(a1),
+        Memoize => collections::create_memoized(a1, None),
+        CacheStats => collections::cache_stats(a1),
+        CacheClear => collections::cache_clear(a1),
+        Compose => functional::compose(std::iter::once(a1)),
+        Identity => a1.ok(),
 
         Peek => collections::peek(a1),
         Pop => collections::pop(a1),
         PopFront => collections::pop_front(a1),
         Clear => collections::clear(a1),
+        ListWithCapacity => collections::list_with_capacity(a1),
+        DictWithCapacity => collections::dict_with_capacity(a1),
 
         Keys => collections::dict_keys(a1),
         Values => collections::dict_values(a1),
@@ -884,13 +1481,51 @@ fn invoke_arg1<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, vm: &mut
         CountZeros => math::count_zeros(a1),
         Real => math::get_real(a1),
         Imag => math::get_imag(a1),
+        Transpose => collections::transpose(a1),
 
         _ => panic!("core::invoke_arg1() not supported for {:?}", f),
     }
 }
 
 fn invoke_arg2<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, a2: ValuePtr, vm: &mut VM) -> ValueResult {
+    check_capability(vm, f)?;
     match f {
+        Color => terminal::color(a1, a2),
+        OnSignal => {
+            let name = a1.check_str()?;
+            let signal = match crate::vm::Signal::parse(name.as_str().borrow_const()) {
+                Some(signal) => signal,
+                None => return ValueErrorInvalidSignal(name.clone()).err(),
+            };
+            if !a2.is_nil() && !a2.is_evaluable() {
+                return TypeErrorArgMustBeFunction(a2).err()
+            }
+            vm.on_signal(signal, if a2.is_nil() { None } else { Some(a2) });
+            ValuePtr::nil().ok()
+        },
+        TimeIt => timing::timeit(vm, a1, Some(a2)),
+        Progress => timing::progress(a1, Some(a2)),
+        TcpConnect => net::tcp_connect(vm, a1, a2),
+        Send => net::send(a1, a2),
+        Coprocess => process::coprocess(vm, a1, Some(a2)),
+        DbExec => database::exec(a1, a2, None),
+        DbQuery => database::query(a1, a2, None),
+        GenInt => property::gen_int(a1, a2),
+        GenList => property::gen_list(a1, a2),
+        GenStr => property::gen_str(a1, a2),
+        Forall => property::forall(vm, a1, a2, None),
+        AssertEq => assertions::assert_eq(a1, a2),
+        OnDrop => memory::on_drop(vm, a1, a2),
+        SendLine => match a1.is_coprocess() {
+            true => process::send_line(a1, a2),
+            false => net::send_line(a1, a2),
+        },
+        LogDebug => logging::log(vm, crate::vm::LogLevel::Debug, a1, Some(a2)),
+        LogInfo => logging::log(vm, crate::vm::LogLevel::Info, a1, Some(a2)),
+        LogWarn => logging::log(vm, crate::vm::LogLevel::Warn, a1, Some(a2)),
+        LogError => logging::log(vm, crate::vm::LogLevel::Error, a1, Some(a2)),
+        Pack => pack::pack(a1, a2),
+        Unpack => pack::unpack(a1, a2),
         WriteText => {
             let path = a1.check_str()?;
             let text = a2.check_str()?;
@@ -900,6 +1535,11 @@ fn invoke_arg2<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, a2: Value
             }
         },
         Int => math::convert_to_int(a1, ValueOption::some(a2)),
+        ParseInt => parse_int(a1, a2),
+        Fraction => math::convert_to_fraction(a1, a2),
+        View => collections::view(a1, a2, ValuePtr::nil()),
+        Matrix => matrix::from_fill(a1, a2, 0i64.to_value()),
+        BitSet => bitset::create(a1, Some(a2)),
 
         OperatorSub => operator::binary_sub(a1, a2),
         OperatorMul => operator::binary_mul(a1, a2),
@@ -926,30 +1566,47 @@ fn invoke_arg2<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, a2: Value
         OperatorBitwiseAnd => operator::binary_bitwise_and(a1, a2),
         OperatorBitwiseOr => operator::binary_bitwise_or(a1, a2),
         OperatorBitwiseXor => operator::binary_bitwise_xor(a1, a2),
-        OperatorLessThan => (a1 < a2).to_value().ok(),
-        OperatorLessThanSwap => (a2 < a1).to_value().ok(),
-        OperatorLessThanEqual => (a1 <= a2).to_value().ok(),
-        OperatorLessThanEqualSwap => (a2 <= a1).to_value().ok(),
-        OperatorGreaterThan => (a1 > a2).to_value().ok(),
-        OperatorGreaterThanSwap => (a2 > a1).to_value().ok(),
-        OperatorGreaterThanEqual => (a1 >= a2).to_value().ok(),
-        OperatorGreaterThanEqualSwap => (a2 >= a1).to_value().ok(),
+        OperatorLessThan => operator::apply_binary(vm, BinaryOp::LessThan, a1, a2),
+        OperatorLessThanSwap => operator::apply_binary(vm, BinaryOp::LessThan, a2, a1),
+        OperatorLessThanEqual => operator::apply_binary(vm, BinaryOp::LessThanEqual, a1, a2),
+        OperatorLessThanEqualSwap => operator::apply_binary(vm, BinaryOp::LessThanEqual, a2, a1),
+        OperatorGreaterThan => operator::apply_binary(vm, BinaryOp::GreaterThan, a1, a2),
+        OperatorGreaterThanSwap => operator::apply_binary(vm, BinaryOp::GreaterThan, a2, a1),
+        OperatorGreaterThanEqual => operator::apply_binary(vm, BinaryOp::GreaterThanEqual, a1, a2),
+        OperatorGreaterThanEqualSwap => operator::apply_binary(vm, BinaryOp::GreaterThanEqual, a2, a1),
         OperatorEqual => (a1 == a2).to_value().ok(),
         OperatorNotEqual => (a1 != a2).to_value().ok(),
 
         Search => strings::search(a1, a2),
+        Scan => strings::scan(a1, a2),
         Split => strings::split(a1, a2),
         Join => strings::join(a1, a2),
+        Template => strings::template(a1, a2),
 
         Range => ValuePtr::range(a1.check_int()?.as_int(), a2.check_int()?.as_int(), 1),
         MinBy => collections::min_by(vm, a1, a2),
         MaxBy => collections::max_by(vm, a1, a2),
+        Repeat => collections::repeat(a1, Some(a2)),
+        CsvDecode => csv::decode(a1, Some(a2)),
+        CsvEncode => csv::encode(a1, Some(a2)),
+        Take => collections::take(a1, a2),
+        Flatten => collections::flatten(a1, a2),
+        UniqueBy => collections::unique_by(vm, a1, a2),
+        Nth => collections::nth(a1, a2),
         Map => collections::map(vm, a1, a2),
         Filter => collections::filter(vm, a1, a2),
+        Partition => collections::partition(vm, a1, a2),
+        Span => collections::span(vm, a1, a2),
+        TakeWhile => collections::take_while(vm, a1, a2),
+        DropWhile => collections::drop_while(vm, a1, a2),
+        Count => collections::count(vm, a1, a2),
+        Tally => collections::tally(vm, a1, a2),
         FlatMap => collections::flat_map(vm, Some(a1), a2),
         Reduce => collections::reduce(vm, a1, a2),
         SortBy => collections::sort_by(vm, a1, a2),
         GroupBy => collections::group_by(vm, a1, a2),
+        IndexBy => collections::index_by(vm, a1, a2, true),
+        Invert => collections::invert(a1, a2.to_bool()),
         Permutations => collections::permutations(a1, a2),
         Combinations => collections::combinations(a1, a2),
         Any => collections::any(vm, a1, a2),
@@ -957,31 +1614,65 @@ fn invoke_arg2<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, a2: Value
         Union => collections::set_union(a1, a2),
         Intersect => collections::set_intersect(a1, a2),
         Difference => collections::set_difference(a1, a2),
+        GetBit => bitset::get_bit(a1, a2),
+        SetBit => bitset::set_bit(a1, a2),
+        FlipBit => bitset::flip_bit(a1, a2),
 
         Push => collections::push(a1, a2),
         PushFront => collections::push_front(a1, a2),
+        ExtendFront => collections::extend_front(a1, a2),
+        Reserve => collections::reserve(a1, a2),
+        Floor => sorted::floor(a1, a2),
+        Ceiling => sorted::ceiling(a1, a2),
+        Conj => persistent::conj(a1, a2),
+        Dissoc => persistent::dissoc(a1, a2),
         Remove => collections::remove(a1, a2),
-        Find => collections::left_find(vm, a1, a2, false),
+        SwapRemove => collections::swap_remove(a1, a2),
+        Rotate => collections::rotate(a1, a2),
+        Find if a2.is_dsu() => dsu::find(a1, a2),
+        Find => collections::left_find(vm, a1, a2, 0, false),
         RightFind => collections::right_find(vm, a1, a2, false),
-        IndexOf => collections::left_find(vm, a1, a2, true),
+        IndexOf => collections::index_of(vm, a1, a2, None),
         RightIndexOf => collections::right_find(vm, a1, a2, true),
+        Contains => operator::binary_in(a1, a2, false),
         Default => collections::dict_set_default(a1, a2),
+        Memoize => collections::create_memoized(a1, Some(a2)),
+        Const => a1.ok(),
+
+        MatMul => matrix::matmul(a1, a2),
 
         _ => panic!("core::invoke_arg2() not supported for {:?}", f),
     }
 }
 
 fn invoke_arg3<VM : VirtualInterface>(f: NativeFunction, a1: ValuePtr, a2: ValuePtr, a3: ValuePtr, vm: &mut VM) -> ValueResult {
+    check_capability(vm, f)?;
     match f {
         Replace => strings::replace(vm, a1, a2, a3),
         Range => ValuePtr::range(a1.check_int()?.as_int(), a2.check_int()?.as_int(), a3.check_int()?.as_int()),
         Insert => collections::insert(a1, a2, a3),
+        Between => sorted::between(a1, a2, a3),
+        Assoc => persistent::assoc(a1, a2, a3),
+        Union => dsu::union(a1, a2, a3),
+        Connected => dsu::connected(a1, a2, a3),
+        Composed => functional::composed(vm, a1, a2, a3),
+        Flip => functional::flip(vm, a1, a2, a3),
+        Curry => functional::curry(vm, a1, a2, a3),
+        IndexBy => collections::index_by(vm, a1, a2, a3.to_bool()),
+        IndexOf => collections::index_of(vm, a1, a2, Some(a3)),
+        Matrix => matrix::from_fill(a1, a2, a3),
+        View => collections::view(a1, a2, a3),
+        DbExec => database::exec(a1, a2, Some(a3)),
+        DbQuery => database::query(a1, a2, Some(a3)),
+        Forall => property::forall(vm, a1, a2, Some(a3)),
+        ApproxEq => assertions::approx_eq(a1, a2, a3),
 
         _ => panic!("core::invoke_arg3() not supported for {:?}", f),
     }
 }
 
 fn invoke_var<VM : VirtualInterface, I : Iterator<Item=ValuePtr>>(f: NativeFunction, mut an: I, vm: &mut VM) -> ValueResult {
+    check_capability(vm, f)?;
     match f {
         Print => {
             vm.print(an.next().unwrap().to_str());
@@ -991,18 +1682,37 @@ fn invoke_var<VM : VirtualInterface, I : Iterator<Item=ValuePtr>>(f: NativeFunct
             vm.println0();
             ValuePtr::nil().ok()
         },
+        EPrint | EPrintLn => {
+            vm.eprint(an.next().unwrap().to_str());
+            for ai in an {
+                vm.eprint(format!(" {}", ai.to_str()));
+            }
+            if f == EPrintLn {
+                vm.eprintln0();
+            }
+            ValuePtr::nil().ok()
+        },
 
         List => an.to_list().ok(),
         Set => an.to_set().ok(),
         Dict => collections::collect_into_dict(an),
         Heap => an.to_heap().ok(),
+        SortedSet => sorted::collect_into_sorted_set(an),
+        SortedDict => sorted::collect_into_sorted_dict(an),
+        PList => an.collect::<PListImpl>().to_value().ok(),
+        PDict => persistent::collect_into_pdict(an),
         Vector => an.to_vector().ok(),
+        Compose => functional::compose(an),
 
         Sum => collections::sum(an),
-        Min => collections::min(an),
-        Max => collections::max(an),
+        Min => collections::min(vm, an),
+        Max => collections::max(vm, an),
+        Prod => collections::prod(an),
+        MinMax => collections::minmax(vm, an),
+        Chain => collections::chain(an),
+        PathJoin => paths::join(an),
         Zip => collections::zip(an),
-        Sort => collections::sort(an).ok(),
+        Sort => collections::sort(vm, an),
         Reverse => collections::reverse(an).ok(),
 
         Gcd => math::gcd(an),
@@ -1012,6 +1722,74 @@ fn invoke_var<VM : VirtualInterface, I : Iterator<Item=ValuePtr>>(f: NativeFunct
     }
 }
 
+/// Evaluates the pure function `f` with the constant arguments `args`, without a VM. This is used by the optimizer to fold calls at compile time.
+///
+/// Returns `None` if `f` is not pure, in which case the call should be evaluated at runtime as normal.
+pub fn invoke_const(f: NativeFunction, args: &[ValuePtr]) -> Option<ValueResult> {
+    if !f.is_pure() {
+        return None
+    }
+    let mut vm: ConstInterface = ConstInterface { stack: args.to_vec() };
+    Some(invoke_stack(f, args.len() as u32, &mut vm))
+}
+
+/// The `VirtualInterface` used to evaluate pure functions at compile time. Since pure functions have no side effects, and do not invoke user code, this only needs to provide a stack for the arguments.
+/// Anything else does nothing, or in the case of calling a function, raises an error so the call is evaluated at runtime instead.
+struct ConstInterface {
+    stack: Vec<ValuePtr>,
+}
+
+impl VirtualInterface for ConstInterface {
+    fn call(&mut self, _: ValuePtr, _: impl IntoIterator<Item=ValuePtr>) -> ValueResult { ConstInterface::not_supported().err() }
+    fn try_call(&mut self, _: ValuePtr, _: impl IntoIterator<Item=ValuePtr>) -> ValueResult { ConstInterface::not_supported().err() }
+    fn invoke_eval(&mut self, _: &String) -> ValueResult { ConstInterface::not_supported().err() }
+    fn store(&mut self, _: StoreOp, _: ValuePtr) -> AnyResult { ConstInterface::not_supported().err() }
+
+    fn println0(&mut self) {}
+    fn println(&mut self, _: String) {}
+    fn print(&mut self, _: String) {}
+    fn flush(&mut self) {}
+    fn write_bytes(&mut self, _: &[u8]) {}
+
+    fn eprintln0(&mut self) {}
+    fn eprintln(&mut self, _: String) {}
+    fn eprint(&mut self, _: String) {}
+
+    fn read_line(&mut self) -> String { String::new() }
+    fn read(&mut self) -> String { String::new() }
+
+    fn get_envs(&self) -> ValuePtr { ValuePtr::nil() }
+    fn get_env(&self, _: &String) -> ValuePtr { ValuePtr::nil() }
+    fn get_args(&self) -> ValuePtr { ValuePtr::nil() }
+
+    fn at_exit(&mut self, _: ValuePtr) {}
+    fn terminal(&mut self) -> Option<&mut dyn Terminal> { None }
+
+    fn log(&mut self, _: crate::vm::LogLevel, _: String, _: Vec<(String, String)>) {}
+    fn log_level(&self) -> crate::vm::LogLevel { crate::vm::LogLevel::Info }
+    fn set_log_level(&mut self, _: crate::vm::LogLevel) {}
+
+    fn entropy(&mut self) -> Option<&mut dyn Entropy> { None }
+    fn is_allowed(&self, _: Capability) -> bool { false }
+    fn register_handle(&mut self, value: ValuePtr) -> ValuePtr { value }
+    fn add_finalizer(&mut self, _: &ValuePtr, _: ValuePtr) {}
+    fn on_signal(&mut self, _: Signal, _: Option<ValuePtr>) {}
+    fn struct_type(&self, _: &str, _: &[String]) -> Option<ValuePtr> { None }
+    fn spawn(&mut self, _: ValuePtr) {}
+    fn stack_depth(&self) -> (usize, usize) { (self.stack.len(), 1) }
+
+    fn peek(&self, offset: usize) -> &ValuePtr { &self.stack[self.stack.len() - 1 - offset] }
+    fn pop(&mut self) -> ValuePtr { self.stack.pop().unwrap() }
+    fn popn(&mut self, n: u32) -> Vec<ValuePtr> { self.stack.split_off(self.stack.len() - n as usize) }
+    fn push(&mut self, value: ValuePtr) { self.stack.push(value) }
+}
+
+impl ConstInterface {
+    fn not_supported() -> RuntimeError {
+        RuntimeInternalError(String::from("Cannot invoke user code at compile time"))
+    }
+}
+
 
 /// Invokes a `Memoized()` function wrapper from the stack. This assumes the stack is already setup a priori with the memoized wrapper, and arguments in place.
 pub fn invoke_memoized<VM : VirtualInterface>(vm: &mut VM, nargs: u32) -> ValueResult {
@@ -1019,61 +1797,158 @@ pub fn invoke_memoized<VM : VirtualInterface>(vm: &mut VM, nargs: u32) -> ValueR
     let func: ValuePtr = vm.pop();
     let memoized = func.as_memoized();
 
+    // Arguments are used as the cache key, so they must be hashable
+    if let Some(arg) = args.iter().find(|arg| arg.is_recursive()) {
+        return ValueErrorRecursiveHash(arg.clone()).err()
+    }
+
     let func: ValuePtr = {
-        // We cannot use the `.entry()` API, as that requires we mutably borrow the cache during the call to `vm.invoke_func()`
-        // We only lookup by key once (in the cached case), and twice (in the uncached case)
-        let borrow = memoized.borrow();
-        if let Some(ret) = borrow.cache.get(&args) {
-            return ret.clone().ok();
+        // We cannot hold a borrow on the cache during the call to `vm.call()`, as the function may recursively invoke itself
+        let mut borrow = memoized.borrow_mut();
+        if let Some(ret) = borrow.get(&args) {
+            return ret.ok();
         }
         borrow.func.clone()
         // `borrow` is dropped here
     };
 
-    let ret: ValuePtr = vm.invoke_func(func, &args)?;
+    let ret: ValuePtr = vm.call(func, args.iter().cloned())?;
 
-    // The above computation might've entered a value into the cache - so we have to go through `.entry()` again
-    return memoized.borrow_mut().cache
-        .entry(args)
-        .or_insert(ret)
-        .clone()
+    // The above computation might've entered a value into the cache, in which case `insert()` returns the existing value
+    return memoized.borrow_mut()
+        .insert(args, ret)
         .ok();
 }
 
 
+/// Closes `handle`, which may be any socket, coprocess, or database. This is `close()`, which is also used to close any handles left open when the VM is torn down.
+pub fn close(handle: ValuePtr) -> ValueResult {
+    match handle.ty() {
+        Type::Coprocess => process::close(handle),
+        Type::Database => database::close(handle),
+        _ => net::close(handle),
+    }
+}
+
+/// Raises an error if `f` requires a capability which the host has denied
+fn check_capability<VM : VirtualInterface>(vm: &VM, f: NativeFunction) -> ErrorResult<()> {
+    match f.capability() {
+        Some(capability) if !vm.is_allowed(capability) => ValueErrorCapabilityDenied(f).err(),
+        _ => Ok(()),
+    }
+}
+
+fn len_of(value: ValuePtr) -> ValueResult {
+    value.len()?.to_value().ok()
+}
+
+fn parse_int(text: ValuePtr, default: ValuePtr) -> ValueResult {
+    math::convert_to_int(text.check_str()?, ValueOption::some(default))
+}
+
+/// Returns the `///` doc comment of a user function, closure, or partial function, or `nil` if it has none.
+fn doc_of(value: ValuePtr) -> ValuePtr {
+    let func: &FunctionImpl = match value.ty() {
+        Type::Function | Type::Closure => value.get_function(),
+        Type::PartialFunction => value.as_partial_function_ref().func.get(),
+        _ => return ValuePtr::nil(),
+    };
+    match func.doc() {
+        Some(doc) => doc.as_str().to_value(),
+        None => ValuePtr::nil(),
+    }
+}
+
+/// Returns the text printed by `help(x)`: the representation of `x`, followed by the summary of a native function, or the doc comment of a user function.
+/// For a native function which requires a capability, this is also included.
+fn help_of(value: ValuePtr) -> String {
+    let native: Option<NativeFunction> = match value.ty() {
+        Type::NativeFunction => Some(value.as_native()),
+        Type::PartialNativeFunction => Some(value.as_partial_native_ref().func),
+        _ => None,
+    };
+    let summary: Option<&str> = match value.ty() {
+        Type::Function | Type::Closure => value.get_function().doc().map(|doc| doc.as_str()),
+        Type::PartialFunction => value.as_partial_function_ref().func.get().doc().map(|doc| doc.as_str()),
+        _ => native.map(|f| f.summary()),
+    };
+    let mut text: String = value.to_repr_str().to_string();
+    for line in summary.into_iter().flat_map(|summary| summary.lines()) {
+        text.push_str("\n  ");
+        text.push_str(line);
+    }
+    if let Some(capability) = native.and_then(|f| f.capability()) {
+        text.push_str("\n  requires: ");
+        text.push_str(capability.name());
+    }
+    text
+}
+
 fn type_of(value: ValuePtr) -> ValuePtr {
     match value.ty() {
         Type::Nil => ValuePtr::nil(),
         Type::Bool => Bool.to_value(),
         Type::Int => Int.to_value(),
         Type::Complex => Complex.to_value(),
+        Type::Fraction => Fraction.to_value(),
         Type::Str => Str.to_value(),
 
         Type::List => List.to_value(),
         Type::Set => Set.to_value(),
         Type::Dict => Dict.to_value(),
         Type::Heap => Heap.to_value(),
+        Type::SortedSet => SortedSet.to_value(),
+        Type::SortedDict => SortedDict.to_value(),
         Type::Vector => Vector.to_value(),
+        Type::View => View.to_value(),
+        Type::PList => PList.to_value(),
+        Type::PDict => PDict.to_value(),
+        Type::Matrix => Matrix.to_value(),
+        Type::BitSet => BitSet.to_value(),
+        Type::Dsu => Dsu.to_value(),
+        Type::Stopwatch => Stopwatch.to_value(),
+        Type::Socket => match &*value.as_socket().borrow() {
+            SocketImpl::Stream(_, _) => TcpConnect.to_value(),
+            SocketImpl::Listener(_, _) => TcpListen.to_value(),
+        },
+        Type::Coprocess => Coprocess.to_value(),
+        Type::Database => DbOpen.to_value(),
+        Type::Generator => Forall.to_value(),
+        Type::Task => Task.to_value(),
+        Type::Weak => Weak.to_value(),
 
         Type::Struct => value.as_struct().borrow().type_impl.get().clone().to_value(), // Structs return their type constructor
         Type::StructType => Function.to_value(), // And the type constructor returns `function`
 
         Type::Range => Range.to_value(),
         Type::Enumerate => Enumerate.to_value(),
+        Type::LazyIter => match value.as_lazy_iter_ref() {
+            LazyIterImpl::Cycle(_) => Cycle.to_value(),
+            LazyIterImpl::Repeat(_, _) => Repeat.to_value(),
+            LazyIterImpl::Chain(_) => Chain.to_value(),
+            LazyIterImpl::Walk(_) => Walk.to_value(),
+            LazyIterImpl::Progress(_, _) => Progress.to_value(),
+        },
         Type::Slice => Function.to_value(),
 
-        Type::Iter | Type::Memoized | Type::Error | Type::None | Type::Never => panic!("{:?} is synthetic and cannot have type_of() called on it", value),
+        Type::Iter | Type::Error | Type::None | Type::Never => panic!("{:?} is synthetic and cannot have type_of() called on it", value),
 
-        Type::Function | Type::PartialFunction | Type::NativeFunction | Type::PartialNativeFunction | Type::Closure | Type::GetField => Function.to_value(),
+        Type::Function | Type::PartialFunction | Type::NativeFunction | Type::PartialNativeFunction | Type::Closure | Type::Memoized | Type::GetField => Function.to_value(),
     }
 }
 
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+    use std::cmp::Reverse;
+    use std::collections::{BinaryHeap, VecDeque};
+    use fxhash::FxBuildHasher;
+    use indexmap::{IndexMap, IndexSet};
+
     use crate::{compiler, core, SourceView};
-    use crate::core::{Argument, NativeFunction};
-    use crate::vm::{IntoValue, ValuePtr, VirtualInterface, VirtualMachine};
+    use crate::core::{Argument, Category, NativeFunction};
+    use crate::vm::{AnyResult, Capability, Entropy, FunctionImpl, IntoValue, LogLevel, Signal, StoreOp, Terminal, Type, ValuePtr, ValueResult, VirtualInterface, VirtualMachine};
 
     #[test]
     fn test_native_functions_are_declared_in_order() {
@@ -1098,10 +1973,231 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_native_functions_have_summary() {
+        // Tests that every function listed in `help()` has a summary, and that hidden functions are not listed
+        for info in &core::NATIVE_FUNCTIONS {
+            assert_eq!(info.summary.is_empty(), info.hidden, "in {:?}", info);
+            assert!(!info.summary.contains('\n') && !info.summary.ends_with('.'), "in {:?}", info);
+            assert!(info.category != Category::Operators || info.hidden, "in {:?}", info);
+        }
+    }
+
+    #[test]
+    fn test_native_functions_metadata_is_consistent() {
+        for info in &core::NATIVE_FUNCTIONS {
+            let f: NativeFunction = info.native;
+            assert!(f.max_nargs().is_none_or(|max| f.min_nargs() <= max), "in {:?}", info);
+            assert!(!f.is_pure() || f.max_nargs().is_some(), "pure function must not expand its arguments as an iterable, in {:?}", info);
+            assert!(!f.is_pure() || f.capability().is_none(), "pure function must not require a capability, in {:?}", info);
+        }
+    }
+
+    #[test]
+    fn test_native_functions_are_in_the_category_of_their_capability() {
+        for info in &core::NATIVE_FUNCTIONS {
+            match info.capability {
+                Some(Capability::Net) => assert_eq!(info.category, Category::Net, "in {:?}", info),
+                Some(Capability::Process) => assert_eq!(info.category, Category::System, "in {:?}", info),
+                Some(Capability::Fs) => assert_eq!(info.category, Category::Io, "in {:?}", info),
+                None => {},
+            }
+        }
+    }
+
+    /// Asserts that evaluating a function at compile time has the same result as evaluating it at runtime
+    #[test]
+    fn test_invoke_const_matches_invoke() {
+        let mut vm = VirtualMachine::new(compiler::default(), SourceView::empty(), &b""[..], vec![], vec![], vec![]);
+        let values: Vec<ValuePtr> = vec![ValuePtr::nil(), true.to_value(), 0i64.to_value(), (-3i64).to_value(), 9i64.to_value(), "a".to_value(), "abc".to_value(), " A1 ".to_value(), "12".to_value()];
+
+        for info in &core::NATIVE_FUNCTIONS {
+            for a1 in &values {
+                if let Some(ret) = core::invoke_const(info.native, &[a1.clone()]) {
+                    assert!(info.pure, "in {:?}", info);
+                    vm.push(a1.clone());
+                    assert_eq!(format!("{:?}", ret.as_result()), format!("{:?}", core::invoke_stack(info.native, 1, &mut vm).as_result()), "in {:?} with {:?}", info, a1);
+                }
+                for a2 in &values {
+                    if let Some(ret) = core::invoke_const(info.native, &[a1.clone(), a2.clone()]) {
+                        assert!(info.pure, "in {:?}", info);
+                        vm.push(a1.clone());
+                        vm.push(a2.clone());
+                        assert_eq!(format!("{:?}", ret.as_result()), format!("{:?}", core::invoke_stack(info.native, 2, &mut vm).as_result()), "in {:?} with {:?}, {:?}", info, a1, a2);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Asserts that a function is marked pure if, and only if, it meets the definition of `pure()`. The expected set is derived by evaluating each function on every combination of a set of sample arguments:
+    ///
+    /// - It must accept a fixed number of arguments, not require a capability, and not be in a category that interacts with the outside world.
+    /// - It must not use the VM for anything other than its arguments, which includes invoking a function passed to it.
+    /// - It must not mutate its arguments, and must return the same result when evaluated twice.
+    #[test]
+    fn test_native_functions_are_pure_if_they_meet_the_definition() {
+        let mut errors: Vec<String> = Vec::new();
+        for info in &core::NATIVE_FUNCTIONS {
+            let f: NativeFunction = info.native;
+            let expected: bool = match f.max_nargs() {
+                Some(max) => !matches!(info.arg, Argument::Invalid)
+                    && f.capability().is_none()
+                    && !matches!(info.category, Category::Io | Category::System | Category::Net | Category::Testing)
+                    && (f.min_nargs()..=max).all(|nargs| all_args(nargs).into_iter().all(|args| is_pure_with(f, &args))),
+                None => false,
+            };
+            if info.pure != expected {
+                errors.push(format!("{} should {}be pure", info.name, if expected { "" } else { "not " }));
+            }
+        }
+        assert!(errors.is_empty(), "{}", errors.join("\n"));
+    }
+
+    /// Returns the indices into `sample_values()` of every combination of `nargs` arguments.
+    fn all_args(nargs: u32) -> Vec<Vec<usize>> {
+        let n: usize = sample_values().len();
+        (0..nargs).fold(vec![vec![]], |acc, _| acc.into_iter()
+            .flat_map(|args| (0..n).map(move |i| {
+                let mut args: Vec<usize> = args.clone();
+                args.push(i);
+                args
+            }))
+            .collect())
+    }
+
+    /// Returns a new copy of each sample value, so that a function which mutates its arguments cannot affect the next evaluation.
+    /// The user functions take one and two arguments, and can only be invoked through the VM.
+    fn sample_values() -> Vec<ValuePtr> {
+        let memoized: ValuePtr = ProbeInterface::eval(NativeFunction::Memoize, vec![NativeFunction::Abs.to_value()]);
+        memoized.as_memoized().borrow_mut().hits = 1;
+        vec![
+            ValuePtr::nil(), true.to_value(), 0i64.to_value(), (-3i64).to_value(), 9i64.to_value(), "a".to_value(), "abc".to_value(), " A1 ".to_value(), "12".to_value(),
+            VecDeque::from([1i64.to_value(), 2i64.to_value()]).to_value(),
+            vec![1i64.to_value(), 2i64.to_value()].to_value(),
+            IndexSet::<ValuePtr, FxBuildHasher>::from_iter([1i64.to_value()]).to_value(),
+            IndexMap::<ValuePtr, ValuePtr, FxBuildHasher>::from_iter([(1i64.to_value(), 2i64.to_value())]).to_value(),
+            BinaryHeap::from([Reverse(1i64.to_value())]).to_value(),
+            ProbeInterface::eval(NativeFunction::BitSet, vec![9i64.to_value()]),
+            ProbeInterface::eval(NativeFunction::Dsu, vec![3i64.to_value()]),
+            memoized,
+            FunctionImpl::new(0, 0, String::from("f"), vec![String::from("a")], vec![], false, false).to_value(),
+            FunctionImpl::new(0, 0, String::from("g"), vec![String::from("a"), String::from("b")], vec![], false, false).to_value(),
+        ]
+    }
+
+    /// Returns a string which changes if `value` is mutated, including state which is not part of its `repr`.
+    fn fingerprint(value: &ValuePtr) -> String {
+        let state: String = match value.ty() {
+            Type::List => format!("{}", value.as_list().borrow().list.capacity()),
+            Type::Dict => format!("{}", value.as_dict().borrow().default.is_some()),
+            Type::Memoized => {
+                let it = value.as_memoized().borrow();
+                format!("{} {} {}", it.hits, it.misses, it.len())
+            },
+            _ => String::new(),
+        };
+        format!("{} {}", value.to_repr_str(), state)
+    }
+
+    fn is_pure_with(f: NativeFunction, indices: &[usize]) -> bool {
+        let args = || -> Vec<ValuePtr> {
+            let values: Vec<ValuePtr> = sample_values();
+            indices.iter().map(|i| values[*i].clone()).collect()
+        };
+
+        let args1: Vec<ValuePtr> = args();
+        let before: Vec<String> = args1.iter().map(fingerprint).collect();
+        let Some(first) = ProbeInterface::invoke(f, &args1) else { return false };
+        let after: Vec<String> = args1.iter().map(fingerprint).collect();
+        if before != after {
+            return false
+        }
+        let Some(second) = ProbeInterface::invoke(f, &args()) else { return false };
+        first == second
+    }
+
+    /// A `VirtualInterface` which records if anything other than the stack was used.
+    struct ProbeInterface {
+        stack: Vec<ValuePtr>,
+        used: Cell<bool>,
+    }
+
+    impl ProbeInterface {
+        /// Evaluates `f` with `args`, and returns the `repr` of the result, or `None` if the VM was used for anything other than the stack.
+        fn invoke(f: NativeFunction, args: &[ValuePtr]) -> Option<String> {
+            let mut vm: ProbeInterface = ProbeInterface { stack: args.to_vec(), used: Cell::new(false) };
+            let ret: ValueResult = core::invoke_stack(f, args.len() as u32, &mut vm);
+            match vm.used.get() {
+                true => None,
+                false => Some(match ret.as_result() {
+                    Ok(ret) => ret.to_repr_str(),
+                    Err(err) => format!("{:?}", err),
+                }),
+            }
+        }
+
+        /// Evaluates `f` with `args`, which must succeed. This is used to construct sample values.
+        fn eval(f: NativeFunction, args: Vec<ValuePtr>) -> ValuePtr {
+            let nargs: u32 = args.len() as u32;
+            let mut vm: ProbeInterface = ProbeInterface { stack: args, used: Cell::new(false) };
+            core::invoke_stack(f, nargs, &mut vm).as_result().unwrap()
+        }
+
+        fn use_vm(&self) {
+            self.used.set(true)
+        }
+    }
+
+    impl VirtualInterface for ProbeInterface {
+        fn call(&mut self, _: ValuePtr, _: impl IntoIterator<Item=ValuePtr>) -> ValueResult { self.use_vm(); ValuePtr::nil().ok() }
+        fn try_call(&mut self, _: ValuePtr, _: impl IntoIterator<Item=ValuePtr>) -> ValueResult { self.use_vm(); ValuePtr::nil().ok() }
+        fn invoke_eval(&mut self, _: &String) -> ValueResult { self.use_vm(); ValuePtr::nil().ok() }
+        fn store(&mut self, _: StoreOp, _: ValuePtr) -> AnyResult { self.use_vm(); Ok(()) }
+
+        fn println0(&mut self) { self.use_vm() }
+        fn println(&mut self, _: String) { self.use_vm() }
+        fn print(&mut self, _: String) { self.use_vm() }
+        fn flush(&mut self) { self.use_vm() }
+        fn write_bytes(&mut self, _: &[u8]) { self.use_vm() }
+
+        fn eprintln0(&mut self) { self.use_vm() }
+        fn eprintln(&mut self, _: String) { self.use_vm() }
+        fn eprint(&mut self, _: String) { self.use_vm() }
+
+        fn read_line(&mut self) -> String { self.use_vm(); String::new() }
+        fn read(&mut self) -> String { self.use_vm(); String::new() }
+
+        fn get_envs(&self) -> ValuePtr { self.use_vm(); ValuePtr::nil() }
+        fn get_env(&self, _: &String) -> ValuePtr { self.use_vm(); ValuePtr::nil() }
+        fn get_args(&self) -> ValuePtr { self.use_vm(); ValuePtr::nil() }
+
+        fn at_exit(&mut self, _: ValuePtr) { self.use_vm() }
+        fn terminal(&mut self) -> Option<&mut dyn Terminal> { self.use_vm(); None }
+
+        fn log(&mut self, _: LogLevel, _: String, _: Vec<(String, String)>) { self.use_vm() }
+        fn log_level(&self) -> LogLevel { self.use_vm(); LogLevel::Info }
+        fn set_log_level(&mut self, _: LogLevel) { self.use_vm() }
+
+        fn entropy(&mut self) -> Option<&mut dyn Entropy> { self.use_vm(); None }
+        fn is_allowed(&self, _: Capability) -> bool { self.use_vm(); false }
+        fn register_handle(&mut self, value: ValuePtr) -> ValuePtr { self.use_vm(); value }
+        fn add_finalizer(&mut self, _: &ValuePtr, _: ValuePtr) { self.use_vm() }
+        fn on_signal(&mut self, _: Signal, _: Option<ValuePtr>) { self.use_vm() }
+        fn struct_type(&self, _: &str, _: &[String]) -> Option<ValuePtr> { self.use_vm(); None }
+        fn spawn(&mut self, _: ValuePtr) { self.use_vm() }
+        fn stack_depth(&self) -> (usize, usize) { self.use_vm(); (self.stack.len(), 1) }
+
+        fn peek(&self, offset: usize) -> &ValuePtr { &self.stack[self.stack.len() - 1 - offset] }
+        fn pop(&mut self) -> ValuePtr { self.stack.pop().unwrap() }
+        fn popn(&mut self, n: u32) -> Vec<ValuePtr> { self.stack.split_off(self.stack.len() - n as usize) }
+        fn push(&mut self, value: ValuePtr) { self.stack.push(value) }
+    }
+
     /// Asserts that no panics are generated from calling all supported combinations of argument types.
     #[test]
     fn test_native_functions_support_from_arg() {
-        let mut vm = VirtualMachine::new(compiler::default(), SourceView::empty(), &b""[..], vec![], vec![]);
+        let mut vm = VirtualMachine::new(compiler::default(), SourceView::empty(), &b""[..], vec![], vec![], vec![]);
 
         for info in &core::NATIVE_FUNCTIONS {
             match info.arg {
@@ -1127,6 +2223,10 @@ mod tests {
                 Argument::Arg2 => {
                     let _ = core::invoke_arg2(info.native, ValuePtr::nil(), ValuePtr::nil(), &mut vm);
                 },
+                Argument::Arg2To3 => {
+                    let _ = core::invoke_arg2(info.native, ValuePtr::nil(), ValuePtr::nil(), &mut vm);
+                    let _ = core::invoke_arg3(info.native, ValuePtr::nil(), ValuePtr::nil(), ValuePtr::nil(), &mut vm);
+                },
                 Argument::Arg3 => {
                     let _ = core::invoke_arg3(info.native, ValuePtr::nil(), ValuePtr::nil(), ValuePtr::nil(), &mut vm);
                 },
@@ -1150,7 +2250,7 @@ mod tests {
     /// Asserts that `nargs < native.nargs()` is a sufficient condition for declaring a function is consistent
     #[test]
     fn test_consistency_condition() {
-        let mut vm = VirtualMachine::new(compiler::default(), SourceView::empty(), &b""[..], vec![], vec![]);
+        let mut vm = VirtualMachine::new(compiler::default(), SourceView::empty(), &b""[..], vec![], vec![], vec![]);
 
         fn is_partial(v: &ValuePtr, f: NativeFunction) -> bool {
             v.is_partial_native() && v.as_partial_native_ref().func == f
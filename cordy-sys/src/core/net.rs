@@ -0,0 +1,241 @@
+use std::io::{BufRead, BufReader, Write};
+#[cfg(feature = "net")]
+use std::io::{ErrorKind, Read};
+#[cfg(feature = "net")]
+use std::net::TcpListener;
+use std::net::TcpStream;
+
+use crate::util;
+#[cfg(feature = "net")]
+use crate::vm::{ErrorResult, Poll, TaskImpl, TaskState};
+use crate::vm::{Handle, IntoValue, RuntimeError, SocketImpl, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// Opens a TCP connection to `host` on `port`, returning a `tcp_stream`.
+#[cfg(feature = "net")]
+pub fn tcp_connect<VM : VirtualInterface>(vm: &mut VM, host: ValuePtr, port: ValuePtr) -> ValueResult {
+    let host = host.check_str()?;
+    let port: u16 = to_port(port)?;
+    match TcpStream::connect((host.as_str().borrow_const().as_str(), port)) {
+        Ok(stream) => to_stream(vm, stream),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+#[cfg(not(feature = "net"))]
+pub fn tcp_connect<VM : VirtualInterface>(_: &mut VM, _: ValuePtr, _: ValuePtr) -> ValueResult {
+    ValueErrorFeatureNotEnabled("net").err()
+}
+
+/// Listens for TCP connections on `port`, on all interfaces, returning a `tcp_listener`. If `port` is zero, the operating system picks an unused port.
+#[cfg(feature = "net")]
+pub fn tcp_listen<VM : VirtualInterface>(vm: &mut VM, port: ValuePtr) -> ValueResult {
+    let port: u16 = to_port(port)?;
+    match TcpListener::bind(("0.0.0.0", port)).and_then(|listener| Ok((listener.local_addr()?, listener))) {
+        Ok((addr, listener)) => vm.register_handle(SocketImpl::Listener(addr, Handle::new(listener)).to_value()).ok(),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+#[cfg(not(feature = "net"))]
+pub fn tcp_listen<VM : VirtualInterface>(_: &mut VM, _: ValuePtr) -> ValueResult {
+    ValueErrorFeatureNotEnabled("net").err()
+}
+
+/// Blocks until a connection is made to `listener`, and returns it as a `tcp_stream`.
+pub fn accept<VM : VirtualInterface>(vm: &mut VM, listener: ValuePtr) -> ValueResult {
+    let listener = listener.check_tcp_listener()?;
+    let result = match &*listener.as_socket().borrow() {
+        SocketImpl::Listener(_, it) => it.get(&listener)?.accept(),
+        _ => unreachable!(),
+    };
+    match result {
+        Ok((stream, _)) => to_stream(vm, stream),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+/// Writes the string `text` to `stream`.
+pub fn send(stream: ValuePtr, text: ValuePtr) -> ValueResult {
+    write(stream.check_tcp_stream()?, text, false)
+}
+
+/// Writes the string `text`, followed by a newline, to `stream`.
+pub fn send_line(stream: ValuePtr, text: ValuePtr) -> ValueResult {
+    match stream.is_tcp_stream() {
+        true => write(stream, text, true),
+        false => TypeErrorArgMustBeStream(stream).err(),
+    }
+}
+
+/// Reads a single line from `stream`, without the trailing newline, blocking until one is available. Returns `nil` if the other end has closed the connection.
+pub fn recv_line(stream: ValuePtr) -> ValueResult {
+    if !stream.is_tcp_stream() {
+        return TypeErrorArgMustBeStream(stream).err()
+    }
+    let mut buf: String = String::new();
+    let result = match &mut *stream.as_socket().borrow_mut() {
+        SocketImpl::Stream(_, it) => it.get_mut(&stream)?.read_line(&mut buf),
+        _ => unreachable!(),
+    };
+    match result {
+        Ok(0) => ValuePtr::nil().ok(),
+        Ok(_) => {
+            util::strip_line_ending(&mut buf);
+            buf.to_value().ok()
+        },
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+/// Closes `socket`, which may be either a `tcp_stream` or a `tcp_listener`. Closing a socket which is already closed does nothing.
+pub fn close(socket: ValuePtr) -> ValueResult {
+    if !socket.is_socket() {
+        return TypeErrorArgMustBeHandle(socket).err()
+    }
+    match &mut *socket.as_socket().borrow_mut() {
+        SocketImpl::Stream(_, it) => drop(it.close()),
+        SocketImpl::Listener(_, it) => drop(it.close()),
+    }
+    ValuePtr::nil().ok()
+}
+
+/// Returns the local address of `socket`, as a vector of `(host, port)`.
+pub fn local_addr(socket: ValuePtr) -> ValueResult {
+    if !socket.is_socket() {
+        return TypeErrorArgMustBeTcpStream(socket).err()
+    }
+    let result = match &*socket.as_socket().borrow() {
+        SocketImpl::Stream(_, it) => it.get(&socket)?.get_ref().local_addr(),
+        SocketImpl::Listener(_, it) => it.get(&socket)?.local_addr(),
+    };
+    match result {
+        Ok(addr) => vec![addr.ip().to_string().to_value(), (addr.port() as i64).to_value()].to_value().ok(),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+/// Starts a HTTP `GET` request to `url`, and returns a task which completes with the body of the response, as a string.
+///
+/// Only plain `http://` urls are supported. The connection is opened immediately, but the request is sent, and the response read, without blocking, by the event loop.
+/// If the response does not have a `2xx` status, the task raises an error instead.
+#[cfg(feature = "net")]
+pub fn http_get_async<VM : VirtualInterface>(vm: &mut VM, url: ValuePtr) -> ValueResult {
+    let url = url.check_str()?;
+    let url: &str = url.as_str().borrow_const().as_str();
+    let (host, port, path) = match parse_url(url) {
+        Some(it) => it,
+        None => return ValueErrorInvalidUrl(String::from(url)).err(),
+    };
+    let stream: TcpStream = match TcpStream::connect((host, port)).and_then(|stream| stream.set_nonblocking(true).map(|_| stream)) {
+        Ok(stream) => stream,
+        Err(err) => return IOError(err.to_string()).err(),
+    };
+    let request: Vec<u8> = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host).into_bytes();
+    let task: ValuePtr = TaskImpl::new(format!("http_get_async('{}')", url), TaskState::Polling(Box::new(HttpGet { url: String::from(url), stream, request, written: 0, response: Vec::new() }))).to_value();
+    vm.spawn(task.clone());
+    task.ok()
+}
+
+#[cfg(not(feature = "net"))]
+pub fn http_get_async<VM : VirtualInterface>(_: &mut VM, _: ValuePtr) -> ValueResult {
+    ValueErrorFeatureNotEnabled("net").err()
+}
+
+/// Splits a url of the form `http://host[:port][/path]` into its host, port (defaulting to `80`), and path (defaulting to `/`)
+#[cfg(feature = "net")]
+fn parse_url(url: &str) -> Option<(&str, u16, &str)> {
+    let rest: &str = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse::<u16>().ok()?),
+        None => (authority, 80),
+    };
+    match host.is_empty() {
+        true => None,
+        false => Some((host, port, path)),
+    }
+}
+
+/// A HTTP `GET` request created by `http_get_async()`, which writes the request, and then reads the response until the server closes the connection.
+#[cfg(feature = "net")]
+struct HttpGet {
+    url: String,
+    stream: TcpStream,
+    request: Vec<u8>,
+    written: usize,
+    response: Vec<u8>,
+}
+
+#[cfg(feature = "net")]
+impl HttpGet {
+    fn body(&self) -> ValueResult {
+        let response: String = String::from_utf8_lossy(&self.response).into_owned();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        match head.split(' ').nth(1).and_then(|status| status.parse::<u16>().ok()) {
+            Some(status) if (200..300).contains(&status) => body.to_value().ok(),
+            Some(status) => IOError(format!("HTTP request to '{}' failed with status {}", self.url, status)).err(),
+            None => IOError(format!("HTTP request to '{}' received an invalid response", self.url)).err(),
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+impl Poll for HttpGet {
+    fn poll(&mut self) -> Option<ValueResult> {
+        while self.written < self.request.len() {
+            match self.stream.write(&self.request[self.written..]) {
+                Ok(n) => self.written += n,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return None,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {},
+                Err(err) => return Some(IOError(err.to_string()).err()),
+            }
+        }
+        let mut buf: [u8; 4096] = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Some(self.body()),
+                Ok(n) => self.response.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return None,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {},
+                Err(err) => return Some(IOError(err.to_string()).err()),
+            }
+        }
+    }
+}
+
+fn write(stream: ValuePtr, text: ValuePtr, newline: bool) -> ValueResult {
+    let text = text.check_str()?;
+    let result = match &mut *stream.as_socket().borrow_mut() {
+        SocketImpl::Stream(_, it) => {
+            let it = it.get_mut(&stream)?.get_mut();
+            it.write_all(text.as_str().borrow_const().as_bytes()).and_then(|_| if newline { it.write_all(b"\n") } else { Ok(()) })
+        },
+        _ => unreachable!(),
+    };
+    match result {
+        Ok(_) => ValuePtr::nil().ok(),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+fn to_stream<VM : VirtualInterface>(vm: &mut VM, stream: TcpStream) -> ValueResult {
+    match stream.peer_addr() {
+        Ok(addr) => vm.register_handle(SocketImpl::Stream(addr, Handle::new(BufReader::new(stream))).to_value()).ok(),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+#[cfg(feature = "net")]
+fn to_port(port: ValuePtr) -> ErrorResult<u16> {
+    let port: i64 = port.check_int()?.as_int();
+    match u16::try_from(port) {
+        Ok(port) => Ok(port),
+        Err(_) => ValueErrorInvalidPort(port).err(),
+    }
+}
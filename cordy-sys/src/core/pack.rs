@@ -0,0 +1,196 @@
+use crate::vm::{ErrorResult, IntoIterableValue, IntoValue, MAX_INT, MIN_INT, RuntimeError, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// A single field of a pack format
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    /// An integer of the given size in bytes
+    Int { size: usize, signed: bool },
+    Bool,
+    /// A zero byte, which does not correspond to a value
+    Pad,
+    /// A string of exactly the given number of bytes, padded with zero bytes
+    Str(usize),
+}
+
+impl Field {
+    fn size(&self) -> usize {
+        match self {
+            Field::Int { size, .. } => *size,
+            Field::Bool | Field::Pad => 1,
+            Field::Str(len) => *len,
+        }
+    }
+}
+
+struct Format {
+    big_endian: bool,
+    fields: Vec<Field>,
+}
+
+
+/// Packs `values` into a list of bytes, according to the pack format `format`.
+///
+/// Each value must fit in the corresponding field, and there must be exactly one value for each field, other than padding bytes.
+pub fn pack(format: ValuePtr, values: ValuePtr) -> ValueResult {
+    let format: Format = parse_format(format)?;
+    let values: Vec<ValuePtr> = values.to_iter()?.collect();
+    let expected: usize = format.fields.iter().filter(|field| !matches!(field, Field::Pad)).count();
+    if values.len() != expected {
+        return ValueErrorCannotPack(format!("expected {} values, got {}", expected, values.len())).err()
+    }
+
+    let mut buf: Vec<u8> = Vec::with_capacity(format.fields.iter().map(Field::size).sum());
+    let mut values = values.into_iter();
+    for field in format.fields {
+        match field {
+            Field::Pad => buf.push(0),
+            Field::Bool => buf.push(values.next().unwrap().to_bool() as u8),
+            Field::Str(len) => {
+                let value = values.next().unwrap().check_str()?;
+                let bytes: &[u8] = value.as_str().borrow_const().as_bytes();
+                if bytes.len() > len {
+                    return ValueErrorCannotPack(format!("{} is longer than {} bytes", value.to_repr_str(), len)).err()
+                }
+                buf.extend_from_slice(bytes);
+                buf.resize(buf.len() + len - bytes.len(), 0);
+            },
+            Field::Int { size, signed } => {
+                let value: i64 = values.next().unwrap().check_int()?.as_int();
+                let bits: usize = size * 8;
+                let in_range: bool = match signed {
+                    true => bits == 64 || (-(1i64 << (bits - 1))..(1i64 << (bits - 1))).contains(&value),
+                    false => value >= 0 && (bits == 64 || value < (1i64 << bits)),
+                };
+                if !in_range {
+                    return ValueErrorCannotPack(format!("{} does not fit in a {}-bit {} integer", value, bits, if signed { "signed" } else { "unsigned" })).err()
+                }
+                let bytes: &[u8] = &value.to_le_bytes()[..size];
+                match format.big_endian {
+                    true => buf.extend(bytes.iter().rev()),
+                    false => buf.extend_from_slice(bytes),
+                }
+            },
+        }
+    }
+    buf.into_iter()
+        .map(|b| (b as i64).to_value())
+        .to_list()
+        .ok()
+}
+
+/// Unpacks a list of bytes into a vector of values, according to the pack format `format`. The number of bytes must be exactly the size of the format.
+pub fn unpack(format: ValuePtr, bytes: ValuePtr) -> ValueResult {
+    let format: Format = parse_format(format)?;
+    let bytes: Vec<u8> = bytes.to_iter()?
+        .map(|b| {
+            let b: i64 = b.check_int()?.as_int();
+            u8::try_from(b).or_else(|_| ValueErrorInvalidByte(b).err())
+        })
+        .collect::<ErrorResult<Vec<u8>>>()?;
+
+    let expected: usize = format.fields.iter().map(Field::size).sum();
+    if bytes.len() != expected {
+        return ValueErrorCannotUnpack(format!("expected {} bytes, got {}", expected, bytes.len())).err()
+    }
+
+    let mut values: Vec<ValuePtr> = Vec::new();
+    let mut pos: usize = 0;
+    for field in format.fields {
+        let raw: &[u8] = &bytes[pos..pos + field.size()];
+        match field {
+            Field::Pad => {},
+            Field::Bool => values.push((raw[0] != 0).to_value()),
+            Field::Str(_) => {
+                let len: usize = raw.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1); // Trailing padding is removed
+                match std::str::from_utf8(&raw[..len]) {
+                    Ok(it) => values.push(it.to_value()),
+                    Err(_) => return ValueErrorCannotUnpack(format!("invalid UTF-8 in string at offset {}", pos)).err(),
+                }
+            },
+            Field::Int { size, signed } => {
+                let mut le: [u8; 8] = [0; 8];
+                for (i, b) in raw.iter().enumerate() {
+                    le[if format.big_endian { size - 1 - i } else { i }] = *b;
+                }
+                let value: Option<i64> = match signed {
+                    true => {
+                        let shift: usize = 64 - size * 8; // Sign extend from the top bit of the field
+                        Some((i64::from_le_bytes(le) << shift) >> shift).filter(|it| (MIN_INT..=MAX_INT).contains(it))
+                    },
+                    false => Some(u64::from_le_bytes(le)).filter(|it| *it <= MAX_INT as u64).map(|it| it as i64),
+                };
+                match value {
+                    Some(value) => values.push(value.to_value()),
+                    None => return ValueErrorCannotUnpack(format!("integer at offset {} is too large to be an int", pos)).err(),
+                }
+            },
+        }
+        pos += field.size();
+    }
+    values.into_iter().to_vector().ok()
+}
+
+
+/// Parses a pack format, which is an optional byte order, followed by any number of fields, each with an optional repeat count.
+/// The count of a string field `s` is instead its length in bytes. Whitespace between fields is ignored.
+fn parse_format(format: ValuePtr) -> ErrorResult<Format> {
+    let format = format.check_str()?;
+    let text: &str = format.as_str().borrow_const();
+    let mut chars = text.chars().peekable();
+    let big_endian: bool = match chars.peek() {
+        Some('<') => { chars.next(); false },
+        Some('>' | '!') => { chars.next(); true },
+        _ => false,
+    };
+
+    let mut fields: Vec<Field> = Vec::new();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue
+        }
+        let mut count: Option<usize> = None;
+        let mut code: Option<char> = Some(c);
+        if let Some(digit) = c.to_digit(10) {
+            let mut n: usize = digit as usize;
+            code = None;
+            for c in chars.by_ref() {
+                match c.to_digit(10) {
+                    Some(digit) => n = match n.checked_mul(10).and_then(|n| n.checked_add(digit as usize)) {
+                        Some(n) => n,
+                        None => return ValueErrorInvalidPackFormat(String::from(text), c).err(),
+                    },
+                    None => {
+                        code = Some(c);
+                        break
+                    },
+                }
+            }
+            count = Some(n);
+        }
+        let field: Field = match code {
+            Some('b') => Field::Int { size: 1, signed: true },
+            Some('B') => Field::Int { size: 1, signed: false },
+            Some('h') => Field::Int { size: 2, signed: true },
+            Some('H') => Field::Int { size: 2, signed: false },
+            Some('i') => Field::Int { size: 4, signed: true },
+            Some('I') => Field::Int { size: 4, signed: false },
+            Some('q') => Field::Int { size: 8, signed: true },
+            Some('Q') => Field::Int { size: 8, signed: false },
+            Some('?') => Field::Bool,
+            Some('x') => Field::Pad,
+            Some('s') => {
+                fields.push(Field::Str(count.unwrap_or(1)));
+                continue
+            },
+            Some(c) => return ValueErrorInvalidPackFormat(String::from(text), c).err(),
+            None => return ValueErrorPackFormatMissingField(String::from(text)).err(),
+        };
+        for _ in 0..count.unwrap_or(1) {
+            fields.push(field);
+        }
+    }
+    Ok(Format { big_endian, fields })
+}
@@ -0,0 +1,260 @@
+use std::{env, fs, io};
+use std::collections::VecDeque;
+use std::path::{Component, Path, PathBuf};
+
+use crate::vm::{IntoIterableValue, IntoValue, LazyIterImpl, RuntimeError, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// Joins each argument into a single path, using the platform separator. If an argument is an absolute path, it replaces everything before it.
+pub fn join(args: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    let mut path: PathBuf = PathBuf::new();
+    for arg in args {
+        path.push(arg.check_str()?.as_str().borrow_const());
+    }
+    to_value(&path).ok()
+}
+
+/// Returns the path of the parent directory of `path`, or an empty string if `path` has no parent.
+/// The root directory is its own parent.
+pub fn dirname(path: ValuePtr) -> ValueResult {
+    let path = path.check_str()?;
+    let path: &Path = Path::new(path.as_str().borrow_const());
+    match path.parent() {
+        Some(parent) => to_value(parent),
+        None if path.has_root() => to_value(path),
+        None => "".to_value(),
+    }.ok()
+}
+
+/// Returns the final component of `path`, or an empty string if `path` ends in `..` or is a root.
+pub fn basename(path: ValuePtr) -> ValueResult {
+    let path = path.check_str()?;
+    match Path::new(path.as_str().borrow_const()).file_name() {
+        Some(name) => name.to_string_lossy().into_owned().to_value(),
+        None => "".to_value(),
+    }.ok()
+}
+
+/// Returns the extension of the final component of `path`, without the leading `.`, or an empty string if there is no extension.
+pub fn extension(path: ValuePtr) -> ValueResult {
+    let path = path.check_str()?;
+    match Path::new(path.as_str().borrow_const()).extension() {
+        Some(ext) => ext.to_string_lossy().into_owned().to_value(),
+        None => "".to_value(),
+    }.ok()
+}
+
+/// Makes `path` absolute, relative to the current working directory. Unlike `normalize()`, this does not resolve `.` or `..` components.
+pub fn absolute(path: ValuePtr) -> ValueResult {
+    let path = path.check_str()?;
+    match std::path::absolute(path.as_str().borrow_const()) {
+        Ok(path) => to_value(&path).ok(),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+/// Lexically normalizes `path`, by removing `.` components and repeated separators, and resolving `..` components where possible.
+/// This does not access the file system, so it does not resolve symbolic links.
+pub fn normalize(path: ValuePtr) -> ValueResult {
+    let path = path.check_str()?;
+    let mut normal: PathBuf = PathBuf::new();
+    for component in Path::new(path.as_str().borrow_const()).components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => { normal.pop(); },
+                Some(Component::RootDir | Component::Prefix(_)) => {}, // `..` of the root is the root
+                _ => normal.push(".."),
+            },
+            _ => normal.push(component),
+        }
+    }
+    if normal.as_os_str().is_empty() {
+        normal.push(".");
+    }
+    to_value(&normal).ok()
+}
+
+/// Returns the current user's home directory, or `nil` if it cannot be determined.
+pub fn home_dir() -> ValueResult {
+    #[allow(deprecated)] // Deprecated due to incorrect behavior on Windows, which has since been fixed
+    match env::home_dir() {
+        Some(path) => to_value(&path),
+        None => ValuePtr::nil(),
+    }.ok()
+}
+
+/// Returns the directory used for temporary files.
+pub fn temp_dir() -> ValueResult {
+    to_value(&env::temp_dir()).ok()
+}
+
+/// Returns the paths of each entry in the directory at `path`, in sorted order.
+pub fn list_dir(path: ValuePtr) -> ValueResult {
+    let path = path.check_str()?;
+    match read_dir(Path::new(path.as_str().borrow_const())) {
+        Ok(entries) => entries.into_iter()
+            .map(|(path, _)| to_value(&path))
+            .to_list()
+            .ok(),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+/// Returns a lazy iterable over the paths of every entry under the directory at `path`, recursively.
+/// This checks that `path` can be read eagerly, but otherwise only reads each directory as it is reached.
+pub fn walk(path: ValuePtr) -> ValueResult {
+    let path = path.check_str()?;
+    match read_dir(Path::new(path.as_str().borrow_const())) {
+        Ok(_) => LazyIterImpl::Walk(path).to_value().ok(),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+/// Reads the entries of the directory at `path`, as `(path, is_dir)` pairs, sorted by path. An empty `path` refers to the current directory.
+///
+/// Each returned path is `path` joined with the name of the entry. Symbolic links are not followed, so a link to a directory is not reported as a directory, which prevents `walk()` and `glob()` from entering cycles.
+pub fn read_dir(path: &Path) -> io::Result<VecDeque<(PathBuf, bool)>> {
+    let mut entries: Vec<(PathBuf, bool)> = Vec::new();
+    for entry in fs::read_dir(if path.as_os_str().is_empty() { Path::new(".") } else { path })? {
+        let entry = entry?;
+        entries.push((path.join(entry.file_name()), entry.file_type()?.is_dir()));
+    }
+    entries.sort();
+    Ok(entries.into())
+}
+
+/// Returns a sorted list of paths which match `pattern`.
+///
+/// Each component of the pattern may contain `*` (matching any sequence of characters), `?` (matching any single character), or `[...]` (matching any character in the set, or not in the set if it starts with `!`).
+/// A component of `**` matches zero or more directories. Wildcards do not match names starting with `.`, unless the component of the pattern also starts with `.`.
+pub fn glob(pattern: ValuePtr) -> ValueResult {
+    let pattern = pattern.check_str()?;
+    let pattern: &Path = Path::new(pattern.as_str().borrow_const());
+
+    // Split the pattern into a literal base directory, and the remaining components which may contain wildcards
+    let mut base: PathBuf = PathBuf::new();
+    let mut parts: Vec<String> = Vec::new();
+    for component in pattern.components() {
+        let part: &str = &component.as_os_str().to_string_lossy();
+        if parts.is_empty() && !part.contains(['*', '?', '[']) {
+            base.push(component);
+        } else {
+            parts.push(String::from(part));
+        }
+    }
+
+    let mut matches: Vec<PathBuf> = Vec::new();
+    if parts.is_empty() {
+        if fs::symlink_metadata(&base).is_ok() {
+            matches.push(base);
+        }
+    } else {
+        glob_in(&base, &parts, &mut matches);
+        matches.sort();
+        matches.dedup(); // Multiple `**` components can match the same path more than once
+    }
+    matches.iter()
+        .map(|path| to_value(path))
+        .to_list()
+        .ok()
+}
+
+fn glob_in(dir: &Path, parts: &[String], matches: &mut Vec<PathBuf>) {
+    let (part, rest) = match parts.split_first() {
+        Some(it) => it,
+        None => return,
+    };
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return, // Directories that cannot be read, or are not directories, have no matches
+    };
+    if part == "**" {
+        // Match zero directories, by skipping this component, and then one or more directories, by descending with this component still present.
+        // A trailing `**` matches everything under this directory.
+        if rest.is_empty() {
+            for (path, is_dir) in entries {
+                if !is_hidden(&path) {
+                    matches.push(path.clone());
+                    if is_dir {
+                        glob_in(&path, parts, matches);
+                    }
+                }
+            }
+        } else {
+            glob_in(dir, rest, matches);
+            for (path, is_dir) in entries {
+                if is_dir && !is_hidden(&path) {
+                    glob_in(&path, parts, matches);
+                }
+            }
+        }
+    } else {
+        let pattern: Vec<char> = part.chars().collect();
+        for (path, is_dir) in entries {
+            let name: Vec<char> = path.file_name().unwrap_or_default().to_string_lossy().chars().collect();
+            if (name.first() != Some(&'.') || pattern.first() == Some(&'.')) && is_match(&pattern, &name) {
+                if rest.is_empty() {
+                    matches.push(path);
+                } else if is_dir {
+                    glob_in(&path, rest, matches);
+                }
+            }
+        }
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Matches a single component of a glob pattern against a name.
+fn is_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| is_match(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && is_match(&pattern[1..], &name[1..]),
+        Some('[') => match (parse_set(pattern), name.first()) {
+            (Some((end, negated, set)), Some(c)) => is_in_set(set, *c) != negated && is_match(&pattern[end + 1..], &name[1..]),
+            (None, Some(c)) => *c == '[' && is_match(&pattern[1..], &name[1..]), // No closing `]`, so treat `[` literally
+            (_, None) => false,
+        },
+        Some(p) => name.first() == Some(p) && is_match(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Parses a set `[...]` at the start of `pattern`, returning the index of the closing `]`, if the set is negated, and the characters in the set.
+/// The first character of the set is never treated as the closing `]`, so `[]]` is a set containing `]`.
+fn parse_set(pattern: &[char]) -> Option<(usize, bool, &[char])> {
+    let (negated, start) = match pattern.get(1) {
+        Some('!' | '^') => (true, 2),
+        _ => (false, 1),
+    };
+    let end: usize = start + 1 + pattern.get(start + 1..)?.iter().position(|c| *c == ']')?;
+    Some((end, negated, &pattern[start..end]))
+}
+
+/// Checks if `c` is in a set, which consists of single characters and ranges `a-z`. A `-` at the start or end of the set is a literal.
+fn is_in_set(set: &[char], c: char) -> bool {
+    let mut i: usize = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            if set[i] <= c && c <= set[i + 2] {
+                return true
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+fn to_value(path: &Path) -> ValuePtr {
+    path.to_string_lossy().into_owned().to_value()
+}
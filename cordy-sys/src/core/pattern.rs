@@ -38,6 +38,28 @@ impl Pattern {
         self.terms.push(Term::Pattern(index, pattern))
     }
 
+    /// Returns the assembly form of this pattern, i.e. `Pattern(2, false, [Index(0, Local(1)), Slice(1, 0, Global(0))])`, which is read by the assembler.
+    pub fn assembly(&self) -> String {
+        let terms: Vec<String> = self.terms.iter()
+            .map(|term| match term {
+                Term::Index(index, op) => format!("Index({}, {:?})", index, op),
+                Term::Slice(low, high, op) => format!("Slice({}, {}, {:?})", low, high, op),
+                Term::Pattern(index, next) => format!("Pattern({}, {})", index, next.assembly()),
+            })
+            .collect();
+        format!("Pattern({}, {}, [{}])", self.len, self.variadic, terms.join(", "))
+    }
+
+    /// Returns every store made by this pattern, including those made by nested patterns
+    pub(crate) fn stores(&self) -> Vec<StoreOp> {
+        self.terms.iter()
+            .flat_map(|term| match term {
+                Term::Index(_, op) | Term::Slice(_, _, op) => vec![*op],
+                Term::Pattern(_, next) => next.stores(),
+            })
+            .collect()
+    }
+
     pub fn apply<VM : VirtualInterface>(&self, vm: &mut VM, ptr: &ValuePtr) -> AnyResult {
         self.check_length(ptr)?;
 
@@ -0,0 +1,77 @@
+use crate::core::collections;
+use crate::vm::{ErrorResult, IntoValue, PDictImpl, PListImpl, RuntimeError, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// Collects an iterator of key-value pairs into a new persistent dict, freezing each key.
+pub fn collect_into_pdict(iter: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    iter.map(|t| t.to_pair())
+        .collect::<ErrorResult<Vec<(ValuePtr, ValuePtr)>>>()?
+        .into_iter()
+        .inspect(|(k, _)| k.freeze())
+        .collect::<PDictImpl>()
+        .to_value()
+        .ok()
+}
+
+pub fn get_index(dict: &ValuePtr, key: ValuePtr) -> ValueResult {
+    match dict.as_pdict().borrow_const().get(&key) {
+        Some(value) => value.clone().ok(),
+        None => ValueErrorKeyNotPresent(key).err(),
+    }
+}
+
+/// Returns a new persistent list with `value` appended to the end, or a new persistent dict with the key-value pair `value` inserted.
+pub fn conj(value: ValuePtr, target: ValuePtr) -> ValueResult {
+    if target.is_plist() {
+        target.as_plist().borrow_const().push(value).to_value().ok()
+    } else if target.is_pdict() {
+        let (key, value) = value.to_pair()?;
+        assoc(key, value, target)
+    } else {
+        TypeErrorArgMustBePersistent(target).err()
+    }
+}
+
+/// Returns a new persistent list with the value at index `key` replaced by `value`, or a new persistent dict with `key` set to `value`.
+///
+/// For a list, `key` may also be the length of the list, in which case `value` is appended to the end.
+pub fn assoc(key: ValuePtr, value: ValuePtr, target: ValuePtr) -> ValueResult {
+    if target.is_plist() {
+        let it = target.as_plist().borrow_const();
+        if key.is_int() && key.as_int() == it.len() as i64 {
+            return it.push(value).to_value().ok()
+        }
+        let index: usize = collections::check_index(key, it.len())?;
+        it.set(index, value).to_value().ok()
+    } else if target.is_pdict() {
+        key.freeze();
+        target.as_pdict().borrow_const().insert(key, value).to_value().ok()
+    } else {
+        TypeErrorArgMustBePersistent(target).err()
+    }
+}
+
+/// Returns a new persistent list without the value at index `key`, or a new persistent dict without `key`.
+///
+/// Removing a key from a dict takes `O(log n)` time, and returns the original dict if `key` is not present. Removing an index from a list must rebuild the list after that index, and so takes `O(n)` time.
+pub fn dissoc(key: ValuePtr, target: ValuePtr) -> ValueResult {
+    if target.is_plist() {
+        let it = target.as_plist().borrow_const();
+        let index: usize = collections::check_index(key, it.len())?;
+        it.iter().enumerate()
+            .filter(|(i, _)| *i != index)
+            .map(|(_, v)| v.clone())
+            .collect::<PListImpl>()
+            .to_value()
+            .ok()
+    } else if target.is_pdict() {
+        match target.as_pdict().borrow_const().remove(&key) {
+            Some(it) => it.to_value().ok(),
+            None => target.ok(),
+        }
+    } else {
+        TypeErrorArgMustBePersistent(target).err()
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::vm::{CoprocessImpl, Handle, IntoValue, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// How long `close()` waits for a coprocess to exit by itself, after closing its standard input, before it is killed.
+const CLOSE_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Starts `cmd` as a coprocess. `cmd` is either a string, which is split on whitespace, or an iterable of the program followed by its arguments.
+/// If `timeout` is given, it is the number of milliseconds `recv_line()` will wait for a line before raising an error.
+pub fn coprocess<VM : VirtualInterface>(vm: &mut VM, cmd: ValuePtr, timeout: Option<ValuePtr>) -> ValueResult {
+    let args: Vec<String> = match cmd.is_str() {
+        true => cmd.as_str().borrow_const().split_whitespace().map(String::from).collect(),
+        false => cmd.clone().to_iter()?.map(|arg| arg.to_str()).collect(),
+    };
+    let timeout: Option<Duration> = match timeout {
+        Some(timeout) => {
+            let timeout: i64 = timeout.check_int()?.as_int();
+            if timeout < 0 {
+                return ValueErrorValueMustBeNonNegative(timeout).err()
+            }
+            Some(Duration::from_millis(timeout as u64))
+        },
+        None => None,
+    };
+    let (program, args) = match args.split_first() {
+        Some(it) => it,
+        None => return ValueErrorValueMustBeNonEmpty.err(),
+    };
+
+    let mut child = match Command::new(program).args(args).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(err) => return IOError(err.to_string()).err(),
+    };
+    let stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+
+    // The reader thread exits once the process closes its output, or the receiving end is dropped
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line).is_err() {
+                break
+            }
+        }
+    });
+
+    vm.register_handle(CoprocessImpl { cmd: cmd.to_repr_str(), child, stdin: Handle::new(stdin), stdout: rx, timeout }.to_value()).ok()
+}
+
+/// Writes `text`, followed by a newline, to the standard input of `process`.
+pub fn send_line(process: ValuePtr, text: ValuePtr) -> ValueResult {
+    let text = text.check_str()?;
+    let mut it = process.as_coprocess().borrow_mut();
+    let stdin = it.stdin.get_mut(&process)?;
+    match writeln!(stdin, "{}", text.as_str().borrow_const()).and_then(|_| stdin.flush()) {
+        Ok(_) => ValuePtr::nil().ok(),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+/// Reads a single line from the standard output of `process`, without the trailing newline. Returns `nil` if the process has closed its output.
+pub fn recv_line(process: ValuePtr) -> ValueResult {
+    let it = process.as_coprocess().borrow();
+    it.stdin.get(&process)?;
+    let line = match it.timeout {
+        Some(timeout) => match it.stdout.recv_timeout(timeout) {
+            Ok(line) => Some(line),
+            Err(RecvTimeoutError::Timeout) => return ValueErrorTimedOut(process.clone()).err(),
+            Err(RecvTimeoutError::Disconnected) => None,
+        },
+        None => it.stdout.recv().ok(),
+    };
+    match line {
+        Some(Ok(line)) => line.to_value().ok(),
+        Some(Err(err)) => IOError(err.to_string()).err(),
+        None => ValuePtr::nil().ok(),
+    }
+}
+
+/// Closes the standard input of `process`, and waits for it to exit, killing it if it does not exit in time.
+/// Returns the exit code of the process, or `nil` if it was killed. Closing a process which is already closed does nothing, and returns `nil`.
+pub fn close(process: ValuePtr) -> ValueResult {
+    let mut it = process.as_coprocess().borrow_mut();
+    if it.stdin.close().is_none() {
+        return ValuePtr::nil().ok()
+    }
+    let start: Instant = Instant::now();
+    let status = loop {
+        match it.child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) if start.elapsed() < CLOSE_GRACE_PERIOD => thread::sleep(Duration::from_millis(5)),
+            Ok(None) => {
+                let _ = it.child.kill();
+                break it.child.wait()
+            },
+            Err(err) => break Err(err),
+        }
+    };
+    match status {
+        Ok(status) => match status.code() {
+            Some(code) => (code as i64).to_value().ok(),
+            None => ValuePtr::nil().ok(),
+        },
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
@@ -0,0 +1,235 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::reporting::AsError;
+use crate::vm::{ErrorResult, GeneratorImpl, IntoIterableValue, IntoValue, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// The number of cases tested by `forall()`, if not specified.
+const DEFAULT_CASES: i64 = 100;
+
+/// The maximum number of candidates tried while shrinking a failing input, which bounds the time spent shrinking large inputs.
+const MAX_SHRINK_STEPS: usize = 1000;
+
+/// If set, the environment variable used to seed `forall()`, so a failure can be reproduced.
+const SEED_VAR: &str = "CORDY_SEED";
+
+
+/// Returns a generator of `int`s in the range `[lo, hi]`, inclusive.
+pub fn gen_int(lo: ValuePtr, hi: ValuePtr) -> ValueResult {
+    let lo: i64 = lo.check_int()?.as_int();
+    let hi: i64 = hi.check_int()?.as_int();
+    if lo > hi {
+        return ValueErrorEmptyRange(lo, hi).err()
+    }
+    GeneratorImpl::Int(lo, hi).to_value().ok()
+}
+
+/// Returns a generator of `list`s with up to `n` elements, each generated by `g`.
+pub fn gen_list(g: ValuePtr, n: ValuePtr) -> ValueResult {
+    let g = g.check_generator()?;
+    GeneratorImpl::List(g, to_len(n)?).to_value().ok()
+}
+
+/// Returns a generator of `str`s with up to `n` characters, each chosen from `alphabet`.
+pub fn gen_str(alphabet: ValuePtr, n: ValuePtr) -> ValueResult {
+    let alphabet: Vec<char> = alphabet.check_str()?.as_str().borrow_const().chars().collect();
+    if alphabet.is_empty() {
+        return ValueErrorValueMustBeNonEmpty.err()
+    }
+    GeneratorImpl::Str(alphabet, to_len(n)?).to_value().ok()
+}
+
+/// Tests that `f` returns a truthy value for `cases` (defaulting to 100) random inputs generated by `g`.
+///
+/// If the property fails, either by returning a falsy value or by raising an error, the input is shrunk towards a simpler input which still fails, and then an error is raised with the shrunk input and the seed.
+/// The seed is random, unless the `CORDY_SEED` environment variable is set, which allows a failure to be reproduced.
+pub fn forall<VM : VirtualInterface>(vm: &mut VM, g: ValuePtr, f: ValuePtr, cases: Option<ValuePtr>) -> ValueResult {
+    let g = g.check_generator()?;
+    if !f.is_evaluable() {
+        return TypeErrorArgMustBeFunction(f).err()
+    }
+    let cases: i64 = match cases {
+        Some(cases) => cases.check_int()?.as_int(),
+        None => DEFAULT_CASES,
+    };
+    if cases < 0 {
+        return ValueErrorValueMustBeNonNegative(cases).err()
+    }
+
+    let seed: u64 = seed(vm)?;
+    let mut rng: Rng = Rng(seed);
+    let generator: &GeneratorImpl = g.as_generator().borrow_const();
+    for case in 1..=cases as usize {
+        let input: ValuePtr = generate(generator, &mut rng);
+        if let Some(reason) = check(vm, &f, input.clone())? {
+            let (input, reason) = shrink(vm, generator, &f, input, reason)?;
+            return ValueErrorPropertyFailed(case, seed, input, reason).err()
+        }
+    }
+    ValuePtr::nil().ok()
+}
+
+
+/// Invokes the property `f` with `input`, returning the reason it failed, if it did.
+fn check<VM : VirtualInterface>(vm: &mut VM, f: &ValuePtr, input: ValuePtr) -> ErrorResult<Option<String>> {
+    match vm.try_call(f.clone(), [input]).as_result() {
+        Ok(ret) if ret.to_bool() => Ok(None),
+        Ok(ret) => Ok(Some(format!("returned {}", ret.to_repr_str()))),
+        Err(err) => {
+            // Exiting the program is not a failure of the property, and so is not caught
+            if matches!(err.value, RuntimeExit(_) | RuntimeYield) {
+                return Err(err)
+            }
+            Ok(Some(err.value.as_error()))
+        },
+    }
+}
+
+/// Repeatedly replaces `input` with the first simpler candidate which still fails, until none do. Returns the simplest failing input found, and the reason it failed.
+fn shrink<VM : VirtualInterface>(vm: &mut VM, generator: &GeneratorImpl, f: &ValuePtr, mut input: ValuePtr, mut reason: String) -> ErrorResult<(ValuePtr, String)> {
+    let mut steps: usize = 0;
+    'shrink: loop {
+        for candidate in candidates(generator, &input) {
+            if steps == MAX_SHRINK_STEPS {
+                break 'shrink
+            }
+            steps += 1;
+            if let Some(candidate_reason) = check(vm, f, candidate.clone())? {
+                input = candidate;
+                reason = candidate_reason;
+                continue 'shrink
+            }
+        }
+        break
+    }
+    Ok((input, reason))
+}
+
+fn generate(generator: &GeneratorImpl, rng: &mut Rng) -> ValuePtr {
+    match generator {
+        GeneratorImpl::Int(lo, hi) => rng.range(*lo, *hi).to_value(),
+        GeneratorImpl::List(g, n) => {
+            let len: i64 = rng.range(0, *n as i64);
+            let g: &GeneratorImpl = g.as_generator().borrow_const();
+            (0..len).map(|_| generate(g, rng)).to_list()
+        },
+        GeneratorImpl::Str(alphabet, n) => {
+            let len: i64 = rng.range(0, *n as i64);
+            (0..len).map(|_| alphabet[rng.range(0, alphabet.len() as i64 - 1) as usize]).collect::<String>().to_value()
+        },
+    }
+}
+
+/// Returns candidates which are simpler than `value`, in order from simplest to least simple.
+fn candidates(generator: &GeneratorImpl, value: &ValuePtr) -> Vec<ValuePtr> {
+    match generator {
+        GeneratorImpl::Int(lo, hi) => {
+            // Shrink towards zero, or the closest bound to it, by successively smaller steps
+            let x: i64 = value.as_int();
+            let target: i64 = 0.clamp(*lo, *hi);
+            let mut candidates: Vec<ValuePtr> = Vec::new();
+            let mut delta: i64 = x - target;
+            while delta != 0 {
+                candidates.push((x - delta).to_value());
+                delta /= 2;
+            }
+            candidates
+        },
+        GeneratorImpl::List(g, _) => {
+            let items: Vec<ValuePtr> = value.as_list().borrow().list.iter().cloned().collect();
+            let mut candidates: Vec<Vec<ValuePtr>> = removals(&items);
+            let g: &GeneratorImpl = g.as_generator().borrow_const();
+            for (i, item) in items.iter().enumerate() {
+                for candidate in self::candidates(g, item) {
+                    let mut shrunk: Vec<ValuePtr> = items.clone();
+                    shrunk[i] = candidate;
+                    candidates.push(shrunk);
+                }
+            }
+            candidates.into_iter().map(|it| it.into_iter().to_list()).collect()
+        },
+        GeneratorImpl::Str(alphabet, _) => {
+            let chars: Vec<char> = value.as_str().borrow_const().chars().collect();
+            let mut candidates: Vec<Vec<char>> = removals(&chars);
+            for (i, c) in chars.iter().enumerate() {
+                if *c != alphabet[0] {
+                    let mut shrunk: Vec<char> = chars.clone();
+                    shrunk[i] = alphabet[0];
+                    candidates.push(shrunk);
+                }
+            }
+            candidates.into_iter().map(|it| it.into_iter().collect::<String>().to_value()).collect()
+        },
+    }
+}
+
+/// Returns candidates formed by removing elements from `items`: all of them, then each half, and then each single element.
+fn removals<T : Clone>(items: &[T]) -> Vec<Vec<T>> {
+    let mut candidates: Vec<Vec<T>> = Vec::new();
+    if items.is_empty() {
+        return candidates
+    }
+    candidates.push(Vec::new());
+    if items.len() >= 4 {
+        let mid: usize = items.len() / 2;
+        candidates.push(items[mid..].to_vec());
+        candidates.push(items[..mid].to_vec());
+    }
+    if items.len() >= 2 {
+        for i in 0..items.len() {
+            let mut shrunk: Vec<T> = items.to_vec();
+            shrunk.remove(i);
+            candidates.push(shrunk);
+        }
+    }
+    candidates
+}
+
+/// Returns the seed for `forall()`, from the environment if set, otherwise from the VM's source of random bytes, if present, or the current time.
+fn seed<VM : VirtualInterface>(vm: &mut VM) -> ErrorResult<u64> {
+    let value: ValuePtr = vm.get_env(&String::from(SEED_VAR));
+    if !value.is_nil() {
+        return match value.as_str().borrow_const().trim().parse::<u64>() {
+            Ok(seed) => Ok(seed),
+            Err(_) => TypeErrorCannotConvertToInt(value).err(),
+        }
+    }
+    if let Some(entropy) = vm.entropy() {
+        let mut bytes: [u8; 8] = [0; 8];
+        if let Err(err) = entropy.fill(&mut bytes) {
+            return IOError(err.to_string()).err()
+        }
+        return Ok(u64::from_le_bytes(bytes))
+    }
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64)
+}
+
+fn to_len(n: ValuePtr) -> ErrorResult<usize> {
+    let n: i64 = n.check_int()?.as_int();
+    match n < 0 {
+        true => ValueErrorValueMustBeNonNegative(n).err(),
+        false => Ok(n as usize),
+    }
+}
+
+
+/// A small, fast, deterministic pseudo-random number generator ([SplitMix64](https://prng.di.unimi.it/splitmix64.c)), so that a failing property can be reproduced from its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z: u64 = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random integer in the range `[lo, hi]`, inclusive.
+    fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        let span: u64 = (hi as u64).wrapping_sub(lo as u64).wrapping_add(1);
+        lo.wrapping_add((self.next() % span) as i64)
+    }
+}
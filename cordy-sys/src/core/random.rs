@@ -0,0 +1,78 @@
+use std::fmt::Write;
+use std::io;
+
+use crate::vm::{AnyResult, ErrorResult, IntoIterableValue, IntoValue, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// Returns a random (version 4) UUID, formatted as a lowercase hyphenated string, i.e. `xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx`.
+pub fn uuid4<VM : VirtualInterface>(vm: &mut VM) -> ValueResult {
+    let mut bytes: [u8; 16] = [0; 16];
+    fill(vm, &mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40; // Version 4
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // Variant 1 (RFC 4122)
+
+    let hex: String = to_hex(&bytes);
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]).to_value().ok()
+}
+
+/// Returns a list of `n` random bytes, as integers in the range `[0, 256)`.
+pub fn rand_bytes<VM : VirtualInterface>(vm: &mut VM, n: ValuePtr) -> ValueResult {
+    random(vm, n)?.into_iter()
+        .map(|b| (b as i64).to_value())
+        .to_list()
+        .ok()
+}
+
+/// Returns a string of `n` random bytes, formatted as `2 * n` lowercase hexadecimal digits.
+pub fn rand_hex<VM : VirtualInterface>(vm: &mut VM, n: ValuePtr) -> ValueResult {
+    to_hex(&random(vm, n)?).to_value().ok()
+}
+
+fn random<VM : VirtualInterface>(vm: &mut VM, n: ValuePtr) -> ErrorResult<Vec<u8>> {
+    let n: i64 = n.check_int()?.as_int();
+    if n < 0 {
+        return ValueErrorValueMustBeNonNegative(n).err()
+    }
+    let mut bytes: Vec<u8> = Vec::new();
+    if bytes.try_reserve_exact(n as usize).is_err() {
+        return ValueErrorCannotReserveCapacity(n).err()
+    }
+    bytes.resize(n as usize, 0);
+    fill(vm, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Fills `buf` with random bytes, from the source provided by the host if present, otherwise from the operating system.
+fn fill<VM : VirtualInterface>(vm: &mut VM, buf: &mut [u8]) -> AnyResult {
+    let result: io::Result<()> = match vm.entropy() {
+        Some(entropy) => entropy.fill(buf),
+        None => return fill_from_os(buf),
+    };
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+#[cfg(feature = "entropy")]
+fn fill_from_os(buf: &mut [u8]) -> AnyResult {
+    match getrandom::getrandom(buf) {
+        Ok(_) => Ok(()),
+        Err(err) => IOError(err.to_string()).err(),
+    }
+}
+
+#[cfg(not(feature = "entropy"))]
+fn fill_from_os(_: &mut [u8]) -> AnyResult {
+    ValueErrorFeatureNotEnabled("entropy").err()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex: String = String::with_capacity(2 * bytes.len());
+    for b in bytes {
+        write!(hex, "{:02x}", b).unwrap();
+    }
+    hex
+}
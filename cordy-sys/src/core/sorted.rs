@@ -0,0 +1,70 @@
+use crate::vm::{AnyResult, ErrorResult, IntoIterableValue, IntoValue, RuntimeError, SortedDictImpl, SortedSetImpl, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// Collects an iterator into a new sorted set, freezing each element.
+pub fn collect_into_sorted_set(iter: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    iter.inspect(|v| v.freeze())
+        .collect::<SortedSetImpl>()
+        .to_value()
+        .ok()
+}
+
+/// Collects an iterator of key-value pairs into a new sorted dict, freezing each key.
+pub fn collect_into_sorted_dict(iter: impl Iterator<Item=ValuePtr>) -> ValueResult {
+    iter.map(|t| t.to_pair())
+        .collect::<ErrorResult<Vec<(ValuePtr, ValuePtr)>>>()?
+        .into_iter()
+        .inspect(|(k, _)| k.freeze())
+        .collect::<SortedDictImpl>()
+        .to_value()
+        .ok()
+}
+
+pub fn get_index(dict: &ValuePtr, key: ValuePtr) -> ValueResult {
+    match dict.as_sorted_dict().borrow().get(&key) {
+        Some(value) => value.clone().ok(),
+        None => ValueErrorKeyNotPresent(key).err(),
+    }
+}
+
+pub fn set_index(dict: &ValuePtr, key: ValuePtr, value: ValuePtr) -> AnyResult {
+    key.freeze();
+    dict.check_mutable()?; // Checked after freezing the key, as if `dict` is reachable from `key`, it cannot be compared while it is being mutated
+    dict.as_sorted_dict().borrow_mut().insert(key, value);
+    Ok(())
+}
+
+/// Returns a list of the elements of a sorted set, or key-value pairs of a sorted dict, in the range `[lo, hi)`. This takes `O(log n + k)` time, where `k` is the number of elements returned.
+pub fn between(lo: ValuePtr, hi: ValuePtr, target: ValuePtr) -> ValueResult {
+    if target.is_sorted_set() {
+        target.as_sorted_set().borrow().between(&lo, &hi).into_iter().to_list().ok()
+    } else if target.is_sorted_dict() {
+        target.as_sorted_dict().borrow().between(&lo, &hi).into_iter().map(|u| u.to_value()).to_list().ok()
+    } else {
+        TypeErrorArgMustBeSorted(target).err()
+    }
+}
+
+/// Returns the largest element of a sorted set, or key-value pair of a sorted dict, which is less than or equal to `value`, or `nil` if there is none.
+pub fn floor(value: ValuePtr, target: ValuePtr) -> ValueResult {
+    if target.is_sorted_set() {
+        target.as_sorted_set().borrow().floor(&value).unwrap_or_else(ValuePtr::nil).ok()
+    } else if target.is_sorted_dict() {
+        target.as_sorted_dict().borrow().floor(&value).map_or_else(ValuePtr::nil, |u| u.to_value()).ok()
+    } else {
+        TypeErrorArgMustBeSorted(target).err()
+    }
+}
+
+/// Returns the smallest element of a sorted set, or key-value pair of a sorted dict, which is greater than or equal to `value`, or `nil` if there is none.
+pub fn ceiling(value: ValuePtr, target: ValuePtr) -> ValueResult {
+    if target.is_sorted_set() {
+        target.as_sorted_set().borrow().ceiling(&value).unwrap_or_else(ValuePtr::nil).ok()
+    } else if target.is_sorted_dict() {
+        target.as_sorted_dict().borrow().ceiling(&value).map_or_else(ValuePtr::nil, |u| u.to_value()).ok()
+    } else {
+        TypeErrorArgMustBeSorted(target).err()
+    }
+}
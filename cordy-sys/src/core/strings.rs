@@ -4,8 +4,7 @@ use fancy_regex::{Captures, Matches, Regex};
 use itertools::Itertools;
 
 use crate::core::InvokeArg1;
-use crate::util;
-use crate::vm::{ErrorResult, IntoIterableValue, IntoValue, Iterable, Prefix, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+use crate::vm::{ErrorResult, IntoIterableValue, IntoValue, Iterable, MAX_INT, MIN_INT, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
 
 use RuntimeError::{*};
 
@@ -43,19 +42,17 @@ pub fn replace<VM: VirtualInterface>(vm: &mut VM, pattern: ValuePtr, replacer: V
     let text = target.as_str().borrow_const().as_str();
     if replacer.is_evaluable() {
         let replacer: InvokeArg1 = InvokeArg1::from(replacer)?;
-        let mut err = None;
-        let replaced: ValuePtr = regex.replace_all(text, |captures: &Captures| {
-            let arg: ValuePtr = as_result(captures);
-            let ret: String = util::catch::<String>(&mut err, || {
-                Ok(replacer.invoke(arg, vm)?
-                    .check_str()?
-                    .as_str()
-                    .borrow_const()
-                    .to_string())
-            }, String::new());
-            ret
-        }).to_value();
-        util::join::<ValuePtr, Box<Prefix<RuntimeError>>, ValueResult>(replaced, err)
+        let mut replaced: String = String::with_capacity(text.len());
+        let mut last: usize = 0;
+        for captures in regex.captures_iter(text) {
+            let captures: Captures = captures.unwrap();
+            let group = captures.get(0).unwrap();
+            replaced.push_str(&text[last..group.start()]);
+            replaced.push_str(replacer.invoke(as_result(&captures), vm)?.check_str()?.as_str().borrow_const());
+            last = group.end();
+        }
+        replaced.push_str(&text[last..]);
+        replaced.to_value().ok()
     } else {
         regex.replace_all(text, replacer.check_str()?.as_str().borrow_const().as_str())
             .to_value()
@@ -80,6 +77,83 @@ pub fn search(pattern: ValuePtr, target: ValuePtr) -> ValueResult {
     }).to_list().ok()
 }
 
+/// Matches the entire string `target` against the scan format `format`, and returns a vector of the value of each token, or `nil` if it does not match.
+///
+/// Tokens are written as `{int}`, which matches an optionally signed integer, `{word}`, which matches one or more non-whitespace characters, and `{str}`,
+/// which matches as few characters as possible. All other text must match exactly, and literal braces are written as `{{` and `}}`.
+pub fn scan(format: ValuePtr, target: ValuePtr) -> ValueResult {
+    let format = format.check_str()?;
+    let target = target.check_str()?;
+    let (regex, tokens) = compile_scan_format(format.as_str().borrow_const())?;
+    let captures: Captures = match regex.captures(target.as_str().borrow_const()).unwrap() {
+        Some(captures) => captures,
+        None => return ValuePtr::nil().ok(),
+    };
+
+    let mut values: Vec<ValuePtr> = Vec::with_capacity(tokens.len());
+    for (token, group) in tokens.iter().zip(captures.iter().skip(1)) {
+        let group: &str = group.unwrap().as_str();
+        values.push(match token {
+            ScanToken::Int => match group.parse::<i64>() {
+                Ok(it) if (MIN_INT..=MAX_INT).contains(&it) => it.to_value(),
+                _ => return ValuePtr::nil().ok(), // Too large to be an `int`, so the string does not match
+            },
+            ScanToken::Word | ScanToken::Str => group.to_value(),
+        });
+    }
+    values.into_iter().to_vector().ok()
+}
+
+enum ScanToken { Int, Word, Str }
+
+/// Compiles a scan format into an equivalent regex, with one capture group for each token.
+fn compile_scan_format(format: &str) -> ErrorResult<(Regex, Vec<ScanToken>)> {
+    let mut raw: String = String::from("(?s)\\A");
+    let mut tokens: Vec<ScanToken> = Vec::new();
+    let mut literal: String = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            },
+            '{' => {
+                let mut name: String = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return ValueErrorUnmatchedBraceInScanFormat('{').err(),
+                    }
+                }
+                let (token, pattern) = match name.as_str() {
+                    "int" => (ScanToken::Int, "([+-]?[0-9]+)"),
+                    "word" => (ScanToken::Word, "(\\S+)"),
+                    "str" => (ScanToken::Str, "(.*?)"),
+                    _ => return ValueErrorInvalidScanToken(name).err(),
+                };
+                raw.push_str(&fancy_regex::escape(&literal));
+                raw.push_str(pattern);
+                literal.clear();
+                tokens.push(token);
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            },
+            '}' => return ValueErrorUnmatchedBraceInScanFormat('}').err(),
+            c => literal.push(c),
+        }
+    }
+    raw.push_str(&fancy_regex::escape(&literal));
+    raw.push_str("\\z");
+    match Regex::new(&raw) {
+        Ok(regex) => Ok((regex, tokens)),
+        Err(e) => ValueErrorCannotCompileRegex(raw, e.to_string()).err()
+    }
+}
+
 pub fn split(pattern: ValuePtr, target: ValuePtr) -> ValueResult {
     let pattern = pattern.check_str()?;
     let target = target.check_str()?;
@@ -180,6 +254,35 @@ pub fn join(joiner: ValuePtr, it: ValuePtr) -> ValueResult {
 }
 
 
+/// Returns the character index of the first occurrence of the substring `sub` in `target`, at or after the character index `start`, or `-1` if not found.
+/// Both `sub` and `target` must be strings. This is a literal search, and does not use regular expressions.
+pub fn index_of(sub: ValuePtr, target: ValuePtr, start: usize) -> ValuePtr {
+    let sub: &str = sub.as_str().borrow_const();
+    let target: &str = target.as_str().borrow_const();
+
+    // Convert `start` into a byte offset, allowing `start` to be the end of the string
+    let offset: usize = match target.char_indices().map(|(i, _)| i).chain(std::iter::once(target.len())).nth(start) {
+        Some(offset) => offset,
+        None => return (-1i64).to_value(),
+    };
+    match target[offset..].find(sub) {
+        Some(i) => (start + target[offset..offset + i].chars().count()) as i64,
+        None => -1,
+    }.to_value()
+}
+
+/// Returns the character index of the last occurrence of the substring `sub` in `target`, or `-1` if not found.
+/// Both `sub` and `target` must be strings.
+pub fn right_index_of(sub: ValuePtr, target: ValuePtr) -> ValuePtr {
+    let sub: &str = sub.as_str().borrow_const();
+    let target: &str = target.as_str().borrow_const();
+    match target.rfind(sub) {
+        Some(i) => target[..i].chars().count() as i64,
+        None => -1,
+    }.to_value()
+}
+
+
 pub fn to_char(value: ValuePtr) -> ValueResult {
     let i = value.check_int()?.as_int();
     if i <= 0 {
@@ -194,8 +297,9 @@ pub fn to_char(value: ValuePtr) -> ValueResult {
 pub fn to_ord(value: ValuePtr) -> ValueResult {
     let value = value.check_str()?;
     let s = value.as_str().borrow_const();
-    match s.len() {
-        1 => (s.chars().next().unwrap() as u32 as i64)
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => (c as u32 as i64)
             .to_value()
             .ok(),
         _ => TypeErrorArgMustBeChar(s.clone().to_value()).err(),
@@ -210,10 +314,87 @@ pub fn to_bin(value: ValuePtr) -> ValueResult {
     format!("{:b}", value.check_int()?.as_int()).to_value().ok()
 }
 
+/// Renders `text`, replacing each `{name}` with the value of `name` in the dictionary `args`, converted to a string as with `str()`.
+/// Names may contain `.` to access nested values, i.e. `{user.name}` looks up `user`, and then `name` in that value, which may either be a dictionary with string keys, or a struct.
+/// Literal braces are written as `{{` and `}}`.
+pub fn template(text: ValuePtr, args: ValuePtr) -> ValueResult {
+    let text = text.check_str()?;
+    let args = args.check_dict()?;
+    let text: &str = text.as_str().borrow_const();
+
+    let mut output: String = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            },
+            '{' => {
+                let mut name: String = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return ValueErrorUnmatchedBraceInTemplate('{').err(),
+                    }
+                }
+                let mut value: ValuePtr = args.clone();
+                for field in name.split('.') {
+                    value = template_field(value, field.trim())?;
+                }
+                output.push_str(value.to_str().as_str());
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            },
+            '}' => return ValueErrorUnmatchedBraceInTemplate('}').err(),
+            c => output.push(c),
+        }
+    }
+    output.to_value().ok()
+}
+
+fn template_field(value: ValuePtr, field: &str) -> ValueResult {
+    if value.is_dict() {
+        let key: ValuePtr = field.to_value();
+        return match value.as_dict().borrow().dict.get(&key) {
+            Some(v) => v.clone().ok(),
+            None => ValueErrorKeyNotPresent(key).err(),
+        }
+    }
+    match value.get_field_by_name(field) {
+        Some(v) => v.ok(),
+        None => TypeErrorFieldNotPresentOnValue(value, String::from(field), false).err(),
+    }
+}
+
+/// Returns `true` if `value` is a non-empty string, where every character satisfies `predicate`.
+pub fn is_all(value: ValuePtr, predicate: fn(char) -> bool) -> ValueResult {
+    let value = value.check_str()?;
+    let s: &str = value.as_str().borrow_const();
+    (!s.is_empty() && s.chars().all(predicate))
+        .to_value()
+        .ok()
+}
+
 pub fn format_string(literal: &String, args: ValuePtr) -> ValueResult {
     StringFormatter::format(literal, args)
 }
 
+/// Inserts a `,` between every group of three digits, counting from the right.
+fn group_thousands(digits: &str) -> String {
+    let mut output: String = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            output.push(',');
+        }
+        output.push(c);
+    }
+    output
+}
+
 struct StringFormatter<'a> {
     chars: Peekable<Chars<'a>>,
     args: Iterable,
@@ -246,48 +427,94 @@ impl<'a> StringFormatter<'a> {
                         continue
                     }
 
-                    let is_zero_padded: bool = match self.peek() {
-                        Some('0') => {
-                            self.next();
-                            true
-                        },
-                        _ => false
-                    };
-
-                    let mut buffer: String = String::new();
+                    // Flags, which may each appear at most once, in any order
+                    let mut is_left_justified: bool = false;
+                    let mut is_zero_padded: bool = false;
+                    let mut is_signed: bool = false;
+                    let mut is_grouped: bool = false;
                     loop {
-                        match self.peek() {
-                            Some(c @ ('1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9')) => {
-                                buffer.push(*c);
-                                self.next();
-                            },
-                            Some('0') => {
-                                self.next();
-                                if buffer.is_empty() {
-                                    return ValueErrorInvalidFormatCharacter(Some('0')).err()
-                                }
-                                buffer.push('0');
-                            },
+                        let flag: &mut bool = match self.peek() {
+                            Some('-') => &mut is_left_justified,
+                            Some('0') => &mut is_zero_padded,
+                            Some('+') => &mut is_signed,
+                            Some(',') => &mut is_grouped,
                             _ => break
+                        };
+                        if *flag {
+                            return ValueErrorInvalidFormatCharacter(self.peek().cloned()).err()
                         }
+                        *flag = true;
+                        self.next();
                     }
 
-                    let padding: usize = if buffer.is_empty() { 0 } else { buffer.parse::<usize>().unwrap() };
-
-                    let text = match (self.peek(), is_zero_padded) {
-                        (Some('d'), false) => format!("{:width$}", self.arg()?.check_int()?.as_int(), width = padding),
-                        (Some('d'), true) => format!("{:0width$}", self.arg()?.check_int()?.as_int(), width = padding),
-                        (Some('x'), false) => format!("{:width$x}", self.arg()?.check_int()?.as_int(), width = padding),
-                        (Some('x'), true) => format!("{:0width$x}", self.arg()?.check_int()?.as_int(), width = padding),
-                        (Some('b'), false) => format!("{:width$b}", self.arg()?.check_int()?.as_int(), width = padding),
-                        (Some('b'), true) => format!("{:0width$b}", self.arg()?.check_int()?.as_int(), width = padding),
-                        (Some('s'), true) => format!("{:width$}", self.arg()?.to_str(), width = padding),
-                        (Some('s'), false) => format!("{:0width$}", self.arg()?.to_str(), width = padding),
-                        (c, _) => return ValueErrorInvalidFormatCharacter(c.cloned()).err(),
+                    let padding: usize = self.number()?.unwrap_or(0);
+                    let precision: Option<usize> = match self.peek() {
+                        Some('.') => {
+                            self.next();
+                            match self.peek() {
+                                Some('0') => {
+                                    self.next();
+                                    Some(0)
+                                },
+                                _ => Some(self.number()?.unwrap_or(0)),
+                            }
+                        },
+                        _ => None
                     };
 
+                    let (sign, body, is_numeric): (&str, String, bool) = match self.peek() {
+                        Some(c @ ('d' | 'x' | 'b')) => {
+                            let c: char = *c;
+                            if is_grouped && c != 'd' {
+                                return ValueErrorInvalidFormatCharacter(Some(',')).err()
+                            }
+                            let int: i64 = self.arg()?.check_int()?.as_int();
+                            let mut digits: String = match c {
+                                'd' => int.unsigned_abs().to_string(),
+                                'x' => format!("{:x}", int),
+                                _ => format!("{:b}", int),
+                            };
+                            if let Some(precision) = precision {
+                                if digits.len() < precision {
+                                    digits.insert_str(0, &"0".repeat(precision - digits.len()));
+                                }
+                            }
+                            if is_grouped {
+                                digits = group_thousands(&digits);
+                            }
+                            let sign: &str = if c == 'd' && int < 0 { "-" } else if is_signed { "+" } else { "" };
+                            (sign, digits, true)
+                        },
+                        Some('s') => {
+                            if is_grouped {
+                                return ValueErrorInvalidFormatCharacter(Some(',')).err()
+                            }
+                            let text: String = self.arg()?.to_str();
+                            let text: String = match precision {
+                                Some(precision) => text.chars().take(precision).collect(),
+                                None => text,
+                            };
+                            ("", text, false)
+                        },
+                        c => return ValueErrorInvalidFormatCharacter(c.cloned()).err(),
+                    };
                     self.next();
-                    self.output.push_str(text.as_str());
+
+                    // Left justification always pads with spaces, and zero padding is only applied to numeric values, after the sign
+                    let fill: usize = padding.saturating_sub(sign.len() + body.chars().count());
+                    if is_left_justified {
+                        self.output.push_str(sign);
+                        self.output.push_str(body.as_str());
+                        self.output.push_str(&" ".repeat(fill));
+                    } else if is_zero_padded && is_numeric {
+                        self.output.push_str(sign);
+                        self.output.push_str(&"0".repeat(fill));
+                        self.output.push_str(body.as_str());
+                    } else {
+                        self.output.push_str(&" ".repeat(fill));
+                        self.output.push_str(sign);
+                        self.output.push_str(body.as_str());
+                    }
                 },
                 Some(c) => self.push(c),
                 None => break
@@ -299,6 +526,28 @@ impl<'a> StringFormatter<'a> {
         }
     }
 
+    /// Parses an optional decimal number, used for the width and precision of a format specifier. Leading zeros are not allowed.
+    fn number(&mut self) -> ErrorResult<Option<usize>> {
+        let mut buffer: String = String::new();
+        loop {
+            match self.peek() {
+                Some(c @ ('1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9')) => {
+                    buffer.push(*c);
+                    self.next();
+                },
+                Some('0') => {
+                    self.next();
+                    if buffer.is_empty() {
+                        return ValueErrorInvalidFormatCharacter(Some('0')).err()
+                    }
+                    buffer.push('0');
+                },
+                _ => break
+            }
+        }
+        Ok(if buffer.is_empty() { None } else { Some(buffer.parse::<usize>().unwrap()) })
+    }
+
     fn next(&mut self) -> Option<char> { self.chars.next() }
     fn peek(&mut self) -> Option<&char> { self.chars.peek() }
     fn push(&mut self, c: char) { self.output.push(c); }
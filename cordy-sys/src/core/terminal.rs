@@ -0,0 +1,63 @@
+use crate::core::NativeFunction;
+use crate::vm::{IntoValue, RuntimeError, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// Returns the size of the terminal, as a vector of `(columns, rows)`.
+pub fn term_size<VM : VirtualInterface>(vm: &mut VM) -> ValueResult {
+    match vm.terminal() {
+        Some(terminal) => match terminal.size() {
+            Ok((cols, rows)) => vec![(cols as i64).to_value(), (rows as i64).to_value()].to_value().ok(),
+            Err(err) => IOError(err.to_string()).err(),
+        },
+        None => ValueErrorNotInteractive(NativeFunction::TermSize).err(),
+    }
+}
+
+/// Reads a single key press from the terminal, without waiting for a newline. See `Terminal::read_key()` for how keys are named.
+pub fn read_key<VM : VirtualInterface>(vm: &mut VM) -> ValueResult {
+    match vm.terminal() {
+        Some(terminal) => match terminal.read_key() {
+            Ok(key) => key.to_value().ok(),
+            Err(err) => IOError(err.to_string()).err(),
+        },
+        None => ValueErrorNotInteractive(NativeFunction::ReadKey).err(),
+    }
+}
+
+/// Clears the screen and moves the cursor to the top left. This only writes an ANSI escape sequence to the output, so it does not require an interactive terminal.
+pub fn clear_screen<VM : VirtualInterface>(vm: &mut VM) -> ValueResult {
+    vm.print(String::from("\x1b[2J\x1b[H"));
+    vm.flush();
+    ValuePtr::nil().ok()
+}
+
+/// Wraps `text` in the ANSI escape sequences for the color or style `name`, followed by a reset.
+pub fn color(name: ValuePtr, text: ValuePtr) -> ValueResult {
+    let name = name.check_str()?;
+    let code: u8 = match name.as_str().borrow_const().as_str() {
+        "bold" => 1,
+        "dim" => 2,
+        "italic" => 3,
+        "underline" => 4,
+        "black" => 30,
+        "red" => 31,
+        "green" => 32,
+        "yellow" => 33,
+        "blue" => 34,
+        "magenta" => 35,
+        "cyan" => 36,
+        "white" => 37,
+        "bright_black" => 90,
+        "bright_red" => 91,
+        "bright_green" => 92,
+        "bright_yellow" => 93,
+        "bright_blue" => 94,
+        "bright_magenta" => 95,
+        "bright_cyan" => 96,
+        "bright_white" => 97,
+        _ => return ValueErrorInvalidColor(name.clone()).err(),
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text.to_str()).to_value().ok()
+}
@@ -0,0 +1,138 @@
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::vm::{IntoValue, LazyIterImpl, MAX_INT, RuntimeError, StopwatchImpl, TaskImpl, TaskState, ValuePtr, ValueResult, VirtualInterface};
+
+use RuntimeError::{*};
+
+
+/// Invokes `f` with no arguments `n` times (defaulting to once), and returns the total elapsed time in nanoseconds.
+pub fn timeit<VM : VirtualInterface>(vm: &mut VM, f: ValuePtr, n: Option<ValuePtr>) -> ValueResult {
+    if !f.is_evaluable() {
+        return TypeErrorArgMustBeFunction(f).err()
+    }
+    let n: i64 = match n {
+        Some(n) => n.check_int()?.as_int(),
+        None => 1,
+    };
+    if n < 0 {
+        return ValueErrorValueMustBeNonNegative(n).err()
+    }
+    let start: Instant = Instant::now();
+    for _ in 0..n {
+        vm.call(f.clone(), [])?;
+    }
+    to_nanos(start.elapsed()).ok()
+}
+
+pub fn stopwatch() -> ValueResult {
+    StopwatchImpl::now().to_value().ok()
+}
+
+/// Returns the time since the last lap of the stopwatch, in nanoseconds, and starts a new lap.
+pub fn lap(stopwatch: ValuePtr) -> ValueResult {
+    let stopwatch = stopwatch.check_stopwatch()?;
+    let lap: Duration = stopwatch.as_stopwatch().borrow_mut().lap();
+    to_nanos(lap).ok()
+}
+
+/// Returns the time since the stopwatch was started, in nanoseconds.
+pub fn elapsed(stopwatch: ValuePtr) -> ValueResult {
+    let stopwatch = stopwatch.check_stopwatch()?;
+    let elapsed: Duration = stopwatch.as_stopwatch().borrow().start.elapsed();
+    to_nanos(elapsed).ok()
+}
+
+/// Returns `x` if it is a task, otherwise a task which has already completed with `x`.
+pub fn task(x: ValuePtr) -> ValueResult {
+    match x.is_task() {
+        true => x.ok(),
+        false => TaskImpl::new(x.to_repr_str(), TaskState::Done(x)).to_value().ok(),
+    }
+}
+
+/// Returns a task which completes with `nil`, once `ms` milliseconds have passed.
+pub fn sleep_async<VM : VirtualInterface>(vm: &mut VM, ms: ValuePtr) -> ValueResult {
+    let ms: i64 = ms.check_int()?.as_int();
+    if ms < 0 {
+        return ValueErrorValueMustBeNonNegative(ms).err()
+    }
+    let task: ValuePtr = TaskImpl::new(format!("sleep_async({})", ms), TaskState::Sleeping(Instant::now() + Duration::from_millis(ms as u64))).to_value();
+    vm.spawn(task.clone());
+    task.ok()
+}
+
+/// Wraps `it` in a lazy iterable which reports its progress to standard error. If `total` is not given, it is the length of `it`, unless `it` is infinite.
+pub fn progress(it: ValuePtr, total: Option<ValuePtr>) -> ValueResult {
+    let it = it.check_iter()?;
+    let total: Option<usize> = match total {
+        Some(total) => {
+            let total: i64 = total.check_int()?.as_int();
+            if total < 0 {
+                return ValueErrorValueMustBeNonNegative(total).err()
+            }
+            Some(total as usize)
+        },
+        None => None,
+    };
+    LazyIterImpl::Progress(it, total).to_value().ok()
+}
+
+fn to_nanos(duration: Duration) -> ValuePtr {
+    (duration.as_nanos().min(MAX_INT as u128) as i64).to_value()
+}
+
+
+/// The state of a progress bar, drawn while iterating a `progress()` iterable.
+///
+/// The bar is written directly to the process's standard error, rather than the VM's error stream, as it is drawn from within an `Iterable`, which has no access to the VM.
+/// For the same reason, it is only drawn when standard error is a terminal, so it does not appear in logs or piped output.
+#[derive(Debug, Clone)]
+pub struct ProgressBar {
+    count: usize,
+    total: Option<usize>,
+    enabled: bool,
+    finished: bool,
+    last_draw: Option<Instant>,
+}
+
+impl ProgressBar {
+    const WIDTH: usize = 30;
+    const INTERVAL: Duration = Duration::from_millis(50);
+
+    pub fn new(total: Option<usize>) -> ProgressBar {
+        ProgressBar { count: 0, total, enabled: io::stderr().is_terminal(), finished: false, last_draw: None }
+    }
+
+    /// Records a single element being produced, and redraws the bar if enough time has passed since it was last drawn.
+    pub fn step(&mut self) {
+        self.count += 1;
+        if self.enabled && self.last_draw.is_none_or(|last| last.elapsed() >= ProgressBar::INTERVAL) {
+            self.draw();
+        }
+    }
+
+    /// Draws the bar in its final state, and moves to the next line. This is only done once, even if the iterable is polled again after it is exhausted.
+    pub fn finish(&mut self) {
+        if self.enabled && !self.finished {
+            self.finished = true;
+            self.draw();
+            eprintln!();
+        }
+    }
+
+    fn draw(&mut self) {
+        self.last_draw = Some(Instant::now());
+        let line: String = match self.total {
+            Some(total) if total > 0 => {
+                let filled: usize = self.count.min(total) * ProgressBar::WIDTH / total;
+                format!("\r[{}{}] {:>3}% ({}/{})", "#".repeat(filled), "-".repeat(ProgressBar::WIDTH - filled), self.count * 100 / total, self.count, total)
+            },
+            _ => format!("\r{} items", self.count),
+        };
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "{}", line);
+        let _ = stderr.flush();
+    }
+}
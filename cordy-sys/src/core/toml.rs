@@ -0,0 +1,70 @@
+#[cfg(feature = "toml")]
+use itertools::Itertools;
+#[cfg(feature = "toml")]
+use toml::{Table, Value};
+
+#[cfg(feature = "toml")]
+use crate::vm::{ErrorResult, IntoDictValue, IntoIterableValue, IntoValue, MAX_INT, MIN_INT, R64};
+use crate::vm::{RuntimeError, ValuePtr, ValueResult};
+
+use RuntimeError::{*};
+
+
+/// Parses `text` as a TOML document, returning the top level table as a `dict`.
+///
+/// Tables map to `dict`s with `str` keys, arrays to `list`s, and strings, integers and booleans to their respective Cordy types.
+/// As Cordy has no floating point type, floats are converted to the nearest `fraction` (or an `int`, if they are whole), and dates and times are converted to their string representation.
+#[cfg(feature = "toml")]
+pub fn decode(text: ValuePtr) -> ValueResult {
+    let text = text.check_str()?;
+    let text: &str = text.as_str().borrow_const();
+    match text.parse::<Table>() {
+        Ok(table) => from_table(table),
+        Err(e) => {
+            // The default error message includes a multi-line snippet, so instead report a single line with the message and position
+            let offset: usize = e.span().map(|span| span.start).unwrap_or(0);
+            let line: usize = text[..offset].matches('\n').count() + 1;
+            let col: usize = text[..offset].chars().rev().take_while(|c| *c != '\n').count() + 1;
+            let message: String = e.message().trim().lines().join(", ");
+            let message: &str = if message.is_empty() { "invalid syntax" } else { message.as_str() };
+            ValueErrorCannotDecodeToml(format!("{} at line {}, column {}", message, line, col)).err()
+        },
+    }
+}
+
+#[cfg(not(feature = "toml"))]
+pub fn decode(_: ValuePtr) -> ValueResult {
+    ValueErrorFeatureNotEnabled("toml").err()
+}
+
+#[cfg(feature = "toml")]
+fn from_table(table: Table) -> ValueResult {
+    table.into_iter()
+        .map(|(k, v)| Ok((k.to_value(), from_value(v)?)))
+        .collect::<ErrorResult<Vec<(ValuePtr, ValuePtr)>>>()?
+        .into_iter()
+        .to_dict()
+        .ok()
+}
+
+#[cfg(feature = "toml")]
+fn from_value(value: Value) -> ValueResult {
+    match value {
+        Value::String(it) => it.to_value().ok(),
+        Value::Integer(it) if (MIN_INT..=MAX_INT).contains(&it) => it.to_value().ok(),
+        Value::Integer(it) => ValueErrorCannotDecodeToml(format!("integer {} is out of range", it)).err(),
+        Value::Float(it) => match R64::approximate_float(it) {
+            Some(f) if !f.is_integer() || (MIN_INT..=MAX_INT).contains(&f.to_integer()) => f.to_value().ok(),
+            _ => ValueErrorCannotDecodeToml(format!("float {} cannot be represented as a fraction", it)).err(),
+        },
+        Value::Boolean(it) => it.to_value().ok(),
+        Value::Datetime(it) => it.to_string().to_value().ok(),
+        Value::Array(it) => it.into_iter()
+            .map(from_value)
+            .collect::<ErrorResult<Vec<ValuePtr>>>()?
+            .into_iter()
+            .to_list()
+            .ok(),
+        Value::Table(it) => from_table(it),
+    }
+}
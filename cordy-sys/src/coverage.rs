@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+
+use crate::reporting::{Location, SourceView};
+
+
+/// The lines executed by a program, recorded by a VM created with `VirtualMachine::with_coverage()`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Coverage {
+    pub files: Vec<FileCoverage>,
+}
+
+/// The lines executed within a single source file, or other entry in the `SourceView`, such as `<eval>`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FileCoverage {
+    pub name: String,
+    /// The number of times each executable line was executed, by line number, starting at `1`. Lines which contain no code are not present.
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl Coverage {
+    /// Builds the coverage from `hits`, the number of times each instruction was executed, and `locations`, the location of each instruction.
+    pub(crate) fn new(view: &SourceView, locations: &[Location], hits: &[u64]) -> Coverage {
+        let mut files: BTreeMap<u32, FileCoverage> = BTreeMap::new();
+        for (ip, loc) in locations.iter().enumerate() {
+            let lineno: usize = match view.lineno(*loc) {
+                Some(lineno) => lineno + 1,
+                None => continue,
+            };
+            let file: &mut FileCoverage = files.entry(loc.index())
+                .or_insert_with(|| FileCoverage { name: view.name_of(*loc).clone(), lines: BTreeMap::new() });
+            let count: &mut u64 = file.lines.entry(lineno).or_insert(0);
+            *count = (*count).max(hits.get(ip).copied().unwrap_or(0));
+        }
+        Coverage { files: files.into_values().collect() }
+    }
+
+    /// Returns a report with one line per file, listing the lines which were not executed, i.e. `main.cor: 9 of 12 lines executed (75.0%), missing 4, 7-8`
+    pub fn report(&self) -> Vec<String> {
+        self.files.iter()
+            .map(|file| {
+                let executed: usize = file.lines.values().filter(|count| **count > 0).count();
+                let total: usize = file.lines.len();
+                let percent: f64 = if total == 0 { 100.0 } else { executed as f64 * 100.0 / total as f64 };
+                let mut line: String = format!("{}: {} of {} lines executed ({:.1}%)", file.name, executed, total, percent);
+                let missing: Vec<String> = ranges(&file.unexecuted());
+                if !missing.is_empty() {
+                    line.push_str(format!(", missing {}", missing.join(", ")).as_str());
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// Returns the coverage in the `lcov` tracefile format, which is understood by most coverage tools.
+    pub fn to_lcov(&self) -> String {
+        let mut text: String = String::new();
+        for file in &self.files {
+            text.push_str(format!("TN:\nSF:{}\n", file.name).as_str());
+            for (lineno, count) in &file.lines {
+                text.push_str(format!("DA:{},{}\n", lineno, count).as_str());
+            }
+            text.push_str(format!("LF:{}\nLH:{}\nend_of_record\n", file.lines.len(), file.lines.values().filter(|count| **count > 0).count()).as_str());
+        }
+        text
+    }
+}
+
+impl FileCoverage {
+    /// Returns the line numbers which contain code, but were never executed.
+    pub fn unexecuted(&self) -> Vec<usize> {
+        self.lines.iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(lineno, _)| *lineno)
+            .collect()
+    }
+}
+
+/// Collapses a sorted list of line numbers into ranges, i.e. `[1, 3, 4, 5]` becomes `1, 3-5`.
+fn ranges(lines: &[usize]) -> Vec<String> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for lineno in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *lineno => *end = *lineno,
+            _ => ranges.push((*lineno, *lineno)),
+        }
+    }
+    ranges.into_iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{compiler, SourceView};
+    use crate::coverage::Coverage;
+    use crate::vm::VirtualMachine;
+
+    fn run(text: &'static str) -> Coverage {
+        let view: SourceView = SourceView::new(String::from("main.cor"), String::from(text));
+        let compile = compiler::compile(false, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).with_coverage();
+        vm.run_until_completion();
+        vm.coverage().unwrap()
+    }
+
+    #[test]
+    fn test_report() {
+        let coverage: Coverage = run("fn f(x) {\n    if x {\n        print('yes')\n    } else {\n        print('no')\n    }\n}\n\nf(true)\n");
+        assert_eq!(coverage.report(), vec!["main.cor: 7 of 8 lines executed (87.5%), missing 5"]);
+    }
+
+    #[test]
+    fn test_report_all_executed() {
+        let coverage: Coverage = run("let x = 1\nprint(x)\n");
+        assert_eq!(coverage.report(), vec!["main.cor: 2 of 2 lines executed (100.0%)"]);
+    }
+
+    #[test]
+    fn test_report_ranges() {
+        let coverage: Coverage = run("fn f() {\n    print(1)\n    print(2)\n}\nfn g() {\n    print(3)\n}\nexit\nf()\n");
+        assert_eq!(coverage.files[0].unexecuted(), vec![2, 3, 4, 6, 7, 9]);
+        assert_eq!(coverage.report(), vec!["main.cor: 3 of 9 lines executed (33.3%), missing 2-4, 6-7, 9"]);
+    }
+
+    #[test]
+    fn test_lcov() {
+        let coverage: Coverage = run("for i in range(3) {\n    print(i)\n}\nif false {\n    print('no')\n}\n");
+        assert_eq!(coverage.to_lcov().as_str(), "TN:\nSF:main.cor\nDA:1,4\nDA:2,3\nDA:3,3\nDA:4,1\nDA:5,0\nDA:6,1\nLF:6\nLH:5\nend_of_record\n");
+    }
+
+    #[test]
+    fn test_without_coverage() {
+        let view: SourceView = SourceView::new(String::from("main.cor"), String::from("print(1)"));
+        let compile = compiler::compile(false, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]);
+        vm.run_until_completion();
+        assert_eq!(vm.coverage(), None);
+    }
+}
@@ -1,11 +1,16 @@
 #![feature(variant_count)]
 #![feature(try_trait_v2)]
+#![feature(try_trait_v2_residual)]
 
 pub use crate::reporting::{AsError, Location, SourceView};
 pub use crate::compiler::ScanTokenType;
+pub use crate::core::{Category, NativeFunction};
 
+pub mod bench;
 pub mod compiler;
+pub mod coverage;
 pub mod repl;
+pub mod session;
 pub mod util;
 pub mod vm;
 
@@ -1,9 +1,9 @@
-use std::io;
-use std::io::{BufRead, Read, Write};
+use std::fs;
+use std::io::{BufRead, Write};
 
 use crate::{compiler, SourceView};
 use crate::compiler::{IncrementalCompileResult, Locals};
-use crate::vm::{ExitType, VirtualInterface, VirtualMachine};
+use crate::vm::{ExitType, Terminal, VirtualInterface, VirtualMachine};
 
 
 /// A trait implementing a predictable, callback-based reader. This is the implementation used by the executable REPL
@@ -17,37 +17,23 @@ pub trait Reader {
     fn read(&mut self, prompt: &'static str) -> ReadResult;
 }
 
-pub struct Repl<W: Write> {
+pub struct Repl<R: BufRead, W: Write, E: Write> {
     /// If `repeat_input` is true, everything written to input will be written directly back to output via the VM's `println` functions
     /// This is used for testing purposes, as the `writer` must be given solely to the VM for output purposes.
     repeat_input: bool,
     continuation: bool,
     locals: Vec<Locals>,
-    vm: VirtualMachine<Empty, W>
+    /// Each input which has compiled and executed without error, in order, which is written by `#save`
+    history: Vec<String>,
+    vm: VirtualMachine<R, W, E>
 }
 
-impl<W : Write> Repl<W> {
+impl<R: BufRead, W : Write, E: Write> Repl<R, W, E> {
     pub fn view(&self) -> &SourceView {
         self.vm.view()
     }
 }
 
-struct Empty;
-
-impl Read for Empty {
-    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
-        Ok(0)
-    }
-}
-
-impl BufRead for Empty {
-    fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        Ok(&[])
-    }
-
-    fn consume(&mut self, _: usize) {}
-}
-
 /// A result returned from a read line operation.
 pub enum ReadResult {
     Exit,
@@ -67,8 +53,13 @@ pub enum RunResult {
 }
 
 /// Create a new REPL, and invoke it in a loop with the given `Reader` until it is exhausted.
-pub fn run<R : Reader, W: Write>(mut reader: R, writer: W, repeat_input: bool) -> Result<(), String> {
-    let mut repl: Repl<W> = Repl::new(writer, repeat_input);
+/// The REPL's input is read from `reader`, whereas `read` is used by the VM for natives like `read_line()`, and `error` for natives like `eprintln()`.
+/// If `terminal` is present, the VM is interactive, see `VirtualMachine::with_terminal()`.
+pub fn run<T : Reader, R: BufRead, W: Write, E: Write>(mut reader: T, read: R, writer: W, error: E, terminal: Option<Box<dyn Terminal>>, repeat_input: bool) -> Result<(), String> {
+    let mut repl: Repl<R, W, E> = Repl::new(read, writer, error, repeat_input);
+    if let Some(terminal) = terminal {
+        repl = repl.with_terminal(terminal);
+    }
     loop {
         let read = reader.read(repl.prompt());
         match repl.run(read) {
@@ -79,9 +70,9 @@ pub fn run<R : Reader, W: Write>(mut reader: R, writer: W, repeat_input: bool) -
     }
 }
 
-impl<W: Write> Repl<W> {
+impl<R: BufRead, W: Write, E: Write> Repl<R, W, E> {
 
-    pub fn new(writer: W, repeat_input: bool) -> Repl<W> {
+    pub fn new(read: R, writer: W, error: E, repeat_input: bool) -> Repl<R, W, E> {
         let compile = compiler::default();
         let view = SourceView::new(String::from("<stdin>"), String::new());
 
@@ -89,10 +80,15 @@ impl<W: Write> Repl<W> {
             repeat_input,
             continuation: false,
             locals: Locals::empty(),
-            vm: VirtualMachine::new(compile, view, Empty, writer, vec![])
+            history: Vec::new(),
+            vm: VirtualMachine::new(compile, view, read, writer, error, vec![])
         }
     }
 
+    pub fn with_terminal(self, terminal: Box<dyn Terminal>) -> Repl<R, W, E> {
+        Repl { vm: self.vm.with_terminal(terminal), ..self }
+    }
+
     pub fn prompt(&self) -> &'static str {
         if self.continuation { "... " } else { ">>> " }
     }
@@ -106,7 +102,7 @@ impl<W: Write> Repl<W> {
                 line
             },
             ReadResult::Error(e) => return RunResult::Error(e),
-            ReadResult::Exit => return RunResult::Exit,
+            ReadResult::Exit => return self.exit(ExitType::Exit(0)),
         };
 
         match line.as_str() {
@@ -122,12 +118,76 @@ impl<W: Write> Repl<W> {
             _ => {},
         }
 
+        if let Some(path) = line.strip_prefix("#save ") {
+            return self.save(path.trim())
+        }
+        if let Some(path) = line.strip_prefix("#load ") {
+            return self.load(path.trim())
+        }
+        if let Some(name) = line.strip_prefix("#closure ") {
+            return self.closure(name.trim())
+        }
+
         let buffer = self.vm.view_mut().text_mut();
 
         buffer.push_str(line.as_str());
         buffer.push('\n');
         self.continuation = false;
+        self.execute()
+    }
+
+    /// Writes each input which has executed successfully so far to `path`, so it can be run as a script, or loaded again with `#load`
+    fn save(&mut self, path: &str) -> RunResult {
+        if fs::write(path, self.history.concat()).is_err() {
+            self.vm.println(format!("Unable to write file '{}'", path));
+        }
+        RunResult::Ok
+    }
+
+    /// Executes the file at `path`, as if it had been entered as a single input, so any globals it declares are available afterwards
+    fn load(&mut self, path: &str) -> RunResult {
+        let mut text: String = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => {
+                self.vm.println(format!("Unable to read file '{}'", path));
+                return RunResult::Ok
+            },
+        };
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+
+        // Any partial input is discarded, and the file is executed with its own name, so errors are reported against it
+        self.vm.view_mut().push(String::from(path), text);
+        self.continuation = false;
+        let result: RunResult = self.execute();
+        if self.continuation {
+            self.vm.println(format!("Unexpected end of file '{}'", path));
+            self.vm.view_mut().push(String::from("<stdin>"), String::new());
+            self.continuation = false;
+        }
+        result
+    }
 
+    /// Prints the name and current value of each upvalue captured by the closure in the global variable `name`
+    fn closure(&mut self, name: &str) -> RunResult {
+        let message: String = match self.vm.global(name) {
+            None => format!("No global variable named '{}'", name),
+            Some(f) => match self.vm.debug_closure(f) {
+                None => format!("'{}' of type '{}' is not a closure", f.to_repr_str(), f.as_type_str()),
+                Some(upvalues) if upvalues.is_empty() => format!("'{}' has no upvalues", f.to_repr_str()),
+                Some(upvalues) => upvalues.iter()
+                    .map(|(name, value)| format!("{} = {}", name, value.to_repr_str()))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            },
+        };
+        self.vm.println(message);
+        RunResult::Ok
+    }
+
+    /// Compiles and executes the current input, which is in the active entry of the VM's view
+    fn execute(&mut self) -> RunResult {
         match self.vm.incremental_compile(&mut self.locals) {
             IncrementalCompileResult::Success => {},
             IncrementalCompileResult::Errors(errors) => {
@@ -144,8 +204,9 @@ impl<W: Write> Repl<W> {
         }
 
         match self.vm.run_until_completion() {
-            ExitType::Exit | ExitType::Return => return RunResult::Exit,
-            ExitType::Yield => {},
+            exit @ ExitType::Exit(_) => return self.exit(exit),
+            ExitType::Return | ExitType::Value(_) => return self.exit(ExitType::Exit(0)),
+            ExitType::Yield => self.history.push(self.vm.view().text().clone()),
             ExitType::Error(error) => self.vm.println(self.vm.view().format(&error)),
         }
 
@@ -153,11 +214,21 @@ impl<W: Write> Repl<W> {
         self.vm.run_recovery(self.locals[0].len());
         RunResult::Ok
     }
+
+    /// Runs any functions registered with `at_exit()`, before the REPL exits. As in a normal program, errors raised by these functions are reported.
+    fn exit(&mut self, exit: ExitType) -> RunResult {
+        if let ExitType::Error(error) = self.vm.run_exit_handlers(exit) {
+            self.vm.println(self.vm.view().format(&error));
+        }
+        RunResult::Exit
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
+    use std::{env, fs, io};
+
     use crate::repl;
     use crate::repl::{Reader, ReadResult};
 
@@ -195,6 +266,27 @@ let x = nil, y = 123, z = 'hello world'
 >>> let x = nil, y = 123, z = 'hello world'
 >>> #stack
 : ['hello world': str, 123: int, nil: nil]
+")}
+
+    #[test] fn test_debug_closure() { run("\
+fn counter() { let x = 0, y = 'y' ; fn() { x += 1 ; y } }
+let c = counter()
+c()
+#closure c
+#closure counter
+#closure nope
+", "\
+>>> fn counter() { let x = 0, y = 'y' ; fn() { x += 1 ; y } }
+>>> let c = counter()
+>>> c()
+y
+>>> #closure c
+x = 1
+y = 'y'
+>>> #closure counter
+'fn counter()' of type 'function' is not a closure
+>>> #closure nope
+No global variable named 'nope'
 ")}
 
     #[test] fn test_declare_and_exec_function() { run("\
@@ -301,13 +393,122 @@ long
 string
 ")}
 
-    fn run(inputs: &'static str, outputs: &'static str) {
+    #[test] fn test_read_line_and_input() { run_with_read("\
+read_line()
+input('name? ')
+read_lines()
+", "first\nsecond\nthird\nfourth\n", "\
+>>> read_line()
+first
+>>> input('name? ')
+name? second
+>>> read_lines()
+['third', 'fourth']
+")}
+
+    #[test] fn test_at_exit() { run("\
+at_exit(fn() -> print('goodbye'))
+1 / 0
+exit
+print('unreachable')", "\
+>>> at_exit(fn() -> print('goodbye'))
+nil
+>>> 1 / 0
+ValueError: Expected value to be non-zero
+  at: line 1 (<stdin>)
+
+1 | 1 / 0
+2 |   ^
+
+>>> exit
+goodbye
+")}
+
+    #[test] fn test_save() {
+        let path: String = temp_path("test_save.cor");
+        run(&format!("\
+let x = 1
+fn f(y) {{
+    x + y
+}}
+print + 1
+f(2)
+#save {}", path), &format!("\
+>>> let x = 1
+>>> fn f(y) {{
+...     x + y
+... }}
+>>> print + 1
+TypeError: Cannot add 'print' of type 'native function' and '1' of type 'int'
+  at: line 1 (<stdin>)
+
+1 | print + 1
+2 |       ^
+
+>>> f(2)
+3
+>>> #save {}
+", path));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "let x = 1\nfn f(y) {\n    x + y\n}\nf(2)\n");
+    }
+
+    #[test] fn test_load() {
+        let path: String = temp_path("test_load.cor");
+        fs::write(&path, "let x = 5\nfn f() -> x * 2").unwrap();
+        run(&format!("\
+#load {}
+f()
+x = 3
+f()", path), &format!("\
+>>> #load {}
+>>> f()
+10
+>>> x = 3
+3
+>>> f()
+6
+", path));
+    }
+
+    #[test] fn test_load_with_error() {
+        let path: String = temp_path("test_load_with_error.cor");
+        fs::write(&path, "let x = 5\nx / 0\n").unwrap();
+        run(&format!("\
+#load {}
+x", path), &format!("\
+>>> #load {}
+ValueError: Expected value to be non-zero
+  at: line 2 ({})
+
+2 | x / 0
+3 |   ^
+
+>>> x
+5
+", path, path));
+    }
+
+    #[test] fn test_load_missing_file() { run("\
+#load does/not/exist.cor", "\
+>>> #load does/not/exist.cor
+Unable to read file 'does/not/exist.cor'
+")}
+
+    fn temp_path(name: &str) -> String {
+        env::temp_dir().join(format!("cordy_repl_{}", name)).to_str().unwrap().to_string()
+    }
+
+    fn run(inputs: &str, outputs: &str) {
+        run_with_read(inputs, "", outputs)
+    }
+
+    fn run_with_read(inputs: &str, read: &'static str, outputs: &str) {
         let repl: Vec<String> = inputs.lines()
             .rev() // rev() because we pop from the end, but list them sequentially.
             .map(String::from)
             .collect();
         let mut buf: Vec<u8> = Vec::new();
-        let result = repl::run(repl, &mut buf, true);
+        let result = repl::run(repl, read.as_bytes(), &mut buf, io::sink(), None, true);
 
         assert!(result.is_ok());
         assert_eq!(String::from_utf8(buf).unwrap(), String::from(outputs));
@@ -38,6 +38,9 @@ impl Location {
 
     // Returns `true` if the location is empty, i.e. zero width
     pub fn is_empty(&self) -> bool { self.width == 0 }
+
+    /// Returns the index of the source view entry this location refers to
+    pub fn index(&self) -> u32 { self.index }
 }
 
 impl BitOr for Location {
@@ -135,6 +138,23 @@ impl SourceView {
         self.0[loc.index as usize].lineno(loc)
     }
 
+    /// Returns the name of the entry containing `loc`.
+    pub fn name_of(&self, loc: Location) -> &String {
+        &self.0[loc.index as usize].name
+    }
+
+    /// Returns the source code spanned by `loc`, or `None` if it is empty. If it spans multiple lines, only the first is returned.
+    pub fn text_of(&self, loc: Location) -> Option<String> {
+        if loc.is_empty() {
+            return None
+        }
+        let text: String = self.0[loc.index as usize].text.chars().skip(loc.start).take(loc.width as usize).collect();
+        match text.split_once('\n') {
+            Some((first, _)) => Some(format!("{} ...", first.trim_end())),
+            None => Some(text),
+        }
+    }
+
     pub fn push(&mut self, name: String, text: String) {
         self.0.push(SourceEntry { name, text, index: RefCell::new(None) });
     }
@@ -156,7 +176,7 @@ impl SourceEntry {
     }
 
     fn format<E : AsErrorWithContext>(&self, view: &SourceView, error: &E) -> String {
-        let mut text = error.as_error();
+        let mut text = error.as_error_with_source(view);
         let index: Ref<'_, SourceIndex> = self.index();
         let loc = error.location();
         let start_lineno = self.lineno(loc).unwrap_or(0);
@@ -255,6 +275,12 @@ pub trait AsError {
 pub trait AsErrorWithContext: AsError {
     fn location(&self) -> Location;
 
+    /// When formatting an error, allows the message to quote the source code, i.e. to show the signature of a function as it was declared.
+    /// By default, this is the same as `as_error()`.
+    fn as_error_with_source(&self, _: &SourceView) -> String {
+        self.as_error()
+    }
+
     /// When formatting a `RuntimeError`, allows inserting additional stack trace elements.
     /// This is appended *after* the initial `at: line X (source file)` line is appended.
     fn add_stack_trace_elements(&self, _: &SourceView, _: &mut String) {}
@@ -264,9 +290,11 @@ pub trait AsErrorWithContext: AsError {
 impl AsError for RuntimeError {
     fn as_error(&self) -> String {
         match self {
-            RuntimeError::RuntimeExit | RuntimeError::RuntimeYield => panic!("Not a real error"),
+            RuntimeError::RuntimeExit(_) | RuntimeError::RuntimeExitValue(_) | RuntimeError::RuntimeYield | RuntimeError::RuntimeAwait => panic!("Not a real error"),
             RuntimeError::RuntimeAssertFailed(reason) => format!("Assertion Failed: {}", reason),
             RuntimeError::RuntimeCompilationError(vec) => format!("Encountered compilation error(s) within 'eval':\n\n{}", vec.join("\n")),
+            RuntimeError::RuntimeExecutionLimit(limit) => format!("Execution limit of {} instructions reached", limit),
+            RuntimeError::RuntimeInternalError(reason) => format!("Internal Error: {}", reason),
 
             RuntimeError::ValueIsNotFunctionEvaluable(v) => format!("Tried to evaluate {} but it is not a function.", v.as_error()),
             RuntimeError::IncorrectArgumentsUserFunction(f, n) => format!("Incorrect number of arguments for {}, got {}", f.as_error(), n),
@@ -277,31 +305,82 @@ impl AsError for RuntimeError {
             RuntimeError::IOError(e) => format!("IOError: {}", e),
 
             RuntimeError::ValueErrorIndexOutOfBounds(i, ln) => format!("Index '{}' is out of bounds for list of length [0, {})", i, ln),
+            RuntimeError::ValueErrorDsuElementOutOfRange(i, ln) => format!("ValueError: Element '{}' is out of range for a dsu with elements in [0, {})", i, ln),
             RuntimeError::ValueErrorStepCannotBeZero => String::from("ValueError: 'step' argument cannot be zero"),
             RuntimeError::ValueErrorVariableNotDeclaredYet(x) => format!("ValueError: '{}' was referenced but has not been declared yet", x),
             RuntimeError::ValueErrorValueMustBeNonEmpty => String::from("ValueError: Expected value to be a non empty iterable"),
+            RuntimeError::ValueErrorCannotReserveCapacity(n) => format!("ValueError: Cannot reserve capacity for {} elements", n),
+            RuntimeError::ValueErrorRepeatTooLarge(len, n) => format!("ValueError: Cannot repeat a sequence of length {}, {} times", len, n),
             RuntimeError::ValueErrorCannotUnpackLengthMustBeGreaterThan(e, a, v) => format!("ValueError: Cannot unpack {} with length {}, expected at least {} elements", v.as_error(), a, e),
             RuntimeError::ValueErrorCannotUnpackLengthMustBeEqual(e, a, v) => format!("ValueError: Cannot unpack {} with length {}, expected exactly {} elements", v.as_error(), a, e),
             RuntimeError::ValueErrorValueMustBeNonNegative(v) => format!("ValueError: Expected value '{}: int' to be non-negative", v),
             RuntimeError::ValueErrorValueMustBePositive(v) => format!("ValueError: Expected value '{}: int' to be positive", v),
             RuntimeError::ValueErrorValueMustBeNonZero => String::from("ValueError: Expected value to be non-zero"),
+            RuntimeError::ValueErrorFractionOverflow => String::from("ValueError: Fraction is too large to be represented"),
+            RuntimeError::ValueErrorIntOverflow => String::from("ValueError: Integer is too large to be represented"),
             RuntimeError::ValueErrorCannotCollectIntoDict(v) => format!("ValueError: Cannot collect key-value pair {} into a dict", v.as_error()),
             RuntimeError::ValueErrorKeyNotPresent(v) => format!("ValueError: Key {} not found in dictionary", v.as_error()),
             RuntimeError::ValueErrorInvalidCharacterOrdinal(i) => format!("ValueError: Cannot convert int {} to a character", i),
+            RuntimeError::ValueErrorInvalidByte(i) => format!("ValueError: Cannot write int {} as a byte, must be in the range [0, 256)", i),
+            RuntimeError::ValueErrorInvalidExitCode(i) => format!("ValueError: Cannot exit with code {}, must be a 32-bit signed integer", i),
             RuntimeError::ValueErrorInvalidFormatCharacter(c) => format!("ValueError: Invalid format character '{}' in format string", c.as_error()),
             RuntimeError::ValueErrorNotAllArgumentsUsedInStringFormatting(v) => format!("ValueError: Not all arguments consumed in format string, next: {}", v.as_error()),
             RuntimeError::ValueErrorMissingRequiredArgumentInStringFormatting => String::from("ValueError: Not enough arguments for format string"),
             RuntimeError::ValueErrorEvalListMustHaveUnitLength(len) => format!("ValueError: Evaluating an index must have len = 1, got len = {}", len),
             RuntimeError::ValueErrorCannotCompileRegex(raw, err) => format!("ValueError: Cannot compile regex '{}'\n            {}", raw, err),
+            RuntimeError::ValueErrorValueIsFrozen(value) => format!("ValueError: Cannot modify {}, as it is used as a key in a dict or set", value.as_error()),
+            RuntimeError::ValueErrorViewIsImmutable(value) => format!("ValueError: Cannot modify {}, as views are immutable", value.as_error()),
             RuntimeError::ValueErrorRecursiveHash(value) => format!("ValueError: Cannot create recursive hash based collection from {}", value.as_error()),
+            RuntimeError::ValueErrorMatrixRowLength(e, a) => format!("ValueError: Expected all rows of a matrix to have length {}, got a row of length {}", e, a),
+            RuntimeError::ValueErrorMatrixDimensionMismatch(lr, lc, rr, rc) => format!("ValueError: Incompatible matrix dimensions {}x{} and {}x{}", lr, lc, rr, rc),
+            RuntimeError::ValueErrorMatrixTooLarge(r, c) => format!("ValueError: Cannot allocate a matrix of dimensions {}x{}", r, c),
+            RuntimeError::ValueErrorBitSetTooLarge(size) => format!("ValueError: Cannot allocate a bitset of size {}", size),
+            RuntimeError::ValueErrorDsuTooLarge(size) => format!("ValueError: Cannot allocate a dsu of size {}", size),
+            RuntimeError::ValueErrorIterableIsInfinite(v) => format!("ValueError: Expected {} to be a finite iterable", v.as_error()),
+            RuntimeError::ValueErrorValueMustBeSingle(v) => format!("ValueError: Expected {} to have exactly one element", v.as_error()),
+            RuntimeError::ValueErrorCannotFlattenRecursive(v) => format!("ValueError: Cannot fully flatten recursive collection {}", v.as_error()),
+            RuntimeError::ValueErrorRowLengthMismatch(e, a) => format!("ValueError: Expected all rows to have length {}, got a row of length {}", e, a),
+            RuntimeError::ValueErrorDuplicateKey(v) => format!("ValueError: Duplicate key {} in dictionary", v.as_error()),
+            RuntimeError::ValueErrorUnmatchedBraceInTemplate(c) => format!("ValueError: Unmatched '{}' in template string, use '{}{}' for a literal brace", c, c, c),
+            RuntimeError::ValueErrorUnmatchedBraceInScanFormat(c) => format!("ValueError: Unmatched '{}' in scan format, use '{}{}' for a literal brace", c, c, c),
+            RuntimeError::ValueErrorInvalidScanToken(token) => format!("ValueError: Invalid token '{{{}}}' in scan format, expected one of '{{int}}', '{{word}}' or '{{str}}'", token),
+            RuntimeError::ValueErrorUnterminatedQuoteInCsv => String::from("ValueError: Unterminated quoted field in CSV"),
+            RuntimeError::ValueErrorInvalidCsvDelimiter(v) => format!("ValueError: Expected {} to be a single character CSV delimiter, which is not a quote or newline", v.as_error()),
+            RuntimeError::ValueErrorCannotDecodeToml(e) => format!("ValueError: Cannot decode TOML: {}", e),
+            RuntimeError::ValueErrorFeatureNotEnabled(f) => format!("ValueError: Cordy was not compiled with the '{}' feature enabled", f),
+            RuntimeError::ValueErrorNotInteractive(f) => format!("ValueError: '{}' requires an interactive terminal", f.repr()),
+            RuntimeError::ValueErrorInvalidColor(v) => format!("ValueError: Unknown color or style {}", v.as_error()),
+            RuntimeError::ValueErrorInvalidLogLevel(v) => format!("ValueError: Unknown log level {}, must be one of 'debug', 'info', 'warn', or 'error'", v.as_error()),
+            RuntimeError::ValueErrorInvalidPort(i) => format!("ValueError: Invalid port {}, must be in the range [0, 65536)", i),
+            RuntimeError::ValueErrorClosed(v) => format!("ValueError: Cannot use {} as it is closed", v.as_error()),
+            RuntimeError::ValueErrorTimedOut(v) => format!("ValueError: Timed out waiting for a response from {}", v.as_error()),
+            RuntimeError::ValueErrorCapabilityDenied(f) => format!("ValueError: '{}' is not permitted by the host", f.repr()),
+            RuntimeError::ValueErrorInvalidSignal(v) => format!("ValueError: Unknown signal {}, must be one of 'INT' or 'TERM'", v.as_error()),
+            RuntimeError::ValueErrorInvalidArgumentSpec(v) => format!("ValueError: Invalid argument name {} in argparse() spec", v.as_error()),
+            RuntimeError::ValueErrorCannotMarshalRecursive(v) => format!("ValueError: Cannot marshal recursive collection {}", v.as_error()),
+            RuntimeError::ValueErrorCannotUnmarshal(e) => format!("ValueError: Cannot unmarshal: {}", e),
+            RuntimeError::ValueErrorInvalidPackFormat(format, c) => format!("ValueError: Invalid character '{}' in pack format '{}'", c, format),
+            RuntimeError::ValueErrorPackFormatMissingField(format) => format!("ValueError: Expected a field after the count in pack format '{}'", format),
+            RuntimeError::ValueErrorCannotPack(e) => format!("ValueError: Cannot pack: {}", e),
+            RuntimeError::ValueErrorCannotUnpack(e) => format!("ValueError: Cannot unpack: {}", e),
+            RuntimeError::ValueErrorDatabase(e) => format!("ValueError: Database error: {}", e),
+            RuntimeError::ValueErrorEmptyRange(lo, hi) => format!("ValueError: Range [{}, {}] is empty, as {} > {}", lo, hi, lo, hi),
+            RuntimeError::ValueErrorPropertyFailed(cases, seed, input, reason) => format!("ValueError: Property failed after {} case{} (seed = {}), with input {}\n  because: {}", cases, if *cases == 1 { "" } else { "s" }, seed, input.to_repr_str(), reason),
+            RuntimeError::ValueErrorTaskNeverCompletes(v) => format!("ValueError: Cannot await {}, as it will never complete", v.as_error()),
+            RuntimeError::ValueErrorInvalidUrl(url) => format!("ValueError: Expected a url of the form 'http://host[:port][/path]', got '{}'", url),
 
             RuntimeError::TypeErrorUnaryOp(op, v) => format!("TypeError: Argument to unary '{}' must be an int, got {}", op.as_error(), v.as_error()),
             RuntimeError::TypeErrorBinaryOp(op, l, r) => format!("TypeError: Cannot {} {} and {}", op.as_error(), l.as_error(), r.as_error()),
             RuntimeError::TypeErrorBinaryIs(l, r) => format!("TypeError: {} is not a type and cannot be used with binary 'is' on {}", r.as_error(), l.as_error()),
             RuntimeError::TypeErrorCannotConvertToInt(v) => format!("TypeError: Cannot convert {} to an int", v.as_error()),
+            RuntimeError::TypeErrorCannotConstructEnum(s) => format!("TypeError: Cannot construct {}, construct one of its variants instead", s.as_error()),
+            RuntimeError::TypeErrorCannotCompare(l, r) => format!("TypeError: Cannot compare {} and {}", l.as_error(), r.as_error()),
+            RuntimeError::TypeErrorPropertyHasNoSetter(s, f) => format!("TypeError: Cannot set field '{}' on {}, as it is a property with no 'set' accessor", f, s),
+            RuntimeError::TypeErrorCannotMarshal(v) => format!("TypeError: Cannot marshal {}", v.as_error()),
             RuntimeError::TypeErrorFieldNotPresentOnValue(v, f, b) => format!("TypeError: Cannot get field '{}' on {}", f, if *b { v.to_repr_str() } else { v.as_error() }),
             RuntimeError::TypeErrorArgMustBeInt(v) => format!("TypeError: Expected {} to be a int", v.as_error()),
             RuntimeError::TypeErrorArgMustBeComplex(v) => format!("TypeError: Expected {} to be a complex", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeFraction(v) => format!("TypeError: Expected {} to be an int or fraction", v.as_error()),
             RuntimeError::TypeErrorArgMustBeStr(v) => format!("TypeError: Expected {} to be a string", v.as_error()),
             RuntimeError::TypeErrorArgMustBeChar(v) => format!("TypeError: Expected {} to be a single character string", v.as_error()),
             RuntimeError::TypeErrorArgMustBeIterable(v) => format!("TypeError: Expected {} to be an iterable", v.as_error()),
@@ -310,7 +389,25 @@ impl AsError for RuntimeError {
             RuntimeError::TypeErrorArgMustBeList(v) => format!("TypeError: Expected {} to be a list", v.as_error()),
             RuntimeError::TypeErrorArgMustBeSet(v) => format!("TypeError: Expected {} to be a set", v.as_error()),
             RuntimeError::TypeErrorArgMustBeDict(v) => format!("TypeError: Expected {} to be a dict", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeSorted(v) => format!("TypeError: Expected {} to be a sorted_set or sorted_dict", v.as_error()),
+            RuntimeError::TypeErrorArgMustBePersistent(v) => format!("TypeError: Expected {} to be a plist or pdict", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeMatrix(v) => format!("TypeError: Expected {} to be a matrix", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeBitSet(v) => format!("TypeError: Expected {} to be a bitset", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeDsu(v) => format!("TypeError: Expected {} to be a dsu", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeStopwatch(v) => format!("TypeError: Expected {} to be a stopwatch", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeWeak(v) => format!("TypeError: Expected {} to be a weak reference", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeShared(v) => format!("TypeError: Expected {} to be a reference type, such as a list, dict, or function", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeTcpStream(v) => format!("TypeError: Expected {} to be a tcp stream", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeTcpListener(v) => format!("TypeError: Expected {} to be a tcp listener", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeStream(v) => format!("TypeError: Expected {} to be a tcp stream or coprocess", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeHandle(v) => format!("TypeError: Expected {} to be a socket, coprocess, or database", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeContext(v) => format!("TypeError: Expected {} to be a struct with an '__exit__' or 'close' member, or a socket, coprocess, or database, in order to be used in a 'with' statement", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeDatabase(v) => format!("TypeError: Expected {} to be a database", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeGenerator(v) => format!("TypeError: Expected {} to be a generator", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeTask(v) => format!("TypeError: Expected {} to be a task", v.as_error()),
+            RuntimeError::TypeErrorCannotBindSqlParameter(v) => format!("TypeError: Cannot bind {} as a SQL parameter, must be a nil, bool, int, fraction, or str", v.as_error()),
             RuntimeError::TypeErrorArgMustBeFunction(v) => format!("TypeError: Expected {} to be a function", v.as_error()),
+            RuntimeError::TypeErrorArgMustBeMemoized(v) => format!("TypeError: Expected {} to be a memoized function", v.as_error()),
             RuntimeError::TypeErrorArgMustBeCmpOrKeyFunction(v) => format!("TypeError: Expected {} to be a '<A, B> fn key(A) -> B' or '<A> cmp(A, A) -> int' function", v.as_error()),
             RuntimeError::TypeErrorArgMustBeReplaceFunction(v) => format!("TypeError: Expected {} to be a 'fn replace(vector<str>) -> str' function", v.as_error()),
         }
@@ -414,24 +511,36 @@ impl AsError for ParserError {
             ParserErrorType::ExpectedUnderscoreOrVariableNameOrPattern(e) => format!("Expected a variable binding, either a name, or '_', or pattern (i.e. 'x, (_, y), *z'), got {} instead", e.as_error()),
             ParserErrorType::ExpectedAnnotationOrNamedFunction(e) => format!("Expected another decorator, or a named function after decorator, got {} instead", e.as_error()),
             ParserErrorType::ExpectedStructNameAfterStruct(e) => format!("Expected a struct name after 'struct' keyword, got {} instead", e.as_error()),
+            ParserErrorType::ExpectedEnumNameAfterEnum(e) => format!("Expected an enum name after 'enum' keyword, got {} instead", e.as_error()),
             ParserErrorType::ExpectedFieldNameAfterArrow(e) => format!("Expected a field name after '->', got {} instead", e.as_error()),
+            ParserErrorType::ExpectedParentStructName(e) => format!("Expected a parent struct name after ':' in struct declaration, got {} instead", e.as_error()),
+            ParserErrorType::ExpectedStructMemberName(e) => format!("Expected a member name after 'const' or 'fn' in struct body, got {} instead", e.as_error()),
+            ParserErrorType::ExpectedNameAfterAs(e) => format!("Expected a variable name after 'as' in 'with' statement, got {} instead", e.as_error()),
 
             ParserErrorType::LocalVariableConflict(e) => format!("Multiple declarations for 'let {}' in the same scope", e),
             ParserErrorType::LocalVariableConflictWithNativeFunction(e) => format!("Name for variable '{}' conflicts with the native function by the same name", e),
             ParserErrorType::UndeclaredIdentifier(e) => format!("Undeclared identifier: '{}'", e),
             ParserErrorType::DuplicateFieldName(e) => format!("Duplicate field name: '{}'", e),
             ParserErrorType::InvalidFieldName(e) => format!("Invalid or unknown field name: '{}'", e),
+            ParserErrorType::InvalidParentStruct(e) => format!("Invalid or unknown parent struct: '{}'", e),
+            ParserErrorType::PropertyWithoutGetter(e) => format!("Property '{}' has a 'set' accessor, but no 'get' accessor", e),
             ParserErrorType::InvalidLValue(e) => format!("Invalid value used as a function parameter: '{}'", e),
+            ParserErrorType::LateBoundGlobalInPattern(e) => format!("Cannot assign to global '{}' in a pattern before it is declared", e),
 
             ParserErrorType::InvalidAssignmentTarget => String::from("The left hand side of an assignment expression must be a variable, array access, or property access"),
             ParserErrorType::MultipleVariadicTermsInPattern => String::from("Pattern is not allowed to have more than one variadic (i.e. '*') term."),
             ParserErrorType::LetWithPatternBindingNoExpression => String::from("'let' with a pattern variable must be followed by an expression if the pattern contains non-simple elements such as variadic (i.e. '*'), empty (i.e. '_'), or nested (i.e. 'x, (_, y)) terms."),
             ParserErrorType::BreakOutsideOfLoop => String::from("Invalid 'break' statement outside of an enclosing loop"),
+            ParserErrorType::ReturnOutsideOfFunction => String::from("Invalid 'return' statement outside of an enclosing function"),
             ParserErrorType::ContinueOutsideOfLoop => String::from("Invalid 'continue' statement outside of an enclosing loop"),
             ParserErrorType::StructNotInGlobalScope => String::from("'struct' statements can only be present in global scope."),
+            ParserErrorType::EnumNotInGlobalScope => String::from("'enum' statements can only be present in global scope."),
             ParserErrorType::NonDefaultParameterAfterDefaultParameter => String::from("Non-default argument cannot follow default argument."),
             ParserErrorType::ParameterAfterVarParameter => String::from("Variadic parameter must be the last one in the function."),
+            ParserErrorType::DefaultValueForVarParameter => String::from("Variadic parameter cannot have a default value."),
             ParserErrorType::UnrollNotAllowedInSlice => String::from("Unrolled expression with '...' not allowed in slice literal."),
+            ParserErrorType::UnrollNotAllowedWithPlaceholder => String::from("Unrolled expression with '...' not allowed in a function call with placeholder (i.e. '_') arguments."),
+            ParserErrorType::UnrollNotAllowedInMethodCall => String::from("Unrolled expression with '...' not allowed in a method call (i.e. 'x.f(...)'), use composition (i.e. 'x . f(...)') instead."),
 
             ParserErrorType::Runtime(e) => e.as_error(),
         }
@@ -443,6 +552,12 @@ impl AsError for ScanError {
         match &self.error {
             ScanErrorType::InvalidNumericPrefix(c) => format!("Invalid numeric prefix: '0{}'", c),
             ScanErrorType::InvalidNumericValue(e) => format!("Invalid numeric value: {}", e),
+            ScanErrorType::InvalidNumericDigit(c, radix) => format!("Invalid numeric value: '{}' is not a valid digit in base {}", c, radix),
+            ScanErrorType::NumericValueTooLarge => String::from("Invalid numeric value: number too large to fit in target type"),
+            ScanErrorType::InvalidEscape(c) => format!("Invalid escape sequence: '\\{}'", c),
+            ScanErrorType::InvalidUnicodeEscape(text) => format!("Invalid unicode escape: expected '\\u{{...}}' with between one and six hex digits, got '{}'", text),
+            ScanErrorType::InvalidUnicodeCodePoint(value) => format!("Invalid unicode escape: '{:x}' is not a valid unicode code point", value),
+            ScanErrorType::InvalidCharLiteral(n) => format!("Invalid char literal: expected a single character, got {} characters", n),
             ScanErrorType::InvalidCharacter(c) => format!("Invalid character: '{}'", c),
             ScanErrorType::UnterminatedStringLiteral => String::from("Unterminated string literal (missing a closing quote)"),
             ScanErrorType::UnterminatedBlockComment => String::from("Unterminated block comment (missing a closing '*/')"),
@@ -466,6 +581,7 @@ impl AsError for ScanToken {
             ScanToken::StringLiteral(s) => format!("string '{}'", s),
             ScanToken::IntLiteral(i) => format!("integer '{}'", i),
             ScanToken::ComplexLiteral(i) => format!("complex integer '{}'", i),
+            ScanToken::CustomOperator(s) => format!("operator '{}'", s),
 
             ScanToken::KeywordLet => String::from("'let' keyword"),
             ScanToken::KeywordFn => String::from("'fn' keyword"),
@@ -487,8 +603,14 @@ impl AsError for ScanToken {
             ScanToken::KeywordFalse => String::from("'false' keyword"),
             ScanToken::KeywordNil => String::from("'nil' keyword"),
             ScanToken::KeywordStruct => String::from("'struct' keyword"),
+            ScanToken::KeywordEnum => String::from("'enum' keyword"),
+            ScanToken::KeywordConst => String::from("'const' keyword"),
             ScanToken::KeywordExit => String::from("'exit' keyword"),
             ScanToken::KeywordAssert => String::from("'assert' keyword"),
+            ScanToken::KeywordWith => String::from("'with' keyword"),
+            ScanToken::KeywordAs => String::from("'as' keyword"),
+            ScanToken::KeywordAsync => String::from("'async' keyword"),
+            ScanToken::KeywordAwait => String::from("'await' keyword"),
 
             ScanToken::Equals => String::from("'=' token"),
             ScanToken::PlusEquals => String::from("'+=' token"),
@@ -546,6 +668,7 @@ impl AsError for ScanToken {
             ScanToken::QuestionMark => String::from("'?' token"),
 
             ScanToken::NewLine => String::from("new line"),
+            ScanToken::DocComment(_) => String::from("doc comment"),
         }
     }
 }
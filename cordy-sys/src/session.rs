@@ -0,0 +1,155 @@
+use std::io::{BufRead, Write};
+
+use crate::{compiler, SourceView};
+use crate::compiler::{IncrementalCompileResult, Locals};
+use crate::vm::{ExitType, ValuePtr, VirtualMachine};
+
+
+/// A persistent, interactive evaluation context, which compiles and runs source code incrementally. Each `push()` can use any functions, structs,
+/// and variables declared by previous ones, in the same way as the REPL, which makes this suitable for embedding Cordy in an application, such as a console or notebook.
+///
+/// The VM reads from `read`, and writes to `write` and `error`, for natives such as `read_line()`, `print()` and `eprintln()`.
+pub struct Session<R: BufRead, W: Write, E: Write> {
+    locals: Vec<Locals>,
+    /// If present, the exit code of the program, once it has exited. After this, every `push()` returns `SessionError::Exit`.
+    exit: Option<i32>,
+    vm: VirtualMachine<R, W, E>,
+}
+
+/// An error returned by `Session::push()`. Compile and runtime errors are already formatted, including the relevant source code.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SessionError {
+    /// The source ended in the middle of an expression or statement, i.e. an unclosed `{`. It is kept, and the next `push()` continues from where it ended.
+    Incomplete,
+    /// The source could not be compiled, and so nothing was run.
+    Compile(Vec<String>),
+    /// The source raised an error while running. Any changes it made before the error, i.e. to variables, are kept.
+    Runtime(String),
+    /// The program has exited, i.e. with `exit`, with the given exit code. Any functions registered with `at_exit()` have been run.
+    Exit(i32),
+}
+
+impl<R: BufRead, W: Write, E: Write> Session<R, W, E> {
+
+    pub fn new(read: R, write: W, error: E) -> Session<R, W, E> {
+        let compile = compiler::default();
+        let view = SourceView::new(String::from("<session>"), String::new());
+
+        Session {
+            locals: Locals::empty(),
+            exit: None,
+            vm: VirtualMachine::new(compile, view, read, write, error, vec![])
+        }
+    }
+
+    /// Compiles and runs `source`, returning the value of its final expression, or `nil` if it does not end in one.
+    pub fn push(&mut self, source: &str) -> Result<ValuePtr, SessionError> {
+        if let Some(code) = self.exit {
+            return Err(SessionError::Exit(code))
+        }
+
+        let buffer = self.vm.view_mut().text_mut();
+        buffer.push_str(source);
+        buffer.push('\n');
+
+        match self.vm.incremental_compile_value(&mut self.locals) {
+            IncrementalCompileResult::Success => {},
+            IncrementalCompileResult::Errors(errors) => {
+                self.next();
+                return Err(SessionError::Compile(errors))
+            },
+            IncrementalCompileResult::Aborted => return Err(SessionError::Incomplete),
+        }
+
+        let result: Result<ValuePtr, SessionError> = match self.vm.run_until_completion() {
+            ExitType::Yield => Ok(self.vm.pop_value()),
+            ExitType::Error(error) => Err(SessionError::Runtime(self.vm.view().format(&error))),
+            exit => Err(self.exit(exit)),
+        };
+        self.next();
+        result
+    }
+
+    /// Discards any incomplete source, kept from previous calls to `push()` which returned `SessionError::Incomplete`.
+    pub fn discard(&mut self) {
+        if !self.vm.view().text().is_empty() {
+            self.next();
+        }
+    }
+
+    /// Returns the VM this session runs in, i.e. to inspect its globals.
+    pub fn vm(&self) -> &VirtualMachine<R, W, E> {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VirtualMachine<R, W, E> {
+        &mut self.vm
+    }
+
+    /// Prepares the VM for the next `push()`, with a new, empty, source
+    fn next(&mut self) {
+        self.vm.view_mut().push(String::from("<session>"), String::new());
+        self.vm.run_recovery(self.locals[0].len());
+    }
+
+    /// Runs any functions registered with `at_exit()`, and returns the error describing how the program exited.
+    fn exit(&mut self, exit: ExitType) -> SessionError {
+        let code: i32 = match exit {
+            ExitType::Exit(code) => code,
+            _ => 0,
+        };
+        self.exit = Some(code);
+        match self.vm.run_exit_handlers(ExitType::Exit(code)) {
+            ExitType::Error(error) => SessionError::Runtime(self.vm.view().format(&error)),
+            ExitType::Exit(code) => {
+                self.exit = Some(code);
+                SessionError::Exit(code)
+            },
+            _ => SessionError::Exit(code),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::session::{Session, SessionError};
+    use crate::vm::ValuePtr;
+
+    #[test] fn test_push_expression() { run(&["1 + 2"], vec![Ok("3")]); }
+    #[test] fn test_push_statement_is_nil() { run(&["let x = 1"], vec![Ok("nil")]); }
+    #[test] fn test_push_retains_locals() { run(&["let x = 1", "x = x + 2", "x * 10"], vec![Ok("nil"), Ok("3"), Ok("30")]); }
+    #[test] fn test_push_retains_functions() { run(&["fn f(x) -> x * 2", "f(3)"], vec![Ok("nil"), Ok("6")]); }
+    #[test] fn test_push_multiple_statements() { run(&["let x = 1 ; let y = 2 ; [x, y]"], vec![Ok("[1, 2]")]); }
+    #[test] fn test_push_incomplete() { run(&["fn f() {", "    'hello'", "}", "f()"], vec![Err(SessionError::Incomplete), Err(SessionError::Incomplete), Ok("nil"), Ok("'hello'")]); }
+    #[test] fn test_push_incomplete_then_discard() {
+        let mut session = Session::new(&b""[..], io::sink(), io::sink());
+        assert_eq!(session.push("let x = ["), Err(SessionError::Incomplete));
+        session.discard();
+        assert_eq!(session.push("1 + 2").map(|value| value.to_repr_str()), Ok(String::from("3")));
+    }
+    #[test] fn test_push_compile_error() { run(&["let x = ", "1)", "x"], vec![Err(SessionError::Incomplete), Err(SessionError::Compile(vec![String::from("Expected an expression terminal, got ')' token instead\n  at: line 2 (<session>)\n\n2 | 1)\n3 |  ^\n")])), Err(SessionError::Compile(vec![String::from("Undeclared identifier: 'x'\n  at: line 1 (<session>)\n\n1 | x\n2 | ^\n")]))]); }
+    #[test] fn test_push_runtime_error() { run(&["let x = [1]", "x . push(2) ; x[5]", "x"], vec![Ok("nil"), Err(SessionError::Runtime(String::from("Index '5' is out of bounds for list of length [0, 2)\n  at: line 1 (<session>)\n\n1 | x . push(2) ; x[5]\n2 |                ^^^\n"))), Ok("[1, 2]")]); }
+    #[test] fn test_push_exit() { run(&["exit(3)", "1"], vec![Err(SessionError::Exit(3)), Err(SessionError::Exit(3))]); }
+    #[test] fn test_push_exit_runs_exit_handlers() {
+        let mut buf: Vec<u8> = Vec::new();
+        let mut session = Session::new(&b""[..], &mut buf, io::sink());
+        assert!(session.push("at_exit(fn() -> print('goodbye'))").is_ok());
+        assert_eq!(session.push("exit"), Err(SessionError::Exit(0)));
+        drop(session);
+        assert_eq!(String::from_utf8(buf).unwrap(), "goodbye\n");
+    }
+
+    fn run(inputs: &[&str], expected: Vec<Result<&str, SessionError>>) {
+        let mut session = Session::new(&b""[..], io::sink(), io::sink());
+        let actual: Vec<Result<String, SessionError>> = inputs.iter()
+            .map(|source| session.push(source).map(|value: ValuePtr| value.to_repr_str()))
+            .collect();
+        let expected: Vec<Result<String, SessionError>> = expected.into_iter()
+            .map(|result| result.map(String::from))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+}
@@ -10,28 +10,4 @@ macro_rules! trace_parser {
     };
 }
 
-macro_rules! trace_interpreter {
-    ($($e:expr),+) => {
-        {
-            #[cfg(feature = "trace_interpreter")]
-            {
-                print!("[vm] ");
-                println!($($e),+);
-            }
-        }
-    };
-}
-
-macro_rules! trace_interpreter_stack {
-    ($($e:expr),+) => {
-        {
-            #[cfg(feature = "trace_interpreter_stack")]
-            {
-                print!("[stack] ");
-                println!($($e),+);
-            }
-        }
-    };
-}
-
-pub(crate) use {trace_parser, trace_interpreter, trace_interpreter_stack};
\ No newline at end of file
+pub(crate) use trace_parser;
\ No newline at end of file
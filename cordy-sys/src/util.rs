@@ -1,6 +1,4 @@
-use std::ops::{ControlFlow, Try};
-
-use crate::vm::{ErrorResult, Prefix, RuntimeError};
+use std::cmp::Ordering;
 
 pub fn strip_line_ending(buffer: &mut String) {
     if buffer.ends_with('\n') {
@@ -11,27 +9,19 @@ pub fn strip_line_ending(buffer: &mut String) {
     }
 }
 
-/// Used to wrap a function that might normally error, with one that passes the error up (i.e. outside of a closure), and returns a default to the inner function.
-///
-/// N.B. This did not work without specifying the types `Box<RuntimeError>` exactly. I do not know why.
-#[inline(always)]
-pub fn catch<T>(err: &mut Option<Box<Prefix<RuntimeError>>>, f: impl FnOnce() -> ErrorResult<T>, default: T) -> T {
-    match f().branch() {
-        ControlFlow::Continue(e) => e,
-        ControlFlow::Break(Err(e)) => {
-            *err = Some(e);
-            default
-        },
-        _ => unreachable!(),
-    }
-}
-
-/// Joins an `Option<Box<RuntimeError>>` error with a result, using fully generic `Try` trait implementation.
-#[inline(always)]
-pub fn join<T, E, R : Try<Output=T, Residual=E>>(result: T, err: Option<E>) -> R {
-    match err {
-        Some(e) => R::from_residual(e),
-        None => R::from_output(result)
+/// Sorts `vec` using a comparator which may raise an error. This is a stable sort, which returns the first error raised by `cmp`, after which no further comparisons are made.
+pub fn try_sort_by<T, E>(mut vec: Vec<T>, cmp: &mut impl FnMut(&T, &T) -> Result<Ordering, E>) -> Result<Vec<T>, E> {
+    let mut error: Option<E> = None;
+    vec.sort_by(|a, b| match error {
+        Some(_) => Ordering::Equal,
+        None => cmp(a, b).unwrap_or_else(|e| {
+            error = Some(e);
+            Ordering::Equal
+        }),
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(vec),
     }
 }
 
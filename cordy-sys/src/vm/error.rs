@@ -7,14 +7,18 @@ use crate::vm::value::{FunctionImpl, Prefix, ValuePtr};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RuntimeError {
-    RuntimeExit,
+    RuntimeExit(i32),
+    RuntimeExitValue(ValuePtr),
     RuntimeYield,
+    RuntimeAwait,
     RuntimeAssertFailed(String),
     RuntimeCompilationError(Vec<String>),
+    RuntimeExecutionLimit(u64),
+    RuntimeInternalError(String),
 
     ValueIsNotFunctionEvaluable(ValuePtr),
 
-    IncorrectArgumentsUserFunction(FunctionImpl, u32),
+    IncorrectArgumentsUserFunction(Box<FunctionImpl>, u32),
     IncorrectArgumentsNativeFunction(NativeFunction, u32),
     IncorrectArgumentsGetField(String, u32),
     IncorrectArgumentsStruct(StructTypeImpl, u32),
@@ -22,32 +26,83 @@ pub enum RuntimeError {
     IOError(String),
 
     ValueErrorIndexOutOfBounds(i64, usize),
+    ValueErrorDsuElementOutOfRange(i64, usize),
     ValueErrorStepCannotBeZero,
     ValueErrorVariableNotDeclaredYet(String),
     ValueErrorValueMustBeNonNegative(i64),
     ValueErrorValueMustBePositive(i64),
     ValueErrorValueMustBeNonZero,
+    ValueErrorFractionOverflow,
+    ValueErrorIntOverflow,
     ValueErrorValueMustBeNonEmpty,
+    ValueErrorCannotReserveCapacity(i64),
+    ValueErrorRepeatTooLarge(usize, i64), // length, repeat
     ValueErrorCannotUnpackLengthMustBeGreaterThan(u32, usize, ValuePtr), // expected, actual
     ValueErrorCannotUnpackLengthMustBeEqual(u32, usize, ValuePtr), // expected, actual
     ValueErrorCannotCollectIntoDict(ValuePtr),
     ValueErrorKeyNotPresent(ValuePtr),
     ValueErrorInvalidCharacterOrdinal(i64),
+    ValueErrorInvalidByte(i64),
+    ValueErrorInvalidExitCode(i64),
     ValueErrorInvalidFormatCharacter(Option<char>),
     ValueErrorNotAllArgumentsUsedInStringFormatting(ValuePtr),
     ValueErrorMissingRequiredArgumentInStringFormatting,
     ValueErrorEvalListMustHaveUnitLength(usize),
     ValueErrorCannotCompileRegex(String, String),
     ValueErrorRecursiveHash(ValuePtr),
+    ValueErrorValueIsFrozen(ValuePtr),
+    ValueErrorViewIsImmutable(ValuePtr),
+    ValueErrorMatrixRowLength(usize, usize), // expected, actual
+    ValueErrorMatrixDimensionMismatch(usize, usize, usize, usize), // lhs rows x cols, rhs rows x cols
+    ValueErrorMatrixTooLarge(usize, usize), // rows x cols
+    ValueErrorBitSetTooLarge(i64),
+    ValueErrorDsuTooLarge(i64),
+    ValueErrorIterableIsInfinite(ValuePtr),
+    ValueErrorValueMustBeSingle(ValuePtr),
+    ValueErrorCannotFlattenRecursive(ValuePtr),
+    ValueErrorRowLengthMismatch(usize, usize), // expected, actual
+    ValueErrorDuplicateKey(ValuePtr),
+    ValueErrorUnmatchedBraceInTemplate(char),
+    ValueErrorUnmatchedBraceInScanFormat(char),
+    ValueErrorInvalidScanToken(String),
+    ValueErrorUnterminatedQuoteInCsv,
+    ValueErrorInvalidCsvDelimiter(ValuePtr),
+    ValueErrorCannotDecodeToml(String),
+    ValueErrorFeatureNotEnabled(&'static str),
+    ValueErrorNotInteractive(NativeFunction),
+    ValueErrorInvalidColor(ValuePtr),
+    ValueErrorInvalidLogLevel(ValuePtr),
+    ValueErrorInvalidPort(i64),
+    ValueErrorClosed(ValuePtr),
+    ValueErrorTimedOut(ValuePtr),
+    ValueErrorCapabilityDenied(NativeFunction),
+    ValueErrorInvalidSignal(ValuePtr),
+    ValueErrorInvalidArgumentSpec(ValuePtr),
+    ValueErrorCannotMarshalRecursive(ValuePtr),
+    ValueErrorCannotUnmarshal(String),
+    ValueErrorInvalidPackFormat(String, char),
+    ValueErrorPackFormatMissingField(String),
+    ValueErrorCannotPack(String),
+    ValueErrorCannotUnpack(String),
+    ValueErrorDatabase(String),
+    ValueErrorEmptyRange(i64, i64),
+    ValueErrorPropertyFailed(usize, u64, ValuePtr, String), // number of cases, seed, shrunk input, reason
+    ValueErrorTaskNeverCompletes(ValuePtr),
+    ValueErrorInvalidUrl(String),
 
     TypeErrorUnaryOp(UnaryOp, ValuePtr),
     TypeErrorBinaryOp(BinaryOp, ValuePtr, ValuePtr),
     TypeErrorBinaryIs(ValuePtr, ValuePtr),
     TypeErrorCannotConvertToInt(ValuePtr),
+    TypeErrorCannotConstructEnum(StructTypeImpl),
+    TypeErrorPropertyHasNoSetter(String, String),
+    TypeErrorCannotCompare(ValuePtr, ValuePtr),
+    TypeErrorCannotMarshal(ValuePtr),
     TypeErrorFieldNotPresentOnValue(ValuePtr, String, bool), // value, field name, is the value to be printed with to_repr_str()?
 
     TypeErrorArgMustBeInt(ValuePtr),
     TypeErrorArgMustBeComplex(ValuePtr),
+    TypeErrorArgMustBeFraction(ValuePtr),
     TypeErrorArgMustBeStr(ValuePtr),
     TypeErrorArgMustBeChar(ValuePtr),
     TypeErrorArgMustBeIterable(ValuePtr),
@@ -56,7 +111,25 @@ pub enum RuntimeError {
     TypeErrorArgMustBeList(ValuePtr),
     TypeErrorArgMustBeSet(ValuePtr),
     TypeErrorArgMustBeDict(ValuePtr),
+    TypeErrorArgMustBeSorted(ValuePtr),
+    TypeErrorArgMustBePersistent(ValuePtr),
+    TypeErrorArgMustBeMatrix(ValuePtr),
+    TypeErrorArgMustBeBitSet(ValuePtr),
+    TypeErrorArgMustBeDsu(ValuePtr),
+    TypeErrorArgMustBeStopwatch(ValuePtr),
+    TypeErrorArgMustBeWeak(ValuePtr),
+    TypeErrorArgMustBeShared(ValuePtr),
+    TypeErrorArgMustBeTcpStream(ValuePtr),
+    TypeErrorArgMustBeTcpListener(ValuePtr),
+    TypeErrorArgMustBeStream(ValuePtr),
+    TypeErrorArgMustBeHandle(ValuePtr),
+    TypeErrorArgMustBeContext(ValuePtr),
+    TypeErrorArgMustBeDatabase(ValuePtr),
+    TypeErrorArgMustBeGenerator(ValuePtr),
+    TypeErrorArgMustBeTask(ValuePtr),
+    TypeErrorCannotBindSqlParameter(ValuePtr),
     TypeErrorArgMustBeFunction(ValuePtr),
+    TypeErrorArgMustBeMemoized(ValuePtr),
     TypeErrorArgMustBeCmpOrKeyFunction(ValuePtr),
     TypeErrorArgMustBeReplaceFunction(ValuePtr),
 }
@@ -79,12 +152,10 @@ impl RuntimeError {
         E::from(self)
     }
 
-    pub fn with_stacktrace(self, ip: usize, call_stack: &[CallFrame], functions: &[ValuePtr], locations: &[Location]) -> DetailRuntimeError {
-        const REPEAT_LIMIT: usize = 3;
-
+    pub fn with_stacktrace(self, ip: usize, call_stack: &[CallFrame], stack: &[ValuePtr], functions: &[ValuePtr], locations: &[Location], options: &TraceOptions) -> DetailRuntimeError {
         // Top level stack frame refers to the code being executed
         let target: Location = locations.get(ip).copied().unwrap_or(Location::empty());
-        let mut stack: Vec<StackFrame> = Vec::new();
+        let mut frames: Vec<StackFrame> = Vec::new();
         let mut prev_ip: usize = ip;
         let mut prev_frame: Option<(usize, usize)> = None;
         let mut prev_count: usize = 0;
@@ -97,15 +168,23 @@ impl RuntimeError {
                 if prev_frame == Some((frame_ip, prev_ip)) {
                     prev_count += 1;
                 } else {
-                    if prev_count > REPEAT_LIMIT {
+                    if prev_count > options.repeat_limit {
                         // Push a 'repeat' element
-                        stack.push(StackFrame::Repeat(prev_count - REPEAT_LIMIT))
+                        frames.push(StackFrame::Repeat(prev_count - options.repeat_limit))
                     }
                     prev_count = 0;
                 }
 
-                if prev_count <= REPEAT_LIMIT {
-                    stack.push(StackFrame::Simple(frame_ip, locations[frame_ip], find_owning_function(prev_ip, functions)));
+                if prev_count <= options.repeat_limit {
+                    let args: Option<String> = match options.verbose {
+                        true => preview_args(frame, stack),
+                        false => None,
+                    };
+                    frames.push(StackFrame::Simple(locations[frame_ip], find_owning_function(prev_ip, functions), args));
+                    if let Some(native) = frame.cause {
+                        // This frame is a callback, invoked by a native function which was unwinding when the error was raised
+                        frames.push(StackFrame::Cause(native.name(), preview_values(frame, stack)));
+                    }
                 }
 
                 prev_frame = Some((frame_ip, prev_ip));
@@ -113,11 +192,122 @@ impl RuntimeError {
             }
         }
 
-        if prev_count > REPEAT_LIMIT {
-            stack.push(StackFrame::Repeat(prev_count - REPEAT_LIMIT))
+        if prev_count > options.repeat_limit {
+            frames.push(StackFrame::Repeat(prev_count - options.repeat_limit))
+        }
+
+        if let Some(depth) = options.depth {
+            limit_depth(&mut frames, depth);
+        }
+        if options.reverse {
+            reverse(&mut frames);
+        }
+
+        // The definition is inserted last, so it is not affected by the depth or order of the other frames
+        if let RuntimeError::IncorrectArgumentsUserFunction(f, _) = &self {
+            if !f.location().is_empty() {
+                frames.insert(0, StackFrame::Definition(f.location()));
+            }
         }
 
-        DetailRuntimeError { error: self, target, stack }
+        DetailRuntimeError { error: self, target, stack: frames }
+    }
+}
+
+/// Options which control how the stack trace of a `DetailRuntimeError` is rendered, set with `VirtualMachine::with_trace_options()`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceOptions {
+    /// The maximum number of frames to show, starting from the innermost. Any remaining frames are summarized in a single line. If `None`, all frames are shown.
+    pub depth: Option<usize>,
+    /// If `true`, frames are shown from the outermost to the innermost call, instead of the innermost first.
+    pub reverse: bool,
+    /// The number of times a frame which is called repeatedly, i.e. by a recursive function, is shown, after the first, before the rest are collapsed into a single line.
+    pub repeat_limit: usize,
+    /// If `true`, each frame also shows a preview of the arguments to the function.
+    pub verbose: bool,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        TraceOptions { depth: None, reverse: false, repeat_limit: 3, verbose: false }
+    }
+}
+
+/// Keeps only the innermost `depth` frames, and replaces the rest with a single `Omitted` frame.
+fn limit_depth(frames: &mut Vec<StackFrame>, depth: usize) {
+    let mut shown: usize = 0;
+    let mut omitted: usize = 0;
+    frames.retain(|frame| {
+        if let StackFrame::Simple(..) = frame {
+            shown += 1;
+        }
+        if shown <= depth {
+            return true
+        }
+        omitted += match frame {
+            StackFrame::Repeat(n) => *n,
+            StackFrame::Cause(..) => 0,
+            _ => 1,
+        };
+        false
+    });
+    if omitted > 0 {
+        frames.push(StackFrame::Omitted(omitted));
+    }
+}
+
+/// Reverses the order of the frames, keeping each `Repeat` and `Cause` after the frame it belongs to.
+fn reverse(frames: &mut Vec<StackFrame>) {
+    let mut groups: Vec<Vec<StackFrame>> = Vec::new();
+    for frame in frames.drain(..) {
+        match (frame, groups.last_mut()) {
+            (frame @ (StackFrame::Repeat(_) | StackFrame::Cause(..)), Some(group)) => group.push(frame),
+            (frame, _) => groups.push(vec![frame]),
+        }
+    }
+    frames.extend(groups.into_iter().rev().flatten());
+}
+
+/// Returns a preview of the arguments of the function called by `frame`, i.e. `x = 1, y = 'hello'`, truncating any long values.
+fn preview_args(frame: &CallFrame, stack: &[ValuePtr]) -> Option<String> {
+    let func: &FunctionImpl = frame_function(frame, stack)?;
+    let args: Vec<String> = func.args().iter()
+        .zip(stack.iter().skip(frame.frame_pointer))
+        .map(|(name, value)| format!("{} = {}", name, preview(value)))
+        .collect();
+    match args.is_empty() {
+        true => None,
+        false => Some(args.join(", ")),
+    }
+}
+
+/// Returns a preview of the values of the arguments of the function called by `frame`, i.e. `argument 1` or `arguments 1, 'hello'`, truncating any long values.
+fn preview_values(frame: &CallFrame, stack: &[ValuePtr]) -> Option<String> {
+    let func: &FunctionImpl = frame_function(frame, stack)?;
+    let args: Vec<String> = stack.iter()
+        .skip(frame.frame_pointer)
+        .take(func.args().len())
+        .map(preview)
+        .collect();
+    match args.len() {
+        0 => None,
+        1 => Some(format!("argument {}", args[0])),
+        _ => Some(format!("arguments {}", args.join(", "))),
+    }
+}
+
+/// Returns the user function called by `frame`, which sits on the stack just below its arguments, if present.
+fn frame_function<'a>(frame: &CallFrame, stack: &'a [ValuePtr]) -> Option<&'a FunctionImpl> {
+    stack.get(frame.frame_pointer.checked_sub(1)?).filter(|f| f.is_function() || f.is_closure()).map(|f| f.get_function())
+}
+
+fn preview(value: &ValuePtr) -> String {
+    const PREVIEW_LIMIT: usize = 24;
+
+    let repr: String = value.to_repr_str();
+    match repr.chars().count() > PREVIEW_LIMIT {
+        true => format!("{}...", repr.chars().take(PREVIEW_LIMIT - 3).collect::<String>()),
+        false => repr,
     }
 }
 
@@ -133,8 +323,11 @@ pub struct DetailRuntimeError {
 
 #[derive(Debug)]
 enum StackFrame {
-    Simple(usize, Location, String),
+    Simple(Location, String, Option<String>), // location, function, and argument preview (if verbose)
     Repeat(usize),
+    Omitted(usize),
+    Cause(&'static str, Option<String>), // native function, and a preview of the arguments to the callback
+    Definition(Location), // location where a function called with an incorrect number of arguments was defined, always the first frame
 }
 
 impl AsError for DetailRuntimeError {
@@ -148,11 +341,23 @@ impl AsErrorWithContext for DetailRuntimeError {
         self.target
     }
 
+    fn as_error_with_source(&self, view: &SourceView) -> String {
+        match &self.error {
+            RuntimeError::IncorrectArgumentsUserFunction(f, n) => format!("Incorrect number of arguments for {}, got {}", f.signature(view), n),
+            error => error.as_error(),
+        }
+    }
+
     fn add_stack_trace_elements(&self, view: &SourceView, text: &mut String) {
         for frame in &self.stack {
             text.push_str(match frame {
-                StackFrame::Simple(_, loc, site) => format!("  at: `{}` (line {})\n", site, view.lineno(*loc).unwrap_or(0) + 1),
+                StackFrame::Simple(loc, site, None) => format!("  at: `{}` (line {})\n", site, view.lineno(*loc).unwrap_or(0) + 1),
+                StackFrame::Simple(loc, site, Some(args)) => format!("  at: `{}` (line {}) with {}\n", site, view.lineno(*loc).unwrap_or(0) + 1, args),
                 StackFrame::Repeat(n) => format!("  ... above line repeated {} more time(s) ...\n", n),
+                StackFrame::Omitted(n) => format!("  ... {} more frame(s) omitted ...\n", n),
+                StackFrame::Cause(native, None) => format!("  caused while evaluating native `{}`\n", native),
+                StackFrame::Cause(native, Some(args)) => format!("  caused while evaluating native `{}` with {}\n", native, args),
+                StackFrame::Definition(loc) => format!("  defined at: line {} ({})\n", view.lineno(*loc).unwrap_or(0) + 1, view.name_of(*loc)),
             }.as_str());
         }
     }
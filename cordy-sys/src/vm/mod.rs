@@ -1,19 +1,26 @@
 use std::cell::Cell;
 use std::collections::HashMap;
+use std::io;
 use std::io::{BufRead, Write};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::SystemTime;
 use fxhash::FxBuildHasher;
 
-use crate::{compiler, core, trace, util};
+use crate::{compiler, core, util};
 use crate::compiler::{CompileParameters, CompileResult, Fields, IncrementalCompileResult, Locals};
+use crate::coverage::Coverage;
 use crate::reporting::{Location, SourceView};
 use crate::util::OffsetAdd;
-use crate::vm::value::{Field, Literal, UpValue, ValueStructType};
+use crate::vm::value::{Field, Literal, UpValue, handle, weak};
+use crate::vm::scheduler::EventLoop;
 use crate::core::Pattern;
 
-pub use crate::vm::error::{DetailRuntimeError, RuntimeError};
+pub use crate::vm::error::{DetailRuntimeError, RuntimeError, TraceOptions};
 pub use crate::vm::opcode::{Opcode, StoreOp};
-pub use crate::vm::value::{C64, FunctionImpl, guard_recursive_hash, IntoDictValue, IntoIterableValue, IntoValue, Iterable, LiteralType, MAX_INT, MIN_INT, StructTypeImpl, Type, ValueOption, ValuePtr, ValueResult, ErrorResult, AnyResult, Prefix};
+pub use crate::vm::tracer::{TraceEvents, Tracer};
+pub use crate::vm::value::{C64, R64, FunctionImpl, guard_recursive_hash, IntoDictValue, IntoIterableValue, IntoValue, Iterable, LiteralType, MatrixImpl, BitSetImpl, DsuImpl, StopwatchImpl, SocketImpl, WeakImpl, Handle, Poll, TaskImpl, TaskState, ViewImpl, SortedSetImpl, SortedDictImpl, PListImpl, PDictImpl, live_stats, MemStat, CoprocessImpl, DatabaseImpl, GeneratorImpl, LazyIterImpl, MAX_INT, MIN_INT, StructKind, StructTypeImpl, Type, ValueOption, ValuePtr, ValueResult, ValueStructType, ErrorResult, AnyResult, Prefix};
 
 use Opcode::{*};
 use RuntimeError::{*};
@@ -23,14 +30,19 @@ pub mod operator;
 mod value;
 mod opcode;
 mod error;
+mod tracer;
+mod scheduler;
 
 /// Per-test, how many instructions should be allowed to execute.
 /// This primarily prevents infinite-loop tests from causing tests to hang, allowing easier debugging.
 #[cfg(test)]
 const TEST_EXECUTION_LIMIT: usize = 1000;
 
+/// The maximum number of values on the stack which are included in a `Snapshot`
+pub const SNAPSHOT_STACK_PREVIEW: usize = 8;
 
-pub struct VirtualMachine<R, W> {
+
+pub struct VirtualMachine<R, W, E> {
     ip: usize,
     code: Vec<Opcode>,
     stack: Vec<ValuePtr>,
@@ -49,7 +61,56 @@ pub struct VirtualMachine<R, W> {
     view: SourceView,
     read: R,
     write: W,
+    error: E,
     args: ValuePtr,
+
+    /// Functions registered with `at_exit()`, which are invoked in reverse order when the program terminates
+    exit_handlers: Vec<ValuePtr>,
+
+    /// Values entered by a `with` statement which have not been exited yet, innermost last.
+    /// These are exited at the end of their `with` statement, or if an error unwinds past it.
+    contexts: Vec<ValuePtr>,
+
+    /// Tasks created by `async fn`s and asynchronous natives, which are run when the program `await`s one of them
+    tasks: EventLoop,
+    /// The number of nested calls to `run()`, which are currently executing
+    runs: usize,
+
+    /// The terminal this VM is attached to, if it is interactive
+    terminal: Option<Box<dyn Terminal>>,
+
+    /// Messages logged below this level are ignored
+    log_level: LogLevel,
+    /// The destination of logged messages. If not present, they are written to the error stream
+    log_sink: Option<Box<dyn LogSink>>,
+
+    /// The source of random bytes. If not present, the operating system's source is used, if the `entropy` feature is enabled
+    entropy: Option<Box<dyn Entropy>>,
+
+    /// Capabilities which the host has denied to this program
+    denied: Vec<Capability>,
+    /// Values holding handles opened by this program, which are closed when the VM is torn down
+    handles: handle::Handles,
+    /// Finalizers registered by this program with `on_drop()`, of values which have since been freed
+    finalizers: Rc<weak::Finalizers>,
+
+    /// Signals which have been delivered by the host, but not yet handled
+    signals: PendingSignals,
+    /// Functions registered with `on_signal()`, which are invoked when the corresponding signal is handled
+    signal_handlers: Vec<(Signal, ValuePtr)>,
+    /// Invoked when the program first registers a handler for a signal, so the host can start delivering it
+    signal_hook: Option<Box<dyn FnMut(Signal)>>,
+
+    /// If present, the number of times each instruction has been executed, by its index in `code`
+    hits: Option<Vec<u64>>,
+    trace_options: TraceOptions,
+    /// If present, receives an event for each instruction executed, and each function called
+    tracer: Option<Tracer>,
+
+    /// If present, the maximum number of instructions the program may execute, before it raises an error
+    execution_limit: Option<u64>,
+    /// The number of instructions executed so far, which is only counted if there is an `execution_limit`
+    executed: u64,
 }
 
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
@@ -59,32 +120,71 @@ pub enum FunctionType {
 
 #[derive(Debug)]
 pub enum ExitType {
-    Exit, Return, Yield, Error(DetailRuntimeError)
+    /// Exited via `exit`, or reaching the end of the program, with the given exit code
+    Exit(i32),
+    /// Reached the end of a program compiled with `compile_print_last()`, with the value of the trailing expression, or `nil` if there was none.
+    /// Also returned by `run_function()`, with the return value of the function.
+    Value(ValuePtr),
+    Return,
+    Yield,
+    Error(DetailRuntimeError)
 }
 
 impl ExitType {
     pub fn is_early_exit(&self) -> bool {
-        matches!(self, ExitType::Exit | ExitType::Error(_))
+        matches!(self, ExitType::Exit(_) | ExitType::Error(_))
     }
 
-    fn of<R: BufRead, W: Write>(vm: &VirtualMachine<R, W>, result: AnyResult) -> ExitType {
+    fn of<R: BufRead, W: Write, E: Write>(vm: &VirtualMachine<R, W, E>, result: AnyResult) -> ExitType {
         match result.map_err(|e| e.value) {
             Ok(_) => ExitType::Return,
-            Err(RuntimeExit) => ExitType::Exit,
+            Err(RuntimeExit(code)) => ExitType::Exit(code),
+            Err(RuntimeExitValue(value)) => ExitType::Value(value),
             Err(RuntimeYield) => ExitType::Yield,
-            Err(error) => ExitType::Error(error.with_stacktrace(vm.ip - 1, &vm.call_stack, &vm.constants, &vm.locations)),
+            Err(error) => ExitType::Error(error.with_stacktrace(vm.ip.saturating_sub(1), &vm.call_stack, &vm.stack, &vm.constants, &vm.locations, &vm.trace_options)),
         }
     }
 }
 
 
+/// The result of executing a single instruction with `VirtualMachine::step()`.
+#[derive(Debug)]
+pub enum Step {
+    /// The instruction was executed, and the program has not terminated
+    Next(Snapshot),
+    /// The instruction terminated the program, either by exiting, or by raising an error
+    Exit(ExitType),
+}
+
+/// The state of a VM in between instructions, as returned by `VirtualMachine::step()` and `VirtualMachine::snapshot()`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    /// The index of the next instruction to be executed
+    pub ip: usize,
+    /// The line number of the next instruction, starting at `1`, if it has a location
+    pub line: Option<usize>,
+    /// The next instruction to be executed
+    pub op: Option<Opcode>,
+    /// The depth of the call stack, which is `1` outside any function
+    pub frames: usize,
+    /// The number of values on the stack
+    pub depth: usize,
+    /// The `repr()` of the values on top of the stack, starting with the topmost, up to `SNAPSHOT_STACK_PREVIEW` values
+    pub stack: Vec<String>,
+}
+
+
 pub trait VirtualInterface {
     // Invoking Functions
 
-    fn invoke_func0(&mut self, f: ValuePtr) -> ValueResult;
-    fn invoke_func1(&mut self, f: ValuePtr, a1: ValuePtr) -> ValueResult;
-    fn invoke_func2(&mut self, f: ValuePtr, a1: ValuePtr, a2: ValuePtr) -> ValueResult;
-    fn invoke_func(&mut self, f: ValuePtr, args: &[ValuePtr]) -> ValueResult;
+    /// Invokes `f` with `args`, and returns its result. This is how any native function calls back into Cordy, for example `vm.call(f, [a, b])?`.
+    ///
+    /// `f` can be any value which can be evaluated as a function, including user functions, native and partial functions, and struct constructors. The call is re-entrant,
+    /// so `f` may itself invoke natives which call back into Cordy. If `f` raises an error, it is returned, and should be propagated by the caller.
+    fn call(&mut self, f: ValuePtr, args: impl IntoIterator<Item=ValuePtr>) -> ValueResult;
+
+    /// Invokes `f` with `args`, like `call()`. If `f` raises an error, the VM is restored to the state it was in before the call, so the caller may recover from the error.
+    fn try_call(&mut self, f: ValuePtr, args: impl IntoIterator<Item=ValuePtr>) -> ValueResult;
 
     fn invoke_eval(&mut self, s: &String) -> ValueResult;
 
@@ -95,6 +195,12 @@ pub trait VirtualInterface {
     fn println0(&mut self);
     fn println(&mut self, str: String);
     fn print(&mut self, str: String);
+    fn flush(&mut self);
+    fn write_bytes(&mut self, bytes: &[u8]);
+
+    fn eprintln0(&mut self);
+    fn eprintln(&mut self, str: String);
+    fn eprint(&mut self, str: String);
 
     fn read_line(&mut self) -> String;
     fn read(&mut self) -> String;
@@ -103,6 +209,41 @@ pub trait VirtualInterface {
     fn get_env(&self, name: &String) -> ValuePtr;
     fn get_args(&self) -> ValuePtr;
 
+    /// Registers `f` to be invoked when the program terminates
+    fn at_exit(&mut self, f: ValuePtr);
+
+    /// Returns the terminal, if this VM is interactive, i.e. it is attached to a terminal with `VirtualMachine::with_terminal()`
+    fn terminal(&mut self) -> Option<&mut dyn Terminal>;
+
+    /// Logs a message with the given level and fields, if the level is enabled. The record is attributed to the location of the currently executing instruction.
+    fn log(&mut self, level: LogLevel, message: String, fields: Vec<(String, String)>);
+    fn log_level(&self) -> LogLevel;
+    fn set_log_level(&mut self, level: LogLevel);
+
+    /// Returns the source of random bytes provided by the host with `VirtualMachine::with_entropy()`, if present
+    fn entropy(&mut self) -> Option<&mut dyn Entropy>;
+
+    /// Returns `true` if the host has not denied `capability` with `VirtualMachine::deny()`
+    fn is_allowed(&self, capability: Capability) -> bool;
+
+    /// Registers `value`, which holds a `Handle`, so that it is closed if it is still open when this VM is torn down. Returns `value`.
+    fn register_handle(&mut self, value: ValuePtr) -> ValuePtr;
+
+    /// Registers `f` to be invoked by this VM, with no arguments, once `value` has been freed. `value` must be a shared value.
+    fn add_finalizer(&mut self, value: &ValuePtr, f: ValuePtr);
+
+    /// Sets the function to be invoked when `signal` is received, or removes it if `f` is `None`
+    fn on_signal(&mut self, signal: Signal, f: Option<ValuePtr>);
+
+    /// Returns the most recently declared struct type with the given name and field names, if one exists
+    fn struct_type(&self, name: &str, field_names: &[String]) -> Option<ValuePtr>;
+
+    /// Registers a task created by an asynchronous native function, which is waiting on a timer or IO, with the event loop
+    fn spawn(&mut self, task: ValuePtr);
+
+    /// Returns the number of values on the stack, and the number of call frames, including the top level frame
+    fn stack_depth(&self) -> (usize, usize);
+
     // Stack Manipulation
     fn peek(&self, offset: usize) -> &ValuePtr;
     fn pop(&mut self) -> ValuePtr;
@@ -113,6 +254,163 @@ pub trait VirtualInterface {
 
 
 
+/// An interactive terminal, which is provided by the host of the VM.
+/// This is separate from the VM's input and output streams, as it is only available when running attached to an actual terminal.
+pub trait Terminal {
+    /// Returns the size of the terminal, as `(columns, rows)`
+    fn size(&self) -> io::Result<(u16, u16)>;
+
+    /// Reads a single key press, in raw mode, without waiting for a newline or echoing it to the output.
+    ///
+    /// Printable keys are returned as a single character string, and other keys are returned by name: `up`, `down`, `left`, `right`, `enter`, `escape`, `backspace`, `tab`, `delete`, `home`, `end`, `page_up`, `page_down`, or `f1` through `f12`.
+    /// Modifiers are prefixed to the key name, for instance `ctrl+c`.
+    fn read_key(&mut self) -> io::Result<String>;
+}
+
+
+/// The severity of a logged message, in increasing order.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LogLevel {
+    Debug, Info, Warn, Error
+}
+
+impl LogLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        match name {
+            "debug" => Some(LogLevel::Debug),
+            "info" => Some(LogLevel::Info),
+            "warn" => Some(LogLevel::Warn),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+}
+
+/// A single message logged by one of the `log_debug()`, `log_info()`, `log_warn()`, or `log_error()` natives.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub message: String,
+    /// Additional structured fields, as `(key, value)` pairs, where each value is the `repr()` of the original value
+    pub fields: Vec<(String, String)>,
+    pub timestamp: SystemTime,
+    /// The name of the source the message was logged from, or empty if it is not known
+    pub source: String,
+    /// The one-indexed line the message was logged from, if known
+    pub lineno: Option<usize>,
+}
+
+impl LogRecord {
+    /// Formats this record as a single line, for instance `2023-01-02T03:04:05.678Z INFO  main.cor:12 message key=value`
+    pub fn format(&self) -> String {
+        core::format_log_record(self)
+    }
+}
+
+/// A destination for logged messages, which can be provided by the host of the VM with `VirtualMachine::with_log_sink()`.
+/// Messages are only passed to the sink if they are at or above the VM's current log level.
+pub trait LogSink {
+    fn log(&mut self, record: LogRecord);
+}
+
+
+/// A source of random bytes, used by natives such as `uuid4()` and `rand_bytes()`.
+/// This can be provided by the host with `VirtualMachine::with_entropy()`, for instance to make a program's output deterministic.
+pub trait Entropy {
+    /// Fills `buf` with random bytes
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<()>;
+}
+
+
+/// A capability of the system the VM is running on, which the host may deny to a program with `VirtualMachine::deny()`. By default, all capabilities are allowed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Capability {
+    /// Opening and accepting network connections, with `tcp_connect()` and `tcp_listen()`
+    Net,
+    /// Starting external processes, with `coprocess()`
+    Process,
+    /// Reading and writing files and directories, with `read_text()`, `write_text()`, `list_dir()`, `walk()`, `glob()`, and `db_open()`, and reading the current, home, and temporary directories, with `absolute()`, `home_dir()`, and `temp_dir()`
+    Fs,
+}
+
+impl Capability {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Net => "net",
+            Capability::Process => "process",
+            Capability::Fs => "fs",
+        }
+    }
+}
+
+
+/// An operating system signal, which a program can intercept with `on_signal()`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Signal {
+    Int, Term
+}
+
+impl Signal {
+    const ALL: [Signal; 2] = [Signal::Int, Signal::Term];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Int => "INT",
+            Signal::Term => "TERM",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Signal> {
+        Signal::ALL.into_iter().find(|signal| signal.name() == name)
+    }
+
+    /// The exit code of a process which was terminated by this signal, by convention `128 + N` where `N` is the signal number.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Signal::Int => 130,
+            Signal::Term => 143,
+        }
+    }
+
+    fn mask(&self) -> u8 {
+        1 << (*self as u8)
+    }
+}
+
+/// The set of signals which have been received, but not yet handled by the VM.
+///
+/// This is shared between the VM and the host, which obtains it with `VirtualMachine::signals()`. Raising a signal only sets an atomic flag, so it is safe to do from within a signal handler or another thread.
+/// The VM then checks for pending signals between instructions, so handlers are never invoked in the middle of an instruction.
+#[derive(Debug, Clone, Default)]
+pub struct PendingSignals(Arc<AtomicU8>);
+
+impl PendingSignals {
+    pub fn raise(&self, signal: Signal) {
+        self.0.fetch_or(signal.mask(), Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    fn is_pending(&self) -> bool {
+        self.0.load(Ordering::Relaxed) != 0
+    }
+
+    /// Clears, and returns, all pending signals
+    fn take(&self) -> impl Iterator<Item=Signal> {
+        let mask: u8 = self.0.swap(0, Ordering::Relaxed);
+        Signal::ALL.into_iter().filter(move |signal| mask & signal.mask() != 0)
+    }
+}
+
+
 #[derive(Debug)]
 pub struct CallFrame {
     /// The return address
@@ -122,19 +420,24 @@ pub struct CallFrame {
     /// A pointer into the current runtime stack, where this function's locals are stored
     /// The local at index 0 will be the function itself, local 1, ...N will be the N parameters, locals after that will be local variables to the function
     frame_pointer: usize,
+
+    /// If this function was invoked as a callback by a native function, which then returned an error raised within this function, that native function.
+    pub cause: Option<core::NativeFunction>,
 }
 
 
-impl<R, W> VirtualMachine<R, W> where
+impl<R, W, E> VirtualMachine<R, W, E> where
     R: BufRead,
-    W: Write {
+    W: Write,
+    E: Write {
 
-    pub fn new(result: CompileResult, view: SourceView, read: R, write: W, args: Vec<String>) -> VirtualMachine<R, W> {
+    /// Creates a new VM, where `read` is used for input, `write` for standard output, and `error` for the standard error stream used by `eprint()` and `eprintln()`
+    pub fn new(result: CompileResult, view: SourceView, read: R, write: W, error: E, args: Vec<String>) -> VirtualMachine<R, W, E> {
         VirtualMachine {
             ip: 0,
             code: result.code,
             stack: Vec::with_capacity(256), // Just guesses, not hard limits
-            call_stack: vec![CallFrame { return_ip: 0, frame_pointer: 0 }],
+            call_stack: vec![CallFrame { return_ip: 0, frame_pointer: 0, cause: None }],
             literal_stack: Vec::with_capacity(16),
             global_count: 0,
             open_upvalues: HashMap::with_hasher(FxBuildHasher::default()),
@@ -149,10 +452,109 @@ impl<R, W> VirtualMachine<R, W> where
             view,
             read,
             write,
+            error,
             args: args.into_iter().map(|u| u.to_value()).to_list(),
+
+            exit_handlers: Vec::new(),
+            contexts: Vec::new(),
+            tasks: EventLoop::default(),
+            runs: 0,
+            terminal: None,
+
+            log_level: LogLevel::Info,
+            log_sink: None,
+
+            entropy: None,
+            denied: Vec::new(),
+            handles: handle::Handles::default(),
+            finalizers: Rc::new(weak::Finalizers::default()),
+
+            signals: PendingSignals::default(),
+            signal_handlers: Vec::new(),
+            signal_hook: None,
+
+            hits: None,
+            trace_options: TraceOptions::default(),
+            tracer: None,
+
+            execution_limit: None,
+            executed: 0,
         }
     }
 
+    /// Attaches this VM to an interactive terminal, which enables natives such as `term_size()` and `read_key()`
+    pub fn with_terminal(mut self, terminal: Box<dyn Terminal>) -> Self {
+        self.terminal = Some(terminal);
+        self
+    }
+
+    /// Sends logged messages to `sink`, instead of the error stream
+    pub fn with_log_sink(mut self, sink: Box<dyn LogSink>) -> Self {
+        self.log_sink = Some(sink);
+        self
+    }
+
+    /// Sets the initial log level, below which messages are ignored. By default, this is `LogLevel::Info`
+    pub fn with_log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    /// Uses `entropy` as the source of random bytes, instead of the operating system's source
+    pub fn with_entropy(mut self, entropy: Box<dyn Entropy>) -> Self {
+        self.entropy = Some(entropy);
+        self
+    }
+
+    /// Denies `capability` to the program, so any natives which require it raise an error instead
+    pub fn deny(mut self, capability: Capability) -> Self {
+        self.denied.push(capability);
+        self
+    }
+
+    /// Sets a function which is invoked when the program first registers a handler for a signal with `on_signal()`.
+    /// The host should then start intercepting that signal, and deliver it to the VM using the handle returned by `signals()`.
+    pub fn with_signal_hook(mut self, hook: Box<dyn FnMut(Signal)>) -> Self {
+        self.signal_hook = Some(hook);
+        self
+    }
+
+    /// Sets how the stack traces of any errors raised by the program are rendered.
+    pub fn with_trace_options(mut self, options: TraceOptions) -> Self {
+        self.trace_options = options;
+        self
+    }
+
+    /// Traces the execution of the program with `tracer`, which emits an event for each instruction executed, or function called
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Limits the program to executing at most `limit` instructions in total, after which any further instruction raises an error.
+    /// This is useful for running untrusted or arbitrary programs, which may never terminate.
+    pub fn with_execution_limit(mut self, limit: u64) -> Self {
+        self.execution_limit = Some(limit);
+        self
+    }
+
+    /// Records which lines of the program are executed, which can be retrieved with `coverage()`.
+    pub fn with_coverage(mut self) -> Self {
+        self.hits = Some(vec![0; self.code.len()]);
+        self
+    }
+
+    /// Returns the lines which have been executed so far, if this VM was created with `with_coverage()`.
+    pub fn coverage(&self) -> Option<Coverage> {
+        self.hits.as_ref().map(|hits| Coverage::new(&self.view, &self.locations, hits))
+    }
+
+    /// Returns a handle which can be used to deliver signals to this VM.
+    /// If a signal is delivered which the program has no handler for, the program exits as if it was terminated by that signal.
+    pub fn signals(&self) -> PendingSignals {
+        self.signals.clone()
+    }
+
     pub fn view(&self) -> &SourceView {
         &self.view
     }
@@ -166,6 +568,16 @@ impl<R, W> VirtualMachine<R, W> where
         compiler::incremental_compile(self.as_compile_parameters(false, locals))
     }
 
+    /// Bridge method to `compiler::incremental_compile_value`
+    pub fn incremental_compile_value(&mut self, locals: &mut Vec<Locals>) -> IncrementalCompileResult {
+        compiler::incremental_compile_value(self.as_compile_parameters(false, locals))
+    }
+
+    /// Removes and returns the value on top of the stack, which is the value left by code compiled with `incremental_compile_value()`, once it has run to completion.
+    pub fn pop_value(&mut self) -> ValuePtr {
+        self.pop()
+    }
+
     /// Bridge method to `compiler::eval_compile`
     pub fn eval_compile(&mut self, text: &String) -> AnyResult {
         let mut locals = Locals::empty();
@@ -178,7 +590,105 @@ impl<R, W> VirtualMachine<R, W> where
 
     pub fn run_until_completion(&mut self) -> ExitType {
         let result = self.run();
-        ExitType::of(self, result)
+        self.terminate(result)
+    }
+
+    /// Returns how the program exited, given the `result` of running it. If the program terminated with an error or `exit`, then this first exits
+    /// any contexts of `with` statements which were still entered. An error raised while doing so is only returned if the program did not already raise one.
+    fn terminate(&mut self, result: AnyResult) -> ExitType {
+        let exit: ExitType = ExitType::of(self, result);
+        match (exit, self.unwind_contexts(0)) {
+            (exit @ ExitType::Error(_), _) | (exit, Ok(())) => exit,
+            (_, error) => ExitType::of(self, error),
+        }
+    }
+
+    /// Executes exactly one instruction, and returns a snapshot of the state of the VM afterwards, or how the program exited, if that instruction terminated it.
+    ///
+    /// This can be called repeatedly in place of `run_until_completion()`, for instance by a debugger. An instruction which calls a user function only enters it,
+    /// so the next step is the first instruction of that function. However, a callback invoked by a native function, such as `map`, runs to completion within a single step.
+    pub fn step(&mut self) -> Step {
+        match self.run_step() {
+            Ok(()) => Step::Next(self.snapshot()),
+            result => Step::Exit(self.terminate(result)),
+        }
+    }
+
+    /// Returns a snapshot of the current state of the VM, which describes the next instruction to be executed.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ip: self.ip,
+            line: self.lineno(self.ip),
+            op: self.code.get(self.ip).copied(),
+            frames: self.call_stack.len(),
+            depth: self.stack.len(),
+            stack: self.stack.iter().rev().take(SNAPSHOT_STACK_PREVIEW).map(|v| v.to_repr_str()).collect(),
+        }
+    }
+
+    /// Invokes each function registered with `at_exit()`, in the reverse order they were registered, and returns the final `ExitType` of the program.
+    /// This should be called once the program has terminated, with `exit` being the result of the program.
+    ///
+    /// Handlers are still invoked if the program terminated with an error. If a handler raises an error or calls `exit`, the remaining handlers are still invoked.
+    /// The first error raised, by either the program or a handler, is returned, otherwise the last exit code is used.
+    ///
+    /// Afterwards, any handles which are still open are closed, so this should only be called once the VM will not be used to run any further code.
+    pub fn run_exit_handlers(&mut self, mut exit: ExitType) -> ExitType {
+        while let Some(f) = self.exit_handlers.pop() {
+            exit = match (exit, self.run_function(f)) {
+                (exit @ ExitType::Error(_), _) => exit,
+                (_, handler @ (ExitType::Error(_) | ExitType::Exit(_))) => handler,
+                (exit, _) => exit,
+            };
+        }
+        // Finally, close any handles (sockets, coprocesses, and databases) which the program left open, as nothing else can close them now.
+        // These are closed gracefully, and any errors raised by closing them are ignored.
+        for handle in self.handles.take() {
+            let _ = core::close(handle);
+        }
+        exit
+    }
+
+    /// Invokes `f` with no arguments, once the program has terminated, and returns how it exited. If `f` returned normally, this is `ExitType::Value` with its return value.
+    ///
+    /// This can be used to run a designated `main` function, i.e. `vm.run_function(vm.global("main").unwrap().clone())`, with a program compiled with `compiler::compile_retaining_globals()`.
+    pub fn run_function(&mut self, f: ValuePtr) -> ExitType {
+        self.run_recovery(self.global_count);
+        let mut value: ValuePtr = ValuePtr::nil();
+        let result: AnyResult = self.call(f, []).as_result().map(|ret| value = ret);
+        match self.terminate(result) {
+            ExitType::Return => ExitType::Value(value),
+            exit => exit,
+        }
+    }
+
+    /// Returns the name and value of each global variable which has been declared, in the order they were declared.
+    pub fn globals(&self) -> impl Iterator<Item=(&str, &ValuePtr)> {
+        self.globals.iter()
+            .zip(self.stack.iter())
+            .take(self.global_count)
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// Returns the value of the global variable `name`, or `None` if it has not been declared. If more than one global has this name, the last one declared is returned.
+    pub fn global(&self, name: &str) -> Option<&ValuePtr> {
+        self.globals()
+            .filter(|(it, _)| *it == name)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    /// Removes and returns the name and value of each global variable which has been declared, in the order they were declared.
+    ///
+    /// Afterwards, the VM has no globals, so this should only be called once it will not be used to run any further code.
+    pub fn take_globals(&mut self) -> Vec<(String, ValuePtr)> {
+        let count: usize = self.global_count.min(self.stack.len());
+        let globals: Vec<(String, ValuePtr)> = self.globals.drain(..)
+            .zip(self.stack.drain(..count))
+            .collect();
+        self.global_count = 0;
+        self.run_recovery(0);
+        globals
     }
 
     /// Recovers the VM into an operational state, in case previous instructions terminated in an error or in the middle of a function
@@ -186,13 +696,40 @@ impl<R, W> VirtualMachine<R, W> where
         self.call_stack.truncate(1);
         self.stack.truncate(locals);
         self.literal_stack.clear();
+        self.contexts.clear();
+        self.tasks.clear();
         self.ip = self.code.len();
     }
 
+    /// Called when `native`, invoked with a call stack of depth `frames`, returns an error. If the error was raised from within a callback, then the
+    /// callback's frame will still be present on the call stack, and so we record `native` as the cause, to be shown in the stack trace.
+    ///
+    /// `eval` is excluded, as the code it evaluates is not a callback, and is already shown in the stack trace.
+    #[cold]
+    fn add_cause(&mut self, frames: usize, native: core::NativeFunction) {
+        if native != core::NativeFunction::Eval {
+            if let Some(frame) = self.call_stack.get_mut(frames) {
+                frame.cause = Some(native);
+            }
+        }
+    }
+
     fn run(&mut self) -> AnyResult {
+        let drop_frame: usize = self.call_stack.len() - 1;
+        self.run_until(drop_frame)
+    }
+
+    /// Runs until the call stack has been unwound to a depth of `drop_frame`, for instance when the function on top of the call stack returns.
+    fn run_until(&mut self, drop_frame: usize) -> AnyResult {
+        self.runs += 1;
+        let result: AnyResult = self.run_loop(drop_frame);
+        self.runs -= 1;
+        result
+    }
+
+    fn run_loop(&mut self, drop_frame: usize) -> AnyResult {
         #[cfg(test)]
         let mut limit = 0;
-        let drop_frame: usize = self.call_stack.len() - 1;
         loop {
             #[cfg(test)]
             {
@@ -201,53 +738,128 @@ impl<R, W> VirtualMachine<R, W> where
                     panic!("Execution limit reached");
                 }
             }
-            let op: Opcode = self.next_op();
-            self.run_instruction(op)?;
+            self.run_step()?;
             if drop_frame == self.call_stack.len() {
                 return Ok(())
             }
         }
     }
 
+    /// Executes the next instruction, and then handles any pending signals or finalizers.
+    #[inline(always)]
+    fn run_step(&mut self) -> AnyResult {
+        if let Some(limit) = self.execution_limit {
+            if self.executed == limit {
+                return RuntimeExecutionLimit(limit).err()
+            }
+            self.executed += 1;
+        }
+        if let Some(hits) = &mut self.hits {
+            // Code may be added after the VM is created, by `eval` or incremental compiles
+            if self.ip >= hits.len() {
+                hits.resize(self.code.len(), 0);
+            }
+            hits[self.ip] += 1;
+        }
+        let ip: usize = self.ip;
+        let op: Opcode = self.next_op();
+        if self.tracer.is_some() {
+            self.trace_op(ip, op);
+        }
+        self.run_instruction(op)?;
+        if self.tracer.is_some() {
+            self.trace_stack(ip);
+        }
+        if self.signals.is_pending() {
+            self.run_signal_handlers()?;
+        }
+        if self.finalizers.is_pending() {
+            self.run_finalizers()?;
+        }
+        Ok(())
+    }
+
+    /// The line number of the instruction at `ip`, starting at `1`, if it has a location
+    fn lineno(&self, ip: usize) -> Option<usize> {
+        self.locations.get(ip).and_then(|loc| self.view.lineno(*loc)).map(|lineno| lineno + 1)
+    }
+
+    #[cold]
+    fn trace_op(&mut self, ip: usize, op: Opcode) {
+        let line: Option<usize> = self.lineno(ip);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.op(ip, line, op, self.stack.len());
+        }
+    }
+
+    #[cold]
+    fn trace_stack(&mut self, ip: usize) {
+        if let Some(tracer) = &mut self.tracer {
+            let stack: &Vec<ValuePtr> = &self.stack;
+            tracer.stack(ip, || stack.iter().map(|v| v.to_repr_str()).collect());
+        }
+    }
+
+    /// Invokes the handler for each pending signal. If there is no handler for a signal, this exits with the signal's exit code instead.
+    #[cold]
+    fn run_signal_handlers(&mut self) -> AnyResult {
+        for signal in self.signals.take() {
+            match self.signal_handlers.iter().find(|(s, _)| *s == signal) {
+                Some((_, f)) => {
+                    self.call(f.clone(), []).as_result()?;
+                },
+                None => return RuntimeExit(signal.exit_code()).err(),
+            }
+        }
+        Ok(())
+    }
+
+    /// Invokes the finalizer of each value which has been freed, registered with `on_drop()`, in the order the values were freed.
+    #[cold]
+    fn run_finalizers(&mut self) -> AnyResult {
+        for f in self.finalizers.take() {
+            self.call(f, []).as_result()?;
+        }
+        Ok(())
+    }
+
     /// Executes a single instruction
     #[inline(always)]
     fn run_instruction(&mut self, op: Opcode) -> AnyResult {
-        trace::trace_interpreter!("vm::run op={:?}", op);
         match op {
-            Noop => panic!("Noop should only be emitted as a temporary instruction"),
+            Noop => return RuntimeInternalError(String::from("Noop should only be emitted as a temporary instruction")).err(),
 
             // Flow Control
             JumpIfFalse(ip) => {
-                let jump: usize = self.ip.add_offset(ip);
+                let jump: usize = ip as usize;
                 let a1: &ValuePtr = self.peek(0);
                 if !a1.to_bool() {
                     self.ip = jump;
                 }
             },
             JumpIfFalsePop(ip) => {
-                let jump: usize = self.ip.add_offset(ip);
+                let jump: usize = ip as usize;
                 let a1: ValuePtr = self.pop();
                 if !a1.to_bool() {
                     self.ip = jump;
                 }
             },
             JumpIfTrue(ip) => {
-                let jump: usize = self.ip.add_offset(ip);
+                let jump: usize = ip as usize;
                 let a1: &ValuePtr = self.peek(0);
                 if a1.to_bool() {
                     self.ip = jump;
                 }
             },
             JumpIfTruePop(ip) => {
-                let jump: usize = self.ip.add_offset(ip);
+                let jump: usize = ip as usize;
                 let a1: ValuePtr = self.pop();
                 if a1.to_bool() {
                     self.ip = jump;
                 }
             },
             Jump(ip) => {
-                let jump: usize = self.ip.add_offset(ip);
-                self.ip = jump;
+                self.ip = ip as usize;
             },
             Return => {
                 // Functions leave their return value as the top of the stack
@@ -256,7 +868,9 @@ impl<R, W> VirtualMachine<R, W> where
                 // [prev values ... function, local0, local1, ... localN, ret_val ]
                 //                            ^frame pointer
                 // So, we pop the return value, truncate the difference between the frame pointer and the top, then push the return value
-                trace::trace_interpreter_stack!("drop frame {}", self.debug_stack());
+                if self.tracer.is_some() {
+                    self.trace_return();
+                }
 
                 let frame: CallFrame = self.call_stack.pop().unwrap(); // Pop the call frame
 
@@ -272,29 +886,24 @@ impl<R, W> VirtualMachine<R, W> where
             PopN(n) => {
                 let len: usize = self.stack.len();
                 self.stack.truncate(len - n as usize);
-                trace::trace_interpreter_stack!("PopN {}", self.debug_stack());
             },
             Swap => {
                 let len: usize = self.stack.len();
                 self.stack.swap(len - 1, len - 2);
-                trace::trace_interpreter_stack!("Swap {}", self.debug_stack());
             },
 
             PushLocal(local) => {
                 // Locals are offset by the frame pointer, and don't need to check existence, as we don't allow late binding.
                 let local = self.frame_pointer() + local as usize;
-                trace::trace_interpreter!("vm::run PushLocal index={}, local={}", local, self.stack[local].as_debug_str());
                 self.push(self.stack[local].clone());
             }
             StoreLocal(local, pop) => {
-                trace::trace_interpreter!("vm::run StoreLocal index={}, value={}, prev={}", local, self.stack.last().unwrap().as_debug_str(), self.stack[local as usize].as_debug_str());
                 let value = if pop { self.pop() } else { self.peek(0).clone() };
                 self.store_local(local, value);
             },
             PushGlobal(local) => {
                 // Globals are absolute offsets, and allow late binding, which means we have to check the global count before referencing.
                 let local: usize = local as usize;
-                trace::trace_interpreter!("vm::run PushGlobal index={}, value={}", local, self.stack[local].as_debug_str());
                 if local < self.global_count {
                     self.push(self.stack[local].clone());
                 } else {
@@ -302,7 +911,6 @@ impl<R, W> VirtualMachine<R, W> where
                 }
             },
             StoreGlobal(local, pop) => {
-                trace::trace_interpreter!("vm::run StoreGlobal index={}, value={}, prev={}", local, self.stack.last().unwrap().as_debug_str(), self.stack[local as usize].as_debug_str());
                 let value = if pop { self.pop() } else { self.peek(0).clone() };
                 self.store_global(local, value)?;
             },
@@ -317,17 +925,14 @@ impl<R, W> VirtualMachine<R, W> where
                     UpValue::Open(index) => self.stack[index].clone(),
                     UpValue::Closed(value) => value,
                 };
-                trace::trace_interpreter!("vm::run PushUpValue index={}, value={}", index, value.as_debug_str());
                 self.push(value);
             },
             StoreUpValue(index) => {
-                trace::trace_interpreter!("vm::run StoreUpValue index={}, value={}, prev={}", index, self.stack.last().unwrap().as_debug_str(), self.stack[index as usize].as_debug_str());
                 let value = self.peek(0).clone();
                 self.store_upvalue(index, value);
             },
 
             StoreArray => {
-                trace::trace_interpreter!("vm::run StoreArray array={}, index={}, value={}", self.stack[self.stack.len() - 3].as_debug_str(), self.stack[self.stack.len() - 2].as_debug_str(), self.stack.last().unwrap().as_debug_str());
                 let a3: ValuePtr = self.pop();
                 let a2: ValuePtr = self.pop();
                 let a1: &ValuePtr = self.peek(0); // Leave this on the stack when done
@@ -345,7 +950,6 @@ impl<R, W> VirtualMachine<R, W> where
 
             CloseLocal(index) => {
                 let local: usize = self.frame_pointer() + index as usize;
-                trace::trace_interpreter!("vm::run CloseLocal index={}, local={}, value={}, closure={}", index, local, self.stack[local].as_debug_str(), self.stack.last().unwrap().as_debug_str());
                 let upvalue: Rc<Cell<UpValue>> = self.open_upvalues.entry(local)
                     .or_insert_with(|| Rc::new(Cell::new(UpValue::Open(local))))
                     .clone();
@@ -356,7 +960,6 @@ impl<R, W> VirtualMachine<R, W> where
                     .push(upvalue);
             },
             CloseUpValue(index) => {
-                trace::trace_interpreter!("vm::run CloseUpValue index={}, value={}, closure={}", index, self.stack.last().unwrap().as_debug_str(), &self.stack[self.frame_pointer() - 1].as_debug_str());
                 let fp = self.frame_pointer() - 1;
                 let index: usize = index as usize;
                 let upvalue: Rc<Cell<UpValue>> = self.stack[fp].as_closure().borrow().get(index);
@@ -367,6 +970,14 @@ impl<R, W> VirtualMachine<R, W> where
                     .borrow_mut()
                     .push(upvalue.clone());
             },
+            CloseValue => {
+                let value: ValuePtr = self.pop();
+                self.stack.last()
+                    .unwrap()
+                    .as_closure()
+                    .borrow_mut()
+                    .push(Rc::new(Cell::new(UpValue::Closed(value))));
+            },
 
             LiftUpValue(index) => {
                 let index = self.frame_pointer() + index as usize;
@@ -375,7 +986,7 @@ impl<R, W> VirtualMachine<R, W> where
                     let unboxed: UpValue = (*upvalue).replace(UpValue::Open(0));
                     let closed: UpValue = match unboxed {
                         UpValue::Open(_) => UpValue::Closed(value),
-                        UpValue::Closed(_) => panic!("Tried to lift an already closed upvalue"),
+                        UpValue::Closed(_) => return RuntimeInternalError(String::from("Tried to lift an already closed upvalue")).err(),
                     };
                     (*upvalue).replace(closed);
                 }
@@ -390,7 +1001,7 @@ impl<R, W> VirtualMachine<R, W> where
                 let iter = self.stack[top].as_iterable_mut();
                 match iter.next() {
                     Some(value) => self.push(value),
-                    None => self.ip = self.ip.add_offset(ip),
+                    None => self.ip = ip as usize,
                 }
             },
 
@@ -418,9 +1029,10 @@ impl<R, W> VirtualMachine<R, W> where
                 top.accumulate(splice(&mut self.stack, length));
             },
             LiteralUnroll => {
-                let arg = self.pop();
+                let arg = self.pop().to_iter()?;
                 let top = self.literal_stack.last_mut().unwrap();
-                top.unroll(arg.to_iter()?)?;
+                top.reserve(arg.len());
+                top.unroll(arg)?;
             },
             LiteralEnd => {
                 let top = self.literal_stack.pop().unwrap();
@@ -478,12 +1090,12 @@ impl<R, W> VirtualMachine<R, W> where
 
             GetField(field_index) => {
                 let a1: ValuePtr = self.pop();
-                let ret: ValuePtr = a1.get_field(&self.fields, field_index)?;
+                let ret: ValuePtr = self.get_field(a1, field_index)?;
                 self.push(ret);
             },
             GetFieldPeek(field_index) => {
                 let a1: ValuePtr = self.peek(0).clone();
-                let ret: ValuePtr = a1.get_field(&self.fields, field_index)?;
+                let ret: ValuePtr = self.get_field(a1, field_index)?;
                 self.push(ret);
             },
             GetFieldFunction(field_index) => {
@@ -492,9 +1104,30 @@ impl<R, W> VirtualMachine<R, W> where
             SetField(field_index) => {
                 let a2: ValuePtr = self.pop();
                 let a1: ValuePtr = self.pop();
-                let ret: ValuePtr = a1.set_field(&self.fields, field_index, a2)?;
+                let ret: ValuePtr = self.set_field(a1, field_index, a2)?;
                 self.push(ret);
             },
+            InitMember(member_offset) => {
+                let value: ValuePtr = self.pop();
+                self.peek(0).as_struct_type().borrow_const().init_member(member_offset as usize, value);
+            },
+            InheritMembers => {
+                let parent: ValuePtr = self.pop();
+                self.peek(0).as_struct_type().borrow_const().inherit_members(parent.as_struct_type().borrow_const());
+            },
+            EnterContext => {
+                let context: ValuePtr = self.pop();
+                let ret: ValuePtr = self.enter_context(context)?;
+                self.push(ret);
+            },
+            ExitContext => {
+                let context: ValuePtr = self.contexts.pop().unwrap();
+                self.exit_context(context)?;
+            },
+            Await => {
+                let task: ValuePtr = self.pop();
+                self.await_task(task)?;
+            },
 
             Unary(op) => {
                 let a1: ValuePtr = self.pop();
@@ -504,7 +1137,19 @@ impl<R, W> VirtualMachine<R, W> where
             Binary(op) => {
                 let a2: ValuePtr = self.pop();
                 let a1: ValuePtr = self.pop();
-                let ret: ValuePtr = op.apply(a1, a2)?;
+                let ret: ValuePtr = operator::apply_binary(self, op, a1, a2)?;
+                self.push(ret);
+            },
+            BinaryLocal(op, local) => {
+                let a2: ValuePtr = self.stack[self.frame_pointer() + local as usize].clone();
+                let a1: ValuePtr = self.pop();
+                let ret: ValuePtr = operator::apply_binary(self, op, a1, a2)?;
+                self.push(ret);
+            },
+            BinaryConst(op, id) => {
+                let a2: ValuePtr = self.constants[id as usize].clone();
+                let a1: ValuePtr = self.pop();
+                let ret: ValuePtr = operator::apply_binary(self, op, a1, a2)?;
                 self.push(ret);
             },
 
@@ -520,7 +1165,15 @@ impl<R, W> VirtualMachine<R, W> where
                 self.push(ValuePtr::slice(arg1, arg2, arg3)?);
             }
 
-            Exit => return RuntimeExit.err(),
+            Exit => return RuntimeExit(0).err(),
+            ExitValue => return RuntimeExitValue(self.pop()).err(),
+            ExitWith => {
+                let code: i64 = self.pop().check_int()?.as_int();
+                return match i32::try_from(code) {
+                    Ok(code) => RuntimeExit(code).err(),
+                    Err(_) => ValueErrorInvalidExitCode(code).err(),
+                }
+            },
             Yield => {
                 // First, jump to the end of current code, so when we startup again, we are in the right location
                 self.ip = self.code.len();
@@ -593,6 +1246,105 @@ impl<R, W> VirtualMachine<R, W> where
         self.pop().ok()
     }
 
+    #[cold]
+    fn trace_call(&mut self, nargs: u32) {
+        let ip: usize = self.ip.saturating_sub(1);
+        let line: Option<usize> = self.lineno(ip);
+        let frames: usize = self.call_stack.len();
+        if let Some(tracer) = &mut self.tracer {
+            let f: &ValuePtr = &self.stack[self.stack.len() - 1 - nargs as usize];
+            tracer.call(ip, line, || f.to_repr_str(), nargs, frames);
+        }
+    }
+
+    #[cold]
+    fn trace_return(&mut self) {
+        let ip: usize = self.ip - 1;
+        let line: Option<usize> = self.lineno(ip);
+        let frames: usize = self.call_stack.len() - 1;
+        if let Some(tracer) = &mut self.tracer {
+            tracer.ret(ip, line, frames);
+        }
+    }
+
+    /// Returns the field `field_index` of `target`. If the field is a computed field (property), this invokes the getter.
+    fn get_field(&mut self, target: ValuePtr, field_index: u32) -> ValueResult {
+        match target.get_property(&self.fields, field_index) {
+            Some((getter, _)) => self.call(getter, [target]),
+            None => target.get_field(&self.fields, field_index),
+        }
+    }
+
+    /// Sets the field `field_index` of `target` to `value`, and returns `value`. If the field is a computed field (property), this invokes the setter.
+    fn set_field(&mut self, target: ValuePtr, field_index: u32, value: ValuePtr) -> ValueResult {
+        match target.get_property(&self.fields, field_index) {
+            Some((_, Some(setter))) => {
+                self.call(setter, [target, value.clone()])?;
+                value.ok()
+            },
+            Some((_, None)) => TypeErrorPropertyHasNoSetter(target.as_struct().borrow().type_impl.get().as_str(), self.fields.get_field_name(field_index)).err(),
+            None => {
+                let ret: ValuePtr = target.clone().set_field(&self.fields, field_index, value)?;
+                operator::update_hash(self, &target)?;
+                ret.ok()
+            },
+        }
+    }
+
+    /// Enters `context` at the beginning of a `with` statement, and returns the value bound by the statement.
+    /// This is the result of the `__enter__` member if the context is a struct which declares one, otherwise it is the context itself.
+    fn enter_context(&mut self, context: ValuePtr) -> ValueResult {
+        let enter: Option<ValuePtr> = match context.ty() {
+            Type::Struct => {
+                let it = context.as_struct().borrow();
+                let type_impl = it.type_impl.get();
+                if type_impl.get_member_by_name("__exit__").is_none() && type_impl.get_member_by_name("close").is_none() {
+                    return TypeErrorArgMustBeContext(context.clone()).err()
+                }
+                type_impl.get_member_by_name("__enter__")
+            },
+            Type::Socket | Type::Coprocess | Type::Database => None,
+            _ => return TypeErrorArgMustBeContext(context).err(),
+        };
+        let ret: ValuePtr = match enter {
+            Some(f) => self.call(f, [context.clone()])?,
+            None => context.clone(),
+        };
+        // Only once the context has been entered successfully, does it need to be exited
+        self.contexts.push(context);
+        ret.ok()
+    }
+
+    /// Exits `context`, by invoking the `__exit__` or `close` member of a struct, or `close()` for a native handle.
+    fn exit_context(&mut self, context: ValuePtr) -> AnyResult {
+        let exit: ValuePtr = match context.ty() {
+            Type::Struct => {
+                let it = context.as_struct().borrow();
+                let type_impl = it.type_impl.get();
+                type_impl.get_member_by_name("__exit__")
+                    .or_else(|| type_impl.get_member_by_name("close"))
+                    .unwrap()
+            },
+            _ => core::NativeFunction::Close.to_value(),
+        };
+        self.call(exit, [context])?;
+        Ok(())
+    }
+
+    /// Exits every context entered after the first `len`, in the reverse order they were entered. This is used when an error unwinds past a `with` statement.
+    /// If exiting a context raises an error, the remaining contexts are still exited, and the first error is returned.
+    fn unwind_contexts(&mut self, len: usize) -> AnyResult {
+        let mut result: AnyResult = Ok(());
+        while self.contexts.len() > len {
+            let context: ValuePtr = self.contexts.pop().unwrap();
+            let exit: AnyResult = self.exit_context(context);
+            if result.is_ok() {
+                result = exit;
+            }
+        }
+        result
+    }
+
     /// Invokes the action of an `OpFuncEval(nargs)` opcode.
     ///
     /// The stack must be setup as `[..., f, arg1, arg2, ... argN ]`, where `f` is the function to be invoked with arguments `arg1, arg2, ... argN`.
@@ -600,12 +1352,18 @@ impl<R, W> VirtualMachine<R, W> where
     ///
     /// Returns a `Result` which may contain an error which occurred during function evaluation.
     fn invoke(&mut self, nargs: u32) -> ErrorResult<FunctionType> {
+        if self.tracer.is_some() {
+            self.trace_call(nargs);
+        }
         let f: &ValuePtr = self.peek(nargs as usize);
-        trace::trace_interpreter!("vm::invoke func={:?}, nargs={}", f, nargs);
         match f.ty() {
             Type::Function | Type::Closure => {
                 let func = f.get_function();
-                if func.in_range(nargs) {
+                if func.in_range(nargs) && func.is_async() {
+                    // Async functions are not evaluated directly, but return a task which will evaluate them
+                    self.spawn_call(nargs);
+                    Ok(FunctionType::Native)
+                } else if func.in_range(nargs) {
                     // Evaluate directly
                     self.call_function(func.jump_offset(nargs), nargs, func.num_var_args(nargs));
                     Ok(FunctionType::User)
@@ -621,7 +1379,7 @@ impl<R, W> VirtualMachine<R, W> where
                     // Partial functions are already evaluated, so we return native, since we don't need to spin
                     Ok(FunctionType::Native)
                 } else {
-                    IncorrectArgumentsUserFunction(func.clone(), nargs).err()
+                    IncorrectArgumentsUserFunction(Box::new(func.clone()), nargs).err()
                 }
             },
             Type::PartialFunction => {
@@ -643,17 +1401,28 @@ impl<R, W> VirtualMachine<R, W> where
                     // Exactly enough arguments to invoke the function
                     // Before we call, we need to pop-push to reorder the arguments and setup partial arguments, so we have the correct calling convention
                     let head: usize = func.jump_offset(total_nargs);
-                    let num_var_args: Option<u32> = func.num_var_args(nargs);
+                    let num_var_args: Option<u32> = func.num_var_args(total_nargs);
+                    let is_async: bool = func.is_async();
                     self.stack[i] = partial.func.inner(); // Replace the `Nil` from earlier
                     insert(&mut self.stack, partial.args.into_iter(), nargs);
+                    if is_async {
+                        self.spawn_call(total_nargs);
+                        return Ok(FunctionType::Native)
+                    }
                     self.call_function(head, total_nargs, num_var_args);
                     Ok(FunctionType::User)
                 } else {
-                    IncorrectArgumentsUserFunction(func.clone(), total_nargs).err()
+                    IncorrectArgumentsUserFunction(Box::new(func.clone()), total_nargs).err()
                 }
             },
             Type::NativeFunction => {
-                let ret = core::invoke_stack(f.as_native(), nargs, self)?;
+                let native: core::NativeFunction = f.as_native();
+                let frames: usize = self.call_stack.len();
+                let ret: ValueResult = core::invoke_stack(native, nargs, self);
+                if ret.is_err() {
+                    self.add_cause(frames, native);
+                }
+                let ret = ret?;
 
                 self.pop();
                 self.push(ret);
@@ -666,7 +1435,13 @@ impl<R, W> VirtualMachine<R, W> where
                 let i: usize = self.stack.len() - 1 - nargs as usize;
                 let partial = std::mem::replace(&mut self.stack[i], ValuePtr::nil()).as_partial_native().value;
 
-                let ret = core::invoke_partial(partial.func, partial.partial, nargs, self)?;
+                let native: core::NativeFunction = partial.func;
+                let frames: usize = self.call_stack.len();
+                let ret: ValueResult = core::invoke_partial(native, partial.partial, nargs, self);
+                if ret.is_err() {
+                    self.add_cause(frames, native);
+                }
+                let ret = ret?;
 
                 self.pop();
                 self.push(ret);
@@ -701,6 +1476,9 @@ impl<R, W> VirtualMachine<R, W> where
             }
             Type::StructType => {
                 let type_impl = f.as_struct_type().borrow_const();
+                if type_impl.kind == StructKind::Enum {
+                    return TypeErrorCannotConstructEnum(type_impl.clone()).err()
+                }
                 let expected_args = type_impl.field_names.len() as u32;
                 if nargs != expected_args {
                     return IncorrectArgumentsStruct(type_impl.clone(), nargs).err()
@@ -710,6 +1488,7 @@ impl<R, W> VirtualMachine<R, W> where
                 let struct_type = self.pop();
                 let instance: ValuePtr = ValuePtr::instance(ValueStructType::new(struct_type), args);
 
+                operator::update_hash(self, &instance)?;
                 self.push(instance);
 
                 Ok(FunctionType::Native)
@@ -721,7 +1500,7 @@ impl<R, W> VirtualMachine<R, W> where
                 }
 
                 let arg: ValuePtr = self.pop();
-                let ret: ValuePtr = arg.get_field(&self.fields, field_index)?;
+                let ret: ValuePtr = self.get_field(arg, field_index)?;
 
                 self.pop(); // The get field
                 self.push(ret);
@@ -743,6 +1522,7 @@ impl<R, W> VirtualMachine<R, W> where
         let frame = CallFrame {
             return_ip: self.ip,
             frame_pointer: self.stack.len() - (nargs as usize),
+            cause: None,
         };
         self.ip = head;
         self.call_stack.push(frame);
@@ -763,39 +1543,70 @@ impl<R, W> VirtualMachine<R, W> where
     pub fn debug_call_stack(&self) -> String {
         format!(": [{}]", self.call_stack.iter().rev().map(|t| format!("{{fp: {}, ret: {}}}", t.frame_pointer, t.return_ip)).collect::<Vec<String>>().join(", "))
     }
+
+    /// Returns the name and current value of each upvalue in the environment of the closure `f`, in order, or `None` if `f` is not a closure.
+    ///
+    /// An upvalue which has not been closed yet refers to a local variable which is still on the stack, so it is read from there, and reflects any later assignments to that variable.
+    /// If the name of an upvalue is not known, which is the case for an assembled program, it is named by its index, i.e. `$0`.
+    pub fn debug_closure(&self, f: &ValuePtr) -> Option<Vec<(String, ValuePtr)>> {
+        if !f.is_closure() {
+            return None
+        }
+        let func: &FunctionImpl = f.get_function();
+        let upvalues = f.as_closure().borrow()
+            .upvalues()
+            .enumerate()
+            .map(|(index, upvalue)| {
+                let name: String = func.upvalue_name(index).cloned().unwrap_or_else(|| format!("${}", index));
+                let value: ValuePtr = match upvalue {
+                    UpValue::Open(local) => self.stack.get(local).cloned().unwrap_or_else(ValuePtr::nil),
+                    UpValue::Closed(value) => value,
+                };
+                (name, value)
+            })
+            .collect();
+        Some(upvalues)
+    }
 }
 
 
-impl <R, W> VirtualInterface for VirtualMachine<R, W> where
+impl <R, W, E> VirtualInterface for VirtualMachine<R, W, E> where
     R : BufRead,
-    W : Write
+    W : Write,
+    E : Write
 {
     // ===== Calling Functions External Interface ===== //
 
-    fn invoke_func0(&mut self, f: ValuePtr) -> ValueResult {
-        self.push(f);
-        self.invoke_and_spin(0)
-    }
-
-    fn invoke_func1(&mut self, f: ValuePtr, a1: ValuePtr) -> ValueResult {
-        self.push(f);
-        self.push(a1);
-        self.invoke_and_spin(1)
-    }
-
-    fn invoke_func2(&mut self, f: ValuePtr, a1: ValuePtr, a2: ValuePtr) -> ValueResult {
+    fn call(&mut self, f: ValuePtr, args: impl IntoIterator<Item=ValuePtr>) -> ValueResult {
         self.push(f);
-        self.push(a1);
-        self.push(a2);
-        self.invoke_and_spin(2)
+        let mut nargs: u32 = 0;
+        for arg in args {
+            self.push(arg);
+            nargs += 1;
+        }
+        self.invoke_and_spin(nargs)
     }
 
-    fn invoke_func(&mut self, f: ValuePtr, args: &[ValuePtr]) -> ValueResult {
-        self.push(f);
-        for arg in args {
-            self.push(arg.clone());
+    fn try_call(&mut self, f: ValuePtr, args: impl IntoIterator<Item=ValuePtr>) -> ValueResult {
+        let (ip, frames, stack, literals, unrolls, contexts) = (self.ip, self.call_stack.len(), self.stack.len(), self.literal_stack.len(), self.unroll_stack.len(), self.contexts.len());
+        let result: ValueResult = self.call(f, args);
+        if !result.is_ok() {
+            // Any `with` statements the error unwound past are exited, before the rest of the state is restored. Errors from exiting them are discarded, as the original error is returned.
+            let _ = self.unwind_contexts(contexts);
+
+            // Any upvalues still open above the restored stack are closed over their current value, as they would have been by the function returning
+            let open: Vec<usize> = self.open_upvalues.keys().copied().filter(|index| *index >= stack).collect();
+            for index in open {
+                let upvalue: Rc<Cell<UpValue>> = self.open_upvalues.remove(&index).unwrap();
+                upvalue.replace(UpValue::Closed(self.stack[index].clone()));
+            }
+            self.ip = ip;
+            self.call_stack.truncate(frames);
+            self.stack.truncate(stack);
+            self.literal_stack.truncate(literals);
+            self.unroll_stack.truncate(unrolls);
         }
-        self.invoke_and_spin(args.len() as u32)
+        result
     }
 
     fn invoke_eval(&mut self, text: &String) -> ValueResult {
@@ -823,6 +1634,12 @@ impl <R, W> VirtualInterface for VirtualMachine<R, W> where
     fn println0(&mut self) { writeln!(&mut self.write).unwrap(); }
     fn println(&mut self, str: String) { writeln!(&mut self.write, "{}", str).unwrap(); }
     fn print(&mut self, str: String) { write!(&mut self.write, "{}", str).unwrap(); }
+    fn flush(&mut self) { self.write.flush().unwrap(); }
+    fn write_bytes(&mut self, bytes: &[u8]) { self.write.write_all(bytes).unwrap(); }
+
+    fn eprintln0(&mut self) { writeln!(&mut self.error).unwrap(); }
+    fn eprintln(&mut self, str: String) { writeln!(&mut self.error, "{}", str).unwrap(); }
+    fn eprint(&mut self, str: String) { write!(&mut self.error, "{}", str).unwrap(); }
 
     fn read_line(&mut self) -> String {
         let mut buf = String::new();
@@ -849,37 +1666,111 @@ impl <R, W> VirtualInterface for VirtualMachine<R, W> where
         self.args.clone()
     }
 
+    fn at_exit(&mut self, f: ValuePtr) {
+        self.exit_handlers.push(f);
+    }
+
+    fn terminal(&mut self) -> Option<&mut dyn Terminal> {
+        match &mut self.terminal {
+            Some(terminal) => Some(terminal.as_mut()),
+            None => None,
+        }
+    }
+
+    fn log(&mut self, level: LogLevel, message: String, fields: Vec<(String, String)>) {
+        if level < self.log_level {
+            return
+        }
+        let (source, lineno) = match self.locations.get(self.ip.wrapping_sub(1)) {
+            Some(loc) => (self.view.name_of(*loc).clone(), self.view.lineno(*loc).map(|u| u + 1)),
+            None => (String::new(), None), // Not called from any instruction, i.e. invoked directly by the host
+        };
+        let record: LogRecord = LogRecord { level, message, fields, timestamp: SystemTime::now(), source, lineno };
+        match &mut self.log_sink {
+            Some(sink) => sink.log(record),
+            None => writeln!(&mut self.error, "{}", record.format()).unwrap(),
+        }
+    }
+
+    fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+
+    fn set_log_level(&mut self, level: LogLevel) {
+        self.log_level = level;
+    }
+
+    fn entropy(&mut self) -> Option<&mut dyn Entropy> {
+        match &mut self.entropy {
+            Some(entropy) => Some(entropy.as_mut()),
+            None => None,
+        }
+    }
+
+    fn is_allowed(&self, capability: Capability) -> bool {
+        !self.denied.contains(&capability)
+    }
+
+    fn register_handle(&mut self, value: ValuePtr) -> ValuePtr {
+        self.handles.register(value)
+    }
+
+    fn add_finalizer(&mut self, value: &ValuePtr, f: ValuePtr) {
+        weak::add_finalizer(value, f, &self.finalizers)
+    }
+
+    fn on_signal(&mut self, signal: Signal, f: Option<ValuePtr>) {
+        let registered: bool = self.signal_handlers.iter().any(|(s, _)| *s == signal);
+        self.signal_handlers.retain(|(s, _)| *s != signal);
+        if let Some(f) = f {
+            self.signal_handlers.push((signal, f));
+            if !registered {
+                if let Some(hook) = &mut self.signal_hook {
+                    hook(signal);
+                }
+            }
+        }
+    }
+
+    fn struct_type(&self, name: &str, field_names: &[String]) -> Option<ValuePtr> {
+        self.constants.iter()
+            .rev()
+            .find(|c| c.is_struct_type() && {
+                let it = c.as_struct_type().borrow_const();
+                it.name == name && it.field_names == field_names
+            })
+            .cloned()
+    }
+
+    fn spawn(&mut self, task: ValuePtr) {
+        self.tasks.spawn(task);
+    }
+
+    fn stack_depth(&self) -> (usize, usize) {
+        (self.stack.len(), self.call_stack.len())
+    }
+
 
     // ===== Stack Manipulations ===== //
 
     /// Peeks at the top element of the stack, or an element `offset` down from the top
     fn peek(&self, offset: usize) -> &ValuePtr {
-        trace::trace_interpreter_stack!("peek({}) -> {}", offset, self.stack[self.stack.len() - 1 - offset].as_debug_str());
-        let ret = self.stack.get(self.stack.len() - 1 - offset).unwrap();
-        trace::trace_interpreter_stack!("{}", self.debug_stack());
-        ret
+        self.stack.get(self.stack.len() - 1 - offset).unwrap()
     }
 
     /// Pops the top of the stack
     fn pop(&mut self) -> ValuePtr {
-        trace::trace_interpreter_stack!("pop() -> {}", self.stack.last().unwrap().as_debug_str());
-        let ret = self.stack.pop().unwrap();
-        trace::trace_interpreter_stack!("{}", self.debug_stack());
-        ret
+        self.stack.pop().unwrap()
     }
 
     /// Pops the top N values off the stack, in order
     fn popn(&mut self, n: u32) -> Vec<ValuePtr> {
-        let ret = splice(&mut self.stack, n).collect();
-        trace::trace_interpreter_stack!("{}", self.debug_stack());
-        ret
+        splice(&mut self.stack, n).collect()
     }
 
     /// Push a value onto the stack
     fn push(&mut self, value: ValuePtr) {
-        trace::trace_interpreter_stack!("push({})", value.as_debug_str());
         self.stack.push(value);
-        trace::trace_interpreter_stack!("{}", self.debug_stack());
     }
 }
 
@@ -910,9 +1801,16 @@ fn insert<I : Iterator<Item=ValuePtr>>(stack: &mut Vec<ValuePtr>, args: I, n: u3
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use std::time::{Duration, UNIX_EPOCH};
+
     use crate::{compiler, test_util};
-    use crate::reporting::SourceView;
-    use crate::vm::{ExitType, VirtualMachine};
+    use crate::compiler::CompileFlags;
+    use crate::core::NativeFunction;
+    use crate::reporting::{AsError, SourceView};
+    use crate::vm::{Capability, Entropy, ExitType, IntoValue, LogLevel, LogRecord, LogSink, Opcode, Signal, Snapshot, Step, Terminal, TraceEvents, TraceOptions, Tracer, ValuePtr, VirtualInterface, VirtualMachine};
 
     #[test] fn test_empty() { run_str("", ""); }
     #[test] fn test_compose_1() { run_str("print . print", "print\n"); }
@@ -932,6 +1830,14 @@ mod tests {
     #[test] fn test_if_short_circuiting_3() { run_str("if true and (print('yes') or true) { print('also yes') }", "yes\nalso yes\n"); }
     #[test] fn test_if_short_circuiting_4() { run_str("if false or print('yes') { print('no') }", "yes\n"); }
     #[test] fn test_if_short_circuiting_5() { run_str("if true or print('no') { print('yes') }", "yes\n"); }
+    #[test] fn test_keyword_not() { run_str("print(not true, not false, not 0, not 3, not [], not 'a', not nil)", "false true true false true false true\n"); }
+    #[test] fn test_keyword_not_not() { run_str("print(not not 5)", "true\n"); }
+    #[test] fn test_keyword_not_lower_than_comparison() { run_str("print(not 1 == 2)", "true\n"); }
+    #[test] fn test_keyword_not_higher_than_and() { run_str("print(not false and 0)", "0\n"); }
+    #[test] fn test_keyword_not_higher_than_or() { run_str("print(not true or 'yes')", "yes\n"); }
+    #[test] fn test_keyword_not_short_circuiting() { run_str("if not true and print('no') { print('no') } else { print('yes') }", "yes\n"); }
+    #[test] fn test_keyword_not_with_not_in() { run_str("print(not 1 not in [1])", "true\n"); }
+    #[test] fn test_keyword_not_in_lambda() { run_str("[1, 2, 3, 4] . filter(fn(x) -> not x % 2) . print", "[2, 4]\n"); }
     #[test] fn test_if_then_else_1() { run_str("(if true then 'hello' else 'goodbye') . print", "hello\n"); }
     #[test] fn test_if_then_else_2() { run_str("(if false then 'hello' else 'goodbye') . print", "goodbye\n"); }
     #[test] fn test_if_then_else_3() { run_str("(if [] then 'hello' else 'goodbye') . print", "goodbye\n"); }
@@ -978,6 +1884,104 @@ mod tests {
     #[test] fn test_struct_more_partial_get_field() { run_str("struct Foo(foo) ; let x = Foo('hello') ; print([x, Foo('')] . filter(->foo) . len)", "1\n"); }
     #[test] fn test_struct_recursive_repr() { run_str("struct S(x) ; let x = S(nil) ; x->x = x ; x.print", "S(x=S(...))\n"); }
     #[test] fn test_struct_operator_is() { run_str("struct A() ; struct B() let a = A(), b = B() ; [a is A, A is function, a is B, A is A, a is function] . print", "[true, true, false, false, false]\n"); }
+    #[test] fn test_struct_inherit_fields() { run_str("struct A(a, b) ; struct B(c) : A ; B(1, 2, 3) . print", "B(a=1, b=2, c=3)\n"); }
+    #[test] fn test_struct_inherit_get_fields() { run_str("struct A(a, b) ; struct B(c) : A ; let x = B(1, 2, 3) ; [x->a, x->b, x->c] . print", "[1, 2, 3]\n"); }
+    #[test] fn test_struct_inherit_set_field() { run_str("struct A(a) ; struct B(b) : A ; let x = B(1, 2) ; x->a += 10 ; x . print", "B(a=11, b=2)\n"); }
+    #[test] fn test_struct_inherit_repr() { run_str("struct A(a) ; struct B(b) : A ; B . repr . print", "struct B(a, b)\n"); }
+    #[test] fn test_struct_inherit_no_own_fields() { run_str("struct A(a) ; struct B() : A ; B(1) . print", "B(a=1)\n"); }
+    #[test] fn test_struct_inherit_operator_is() { run_str("struct A(a) ; struct B(b) : A ; let a = A(1), b = B(1, 2) ; [a is A, a is B, b is A, b is B] . print", "[true, false, true, true]\n"); }
+    #[test] fn test_struct_inherit_operator_is_transitive() { run_str("struct A() ; struct B() : A ; struct C() : B ; let c = C() ; [c is A, c is B, c is C, B() is C] . print", "[true, true, true, false]\n"); }
+    #[test] fn test_struct_inherit_operator_is_siblings() { run_str("struct A() ; struct B() : A ; struct C() : A ; [B() is C, C() is B, B() is A, C() is A] . print", "[false, false, true, true]\n"); }
+    #[test] fn test_struct_inherit_function_on_parent() { run_str("struct A(a) ; struct B(b) : A ; fn get_a(x) -> if x is A then x->a else nil ; [A(1), B(2, 3), 4] . map(get_a) . print", "[1, 2, nil]\n"); }
+    #[test] fn test_struct_inherit_field_not_on_parent() { run_str("struct A(a) ; struct B(b) : A ; A(1)->b . print", "TypeError: Cannot get field 'b' on struct A(a)\n  at: line 1 (<test>)\n\n1 | struct A(a) ; struct B(b) : A ; A(1)->b . print\n2 |                                     ^^^\n"); }
+    #[test] fn test_struct_inherit_duplicate_field() { run_str("struct A(a) ; struct B(a) : A", "Compile Error:\n\nDuplicate field name: 'a'\n  at: line 1 (<test>)\n\n1 | struct A(a) ; struct B(a) : A\n2 |                             ^\n"); }
+    #[test] fn test_struct_inherit_unknown_parent() { run_str("struct B(b) : A", "Compile Error:\n\nInvalid or unknown parent struct: 'A'\n  at: line 1 (<test>)\n\n1 | struct B(b) : A\n2 |               ^\n"); }
+    #[test] fn test_struct_inherit_not_a_struct() { run_str("let A = 1 ; struct B(b) : A", "Compile Error:\n\nInvalid or unknown parent struct: 'A'\n  at: line 1 (<test>)\n\n1 | let A = 1 ; struct B(b) : A\n2 |                           ^\n"); }
+    #[test] fn test_struct_inherit_expected_parent_name() { run_str("struct B(b) : 1", "Compile Error:\n\nExpected a parent struct name after ':' in struct declaration, got integer '1' instead\n  at: line 1 (<test>)\n\n1 | struct B(b) : 1\n2 |               ^\n"); }
+    #[test] fn test_struct_inherit_members() { run_str("struct A(a) { fn __enter__(self) { print('enter', self->a) ; self } ; fn __exit__(self) -> print('exit', self->a) } ; struct B(b) : A ; with B(1, 2) { print('body') }", "enter 1\nbody\nexit 1\n"); }
+    #[test] fn test_struct_inherit_members_replaced_by_child() { run_str("struct A(a) { fn __enter__(self) { print('enter A') ; self } ; fn __exit__(self) -> print('exit A') } ; struct B(b) : A { fn __exit__(self) -> print('exit B') } ; with B(1, 2) {} ; with A(1) {}", "enter A\nexit B\nenter A\nexit A\n"); }
+    #[test] fn test_struct_inherit_members_resolution_order() { run_str("struct A() { fn __enter__(self) { print('enter A') ; self } ; fn __exit__(self) -> print('exit A') } ; struct B() : A { fn __exit__(self) -> print('exit B') } ; struct C() : B { fn __enter__(self) { print('enter C') ; self } } ; with C() {}", "enter C\nexit B\n"); }
+    #[test] fn test_struct_inherit_members_duplicate_in_child() { run_str("struct A() { const X = 1 } ; struct B() : A { const X = 2 ; const X = 3 }", "Compile Error:\n\nDuplicate field name: 'X'\n  at: line 1 (<test>)\n\n1 | struct A() { const X = 1 } ; struct B() : A { const X = 2 ; const X = 3 }\n2 |                                                                       ^\n"); }
+    #[test] fn test_enum_construct_variants() { run_str("enum Color { Red, Green, Rgb(r, g, b) } ; [Red(), Green(), Rgb(1, 2, 3)] . print", "[Red(), Green(), Rgb(r=1, g=2, b=3)]\n"); }
+    #[test] fn test_enum_repr() { run_str("enum Color { Red, Rgb(r, g, b) } ; [Color, Red, Rgb] . print", "[enum Color, struct Red(), struct Rgb(r, g, b)]\n"); }
+    #[test] fn test_enum_get_field() { run_str("enum Color { Red, Rgb(r, g, b) } ; Rgb(1, 2, 3)->g . print", "2\n"); }
+    #[test] fn test_enum_operator_is() { run_str("enum Color { Red, Green } ; let r = Red() ; [r is Color, r is Red, r is Green, Red is Color] . print", "[true, true, false, false]\n"); }
+    #[test] fn test_enum_operator_is_other_enum() { run_str("enum A { X } ; enum B { Y } ; [X() is A, X() is B, Y() is A, Y() is B] . print", "[true, false, false, true]\n"); }
+    #[test] fn test_enum_equality() { run_str("enum Color { Red, Green, Rgb(r, g, b) } ; [Red() == Red(), Red() == Green(), Rgb(1, 2, 3) == Rgb(1, 2, 3), Rgb(1, 2, 3) == Rgb(3, 2, 1)] . print", "[true, false, true, false]\n"); }
+    #[test] fn test_enum_discriminant() { run_str("enum Color { Red, Green, Rgb(r, g, b) } ; [Red(), Green(), Rgb(0, 0, 0)] . map(int) . print", "[0, 1, 2]\n"); }
+    #[test] fn test_enum_multiline_trailing_comma() { run_str("enum Shape {\n    Circle(radius),\n    Rect(w, h),\n}\n[Circle(1), Rect(2, 3)] . print", "[Circle(radius=1), Rect(w=2, h=3)]\n"); }
+    #[test] fn test_enum_empty() { run_str("enum Never {} ; Never . print", "enum Never\n"); }
+    #[test] fn test_enum_construct_enum() { run_str("enum Color { Red } ; Color()", "TypeError: Cannot construct enum Color, construct one of its variants instead\n  at: line 1 (<test>)\n\n1 | enum Color { Red } ; Color()\n2 |                           ^^\n"); }
+    #[test] fn test_enum_variant_wrong_arguments() { run_str("enum Color { Rgb(r, g, b) } ; Rgb(1)", "Incorrect number of arguments for struct Rgb(r, g, b), got 1\n  at: line 1 (<test>)\n\n1 | enum Color { Rgb(r, g, b) } ; Rgb(1)\n2 |                                  ^^^\n"); }
+    #[test] fn test_enum_not_in_global_scope() { run_str("fn f() { enum }", "Compile Error:\n\n'enum' statements can only be present in global scope.\n  at: line 1 (<test>)\n\n1 | fn f() { enum }\n2 |          ^^^^\n"); }
+    #[test] fn test_enum_duplicate_variant() { run_str("enum Color { Red, Red }", "Compile Error:\n\nMultiple declarations for 'let Red' in the same scope\n  at: line 1 (<test>)\n\n1 | enum Color { Red, Red }\n2 |                   ^^^\n"); }
+    #[test] fn test_enum_expected_name() { run_str("enum { Red }", "Compile Error:\n\nExpected an enum name after 'enum' keyword, got '{' token instead\n  at: line 1 (<test>)\n\n1 | enum { Red }\n2 |      ^\n"); }
+    #[test] fn test_enum_cannot_be_parent() { run_str("enum Color { Red } ; struct Foo() : Color", "Compile Error:\n\nInvalid or unknown parent struct: 'Color'\n  at: line 1 (<test>)\n\n1 | enum Color { Red } ; struct Foo() : Color\n2 |                                     ^^^^^\n"); }
+    #[test] fn test_struct_static_const() { run_str("struct P(x, y) { const ORIGIN = P(0, 0) } ; P->ORIGIN . print", "P(x=0, y=0)\n"); }
+    #[test] fn test_struct_static_fn() { run_str("struct P(x, y) { fn from_str(s) -> P(...s . split(',') . map(int)) } ; P->from_str('1,2') . print", "P(x=1, y=2)\n"); }
+    #[test] fn test_struct_static_multiple_members() { run_str("struct P(x, y) {\n    const ORIGIN = P(0, 0)\n    const UNIT = P(1, 1)\n    fn add(a, b) -> P(a->x + b->x, a->y + b->y)\n}\nP->add(P->UNIT, P->UNIT) . print", "P(x=2, y=2)\n"); }
+    #[test] fn test_struct_static_members_in_order() { run_str("struct A() { const X = 1 ; const Y = A->X + 1 } ; [A->X, A->Y] . print", "[1, 2]\n"); }
+    #[test] fn test_struct_static_fn_references_const() { run_str("struct A() { const X = 3 ; fn f(y) -> A->X * y } ; A->f(5) . print", "15\n"); }
+    #[test] fn test_struct_static_partial_get_field() { run_str("struct A() { const X = 3 } ; struct B() { const X = 4 } ; [A, B] . map(->X) . print", "[3, 4]\n"); }
+    #[test] fn test_struct_static_through_variable() { run_str("struct A() { const X = 'hello' } ; let t = A ; t->X . print", "hello\n"); }
+    #[test] fn test_struct_static_same_name_as_field() { run_str("struct A(x) { const x = 'static' } ; [A->x, A('instance')->x] . print", "['static', 'instance']\n"); }
+    #[test] fn test_struct_static_not_on_instance() { run_str("struct A() { const X = 1 } ; A()->X", "TypeError: Cannot get field 'X' on struct A()\n  at: line 1 (<test>)\n\n1 | struct A() { const X = 1 } ; A()->X\n2 |                                 ^^^\n"); }
+    #[test] fn test_struct_static_not_present() { run_str("struct A() { const X = 1 } ; struct B(Y) ; A->Y", "TypeError: Cannot get field 'Y' on 'struct A()' of type 'struct type'\n  at: line 1 (<test>)\n\n1 | struct A() { const X = 1 } ; struct B(Y) ; A->Y\n2 |                                             ^^^\n"); }
+    #[test] fn test_struct_static_cannot_set() { run_str("struct A() { const X = 1 } ; A->X = 2", "TypeError: Cannot get field 'X' on 'struct A()' of type 'struct type'\n  at: line 1 (<test>)\n\n1 | struct A() { const X = 1 } ; A->X = 2\n2 |                                   ^\n"); }
+    #[test] fn test_struct_static_duplicate_member() { run_str("struct A() { const X = 1 ; fn X() -> 2 }", "Compile Error:\n\nDuplicate field name: 'X'\n  at: line 1 (<test>)\n\n1 | struct A() { const X = 1 ; fn X() -> 2 }\n2 |                                      ^\n"); }
+    #[test] fn test_struct_static_expected_member_name() { run_str("struct A() { const = 1 }", "Compile Error:\n\nExpected a member name after 'const' or 'fn' in struct body, got '=' token instead\n  at: line 1 (<test>)\n\n1 | struct A() { const = 1 }\n2 |                    ^\n"); }
+    #[test] fn test_struct_static_with_parent() { run_str("struct A(a) ; struct B(b) : A { const X = B(1, 2) } ; B->X . print", "B(a=1, b=2)\n"); }
+    #[test] fn test_struct_static_inherited() { run_str("struct A(x) { const X = 'const' ; fn make() -> 1 } ; struct B(y) : A ; [B->X, B->make()] . print", "['const', 1]\n"); }
+    #[test] fn test_struct_static_inherited_from_grandparent() { run_str("struct A() { const X = 1 } ; struct B() : A ; struct C() : B ; C->X . print", "1\n"); }
+    #[test] fn test_struct_static_inherited_replaced_by_child() { run_str("struct A() { const X = 'A' ; const Y = 'A' } ; struct B() : A { const X = 'B' } ; struct C() : B ; [A->X, A->Y, B->X, B->Y, C->X, C->Y] . print", "['A', 'A', 'B', 'A', 'B', 'A']\n"); }
+    #[test] fn test_struct_static_inherited_partial_get_field() { run_str("struct A() { const X = 3 } ; struct B() : A ; struct C() : A { const X = 4 } ; [A, B, C] . map(->X) . print", "[3, 3, 4]\n"); }
+    #[test] fn test_struct_property_get() { run_str("struct Rect(w, h) { get area(self) -> self->w * self->h } ; Rect(2, 3)->area . print", "6\n"); }
+    #[test] fn test_struct_property_get_is_computed() { run_str("struct Rect(w, h) { get area(self) -> self->w * self->h } ; let r = Rect(2, 3) ; r->w = 5 ; r->area . print", "15\n"); }
+    #[test] fn test_struct_property_partial_get_field() { run_str("struct Rect(w, h) { get area(self) -> self->w * self->h } ; [Rect(1, 2), Rect(3, 4)] . map(->area) . print", "[2, 12]\n"); }
+    #[test] fn test_struct_property_set() { run_str("struct Square(side) { get area(self) -> self->side ** 2 ; set area(self, value) -> self->side = sqrt(value) } ; let s = Square(2) ; s->area = 25 ; [s, s->area] . print", "[Square(side=5), 25]\n"); }
+    #[test] fn test_struct_property_set_returns_value() { run_str("struct A(x) { get y(self) -> self->x ; set y(self, value) -> self->x = value + 1 } ; let a = A(1) ; (a->y = 5) . print ; a . print", "5\nA(x=6)\n"); }
+    #[test] fn test_struct_property_set_before_get() { run_str("struct A(x) { set y(self, value) -> self->x = value ; get y(self) -> self->x } ; let a = A(1) ; a->y = 3 ; a->y . print", "3\n"); }
+    #[test] fn test_struct_property_op_set() { run_str("struct A(x) { get y(self) -> self->x * 10 ; set y(self, value) -> self->x = value / 10 } ; let a = A(1) ; a->y += 20 ; a . print", "A(x=3)\n"); }
+    #[test] fn test_struct_property_references_earlier_property() { run_str("struct A(x) { get y(self) -> self->x + 1 ; get z(self) -> self->y + 1 } ; A(1)->z . print", "3\n"); }
+    #[test] fn test_struct_property_not_on_struct_type() { run_str("struct A(x) { get y(self) -> 1 } ; A->y", "TypeError: Cannot get field 'y' on 'struct A(x)' of type 'struct type'\n  at: line 1 (<test>)\n\n1 | struct A(x) { get y(self) -> 1 } ; A->y\n2 |                                     ^^^\n"); }
+    #[test] fn test_struct_property_set_without_setter() { run_str("struct A(x) { get y(self) -> 1 } ; A(1)->y = 2", "TypeError: Cannot set field 'y' on struct A(x), as it is a property with no 'set' accessor\n  at: line 1 (<test>)\n\n1 | struct A(x) { get y(self) -> 1 } ; A(1)->y = 2\n2 |                                            ^\n"); }
+    #[test] fn test_struct_property_setter_without_getter() { run_str("struct A(x) { set y(self, value) -> nil }", "Compile Error:\n\nProperty 'y' has a 'set' accessor, but no 'get' accessor\n  at: line 1 (<test>)\n\n1 | struct A(x) { set y(self, value) -> nil }\n2 |                                         ^\n"); }
+    #[test] fn test_struct_property_same_name_as_field() { run_str("struct A(x) { get x(self) -> 1 }", "Compile Error:\n\nDuplicate field name: 'x'\n  at: line 1 (<test>)\n\n1 | struct A(x) { get x(self) -> 1 }\n2 |                              ^\n"); }
+    #[test] fn test_struct_property_duplicate_getter() { run_str("struct A(x) { get y(self) -> 1 ; get y(self) -> 2 }", "Compile Error:\n\nDuplicate field name: 'y'\n  at: line 1 (<test>)\n\n1 | struct A(x) { get y(self) -> 1 ; get y(self) -> 2 }\n2 |                                                 ^\n"); }
+    #[test] fn test_struct_property_get_and_set_as_names() { run_str("struct A(x) { const get = 1 ; fn set(x) -> x } ; [A->get, A->set(2), set([3])] . print", "[1, 2, {3}]\n"); }
+    #[test] fn test_struct_property_inherited() { run_str("struct A(x) { get dbl(self) -> self->x * 2 } ; struct B(y) : A ; let b = B(1, 2) ; [b->dbl, [b, A(3)] . map(->dbl)] . print", "[2, [2, 6]]\n"); }
+    #[test] fn test_struct_property_inherited_set() { run_str("struct A(x) { get y(self) -> self->x ; set y(self, value) -> self->x = value } ; struct B(z) : A ; struct C() : B ; let c = C(1, 2) ; c->y = 5 ; [c, c->y] . print", "[C(x=5, z=2), 5]\n"); }
+    #[test] fn test_struct_property_inherited_getter_replaced_by_child() { run_str("struct A(x) { get y(self) -> 'A' ; set y(self, value) -> self->x = value } ; struct B() : A { get y(self) -> 'B' } ; let b = B(1) ; b->y = 3 ; [A(1)->y, b->y, b] . print", "['A', 'B', B(x=3)]\n"); }
+    #[test] fn test_struct_property_inherited_setter_added_by_child() { run_str("struct A(x) { get y(self) -> self->x } ; struct B() : A { set y(self, value) -> self->x = value * 10 } ; let b = B(1) ; b->y = 3 ; b->y . print ; A(1)->y = 3", "30\nTypeError: Cannot set field 'y' on struct A(x), as it is a property with no 'set' accessor\n  at: line 1 (<test>)\n\n1 | struct A(x) { get y(self) -> self->x } ; struct B() : A { set y(self, value) -> self->x = value * 10 } ; let b = B(1) ; b->y = 3 ; b->y . print ; A(1)->y = 3\n2 |                                                                                                                                                           ^\n"); }
+    #[test] fn test_struct_cmp_operators() { run_str("struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; let a = V(1), b = V(2) ; [a < b, a <= b, a > b, a >= b, a < a, a <= a] . print", "[false, false, true, true, false, true]\n"); }
+    #[test] fn test_struct_lt_operators() { run_str("struct V(x) { fn __lt__(a, b) -> a->x > b->x } ; let a = V(1), b = V(2) ; [a < b, a <= b, a > b, a >= b, a < a, a <= a] . print", "[false, false, true, true, false, true]\n"); }
+    #[test] fn test_struct_cmp_sort() { run_str("struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; [V(2), V(3), V(1)] . sort . print", "[V(x=3), V(x=2), V(x=1)]\n"); }
+    #[test] fn test_struct_lt_sort() { run_str("struct V(x) { fn __lt__(a, b) -> a->x > b->x } ; [V(2), V(3), V(1)] . sort . print", "[V(x=3), V(x=2), V(x=1)]\n"); }
+    #[test] fn test_struct_cmp_min_max() { run_str("struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; let vs = [V(2), V(3), V(1)] ; [min(vs), max(vs), min(V(1), V(2)), max(V(1), V(2))] . print", "[V(x=3), V(x=1), V(x=2), V(x=1)]\n"); }
+    #[test] fn test_struct_cmp_minmax() { run_str("struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; [V(2), V(3), V(1)] . minmax . print", "(V(x=3), V(x=1))\n"); }
+    #[test] fn test_struct_cmp_operator_functions() { run_str("struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; let a = V(1), b = V(2) ; [(<)(a, b), (>)(a, b), (<b)(a), (a<)(b)] . print", "[false, true, false, false]\n"); }
+    #[test] fn test_struct_cmp_max_equals() { run_str("struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; let a = V(1) ; a max= V(5) ; a . print", "V(x=1)\n"); }
+    #[test] fn test_struct_cmp_with_int() { run_str("struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; V(1) < 2", "TypeError: Cannot compare 'V(x=1)' of type 'struct' and '2' of type 'int'\n  at: line 1 (<test>)\n\n1 | struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; V(1) < 2\n2 |                                                        ^\n"); }
+    #[test] fn test_struct_cmp_with_other_struct() { run_str("struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; struct W(x) ; [V(1), W(2)] . sort", "TypeError: Cannot compare 'W(x=2)' of type 'struct' and 'V(x=1)' of type 'struct'\n  at: line 1 (<test>)\n\n1 | struct V(x) { fn __cmp__(a, b) -> b->x - a->x } ; struct W(x) ; [V(1), W(2)] . sort\n2 |                                                                              ^^^^^^\n"); }
+    #[test] fn test_struct_cmp_error_in_comparator() { run_str("struct V(x) { fn __cmp__(a, b) -> 'nope' } ; V(1) < V(2)", "TypeError: Expected 'nope' of type 'str' to be a int\n  at: line 1 (<test>)\n\n1 | struct V(x) { fn __cmp__(a, b) -> 'nope' } ; V(1) < V(2)\n2 |                                                   ^\n"); }
+    #[test] fn test_struct_lt_inherited_sort() { run_str("struct A(x) { fn __lt__(a, b) -> a->x > b->x } ; struct B(y) : A ; [B(2, 0), B(3, 0), B(1, 0)] . sort . print", "[B(x=3, y=0), B(x=2, y=0), B(x=1, y=0)]\n"); }
+    #[test] fn test_struct_cmp_inherited_operators() { run_str("struct A(x) { fn __cmp__(a, b) -> b->x - a->x } ; struct B() : A ; struct C() : B ; let a = C(1), b = C(2) ; [a < b, a > b, min(a, b)] . print", "[false, true, C(x=2)]\n"); }
+    #[test] fn test_struct_cmp_inherited_replaced_by_child() { run_str("struct A(x) { fn __cmp__(a, b) -> b->x - a->x } ; struct B() : A { fn __cmp__(a, b) -> a->x - b->x } ; [[A(1), A(2)] . sort, [B(1), B(2)] . sort] . print", "[[A(x=2), A(x=1)], [B(x=1), B(x=2)]]\n"); }
+    #[test] fn test_struct_lt_replaces_inherited_cmp() { run_str("struct A(x) { fn __cmp__(a, b) -> b->x - a->x } ; struct B() : A { fn __lt__(a, b) -> a->x < b->x } ; [[A(1), A(2)] . sort, [B(1), B(2)] . sort] . print", "[[A(x=2), A(x=1)], [B(x=1), B(x=2)]]\n"); }
+    #[test] fn test_struct_cmp_inherited_with_parent_instance() { run_str("struct A(x) { fn __cmp__(a, b) -> a->x - b->x } ; struct B() : A ; A(1) < B(2)", "TypeError: Cannot compare 'A(x=1)' of type 'struct' and 'B(x=2)' of type 'struct'\n  at: line 1 (<test>)\n\n1 | struct A(x) { fn __cmp__(a, b) -> a->x - b->x } ; struct B() : A ; A(1) < B(2)\n2 |                                                                         ^\n"); }
+    #[test] fn test_struct_no_cmp_uses_fields() { run_str("struct V(x) ; [V(2), V(3), V(1)] . sort . print", "[V(x=1), V(x=2), V(x=3)]\n"); }
+    #[test] fn test_struct_hash_field_wise() { run_str("struct A(x, y) ; {A(1, 2), A(1, 2), A(2, 1)} . print", "{A(x=1, y=2), A(x=2, y=1)}\n"); }
+    #[test] fn test_struct_hash_dict_key() { run_str("struct A(x, y) ; let d = {A(1, 2): 'a'} ; d[A(1, 2)] . print", "a\n"); }
+    #[test] fn test_struct_hash_different_types() { run_str("struct A(x) ; struct B(x) ; {A(1), B(1), A(1)} . print", "{A(x=1), B(x=1)}\n"); }
+    #[test] fn test_struct_hash_enum_variants() { run_str("enum E { X(a), Y(a) } ; {X(1), Y(1), X(1)} . print", "{X(a=1), Y(a=1)}\n"); }
+    #[test] fn test_struct_hash_override() { run_str("struct P(x, y) { fn __hash__(self) -> self->x } ; {P(1, 2), P(1, 3), P(1, 2)} . print", "{P(x=1, y=2), P(x=1, y=3)}\n"); }
+    #[test] fn test_struct_hash_override_is_called() { run_str("struct P(x) { fn __hash__(self) { print('hash', self->x) ; self->x } } ; let p = P(1) ; p->x = 2", "hash 1\nhash 2\n"); }
+    #[test] fn test_struct_hash_override_after_modify() { run_str("struct P(x, y) { fn __hash__(self) -> self->x * 31 + self->y } ; let p = P(1, 2) ; p->y = 3 ; [P(1, 3) in {p}, P(1, 2) in {p}] . print", "[true, false]\n"); }
+    #[test] fn test_struct_hash_override_unmarshal() { run_str("struct P(x, y) { fn __hash__(self) -> self->x * 31 + self->y } ; let p = unmarshal(marshal(P(1, 2))) ; P(1, 2) in {p} . print", "true\n"); }
+    #[test] fn test_struct_hash_override_not_int() { run_str("struct P(x) { fn __hash__(self) -> 'no' } ; P(1)", "TypeError: Expected 'no' of type 'str' to be a int\n  at: line 1 (<test>)\n\n1 | struct P(x) { fn __hash__(self) -> 'no' } ; P(1)\n2 |                                              ^^^\n"); }
+    #[test] fn test_struct_hash_override_inherited() { run_str("struct P(x, y) { fn __hash__(self) { print('hash P', self->x) ; self->x } } ; struct Q(z) : P ; let q = Q(1, 2, 3) ; q->y = 5 ; (Q(1, 5, 3) in {q}) . print", "hash P 1\nhash P 1\nhash P 1\ntrue\n"); }
+    #[test] fn test_struct_hash_override_inherited_replaced_by_child() { run_str("struct P(x) { fn __hash__(self) { print('hash P') ; self->x } } ; struct Q() : P ; struct R() : Q { fn __hash__(self) { print('hash R') ; 0 } } ; P(1) ; Q(1) ; R(1)", "hash P\nhash P\nhash R\n"); }
+    #[test] fn test_struct_hash_override_inherited_unmarshal() { run_str("struct P(x, y) { fn __hash__(self) -> self->x * 31 + self->y } ; struct Q() : P ; let q = unmarshal(marshal(Q(1, 2))) ; Q(1, 2) in {q} . print", "true\n"); }
     #[test] fn test_struct_construct_not_enough_arguments() { run_str("struct Foo(a, b, c) ; Foo(1)(2) . print ; ", "Incorrect number of arguments for struct Foo(a, b, c), got 1\n  at: line 1 (<test>)\n\n1 | struct Foo(a, b, c) ; Foo(1)(2) . print ; \n2 |                          ^^^\n"); }
     #[test] fn test_struct_construct_too_many_arguments() { run_str("struct Foo(a, b, c) ; Foo(1, 2, 3, 4) . print", "Incorrect number of arguments for struct Foo(a, b, c), got 4\n  at: line 1 (<test>)\n\n1 | struct Foo(a, b, c) ; Foo(1, 2, 3, 4) . print\n2 |                          ^^^^^^^^^^^^\n"); }
     #[test] fn test_local_vars_01() { run_str("let x=0 do { x.print }", "0\n"); }
@@ -1119,6 +2123,25 @@ mod tests {
     #[test] fn test_function_return_4() { run_str("fn foo() { let x = 3; do { let x; } return x } foo() . print", "3\n"); }
     #[test] fn test_function_return_5() { run_str("fn foo() { let x; do { let x = 3; return x } } foo() . print", "3\n"); }
     #[test] fn test_function_return_no_value() { run_str("fn foo() { print('hello') ; return ; print('world') } foo() . print", "hello\nnil\n"); }
+    #[test] fn test_function_return_no_value_at_end_of_line() { run_str("fn foo() {\n    print('hello')\n    return\n    print('world')\n}\nfoo() . print", "hello\nnil\n"); }
+    #[test] fn test_function_return_no_value_before_comment() { run_str("fn foo() {\n    return // done\n    'world'\n}\nfoo() . print", "nil\n"); }
+    #[test] fn test_function_return_value_on_same_line() { run_str("fn foo() {\n    return 'hello' +\n        ' world'\n}\nfoo() . print", "hello world\n"); }
+    #[test] fn test_function_return_in_arrow() { run_str("fn foo(x) -> return x + 1 ; foo(2) . print", "3\n"); }
+    #[test] fn test_function_return_in_arrow_no_value() { run_str("fn foo() -> return\nfoo() . print", "nil\n"); }
+    #[test] fn test_function_return_in_anonymous_arrow() { run_str("[1, 2, 3] . map(fn(x) -> return x * 2) . print", "[2, 4, 6]\n"); }
+    #[test] fn test_function_return_in_nested_loops() { run_str("fn foo(xs) { for x in xs { for y in xs { if x + y == 5 { return (x, y) } } } 'none' } ; print(foo([1, 2, 3, 4]), foo([1]))", "(1, 4) none\n"); }
+    #[test] fn test_function_return_in_while_loop() { run_str("fn foo() { let i = 0 ; while true { let j = i * 2 ; if j > 4 { return j } ; i += 1 } } ; foo() . print", "6\n"); }
+    #[test] fn test_function_return_in_nested_blocks() { run_str("fn foo(x) { if x { do { let y = x ; loop { do { return y } } } } else { 'no' } } ; print(foo(3), foo(0))", "3 nil\n"); }
+    #[test] fn test_function_return_from_loop_stack_discipline() { run_str("fn foo(xs) { for x in xs { if x > 1 { return x } } } ; let a = 10 ; print(a, 1 + foo([1, 2, 3]), [foo([5]), foo([0, 7])], a)", "10 3 [5, 7] 10\n"); }
+    #[test] fn test_function_implicit_return_last_expression() { run_str("fn foo() { let x = 3 ; x * 2 } ; foo() . print", "6\n"); }
+    #[test] fn test_function_implicit_return_last_expression_after_statements() { run_str("fn foo() { 1 ; 2\n3 } ; foo() . print", "3\n"); }
+    #[test] fn test_function_implicit_return_trailing_semicolon() { run_str("fn foo() { 1 ; } ; foo() . print", "nil\n"); }
+    #[test] fn test_function_implicit_return_assignment() { run_str("fn foo() { let x = 1 ; x = 3 } ; foo() . print", "3\n"); }
+    #[test] fn test_function_implicit_return_let() { run_str("fn foo() { 1 ; let x = 3 } ; foo() . print", "nil\n"); }
+    #[test] fn test_function_implicit_return_block_statement() { run_str("fn foo() { if true { 1 } else { 2 } } ; foo() . print", "nil\n"); }
+    #[test] fn test_function_implicit_return_loop() { run_str("fn foo() { for x in [1, 2] { x } } ; foo() . print", "nil\n"); }
+    #[test] fn test_function_implicit_return_inline_if() { run_str("fn foo(x) { if x then 1 else 2 } ; print(foo(true), foo(false))", "1 2\n"); }
+    #[test] fn test_function_implicit_return_after_return() { run_str("fn foo() { return 1 ; 2 } ; foo() . print", "1\n"); }
     #[test] fn test_partial_func_1() { run_str("'apples and bananas' . replace ('a', 'o') . print", "opples ond bononos\n"); }
     #[test] fn test_partial_func_2() { run_str("'apples and bananas' . replace ('a') ('o') . print", "opples ond bononos\n"); }
     #[test] fn test_partial_func_3() { run_str("print('apples and bananas' . replace ('a') ('o'))", "opples ond bononos\n"); }
@@ -1135,6 +2158,40 @@ mod tests {
     #[test] fn test_partial_function_zero_arg_native_function() { run_str("len() . repr . print", "fn len(x)\n"); }
     #[test] fn test_partial_function_zero_arg_operator_function() { run_str("(+)() . repr . print", "fn (+)(lhs, rhs)\n"); }
     #[test] fn test_partial_function_zero_arg_partial_user_function() { run_str("fn foo(a, b) {} ; foo(1)() . repr . print", "fn foo(a, b)\n"); }
+    #[test] fn test_placeholder_first_argument() { run_str("fn foo(a, b) -> a - b ; let f = foo(_, 2) ; f(5) . print", "3\n"); }
+    #[test] fn test_placeholder_last_argument() { run_str("fn foo(a, b) -> a - b ; let f = foo(5, _) ; f(2) . print", "3\n"); }
+    #[test] fn test_placeholder_many_arguments() { run_str("fn foo(a, b, c, d) -> [a, b, c, d] ; foo(_, 2, _, 4)(1, 3) . print", "[1, 2, 3, 4]\n"); }
+    #[test] fn test_placeholder_only_arguments() { run_str("fn foo(a, b) -> [a, b] ; foo(_, _)(1)(2) . print", "[1, 2]\n"); }
+    #[test] fn test_placeholder_native_function() { run_str("'a' . replace(_, 'o', 'apples and bananas') . print", "opples ond bononos\n"); }
+    #[test] fn test_placeholder_in_map() { run_str("[1, 2, 3] . map((**)(2, _)) . print", "[2, 4, 8]\n"); }
+    #[test] fn test_placeholder_in_composition() { run_str("5 . max(_, 3) . print", "5\n"); }
+    #[test] fn test_placeholder_evaluates_arguments_once() { run_str("fn foo(a, b) -> a + b ; let f = foo(_, print('once') or 1) ; [f(1), f(2)] . print", "once\n[2, 3]\n"); }
+    #[test] fn test_placeholder_with_closure() { run_str("fn outer() { let x = 10 ; fn inner(a, b) -> a + b + x ; inner(_, 1) } outer()(2) . print", "13\n"); }
+    #[test] fn test_placeholder_with_trailing_comma() { run_str("fn foo(a, b) -> [a, b] ; foo(1, _,)(2) . print", "[1, 2]\n"); }
+    #[test] fn test_method_call() { run_str("[1, 2, 3].map(str).print", "['1', '2', '3']\n"); }
+    #[test] fn test_method_call_many_arguments() { run_str("'apples and bananas'.replace('a', 'o').print", "opples ond bononos\n"); }
+    #[test] fn test_method_call_no_arguments() { run_str("[1, 2, 3].sum().print", "6\n"); }
+    #[test] fn test_method_call_chained() { run_str("[3, 1, 2].sort().map(str).reduce(+).print", "123\n"); }
+    #[test] fn test_method_call_with_optional_argument() { run_str("[1, 2, 3].join(', ').print", "1, 2, 3\n"); }
+    #[test] fn test_method_call_is_high_precedence() { run_str("(1 + [1, 2].sum()) . print", "4\n"); }
+    #[test] fn test_method_call_with_index() { run_str("'a b c'.split(' ')[1] . print", "b\n"); }
+    #[test] fn test_method_call_user_function() { run_str("fn foo(a, b) -> [a, b] ; 1.foo(2) . print", "[2, 1]\n"); }
+    #[test] fn test_method_call_with_placeholder() { run_str("fn foo(a, b, c) -> [a, b, c] ; 3.foo(_, 2)(1) . print", "[1, 2, 3]\n"); }
+    #[test] fn test_method_call_evaluates_receiver_first() { run_str("fn f(x) { print(x) ; x } f([1]).push(f(2)) . print", "[1]\n2\n[1, 2]\n"); }
+    #[test] fn test_method_call_with_whitespace_is_compose() { run_str("([1] + [1, 2] . sum) . print", "4\n"); }
+    #[test] fn test_method_call_with_unroll() { run_str("[1].push(...[2])", "Compile Error:\n\nUnrolled expression with '...' not allowed in a method call (i.e. 'x.f(...)'), use composition (i.e. 'x . f(...)') instead.\n  at: line 1 (<test>)\n\n1 | [1].push(...[2])\n2 |                ^\n"); }
+    #[test] fn test_custom_operator() { run_str("fn <+> (a, b) -> [a, b] ; (1 <+> 2) . print", "[1, 2]\n"); }
+    #[test] fn test_custom_operator_is_left_associative() { run_str("fn <+> (a, b) -> [a, b] ; (1 <+> 2 <+> 3) . print", "[[1, 2], 3]\n"); }
+    #[test] fn test_custom_operator_precedence() { run_str("fn <+> (a, b) -> [a, b] ; (1 + 2 <+> 3 * 4 == [3, 12]) . print", "true\n"); }
+    #[test] fn test_custom_operator_same_precedence_as_bitwise() { run_str("fn <+> (a, b) -> [a, b] ; (1 | 2 <+> 3) . print", "[3, 3]\n"); }
+    #[test] fn test_custom_operator_repr() { run_str("fn <$> (f, x) -> f(x) ; (<$>) . repr . print", "fn <$>(f, x)\n"); }
+    #[test] fn test_custom_operator_partial_left() { run_str("fn <-> (a, b) -> a - b ; [1, 2, 3] . map(<-> 1) . print", "[0, 1, 2]\n"); }
+    #[test] fn test_custom_operator_partial_right() { run_str("fn <-> (a, b) -> a - b ; [1, 2, 3] . map((10 <->)) . print", "[9, 8, 7]\n"); }
+    #[test] fn test_custom_operator_as_function() { run_str("fn <+> (a, b) -> a + b ; [1, 2, 3] . reduce(<+>) . print", "6\n"); }
+    #[test] fn test_custom_operator_late_bound() { run_str("fn f(a, b) -> a <?> b ; fn <?> (a, b) -> if a then a else b ; f(nil, 3) . print", "3\n"); }
+    #[test] fn test_custom_operator_local() { run_str("fn f() { fn <*> (a, b) -> a * b ; 3 <*> 4 } f() . print", "12\n"); }
+    #[test] fn test_custom_operator_undeclared() { run_str("1 <+> 2", "Compile Error:\n\nUndeclared identifier: '<+>'\n  at: line 1 (<test>)\n\n1 | 1 <+> 2\n2 |   ^^^\n"); }
+    #[test] fn test_placeholder_with_unroll() { run_str("fn foo(a, b) -> a ; foo(...[1], _)", "Compile Error:\n\nUnrolled expression with '...' not allowed in a function call with placeholder (i.e. '_') arguments.\n  at: line 1 (<test>)\n\n1 | fn foo(a, b) -> a ; foo(...[1], _)\n2 |                                  ^\n"); }
     #[test] fn test_partial_function_zero_arg_partial_native_function() { run_str("push(1)() . repr . print", "fn push(value, collection)\n"); }
     #[test] fn test_partial_function_zero_arg_partial_operator_function() { run_str("(+)(1)() . repr . print", "fn (+)(lhs, rhs)\n"); }
     #[test] fn test_partial_function_zero_arg_user_not_optimized() { run_str("fn f(x) -> x() ; f(f(f(f))) . repr . print", "fn f(x)\n"); }
@@ -1150,8 +2207,27 @@ mod tests {
     #[test] fn test_partial_user_functions_8() { run_str("fn add(x, y) -> x + y ; add(1)(2) . print", "3\n"); }
     #[test] fn test_function_with_one_default_arg() { run_str("fn foo(a, b?) { print(a, b) } ; foo('test') ; foo('test', 'bar')", "test nil\ntest bar\n"); }
     #[test] fn test_function_with_one_default_arg_not_enough() { run_str("fn foo(a, b?) { print(a, b) } ; foo()", ""); }
-    #[test] fn test_function_with_one_default_arg_too_many() { run_str("fn foo(a, b?) { print(a, b) } ; foo(1, 2, 3)", "Incorrect number of arguments for fn foo(a, b), got 3\n  at: line 1 (<test>)\n\n1 | fn foo(a, b?) { print(a, b) } ; foo(1, 2, 3)\n2 |                                    ^^^^^^^^^\n"); }
+    #[test] fn test_function_with_one_default_arg_too_many() { run_str("fn foo(a, b?) { print(a, b) } ; foo(1, 2, 3)", "Incorrect number of arguments for fn foo(a, b?), got 3\n  at: line 1 (<test>)\n  defined at: line 1 (<test>)\n\n1 | fn foo(a, b?) { print(a, b) } ; foo(1, 2, 3)\n2 |                                    ^^^^^^^^^\n"); }
+    #[test] fn test_function_incorrect_arguments_shows_defaults() { run_str("fn foo(a, b = 1 + 2, c = [a]) -> a\nfoo(1, 2, 3, 4)", "Incorrect number of arguments for fn foo(a, b = 1 + 2, c = [a]), got 4\n  at: line 2 (<test>)\n  defined at: line 1 (<test>)\n\n2 | foo(1, 2, 3, 4)\n3 |    ^^^^^^^^^^^^\n"); }
+    #[test] fn test_function_incorrect_arguments_shows_patterns() { run_str("fn foo(a, (b, *c)) -> a\nfoo(1, 2, 3)", "Incorrect number of arguments for fn foo(a, (b, *c)), got 3\n  at: line 2 (<test>)\n  defined at: line 1 (<test>)\n\n2 | foo(1, 2, 3)\n3 |    ^^^^^^^^^\n"); }
+    #[test] fn test_function_incorrect_arguments_on_other_line() { run_str("\nfn foo(\n    a,\n    b = 'b'\n) {\n    a\n}\nfoo(1, 2, 3)", "Incorrect number of arguments for fn foo(a, b = 'b'), got 3\n  at: line 8 (<test>)\n  defined at: line 2 (<test>)\n\n8 | foo(1, 2, 3)\n9 |    ^^^^^^^^^\n"); }
+    #[test] fn test_function_incorrect_arguments_async() { run_str("async fn foo(a, b?) -> a\nfoo(1, 2, 3)", "Incorrect number of arguments for async fn foo(a, b?), got 3\n  at: line 2 (<test>)\n  defined at: line 1 (<test>)\n\n2 | foo(1, 2, 3)\n3 |    ^^^^^^^^^\n"); }
+    #[test] fn test_function_incorrect_arguments_anonymous() { run_str("let f = fn(x, y) -> x\nf(1, 2, 3)", "Incorrect number of arguments for fn _(x, y), got 3\n  at: line 2 (<test>)\n  defined at: line 1 (<test>)\n\n2 | f(1, 2, 3)\n3 |  ^^^^^^^^^\n"); }
+    #[test] fn test_function_incorrect_arguments_in_function() { run_str("fn foo(x) -> x\nfn bar() -> foo(1, 2)\nbar()", "Incorrect number of arguments for fn foo(x), got 2\n  at: line 2 (<test>)\n  defined at: line 1 (<test>)\n  at: `fn bar()` (line 3)\n\n2 | fn bar() -> foo(1, 2)\n3 |                ^^^^^^\n"); }
+    #[test] fn test_function_incorrect_arguments_from_eval() { run_str("let f = eval('fn(x) -> x')\nf(1, 2)", "Incorrect number of arguments for fn _(x), got 2\n  at: line 2 (<test>)\n  defined at: line 1 (<eval>)\n\n2 | f(1, 2)\n3 |  ^^^^^^\n"); }
+    #[test] fn test_function_incorrect_arguments_struct_method() { run_str("struct S(x) { fn get_x(self, y?) -> self->x }\nS->get_x(S(1), 2, 3)", "Incorrect number of arguments for fn get_x(self, y?), got 3\n  at: line 2 (<test>)\n  defined at: line 1 (<test>)\n\n2 | S->get_x(S(1), 2, 3)\n3 |         ^^^^^^^^^^^^\n"); }
     #[test] fn test_function_many_default_args() { run_str("fn foo(a, b = 1, c = 1 + 1, d = 1 * 3) { print(a, b, c, d) } foo('test') ; foo('and', 11) ; foo('other', 11, 22) ; foo('things', 11, 22, 33)", "test 1 2 3\nand 11 2 3\nother 11 22 3\nthings 11 22 33\n"); }
+    #[test] fn test_function_default_arg_references_earlier_args() { run_str("fn f(a, b = a + 1, c = a * b) -> (a, b, c) ; print(f(2), f(2, 5), f(2, 5, 0))", "(2, 3, 6) (2, 5, 10) (2, 5, 0)\n"); }
+    #[test] fn test_function_default_arg_is_bare_earlier_arg() { run_str("fn f(a, b = a, c = 1) -> (a, b, c) ; print(f(2), repr(f))", "(2, 2, 1) fn f(a, b, c)\n"); }
+    #[test] fn test_function_default_arg_cannot_reference_itself_or_later_args() { run_str("let a = 'outer', b = 'outer' ; fn f(x = b, b = 1, a = a) -> (x, b, a) ; print(f())", "('outer', 1, 'outer')\n"); }
+    #[test] fn test_function_default_arg_cannot_reference_pattern_arg() { run_str("fn f((a, b), c = a) -> c", "Compile Error:\n\nUndeclared identifier: 'a'\n  at: line 1 (<test>)\n\n1 | fn f((a, b), c = a) -> c\n2 |                  ^\n"); }
+    #[test] fn test_function_default_arg_evaluated_per_call() { run_str("fn f(x = []) { x.push(1) ; x } ; print(f(), f())", "[1] [1]\n"); }
+    #[test] fn test_function_default_arg_in_expression_function() { run_str("(fn(a, b = a * 2) -> b)(3) . print", "6\n"); }
+    #[test] fn test_function_default_arg_with_var_arg() { run_str("fn f(a, n = len(a), *rest) -> (n, rest) ; print(f('abc'), f('abc', 1, 2))", "(3, ()) (1, (2))\n"); }
+    #[test] fn test_function_default_arg_captured_by_closure() { run_str("fn f(a, g = fn() -> a * 3) -> g() ; print(f(4))", "12\n"); }
+    #[test] fn test_function_default_arg_references_upvalue() { run_str("fn outer() { let x = 5 ; fn inner(y = x * 2) -> y ; inner() } ; print(outer())", "10\n"); }
+    #[test] fn test_function_default_arg_with_partial_application() { run_str("fn f(a, b, c = a + b) -> c ; print(f(1)(2), f(1)(2, 7), f(_, 10)(1), f(1, _)(2))", "3 7 11 3\n"); }
+    #[test] fn test_function_default_arg_in_struct_method() { run_str("struct P(x) { fn scaled(p, k = p->x) -> p->x * k } ; print(P->scaled(P(3)), P->scaled(P(3), 2))", "9 6\n"); }
     #[test] fn test_function_unroll_1() { run_str("fn foo(a, b, c) -> print(a, b, c) ; foo(...['hello', 'the', 'world'])", "hello the world\n"); }
     #[test] fn test_function_unroll_2() { run_str("fn foo(a, b, c) -> print(a, b, c) ; foo(1, 2, 3, ...[])", "1 2 3\n"); }
     #[test] fn test_function_unroll_3() { run_str("fn foo(a, b, c) -> print(a, b, c) ; foo(1, ...[], 2, ...[], 3)", "1 2 3\n"); }
@@ -1161,7 +2237,7 @@ mod tests {
     #[test] fn test_function_unroll_7() { run_str("fn foo(a, b, c, d) -> print(a, b, c, d) ; foo('a', ...'bc', 'd')", "a b c d\n"); }
     #[test] fn test_function_unroll_8() { run_str("fn foo(a, b, c) -> print(a, b, c) ; foo(1, ...'ab')", "1 a b\n"); }
     #[test] fn test_function_unroll_9() { run_str("fn foo(a, b, c) -> print(a, b, c) ; foo(...'ab', 3)", "a b 3\n"); }
-    #[test] fn test_function_unroll_10() { run_str("fn foo(a, b, c) -> print(a, b, c) ; foo(1, 2, ...[3, 4])", "Incorrect number of arguments for fn foo(a, b, c), got 4\n  at: line 1 (<test>)\n\n1 | fn foo(a, b, c) -> print(a, b, c) ; foo(1, 2, ...[3, 4])\n2 |                                        ^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_function_unroll_10() { run_str("fn foo(a, b, c) -> print(a, b, c) ; foo(1, 2, ...[3, 4])", "Incorrect number of arguments for fn foo(a, b, c), got 4\n  at: line 1 (<test>)\n  defined at: line 1 (<test>)\n\n1 | fn foo(a, b, c) -> print(a, b, c) ; foo(1, 2, ...[3, 4])\n2 |                                        ^^^^^^^^^^^^^^^^^\n"); }
     #[test] fn test_function_unroll_11() { run_str("fn foo(a, b, c) -> print(a, b, c) ; foo(1, 2, ...[]) is function . print", "true\n"); }
     #[test] fn test_function_unroll_12() { run_str("sum([1, 2, 3, 4, 5]) . print", "15\n"); }
     #[test] fn test_function_unroll_13() { run_str("sum(...[1, 2, 3, 4, 5]) . print", "15\n"); }
@@ -1178,6 +2254,10 @@ mod tests {
     #[test] fn test_function_var_args_7() { run_str("fn foo(a, b?, *c) -> print(a, b, c) ; foo(1, 2, 3)", "1 2 (3)\n"); }
     #[test] fn test_function_var_args_8() { run_str("fn foo(a, b?, *c) -> print(a, b, c) ; foo(1, 2, 3, 4)", "1 2 (3, 4)\n"); }
     #[test] fn test_function_var_args_9() { run_str("fn foo(a, b?, *c) -> print(a, b, c) ; foo(1, 2, 3, 4, 5)", "1 2 (3, 4, 5)\n"); }
+    #[test] fn test_function_var_args_len() { run_str("fn foo(a, *b) -> print(a, len(b)) ; foo(1) ; foo(1, 2, 3)", "1 0\n1 2\n"); }
+    #[test] fn test_function_var_args_unrolled() { run_str("fn foo(a, *b) -> print(a, b) ; foo(...[1, 2, 3]) ; foo(1, ...[2, 3], 4)", "1 (2, 3)\n1 (2, 3, 4)\n"); }
+    #[test] fn test_function_var_args_partial() { run_str("fn foo(a, b, *c) -> print(a, b, c) ; foo(1)(2) ; foo(1)(2, 3, 4)", "1 2 ()\n1 2 (3, 4)\n"); }
+    #[test] fn test_function_var_args_repr() { run_str("fn foo(a, b?, *c) -> nil ; foo . repr . print ; (fn(*x) -> x) . repr . print", "fn foo(a, b, *c)\nfn _(*x)\n"); }
     #[test] fn test_function_call_with_over_u8_arguments() { run_str("sum(...range(1 + 1000)) . print", "500500\n"); }
     #[test] fn test_operator_functions_01() { run_str("(+3) . print", "(+)\n"); }
     #[test] fn test_operator_functions_02() { run_str("4 . (+3) . print", "7\n"); }
@@ -1221,6 +2301,19 @@ mod tests {
     #[test] fn test_operator_is_function_no() { run_str("[nil, true, 123, '123', [], set()] . any(is function) . print", "false\n"); }
     #[test] fn test_operator_is_partial_left() { run_str("let f = (1 is) ; f(int) . print", "true\n"); }
     #[test] fn test_operator_is_partial_right() { run_str("let f = (is int) ; f(1) . print", "true\n"); }
+    #[test] fn test_operator_section_filter() { run_str("[1, 2, 3, 4] . filter(<3) . print", "[1, 2]\n"); }
+    #[test] fn test_operator_section_mod_map() { run_str("[1, 2, 3, 4] . map(%2) . print", "[1, 0, 1, 0]\n"); }
+    #[test] fn test_operator_section_pow_map() { run_str("[1, 2, 3] . map(**2) . print", "[1, 4, 9]\n"); }
+    #[test] fn test_operator_section_in_filter() { run_str("[1, 2, 3] . filter(in [1, 3]) . print", "[1, 3]\n"); }
+    #[test] fn test_operator_section_index() { run_str("let f = ([1]) ; f('abc') . print", "b\n"); }
+    #[test] fn test_operator_section_index_map() { run_str("[[1, 2], [3, 4]] . map([0]) . print", "[1, 3]\n"); }
+    #[test] fn test_operator_section_index_filter() { run_str("[[1, true], [2, false]] . filter([1]) . print", "[[1, true]]\n"); }
+    #[test] fn test_operator_section_index_dict() { run_str("[{'a': 1}, {'a': 2}] . map(['a']) . print", "[1, 2]\n"); }
+    #[test] fn test_operator_section_index_eval() { run_str("let x = [5, 6] ; [1](x) . print", "6\n"); }
+    #[test] fn test_operator_section_index_eval_out_of_bounds() { run_str("[3]([5, 6]) . print", "Index '3' is out of bounds for list of length [0, 2)\n  at: line 1 (<test>)\n\n1 | [3]([5, 6]) . print\n2 |    ^^^^^^^^\n"); }
+    #[test] fn test_operator_section_index_eval_wrong_length() { run_str("[1, 2]([5, 6]) . print", "ValueError: Evaluating an index must have len = 1, got len = 2\n  at: line 1 (<test>)\n\n1 | [1, 2]([5, 6]) . print\n2 |       ^^^^^^^^\n"); }
+    #[test] fn test_operator_section_slice_eval() { run_str("[1:]([5, 6, 7]) . print", "[6, 7]\n"); }
+    #[test] fn test_operator_section_slice_map() { run_str("['abc', 'de'] . map([::-1]) . print", "['cba', 'ed']\n"); }
     #[test] fn test_operator_not_is() { run_str("let f = (is not) ; f(1, str) . print", "true\n"); }
     #[test] fn test_operator_not_is_partial_left() { run_str("let f = (1 is not) ; f(str) . print", "true\n"); }
     #[test] fn test_operator_not_is_partial_right() { run_str("let f = (is not str) ; f(1) . print", "true\n"); }
@@ -1266,13 +2359,917 @@ mod tests {
     #[test] fn test_int_operators() { run_str("print(5 - 3, 12 + 5, 3 * 9, 16 / 3)", "2 17 27 5\n"); }
     #[test] fn test_int_div_mod() { run_str("print(3 / 2, 3 / 3, -3 / 2, 10 % 3, 11 % 3, 12 % 3)", "1 1 -2 1 2 0\n"); }
     #[test] fn test_int_div_by_zero() { run_str("print(15 / 0)", "Compile Error:\n\nValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n\n1 | print(15 / 0)\n2 |          ^\n"); }
+    #[test] fn test_int_mod_by_zero() { run_str("print(15 % 0)", "Compile Error:\n\nValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n\n1 | print(15 % 0)\n2 |          ^\n"); }
+    #[test] fn test_int_mod_by_zero_at_runtime() { run_str("let x = 0 ; print(15 % x)", "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n\n1 | let x = 0 ; print(15 % x)\n2 |                      ^\n"); }
+    #[test] fn test_return_outside_of_function() { run_str("print('before') ; do { let x = 1 ; return x }", "Compile Error:\n\nInvalid 'return' statement outside of an enclosing function\n  at: line 1 (<test>)\n\n1 | print('before') ; do { let x = 1 ; return x }\n2 |                                    ^^^^^^\n"); }
+    #[test] fn test_pattern_assign_to_native_function() { run_str("let x ; print, x = [1, 2]", "Compile Error:\n\nThe left hand side of an assignment expression must be a variable, array access, or property access\n  at: line 1 (<test>)\n\n1 | let x ; print, x = [1, 2]\n2 |                  ^\n"); }
+    #[test] fn test_pattern_assign_to_late_bound_global() { run_str("let y ; fn f() { x, y = [1, 2] } let x", "Compile Error:\n\nCannot assign to global 'x' in a pattern before it is declared\n  at: line 1 (<test>)\n\n1 | let y ; fn f() { x, y = [1, 2] } let x\n2 |                       ^\n"); }
     #[test] fn test_int_left_right_shift() { run_str("print(1 << 10, 16 >> 1, 16 << -1, 1 >> -10)", "1024 8 8 1024\n"); }
     #[test] fn test_int_comparisons_1() { run_str("print(1 < 3, -5 < -10, 6 > 7, 6 > 4)", "true false false true\n"); }
     #[test] fn test_int_comparisons_2() { run_str("print(1 <= 3, -5 < -10, 3 <= 3, 2 >= 2, 6 >= 7, 6 >= 4, 6 <= 6, 8 >= 8)", "true false true true false true true true\n"); }
     #[test] fn test_int_equality() { run_str("print(1 == 3, -5 == -10, 3 != 3, 2 == 2, 6 != 7)", "false false false true true\n"); }
     #[test] fn test_int_bitwise_operators() { run_str("print(0b111 & 0b100, 0b1100 | 0b1010, 0b1100 ^ 0b1010)", "4 14 6\n"); }
+    #[test] fn test_int_octal_literal() { run_str("print(0o755, 0o7_7 + 1)", "493 64\n"); }
+    #[test] fn test_int_scientific_literal() { run_str("print(1e9 + 7, 25e2, 1e0)", "1000000007 2500 1\n"); }
+    #[test] fn test_int_char_literal() { run_str("print('a'c, 'a'c == ord('a'), 'z'c - 'a'c, ['x', 'y'] . map(fn(c) -> ord(c) - 'a'c))", "97 true 25 [23, 24]\n"); }
     #[test] fn test_int_to_hex() { run_str("1234 . hex . print", "4d2\n"); }
     #[test] fn test_int_to_bin() { run_str("1234 . bin . print", "10011010010\n"); }
+    #[test] fn test_str_is_digit() { run_str("['0', '123', '12a', '', ' 1', '٣'] . map(is_digit) . print", "[true, true, false, false, false, false]\n"); }
+    #[test] fn test_str_is_alpha() { run_str("['a', 'abc', 'ab1', '', 'é'] . map(is_alpha) . print", "[true, true, false, false, true]\n"); }
+    #[test] fn test_str_is_alnum() { run_str("['a', 'ab1', 'a_1', ''] . map(is_alnum) . print", "[true, true, false, false]\n"); }
+    #[test] fn test_str_is_space() { run_str("[' ', ' \\t\\n', ' a', ''] . map(is_space) . print", "[true, true, false, false]\n"); }
+    #[test] fn test_str_is_upper() { run_str("['A', 'AB', 'Ab', 'A1', ''] . map(is_upper) . print", "[true, true, false, false, false]\n"); }
+    #[test] fn test_str_is_lower() { run_str("['a', 'ab', 'aB', 'a1', ''] . map(is_lower) . print", "[true, true, false, false, false]\n"); }
+    #[test] fn test_str_is_digit_filter() { run_str("'a1b2c3' . filter(is_digit) . join('') . print", "123\n"); }
+    #[test] fn test_str_is_digit_not_str() { run_str("is_digit(1)", "TypeError: Expected '1' of type 'int' to be a string\n  at: line 1 (<test>)\n\n1 | is_digit(1)\n2 |         ^^^\n"); }
+    #[test] fn test_csv_decode_empty() { run_str("csv_decode('') . print", "[]\n"); }
+    #[test] fn test_csv_decode_simple() { run_str("csv_decode('a,b\\n1,2\\n') . print", "[('a', 'b'), ('1', '2')]\n"); }
+    #[test] fn test_csv_decode_no_trailing_newline() { run_str("csv_decode('a,b\\n1,2') . print", "[('a', 'b'), ('1', '2')]\n"); }
+    #[test] fn test_csv_decode_crlf() { run_str("csv_decode('a,b\\r\\n1,2\\r\\n') . print", "[('a', 'b'), ('1', '2')]\n"); }
+    #[test] fn test_csv_decode_empty_fields() { run_str("csv_decode(',a,\\n') . print", "[('', 'a', '')]\n"); }
+    #[test] fn test_csv_decode_quoted() { run_str("csv_decode('\"a,b\",\"c\\nd\",\"say \"\"hi\"\"\"') . print", "[('a,b', 'c\\nd', 'say \\\"hi\\\"')]\n"); }
+    #[test] fn test_csv_decode_quoted_empty_field() { run_str("print(csv_decode('\"\"'), csv_decode('\"\"\\n'), csv_decode('a\\n\"\"'))", "[('')] [('')] [('a'), ('')]\n"); }
+    #[test] fn test_csv_decode_delimiter() { run_str("csv_decode('a;b,c\\n', ';') . print", "[('a', 'b,c')]\n"); }
+    #[test] fn test_csv_decode_unterminated_quote() { run_str("csv_decode('\"a')", "ValueError: Unterminated quoted field in CSV\n  at: line 1 (<test>)\n\n1 | csv_decode('\"a')\n2 |           ^^^^^^\n"); }
+    #[test] fn test_csv_decode_invalid_delimiter() { run_str("csv_decode('a', ';;')", "ValueError: Expected ';;' of type 'str' to be a single character CSV delimiter, which is not a quote or newline\n  at: line 1 (<test>)\n\n1 | csv_decode('a', ';;')\n2 |           ^^^^^^^^^^^\n"); }
+    #[test] fn test_csv_encode_empty() { run_str("csv_encode([]) . repr . print", "''\n"); }
+    #[test] fn test_csv_encode_simple() { run_str("csv_encode([['a', 'b'], [1, nil]]) . repr . print", "'a,b\\n1,nil\\n'\n"); }
+    #[test] fn test_csv_encode_quoted() { run_str("csv_encode([['a,b', 'c\\nd', 'say \"hi\"']]) . repr . print", "'\\\"a,b\\\",\\\"c\\nd\\\",\\\"say \\\"\\\"hi\\\"\\\"\\\"\\n'\n"); }
+    #[test] fn test_csv_encode_delimiter() { run_str("csv_encode([['a;b', 'c,d']], ';') . repr . print", "'\\\"a;b\\\";c,d\\n'\n"); }
+    #[test] fn test_csv_encode_decode_round_trip() { run_str("let rows = [('a', 'b,c'), ('\"', '\\n')] ; rows . csv_encode . csv_decode . (==rows) . print", "true\n"); }
+    #[test] fn test_read() { run_str_with_read("read() . repr . print", "a\nb\n", "'a\\nb\\n'\n"); }
+    #[test] fn test_read_line() { run_str_with_read("print(read_line(), read_line())", "a\nb\nc\n", "a b\n"); }
+    #[test] fn test_read_line_crlf() { run_str_with_read("read_line() . repr . print", "a\r\nb", "'a'\n"); }
+    #[test] fn test_read_line_eof() { run_str_with_read("read_line() . repr . print", "", "''\n"); }
+    #[test] fn test_read_lines() { run_str_with_read("read_lines() . print", "a\nb\r\n\nc", "['a', 'b', '', 'c']\n"); }
+    #[test] fn test_read_lines_after_read_line() { run_str_with_read("print(read_line(), read_lines())", "a\nb\nc\n", "a ['b', 'c']\n"); }
+    #[test] fn test_read_lines_empty() { run_str_with_read("read_lines() . print", "", "[]\n"); }
+    #[test] fn test_input() { run_str_with_read("let name = input('name? ') ; print('hello', name)", "world\n", "name? hello world\n"); }
+    #[test] fn test_input_prompt_is_str() { run_str_with_read("input(123) . print", "abc\n", "123abc\n"); }
+    #[test] fn test_eprintln() { run_str_with_stderr("eprintln('a', 1) ; print('b') ; eprintln()", "b\n", "a 1\n\n"); }
+    #[test] fn test_eprint() { run_str_with_stderr("eprint() ; eprint('a') ; eprint('b', 'c')", "", "ab c"); }
+    #[test] fn test_write_bytes() { run_str("write_bytes([104, 105]) ; write_bytes((10,))", "hi\n"); }
+    #[test] fn test_write_bytes_empty() { run_str("write_bytes([]) ; print('a')", "a\n"); }
+    #[test] fn test_write_bytes_out_of_range() { run_str("write_bytes([1, 256])", "ValueError: Cannot write int 256 as a byte, must be in the range [0, 256)\n  at: line 1 (<test>)\n\n1 | write_bytes([1, 256])\n2 |            ^^^^^^^^^^\n"); }
+    #[test] fn test_exit() { run_str_with_exit_code("print('a') ; exit ; print('b')", "a\n", 0); }
+    #[test] fn test_exit_with_code() { run_str_with_exit_code("print('a') ; exit(3) ; print('b')", "a\n", 3); }
+    #[test] fn test_exit_with_code_in_function() { run_str_with_exit_code("fn f(x) { exit(x + 1) } f(4)", "", 5); }
+    #[test] fn test_exit_with_code_not_int() { run_str("exit('no')", "TypeError: Expected 'no' of type 'str' to be a int\n  at: line 1 (<test>)\n\n1 | exit('no')\n2 | ^^^^^^^^^^\n"); }
+    #[test] fn test_exit_with_code_out_of_range() { run_str("exit(1 << 40)", "ValueError: Cannot exit with code 1099511627776, must be a 32-bit signed integer\n  at: line 1 (<test>)\n\n1 | exit(1 << 40)\n2 | ^^^^^^^^^^^^^\n"); }
+    #[test] fn test_with() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; with R(1) as x { print('body', x) }", "enter 1\nbody 1\nexit 1\n"); }
+    #[test] fn test_with_no_name() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; with R(1) { print('body') }", "enter 1\nbody\nexit 1\n"); }
+    #[test] fn test_with_nested() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; with R(1) { with R(2) { print('body') } }", "enter 1\nenter 2\nbody\nexit 2\nexit 1\n"); }
+    #[test] fn test_with_no_enter() { run_str("struct R(x) { fn __exit__(self) -> print('exit') } ; with R(1) as r { print(r) }", "R(x=1)\nexit\n"); }
+    #[test] fn test_with_close() { run_str("struct R(x) { fn close(self) -> print('close') } ; with R(1) as r { print(r) }", "R(x=1)\nclose\n"); }
+    #[test] fn test_with_exit_over_close() { run_str("struct R(x) { fn close(self) -> print('close') ; fn __exit__(self) -> print('exit') } ; with R(1) {}", "exit\n"); }
+    #[test] fn test_with_return() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; fn f() { with R(1) as x { return x + 1 } } ; f() . print", "enter 1\nexit 1\n2\n"); }
+    #[test] fn test_with_implicit_return() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; fn f() { with R(1) as x { x + 1 } } ; f() . print", "enter 1\nexit 1\nnil\n"); }
+    #[test] fn test_with_return_nested_in_loop() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; fn f() { with R(1) { for i in range(5) { with R(i + 10) as x { if i == 1 { return x } } } } } ; f() . print", "enter 1\nenter 10\nexit 10\nenter 11\nexit 11\nexit 1\n11\n"); }
+    #[test] fn test_with_return_value_evaluated_before_exit() { run_str("struct R(x) { fn __exit__(self) -> print('exit') } ; fn f() { with R(1) { return print('value') or 2 } } ; f() . print", "value\nexit\n2\n"); }
+    #[test] fn test_with_break() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; fn f() { loop { with R(1) { break } } print('after') } ; f()", "enter 1\nexit 1\nafter\n"); }
+    #[test] fn test_with_continue() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; fn f() { for i in range(2) { with R(i) { continue } } } ; f()", "enter 0\nexit 0\nenter 1\nexit 1\n"); }
+    #[test] fn test_with_break_inside_loop() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; with R(1) { loop { break } print('body') }", "enter 1\nbody\nexit 1\n"); }
+    #[test] fn test_with_error() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; with R(1) { with R(2) { assert false } }", "enter 1\nenter 2\nexit 2\nexit 1\nAssertion Failed: nil\n  at: line 1 (<test>)\n\n1 | struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; with R(1) { with R(2) { assert false } }\n2 |                                                                                                                                                       ^^^^^\n"); }
+    #[test] fn test_with_error_in_function() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; fn f() { with R(1) { assert false } } ; f()", "enter 1\nexit 1\nAssertion Failed: nil\n  at: line 1 (<test>)\n  at: `fn f()` (line 1)\n\n1 | struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; fn f() { with R(1) { assert false } } ; f()\n2 |                                                                                                                                                    ^^^^^\n"); }
+    #[test] fn test_with_exit() { run_str_with_exit_code("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; with R(1) { exit(2) }", "enter 1\nexit 1\n", 2); }
+    #[test] fn test_with_assert_err() { run_str("struct R(x) { fn __enter__(self) { print('enter', self->x) ; self->x } fn __exit__(self) -> print('exit', self->x) } ; assert_err(fn() { with R(1) { assert false } }) ; print('after')", "enter 1\nexit 1\nafter\n"); }
+    #[test] fn test_with_error_in_enter() { run_str("struct R(x) { fn __enter__(self) { assert false } fn __exit__(self) -> print('exit') } ; with R(1) {}", "Assertion Failed: nil\n  at: line 1 (<test>)\n  at: `fn __enter__(self)` (line 1)\n\n1 | struct R(x) { fn __enter__(self) { assert false } fn __exit__(self) -> print('exit') } ; with R(1) {}\n2 |                                           ^^^^^\n"); }
+    #[test] fn test_with_error_in_exit() { run_str("struct R(x) { fn __exit__(self) { assert false } } ; with R(1) { print('body') }", "body\nAssertion Failed: nil\n  at: line 1 (<test>)\n  at: `fn __exit__(self)` (line 1)\n\n1 | struct R(x) { fn __exit__(self) { assert false } } ; with R(1) { print('body') }\n2 |                                          ^^^^^\n"); }
+    #[test] fn test_with_not_context() { run_str("with 1 {}", "TypeError: Expected '1' of type 'int' to be a struct with an '__exit__' or 'close' member, or a socket, coprocess, or database, in order to be used in a 'with' statement\n  at: line 1 (<test>)\n\n1 | with 1 {}\n2 |      ^\n"); }
+    #[test] fn test_with_struct_not_context() { run_str("struct R(x) ; with R(1) {}", "TypeError: Expected 'R(x=1)' of type 'struct' to be a struct with an '__exit__' or 'close' member, or a socket, coprocess, or database, in order to be used in a 'with' statement\n  at: line 1 (<test>)\n\n1 | struct R(x) ; with R(1) {}\n2 |                       ^\n"); }
+    #[test] fn test_with_no_name_after_as() { run_str("with 1 as {}", "Compile Error:\n\nExpected a variable name after 'as' in 'with' statement, got '{' token instead\n  at: line 1 (<test>)\n\n1 | with 1 as {}\n2 |           ^\n"); }
+    #[test] fn test_async_fn_returns_task() { run_str("async fn f() -> 1 ; f() . print", "task(async fn f())\n"); }
+    #[test] fn test_async_fn_repr() { run_str("async fn f(x) -> x ; f . repr . print", "async fn f(x)\n"); }
+    #[test] fn test_async_fn_await() { run_str("async fn f(x) -> x + 1 ; print(await f(2))", "3\n"); }
+    #[test] fn test_async_fn_not_run_until_await() { run_str("async fn f() -> print('f') ; let t = f() ; print('before') ; await t ; print('after')", "before\nf\nafter\n"); }
+    #[test] fn test_async_fn_await_twice() { run_str("async fn f() { print('f') ; 3 } ; let t = f() ; print(await t, await t)", "f\n3 3\n"); }
+    #[test] fn test_async_fn_await_nested() { run_str("async fn f(x) -> x * 2 ; async fn g(x) -> 1 + await f(x) ; print(await g(3))", "7\n"); }
+    #[test] fn test_async_fn_await_in_fn() { run_str("async fn f(x) -> x * 2 ; fn g(x) -> await f(x) ; [1, 2, 3] . map g . print", "[2, 4, 6]\n"); }
+    #[test] fn test_async_fn_await_in_expression() { run_str("async fn f(x) -> x ; async fn g() { let x = [1, 2] ; x + [await f(3), 4] } ; print(await g())", "[1, 2, 3, 4]\n"); }
+    #[test] fn test_async_fn_interleaved() { run_str("async fn f(name) { print(name, 1) ; await sleep_async(0) ; print(name, 2) } ; let a = f('a'), b = f('b') ; await a ; await b", "a 1\nb 1\na 2\nb 2\n"); }
+    #[test] fn test_async_fn_sleep_order() { run_str("async fn f(name, ms) { await sleep_async(ms) ; print(name) } ; let a = f('a', 30), b = f('b', 10), c = f('c', 20) ; await a ; await b ; await c", "b\nc\na\n"); }
+    #[test] fn test_async_fn_closure_over_suspended_local() { run_str("async fn f() { let x = 1 ; let g = fn() -> x += 10 ; await sleep_async(0) ; g() ; x } ; async fn h(t) { let x = await t ; x } ; print(await f())", "11\n"); }
+    #[test] fn test_async_fn_closure_modified_while_suspended() { run_str("let g ; async fn f() { let x = 1 ; g = fn() -> x += 10 ; await sleep_async(10) ; x } ; async fn h() { await sleep_async(0) ; g() } ; let t = f() ; h() ; print(await t)", "11\n"); }
+    #[test] fn test_async_fn_in_struct() { run_str("struct A(x) { async fn get(self) -> self->x } ; print(await A->get(A(5)))", "5\n"); }
+    #[test] fn test_async_fn_partial() { run_str("async fn f(a, b) -> a - b ; let g = f(5) ; print(await g(2))", "3\n"); }
+    #[test] fn test_async_fn_expression() { run_str("let f = async fn(x) -> x * 3 ; print(await f(2))", "6\n"); }
+    #[test] fn test_async_fn_await_loop() { run_str("async fn f(x) -> x ; async fn g() { let s = 0 ; for i in range(5) { s += await f(i) } ; s } ; print(await g())", "10\n"); }
+    #[test] fn test_async_fn_await_in_with() { run_str("struct R(x) { fn __exit__(self) -> print('exit') } ; async fn f() { with R(1) { await sleep_async(0) ; print('body') } } ; await f()", "body\nexit\n"); }
+    #[test] fn test_async_fn_await_self() { run_str("let t ; async fn f() -> await t ; t = f() ; await t", "ValueError: Cannot await 'task(async fn f())' of type 'task', as it will never complete\n  at: line 1 (<test>)\n\n1 | let t ; async fn f() -> await t ; t = f() ; await t\n2 |                                             ^^^^^\n"); }
+    #[test] fn test_async_fn_error() { run_str("async fn f() { assert false } ; await f()", "Assertion Failed: nil\n  at: line 1 (<test>)\n  at: `async fn f()` (line 1)\n\n1 | async fn f() { assert false } ; await f()\n2 |                       ^^^^^\n"); }
+    #[test] fn test_await_not_task() { run_str("await 1", "TypeError: Expected '1' of type 'int' to be a task\n  at: line 1 (<test>)\n\n1 | await 1\n2 | ^^^^^\n"); }
+    #[test] fn test_await_sleep_async() { run_str("print(await sleep_async(1))", "nil\n"); }
+    #[test] fn test_sleep_async_negative() { run_str("sleep_async(-1)", "ValueError: Expected value '-1: int' to be non-negative\n  at: line 1 (<test>)\n\n1 | sleep_async(-1)\n2 |            ^^^^\n"); }
+    #[test] fn test_task_of_value() { run_str("print(await task(3), task(3))", "3 task(3)\n"); }
+    #[test] fn test_task_of_task() { run_str("let t = sleep_async(0) ; print(task(t) == t, t)", "true task(sleep_async(0))\n"); }
+    #[test] fn test_task_type() { run_str("async fn f() -> 1 ; let t = f() ; print(typeof t, t is task, 1 is task, task(1) is task)", "task true false true\n"); }
+    #[test] fn test_at_exit() { run_str_with_exit_code("at_exit(fn() -> print('first')) ; at_exit(fn() -> print('second')) ; print('main')", "main\nsecond\nfirst\n", 0); }
+    #[test] fn test_at_exit_after_exit() { run_str_with_exit_code("at_exit(fn() -> print('cleanup')) ; exit(2) ; print('unreachable')", "cleanup\n", 2); }
+    #[test] fn test_at_exit_after_error() { run_str("at_exit(fn() -> print('cleanup')) ; fn f(x) -> 1 / x ; f(0)", "cleanup\nValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(x)` (line 1)\n\n1 | at_exit(fn() -> print('cleanup')) ; fn f(x) -> 1 / x ; f(0)\n2 |                                                  ^\n"); }
+    #[test] fn test_at_exit_handler_calls_exit() { run_str_with_exit_code("at_exit(fn() -> print('first')) ; at_exit(fn() -> exit(4)) ; print('main')", "main\nfirst\n", 4); }
+    #[test] fn test_at_exit_registered_in_handler() { run_str("at_exit(fn() { print('outer') ; at_exit(fn() -> print('inner')) })", "outer\ninner\n"); }
+    #[test] fn test_at_exit_not_function() { run_str("at_exit(1)", "TypeError: Expected '1' of type 'int' to be a function\n  at: line 1 (<test>)\n\n1 | at_exit(1)\n2 |        ^^^\n"); }
+    #[test] fn test_color() { run_str("color('red', 'abc') . repr . print", "'\\u{1b}[31mabc\\u{1b}[0m'\n"); }
+    #[test] fn test_color_partial_and_non_str() { run_str("123 . color('bright_blue') . repr . print", "'\\u{1b}[94m123\\u{1b}[0m'\n"); }
+    #[test] fn test_color_invalid() { run_str("color('purple', 'abc')", "ValueError: Unknown color or style 'purple' of type 'str'\n  at: line 1 (<test>)\n\n1 | color('purple', 'abc')\n2 |      ^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_clear_screen() { run_str("clear_screen() ; print('a')", "\x1b[2J\x1b[Ha\n"); }
+    #[test] fn test_term_size_not_interactive() { run_str("term_size()", "ValueError: 'fn term_size()' requires an interactive terminal\n  at: line 1 (<test>)\n\n1 | term_size()\n2 |          ^^\n"); }
+    #[test] fn test_read_key_not_interactive() { run_str("read_key()", "ValueError: 'fn read_key()' requires an interactive terminal\n  at: line 1 (<test>)\n\n1 | read_key()\n2 |         ^^\n"); }
+
+    #[test] fn test_timeit() { run_str("let n = 0 ; let t = timeit(fn() -> n += 1, 5) ; print(n, t is int, t >= 0)", "5 true true\n"); }
+    #[test] fn test_timeit_default_once() { run_str("let n = 0 ; timeit(fn() -> n += 1) ; print(n)", "1\n"); }
+    #[test] fn test_timeit_zero() { run_str("timeit(fn() -> print('no'), 0) ; print('yes')", "yes\n"); }
+    #[test] fn test_timeit_negative() { run_str("timeit(fn() -> nil, -1)", "ValueError: Expected value '-1: int' to be non-negative\n  at: line 1 (<test>)\n\n1 | timeit(fn() -> nil, -1)\n2 |       ^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_timeit_not_function() { run_str("timeit(1)", "TypeError: Expected '1' of type 'int' to be a function\n  at: line 1 (<test>)\n\n1 | timeit(1)\n2 |       ^^^\n"); }
+    #[test] fn test_stopwatch() { run_str("let s = stopwatch() ; let a = lap(s), b = elapsed(s) ; print(s is stopwatch, typeof s, a >= 0, b >= a)", "true stopwatch true true\n"); }
+    #[test] fn test_stopwatch_lap_is_less_than_elapsed() { run_str("let s = stopwatch() ; for _ in range(20) {} ; lap(s) ; let a = lap(s) ; print(a <= elapsed(s))", "true\n"); }
+    #[test] fn test_lap_not_stopwatch() { run_str("lap(1)", "TypeError: Expected '1' of type 'int' to be a stopwatch\n  at: line 1 (<test>)\n\n1 | lap(1)\n2 |    ^^^\n"); }
+    #[test] fn test_progress() { run_str("progress([1, 2, 3]) . map(*2) . print", "[2, 4, 6]\n"); }
+    #[test] fn test_progress_with_total() { run_str("for x in progress(range(3), 10) { print(x) }", "0\n1\n2\n"); }
+    #[test] fn test_progress_infinite() { run_str("progress(repeat(1)) . take(2) . print", "[1, 1]\n"); }
+    #[test] fn test_progress_repr_and_len() { run_str("let p = progress('abc', 5) ; print(repr(p), len(p), typeof p, reverse(p))", "progress('abc', 5) 3 progress ['c', 'b', 'a']\n"); }
+    #[test] fn test_progress_not_iterable() { run_str("progress(1)", "TypeError: Expected '1' of type 'int' to be an iterable\n  at: line 1 (<test>)\n\n1 | progress(1)\n2 |         ^^^\n"); }
+
+    #[test]
+    fn test_terminal() {
+        struct MockTerminal;
+        impl Terminal for MockTerminal {
+            fn size(&self) -> io::Result<(u16, u16)> { Ok((80, 24)) }
+            fn read_key(&mut self) -> io::Result<String> { Ok(String::from("ctrl+c")) }
+        }
+
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("print(term_size(), read_key())"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]).with_terminal(Box::new(MockTerminal));
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        assert_eq!(String::from_utf8(buf).unwrap().as_str(), "(80, 24) ctrl+c\n");
+    }
+
+    #[test] fn test_log_debug_is_disabled_by_default() { run_str("log_debug('hidden') ; log_level() . print", "info\n"); }
+    #[test] fn test_log_level_returns_previous() { run_str("log_level('warn') . print ; log_level() . print", "info\nwarn\n"); }
+    #[test] fn test_log_level_invalid() { run_str("log_level('loud')", "ValueError: Unknown log level 'loud' of type 'str', must be one of 'debug', 'info', 'warn', or 'error'\n  at: line 1 (<test>)\n\n1 | log_level('loud')\n2 |          ^^^^^^^^\n"); }
+    #[test] fn test_log_info_fields_not_dict() { run_str("log_info('msg', [1, 2])", "TypeError: Expected '[1, 2]' of type 'list' to be a dict\n  at: line 1 (<test>)\n\n1 | log_info('msg', [1, 2])\n2 |         ^^^^^^^^^^^^^^^\n"); }
+
+    #[test]
+    fn test_log_sink() {
+        struct MockLogSink(Rc<RefCell<Vec<String>>>);
+        impl LogSink for MockLogSink {
+            fn log(&mut self, record: LogRecord) {
+                self.0.borrow_mut().push(format!("{:?} {}:{:?} {} {:?}", record.level, record.source, record.lineno, record.message, record.fields));
+            }
+        }
+
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("log_debug('a')\nlog_info('b', {'x': 1, 'y': 'z'})\nlog_level('debug')\nlog_debug(123)\nlog_level('error')\nlog_warn('c')\nlog_error('d')"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let records: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).with_log_sink(Box::new(MockLogSink(records.clone())));
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        assert_eq!(records.borrow().clone(), vec![
+            String::from("Info <test>:Some(2) b [(\"x\", \"1\"), (\"y\", \"'z'\")]"),
+            String::from("Debug <test>:Some(4) 123 []"),
+            String::from("Error <test>:Some(7) d []"),
+        ]);
+    }
+
+    #[test]
+    fn test_tracer() {
+        #[derive(Clone, Default)]
+        struct MockTraceSink(Rc<RefCell<Vec<u8>>>);
+        impl std::io::Write for MockTraceSink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.borrow_mut().write(buf) }
+            fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+        }
+
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("fn f(x) -> x + 1\nf(2)"));
+        let compile = compiler::compile(false, &view).unwrap();
+        let sink: MockTraceSink = MockTraceSink::default();
+        let events: TraceEvents = TraceEvents::parse("ops,stack,calls").unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).with_tracer(Tracer::new(events, Box::new(sink.clone())));
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        let trace: String = String::from_utf8(sink.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines.len(), 25);
+        assert_eq!(lines[8..11].to_vec(), vec![
+            "{\"event\":\"op\",\"ip\":4,\"line\":2,\"op\":\"Call(1, false)\",\"depth\":3}",
+            "{\"event\":\"call\",\"ip\":4,\"line\":2,\"function\":\"fn f(x)\",\"nargs\":1,\"frames\":1}",
+            "{\"event\":\"stack\",\"ip\":4,\"stack\":[\"fn f(x)\",\"fn f(x)\",\"2\"]}",
+        ]);
+        assert_eq!(lines[18], "{\"event\":\"return\",\"ip\":11,\"line\":1,\"frames\":1}");
+    }
+
+    #[test]
+    fn test_step() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("fn f(x) -> x * 2\nprint(f(1 + 2))"));
+        let compile = compiler::compile(false, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]);
+
+        let mut snapshots: Vec<Snapshot> = vec![vm.snapshot()];
+        let exit: ExitType = loop {
+            match vm.step() {
+                Step::Next(snapshot) => snapshots.push(snapshot),
+                Step::Exit(exit) => break exit,
+            }
+        };
+        assert_eq!(snapshots.len(), 16);
+        assert_eq!(snapshots[7], Snapshot { ip: 7, line: Some(2), op: Some(Opcode::Call(1, false)), frames: 1, depth: 4, stack: vec![String::from("3"), String::from("fn f(x)"), String::from("fn print(...)"), String::from("fn f(x)")] });
+        assert_eq!(snapshots[8], Snapshot { ip: 12, line: Some(1), op: Some(Opcode::PushLocal(0)), frames: 2, depth: 4, stack: vec![String::from("3"), String::from("fn f(x)"), String::from("fn print(...)"), String::from("fn f(x)")] });
+        assert!(matches!(exit, ExitType::Exit(0)));
+        assert_eq!(String::from_utf8(buf).unwrap(), "6\n");
+    }
+
+    #[test]
+    fn test_step_until_error() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("let x = [] ; x[0]"));
+        let compile = compiler::compile(false, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]);
+
+        let mut steps: usize = 0;
+        let exit: ExitType = loop {
+            match vm.step() {
+                Step::Next(_) => steps += 1,
+                Step::Exit(exit) => break exit,
+            }
+        };
+        assert_eq!(steps, 5);
+        assert!(matches!(exit, ExitType::Error(_)));
+    }
+
+    #[test]
+    fn test_log_to_error_stream() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("log_warn('careful')"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], &mut buf, vec![]).with_log_level(LogLevel::Warn);
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        let text: String = String::from_utf8(buf).unwrap();
+        assert!(text.ends_with("Z WARN  <test>:1 careful\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_log_record_format() {
+        let record: LogRecord = LogRecord {
+            level: LogLevel::Info,
+            message: String::from("hello"),
+            fields: vec![(String::from("n"), String::from("3")), (String::from("s"), String::from("'x'"))],
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            source: String::from("main.cor"),
+            lineno: Some(12),
+        };
+        assert_eq!(record.format(), "2023-11-14T22:13:20.123Z INFO  main.cor:12 hello n=3 s='x'");
+    }
+
+    #[cfg(feature = "entropy")] #[test] fn test_uuid4() { run_str("let u = uuid4() ; print(u . len, u[14], u . split('-') . map(len))", "36 4 [8, 4, 4, 4, 12]\n"); }
+    #[cfg(feature = "entropy")] #[test] fn test_uuid4_is_unique() { run_str("range(100) . map(fn(x) -> uuid4()) . set . len . print", "100\n"); }
+    #[cfg(feature = "entropy")] #[test] fn test_rand_bytes() { run_str("let b = rand_bytes(100) ; print(b . len, b . min >= 0, b . max < 256)", "100 true true\n"); }
+    #[cfg(feature = "entropy")] #[test] fn test_rand_hex() { run_str("let h = rand_hex(10) ; print(h . len, h . all(fn(c) -> c in '0123456789abcdef'))", "20 true\n"); }
+    #[cfg(feature = "entropy")] #[test] fn test_rand_hex_empty() { run_str("rand_hex(0) . repr . print", "''\n"); }
+    #[cfg(feature = "entropy")] #[test] fn test_rand_bytes_too_large() { run_str("rand_bytes(4611686018427387903)", "ValueError: Cannot reserve capacity for 4611686018427387903 elements\n  at: line 1 (<test>)\n\n1 | rand_bytes(4611686018427387903)\n2 |           ^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(feature = "entropy")] #[test] fn test_rand_bytes_negative() { run_str("rand_bytes(-1)", "ValueError: Expected value '-1: int' to be non-negative\n  at: line 1 (<test>)\n\n1 | rand_bytes(-1)\n2 |           ^^^^\n"); }
+    #[cfg(not(feature = "entropy"))] #[test] fn test_uuid4_not_enabled() { run_str("uuid4()", "ValueError: Cordy was not compiled with the 'entropy' feature enabled\n  at: line 1 (<test>)\n\n1 | uuid4()\n2 |      ^^\n"); }
+
+    #[test]
+    fn test_entropy() {
+        struct MockEntropy(u8);
+        impl Entropy for MockEntropy {
+            fn fill(&mut self, buf: &mut [u8]) -> io::Result<()> {
+                for b in buf {
+                    *b = self.0;
+                    self.0 = self.0.wrapping_add(0x11);
+                }
+                Ok(())
+            }
+        }
+
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("print(uuid4(), rand_hex(3), rand_bytes(3))"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]).with_entropy(Box::new(MockEntropy(0)));
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        assert_eq!(String::from_utf8(buf).unwrap().as_str(), "00112233-4455-4677-8899-aabbccddeeff 102132 [67, 84, 101]\n");
+    }
+
+    #[cfg(feature = "net")] #[test] fn test_tcp_send_and_recv_line() { run_str("let l = tcp_listen(0) ; let c = tcp_connect('127.0.0.1', local_addr(l)[1]) ; let s = accept(l) ; send(c, 'hello\\nworld\\n') ; print(recv_line(s), recv_line(s)) ; send(s, 'reply\\r\\n') ; recv_line(c) . print", "hello world\nreply\n"); }
+    #[cfg(feature = "net")] #[test] fn test_tcp_recv_line_after_peer_closed() { run_str("let l = tcp_listen(0) ; let c = tcp_connect('127.0.0.1', local_addr(l)[1]) ; let s = accept(l) ; send(c, 'last') ; close(c) ; print(recv_line(s), recv_line(s))", "last nil\n"); }
+    #[cfg(feature = "net")] #[test] fn test_tcp_types() { run_str("let l = tcp_listen(0) ; let c = tcp_connect('127.0.0.1', local_addr(l)[1]) ; print(typeof(c), typeof(l), c is tcp_connect, c is tcp_listen, l is tcp_listen, c == c, c == accept(l))", "tcp_connect tcp_listen true false true true false\n"); }
+    #[cfg(feature = "sqlite")] #[test] fn test_db_exec_and_query() { run_str("let db = db_open(':memory:') ; db_exec(db, 'create table t (a, b)') ; print(db_exec(db, 'insert into t values (?, ?), (?, ?)', [1, 'x', nil, true])) ; db_query(db, 'select * from t') . print", "2\n[{'a': 1, 'b': 'x'}, {'a': nil, 'b': 1}]\n"); }
+    #[cfg(feature = "sqlite")] #[test] fn test_db_named_params() { run_str("let db = db_open(':memory:') ; db_exec(db, 'create table t (a)') ; db_exec(db, 'insert into t values (:a)', {'a': 5}) ; db_query(db, 'select a from t where a = :a', {':a': 5}) . print", "[{'a': 5}]\n"); }
+    #[cfg(feature = "sqlite")] #[test] fn test_db_real_and_blob() { run_str("let db = db_open(':memory:') ; db_query(db, 'select ? as r, x\\'0aff\\' as b', [fraction(3, 4)]) . print", "[{'r': fraction(3, 4), 'b': [10, 255]}]\n"); }
+    #[cfg(feature = "sqlite")] #[test] fn test_db_types() { run_str("let db = db_open(':memory:') ; print(db, typeof db, db is db_open, db == db)", "database(:memory:) db_open true true\n"); }
+    #[cfg(feature = "sqlite")] #[test] fn test_db_syntax_error() { run_str("db_exec(db_open(':memory:'), 'bad')", "ValueError: Database error: near \"bad\": syntax error in bad at offset 0\n  at: line 1 (<test>)\n\n1 | db_exec(db_open(':memory:'), 'bad')\n2 |        ^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(feature = "sqlite")] #[test] fn test_db_invalid_param() { run_str("db_exec(db_open(':memory:'), 'select ?', [[1]])", "TypeError: Cannot bind '[1]' of type 'list' as a SQL parameter, must be a nil, bool, int, fraction, or str\n  at: line 1 (<test>)\n\n1 | db_exec(db_open(':memory:'), 'select ?', [[1]])\n2 |        ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(feature = "sqlite")] #[test] fn test_db_closed() { run_str("let db = db_open(':memory:') ; close(db) ; close(db) ; db_query(db, 'select 1')", "ValueError: Cannot use 'database(:memory:)' of type 'database' as it is closed\n  at: line 1 (<test>)\n\n1 | let db = db_open(':memory:') ; close(db) ; close(db) ; db_query(db, 'select 1')\n2 |                                                                ^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(feature = "sqlite")] #[test] fn test_db_query_not_database() { run_str("db_query('db', 'select 1')", "TypeError: Expected 'db' of type 'str' to be a database\n  at: line 1 (<test>)\n\n1 | db_query('db', 'select 1')\n2 |         ^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(feature = "net")] #[test] fn test_tcp_local_addr() { run_str("let l = tcp_listen(0) ; let c = tcp_connect('127.0.0.1', local_addr(l)[1]) ; print(local_addr(l)[0], local_addr(c)[0])", "0.0.0.0 127.0.0.1\n"); }
+    #[cfg(feature = "net")] #[test] fn test_tcp_close_twice() { run_str("let l = tcp_listen(0) ; close(l) ; close(l) . print", "nil\n"); }
+    #[cfg(feature = "net")] #[test] fn test_tcp_send_not_str() { run_str("let l = tcp_listen(0) ; let c = tcp_connect('127.0.0.1', local_addr(l)[1]) ; send(c, 123)", "TypeError: Expected '123' of type 'int' to be a string\n  at: line 1 (<test>)\n\n1 | let l = tcp_listen(0) ; let c = tcp_connect('127.0.0.1', local_addr(l)[1]) ; send(c, 123)\n2 |                                                                                  ^^^^^^^^\n"); }
+    #[cfg(feature = "net")] #[test] fn test_tcp_send_not_stream() { run_str("send('stream', 'hello')", "TypeError: Expected 'stream' of type 'str' to be a tcp stream\n  at: line 1 (<test>)\n\n1 | send('stream', 'hello')\n2 |     ^^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(feature = "net")] #[test] fn test_tcp_listen_invalid_port() { run_str("tcp_listen(65536)", "ValueError: Invalid port 65536, must be in the range [0, 65536)\n  at: line 1 (<test>)\n\n1 | tcp_listen(65536)\n2 |           ^^^^^^^\n"); }
+    #[cfg(feature = "net")] #[test] fn test_http_get_async_invalid_url() { run_str("http_get_async('https://example.com')", "ValueError: Expected a url of the form 'http://host[:port][/path]', got 'https://example.com'\n  at: line 1 (<test>)\n\n1 | http_get_async('https://example.com')\n2 |               ^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(feature = "net")] #[test] fn test_http_get_async_invalid_port() { run_str("http_get_async('http://localhost:http/')", "ValueError: Expected a url of the form 'http://host[:port][/path]', got 'http://localhost:http/'\n  at: line 1 (<test>)\n\n1 | http_get_async('http://localhost:http/')\n2 |               ^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(not(feature = "net"))] #[test] fn test_http_get_async_not_enabled() { run_str("http_get_async('http://localhost/')", "ValueError: Cordy was not compiled with the 'net' feature enabled\n  at: line 1 (<test>)\n\n1 | http_get_async('http://localhost/')\n2 |               ^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(not(feature = "net"))] #[test] fn test_tcp_not_enabled() { run_str("tcp_listen(0)", "ValueError: Cordy was not compiled with the 'net' feature enabled\n  at: line 1 (<test>)\n\n1 | tcp_listen(0)\n2 |           ^^^\n"); }
+
+    #[test] fn test_coprocess_send_and_recv_line() { run_str("let p = coprocess('cat') ; send_line(p, 'hello') ; send_line(p, 'world') ; print(recv_line(p), recv_line(p)) ; close(p) . print", "hello world\n0\n"); }
+    #[test] fn test_coprocess_with_args() { run_str("let p = coprocess(['sh', '-c', 'read x ; echo $((x * 2))']) ; send_line(p, '21') ; print(recv_line(p), recv_line(p))", "42 nil\n"); }
+    #[test] fn test_coprocess_exit_code() { run_str("coprocess(['sh', '-c', 'exit 3']) . close . print", "3\n"); }
+    #[test] fn test_coprocess_close_kills() { run_str("let p = coprocess('sleep 5') ; print(close(p), close(p))", "nil nil\n"); }
+    #[test] fn test_coprocess_types() { run_str("let p = coprocess('cat') ; print(typeof(p), p is coprocess, p == p, p == coprocess('cat'), repr(p))", "coprocess true true false coprocess('cat')\n"); }
+    #[test] fn test_coprocess_timeout() { run_str("coprocess('sleep 5', 20) . recv_line", "ValueError: Timed out waiting for a response from 'coprocess('sleep 5')' of type 'coprocess'\n  at: line 1 (<test>)\n\n1 | coprocess('sleep 5', 20) . recv_line\n2 |                          ^^^^^^^^^^^\n"); }
+    #[test] fn test_coprocess_send_line_after_close() { run_str("let p = coprocess('cat') ; close(p) ; send_line(p, 'hello')", "ValueError: Cannot use 'coprocess('cat')' of type 'coprocess' as it is closed\n  at: line 1 (<test>)\n\n1 | let p = coprocess('cat') ; close(p) ; send_line(p, 'hello')\n2 |                                                ^^^^^^^^^^^^\n"); }
+    #[test] fn test_coprocess_empty() { run_str("coprocess([])", "ValueError: Expected value to be a non empty iterable\n  at: line 1 (<test>)\n\n1 | coprocess([])\n2 |          ^^^^\n"); }
+    #[test] fn test_coprocess_not_found() { run_str("coprocess('not_a_real_program')", "IOError: No such file or directory (os error 2)\n  at: line 1 (<test>)\n\n1 | coprocess('not_a_real_program')\n2 |          ^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_recv_line_not_stream() { run_str("recv_line(123)", "TypeError: Expected '123' of type 'int' to be a tcp stream or coprocess\n  at: line 1 (<test>)\n\n1 | recv_line(123)\n2 |          ^^^^^\n"); }
+    #[test] fn test_close_not_handle() { run_str("close('file')", "TypeError: Expected 'file' of type 'str' to be a socket, coprocess, or database\n  at: line 1 (<test>)\n\n1 | close('file')\n2 |      ^^^^^^^^\n"); }
+
+    #[test] fn test_on_signal_invalid_signal() { run_str("on_signal('KILL', fn() -> nil)", "ValueError: Unknown signal 'KILL' of type 'str', must be one of 'INT' or 'TERM'\n  at: line 1 (<test>)\n\n1 | on_signal('KILL', fn() -> nil)\n2 |          ^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_on_signal_remove_without_handler() { run_str("on_signal('INT', nil) ; print('ok')", "ok\n"); }
+    #[test] fn test_on_signal_not_function() { run_str("on_signal('INT', 123)", "TypeError: Expected '123' of type 'int' to be a function\n  at: line 1 (<test>)\n\n1 | on_signal('INT', 123)\n2 |          ^^^^^^^^^^^^\n"); }
+
+    #[test] fn test_argparse_invalid_spec() { run_str("argparse({'--': false})", "ValueError: Invalid argument name '--' of type 'str' in argparse() spec\n  at: line 1 (<test>)\n\n1 | argparse({'--': false})\n2 |         ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_argparse_help_is_reserved() { run_str("argparse({'--help': false})", "ValueError: Invalid argument name '--help' of type 'str' in argparse() spec\n  at: line 1 (<test>)\n\n1 | argparse({'--help': false})\n2 |         ^^^^^^^^^^^^^^^^^^^\n"); }
+
+    #[test] fn test_marshal_int() { run_str("marshal(1) . print", "[67, 77, 1, 3, 2]\n"); }
+    #[test] fn test_marshal_negative_int() { run_str("marshal(-65) . print", "[67, 77, 1, 3, 129, 1]\n"); }
+    #[test] fn test_marshal_str() { run_str("marshal('ab') . print", "[67, 77, 1, 4, 2, 97, 98]\n"); }
+    #[test] fn test_marshal_round_trip() { run_str("let x = [nil, true, false, 0, -1, 123456789, 'abc', (1, 2), {3, 4}, {'a': [5]}, [], ''] ; let y = x . marshal . unmarshal ; print(y, y == x)", "[nil, true, false, 0, -1, 123456789, 'abc', (1, 2), {3, 4}, {'a': [5]}, [], ''] true\n"); }
+    #[test] fn test_marshal_round_trip_int_limits() { run_str("let x = [int.min, int.max] ; print(x . marshal . unmarshal == x)", "true\n"); }
+    #[test] fn test_marshal_round_trip_int_keys() { run_str("{1: 'a', '1': 'b'} . marshal . unmarshal . print", "{1: 'a', '1': 'b'}\n"); }
+    #[test] fn test_marshal_round_trip_heap() { run_str("heap([3, 1, 2]) . marshal . unmarshal . pop . print", "1\n"); }
+    #[test] fn test_marshal_round_trip_struct() { run_str("struct Point(x, y) ; let p = Point(1, [Point(2, 3)]) ; let q = p . marshal . unmarshal ; print(q, q is Point, q == p)", "Point(x=1, y=[Point(x=2, y=3)]) true true\n"); }
+    #[test] fn test_marshal_function() { run_str("marshal([print])", "TypeError: Cannot marshal 'print' of type 'native function'\n  at: line 1 (<test>)\n\n1 | marshal([print])\n2 |        ^^^^^^^^^\n"); }
+    #[test] fn test_marshal_recursive() { run_str("let x = [] ; x.push(x) ; marshal(x)", "ValueError: Cannot marshal recursive collection '[[...]]' of type 'list'\n  at: line 1 (<test>)\n\n1 | let x = [] ; x.push(x) ; marshal(x)\n2 |                                 ^^^\n"); }
+    #[test] fn test_unmarshal_not_marshalled() { run_str("unmarshal([1, 2, 3])", "ValueError: Cannot unmarshal: not a marshalled value\n  at: line 1 (<test>)\n\n1 | unmarshal([1, 2, 3])\n2 |          ^^^^^^^^^^^\n"); }
+    #[test] fn test_unmarshal_truncated() { run_str("marshal('abc')[:-1] . unmarshal", "ValueError: Cannot unmarshal: unexpected end of input\n  at: line 1 (<test>)\n\n1 | marshal('abc')[:-1] . unmarshal\n2 |                     ^^^^^^^^^^^\n"); }
+    #[test] fn test_unmarshal_trailing_bytes() { run_str("marshal(1) + [0] . unmarshal", "ValueError: Cannot unmarshal: unexpected trailing bytes at offset 5\n  at: line 1 (<test>)\n\n1 | marshal(1) + [0] . unmarshal\n2 |                  ^^^^^^^^^^^\n"); }
+    #[test] fn test_unmarshal_unknown_struct() { run_str("struct A(x) ; let b = marshal(A(1)) ; unmarshal(b[:4] + [1, 66] + b[6:])", "ValueError: Cannot unmarshal: no struct B(x) is declared\n  at: line 1 (<test>)\n\n1 | struct A(x) ; let b = marshal(A(1)) ; unmarshal(b[:4] + [1, 66] + b[6:])\n2 |                                                ^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_unmarshal_invalid_byte() { run_str("unmarshal([256])", "ValueError: Cannot write int 256 as a byte, must be in the range [0, 256)\n  at: line 1 (<test>)\n\n1 | unmarshal([256])\n2 |          ^^^^^^^\n"); }
+    #[test] fn test_pack_ints() { run_str("pack('<bBhHiI', [-1, 255, -2, 258, -3, 65536]) . print", "[255, 255, 254, 255, 2, 1, 253, 255, 255, 255, 0, 0, 1, 0]\n"); }
+    #[test] fn test_pack_big_endian() { run_str("pack('>hI', [-2, 258]) . print ; pack('!H', [1]) . print", "[255, 254, 0, 0, 1, 2]\n[0, 1]\n"); }
+    #[test] fn test_pack_q() { run_str("pack('>qQ', [-2, 1]) . print", "[255, 255, 255, 255, 255, 255, 255, 254, 0, 0, 0, 0, 0, 0, 0, 1]\n"); }
+    #[test] fn test_pack_repeat_pad_bool_str() { run_str("pack('3B x ? 4s', [1, 2, 3, true, 'ab']) . print", "[1, 2, 3, 0, 1, 97, 98, 0, 0]\n"); }
+    #[test] fn test_pack_unpack_round_trip() { run_str("let f = '>2h I 5s ? x Q' ; let v = (-300, 7, 4000000000, 'héé', false, 1 << 40) ; print(unpack(f, pack(f, v)), unpack(f, pack(f, v)) == v)", "(-300, 7, 4000000000, 'héé', false, 1099511627776) true\n"); }
+    #[test] fn test_unpack_ints() { run_str("unpack('<bBhHiI', [255, 255, 254, 255, 2, 1, 253, 255, 255, 255, 0, 0, 1, 0]) . print", "(-1, 255, -2, 258, -3, 65536)\n"); }
+    #[test] fn test_unpack_empty() { run_str("unpack('', []) . print ; pack('>', []) . print", "()\n[]\n"); }
+    #[test] fn test_pack_out_of_range() { run_str("pack('b', [128])", "ValueError: Cannot pack: 128 does not fit in a 8-bit signed integer\n  at: line 1 (<test>)\n\n1 | pack('b', [128])\n2 |     ^^^^^^^^^^^^\n"); }
+    #[test] fn test_pack_negative_unsigned() { run_str("pack('Q', [-1])", "ValueError: Cannot pack: -1 does not fit in a 64-bit unsigned integer\n  at: line 1 (<test>)\n\n1 | pack('Q', [-1])\n2 |     ^^^^^^^^^^^\n"); }
+    #[test] fn test_pack_wrong_number_of_values() { run_str("pack('2Bx', [1])", "ValueError: Cannot pack: expected 2 values, got 1\n  at: line 1 (<test>)\n\n1 | pack('2Bx', [1])\n2 |     ^^^^^^^^^^^^\n"); }
+    #[test] fn test_pack_str_too_long() { run_str("pack('2s', ['abc'])", "ValueError: Cannot pack: 'abc' is longer than 2 bytes\n  at: line 1 (<test>)\n\n1 | pack('2s', ['abc'])\n2 |     ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_pack_not_int() { run_str("pack('B', ['a'])", "TypeError: Expected 'a' of type 'str' to be a int\n  at: line 1 (<test>)\n\n1 | pack('B', ['a'])\n2 |     ^^^^^^^^^^^^\n"); }
+    #[test] fn test_pack_invalid_format() { run_str("pack('<Bz', [1, 2])", "ValueError: Invalid character 'z' in pack format '<Bz'\n  at: line 1 (<test>)\n\n1 | pack('<Bz', [1, 2])\n2 |     ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_pack_format_missing_field() { run_str("pack('B3', [1])", "ValueError: Expected a field after the count in pack format 'B3'\n  at: line 1 (<test>)\n\n1 | pack('B3', [1])\n2 |     ^^^^^^^^^^^\n"); }
+    #[test] fn test_unpack_wrong_length() { run_str("unpack('H', [1])", "ValueError: Cannot unpack: expected 2 bytes, got 1\n  at: line 1 (<test>)\n\n1 | unpack('H', [1])\n2 |       ^^^^^^^^^^\n"); }
+    #[test] fn test_unpack_too_large() { run_str("unpack('>Q', [255, 0, 0, 0, 0, 0, 0, 0])", "ValueError: Cannot unpack: integer at offset 0 is too large to be an int\n  at: line 1 (<test>)\n\n1 | unpack('>Q', [255, 0, 0, 0, 0, 0, 0, 0])\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_unpack_invalid_utf8() { run_str("unpack('B2s', [0, 255, 0])", "ValueError: Cannot unpack: invalid UTF-8 in string at offset 1\n  at: line 1 (<test>)\n\n1 | unpack('B2s', [0, 255, 0])\n2 |       ^^^^^^^^^^^^^^^^^^^^\n"); }
+
+    #[test] fn test_gen_repr() { run_str("print(gen_int(0, 10), gen_list(gen_int(-1, 1), 3), gen_str('ab', 2), typeof(gen_int(0, 0)), gen_int(0, 1) is forall)", "gen_int(0, 10) gen_list(gen_int(-1, 1), 3) gen_str('ab', 2) forall true\n"); }
+    #[test] fn test_gen_int_empty_range() { run_str("gen_int(3, 2)", "ValueError: Range [3, 2] is empty, as 3 > 2\n  at: line 1 (<test>)\n\n1 | gen_int(3, 2)\n2 |        ^^^^^^\n"); }
+    #[test] fn test_gen_list_not_generator() { run_str("gen_list([1, 2], 3)", "TypeError: Expected '[1, 2]' of type 'list' to be a generator\n  at: line 1 (<test>)\n\n1 | gen_list([1, 2], 3)\n2 |         ^^^^^^^^^^^\n"); }
+    #[test] fn test_gen_str_empty_alphabet() { run_str("gen_str('', 3)", "ValueError: Expected value to be a non empty iterable\n  at: line 1 (<test>)\n\n1 | gen_str('', 3)\n2 |        ^^^^^^^\n"); }
+    #[test] fn test_forall_passes() { run_str("forall(gen_list(gen_int(0, 9), 5), fn(xs) -> len(xs) <= 5) . print", "nil\n"); }
+    #[test] fn test_forall_no_cases() { run_str("forall(gen_int(0, 9), fn(x) -> false, 0) . print", "nil\n"); }
+
+    #[test]
+    fn test_forall_shrinks_int() {
+        run_property("forall(gen_int(0, 1000), fn(x) -> x < 10)", "ValueError: Property failed after 1 case (seed = 0), with input 10\n  because: returned false\n  at: line 1 (<test>)\n\n1 | forall(gen_int(0, 1000), fn(x) -> x < 10)\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n");
+    }
+
+    #[test]
+    fn test_forall_shrinks_list() {
+        run_property("forall(gen_list(gen_int(-100, 100), 20), fn(xs) -> len(xs) < 3)", "ValueError: Property failed after 1 case (seed = 0), with input [0, 0, 0]\n  because: returned false\n  at: line 1 (<test>)\n\n1 | forall(gen_list(gen_int(-100, 100), 20), fn(xs) -> len(xs) < 3)\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n");
+    }
+
+    #[test]
+    fn test_forall_shrinks_str() {
+        run_property("forall(gen_str('abc', 10), fn(s) -> 'c' not in s)", "ValueError: Property failed after 3 cases (seed = 0), with input 'c'\n  because: returned false\n  at: line 1 (<test>)\n\n1 | forall(gen_str('abc', 10), fn(s) -> 'c' not in s)\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n");
+    }
+
+    #[test]
+    fn test_forall_catches_error() {
+        run_property("forall(gen_int(-50, 50), fn(x) { assert x != 7 ; true }, 1000)", "ValueError: Property failed after 189 cases (seed = 0), with input 7\n  because: Assertion Failed: nil\n  at: line 1 (<test>)\n\n1 | forall(gen_int(-50, 50), fn(x) { assert x != 7 ; true }, 1000)\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n");
+    }
+
+    #[test]
+    fn test_forall_shrinks_nested_error() {
+        run_property("fn g(y) { assert y[0] < 3 : 'too big' ; true } ; fn f(x) { let y = [x] ; g(y) } ; print('before') ; forall(gen_int(0, 100), f)", "before\nValueError: Property failed after 1 case (seed = 0), with input 3\n  because: Assertion Failed: too big\n  at: line 1 (<test>)\n\n1 | fn g(y) { assert y[0] < 3 : 'too big' ; true } ; fn f(x) { let y = [x] ; g(y) } ; print('before') ; forall(gen_int(0, 100), f)\n2 |                                                                                                           ^^^^^^^^^^^^^^^^^^^^\n");
+    }
+
+    #[test] fn test_weak_get() { run_str("let x = [1, 2] ; let w = weak(x) ; w . get . print", "[1, 2]\n"); }
+    #[test] fn test_weak_get_after_drop() { run_str("let x = [1, 2] ; let w = weak(x) ; x = nil ; w . get . print", "nil\n"); }
+    #[test] fn test_weak_get_temporary() { run_str("weak([1, 2]) . get . print", "nil\n"); }
+    #[test] fn test_weak_get_is_strong() { run_str("let x = [1, 2] ; let w = weak(x) ; let y = w . get ; x = nil ; w . get . print", "[1, 2]\n"); }
+    #[test] fn test_weak_repr() { run_str("let x = [1, 2] ; let w = weak(x) ; w . print ; x = nil ; w . print", "weak([1, 2])\nweak(nil)\n"); }
+    #[test] fn test_weak_typeof() { run_str("let x = weak('hello') ; typeof(x) . print ; x is weak . print", "weak\ntrue\n"); }
+    #[test] fn test_weak_equality() { run_str("let x = [1] ; let a = weak(x) ; let b = weak(x) ; (a == a, a == b) . print", "(true, false)\n"); }
+    #[test] fn test_weak_of_int() { run_str("weak(123)", "TypeError: Expected '123' of type 'int' to be a reference type, such as a list, dict, or function\n  at: line 1 (<test>)\n\n1 | weak(123)\n2 |     ^^^^^\n"); }
+    #[test] fn test_weak_get_not_weak() { run_str("get([1])", "TypeError: Expected '[1]' of type 'list' to be a weak reference\n  at: line 1 (<test>)\n\n1 | get([1])\n2 |    ^^^^^\n"); }
+    #[test] fn test_weak_cache() { run_str("let cache = dict() ; fn load(k) { let v = [k] ; cache[k] = weak(v) ; v } ; let a = load(1) ; load(2) ; cache . map(fn((k, w)) -> (k, w . get)) . print", "[(1, [1]), (2, nil)]\n"); }
+    #[test] fn test_on_drop() { run_str("let x = [1] ; on_drop(x, fn() -> print('dropped')) ; print('before') ; x = nil ; print('after')", "before\ndropped\nafter\n"); }
+    #[test] fn test_on_drop_in_order() { run_str("let x = [1] ; on_drop(x, fn() -> print('first')) ; on_drop(x, fn() -> print('second')) ; x = nil", "first\nsecond\n"); }
+    #[test] fn test_on_drop_at_exit() { run_str("let x = [1] ; on_drop(x, fn() -> print('dropped')) ; print('end')", "end\ndropped\n"); }
+    #[test] fn test_on_drop_with_weak() { run_str("let x = [1] ; let w = weak(x) ; on_drop(x, fn() -> print(w . get)) ; x = nil", "nil\n"); }
+    #[test] fn test_on_drop_not_function() { run_str("on_drop([1], 2)", "TypeError: Expected '2' of type 'int' to be a function\n  at: line 1 (<test>)\n\n1 | on_drop([1], 2)\n2 |        ^^^^^^^^\n"); }
+
+    #[test] fn test_memstats_keys() { run_str("memstats() . keys . print", "{'count', 'bytes', 'types', 'stack', 'frames'}\n"); }
+    #[test] fn test_memstats_counts_lists() { run_str("let a = memstats()['types']['list']['count'] ; let x = [[], []] ; (memstats()['types']['list']['count'] - a) . print", "3\n"); }
+    #[test] fn test_memstats_counts_freed() { run_str("let x = [[], []] ; let a = memstats()['count'] ; x = nil ; (a - memstats()['count']) . print", "3\n"); }
+    #[test] fn test_memstats_str_bytes() { run_str("let n = 1000 ; let a = memstats()['types']['str']['bytes'] ; let s = 'a' * n ; (memstats()['types']['str']['bytes'] - a >= 1000) . print", "true\n"); }
+    #[test] fn test_memstats_closures() { run_str("fn f(x) -> fn() -> x ; let g = f(1) ; memstats()['types']['closure']['count'] . print", "1\n"); }
+    #[test] fn test_memstats_frames() { run_str("fn f() -> memstats()['frames'] ; fn g() -> f() ; (memstats()['frames'], f(), g()) . print", "(1, 2, 3)\n"); }
+    #[test] fn test_try_int() { run_str("['12', '-3', 'abc', '', nil, true, 7 / 2, [1]] . map(try_int) . print", "[12, -3, nil, nil, 0, 1, 3, nil]\n"); }
+    #[test] fn test_try_int_filter() { run_str("'12 x 7' . split(' ') . map(try_int) . filter(fn(x) -> x != nil) . print", "[12, 7]\n"); }
+    #[test] fn test_parse_int() { run_str("['12', '-3', 'abc', ''] . map(fn(x) -> parse_int(x, -1)) . print", "[12, -3, -1, -1]\n"); }
+    #[test] fn test_parse_int_not_str() { run_str("parse_int(12, 0)", "TypeError: Expected '12' of type 'int' to be a string\n  at: line 1 (<test>)\n\n1 | parse_int(12, 0)\n2 |          ^^^^^^^\n"); }
+    #[test] fn test_int_with_default_not_str() { run_str("int([1], 0)", "TypeError: Cannot convert '[1]' of type 'list' to an int\n  at: line 1 (<test>)\n\n1 | int([1], 0)\n2 |    ^^^^^^^^\n"); }
+    #[test] fn test_assert_eq() { run_str("assert_eq(1 + 2, 3) . print", "nil\n"); }
+    #[test] fn test_assert_eq_fail() { run_str("assert_eq(1 + 2, 4)", "Assertion Failed: 3 != 4\n  at: line 1 (<test>)\n\n1 | assert_eq(1 + 2, 4)\n2 |          ^^^^^^^^^^\n"); }
+    #[test] fn test_assert_eq_fail_list() { run_str("assert_eq([1, 2, 3], [1, 5, 3])", "Assertion Failed: [1, 2, 3] != [1, 5, 3], first difference at index 1: 2 != 5\n  at: line 1 (<test>)\n\n1 | assert_eq([1, 2, 3], [1, 5, 3])\n2 |          ^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_assert_eq_fail_list_length() { run_str("assert_eq([1, 2, 3], [1, 2])", "Assertion Failed: [1, 2, 3] != [1, 2], lengths differ: 3 != 2\n  at: line 1 (<test>)\n\n1 | assert_eq([1, 2, 3], [1, 2])\n2 |          ^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_assert_eq_fail_str() { run_str("assert_eq('hello', 'help')", "Assertion Failed: 'hello' != 'help', first difference at index 3: 'l' != 'p'\n  at: line 1 (<test>)\n\n1 | assert_eq('hello', 'help')\n2 |          ^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_assert_eq_fail_dict() { run_str("assert_eq({1: 'a', 2: 'b'}, {1: 'a', 2: 'c'})", "Assertion Failed: {1: 'a', 2: 'b'} != {1: 'a', 2: 'c'}, first difference at key 2: 'b' != 'c'\n  at: line 1 (<test>)\n\n1 | assert_eq({1: 'a', 2: 'b'}, {1: 'a', 2: 'c'})\n2 |          ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_assert_eq_fail_dict_missing_key() { run_str("assert_eq({1: 'a'}, {1: 'a', 2: 'b'})", "Assertion Failed: {1: 'a'} != {1: 'a', 2: 'b'}, key 2 is missing on the left\n  at: line 1 (<test>)\n\n1 | assert_eq({1: 'a'}, {1: 'a', 2: 'b'})\n2 |          ^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_assert_eq_fail_set() { run_str("assert_eq({1, 2}, {1, 3})", "Assertion Failed: {1, 2} != {1, 3}, element 2 is missing on the right\n  at: line 1 (<test>)\n\n1 | assert_eq({1, 2}, {1, 3})\n2 |          ^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_assert_eq_fail_different_types() { run_str("assert_eq([1], (1,))", "Assertion Failed: [1] != (1)\n  at: line 1 (<test>)\n\n1 | assert_eq([1], (1,))\n2 |          ^^^^^^^^^^^\n"); }
+    #[test] fn test_assert_eq_in_forall() { run_str("forall(gen_int(0, 10), fn(x) { assert_eq(x * 2, x + x) ; true }) . print", "nil\n"); }
+    #[test] fn test_assert_err() { run_str("let z = 0 ; assert_err(fn() -> 1 / z) . print", "ValueError: Expected value to be non-zero\n"); }
+    #[test] fn test_assert_err_fail() { run_str("assert_err(fn() -> 1 / 1)", "Assertion Failed: Expected fn _() to raise an error, but it returned 1\n  at: line 1 (<test>)\n\n1 | assert_err(fn() -> 1 / 1)\n2 |           ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_assert_err_then_continue() { run_str("let x = [1] ; assert_err(fn() { x . push(2) ; x[5] }) ; x . print", "[1, 2]\n"); }
+    #[test] fn test_approx_eq() { run_str("let f = fraction ; [approx_eq(1, 1, 0), approx_eq(f(1, 3), f(33, 100), f(1, 100)), approx_eq(f(1, 3), f(3, 10), f(1, 100)), approx_eq(2, 1, 1)] . print", "[true, true, false, true]\n"); }
+    #[test] fn test_approx_eq_not_fraction() { run_str("approx_eq('1', 1, 0)", "TypeError: Expected '1' of type 'str' to be an int or fraction\n  at: line 1 (<test>)\n\n1 | approx_eq('1', 1, 0)\n2 |          ^^^^^^^^^^^\n"); }
+
+    #[test] fn test_trace_default() { run_trace("fn f(n) -> if n == 0 then 1 / n else f(n - 1) ; fn g(x) -> f(x) ; g(6)", TraceOptions::default(), "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  ... above line repeated 1 more time(s) ...\n  at: `fn f(n)` (line 1)\n  at: `fn g(x)` (line 1)\n\n1 | fn f(n) -> if n == 0 then 1 / n else f(n - 1) ; fn g(x) -> f(x) ; g(6)\n2 |                             ^\n"); }
+    #[test] fn test_trace_repeat_limit() { run_trace("fn f(n) -> if n == 0 then 1 / n else f(n - 1) ; fn g(x) -> f(x) ; g(6)", TraceOptions { repeat_limit: 1, ..TraceOptions::default() }, "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  ... above line repeated 3 more time(s) ...\n  at: `fn f(n)` (line 1)\n  at: `fn g(x)` (line 1)\n\n1 | fn f(n) -> if n == 0 then 1 / n else f(n - 1) ; fn g(x) -> f(x) ; g(6)\n2 |                             ^\n"); }
+    #[test] fn test_trace_depth() { run_trace("fn f(n) -> if n == 0 then 1 / n else f(n - 1) ; fn g(x) -> f(x) ; g(6)", TraceOptions { depth: Some(2), ..TraceOptions::default() }, "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  ... 6 more frame(s) omitted ...\n\n1 | fn f(n) -> if n == 0 then 1 / n else f(n - 1) ; fn g(x) -> f(x) ; g(6)\n2 |                             ^\n"); }
+    #[test] fn test_trace_reverse() { run_trace("fn f(n) -> if n == 0 then 1 / n else f(n - 1) ; fn g(x) -> f(x) ; g(6)", TraceOptions { reverse: true, ..TraceOptions::default() }, "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn g(x)` (line 1)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  ... above line repeated 1 more time(s) ...\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n  at: `fn f(n)` (line 1)\n\n1 | fn f(n) -> if n == 0 then 1 / n else f(n - 1) ; fn g(x) -> f(x) ; g(6)\n2 |                             ^\n"); }
+    #[test] fn test_trace_verbose() { run_trace("fn f(a, b) -> a / b ; fn g(x) -> f(x, 0) ; g('abcdefghijklmnopqrstuvwxyz' * 2)", TraceOptions { verbose: true, ..TraceOptions::default() }, "TypeError: Cannot divide 'abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz' of type 'str' and '0' of type 'int'\n  at: line 1 (<test>)\n  at: `fn f(a, b)` (line 1) with a = 'abcdefghijklmnopqrst..., b = 0\n  at: `fn g(x)` (line 1) with x = 'abcdefghijklmnopqrst...\n\n1 | fn f(a, b) -> a / b ; fn g(x) -> f(x, 0) ; g('abcdefghijklmnopqrstuvwxyz' * 2)\n2 |                 ^\n"); }
+
+    #[test] fn test_trace_cause_map() { run_str("fn f(x) -> 1 / x ; map(f, [2, 1, 0])", "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(x)` (line 1)\n  caused while evaluating native `map` with argument 0\n\n1 | fn f(x) -> 1 / x ; map(f, [2, 1, 0])\n2 |              ^\n"); }
+    #[test] fn test_trace_cause_partial_map() { run_str("fn f(x) -> 1 / x ; [2, 1, 0] . map(f) . list", "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(x)` (line 1)\n  caused while evaluating native `map` with argument 0\n\n1 | fn f(x) -> 1 / x ; [2, 1, 0] . map(f) . list\n2 |              ^\n"); }
+    #[test] fn test_trace_cause_sort_by_cmp() { run_str("fn f(a, b) -> a / (b - b) ; [1, 2] . sort_by(f)", "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(a, b)` (line 1)\n  caused while evaluating native `sort_by` with arguments 2, 1\n\n1 | fn f(a, b) -> a / (b - b) ; [1, 2] . sort_by(f)\n2 |                 ^\n"); }
+    #[test] fn test_trace_cause_nested() { run_str("fn f(x) -> 1 / x ; fn g(xs) -> xs . map(f) . sum ; [[1], [0, 2]] . map(g)", "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(x)` (line 1)\n  caused while evaluating native `map` with argument 0\n  at: `fn g(xs)` (line 1)\n  caused while evaluating native `map` with argument [0, 2]\n\n1 | fn f(x) -> 1 / x ; fn g(xs) -> xs . map(f) . sum ; [[1], [0, 2]] . map(g)\n2 |              ^\n"); }
+    #[test] fn test_trace_cause_not_from_callback() { run_str("fn f(x) -> nil ; [1, 2] . map(f) . sum", "TypeError: Expected 'nil' of type 'nil' to be a int\n  at: line 1 (<test>)\n\n1 | fn f(x) -> nil ; [1, 2] . map(f) . sum\n2 |                                  ^^^^^\n"); }
+    #[test] fn test_trace_cause_sort_by_first_error() { run_str("fn f(x) -> 1 / x ; [3, 0, 2, 1, 0] . sort_by(f)", "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n  at: `fn f(x)` (line 1)\n  caused while evaluating native `sort_by` with argument 0\n\n1 | fn f(x) -> 1 / x ; [3, 0, 2, 1, 0] . sort_by(f)\n2 |              ^\n"); }
+    /// Runs `text`, which raises an error, and renders the stack trace with `options`
+    fn run_trace(text: &'static str, options: TraceOptions, expected: &'static str) {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).with_trace_options(options);
+        match vm.run_until_completion() {
+            ExitType::Error(error) => assert_eq!(vm.view.format(&error).as_str(), expected),
+            exit => panic!("Expected an error, got {:?}", exit),
+        }
+    }
+
+    /// Runs `text` with a source of random bytes which always seeds `forall()` with zero, so that failures are deterministic
+    fn run_property(text: &'static str, expected: &'static str) {
+        struct ZeroEntropy;
+        impl Entropy for ZeroEntropy {
+            fn fill(&mut self, buf: &mut [u8]) -> io::Result<()> {
+                buf.fill(0);
+                Ok(())
+            }
+        }
+
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]).with_entropy(Box::new(ZeroEntropy));
+
+        let mut output: String = match vm.run_until_completion() {
+            ExitType::Error(error) => vm.view.format(&error),
+            _ => String::new(),
+        };
+        drop(vm);
+        output.insert_str(0, String::from_utf8(buf).unwrap().as_str());
+        assert_eq!(output.as_str(), expected);
+    }
+
+    const ARGPARSE_SPEC: &str = "argparse({'--verbose': false, '--count': 3, '--name': {'default': 'x', 'help': 'the name'}, 'file': {'help': 'input file'}, 'out': 'a.out'}) . print";
+
+    /// Runs `text` with the program arguments `args`, and checks the output and error streams
+    fn run_args(text: &'static str, args: &[&str], expected_out: &'static str, expected_err: &'static str) -> ExitType {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut out: Vec<u8> = Vec::new();
+        let mut err: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut out, &mut err, args.iter().map(|arg| arg.to_string()).collect());
+
+        let exit = vm.run_until_completion();
+        drop(vm);
+        assert_eq!(String::from_utf8(out).unwrap().as_str(), expected_out);
+        assert_eq!(String::from_utf8(err).unwrap().as_str(), expected_err);
+        exit
+    }
+
+    #[test]
+    fn test_argparse_defaults() {
+        let exit = run_args(ARGPARSE_SPEC, &["in.txt"], "{'verbose': false, 'count': 3, 'name': 'x', 'file': 'in.txt', 'out': 'a.out'}\n", "");
+        assert!(matches!(exit, ExitType::Exit(0)));
+    }
+
+    #[test]
+    fn test_argparse_all() {
+        let exit = run_args(ARGPARSE_SPEC, &["--count", "5", "in.txt", "--verbose", "--name=y", "--", "-o"], "{'verbose': true, 'count': 5, 'name': 'y', 'file': 'in.txt', 'out': '-o'}\n", "");
+        assert!(matches!(exit, ExitType::Exit(0)));
+    }
+
+    #[test]
+    fn test_argparse_help() {
+        let exit = run_args(ARGPARSE_SPEC, &["in.txt", "-h"], "usage: [-h] [--verbose] [--count COUNT] [--name NAME] file [out]\n\npositional arguments:\n  file           input file\n  out            (default: 'a.out')\n\noptions:\n  -h, --help     show this help message and exit\n  --verbose\n  --count COUNT  (default: 3)\n  --name NAME    the name (default: 'x')\n", "");
+        assert!(matches!(exit, ExitType::Exit(0)));
+    }
+
+    #[test]
+    fn test_argparse_missing_required() {
+        let exit = run_args(ARGPARSE_SPEC, &["--verbose"], "", "usage: [-h] [--verbose] [--count COUNT] [--name NAME] file [out]\nerror: missing required argument file\n");
+        assert!(matches!(exit, ExitType::Exit(2)));
+    }
+
+    #[test]
+    fn test_argparse_not_an_int() {
+        let exit = run_args(ARGPARSE_SPEC, &["--count", "five", "in.txt"], "", "usage: [-h] [--verbose] [--count COUNT] [--name NAME] file [out]\nerror: argument --count: expected an int, got 'five'\n");
+        assert!(matches!(exit, ExitType::Exit(2)));
+    }
+
+    #[test]
+    fn test_argparse_unrecognized() {
+        let exit = run_args(ARGPARSE_SPEC, &["in.txt", "out.txt", "extra"], "", "usage: [-h] [--verbose] [--count COUNT] [--name NAME] file [out]\nerror: unrecognized argument 'extra'\n");
+        assert!(matches!(exit, ExitType::Exit(2)));
+    }
+
+    #[test]
+    fn test_argparse_missing_value() {
+        let exit = run_args(ARGPARSE_SPEC, &["in.txt", "--name"], "", "usage: [-h] [--verbose] [--count COUNT] [--name NAME] file [out]\nerror: argument --name: expected a value\n");
+        assert!(matches!(exit, ExitType::Exit(2)));
+    }
+
+    /// Runs `text` compiled with `compile_print_last()`, and returns the `repr()` of the reported value, or `None` if it did not exit with one
+    fn run_print_last(text: &'static str, expected: &'static str) -> Option<String> {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let compile = compiler::compile_print_last(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]);
+
+        let exit = vm.run_until_completion();
+        drop(vm);
+        assert_eq!(String::from_utf8(buf).unwrap().as_str(), expected);
+        match exit {
+            ExitType::Value(value) => Some(value.to_repr_str()),
+            _ => None,
+        }
+    }
+
+    #[test] fn test_print_last_expression() { assert_eq!(run_print_last("let x = 3\nx * 2", ""), Some(String::from("6"))); }
+    #[test] fn test_print_last_expression_after_output() { assert_eq!(run_print_last("print('hello') ; 'world'", "hello\n"), Some(String::from("'world'"))); }
+    #[test] fn test_print_last_no_expression() { assert_eq!(run_print_last("let x = 3", ""), Some(String::from("nil"))); }
+    #[test] fn test_print_last_block_statement() { assert_eq!(run_print_last("let x = 3\nif x > 5 { x }", ""), Some(String::from("nil"))); }
+    #[test] fn test_print_last_with_locals() { assert_eq!(run_print_last("do { let y = 2 }\nlet x = [1, 2] ; x . map(+1)", ""), Some(String::from("[2, 3]"))); }
+    #[test] fn test_print_last_exit() { assert_eq!(run_print_last("exit ; 3", ""), None); }
+
+    /// Runs `text` compiled with `CompileFlags::per_iteration_loops`, asserting the output is `expected`
+    fn run_per_iteration_loops(text: &'static str, expected: &'static str) {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let compile = compiler::compile_with_flags(true, CompileFlags { per_iteration_loops: true, ..CompileFlags::default() }, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]);
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        drop(vm);
+        assert_eq!(String::from_utf8(buf).unwrap().as_str(), expected);
+    }
+
+    #[test] fn test_per_iteration_loops() { run_per_iteration_loops("let fs = [] ; for x in [1, 2, 3] { push(fn() -> x, fs) } ; fs . map(fn(f) -> f()) . print", "[1, 2, 3]\n"); }
+    #[test] fn test_per_iteration_loops_with_continue() { run_per_iteration_loops("let fs = [] ; for x in [1, 2, 3] { push(fn() -> x, fs) ; if x < 3 { continue } } ; fs . map(fn(f) -> f()) . print", "[1, 2, 3]\n"); }
+    #[test] fn test_per_iteration_loops_with_continue_always() { run_per_iteration_loops("let fs = [] ; for x in [1, 2, 3] { push(fn() -> x, fs) ; continue } ; fs . map(fn(f) -> f()) . print", "[1, 2, 3]\n"); }
+    #[test] fn test_per_iteration_loops_with_break() { run_per_iteration_loops("let fs = [] ; for x in [1, 2, 3] { push(fn() -> x, fs) ; if x == 2 { break } } ; fs . map(fn(f) -> f()) . print", "[1, 2]\n"); }
+    #[test] fn test_per_iteration_loops_with_pattern() { run_per_iteration_loops("let fs = [] ; for x, y in [(1, 2), (3, 4)] { push(fn() -> x + y, fs) ; continue } ; fs . map(fn(f) -> f()) . print", "[3, 7]\n"); }
+    #[test] fn test_per_iteration_loops_in_function() { run_per_iteration_loops("fn f() { let fs = [] ; for x in [1, 2, 3] { push(fn() -> x, fs) ; continue } ; fs } ; f() . map(fn(f) -> f()) . print", "[1, 2, 3]\n"); }
+    #[test] fn test_per_iteration_loops_with_continue_in_nested_loop() { run_per_iteration_loops("let fs = [] ; for x in [1, 2] { let i = 0 ; while i < 2 { push(fn() -> (x, i), fs) ; i += 1 ; continue } } ; fs . map(fn(f) -> f()) . print", "[(1, 2), (1, 2), (2, 2), (2, 2)]\n"); }
+
+    /// Runs `text`, where the host raises each signal as soon as the program registers a handler for it
+    fn run_signals(text: &'static str, raise: &[Signal], expected: &'static str) -> ExitType {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]);
+        let signals = vm.signals();
+        for signal in raise {
+            signals.raise(*signal);
+        }
+        let hooked = vm.signals();
+        let mut vm = vm.with_signal_hook(Box::new(move |signal| hooked.raise(signal)));
+
+        let exit = vm.run_until_completion();
+        drop(vm);
+        assert_eq!(String::from_utf8(buf).unwrap().as_str(), expected);
+        exit
+    }
+
+    #[test]
+    fn test_on_signal() {
+        let exit = run_signals("on_signal('INT', fn() -> print('caught')) ; print('after')", &[], "caught\nafter\n");
+        assert!(matches!(exit, ExitType::Exit(0)));
+    }
+
+    #[test]
+    fn test_on_signal_exit() {
+        let exit = run_signals("on_signal('TERM', fn() { print('stopping') ; exit(2) }) ; print('never')", &[], "stopping\n");
+        assert!(matches!(exit, ExitType::Exit(2)));
+    }
+
+    #[test]
+    fn test_signal_without_handler() {
+        let exit = run_signals("print('never')", &[Signal::Term], "");
+        assert!(matches!(exit, ExitType::Exit(143)));
+    }
+
+    #[test]
+    fn test_coprocess_capability_denied() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("coprocess('cat')"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).deny(Capability::Process);
+
+        match vm.run_until_completion() {
+            ExitType::Error(error) => assert_eq!(error.as_error(), "ValueError: 'fn coprocess(cmd, timeout?)' is not permitted by the host"),
+            exit => panic!("Expected an error, got {:?}", exit),
+        }
+    }
+
+    #[test]
+    fn test_fs_capability_denied_in_callback() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("['in.txt'] . map(read_text) . print"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).deny(Capability::Fs);
+
+        match vm.run_until_completion() {
+            ExitType::Error(error) => assert_eq!(error.as_error(), "ValueError: 'fn read_text(file)' is not permitted by the host"),
+            exit => panic!("Expected an error, got {:?}", exit),
+        }
+    }
+
+    #[test]
+    fn test_execution_limit() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("print('start') ; loop {}"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]).with_execution_limit(100);
+
+        match vm.run_until_completion() {
+            ExitType::Error(error) => assert_eq!(error.as_error(), "Execution limit of 100 instructions reached"),
+            exit => panic!("Expected an error, got {:?}", exit),
+        }
+        drop(vm);
+        assert_eq!(String::from_utf8(buf).unwrap().as_str(), "start\n");
+    }
+
+    #[test]
+    fn test_handles_closed_on_teardown() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("let p = coprocess('cat') ; exit"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]);
+
+        let exit: ExitType = vm.run_until_completion();
+        let process: ValuePtr = vm.globals().find(|(name, _)| *name == "p").unwrap().1.clone();
+        assert!(process.as_coprocess().borrow().stdin.get(&process).is_ok());
+
+        assert!(matches!(vm.run_exit_handlers(exit), ExitType::Exit(0)));
+        assert!(process.as_coprocess().borrow().stdin.get(&process).is_err());
+    }
+
+    #[test]
+    fn test_handles_closed_on_teardown_of_own_vm() {
+        let new_vm = || {
+            let view: SourceView = SourceView::new(String::from("<test>"), String::from("let p = coprocess('cat') ; exit"));
+            let compile = compiler::compile(true, &view).unwrap();
+            VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![])
+        };
+        let (mut vm1, mut vm2) = (new_vm(), new_vm());
+
+        let (exit1, exit2) = (vm1.run_until_completion(), vm2.run_until_completion());
+        let process: ValuePtr = vm2.globals().find(|(name, _)| *name == "p").unwrap().1.clone();
+
+        assert!(matches!(vm1.run_exit_handlers(exit1), ExitType::Exit(0)));
+        assert!(process.as_coprocess().borrow().stdin.get(&process).is_ok());
+
+        assert!(matches!(vm2.run_exit_handlers(exit2), ExitType::Exit(0)));
+        assert!(process.as_coprocess().borrow().stdin.get(&process).is_err());
+    }
+
+    #[test]
+    fn test_on_drop_invoked_by_own_vm() {
+        let (mut buf1, mut buf2) = (vec![], vec![]);
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("let x = [[1]] ; on_drop(x[0], fn() -> print('dropped')) ; fn f() -> nil ; exit"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm1 = VirtualMachine::new(compile, view, &b""[..], &mut buf1, vec![], vec![]);
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("fn release(x) { x.pop ; print('cleared') } ; exit"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm2 = VirtualMachine::new(compile, view, &b""[..], &mut buf2, vec![], vec![]);
+
+        assert!(matches!(vm1.run_until_completion(), ExitType::Exit(0)));
+        assert!(matches!(vm2.run_until_completion(), ExitType::Exit(0)));
+        let (x, f) = (vm1.globals().find(|(name, _)| *name == "x").unwrap().1.clone(), vm1.globals().find(|(name, _)| *name == "f").unwrap().1.clone());
+        let release: ValuePtr = vm2.globals().find(|(name, _)| *name == "release").unwrap().1.clone();
+
+        // The value is freed while `vm2` is running, but the finalizer is only invoked by `vm1`
+        assert!(vm2.call(release, [x]).as_result().is_ok());
+        assert!(matches!(vm1.run_function(f), ExitType::Value(_)));
+
+        drop((vm1, vm2));
+        assert_eq!(String::from_utf8(buf1).unwrap(), "dropped\n");
+        assert_eq!(String::from_utf8(buf2).unwrap(), "cleared\n");
+    }
+
+    #[test]
+    fn test_call_from_host() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("fn add(a, b) -> a + b ; fn fail() { assert false } ; let f = map(add(1)) ; exit"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]);
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        let global = |vm: &VirtualMachine<&[u8], Vec<u8>, Vec<u8>>, name: &str| vm.globals().find(|(it, _)| *it == name).unwrap().1.clone();
+        let (add, fail, f) = (global(&vm, "add"), global(&vm, "fail"), global(&vm, "f"));
+        let depth: (usize, usize) = vm.stack_depth();
+
+        assert_eq!(vm.call(add.clone(), [1i64.to_value(), 2i64.to_value()]).as_result().unwrap(), 3i64.to_value());
+        assert_eq!(vm.call(f, [(1i64.to_value(), 2i64.to_value()).to_value()]).as_result().unwrap().to_repr_str(), "[2, 3]");
+        assert_eq!(vm.call(NativeFunction::Sum.to_value(), [4i64.to_value(), 5i64.to_value()]).as_result().unwrap(), 9i64.to_value());
+        assert!(vm.try_call(fail, []).is_err());
+        assert_eq!(vm.call(add, ["a".to_value(), "b".to_value()]).as_result().unwrap(), "ab".to_value());
+        assert_eq!(vm.stack_depth(), depth);
+    }
+
+    #[test]
+    fn test_run_main_function() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("let x = 3\nfn main() { print('main') ; x * 2 }\nfn fail() -> exit(2)"));
+        let compile = compiler::compile_retaining_globals(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]);
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        assert!(vm.global("missing").is_none());
+        match vm.run_function(vm.global("main").unwrap().clone()) {
+            ExitType::Value(value) => assert_eq!(value, 6i64.to_value()),
+            exit => panic!("Expected a value, got {:?}", exit),
+        }
+        assert!(matches!(vm.run_function(vm.global("fail").unwrap().clone()), ExitType::Exit(2)));
+        drop(vm);
+        assert_eq!(String::from_utf8(buf).unwrap().as_str(), "main\n");
+    }
+
+    #[test]
+    fn test_take_globals() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("let x = 3, y = 'four'\nfn f() {}\nx += 1"));
+        let compile = compiler::compile_retaining_globals(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]);
+
+        assert!(matches!(vm.run_until_completion(), ExitType::Exit(0)));
+        let globals: Vec<(String, String)> = vm.take_globals()
+            .into_iter()
+            .map(|(name, value)| (name, value.to_repr_str()))
+            .collect();
+        assert_eq!(globals, vec![(String::from("x"), String::from("4")), (String::from("y"), String::from("'four'")), (String::from("f"), String::from("fn f()"))]);
+        assert_eq!(vm.globals().count(), 0);
+        assert_eq!(vm.stack_depth(), (0, 1));
+    }
+
+    #[test]
+    #[cfg(feature = "net")]
+    fn test_tcp_accept_closed() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("let l = tcp_listen(0) ; close(l) ; accept(l)"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]);
+
+        match vm.run_until_completion() {
+            ExitType::Error(error) => assert!(error.as_error().starts_with("ValueError: Cannot use 'tcp_listener(0.0.0.0:") && error.as_error().ends_with(")' of type 'socket' as it is closed"), "{}", error.as_error()),
+            exit => panic!("Expected an error, got {:?}", exit),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "net")]
+    fn test_tcp_capability_denied() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("tcp_listen(0)"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).deny(Capability::Net);
+
+        match vm.run_until_completion() {
+            ExitType::Error(error) => assert_eq!(error.as_error(), "ValueError: 'fn tcp_listen(port)' is not permitted by the host"),
+            exit => panic!("Expected an error, got {:?}", exit),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "net")]
+    fn test_http_get_async() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port: u16 = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for status in ["200 OK", "404 Not Found"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request: Vec<u8> = Vec::new();
+                let mut buf: [u8; 256] = [0; 256];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                assert!(request.starts_with(b"GET /hello HTTP/1.0\r\nHost: 127.0.0.1\r\n"));
+                write!(stream, "HTTP/1.0 {}\r\nContent-Type: text/plain\r\n\r\nhello world", status).unwrap();
+            }
+        });
+
+        let text: String = format!("let a = http_get_async('http://127.0.0.1:{0}/hello'), b = sleep_async(0) ; await b ; print(await a) ; await http_get_async('http://127.0.0.1:{0}/hello')", port);
+        let view: SourceView = SourceView::new(String::from("<test>"), text);
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]);
+
+        match vm.run_until_completion() {
+            ExitType::Error(error) => assert_eq!(error.as_error(), format!("IOError: HTTP request to 'http://127.0.0.1:{}/hello' failed with status 404", port)),
+            exit => panic!("Expected an error, got {:?}", exit),
+        }
+        server.join().unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "hello world\n");
+    }
+
+    #[test] fn test_path_join() { run_str("path_join('a', 'b', 'c.txt') . print", "a/b/c.txt\n"); }
+    #[test] fn test_path_join_iterable() { run_str("path_join(['a', 'b']) . print", "a/b\n"); }
+    #[test] fn test_path_join_absolute() { run_str("path_join('a', '/b', 'c') . print", "/b/c\n"); }
+    #[test] fn test_path_join_trailing_separator() { run_str("path_join('a/', 'b') . print", "a/b\n"); }
+    #[test] fn test_dirname() { run_str("['a/b/c.txt', 'c.txt', '/', '', 'a/b/', '/a'] . map(dirname) . print", "['a/b', '', '/', '', 'a', '/']\n"); }
+    #[test] fn test_basename() { run_str("['a/b/c.txt', 'c.txt', '/', '', 'a/b/', 'a/..'] . map(basename) . print", "['c.txt', 'c.txt', '', '', 'b', '']\n"); }
+    #[test] fn test_extension() { run_str("['a/b/c.txt', 'c.tar.gz', '.bashrc', 'a'] . map(extension) . print", "['txt', 'gz', '', '']\n"); }
+    #[test] fn test_normalize() { run_str("['a/./b/../c', 'a//b/', '', '.', 'a/..'] . map(normalize) . print", "['a/c', 'a/b', '.', '.', '.']\n"); }
+    #[test] fn test_normalize_parent_of_relative() { run_str("['..', '../a/..', 'a/../..'] . map(normalize) . print", "['..', '..', '..']\n"); }
+    #[test] fn test_normalize_parent_of_root() { run_str("['/..', '/../a', '/a/b/../../..'] . map(normalize) . print", "['/', '/a', '/']\n"); }
+    #[test] fn test_absolute() { run_str("let p = absolute('a/../b') ; print(p[0] == '/', p . basename, p . dirname . dirname . basename)", "true b a\n"); }
+    #[test] fn test_absolute_of_absolute() { run_str("absolute('/a/b') . print", "/a/b\n"); }
+    #[test] fn test_temp_dir() { run_str("temp_dir() . typeof . print", "str\n"); }
+    #[test] fn test_list_dir() { run_str("list_dir('test/fs') . print", "['test/fs/alpha.cor', 'test/fs/alpha.cor.bak', 'test/fs/beta.cor', 'test/fs/gamma.txt', 'test/fs/nested']\n"); }
+    #[test] fn test_list_dir_not_found() { run_str("list_dir('not/a/directory')", "IOError: No such file or directory (os error 2)\n  at: line 1 (<test>)\n\n1 | list_dir('not/a/directory')\n2 |         ^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_walk() { run_str("walk('test/fs') . filter(fn(p) -> 'alpha' in p) . print", "['test/fs/alpha.cor', 'test/fs/alpha.cor.bak', 'test/fs/nested/alpha.cor']\n"); }
+    #[test] fn test_walk_includes_directories() { run_str("walk('test/fs') . filter(fn(p) -> p . count('/') == 3) . print", "['test/fs/nested/alpha.cor', 'test/fs/nested/deep']\n"); }
+    #[test] fn test_walk_is_lazy() { run_str("walk('test/fs') . take(2) . print", "['test/fs/alpha.cor', 'test/fs/alpha.cor.bak']\n"); }
+    #[test] fn test_walk_repr() { run_str("let w = walk('test/fs') ; print(w, typeof(w))", "walk('test/fs') walk\n"); }
+    #[test] fn test_walk_enumerate_reverse() { run_str("walk('test/fs') . enumerate . reverse . first . print", "(7, 'test/fs/nested/deep/omega.cor')\n"); }
+    #[test] fn test_walk_not_found() { run_str("walk('not/a/directory')", "IOError: No such file or directory (os error 2)\n  at: line 1 (<test>)\n\n1 | walk('not/a/directory')\n2 |     ^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_glob_star() { run_str("glob('test/fs/*.cor') . print", "['test/fs/alpha.cor', 'test/fs/beta.cor']\n"); }
+    #[test] fn test_glob_question_mark() { run_str("glob('test/fs/alpha.cor.???') . print", "['test/fs/alpha.cor.bak']\n"); }
+    #[test] fn test_glob_set() { run_str("glob('test/fs/[b-g]*') . print", "['test/fs/beta.cor', 'test/fs/gamma.txt']\n"); }
+    #[test] fn test_glob_negated_set() { run_str("glob('test/fs/[!a]*') . print", "['test/fs/beta.cor', 'test/fs/gamma.txt', 'test/fs/nested']\n"); }
+    #[test] fn test_glob_recursive() { run_str("glob('test/fs/**/alpha.cor') . print", "['test/fs/alpha.cor', 'test/fs/nested/alpha.cor']\n"); }
+    #[test] fn test_glob_recursive_zero_directories() { run_str("glob('test/fs/**/nested') . print", "['test/fs/nested']\n"); }
+    #[test] fn test_glob_directories() { run_str("glob('test/fs/*/*') . print", "['test/fs/nested/alpha.cor', 'test/fs/nested/deep']\n"); }
+    #[test] fn test_glob_literal() { run_str("glob('test/fs/nested') . print", "['test/fs/nested']\n"); }
+    #[test] fn test_glob_no_matches() { run_str("glob('test/fs/*/not_a_file.*') . print", "[]\n"); }
+
+    #[test]
+    fn test_fs_capability_denied() {
+        let view: SourceView = SourceView::new(String::from("<test>"), String::from("list_dir('test')"));
+        let compile = compiler::compile(true, &view).unwrap();
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).deny(Capability::Fs);
+
+        match vm.run_until_completion() {
+            ExitType::Error(error) => assert_eq!(error.as_error(), "ValueError: 'fn list_dir(path)' is not permitted by the host"),
+            exit => panic!("Expected an error, got {:?}", exit),
+        }
+    }
+
+    #[test]
+    fn test_fs_capability_denied_for_environment_paths() {
+        for (text, expected) in [
+            ("absolute('test')", "ValueError: 'fn absolute(path)' is not permitted by the host"),
+            ("home_dir()", "ValueError: 'fn home_dir()' is not permitted by the host"),
+            ("temp_dir()", "ValueError: 'fn temp_dir()' is not permitted by the host"),
+        ] {
+            let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
+            let compile = compiler::compile(true, &view).unwrap();
+            let mut vm = VirtualMachine::new(compile, view, &b""[..], vec![], vec![], vec![]).deny(Capability::Fs);
+
+            match vm.run_until_completion() {
+                ExitType::Error(error) => assert_eq!(error.as_error(), expected),
+                exit => panic!("Expected an error, got {:?}", exit),
+            }
+        }
+    }
+
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_empty() { run_str("toml_decode('') . print", "{}\n"); }
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_scalars() { run_str("toml_decode('a = \"b\"\\nc = 123\\nd = true') . print", "{'a': 'b', 'c': 123, 'd': true}\n"); }
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_floats() { run_str("toml_decode('a = 0.25\\nb = 2.0') . print", "{'a': fraction(1, 4), 'b': 2}\n"); }
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_datetime() { run_str("toml_decode('a = 1979-05-27T07:32:00Z') . print", "{'a': '1979-05-27T07:32:00Z'}\n"); }
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_arrays() { run_str("toml_decode('a = [1, [2, 3], []]') . print", "{'a': [1, [2, 3], []]}\n"); }
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_tables() { run_str("toml_decode('[a.b]\\nc = 1\\n[[d]]\\ne = 2\\n[[d]]\\ne = 3') . print", "{'a': {'b': {'c': 1}}, 'd': [{'e': 2}, {'e': 3}]}\n"); }
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_invalid() { run_str("toml_decode('a = [1,')", "ValueError: Cannot decode TOML: invalid array, expected `]` at line 1, column 8\n  at: line 1 (<test>)\n\n1 | toml_decode('a = [1,')\n2 |            ^^^^^^^^^^^\n"); }
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_int_out_of_range() { run_str("toml_decode('a = 9223372036854775807')", "ValueError: Cannot decode TOML: integer 9223372036854775807 is out of range\n  at: line 1 (<test>)\n\n1 | toml_decode('a = 9223372036854775807')\n2 |            ^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[cfg(feature = "toml")] #[test] fn test_toml_decode_float_not_finite() { run_str("toml_decode('a = nan')", "ValueError: Cannot decode TOML: float NaN cannot be represented as a fraction\n  at: line 1 (<test>)\n\n1 | toml_decode('a = nan')\n2 |            ^^^^^^^^^^^\n"); }
+    #[cfg(not(feature = "toml"))] #[test] fn test_toml_decode_not_enabled() { run_str("toml_decode('')", "ValueError: Cordy was not compiled with the 'toml' feature enabled\n  at: line 1 (<test>)\n\n1 | toml_decode('')\n2 |            ^^^^\n"); }
     #[test] fn test_int_default_value_yes() { run_str("int('123', 567) . print", "123\n"); }
     #[test] fn test_int_default_value_no() { run_str("int('yes', 567) . print", "567\n"); }
     #[test] fn test_int_min_and_max() { run_str("[int.min, max(int)] . print", "[-4611686018427387904, 4611686018427387903]\n") }
@@ -1283,6 +3280,134 @@ mod tests {
     #[test] fn test_complex_typeof() { run_str("123i . typeof . print", "complex\n"); }
     #[test] fn test_complex_no_real_part_is_int() { run_str("1i * 1i . typeof . print", "int\n"); }
     #[test] fn test_complex_to_vector() { run_str("1 + 3i . vector . print", "(1, 3)\n"); }
+    #[test] fn test_fraction_str() { run_str("fraction(1, 2) . print", "1/2\n"); }
+    #[test] fn test_fraction_repr() { run_str("fraction(-2, 4) . repr . print", "fraction(-1, 2)\n"); }
+    #[test] fn test_fraction_normalizes_to_int() { run_str("fraction(6, 3) . typeof . print", "int\n"); }
+    #[test] fn test_fraction_typeof() { run_str("fraction(1, 3) . typeof . print", "fraction\n"); }
+    #[test] fn test_fraction_is() { run_str("[fraction(1, 3) is fraction, 3 is fraction, 'x' is fraction] . print", "[true, true, false]\n"); }
+    #[test] fn test_fraction_add() { run_str("fraction(1, 2) + fraction(1, 3) . print", "5/6\n"); }
+    #[test] fn test_fraction_add_int() { run_str("1 + fraction(1, 2) . print", "3/2\n"); }
+    #[test] fn test_fraction_sub() { run_str("fraction(1, 2) - fraction(1, 2) . repr . print", "0\n"); }
+    #[test] fn test_fraction_mul() { run_str("fraction(2, 3) * 3 . print", "2\n"); }
+    #[test] fn test_fraction_div() { run_str("fraction(2, 3) / 4 . print", "1/6\n"); }
+    #[test] fn test_fraction_div_by_zero() { run_str("fraction(2, 3) / 0", "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n\n1 | fraction(2, 3) / 0\n2 |                ^\n"); }
+    #[test] fn test_fraction_int_div_is_floor() { run_str("7 / 2 . print", "3\n"); }
+    #[test] fn test_fraction_pow() { run_str("fraction(2, 3) ** -2 . print", "9/4\n"); }
+    #[test] fn test_fraction_pow_exponent_too_large() { run_str("fraction(2, 3) ** 4294967296", "ValueError: Fraction is too large to be represented\n  at: line 1 (<test>)\n\n1 | fraction(2, 3) ** 4294967296\n2 |                ^^\n"); }
+    #[test] fn test_fraction_pow_overflow() { run_str("fraction(2, 3) ** 100", "ValueError: Fraction is too large to be represented\n  at: line 1 (<test>)\n\n1 | fraction(2, 3) ** 100\n2 |                ^^\n"); }
+    #[test] fn test_fraction_add_overflow() { run_str("fraction(1, 4611686018427387903) + fraction(1, 4611686018427387902)", "ValueError: Fraction is too large to be represented\n  at: line 1 (<test>)\n\n1 | fraction(1, 4611686018427387903) + fraction(1, 4611686018427387902)\n2 |                                  ^\n"); }
+    #[test] fn test_fraction_mul_overflow() { run_str("fraction(1, 4611686018427387903) * fraction(1, 3)", "ValueError: Fraction is too large to be represented\n  at: line 1 (<test>)\n\n1 | fraction(1, 4611686018427387903) * fraction(1, 3)\n2 |                                  ^\n"); }
+    #[test] fn test_fraction_to_int_out_of_range() { run_str("fraction(-4611686018427387903 - 1, -1)", "ValueError: Fraction is too large to be represented\n  at: line 1 (<test>)\n\n1 | fraction(-4611686018427387903 - 1, -1)\n2 |         ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_fraction_neg_out_of_range() { run_str("-fraction(-4611686018427387903 - 1, 3)", "ValueError: Fraction is too large to be represented\n  at: line 1 (<test>)\n\n1 | -fraction(-4611686018427387903 - 1, 3)\n2 | ^\n"); }
+    #[test] fn test_fraction_abs_out_of_range() { run_str("abs(fraction(-4611686018427387903 - 1, 3))", "ValueError: Fraction is too large to be represented\n  at: line 1 (<test>)\n\n1 | abs(fraction(-4611686018427387903 - 1, 3))\n2 |    ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_abs_min_int() { run_str("abs(-4611686018427387903 - 1)", "ValueError: Integer is too large to be represented\n  at: line 1 (<test>)\n\n1 | abs(-4611686018427387903 - 1)\n2 |    ^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_fraction_neg_and_abs() { run_str("-fraction(1, 2) . abs . print", "1/2\n"); }
+    #[test] fn test_fraction_compare_bool() { run_str("print(fraction(1, 2) < true, fraction(1, 2) > false, true < fraction(3, 2), fraction(1, 2) > true, [true, fraction(1, 2), false] . sort)", "true true true false [false, fraction(1, 2), true]\n"); }
+    #[test] fn test_fraction_compare() { run_str("[fraction(1, 2) < 1, fraction(3, 2) > 1, fraction(1, 3) < fraction(1, 2)] . print", "[true, true, true]\n"); }
+    #[test] fn test_fraction_sort_with_ints() { run_str("[1, fraction(1, 2), 0, fraction(3, 2)] . sort . print", "[0, fraction(1, 2), 1, fraction(3, 2)]\n"); }
+    #[test] fn test_fraction_of_fraction() { run_str("fraction(fraction(1, 2), 3) . print", "1/6\n"); }
+    #[test] fn test_fraction_zero_denominator() { run_str("fraction(1, 0)", "ValueError: Expected value to be non-zero\n  at: line 1 (<test>)\n\n1 | fraction(1, 0)\n2 |         ^^^^^^\n"); }
+    #[test] fn test_fraction_of_str() { run_str("fraction('1')", "TypeError: Expected '1' of type 'str' to be an int or fraction\n  at: line 1 (<test>)\n\n1 | fraction('1')\n2 |         ^^^^^\n"); }
+    #[test] fn test_fraction_to_int() { run_str("fraction(-7, 2) . int . print", "-4\n"); }
+    #[test] fn test_fraction_to_vector() { run_str("fraction(3, 4) . vector . print", "(3, 4)\n"); }
+    #[test] fn test_matrix_from_rows() { run_str("matrix([[1, 2], [3, 4]]) . print", "matrix([[1, 2], [3, 4]])\n"); }
+    #[test] fn test_matrix_from_fill() { run_str("matrix(2, 3, 7) . print", "matrix([[7, 7, 7], [7, 7, 7]])\n"); }
+    #[test] fn test_matrix_zeros() { run_str("matrix(2, 1) . print", "matrix([[0], [0]])\n"); }
+    #[test] fn test_matrix_empty() { run_str("matrix(0, 3) . repr . print", "matrix(0, 3)\n"); }
+    #[test] fn test_matrix_dimensions_overflow() { run_str("matrix(4611686018427387903, 4611686018427387903)", "ValueError: Cannot allocate a matrix of dimensions 4611686018427387903x4611686018427387903\n  at: line 1 (<test>)\n\n1 | matrix(4611686018427387903, 4611686018427387903)\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_matrix_too_large() { run_str("matrix(3000000000, 3000000000)", "ValueError: Cannot allocate a matrix of dimensions 3000000000x3000000000\n  at: line 1 (<test>)\n\n1 | matrix(3000000000, 3000000000)\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_matrix_matmul_too_large() { run_str("matmul(matrix(100000000000, 0), matrix(0, 100000000000))", "ValueError: Cannot allocate a matrix of dimensions 100000000000x100000000000\n  at: line 1 (<test>)\n\n1 | matmul(matrix(100000000000, 0), matrix(0, 100000000000))\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_matrix_ragged_rows() { run_str("matrix([[1, 2], [3]])", "ValueError: Expected all rows of a matrix to have length 2, got a row of length 1\n  at: line 1 (<test>)\n\n1 | matrix([[1, 2], [3]])\n2 |       ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_matrix_typeof() { run_str("let m = matrix(1, 1) ; print(typeof m, m is matrix, m is iterable, [] is matrix)", "matrix true true false\n"); }
+    #[test] fn test_matrix_len_and_bool() { run_str("print(len(matrix(3, 2)), bool(matrix(3, 0)), bool(matrix(1, 1)))", "3 false true\n"); }
+    #[test] fn test_matrix_get_element() { run_str("let m = matrix([[1, 2], [3, 4]]) ; print(m[(0, 1)], m[[1, 0]], m[(-1, -1)])", "2 3 4\n"); }
+    #[test] fn test_matrix_get_row() { run_str("matrix([[1, 2], [3, 4]])[1] . print", "(3, 4)\n"); }
+    #[test] fn test_matrix_get_out_of_bounds() { run_str("matrix(2, 2)[(0, 2)]", "Index '2' is out of bounds for list of length [0, 2)\n  at: line 1 (<test>)\n\n1 | matrix(2, 2)[(0, 2)]\n2 |             ^^^^^^^^\n"); }
+    #[test] fn test_matrix_set_element() { run_str("let m = matrix(2, 2) ; m[(1, 0)] = 5 ; m . print", "matrix([[0, 0], [5, 0]])\n"); }
+    #[test] fn test_matrix_set_row() { run_str("let m = matrix(2, 2) ; m[0] = m[0] + (1, 2) ; m . print", "matrix([[1, 2], [0, 0]])\n"); }
+    #[test] fn test_matrix_iter_rows() { run_str("matrix([[1, 2], [3, 4]]) . list . print", "[(1, 2), (3, 4)]\n"); }
+    #[test] fn test_matrix_iter_columns() { run_str("for col in matrix([[1, 2], [3, 4]]) . transpose { col . print }", "(1, 3)\n(2, 4)\n"); }
+    #[test] fn test_matrix_add() { run_str("matrix([[1, 2], [3, 4]]) + matrix(2, 2, 10) . print", "matrix([[11, 12], [13, 14]])\n"); }
+    #[test] fn test_matrix_sub_scalar() { run_str("10 - matrix([[1, 2], [3, 4]]) . print", "matrix([[9, 8], [7, 6]])\n"); }
+    #[test] fn test_matrix_mul_elementwise() { run_str("matrix([[1, 2], [3, 4]]) * matrix([[1, 2], [3, 4]]) . print", "matrix([[1, 4], [9, 16]])\n"); }
+    #[test] fn test_matrix_mul_scalar() { run_str("matrix([[1, 2], [3, 4]]) * 3 . print", "matrix([[3, 6], [9, 12]])\n"); }
+    #[test] fn test_matrix_neg() { run_str("-matrix([[1, -2]]) . print", "matrix([[-1, 2]])\n"); }
+    #[test] fn test_matrix_add_dimension_mismatch() { run_str("matrix(2, 2) + matrix(2, 3)", "ValueError: Incompatible matrix dimensions 2x2 and 2x3\n  at: line 1 (<test>)\n\n1 | matrix(2, 2) + matrix(2, 3)\n2 |              ^\n"); }
+    #[test] fn test_matrix_transpose() { run_str("matrix([[1, 2, 3], [4, 5, 6]]) . transpose . print", "matrix([[1, 4], [2, 5], [3, 6]])\n"); }
+    #[test] fn test_matrix_matmul() { run_str("matmul(matrix([[1, 2], [3, 4]]), matrix([[5, 6], [7, 8]])) . print", "matrix([[19, 22], [43, 50]])\n"); }
+    #[test] fn test_matrix_matmul_dimension_mismatch() { run_str("matmul(matrix(2, 3), matrix(2, 3))", "ValueError: Incompatible matrix dimensions 2x3 and 2x3\n  at: line 1 (<test>)\n\n1 | matmul(matrix(2, 3), matrix(2, 3))\n2 |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_matrix_equality() { run_str("print(matrix(2, 2) == matrix([[0, 0], [0, 0]]), matrix(2, 2) == matrix(2, 2, 1))", "true false\n"); }
+    #[test] fn test_bitset_empty() { run_str("bitset(10) . print", "bitset(10, {})\n"); }
+    #[test] fn test_bitset_from_bits() { run_str("bitset(10, [1, 3, 3, -1]) . print", "bitset(10, {1, 3, 9})\n"); }
+    #[test] fn test_bitset_from_bits_out_of_bounds() { run_str("bitset(4, [4])", "Index '4' is out of bounds for list of length [0, 4)\n  at: line 1 (<test>)\n\n1 | bitset(4, [4])\n2 |       ^^^^^^^^\n"); }
+    #[test] fn test_bitset_typeof() { run_str("let b = bitset(3) ; print(typeof b, b is bitset, b is iterable, {} is bitset)", "bitset true true false\n"); }
+    #[test] fn test_bitset_get_set() { run_str("let b = bitset(70) ; b[3] = true ; b[69] = 1 ; b[-1] = false ; print(b[3], b[4], b[68], b)", "true false false bitset(70, {3})\n"); }
+    #[test] fn test_bitset_flip_bit() { run_str("let b = bitset(4) ; b[2] = !b[2] ; b . print", "bitset(4, {2})\n"); }
+    #[test] fn test_bitset_len_and_count_ones() { run_str("let b = bitset(100, [0, 50, 99]) ; print(len(b), count_ones(b), bool(b), bool(bitset(100)))", "3 3 true false\n"); }
+    #[test] fn test_bitset_iter() { run_str("bitset(200, [199, 0, 64, 63]) . list . print", "[0, 63, 64, 199]\n"); }
+    #[test] fn test_bitset_in() { run_str("let b = bitset(8, [2]) ; print(2 in b, 3 in b, -1 in b, 100 in b)", "true false false false\n"); }
+    #[test] fn test_bitset_not() { run_str("!bitset(5, [0, 2]) . print", "bitset(5, {1, 3, 4})\n"); }
+    #[test] fn test_bitset_and_or_xor() { run_str("let a = bitset(8, [1, 2]), b = bitset(10, [2, 9]) ; print(a & b, a | b, a ^ b)", "bitset(10, {2}) bitset(10, {1, 2, 9}) bitset(10, {1, 9})\n"); }
+    #[test] fn test_bitset_left_shift() { run_str("print(bitset(130, [0, 63, 64, 127]) << 65, bitset(10, [3]) << -3)", "bitset(130, {65, 128, 129}) bitset(10, {0})\n"); }
+    #[test] fn test_bitset_right_shift() { run_str("print(bitset(130, [0, 63, 64, 127, 129]) >> 63, bitset(10, [3, 9]) >> 64)", "bitset(130, {0, 1, 64, 66}) bitset(10, {})\n"); }
+    #[test] fn test_bitset_too_large() { run_str("bitset(4611686018427387903)", "ValueError: Cannot allocate a bitset of size 4611686018427387903\n  at: line 1 (<test>)\n\n1 | bitset(4611686018427387903)\n2 |       ^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_bitset_get_set_flip_bit() { run_str("let b = bitset(70) ; b . set_bit(3) . set_bit(69) . flip_bit(3) . flip_bit(5) ; print(b, get_bit(5, b), get_bit(3, b), b . get_bit(-1))", "bitset(70, {5, 69}) true false true\n"); }
+    #[test] fn test_bitset_set_bit_out_of_bounds() { run_str("bitset(4) . set_bit(4)", "Index '4' is out of bounds for list of length [0, 4)\n  at: line 1 (<test>)\n\n1 | bitset(4) . set_bit(4)\n2 |           ^^^^^^^^^^^^\n"); }
+    #[test] fn test_bitset_get_bit_not_bitset() { run_str("get_bit(0, [true])", "TypeError: Expected '[true]' of type 'list' to be a bitset\n  at: line 1 (<test>)\n\n1 | get_bit(0, [true])\n2 |        ^^^^^^^^^^^\n"); }
+    #[test] fn test_bitset_equality() { run_str("print(bitset(4, [1]) == bitset(4, [1]), bitset(4, [1]) == bitset(5, [1]))", "true false\n"); }
+    #[test] fn test_dsu_empty() { run_str("dsu(0) . print", "dsu()\n"); }
+    #[test] fn test_dsu_new() { run_str("dsu(3) . print", "dsu([0], [1], [2])\n"); }
+    #[test] fn test_dsu_too_large() { run_str("dsu(4611686018427387903)", "ValueError: Cannot allocate a dsu of size 4611686018427387903\n  at: line 1 (<test>)\n\n1 | dsu(4611686018427387903)\n2 |    ^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_dsu_negative_size() { run_str("dsu(-1)", "ValueError: Expected value '-1: int' to be non-negative\n  at: line 1 (<test>)\n\n1 | dsu(-1)\n2 |    ^^^^\n"); }
+    #[test] fn test_dsu_typeof() { run_str("let d = dsu(3) ; print(typeof d, d is dsu, d is iterable)", "dsu true false\n"); }
+    #[test] fn test_dsu_union() { run_str("let d = dsu(5) ; print(union(0, 1, d), union(3, 4, d), union(1, 0, d), d)", "true true false dsu([0, 1], [2], [3, 4])\n"); }
+    #[test] fn test_dsu_find() { run_str("let d = dsu(4) ; union(1, 2, d) ; union(2, 3, d) ; print(d . find(1) == d . find(3), d . find(0) == d . find(1), d . find(0))", "true false 0\n"); }
+    #[test] fn test_dsu_connected() { run_str("let d = dsu(4) ; union(0, 3, d) ; print(d . connected(3, 0), d . connected(0, 1), connected(2, 1, d))", "true false false\n"); }
+    #[test] fn test_dsu_groups() { run_str("let d = dsu(6) ; for a, b in [(5, 0), (2, 4), (4, 5)] { union(a, b, d) } ; d . groups . print", "[[0, 2, 4, 5], [1], [3]]\n"); }
+    #[test] fn test_dsu_out_of_bounds() { run_str("dsu(3) . find(3)", "ValueError: Element '3' is out of range for a dsu with elements in [0, 3)\n  at: line 1 (<test>)\n\n1 | dsu(3) . find(3)\n2 |        ^^^^^^^^^\n"); }
+    #[test] fn test_dsu_find_negative() { run_str("find(-1, dsu(5))", "ValueError: Element '-1' is out of range for a dsu with elements in [0, 5)\n  at: line 1 (<test>)\n\n1 | find(-1, dsu(5))\n2 |     ^^^^^^^^^^^^\n"); }
+    #[test] fn test_dsu_union_negative() { run_str("union(0, -2, dsu(5))", "ValueError: Element '-2' is out of range for a dsu with elements in [0, 5)\n  at: line 1 (<test>)\n\n1 | union(0, -2, dsu(5))\n2 |      ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_dsu_union_out_of_bounds() { run_str("union(5, 0, dsu(5))", "ValueError: Element '5' is out of range for a dsu with elements in [0, 5)\n  at: line 1 (<test>)\n\n1 | union(5, 0, dsu(5))\n2 |      ^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_dsu_connected_negative() { run_str("connected(-1, 0, dsu(5))", "ValueError: Element '-1' is out of range for a dsu with elements in [0, 5)\n  at: line 1 (<test>)\n\n1 | connected(-1, 0, dsu(5))\n2 |          ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_dsu_union_not_dsu() { run_str("union(1, 2, [])", "TypeError: Expected '[]' of type 'list' to be a dsu\n  at: line 1 (<test>)\n\n1 | union(1, 2, [])\n2 |      ^^^^^^^^^^\n"); }
+    #[test] fn test_dsu_set_union_still_works() { run_str("union({1}, {2}) . print", "{2, 1}\n"); }
+    #[test] fn test_dsu_find_still_works() { run_str("[1, 2, 3] . find(fn(x) -> x > 1) . print", "2\n"); }
+    #[test] fn test_dsu_equality() { run_str("let d = dsu(2) ; print(d == d, d == dsu(2))", "true false\n"); }
+    #[test] fn test_dsu_chain() { run_str("let d = dsu(50) ; for i in range(49) { union(i, i + 1, d) } ; print(d . connected(0, 49), len(d . groups))", "true 1\n"); }
+    #[test] fn test_sorted_set_empty() { run_str("sorted_set() . print", "sorted_set({})\n"); }
+    #[test] fn test_sorted_set_from_iterable() { run_str("sorted_set([5, 1, 3, 1]) . print", "sorted_set({1, 3, 5})\n"); }
+    #[test] fn test_sorted_set_from_args() { run_str("sorted_set('c', 'a', 'b') . print", "sorted_set({'a', 'b', 'c'})\n"); }
+    #[test] fn test_sorted_set_typeof() { run_str("let s = sorted_set() ; print(typeof s, s is sorted_set, s is iterable, s is set, set() is sorted_set)", "sorted_set true true false false\n"); }
+    #[test] fn test_sorted_set_push() { run_str("let s = sorted_set(3, 1) ; s . push(2) . push(1) ; print(s, len(s))", "sorted_set({1, 2, 3}) 3\n"); }
+    #[test] fn test_sorted_set_remove() { run_str("let s = sorted_set(1, 2, 3) ; print(s . remove(2), s . remove(2), s)", "true false sorted_set({1, 3})\n"); }
+    #[test] fn test_sorted_set_in() { run_str("let s = sorted_set(1, 2, 3) ; print(2 in s, 4 in s, 4 not in s)", "true false true\n"); }
+    #[test] fn test_sorted_set_iter() { run_str("for x in sorted_set(3, 1, 2) { x . print }", "1\n2\n3\n"); }
+    #[test] fn test_sorted_set_first_last() { run_str("let s = sorted_set(5, 2, 8) ; print(first(s), last(s))", "2 8\n"); }
+    #[test] fn test_sorted_set_first_empty() { run_str("sorted_set() . first", "ValueError: Expected value to be a non empty iterable\n  at: line 1 (<test>)\n\n1 | sorted_set() . first\n2 |              ^^^^^^^\n"); }
+    #[test] fn test_sorted_set_pop() { run_str("let s = sorted_set(5, 2, 8) ; print(s . pop, s)", "8 sorted_set({2, 5})\n"); }
+    #[test] fn test_sorted_set_clear() { run_str("let s = sorted_set(1, 2) ; print(s . clear, bool(s))", "sorted_set({}) false\n"); }
+    #[test] fn test_sorted_set_between() { run_str("let s = sorted_set(range(0, 20, 3)) ; print(s . between(4, 12), s . between(3, 4), s . between(12, 4))", "[6, 9] [3] []\n"); }
+    #[test] fn test_sorted_set_floor_ceiling() { run_str("let s = sorted_set(10, 20, 30) ; print(s . floor(25), s . floor(20), s . floor(5), s . ceiling(25), s . ceiling(20), s . ceiling(35))", "20 20 nil 30 20 nil\n"); }
+    #[test] fn test_sorted_set_freezes_elements() { run_str("let x = [1], s = sorted_set() ; s . push(x) ; x . push(2)", "ValueError: Cannot modify '[1]' of type 'list', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let x = [1], s = sorted_set() ; s . push(x) ; x . push(2)\n2 |                                                 ^^^^^^^^^\n"); }
+    #[test] fn test_sorted_set_push_self() { run_str("let s = sorted_set() ; s . push(s)", "ValueError: Cannot modify 'sorted_set({})' of type 'sorted_set', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let s = sorted_set() ; s . push(s)\n2 |                          ^^^^^^^^^\n"); }
+    #[test] fn test_sorted_set_mixed_types() { run_str("let s = sorted_set('b', 2, 'a', fraction(1, 2), 1, true) ; print(s, 'a' in s, 3 in s, s . between(0, 3))", "sorted_set({true, fraction(1, 2), 1, 2, 'a', 'b'}) true false [fraction(1, 2), 1, 2]\n"); }
+    #[test] fn test_sorted_set_equality() { run_str("print(sorted_set(1, 2) == sorted_set(2, 1), sorted_set(1, 2) == sorted_set([1]), sorted_set(1, 2) < sorted_set(1, 3))", "true false true\n"); }
+    #[test] fn test_sorted_set_as_key() { run_str("let d = {sorted_set(1, 2): 'x'} ; d[sorted_set(2, 1)] . print", "x\n"); }
+    #[test] fn test_sorted_dict_empty() { run_str("sorted_dict() . print", "sorted_dict({})\n"); }
+    #[test] fn test_sorted_dict_from_pairs() { run_str("sorted_dict([(3, 'c'), (1, 'a')]) . print", "sorted_dict({1: 'a', 3: 'c'})\n"); }
+    #[test] fn test_sorted_dict_from_args() { run_str("sorted_dict((3, 'c'), (1, 'a'), (2, 'b')) . print", "sorted_dict({1: 'a', 2: 'b', 3: 'c'})\n"); }
+    #[test] fn test_sorted_dict_typeof() { run_str("let d = sorted_dict() ; print(typeof d, d is sorted_dict, d is dict)", "sorted_dict true false\n"); }
+    #[test] fn test_sorted_dict_index() { run_str("let d = sorted_dict() ; d['b'] = 2 ; d['a'] = 1 ; d['b'] += 10 ; print(d, d['a'], d['b'])", "sorted_dict({'a': 1, 'b': 12}) 1 12\n"); }
+    #[test] fn test_sorted_dict_index_missing() { run_str("sorted_dict()['a']", "ValueError: Key 'a' of type 'str' not found in dictionary\n  at: line 1 (<test>)\n\n1 | sorted_dict()['a']\n2 |              ^^^^^\n"); }
+    #[test] fn test_sorted_dict_insert() { run_str("sorted_dict() . insert(2, 'b') . insert(1, 'a') . print", "sorted_dict({1: 'a', 2: 'b'})\n"); }
+    #[test] fn test_sorted_dict_remove() { run_str("let d = sorted_dict((1, 'a'), (2, 'b')) ; print(d . remove(1), d . remove(1), d)", "true false sorted_dict({2: 'b'})\n"); }
+    #[test] fn test_sorted_dict_in() { run_str("let d = sorted_dict([(1, 'a')]) ; print(1 in d, 'a' in d)", "true false\n"); }
+    #[test] fn test_sorted_dict_iter() { run_str("for k, v in sorted_dict((2, 'b'), (1, 'a')) { print(k, v) }", "1 a\n2 b\n"); }
+    #[test] fn test_sorted_dict_first_last_pop() { run_str("let d = sorted_dict((2, 'b'), (1, 'a'), (3, 'c')) ; print(first(d), last(d), d . pop, d)", "(1, 'a') (3, 'c') (3, 'c') sorted_dict({1: 'a', 2: 'b'})\n"); }
+    #[test] fn test_sorted_dict_between() { run_str("let d = sorted_dict((1, 'a'), (5, 'e'), (9, 'i')) ; d . between(1, 9) . print", "[(1, 'a'), (5, 'e')]\n"); }
+    #[test] fn test_sorted_dict_floor_ceiling() { run_str("let d = sorted_dict((1, 'a'), (5, 'e')) ; print(d . floor(4), d . ceiling(4), d . ceiling(6))", "(1, 'a') (5, 'e') nil\n"); }
+    #[test] fn test_sorted_dict_freezes_keys() { run_str("let k = [1], d = sorted_dict() ; d[k] = 1 ; k . push(2)", "ValueError: Cannot modify '[1]' of type 'list', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let k = [1], d = sorted_dict() ; d[k] = 1 ; k . push(2)\n2 |                                               ^^^^^^^^^\n"); }
+    #[test] fn test_floor_not_sorted() { run_str("[1, 2] . floor(1)", "TypeError: Expected '[1, 2]' of type 'list' to be a sorted_set or sorted_dict\n  at: line 1 (<test>)\n\n1 | [1, 2] . floor(1)\n2 |        ^^^^^^^^^^\n"); }
     #[test] fn test_bool_comparisons_1() { run_str("print(false < false, false < true, true < false, true < true)", "false true false false\n"); }
     #[test] fn test_bool_comparisons_2() { run_str("print(false <= false, false >= true, true >= false, true <= true)", "true false true true\n"); }
     #[test] fn test_bool_operator_add() { run_str("true + true + false + false . print", "2\n"); }
@@ -1293,6 +3418,8 @@ mod tests {
     #[test] fn test_str_partial_left_add() { run_str("'world ' . (+'hello') . print", "world hello\n"); }
     #[test] fn test_str_partial_right_add() { run_str("' world' . ('hello'+) . print", "hello world\n"); }
     #[test] fn test_str_mul() { run_str("print('abc' * 3)", "abcabcabc\n"); }
+    #[test] fn test_str_mul_too_large() { run_str("let n = 4611686018427387903 ; 'abc' * n", "ValueError: Cannot repeat a sequence of length 3, 4611686018427387903 times\n  at: line 1 (<test>)\n\n1 | let n = 4611686018427387903 ; 'abc' * n\n2 |                                     ^\n"); }
+    #[test] fn test_str_mul_empty_large() { run_str("let n = 4611686018427387903 ; print(repr('' * n))", "''\n"); }
     #[test] fn test_str_index() { run_str("'hello'[1] . print", "e\n"); }
     #[test] fn test_str_slice_start() { run_str("'hello'[1:] . print", "ello\n"); }
     #[test] fn test_str_slice_stop() { run_str("'hello'[:3] . print", "hel\n"); }
@@ -1323,6 +3450,23 @@ mod tests {
     #[test] fn test_str_format_too_few_args() { run_str("'%d %d %d' % (1, 2, 3, 4)", "ValueError: Not all arguments consumed in format string, next: '4' of type 'int'\n  at: line 1 (<test>)\n\n1 | '%d %d %d' % (1, 2, 3, 4)\n2 |            ^\n"); }
     #[test] fn test_str_format_incorrect_character() { run_str("'%g' % (1,)", "ValueError: Invalid format character 'g' in format string\n  at: line 1 (<test>)\n\n1 | '%g' % (1,)\n2 |      ^\n"); }
     #[test] fn test_str_format_incorrect_width() { run_str("'%00' % (1,)", "ValueError: Invalid format character '0' in format string\n  at: line 1 (<test>)\n\n1 | '%00' % (1,)\n2 |       ^\n"); }
+    #[test] fn test_str_format_left_justify_int() { run_str("'[%-6d]' % (42,) . print", "[42    ]\n"); }
+    #[test] fn test_str_format_left_justify_str() { run_str("'[%-6s]' % ('ab',) . print", "[ab    ]\n"); }
+    #[test] fn test_str_format_left_justify_ignores_zero_pad() { run_str("'[%-05d]' % (7,) . print", "[7    ]\n"); }
+    #[test] fn test_str_format_plus_sign() { run_str("'%+d %+d %+d' % (5, 0, -5) . print", "+5 +0 -5\n"); }
+    #[test] fn test_str_format_plus_sign_zero_pad() { run_str("'%+05d' % (5,) . print", "+0005\n"); }
+    #[test] fn test_str_format_plus_sign_hex() { run_str("'%+x' % (255,) . print", "+ff\n"); }
+    #[test] fn test_str_format_thousands() { run_str("'%,d %,d %,d %,d' % (0, 999, 1000, 1234567) . print", "0 999 1,000 1,234,567\n"); }
+    #[test] fn test_str_format_thousands_neg() { run_str("'%,d' % (-1234567,) . print", "-1,234,567\n"); }
+    #[test] fn test_str_format_thousands_with_width() { run_str("'[%,10d]' % (1234567,) . print", "[ 1,234,567]\n"); }
+    #[test] fn test_str_format_thousands_not_int() { run_str("'%,x' % (1,)", "ValueError: Invalid format character ',' in format string\n  at: line 1 (<test>)\n\n1 | '%,x' % (1,)\n2 |       ^\n"); }
+    #[test] fn test_str_format_duplicate_flag() { run_str("'%--d' % (1,)", "ValueError: Invalid format character '-' in format string\n  at: line 1 (<test>)\n\n1 | '%--d' % (1,)\n2 |        ^\n"); }
+    #[test] fn test_str_format_precision_str() { run_str("'[%.3s]' % ('abcdef',) . print", "[abc]\n"); }
+    #[test] fn test_str_format_precision_str_zero() { run_str("'[%.0s]' % ('abcdef',) . print", "[]\n"); }
+    #[test] fn test_str_format_precision_str_with_width() { run_str("'[%-6.2s]' % ('abcdef',) . print", "[ab    ]\n"); }
+    #[test] fn test_str_format_precision_int() { run_str("'[%.5d]' % (42,) . print", "[00042]\n"); }
+    #[test] fn test_str_format_precision_neg_int_with_width() { run_str("'[%8.5d]' % (-42,) . print", "[  -00042]\n"); }
+    #[test] fn test_str_format_precision_bin() { run_str("'%.4b' % (3,) . print", "0011\n"); }
     #[test] fn test_list_empty_constructor() { run_str("list() . print", "[]\n"); }
     #[test] fn test_list_literal_empty() { run_str("[] . print", "[]\n"); }
     #[test] fn test_list_literal_len_1() { run_str("['hello'] . print", "['hello']\n"); }
@@ -1336,6 +3480,8 @@ mod tests {
     #[test] fn test_list_add() { run_str("[1, 2, 3] + [4, 5, 6] . print", "[1, 2, 3, 4, 5, 6]\n"); }
     #[test] fn test_list_multiply_left() { run_str("[1, 2, 3] * 3 . print", "[1, 2, 3, 1, 2, 3, 1, 2, 3]\n"); }
     #[test] fn test_list_multiply_right() { run_str("3 * [1, 2, 3] . print", "[1, 2, 3, 1, 2, 3, 1, 2, 3]\n"); }
+    #[test] fn test_list_multiply_too_large() { run_str("let n = 4611686018427387903 ; [1, 2] * n", "ValueError: Cannot repeat a sequence of length 2, 4611686018427387903 times\n  at: line 1 (<test>)\n\n1 | let n = 4611686018427387903 ; [1, 2] * n\n2 |                                      ^\n"); }
+    #[test] fn test_list_multiply_empty_large() { run_str("let n = 4611686018427387903 ; print([] * n)", "[]\n"); }
     #[test] fn test_list_multiply_nested() { run_str("let a = [[1]] * 3; a[0][0] = 2; a . print", "[[2], [2], [2]]\n"); }
     #[test] fn test_list_operator_in_yes() { run_str("13 in [10, 11, 12, 13, 14, 15] . print", "true\n"); }
     #[test] fn test_list_operator_in_no() { run_str("3 in [10, 11, 12, 13, 14, 15] . print", "false\n"); }
@@ -1399,6 +3545,16 @@ mod tests {
     #[test] fn test_list_pop_front() { run_str("let x = [1, 2, 3], y = x . pop_front ; (x, y) . print", "([2, 3], 1)\n"); }
     #[test] fn test_list_push() { run_str("let x = [1, 2, 3] ; x . push(4) ; x . print", "[1, 2, 3, 4]\n"); }
     #[test] fn test_list_push_front() { run_str("let x = [1, 2, 3] ; x . push_front(4) ; x . print", "[4, 1, 2, 3]\n"); }
+    #[test] fn test_list_extend_front() { run_str("let x = [1, 2, 3] ; x . extend_front([4, 5]) ; x . print", "[4, 5, 1, 2, 3]\n"); }
+    #[test] fn test_list_extend_front_self() { run_str("let x = [1, 2] ; x . extend_front(x) . print", "[1, 2, 1, 2]\n"); }
+    #[test] fn test_list_extend_front_not_list() { run_str("range(3) . extend_front([9]) . print", "TypeError: Expected 'range(0, 3, 1)' of type 'range' to be a list\n  at: line 1 (<test>)\n\n1 | range(3) . extend_front([9]) . print\n2 |          ^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_list_swap_remove() { run_str("let x = [1, 2, 3, 4] ; x . swap_remove(1) . print ; x . print", "2\n[1, 4, 3]\n"); }
+    #[test] fn test_list_swap_remove_last() { run_str("let x = [1, 2, 3] ; x . swap_remove(2) . print ; x . print", "3\n[1, 2]\n"); }
+    #[test] fn test_list_swap_remove_out_of_bounds() { run_str("[1, 2, 3] . swap_remove(3)", "Index '3' is out of bounds for list of length [0, 3)\n  at: line 1 (<test>)\n\n1 | [1, 2, 3] . swap_remove(3)\n2 |           ^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_list_rotate() { run_str("let x = [1, 2, 3, 4] ; x . rotate(1) . print", "[4, 1, 2, 3]\n"); }
+    #[test] fn test_list_rotate_negative() { run_str("let x = [1, 2, 3, 4] ; x . rotate(-1) . print", "[2, 3, 4, 1]\n"); }
+    #[test] fn test_list_rotate_wraps() { run_str("[1, 2, 3] . rotate(7) . print", "[3, 1, 2]\n"); }
+    #[test] fn test_list_rotate_empty() { run_str("[] . rotate(3) . print", "[]\n"); }
     #[test] fn test_list_insert_front() { run_str("let x = [1, 2, 3] ; x . insert(0, 4) ; x . print", "[4, 1, 2, 3]\n"); }
     #[test] fn test_list_insert_middle() { run_str("let x = [1, 2, 3] ; x . insert(1, 4) ; x . print", "[1, 4, 2, 3]\n"); }
     #[test] fn test_list_insert_end() { run_str("let x = [1, 2, 3] ; x . insert(2, 4) ; x . print", "[1, 2, 4, 3]\n"); }
@@ -1431,6 +3587,58 @@ mod tests {
     #[test] fn test_set_empty_constructor() { run_str("set() . print", "{}\n"); }
     #[test] fn test_vector_array_assign() { run_str("let x = (1, 2, 3) ; x[0] = 3 ; x . print", "(3, 2, 3)\n"); }
     #[test] fn test_vector_recursive_repr() { run_str("let x = (nil,) ; x[0] = x ; x.print", "((...))\n"); }
+    #[test] fn test_view() { run_str("let v = view([1, 2, 3]) ; print(v, typeof v, v is view, len(v))", "view([1, 2, 3]) view true 3\n"); }
+    #[test] fn test_view_with_start_and_stop() { run_str("print(view([1, 2, 3, 4], 1), view([1, 2, 3, 4], 1, -1), view('abcd', nil, 2))", "view([2, 3, 4]) view([2, 3]) view(['a', 'b'])\n"); }
+    #[test] fn test_view_of_view() { run_str("let v = view([1, 2, 3, 4]) ; print(view(v, 1), view(v) == v)", "view([2, 3, 4]) true\n"); }
+    #[test] fn test_view_index() { run_str("let v = view([1, 2, 3])[1:] ; print(v[0], v[-1], v[1])", "2 3 3\n"); }
+    #[test] fn test_view_index_out_of_bounds() { run_str("view([1, 2, 3])[1:][2]", "Index '2' is out of bounds for list of length [0, 2)\n  at: line 1 (<test>)\n\n1 | view([1, 2, 3])[1:][2]\n2 |                    ^^^\n"); }
+    #[test] fn test_view_slice() { run_str("let v = view([1, 2, 3, 4, 5]) ; print(v[1:], v[1:][1:], v[:-1][1:], v[10:], v[3:1], v[-2:])", "view([2, 3, 4, 5]) view([3, 4, 5]) view([2, 3, 4]) view([]) view([]) view([4, 5])\n"); }
+    #[test] fn test_view_slice_with_step() { run_str("let v = view([1, 2, 3, 4, 5])[1:] ; print(v[::2], v[::-1])", "view([2, 4]) view([5, 4, 3, 2])\n"); }
+    #[test] fn test_view_is_snapshot() { run_str("let x = [1, 2, 3], v = view(x) ; x.push(4) ; x[0] = 5 ; print(x, v)", "[5, 2, 3, 4] view([1, 2, 3])\n"); }
+    #[test] fn test_view_is_immutable() { run_str("let v = view([1, 2, 3]) ; v[0] = 2", "ValueError: Cannot modify 'view([1, 2, 3])' of type 'view', as views are immutable\n  at: line 1 (<test>)\n\n1 | let v = view([1, 2, 3]) ; v[0] = 2\n2 |                                ^\n"); }
+    #[test] fn test_view_push_is_immutable() { run_str("push(7, view([1, 2, 3], 0, 2))", "ValueError: Cannot modify 'view([1, 2])' of type 'view', as views are immutable\n  at: line 1 (<test>)\n\n1 | push(7, view([1, 2, 3], 0, 2))\n2 |     ^^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_view_pop_is_immutable() { run_str("view([1, 2, 3]) . pop", "ValueError: Cannot modify 'view([1, 2, 3])' of type 'view', as views are immutable\n  at: line 1 (<test>)\n\n1 | view([1, 2, 3]) . pop\n2 |                 ^^^^^\n"); }
+    #[test] fn test_view_to_list() { run_str("let v = view([1, 2, 3])[1:], x = list(v) ; x.push(4) ; print(v, x)", "view([2, 3]) [2, 3, 4]\n"); }
+    #[test] fn test_view_iter() { run_str("for x in view([1, 2, 3])[1:] { print(x) }", "2\n3\n"); }
+    #[test] fn test_view_in() { run_str("let v = view([1, 2, 3])[1:] ; print(1 in v, 2 in v, 1 not in v)", "false true true\n"); }
+    #[test] fn test_view_eq_and_hash() { run_str("let v = view([1, 2, 3])[1:] ; print(v == view([2, 3]), v == [2, 3], {v: 'yes'}[view([2, 3])])", "true false yes\n"); }
+    #[test] fn test_view_ordering() { run_str("[view([2]), view([1, 2]), view([1])] . sort . print", "[view([1]), view([1, 2]), view([2])]\n"); }
+    #[test] fn test_view_bool() { run_str("print(bool(view([1])), bool(view([1])[1:]))", "true false\n"); }
+    #[test] fn test_view_unpack() { run_str("let a, *b = view([1, 2, 3]) ; print(a, b)", "1 view([2, 3])\n"); }
+    #[test] fn test_view_recursive_tail() { run_str("fn f(xs) -> if xs then xs[0] + f(xs[1:]) else 0 ; f(view(range(20))) . print", "190\n"); }
+    #[test] fn test_plist_empty() { run_str("print(plist(), len(plist()), bool(plist()), plist() . typeof)", "plist([]) 0 false plist\n"); }
+    #[test] fn test_plist_of_iterable() { run_str("print(plist([1, 2, 3]), plist(1, 2, 3), plist('ab'))", "plist([1, 2, 3]) plist([1, 2, 3]) plist(['a', 'b'])\n"); }
+    #[test] fn test_plist_index() { run_str("let p = plist(range(100)) ; print(p[0], p[31], p[32], p[99], p[-1], len(p))", "0 31 32 99 99 100\n"); }
+    #[test] fn test_plist_index_out_of_bounds() { run_str("plist(1, 2)[2]", "Index '2' is out of bounds for list of length [0, 2)\n  at: line 1 (<test>)\n\n1 | plist(1, 2)[2]\n2 |            ^^^\n"); }
+    #[test] fn test_plist_is_immutable() { run_str("let p = plist(1, 2) ; p[0] = 3", "TypeError: Cannot index 'plist([1, 2])' of type 'plist'\n  at: line 1 (<test>)\n\n1 | let p = plist(1, 2) ; p[0] = 3\n2 |                            ^\n"); }
+    #[test] fn test_plist_conj() { run_str("let a = plist(1, 2), b = a . conj(3), c = a . conj(4) ; print(a, b, c)", "plist([1, 2]) plist([1, 2, 3]) plist([1, 2, 4])\n"); }
+    #[test] fn test_plist_conj_large() { run_str("let p = plist(range(1056)) . conj('x') ; print(len(p), p[1055], p[1056], p[1024])", "1057 1055 x 1024\n"); }
+    #[test] fn test_plist_assoc() { run_str("let a = plist(range(40)), b = a . assoc(33, 'x') . assoc(-1, 'y') ; print(a[33], a[39], b[33], b[39], b[32])", "33 39 x y 32\n"); }
+    #[test] fn test_plist_assoc_at_end() { run_str("plist(1, 2) . assoc(2, 3) . print", "plist([1, 2, 3])\n"); }
+    #[test] fn test_plist_assoc_out_of_bounds() { run_str("plist(1, 2) . assoc(3, 3)", "Index '3' is out of bounds for list of length [0, 2)\n  at: line 1 (<test>)\n\n1 | plist(1, 2) . assoc(3, 3)\n2 |             ^^^^^^^^^^^^^\n"); }
+    #[test] fn test_plist_dissoc() { run_str("let a = plist(1, 2, 3) ; print(a . dissoc(1), a . dissoc(-1), a)", "plist([1, 3]) plist([1, 2]) plist([1, 2, 3])\n"); }
+    #[test] fn test_plist_iter() { run_str("for x in plist(1, 2) . conj(3) { print(x) }", "1\n2\n3\n"); }
+    #[test] fn test_plist_in() { run_str("let p = plist(1, 2) ; print(1 in p, 3 in p, 3 not in p)", "true false true\n"); }
+    #[test] fn test_plist_is() { run_str("print(plist() is plist, [] is plist, plist() is list)", "true false false\n"); }
+    #[test] fn test_plist_eq_and_hash() { run_str("let p = plist(1, 2) ; print(p == plist([1]) . conj(2), p == [1, 2], {p: 'yes'}[plist(1, 2)])", "true false yes\n"); }
+    #[test] fn test_plist_ordering() { run_str("[plist([2]), plist(1, 2), plist([1])] . sort . print", "[plist([1]), plist([1, 2]), plist([2])]\n"); }
+    #[test] fn test_plist_to_list() { run_str("let p = plist(1, 2), x = list(p) ; x.push(3) ; print(p, x)", "plist([1, 2]) [1, 2, 3]\n"); }
+    #[test] fn test_plist_unpack_and_slice() { run_str("let a, *b = plist(1, 2, 3) ; print(a, b, plist(1, 2, 3)[::-1])", "1 plist([2, 3]) plist([3, 2, 1])\n"); }
+    #[test] fn test_plist_recursive_repr() { run_str("let x = [] ; let p = plist([x]) ; x.push(p) ; print(x, p)", "[plist([[...]])] plist([[plist(...)]])\n"); }
+    #[test] fn test_pdict_empty() { run_str("print(pdict(), len(pdict()), bool(pdict()), pdict() . typeof)", "pdict({}) 0 false pdict\n"); }
+    #[test] fn test_pdict_of_pairs() { run_str("let d = pdict([(1, 'a'), (2, 'b')]) ; print(len(d), d[1], d[2])", "2 a b\n"); }
+    #[test] fn test_pdict_index_not_present() { run_str("pdict()['a']", "ValueError: Key 'a' of type 'str' not found in dictionary\n  at: line 1 (<test>)\n\n1 | pdict()['a']\n2 |        ^^^^^\n"); }
+    #[test] fn test_pdict_large() { run_str("let d = pdict(zip(range(500), range(500, 1000))) ; print(len(d), d[0], d[123], d[499], 500 in d)", "500 500 623 999 false\n"); }
+    #[test] fn test_pdict_assoc() { run_str("let a = pdict([('x', 1)]), b = a . assoc('y', 2), c = b . assoc('x', 3) ; print(len(a), len(b), len(c), a['x'], c['x'], c['y'], 'y' in a)", "1 2 2 1 3 2 false\n"); }
+    #[test] fn test_pdict_conj() { run_str("let d = pdict() . conj((1, 2)) . conj([3, 4]) ; print(len(d), d[1], d[3])", "2 2 4\n"); }
+    #[test] fn test_pdict_dissoc() { run_str("let a = pdict(zip(range(100), range(100))), b = a . dissoc(50) . dissoc(200) ; print(len(a), len(b), 50 in a, 50 in b, b[51])", "100 99 true false 51\n"); }
+    #[test] fn test_pdict_dissoc_all() { run_str("let d = pdict([(1, 2), (3, 4)]) . dissoc(1) . dissoc(3) ; print(d, len(d), d == pdict())", "pdict({}) 0 true\n"); }
+    #[test] fn test_pdict_iter() { run_str("pdict(zip(range(50), range(50))) . map(fn((k, v)) -> k * v) . sum . print", "40425\n"); }
+    #[test] fn test_pdict_eq_and_hash() { run_str("let a = pdict([(1, 2), (3, 4)]), b = pdict([(3, 4)]) . assoc(1, 2) ; print(a == b, a == {1: 2, 3: 4}, {a: 'yes'}[b])", "true false yes\n"); }
+    #[test] fn test_pdict_is() { run_str("print(pdict() is pdict, {} is pdict, pdict() is dict)", "true false false\n"); }
+    #[test] fn test_pdict_freezes_keys() { run_str("let k = [1], d = pdict() . assoc(k, 1) ; k . push(2)", "ValueError: Cannot modify '[1]' of type 'list', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let k = [1], d = pdict() . assoc(k, 1) ; k . push(2)\n2 |                                            ^^^^^^^^^\n"); }
+    #[test] fn test_pdict_is_immutable() { run_str("let d = pdict() ; d['a'] = 1", "TypeError: Cannot index 'pdict({})' of type 'pdict'\n  at: line 1 (<test>)\n\n1 | let d = pdict() ; d['a'] = 1\n2 |                          ^\n"); }
+    #[test] fn test_conj_not_persistent() { run_str("conj(1, [])", "TypeError: Expected '[]' of type 'list' to be a plist or pdict\n  at: line 1 (<test>)\n\n1 | conj(1, [])\n2 |     ^^^^^^^\n"); }
     #[test] fn test_set_literal_empty() { run_str("{} is set . print ; {} . print", "true\n{}\n"); }
     #[test] fn test_set_literal_single() { run_str("{'hello'} . print", "{'hello'}\n"); }
     #[test] fn test_set_literal_multiple() { run_str("{1, 2, 3, 4} . print", "{1, 2, 3, 4}\n"); }
@@ -1481,6 +3689,16 @@ mod tests {
     #[test] fn test_dict_remove_yes() { run_str("let x = {1: 'a', 2: 'b', 3: 'c'}, y = x . remove(2) ; (x, y) . print", "({1: 'a', 3: 'c'}, true)\n"); }
     #[test] fn test_dict_remove_no() { run_str("let x = {1: 'a', 2: 'b', 3: 'c'}, y = x . remove(5) ; (x, y) . print", "({1: 'a', 2: 'b', 3: 'c'}, false)\n"); }
     #[test] fn test_dict_clear() { run_str("let x = {1: 'a', 2: 'b', 3: 'c'} ; x . clear ; x . print", "{}\n"); }
+    #[test] fn test_list_with_capacity() { run_str("let x = list_with_capacity(100) ; x . push(1) ; x . print", "[1]\n"); }
+    #[test] fn test_list_with_capacity_negative() { run_str("list_with_capacity(-1)", "ValueError: Expected value '-1: int' to be non-negative\n  at: line 1 (<test>)\n\n1 | list_with_capacity(-1)\n2 |                   ^^^^\n"); }
+    #[test] fn test_list_with_capacity_too_large() { run_str("list_with_capacity(1 << 61)", "ValueError: Cannot reserve capacity for 2305843009213693952 elements\n  at: line 1 (<test>)\n\n1 | list_with_capacity(1 << 61)\n2 |                   ^^^^^^^^^\n"); }
+    #[test] fn test_dict_with_capacity() { run_str("let x = dict_with_capacity(100) ; x[1] = 'a' ; x . print", "{1: 'a'}\n"); }
+    #[test] fn test_dict_with_capacity_too_large() { run_str("dict_with_capacity(1 << 61)", "ValueError: Cannot reserve capacity for 2305843009213693952 elements\n  at: line 1 (<test>)\n\n1 | dict_with_capacity(1 << 61)\n2 |                   ^^^^^^^^^\n"); }
+    #[test] fn test_reserve() { run_str("[[1], (1,), {1}, {1: 2}, heap([1])] . map(fn(x) -> reserve(x, 100)) . print", "[[1], (1), {1}, {1: 2}, [1]]\n"); }
+    #[test] fn test_reserve_returns_collection() { run_str("let x = [] ; reserve(x, 10) . push(1) ; x . print", "[1]\n"); }
+    #[test] fn test_reserve_too_large() { run_str("reserve([], 1 << 61)", "ValueError: Cannot reserve capacity for 2305843009213693952 elements\n  at: line 1 (<test>)\n\n1 | reserve([], 1 << 61)\n2 |        ^^^^^^^^^^^^^\n"); }
+    #[test] fn test_reserve_not_collection() { run_str("reserve('abc', 10)", "TypeError: Expected 'abc' of type 'str' to be an iterable\n  at: line 1 (<test>)\n\n1 | reserve('abc', 10)\n2 |        ^^^^^^^^^^^\n"); }
+    #[test] fn test_literal_unroll_sized() { run_str("[0, ...range(3), ...'ab', ...[4, 5]] . print", "[0, 0, 1, 2, 'a', 'b', 4, 5]\n"); }
     #[test] fn test_dict_from_enumerate() { run_str("'hey' . enumerate . dict . print", "{0: 'h', 1: 'e', 2: 'y'}\n"); }
     #[test] fn test_dict_peek() { run_str("let x = {1: 'a', 2: 'b', 3: 'c'}, y = x . peek ; (x, y) . print", "({1: 'a', 2: 'b', 3: 'c'}, (1, 'a'))\n"); }
     #[test] fn test_dict_default_with_query() { run_str("let d = dict() . default(3) ; d[0] ; d.print", "{0: 3}\n"); }
@@ -1492,6 +3710,14 @@ mod tests {
     #[test] fn test_dict_insert_self_as_value() { run_str("let x = dict() ; x['yes'] = x", ""); }
     #[test] fn test_dict_recursive_key_index() { run_str("let x = dict() ; x[x] = 'yes' ; x.print", "ValueError: Cannot create recursive hash based collection from '{{...}: 'yes'}' of type 'dict'\n  at: line 1 (<test>)\n\n1 | let x = dict() ; x[x] = 'yes' ; x.print\n2 |                       ^\n"); }
     #[test] fn test_dict_recursive_key_insert() { run_str("let x = dict() ; x.insert(x, 'yes') ; x.print", "ValueError: Cannot create recursive hash based collection from '{{...}: 'yes'}' of type 'dict'\n  at: line 1 (<test>)\n\n1 | let x = dict() ; x.insert(x, 'yes') ; x.print\n2 |                   ^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_dict_key_is_frozen() { run_str("let k = [1] ; let d = {k: 'yes'} ; k.push(2)", "ValueError: Cannot modify '[1]' of type 'list', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let k = [1] ; let d = {k: 'yes'} ; k.push(2)\n2 |                                     ^^^^^^^^\n"); }
+    #[test] fn test_dict_key_is_frozen_after_index() { run_str("let k = [1] ; let d = dict() ; d[k] = 'yes' ; k[0] = 2", "ValueError: Cannot modify '[1]' of type 'list', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let k = [1] ; let d = dict() ; d[k] = 'yes' ; k[0] = 2\n2 |                                                    ^\n"); }
+    #[test] fn test_dict_key_is_frozen_nested() { run_str("let k = [[1]] ; let d = {k: 'yes'} ; k[0].push(2)", "ValueError: Cannot modify '[1]' of type 'list', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let k = [[1]] ; let d = {k: 'yes'} ; k[0].push(2)\n2 |                                          ^^^^^^^^\n"); }
+    #[test] fn test_dict_key_is_frozen_struct() { run_str("struct P(x) ; let k = P(1) ; let d = {k: 'yes'} ; k->x = 2", "ValueError: Cannot modify 'P(x=1)' of type 'struct', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | struct P(x) ; let k = P(1) ; let d = {k: 'yes'} ; k->x = 2\n2 |                                                        ^\n"); }
+    #[test] fn test_dict_value_is_not_frozen() { run_str("let v = [1] ; let d = {1: v} ; v.push(2) ; d . print", "{1: [1, 2]}\n"); }
+    #[test] fn test_dict_key_copy_is_not_frozen() { run_str("let k = [1] ; let d = {k: 'yes'} ; let c = list(k) ; c.push(2) ; c . print", "[1, 2]\n"); }
+    #[test] fn test_set_element_is_frozen() { run_str("let k = {1} ; let s = set() ; s.push(k) ; k.push(2)", "ValueError: Cannot modify '{1}' of type 'set', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let k = {1} ; let s = set() ; s.push(k) ; k.push(2)\n2 |                                            ^^^^^^^^\n"); }
+    #[test] fn test_set_element_is_frozen_via_set() { run_str("let k = [1] ; let s = set([k]) ; k.clear()", "ValueError: Cannot modify '[1]' of type 'list', as it is used as a key in a dict or set\n  at: line 1 (<test>)\n\n1 | let k = [1] ; let s = set([k]) ; k.clear()\n2 |                                   ^^^^^^^^\n"); }
     #[test] fn test_dict_recursive_value_repr() { run_str("let x = dict() ; x['yes'] = x ; x.print", "{'yes': {...}}\n"); }
     #[test] fn test_heap_empty_constructor() { run_str("heap() . print", "[]\n"); }
     #[test] fn test_heap_from_list() { run_str("let h = [1, 7, 3, 2, 7, 6] . heap; h . print", "[1, 2, 3, 7, 7, 6]\n"); }
@@ -1512,6 +3738,12 @@ mod tests {
     #[test] fn test_assert_messages_are_lazy() { run_str("assert true : exit ; print('should reach here')", "should reach here\n"); }
     #[test] fn test_len_list() { run_str("[1, 2, 3] . len . print", "3\n"); }
     #[test] fn test_len_str() { run_str("'12345' . len . print", "5\n"); }
+    #[test] fn test_len_infinite() { run_str("repeat(1) . len", "ValueError: Expected 'repeat(1)' of type 'repeat' to be a finite iterable\n  at: line 1 (<test>)\n\n1 | repeat(1) . len\n2 |           ^^^^^\n"); }
+    #[test] fn test_is_empty() { run_str("print(is_empty([]), is_empty('a'), is_empty(range(0)), is_empty(chain([], [])))", "true false true true\n"); }
+    #[test] fn test_is_empty_infinite() { run_str("print(is_empty(cycle([1])), is_empty(cycle([])), is_empty(repeat(1)))", "false true false\n"); }
+    #[test] fn test_is_empty_not_iterable() { run_str("is_empty(3)", "TypeError: Expected '3' of type 'int' to be an iterable\n  at: line 1 (<test>)\n\n1 | is_empty(3)\n2 |         ^^^\n"); }
+    #[test] fn test_count_all() { run_str("print(count_all([1, 2]), count_all(chain([1], 'ab')), count_all(enumerate('abc')))", "2 3 3\n"); }
+    #[test] fn test_count_all_infinite() { run_str("count_all(repeat(1))", "ValueError: Expected 'repeat(1)' of type 'repeat' to be a finite iterable\n  at: line 1 (<test>)\n\n1 | count_all(repeat(1))\n2 |          ^^^^^^^^^^^\n"); }
     #[test] fn test_sum_list() { run_str("[1, 2, 3, 4] . sum . print", "10\n"); }
     #[test] fn test_sum_values() { run_str("sum(1, 3, 5, 7) . print", "16\n"); }
     #[test] fn test_sum_no_arg() { run_str("sum()", "Incorrect number of arguments for fn sum(...), got 0\n  at: line 1 (<test>)\n\n1 | sum()\n2 |    ^^\n"); }
@@ -1520,6 +3752,18 @@ mod tests {
     #[test] fn test_map_lambda() { run_str("[-1, 2, -3] . map(fn(x) -> x . abs) . print", "[1, 2, 3]\n") }
     #[test] fn test_filter() { run_str("[2, 3, 4, 5, 6] . filter (>3) . print", "[4, 5, 6]\n") }
     #[test] fn test_filter_lambda() { run_str("[2, 3, 4, 5, 6] . filter (fn(x) -> x % 2 == 0) . print", "[2, 4, 6]\n") }
+    #[test] fn test_partition() { run_str("partition(fn(x) -> x % 2 == 0, range(7)) . print", "([0, 2, 4, 6], [1, 3, 5])\n"); }
+    #[test] fn test_partition_empty() { run_str("[] . partition(>0) . print", "([], [])\n"); }
+    #[test] fn test_span() { run_str("[1, 2, 3, 1, 2] . span(<3) . print ; [1, 2] . span(<3) . print", "([1, 2], [3, 1, 2])\n([1, 2], [])\n"); }
+    #[test] fn test_span_unpack() { run_str("let a, b = 'hi there' . span(!= ' ') ; print(a, b)", "['h', 'i'] [' ', 't', 'h', 'e', 'r', 'e']\n"); }
+    #[test] fn test_take_while() { run_str("[1, 2, 3, 1, 2] . take_while(<3) . print ; [] . take_while(<3) . print", "[1, 2]\n[]\n"); }
+    #[test] fn test_take_while_infinite() { run_str("cycle([1, 2, 3]) . take_while(<3) . print", "[1, 2]\n"); }
+    #[test] fn test_take_while_stops_invoking() { run_str("[1, 2, 3, 1, 2] . take_while(fn(x) { print('f', x) ; x < 2 }) . print", "f 1\nf 2\n[1]\n"); }
+    #[test] fn test_drop_while() { run_str("[1, 2, 3, 1, 2] . drop_while(<3) . print ; [1, 2] . drop_while(<3) . print", "[3, 1, 2]\n[]\n"); }
+    #[test] fn test_drop_while_stops_invoking() { run_str("'  hello' . drop_while(fn(c) { print('f', repr(c)) ; c == ' ' }) . reduce(+) . print", "f ' '\nf ' '\nf 'h'\nhello\n"); }
+    #[test] fn test_count_value() { run_str("count(2, [1, 2, 3, 2]) . print ; 'banana' . count('a') . print", "2\n3\n"); }
+    #[test] fn test_count_predicate() { run_str("range(10) . count(fn(x) -> x % 3 == 0) . print", "4\n"); }
+    #[test] fn test_tally() { run_str("[1, 2, 3, 4, 5] . tally(>2) . print ; [] . tally(>2) . print", "(3, 2)\n(0, 0)\n"); }
     #[test] fn test_reduce_with_operator() { run_str("[1, 2, 3, 4, 5, 6] . reduce (*) . print", "720\n"); }
     #[test] fn test_reduce_with_function() { run_str("[1, 2, 3, 4, 5, 6] . reduce (fn(a, b) -> a * b) . print", "720\n"); }
     #[test] fn test_reduce_with_unary_operator() { run_str("[1, 2, 3] . reduce (!) . print", "Incorrect number of arguments for fn (!)(x), got 2\n  at: line 1 (<test>)\n\n1 | [1, 2, 3] . reduce (!) . print\n2 |           ^^^^^^^^^^^^\n"); }
@@ -1534,11 +3778,18 @@ mod tests {
     #[test] fn test_group_by_int_by_one_empty_iterable() { run_str("[] . group_by(1) . print", "[]\n"); }
     #[test] fn test_group_by_int_by_three_empty_iterable() { run_str("[] . group_by(3) . print", "[]\n"); }
     #[test] fn test_group_by_int_by_three_with_remainder() { run_str("[1, 2, 3, 4] . group_by(3) . print", "[(1, 2, 3), (4)]\n"); }
+    #[test] fn test_group_by_int_very_large() { run_str("[1, 2] . group_by(3000000000) . print", "[(1, 2)]\n"); }
     #[test] fn test_group_by_int_by_three_not_enough() { run_str("[1, 2] . group_by(3) . print", "[(1, 2)]\n"); }
     #[test] fn test_group_by_function_empty_iterable() { run_str("[] . group_by(fn(x) -> nil) . print", "{}\n"); }
     #[test] fn test_group_by_function_all_same_keys() { run_str("[1, 2, 3, 4] . group_by(fn(x) -> nil) . print", "{nil: (1, 2, 3, 4)}\n"); }
     #[test] fn test_group_by_function_all_different_keys() { run_str("[1, 2, 3, 4] . group_by(fn(x) -> x) . print", "{1: (1), 2: (2), 3: (3), 4: (4)}\n"); }
     #[test] fn test_group_by_function_remainder_by_three() { run_str("[1, 2, 3, 4, 5] . group_by(%3) . print", "{1: (1, 4), 2: (2, 5), 0: (3)}\n"); }
+    #[test] fn test_index_by() { run_str("['apple', 'bob', 'carrot'] . index_by(len) . print", "{5: 'apple', 3: 'bob', 6: 'carrot'}\n"); }
+    #[test] fn test_index_by_duplicate() { run_str("['bob', 'cat'] . index_by(len)", "ValueError: Duplicate key '3' of type 'int' in dictionary\n  at: line 1 (<test>)\n\n1 | ['bob', 'cat'] . index_by(len)\n2 |                ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_index_by_not_strict() { run_str("index_by(len, ['ab', 'cd', 'e'], false) . print", "{2: 'cd', 1: 'e'}\n"); }
+    #[test] fn test_invert() { run_str("invert({1: 'a', 2: 'b'}) . print ; invert({1: 'a', 2: 'a'}, false) . print", "{'a': 1, 'b': 2}\n{'a': 2}\n"); }
+    #[test] fn test_invert_duplicate() { run_str("invert({1: 'a', 2: 'a'})", "ValueError: Duplicate key 'a' of type 'str' in dictionary\n  at: line 1 (<test>)\n\n1 | invert({1: 'a', 2: 'a'})\n2 |       ^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_invert_not_dict() { run_str("invert([1])", "TypeError: Expected '[1]' of type 'list' to be a dict\n  at: line 1 (<test>)\n\n1 | invert([1])\n2 |       ^^^^^\n"); }
     #[test] fn test_reverse() { run_str("[8, 1, 2, 6, 3, 2, 3] . reverse . print", "[3, 2, 3, 6, 2, 1, 8]\n"); }
     #[test] fn test_range_1() { run_str("range(3) . list . print", "[0, 1, 2]\n"); }
     #[test] fn test_range_2() { run_str("range(3, 7) . list . print", "[3, 4, 5, 6]\n"); }
@@ -1554,6 +3805,33 @@ mod tests {
     #[test] fn test_enumerate_1() { run_str("[] . enumerate . list . print", "[]\n"); }
     #[test] fn test_enumerate_2() { run_str("[1, 2, 3] . enumerate . list . print", "[(0, 1), (1, 2), (2, 3)]\n"); }
     #[test] fn test_enumerate_3() { run_str("'foobar' . enumerate . list . print", "[(0, 'f'), (1, 'o'), (2, 'o'), (3, 'b'), (4, 'a'), (5, 'r')]\n"); }
+    #[test] fn test_cycle() { run_str("cycle([1, 2, 3]) . take(7) . print", "[1, 2, 3, 1, 2, 3, 1]\n"); }
+    #[test] fn test_cycle_empty() { run_str("cycle([]) . take(5) . print", "[]\n"); }
+    #[test] fn test_cycle_with_zip() { run_str("zip(range(5), cycle('ab')) . print", "[(0, 'a'), (1, 'b'), (2, 'a'), (3, 'b'), (4, 'a')]\n"); }
+    #[test] fn test_cycle_len() { run_str("len(cycle([1]))", "ValueError: Expected 'cycle([1])' of type 'cycle' to be a finite iterable\n  at: line 1 (<test>)\n\n1 | len(cycle([1]))\n2 |    ^^^^^^^^^^^^\n"); }
+    #[test] fn test_cycle_not_iterable() { run_str("cycle(1)", "TypeError: Expected '1' of type 'int' to be an iterable\n  at: line 1 (<test>)\n\n1 | cycle(1)\n2 |      ^^^\n"); }
+    #[test] fn test_repeat() { run_str("repeat('a', 3) . list . print", "['a', 'a', 'a']\n"); }
+    #[test] fn test_repeat_infinite() { run_str("repeat(0) . take(4) . print", "[0, 0, 0, 0]\n"); }
+    #[test] fn test_repeat_len() { run_str("repeat(nil, 4) . len . print", "4\n"); }
+    #[test] fn test_chain() { run_str("chain([1, 2], 'ab', range(3)) . list . print", "[1, 2, 'a', 'b', 0, 1, 2]\n"); }
+    #[test] fn test_chain_of_iterable() { run_str("chain([[1], [2, 3], []]) . list . print", "[1, 2, 3]\n"); }
+    #[test] fn test_chain_empty() { run_str("chain() . list . print", "[]\n"); }
+    #[test] fn test_chain_reverse() { run_str("chain([1, 2, 3], [4, 5]) . reverse . print", "[5, 4, 3, 2, 1]\n"); }
+    #[test] fn test_lazy_iter_repr() { run_str("print(cycle([1, 2]), repeat(1, 2), repeat(nil), chain([1], (2, 3)))", "cycle([1, 2]) repeat(1, 2) repeat(nil) chain([1], (2, 3))\n"); }
+    #[test] fn test_lazy_iter_typeof() { run_str("print(typeof(cycle([])), typeof(repeat(1)), typeof(chain()))", "cycle repeat chain\n"); }
+    #[test] fn test_take() { run_str("take(2, [1, 2, 3]) . print ; take(5, 'abc') . print", "[1, 2]\n['a', 'b', 'c']\n"); }
+    #[test] fn test_first() { run_str("first([1, 2]) . print ; first(cycle('xy')) . print ; first({3, 4}) . print", "1\nx\n3\n"); }
+    #[test] fn test_first_empty() { run_str("first([])", "ValueError: Expected value to be a non empty iterable\n  at: line 1 (<test>)\n\n1 | first([])\n2 |      ^^^^\n"); }
+    #[test] fn test_last() { run_str("last([1, 2]) . print ; last('abc') . print ; last(chain([1], [2, 3])) . print", "2\nc\n3\n"); }
+    #[test] fn test_last_range() { run_str("print(last(range(10)), last(range(1, 10, 3)), last(range(10, 0, -3)))", "9 7 4\n"); }
+    #[test] fn test_last_enumerate() { run_str("print(last(enumerate('xyz')), last(enumerate([1, 2])))", "(2, 'z') (1, 2)\n"); }
+    #[test] fn test_last_infinite() { run_str("last(cycle([1]))", "ValueError: Expected 'cycle([1])' of type 'cycle' to be a finite iterable\n  at: line 1 (<test>)\n\n1 | last(cycle([1]))\n2 |     ^^^^^^^^^^^^\n"); }
+    #[test] fn test_nth() { run_str("nth(5, cycle([1, 2, 3])) . print ; nth(1, {1: 2, 3: 4}) . print", "3\n(3, 4)\n"); }
+    #[test] fn test_nth_out_of_bounds() { run_str("nth(5, [1, 2])", "Index '5' is out of bounds for list of length [0, 2)\n  at: line 1 (<test>)\n\n1 | nth(5, [1, 2])\n2 |    ^^^^^^^^^^^\n"); }
+    #[test] fn test_single() { run_str("single([7]) . print ; single('a') . print", "7\na\n"); }
+    #[test] fn test_single_too_many() { run_str("single([1, 2])", "ValueError: Expected '[1, 2]' of type 'list' to have exactly one element\n  at: line 1 (<test>)\n\n1 | single([1, 2])\n2 |       ^^^^^^^^\n"); }
+    #[test] fn test_single_empty() { run_str("single(repeat(1, 0))", "ValueError: Expected 'repeat(1, 0)' of type 'repeat' to have exactly one element\n  at: line 1 (<test>)\n\n1 | single(repeat(1, 0))\n2 |       ^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_rindex_of_range() { run_str("rindex_of(fn(x) -> x < 5, range(10)) . print", "4\n"); }
     #[test] fn test_sqrt() { run_str("[0, 1, 4, 9, 25, 3, 6, 8, 13] . map(sqrt) . print", "[0, 1, 2, 3, 5, 1, 2, 2, 3]\n"); }
     #[test] fn test_sqrt_very_large() { run_str("[1 << 61, (1 << 61) + 1, (1 << 61) - 1] . map(sqrt) . print", "[1518500249, 1518500249, 1518500249]\n"); }
     #[test] fn test_gcd() { run_str("gcd(12, 8) . print", "4\n"); }
@@ -1563,15 +3841,31 @@ mod tests {
     #[test] fn test_flat_map_identity() { run_str("['hi', 'bob'] . flat_map(fn(i) -> i) . print", "['h', 'i', 'b', 'o', 'b']\n"); }
     #[test] fn test_flat_map_with_func() { run_str("['hello', 'bob'] . flat_map(fn(i) -> i[2:]) . print", "['l', 'l', 'o', 'b']\n"); }
     #[test] fn test_concat() { run_str("[[], [1], [2, 3], [4, 5, 6], [7, 8, 9, 0]] . concat . print", "[1, 2, 3, 4, 5, 6, 7, 8, 9, 0]\n"); }
+    #[test] fn test_flatten() { run_str("flatten([1, [2, [3, [4]]], 'ab', (5, 6)]) . print", "[1, 2, [3, [4]], 'ab', 5, 6]\n"); }
+    #[test] fn test_flatten_depth() { run_str("flatten([1, [2, [3, [4]]]], 2) . print ; flatten([1, [2]], 0) . print", "[1, 2, 3, [4]]\n[1, [2]]\n"); }
+    #[test] fn test_flatten_fully() { run_str("flatten([1, [2, [3, [4]]], ['cd']], nil) . print", "[1, 2, 3, 4, 'cd']\n"); }
+    #[test] fn test_flatten_negative_depth() { run_str("flatten([], -1)", "ValueError: Expected value '-1: int' to be non-negative\n  at: line 1 (<test>)\n\n1 | flatten([], -1)\n2 |        ^^^^^^^^\n"); }
+    #[test] fn test_flatten_recursive() { run_str("let x = [1] ; x.push(x) ; flatten(x) . print ; flatten(x, nil)", "[1, 1, [1, [...]]]\nValueError: Cannot fully flatten recursive collection '[1, [...]]' of type 'list'\n  at: line 1 (<test>)\n\n1 | let x = [1] ; x.push(x) ; flatten(x) . print ; flatten(x, nil)\n2 |                                                       ^^^^^^^^\n"); }
+    #[test] fn test_unique() { run_str("unique([3, 1, 3, 2, 1]) . print ; unique('mississippi') . print", "[3, 1, 2]\n['m', 'i', 's', 'p']\n"); }
+    #[test] fn test_unique_recursive() { run_str("let x = [] ; x.push(x) ; unique([1, x])", "ValueError: Cannot create recursive hash based collection from '[[...]]' of type 'list'\n  at: line 1 (<test>)\n\n1 | let x = [] ; x.push(x) ; unique([1, x])\n2 |                                ^^^^^^^^\n"); }
+    #[test] fn test_unique_by() { run_str("unique_by(fn(x) -> x % 3, range(10)) . print ; unique_by(len, ['a', 'bb', 'c', 'dd', 'eee']) . print", "[0, 1, 2]\n['a', 'bb', 'eee']\n"); }
+    #[test] fn test_dedup() { run_str("dedup([1, 1, 2, 2, 2, 1, 3, 3]) . print ; dedup('aabbbc') . print ; dedup([]) . print", "[1, 2, 1, 3]\n['a', 'b', 'c']\n[]\n"); }
     #[test] fn test_zip() { run_str("zip([1, 2, 3, 4, 5], 'hello') . print", "[(1, 'h'), (2, 'e'), (3, 'l'), (4, 'l'), (5, 'o')]\n"); }
     #[test] fn test_zip_with_empty() { run_str("zip('hello', []) . print", "[]\n"); }
     #[test] fn test_zip_with_longer_last() { run_str("zip('hi', 'hello', 'hello the world!') . print", "[('h', 'h', 'h'), ('i', 'e', 'e')]\n"); }
     #[test] fn test_zip_with_longer_first() { run_str("zip('hello the world!', 'hello', 'hi') . print", "[('h', 'h', 'h'), ('e', 'e', 'i')]\n"); }
     #[test] fn test_zip_of_list() { run_str("[[1, 2, 3], [4, 5, 6], [7, 8, 9]] . zip . print", "[(1, 4, 7), (2, 5, 8), (3, 6, 9)]\n"); }
+    #[test] fn test_transpose() { run_str("transpose([[1, 2, 3], [4, 5, 6]]) . print ; transpose(['ab', 'cd']) . print", "([1, 4], [2, 5], [3, 6])\n(['a', 'c'], ['b', 'd'])\n"); }
+    #[test] fn test_transpose_ragged() { run_str("transpose([[1, 2], [3]])", "ValueError: Expected all rows to have length 2, got a row of length 1\n  at: line 1 (<test>)\n\n1 | transpose([[1, 2], [3]])\n2 |          ^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_unzip() { run_str("unzip(zip([1, 2, 3], 'abc')) . print", "([1, 2, 3], ['a', 'b', 'c'])\n"); }
+    #[test] fn test_unzip_empty() { run_str("unzip([]) . print", "()\n"); }
+    #[test] fn test_unzip_unpack() { run_str("let xs, ys = unzip([(1, 2), (3, 4)]) ; print(xs, ys)", "[1, 3] [2, 4]\n"); }
     #[test] fn test_permutations_empty() { run_str("[] . permutations(3) . print", "[]\n"); }
     #[test] fn test_permutations_n_larger_than_size() { run_str("[1, 2, 3] . permutations(5) . print", "[]\n"); }
+    #[test] fn test_permutations_n_very_large() { run_str("[1, 2, 3] . permutations(3000000000) . print", "[]\n"); }
     #[test] fn test_permutations() { run_str("[1, 2, 3] . permutations(2) . print", "[(1, 2), (1, 3), (2, 1), (2, 3), (3, 1), (3, 2)]\n"); }
     #[test] fn test_combinations_empty() { run_str("[] . combinations(3) . print", "[]\n"); }
+    #[test] fn test_combinations_n_very_large() { run_str("[1, 2, 3] . combinations(3000000000) . print", "[]\n"); }
     #[test] fn test_combinations_n_larger_than_size() { run_str("[1, 2, 3] . combinations(5) . print", "[]\n"); }
     #[test] fn test_combinations() { run_str("[1, 2, 3] . combinations(2) . print", "[(1, 2), (1, 3), (2, 3)]\n"); }
     #[test] fn test_replace_regex_1() { run_str("'apples and bananas' . replace('[abe]+', 'o') . print", "opplos ond ononos\n"); }
@@ -1580,8 +3874,18 @@ mod tests {
     #[test] fn test_replace_regex_with_wrong_function() { run_str("'apples and bananas' . replace('apples', argv) . print", "Incorrect number of arguments for fn argv(), got 1\n  at: line 1 (<test>)\n\n1 | 'apples and bananas' . replace('apples', argv) . print\n2 |                      ^^^^^^^^^^^^^^^^^^^^^^^^^\n"); }
     #[test] fn test_replace_regex_with_capture_group() { run_str("'apples and bananas' . replace('([a-z])([a-z]+)', 'yes') . print", "yes yes yes\n"); }
     #[test] fn test_replace_regex_with_capture_group_function() { run_str("'apples and bananas' . replace('([a-z])([a-z]+)', fn((_, a, b)) -> to_upper(a) + b) . print", "Apples And Bananas\n"); }
+    #[test] fn test_replace_regex_with_function_error_stops_replacing() { run_str("'a b c' . replace('[a-z]', fn((c, *_)) { print(c) ; assert c != 'b' ; c })", "a\nb\nAssertion Failed: nil\n  at: line 1 (<test>)\n  at: `fn _((c, *_))` (line 1)\n  caused while evaluating native `replace` with argument ('b')\n\n1 | 'a b c' . replace('[a-z]', fn((c, *_)) { print(c) ; assert c != 'b' ; c })\n2 |                                                            ^^^^^^^^\n"); }
     #[test] fn test_replace_regex_implicit_newline() { run_str("'first\nsecond\nthird\nfourth' . replace('\\n', ', ') . print", "first, second, third, fourth\n"); }
     #[test] fn test_replace_regex_explicit_newline() { run_str("'first\nsecond\nthird\nfourth' . replace('\n', ', ') . print", "first, second, third, fourth\n"); }
+    #[test] fn test_str_unicode_escapes() { run_str(r"'\u{41}\u{1F600}\0' . map(ord) . print", "[65, 128512, 0]\n"); }
+    #[test] fn test_str_repr_escapes_single_quote() { run_str(r#"print(repr("it's"), repr('a"b'))"#, "'it\\'s' 'a\\\"b'\n"); }
+    #[test] fn test_str_repr_escapes_control_characters() { run_str(r"'\0\t\u{1b}\\' . repr . print", "'\\0\\t\\u{1b}\\\\'\n"); }
+    #[test] fn test_str_repr_round_trip() { run_str(r#"let s = 'it\'s "a"\n\t\u{1b}\\' ; print(s . repr . eval == s)"#, "true\n"); }
+    #[test] fn test_raw_str_regex() { run_str(r"'a1 b22' . search(r'\d+') . print", "[('1'), ('22')]\n"); }
+    #[test] fn test_raw_str_no_escapes() { run_str(r"print(r'\n' . len, r'\'' . len, r'\\' . len)", "2 2 2\n"); }
+    #[test] fn test_triple_quoted_str() { run_str(r#"'''it's "quoted"''' . print"#, "it's \"quoted\"\n"); }
+    #[test] fn test_triple_quoted_str_multiline() { run_str("print('''a\n  b\n''')", "a\n  b\n\n"); }
+    #[test] fn test_triple_quoted_str_line_numbers() { run_str("print('''a\n\nb''')\n1 / 0", "Compile Error:\n\nValueError: Expected value to be non-zero\n  at: line 4 (<test>)\n\n4 | 1 / 0\n5 |   ^\n"); }
     #[test] fn test_search_regex_match_all_yes() { run_str("'test' . search('test') . print", "[('test')]\n"); }
     #[test] fn test_search_regex_match_all_no() { run_str("'test' . search('nope') . print", "[]\n"); }
     #[test] fn test_search_regex_match_partial_yes() { run_str("'any and nope and nothing' . search('nope') . print", "[('nope')]\n"); }
@@ -1600,7 +3904,7 @@ mod tests {
     #[test] fn test_split_regex_space() { run_str("'a b c' . split(' ') . print", "['a', 'b', 'c']\n"); }
     #[test] fn test_split_regex_space_duplicates() { run_str("' a  b   c' . split(' ') . print", "['', 'a', '', 'b', '', '', 'c']\n"); }
     #[test] fn test_split_regex_space_any_whitespace() { run_str("' a  b   c' . split(' +') . print", "['', 'a', 'b', 'c']\n"); }
-    #[test] fn test_split_regex_space_any_with_trim() { run_str("' \nabc  \rabc \\r\\n  abc \\t  \t  \t' . trim . split('\\s+') . print", "['abc', 'abc', 'abc']\n"); }
+    #[test] fn test_split_regex_space_any_with_trim() { run_str("' \nabc  \rabc \\r\\n  abc \\t  \t  \t' . trim . split(r'\\s+') . print", "['abc', 'abc', 'abc']\n"); }
     #[test] fn test_split_regex_on_substring() { run_str("'the horse escaped the barn' . split('the') . print", "['', ' horse escaped ', ' barn']\n"); }
     #[test] fn test_split_regex_on_substring_with_or() { run_str("'the horse escaped the barn' . split('(the| )') . print", "['', '', 'horse', 'escaped', '', '', 'barn']\n"); }
     #[test] fn test_split_regex_on_substring_with_wildcard() { run_str("'the horse escaped the barn' . split(' *e *') . print", "['th', 'hors', '', 'scap', 'd th', 'barn']\n"); }
@@ -1608,6 +3912,27 @@ mod tests {
     #[test] fn test_join_single() { run_str("['apples'] . join('test') . print", "apples\n"); }
     #[test] fn test_join_strings() { run_str("'test' . join(' ') . print", "t e s t\n"); }
     #[test] fn test_join_ints() { run_str("[1, 3, 5, 7, 9] . join('') . print", "13579\n"); }
+    #[test] fn test_scan() { run_str("scan('{int},{int} -> {int}', '3,-4 -> +5') . print", "(3, -4, 5)\n"); }
+    #[test] fn test_scan_word_and_str() { run_str("scan('{word} says {str}!', 'alice says hello, world!') . print", "('alice', 'hello, world')\n"); }
+    #[test] fn test_scan_str_matches_shortest() { run_str("scan('{str} {str}', 'a b c') . print ; scan('[{str}]', '[]') . print", "('a', 'b c')\n('')\n"); }
+    #[test] fn test_scan_no_tokens() { run_str("scan('abc', 'abc') . print ; scan('abc', 'abcd') . print", "()\nnil\n"); }
+    #[test] fn test_scan_no_match() { run_str("scan('{int}', 'x') . print ; scan('{int}', '1 ') . print ; scan('{word}', '') . print ; scan('{int}', '99999999999999999999') . print", "nil\nnil\nnil\nnil\n"); }
+    #[test] fn test_scan_literal_regex_characters() { run_str("scan('({int}) * [{word}]?', '(12) * [ab]?') . print", "(12, 'ab')\n"); }
+    #[test] fn test_scan_escaped_braces() { run_str("scan('{{{int}}}', '{7}') . print", "(7)\n"); }
+    #[test] fn test_scan_destructure() { run_str("let x, y = scan('{int} {int}', '1 2') ; print(x + y)", "3\n"); }
+    #[test] fn test_scan_invalid_token() { run_str("scan('{float}', '1.5')", "ValueError: Invalid token '{float}' in scan format, expected one of '{int}', '{word}' or '{str}'\n  at: line 1 (<test>)\n\n1 | scan('{float}', '1.5')\n2 |     ^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_scan_unmatched_brace() { run_str("scan('{int', '1')", "ValueError: Unmatched '{' in scan format, use '{{' for a literal brace\n  at: line 1 (<test>)\n\n1 | scan('{int', '1')\n2 |     ^^^^^^^^^^^^^\n"); }
+    #[test] fn test_template_empty() { run_str("template('', dict()) . print", "\n"); }
+    #[test] fn test_template_no_placeholders() { run_str("template('hello', {'a': 1}) . print", "hello\n"); }
+    #[test] fn test_template_placeholders() { run_str("template('{a} + {b} = {c}', {'a': 1, 'b': 2, 'c': 3}) . print", "1 + 2 = 3\n"); }
+    #[test] fn test_template_uses_str() { run_str("template('{a}, {b}', {'a': 'x', 'b': [1, 'y']}) . print", "x, [1, 'y']\n"); }
+    #[test] fn test_template_escapes() { run_str("template('{{a}} {{{a}}} }}', {'a': 1}) . print", "{a} {1} }\n"); }
+    #[test] fn test_template_nested_dict() { run_str("template('{a.b.c}', {'a': {'b': {'c': 'd'}}}) . print", "d\n"); }
+    #[test] fn test_template_nested_struct() { run_str("struct User(name, age) template('{user.name} is {user.age}', {'user': User('Alice', 30)}) . print", "Alice is 30\n"); }
+    #[test] fn test_template_key_not_present() { run_str("template('{a}', {'b': 1})", "ValueError: Key 'a' of type 'str' not found in dictionary\n  at: line 1 (<test>)\n\n1 | template('{a}', {'b': 1})\n2 |         ^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_template_field_not_present() { run_str("template('{a.b}', {'a': 1})", "TypeError: Cannot get field 'b' on '1' of type 'int'\n  at: line 1 (<test>)\n\n1 | template('{a.b}', {'a': 1})\n2 |         ^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_template_unmatched_open() { run_str("template('{a', {'a': 1})", "ValueError: Unmatched '{' in template string, use '{{' for a literal brace\n  at: line 1 (<test>)\n\n1 | template('{a', {'a': 1})\n2 |         ^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_template_unmatched_close() { run_str("template('a}', {'a': 1})", "ValueError: Unmatched '}' in template string, use '}}' for a literal brace\n  at: line 1 (<test>)\n\n1 | template('a}', {'a': 1})\n2 |         ^^^^^^^^^^^^^^^^\n"); }
     #[test] fn test_find_value_empty() { run_str("[] . find(1) . print", "nil\n"); }
     #[test] fn test_find_func_empty() { run_str("[] . find(==3) . print", "nil\n"); }
     #[test] fn test_find_value_not_found() { run_str("[1, 3, 5, 7] . find(6) . print", "nil\n"); }
@@ -1640,17 +3965,36 @@ mod tests {
     #[test] fn test_rindex_of_func_found() { run_str("[1, 3, 5, 7] . rindex_of(>3) . print", "3\n"); }
     #[test] fn test_rindex_of_value_found_multiple() { run_str("[1, 3, 5, 5, 7, 5, 3, 1] . rindex_of(5) . print", "5\n"); }
     #[test] fn test_rindex_of_func_found_multiple() { run_str("[1, 3, 5, 5, 7, 5, 3, 1] . rindex_of(>3) . print", "5\n"); }
+    #[test] fn test_index_of_start() { run_str("print(index_of(2, [1, 2, 3, 2], 2), index_of(fn(x) -> x > 1, [1, 2, 3, 2], 2), index_of(5, [1, 2], 1))", "3 2 -1\n"); }
+    #[test] fn test_index_of_substring() { run_str("print(index_of('lo', 'hello world'), index_of('xyz', 'abc'), index_of('é', 'aébé', 2))", "3 -1 3\n"); }
+    #[test] fn test_index_of_substring_start() { run_str("print(index_of('o', 'hello world', 5), index_of('', 'abc', 3), index_of('a', 'abc', 9))", "7 3 -1\n"); }
+    #[test] fn test_index_of_negative_start() { run_str("index_of('a', 'abc', -1)", "ValueError: Expected value '-1: int' to be non-negative\n  at: line 1 (<test>)\n\n1 | index_of('a', 'abc', -1)\n2 |         ^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_rindex_of_substring() { run_str("print(rindex_of('o', 'hello world'), rindex_of('ll', 'hello'), rindex_of('zz', 'abc'), rindex_of('é', 'aébé'))", "7 2 -1 3\n"); }
+    #[test] fn test_contains() { run_str("print(contains('ell', 'hello'), contains(3, [1, 2]), 'hello' . contains('ll'))", "true false true\n"); }
     #[test] fn test_min_by_key() { run_str("[[1, 5], [2, 3], [6, 4]] . min_by(fn(i) -> i[1]) . print", "[2, 3]\n"); }
     #[test] fn test_min_by_cmp() { run_str("[[1, 5], [2, 3], [6, 4]] . min_by(fn(a, b) -> a[1] - b[1]) . print", "[2, 3]\n"); }
+    #[test] fn test_min_by_key_invoked_once() { run_str("[3, 1, 2] . min_by(fn(x) { print('key', x) ; x }) . print", "key 3\nkey 1\nkey 2\n1\n"); }
+    #[test] fn test_min_by_cmp_error_stops_comparing() { run_str("[1, 2, 3] . min_by(fn(a, b) { print(a, b) ; assert false })", "1 2\nAssertion Failed: nil\n  at: line 1 (<test>)\n  at: `fn _(a, b)` (line 1)\n  caused while evaluating native `min_by` with arguments 1, 2\n\n1 | [1, 2, 3] . min_by(fn(a, b) { print(a, b) ; assert false })\n2 |                                                    ^^^^^\n"); }
+    #[test] fn test_max_by_key_last_of_equal() { run_str("[[1, 'a'], [2, 'b'], [2, 'c'], [0, 'd']] . max_by(fn(x) -> x[0]) . print ; [[1, 'a'], [1, 'b']] . min_by(fn(x) -> x[0]) . print", "[2, 'c']\n[1, 'a']\n"); }
     #[test] fn test_min_by_wrong_fn() { run_str("[[1, 5], [2, 3], [6, 4]] . min_by(fn() -> 1) . print", "TypeError: Expected '_' of type 'function' to be a '<A, B> fn key(A) -> B' or '<A> cmp(A, A) -> int' function\n  at: line 1 (<test>)\n\n1 | [[1, 5], [2, 3], [6, 4]] . min_by(fn() -> 1) . print\n2 |                          ^^^^^^^^^^^^^^^^^^^\n"); }
     #[test] fn test_max_by_key() { run_str("[[1, 5], [2, 3], [6, 4]] . max_by(fn(i) -> i[1]) . print", "[1, 5]\n"); }
     #[test] fn test_max_by_cmp() { run_str("[[1, 5], [2, 3], [6, 4]] . max_by(fn(a, b) -> a[1] - b[1]) . print", "[1, 5]\n"); }
     #[test] fn test_max_by_wrong_fn() { run_str("[[1, 5], [2, 3], [6, 4]] . max_by(fn() -> 1) . print", "TypeError: Expected '_' of type 'function' to be a '<A, B> fn key(A) -> B' or '<A> cmp(A, A) -> int' function\n  at: line 1 (<test>)\n\n1 | [[1, 5], [2, 3], [6, 4]] . max_by(fn() -> 1) . print\n2 |                          ^^^^^^^^^^^^^^^^^^^\n"); }
+    #[test] fn test_prod() { run_str("prod([1, 2, 3, 4]) . print ; prod(2, 5) . print ; prod([]) . print", "24\n10\n1\n"); }
+    #[test] fn test_prod_not_int() { run_str("prod([1, 'a'])", "TypeError: Expected 'a' of type 'str' to be a int\n  at: line 1 (<test>)\n\n1 | prod([1, 'a'])\n2 |     ^^^^^^^^^^\n"); }
+    #[test] fn test_cumsum() { run_str("[1, 2, 3] . cumsum . print ; cumsum([]) . print", "[1, 3, 6]\n[]\n"); }
+    #[test] fn test_minmax() { run_str("minmax([3, 1, 4, 1, 5]) . print ; minmax('hello') . print ; minmax([7]) . print ; minmax(2, 1) . print", "(1, 5)\n('e', 'o')\n(7, 7)\n(1, 2)\n"); }
+    #[test] fn test_minmax_empty() { run_str("minmax([])", "ValueError: Expected value to be a non empty iterable\n  at: line 1 (<test>)\n\n1 | minmax([])\n2 |       ^^^^\n"); }
     #[test] fn test_sort_by_key() { run_str("[[1, 5], [2, 3], [6, 4]] . sort_by(fn(i) -> i[1]) . print", "[[2, 3], [6, 4], [1, 5]]\n"); }
     #[test] fn test_sort_by_cmp() { run_str("[[1, 5], [2, 3], [6, 4]] . sort_by(fn(a, b) -> a[1] - b[1]) . print", "[[2, 3], [6, 4], [1, 5]]\n"); }
+    #[test] fn test_sort_by_key_is_stable() { run_str("[[2, 'a'], [1, 'b'], [2, 'c'], [1, 'd'], [0, 'e']] . sort_by(fn(x) -> x[0]) . print", "[[0, 'e'], [1, 'b'], [1, 'd'], [2, 'a'], [2, 'c']]\n"); }
+    #[test] fn test_sort_by_cmp_is_stable() { run_str("[[2, 'a'], [1, 'b'], [2, 'c'], [1, 'd'], [0, 'e']] . sort_by(fn(a, b) -> a[0] - b[0]) . print", "[[0, 'e'], [1, 'b'], [1, 'd'], [2, 'a'], [2, 'c']]\n"); }
+    #[test] fn test_sort_by_cmp_error_stops_comparing() { run_str("let n = 0 ; assert_err(fn() -> range(10) . sort_by(fn(a, b) { n += 1 ; assert false })) ; print(n)", "1\n"); }
     #[test] fn test_sort_by_wrong_fn() { run_str("[[1, 5], [2, 3], [6, 4]] . sort_by(fn() -> 1) . print", "TypeError: Expected '_' of type 'function' to be a '<A, B> fn key(A) -> B' or '<A> cmp(A, A) -> int' function\n  at: line 1 (<test>)\n\n1 | [[1, 5], [2, 3], [6, 4]] . sort_by(fn() -> 1) . print\n2 |                          ^^^^^^^^^^^^^^^^^^^^\n"); }
     #[test] fn test_ord() { run_str("'a' . ord . print", "97\n"); }
     #[test] fn test_char() { run_str("97 . char . repr . print", "'a'\n"); }
+    #[test] fn test_constant_folded_natives() { run_str("print(ord('a') + len('abc'), abs(-3), 'x' . to_upper)", "100 3 X\n"); }
+    #[test] fn test_constant_folded_native_error_is_raised_at_runtime() { run_str("print('before')\nprint(ord('ab'))", "before\nTypeError: Expected 'ab' of type 'str' to be a single character string\n  at: line 2 (<test>)\n\n2 | print(ord('ab'))\n3 |          ^^^^^^\n"); }
     #[test] fn test_eval_nil() { run_str("'nil' . eval . print", "nil\n"); }
     #[test] fn test_eval_bool() { run_str("'true' . eval . print", "true\n"); }
     #[test] fn test_eval_int_expression() { run_str("'3 + 4' . eval . print", "7\n"); }
@@ -1665,11 +4009,33 @@ mod tests {
     #[test] fn test_any_yes_all() { run_str("[1, 3, 4, 5] . any(>0) . print", "true\n"); }
     #[test] fn test_any_yes_some() { run_str("[1, 3, 4, 5] . any(>3) . print", "true\n"); }
     #[test] fn test_any_yes_none() { run_str("[1, 3, 4, 5] . any(<0) . print", "false\n"); }
+    #[test] fn test_any_stops_invoking() { run_str("[1, 3, 4, 5] . any(fn(x) { print(x) ; x > 2 }) . print", "1\n3\ntrue\n"); }
     #[test] fn test_typeof_of_basic_types() { run_str("[nil, 0, false, 'test', [], {1}, {1: 2}, heap(), (1, 2), range(30), enumerate([])] . map(typeof) . map(print)", "nil\nint\nbool\nstr\nlist\nset\ndict\nheap\nvector\nrange\nenumerate\n"); }
     #[test] fn test_typeof_functions() { run_str("[range, fn() -> nil, push(3), ((fn(a, b) -> nil)(1))] . map(typeof) . all(==function) . print", "true\n"); }
     #[test] fn test_typeof_struct_constructor() { run_str("struct Foo(a, b) Foo . typeof . print", "function\n"); }
     #[test] fn test_typeof_struct_instance() { run_str("struct Foo(a, b) Foo(1, 2) . typeof . print", "struct Foo(a, b)\n"); }
     #[test] fn test_typeof_slice() { run_str("[:] . typeof . print", "function\n"); }
+    #[test] fn test_doc_of_function() { run_str("/// Adds two numbers\nfn add(a, b) -> a + b\nadd . doc . print", "Adds two numbers\n"); }
+    #[test] fn test_doc_of_function_multiline() { run_str("/// First line\n///\n/// Third line\nfn f() {}\nf . doc . repr . print", "'First line\\n\\nThird line'\n"); }
+    #[test] fn test_doc_of_function_without_doc() { run_str("// Not a doc comment\nfn f() {}\nf . doc . print", "nil\n"); }
+    #[test] fn test_doc_of_async_function() { run_str("/// Async\nasync fn f() {}\nf . doc . print", "Async\n"); }
+    #[test] fn test_doc_of_annotated_function() { run_str("fn id(f) -> f\n/// Annotated\n@id\nfn f() {}\nf . doc . print", "Annotated\n"); }
+    #[test] fn test_doc_of_closure() { run_str("fn outer() { let x = 1\n/// Inner\nfn inner() -> x\ninner }\nouter() . doc . print", "Inner\n"); }
+    #[test] fn test_doc_of_partial_function() { run_str("/// Partial\nfn f(a, b) {}\nf(1) . doc . print", "Partial\n"); }
+    #[test] fn test_doc_of_struct_method() { run_str("struct Foo(x) {\n    /// Method\n    fn bar(self) {}\n}\nFoo->bar . doc . print", "Method\n"); }
+    #[test] fn test_doc_of_non_functions() { run_str("[nil, 1, 'abc', print, fn() -> 1] . map(doc) . print", "[nil, nil, nil, nil, nil]\n"); }
+    #[test] fn test_doc_comment_between_tokens() { run_str("let x = 1 +\n/// stray\n2\nprint(x)", "3\n"); }
+    #[test] fn test_doc_comment_does_not_leak_to_next_function() { run_str("/// Doc\nlet x = 1\nfn f() {}\nf . doc . print", "nil\n"); }
+    #[test] fn test_help_index() { run_str("help()", format!("{}\n", NativeFunction::help_index()).leak()); }
+    #[test] fn test_help_index_returns_nil() { run_str("let x = help() ; x . print", format!("{}\nnil\n", NativeFunction::help_index()).leak()); }
+    #[test] fn test_help_of_native_function() { run_str("help(map)", "fn map(f, iter)\n  Applies a function to each element\n"); }
+    #[test] fn test_help_of_partial_native_function() { run_str("help(map(str))", "fn map(f, iter)\n  Applies a function to each element\n"); }
+    #[test] fn test_help_of_function() { run_str("/// Adds two numbers\n/// and returns the sum\nfn add(a, b) -> a + b\nhelp(add)", "fn add(a, b)\n  Adds two numbers\n  and returns the sum\n"); }
+    #[test] fn test_help_of_function_without_doc() { run_str("fn f(x) {}\nhelp(f)", "fn f(x)\n"); }
+    #[test] fn test_help_of_partial_function() { run_str("/// Partial\nfn f(a, b) {}\nhelp(f(1))", "fn f(a, b)\n  Partial\n"); }
+    #[test] fn test_help_of_non_function() { run_str("help('abc')", "'abc'\n"); }
+    #[test] fn test_help_of_native_function_with_capability() { run_str("help(read_text)", "fn read_text(file)\n  Reads the contents of a file as a string\n  requires: fs\n"); }
+    #[test] fn test_nested_block_comments() { run_str("/* outer /* inner */ still a comment */ print('ok')", "ok\n"); }
     #[test] fn test_count_ones() { run_str("0b11011011 . count_ones . print", "6\n"); }
     #[test] fn test_count_zeros() { run_str("0 . count_zeros . print", "64\n"); }
     #[test] fn test_env_exists() { run_str("env . repr . print", "fn env(...)\n"); }
@@ -1685,6 +4051,24 @@ mod tests {
     #[test] fn test_imag_of_imag() { run_str("123j . imag . print", "123\n"); }
     #[test] fn test_imag_of_complex() { run_str("4i + 6 . imag . print", "4\n"); }
     #[test] fn test_imag_of_str() { run_str("'4i + 6' . imag . print", "TypeError: Expected '4i + 6' of type 'str' to be a complex\n  at: line 1 (<test>)\n\n1 | '4i + 6' . imag . print\n2 |          ^^^^^^\n"); }
+    #[test] fn test_memoize_typeof() { run_str("memoize(abs) . typeof . print", "function\n"); }
+    #[test] fn test_memoize_to_bool() { run_str("print(bool(memoize(abs)), memoize(abs) and 1, not memoize(abs))", "true 1 false\n"); }
+    #[test] fn test_memoize_max_size_must_be_positive() { run_str("memoize(print, 0)", "ValueError: Expected value '0: int' to be positive\n  at: line 1 (<test>)\n\n1 | memoize(print, 0)\n2 |        ^^^^^^^^^^\n"); }
+    #[test] fn test_memoize_cache_stats() { run_str("let f = memoize(+1) ; f(1) ; f(1) ; f(2) ; f . cache_stats . print", "{'hits': 1, 'misses': 2, 'size': 2, 'max_size': nil}\n"); }
+    #[test] fn test_memoize_cache_stats_not_memoized() { run_str("cache_stats(print)", "TypeError: Expected 'print' of type 'native function' to be a memoized function\n  at: line 1 (<test>)\n\n1 | cache_stats(print)\n2 |            ^^^^^^^\n"); }
+    #[test] fn test_memoize_collection_keys() { run_str("let f = memoize(fn(x, y) { print('call') ; len(x) + len(y) }) ; f([1, 2], {3}) . print ; f([1, 2], {3}) . print", "call\n3\n3\n"); }
+    #[test] fn test_memoize_recursive_key() { run_str("let x = [] ; x.push(x) ; memoize(len)(x)", "ValueError: Cannot create recursive hash based collection from '[[...]]' of type 'list'\n  at: line 1 (<test>)\n\n1 | let x = [] ; x.push(x) ; memoize(len)(x)\n2 |                                      ^^^\n"); }
+    #[test] fn test_compose() { run_str("let inc = fn(x) -> x + 1, dbl = fn(x) -> x * 2 ; print(compose(inc, dbl)(5), compose(dbl, inc)(5), compose(inc, dbl, inc)(1))", "11 12 5\n"); }
+    #[test] fn test_compose_in_pipeline() { run_str("[1, 2, 3] . map(compose(str, (*3))) . print", "['3', '6', '9']\n"); }
+    #[test] fn test_compose_none_or_one() { run_str("print(compose()(7), compose(+1)(1), compose() == identity)", "7 2 true\n"); }
+    #[test] fn test_compose_repr() { run_str("print(compose((+1), (+2)), repr(compose((+1), (+2), (+3))))", "compose fn compose(f, g, x)\n"); }
+    #[test] fn test_compose_not_function() { run_str("compose((+1), 2)", "TypeError: Expected '2' of type 'int' to be a function\n  at: line 1 (<test>)\n\n1 | compose((+1), 2)\n2 |        ^^^^^^^^^\n"); }
+    #[test] fn test_compose_incorrect_arguments() { run_str("compose((+1), (+2))(1, 2)", "Incorrect number of arguments for fn compose(f, g, x), got 4\n  at: line 1 (<test>)\n\n1 | compose((+1), (+2))(1, 2)\n2 |                    ^^^^^^\n"); }
+    #[test] fn test_identity() { run_str("print(identity(3), [1, 0, 2] . filter(identity), repr(identity))", "3 [1, 2] fn identity(x)\n"); }
+    #[test] fn test_const() { run_str("print([1, 2, 3] . map(const_(0)), const_('a', 'b'), repr(const_(0)))", "[0, 0, 0] a fn const_(value, x)\n"); }
+    #[test] fn test_flip() { run_str("print(flip((-))(1, 10), flip((-), 1)(10), flip(fn(a, b) -> [a, b])(1)(2))", "9 9 [2, 1]\n"); }
+    #[test] fn test_flip_repr() { run_str("print(flip(max), repr(flip(max)))", "flip fn flip(f, a, b)\n"); }
+    #[test] fn test_curry() { run_str("let f = fn((a, b)) -> a * 10 + b ; print(curry(f)(1, 2), curry(f)(3)(4), curry(first)('a', 'b'))", "12 34 a\n"); }
 
 
     #[test] fn test_aoc_2022_01_01() { run("aoc_2022_01_01"); }
@@ -1708,6 +4092,18 @@ mod tests {
     #[test] fn test_closures_nested_inner_read_heap() { run("closures_nested_inner_read_heap"); }
     #[test] fn test_closures_nested_inner_read_heap_x2() { run("closures_nested_inner_read_heap_x2"); }
     #[test] fn test_closures_nested_inner_read_stack() { run("closures_nested_inner_read_stack"); }
+    #[test] fn test_closure_capture_by_value_in_loop() { run_str("let fs = [] ; for i in range(3) { fs.push(fn [i] () -> i) } ; fs . map(fn(f) -> f()) . print", "[0, 1, 2]\n"); }
+    #[test] fn test_closure_capture_by_value_outer_assignment() { run_str("let x = 1 ; let f = fn [x] () -> x ; x = 5 ; print(f(), x)", "1 5\n"); }
+    #[test] fn test_closure_capture_by_value_inner_assignment() { run_str("let x = 1 ; let f = fn [x] () { x += 10 ; x } ; print(f(), f(), x)", "11 21 1\n"); }
+    #[test] fn test_closure_capture_by_value_multiple() { run_str("let x = 1, y = 2 ; let f = fn [x, y] (z) -> x + y + z ; x = 10 ; y = 20 ; f(3) . print", "6\n"); }
+    #[test] fn test_closure_capture_by_value_named() { run_str("let x = 1 ; fn f [x] (a) -> x + a ; x = 5 ; f(100) . print", "101\n"); }
+    #[test] fn test_closure_capture_by_value_local() { run_str("fn f() { let y = 'y' ; let g = fn [y] () -> y ; y = 'z' ; (g(), y) } ; f() . print", "('y', 'z')\n"); }
+    #[test] fn test_closure_capture_by_value_upvalue() { run_str("fn f() { let y = 'y' ; fn g() -> fn [y] () -> y ; let h = g() ; y = 'z' ; h() } ; f() . print", "y\n"); }
+    #[test] fn test_closure_capture_by_value_nested() { run_str("fn f() { let y = 'y' ; let g = fn [y] () -> fn() -> y ; y = 'z' ; g()() } ; f() . print", "y\n"); }
+    #[test] fn test_closure_capture_by_value_with_sibling_by_reference() { run_str("fn f() { let n = 0 ; let inc = fn() -> n += 1 ; let snap = fn [n] () -> n ; inc() ; inc() ; (snap(), n) } ; f() . print", "(0, 2)\n"); }
+    #[test] fn test_closure_capture_by_value_native() { run_str("let f = fn [print] () -> print ; f() . repr . print", "fn print(...)\n"); }
+    #[test] fn test_closure_capture_by_value_undeclared() { run_str("let f = fn [w] () -> w", "Compile Error:\n\nUndeclared identifier: 'w'\n  at: line 1 (<test>)\n\n1 | let f = fn [w] () -> w\n2 |             ^\n"); }
+    #[test] fn test_closure_capture_by_value_bare_evaluation() { run_str("let x = 3 ; let r = [1, 2] . map fn [x] (y) -> x * y ; r . print", "[3, 6]\n"); }
     #[test] fn test_fibonacci() { run("fibonacci"); }
     #[test] fn test_for_loop_modify_loop_variable() { run("for_loop_modify_loop_variable"); }
     #[test] fn test_for_loop_range_map() { run("for_loop_range_map"); }
@@ -1717,9 +4113,11 @@ mod tests {
     #[test] fn test_late_bound_global() { run("late_bound_global"); }
     #[test] fn test_late_bound_global_assignment() { run("late_bound_global_assignment"); }
     #[test] fn test_late_bound_global_invalid() { run("late_bound_global_invalid"); }
+    #[test] fn test_late_bound_global_after_nested_function() { run("late_bound_global_after_nested_function"); }
     #[test] fn test_map_loop_with_multiple_references() { run("map_loop_with_multiple_references"); }
     #[test] fn test_memoize() { run("memoize"); }
     #[test] fn test_memoize_recursive() { run("memoize_recursive"); }
+    #[test] fn test_memoize_max_size() { run("memoize_max_size"); }
     #[test] fn test_memoize_recursive_as_annotation() { run("memoize_recursive_as_annotation"); }
     #[test] fn test_quine() { run("quine"); }
     #[test] fn test_range_used_twice() { run("range_used_twice"); }
@@ -1728,34 +4126,58 @@ mod tests {
 
 
     fn run_str(text: &'static str, expected: &'static str) {
+        run_str_with(text, "", expected, "");
+    }
+
+    fn run_str_with_read(text: &'static str, read: &'static str, expected: &'static str) {
+        run_str_with(text, read, expected, "");
+    }
+
+    fn run_str_with_stderr(text: &'static str, expected: &'static str, expected_error: &'static str) {
+        run_str_with(text, "", expected, expected_error);
+    }
+
+    fn run_str_with_exit_code(text: &'static str, expected: &'static str, expected_code: i32) {
+        assert_eq!(run_str_with(text, "", expected, ""), expected_code);
+    }
+
+    /// Runs `text`, asserting the output to stdout and stderr is `expected` and `expected_error` respectively, and returns the exit code.
+    fn run_str_with(text: &'static str, read: &'static str, expected: &'static str, expected_error: &'static str) -> i32 {
         let view: SourceView = SourceView::new(String::from("<test>"), String::from(text));
         let compile = compiler::compile(true, &view);
 
         if compile.is_err() {
             assert_eq!(format!("Compile Error:\n\n{}", compile.err().unwrap().join("\n")).as_str(), expected);
-            return
+            return 0
         }
 
         let compile = compile.unwrap();
         println!("[-d] === Compiled ===");
-        for line in compile.disassemble(&view, true) {
+        for line in compile.disassemble(&view, &compiler::DisassemblyOptions::default()) {
             println!("[-d] {}", line);
         }
 
         let mut buf: Vec<u8> = Vec::new();
-        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![]);
+        let mut error_buf: Vec<u8> = Vec::new();
+        let mut vm = VirtualMachine::new(compile, view, read.as_bytes(), &mut buf, &mut error_buf, vec![]);
 
         let result: ExitType = vm.run_until_completion();
         assert!(vm.stack.is_empty() || result.is_early_exit());
 
+        let result: ExitType = vm.run_exit_handlers(result);
         let view: SourceView = vm.view;
         let mut output: String = String::from_utf8(buf).unwrap();
+        let mut code: i32 = 0;
 
-        if let ExitType::Error(error) = result {
-            output.push_str(view.format(&error).as_str());
+        match result {
+            ExitType::Error(error) => output.push_str(view.format(&error).as_str()),
+            ExitType::Exit(it) => code = it,
+            _ => {},
         }
 
         assert_eq!(output.as_str(), expected);
+        assert_eq!(String::from_utf8(error_buf).unwrap().as_str(), expected_error);
+        code
     }
 
     fn run(path: &'static str) {
@@ -1770,12 +4192,12 @@ mod tests {
 
         let compile = compile.unwrap();
         println!("[-d] === Compiled ===");
-        for line in compile.disassemble(&view, true) {
+        for line in compile.disassemble(&view, &compiler::DisassemblyOptions::default()) {
             println!("[-d] {}", line);
         }
 
         let mut buf: Vec<u8> = Vec::new();
-        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![]);
+        let mut vm = VirtualMachine::new(compile, view, &b""[..], &mut buf, vec![], vec![]);
 
         let result: ExitType = vm.run_until_completion();
         assert!(vm.stack.is_empty() || result.is_early_exit());
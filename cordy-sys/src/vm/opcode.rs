@@ -1,6 +1,5 @@
 use crate::compiler::Fields;
 use crate::core::NativeFunction;
-use crate::util::OffsetAdd;
 use crate::vm::{Type, ValuePtr};
 use crate::vm::operator::{BinaryOp, UnaryOp};
 use crate::vm::value::LiteralType;
@@ -13,18 +12,19 @@ pub enum Opcode {
 
     Noop,
 
-    /// The parameter is an offset value, based on the IP *after* the instruction is executed. So an instruction:
+    /// The parameter is the absolute index of the instruction to jump to. So an instruction:
     ///
-    /// `005: Jump(-2)`
+    /// `005: Jump(4)`
     ///
-    /// will jump to 005 + 1 (since we finish executing this instruction) - 2 = 004
+    /// will jump back to, and next execute, the instruction at 004.
     ///
-    /// `Jump(-1)` is a no-op.
-    JumpIfFalse(i32),
-    JumpIfFalsePop(i32),
-    JumpIfTrue(i32),
-    JumpIfTruePop(i32),
-    Jump(i32),
+    /// While a function is being compiled, the index is instead relative to the start of that function, as its final position is not yet known.
+    /// Once the code is laid out, each jump is converted to an absolute index with `relocate()`, so the VM never needs to compute the target at runtime.
+    JumpIfFalse(u32),
+    JumpIfFalsePop(u32),
+    JumpIfTrue(u32),
+    JumpIfTruePop(u32),
+    Jump(u32),
 
     Return,
 
@@ -54,6 +54,9 @@ pub enum Opcode {
     CloseLocal(u32),
     CloseUpValue(u32),
 
+    /// Pops the top of the stack, and adds it to the closure below it as an already closed upvalue, for a variable captured by value.
+    CloseValue,
+
     /// Lifts an UpValue from a stack slot (offset by the frame pointer) to the heap
     /// It does so by boxing it into a `Rc<Cell<Value>>`, stored on the closure's `environment` array. Each closure references the same `UpValue`, and hence will see all mutations.
     /// Takes a local index of an upvalue to lift.
@@ -65,7 +68,7 @@ pub enum Opcode {
     /// Expects the top of the stack to contain a `Value::Iter()`. Tests if this has reached the end of the iterable.
     /// If yes, jump to the offset given by `i32`, i.e. the end of the loop.
     /// If no, it will push the next value in the iterable.
-    TestIterable(i32),
+    TestIterable(u32),
 
     /// Executes a pattern, similar to a native or builtin function, against the top of the stack.
     ExecPattern(u32),
@@ -127,12 +130,39 @@ pub enum Opcode {
     GetFieldPeek(u32),
     GetFieldFunction(u32),
     SetField(u32),
+    /// Takes a stack of `[value, struct type, ...]`, pops the value, and initializes the static member at the given offset of the struct type, which is left on the stack.
+    /// This is emitted once for each member, directly after the struct is declared.
+    InitMember(u32),
+    /// Takes a stack of `[parent struct type, struct type, ...]`, pops the parent, and initializes each member the struct type inherits from the parent, which is left on the stack.
+    /// This is emitted directly after a struct with a parent is declared, before any of its own members are initialized, which may replace the inherited ones.
+    InheritMembers,
+
+    /// Pops the top of the stack, and enters it as a context, at the beginning of a `with` statement. This pushes the result of its `__enter__` member, if present, or otherwise the value itself.
+    /// The value is held on the VM's context stack until it is exited, either by a matching `ExitContext`, or when an error unwinds past the `with` statement.
+    EnterContext,
+    /// Exits the most recently entered context, by invoking its `__exit__` or `close` member, or `close()` for a native handle. This does not modify the stack.
+    /// This is emitted at the end of a `with` statement, and before any `break`, `continue`, or `return` which leaves one.
+    ExitContext,
+
+    /// Pops a task off the stack, and pushes its result, once it has completed. Within an `async fn`, this suspends the function until then, so other tasks can run.
+    /// Otherwise, this runs the event loop until the task has completed.
+    Await,
 
     Unary(UnaryOp),
     Binary(BinaryOp),
 
+    /// Equivalent to a `PushLocal(u32)` followed by a `Binary(BinaryOp)`, where the local is the right hand side of the operator.
+    /// These are emitted when optimizations are enabled, and avoid pushing and popping the right hand side, for common expressions such as `a < b` or `a + b * c`.
+    BinaryLocal(BinaryOp, u32),
+    /// Equivalent to a `Constant(u32)` followed by a `Binary(BinaryOp)`, for instance `i + 1`
+    BinaryConst(BinaryOp, u32),
+
     // Special
     Exit,
+    ExitWith,
+    /// Pops the top of the stack, and exits the program, reporting the value as `ExitType::Value`.
+    /// This is emitted at the end of a program compiled with `compile_print_last()`, in place of `Exit`.
+    ExitValue,
     Yield,
     AssertFailed,
 }
@@ -148,7 +178,42 @@ pub enum StoreOp {
 
 impl Opcode {
 
-    pub fn disassembly<I : Iterator<Item=String>>(self: &Opcode, ip: usize, locals: &mut I, fields: &Fields, constants: &[ValuePtr]) -> String {
+    /// If this opcode is a jump, returns the index of the instruction it jumps to
+    pub fn jump_target(&self) -> Option<usize> {
+        match self {
+            JumpIfFalse(target) | JumpIfFalsePop(target) | JumpIfTrue(target) | JumpIfTruePop(target) | Jump(target) | TestIterable(target) => Some(*target as usize),
+            _ => None,
+        }
+    }
+
+    /// If this opcode is a jump, converts the target from an index relative to `base`, to an absolute index.
+    pub fn relocate(self, base: usize) -> Opcode {
+        let base: u32 = base as u32;
+        match self {
+            JumpIfFalse(target) => JumpIfFalse(base + target),
+            JumpIfFalsePop(target) => JumpIfFalsePop(base + target),
+            JumpIfTrue(target) => JumpIfTrue(base + target),
+            JumpIfTruePop(target) => JumpIfTruePop(base + target),
+            Jump(target) => Jump(base + target),
+            TestIterable(target) => TestIterable(base + target),
+            op => op,
+        }
+    }
+
+    /// Returns the name of a jump opcode, without the offset
+    pub fn jump_name(&self) -> &'static str {
+        match self {
+            JumpIfFalse(_) => "JumpIfFalse",
+            JumpIfFalsePop(_) => "JumpIfFalsePop",
+            JumpIfTrue(_) => "JumpIfTrue",
+            JumpIfTruePop(_) => "JumpIfTruePop",
+            Jump(_) => "Jump",
+            TestIterable(_) => "TestIterable",
+            _ => panic!("Not a jump: {:?}", self),
+        }
+    }
+
+    pub fn disassembly<I : Iterator<Item=String>>(self: &Opcode, locals: &mut I, fields: &Fields, constants: &[ValuePtr]) -> String {
         match self {
             Constant(id) => {
                 let constant = &constants[*id as usize];
@@ -168,6 +233,11 @@ impl Opcode {
                     }, constant.to_repr_str())
                 }
             },
+            BinaryLocal(op, id) => match locals.next() {
+                Some(local) => format!("{:?}Local({}) -> {}", op, id, local),
+                None => format!("{:?}Local({})", op, id),
+            },
+            BinaryConst(op, id) => format!("{:?}Const({})", op, Constant(*id).disassembly(locals, fields, constants)),
             PushGlobal(id) | StoreGlobal(id, _) | PushLocal(id) | StoreLocal(id, _) => match locals.next() {
                 Some(local) => format!("{}({}) -> {}", match self {
                     StoreGlobal(_, true) => "StoreGlobalPop",
@@ -181,15 +251,7 @@ impl Opcode {
                 None => format!("{:?}", self),
             },
             GetField(fid) | SetField(fid) | GetFieldFunction(fid) => format!("{:?} -> {}", self, fields.get_field_name(*fid)),
-            JumpIfFalse(offset) | JumpIfFalsePop(offset) | JumpIfTrue(offset) | JumpIfTruePop(offset) | Jump(offset) | TestIterable(offset) => format!("{}({})", match self {
-                JumpIfFalse(_) => "JumpIfFalse",
-                JumpIfFalsePop(_) => "JumpIfFalsePop",
-                JumpIfTrue(_) => "JumpIfTrue",
-                JumpIfTruePop(_) => "JumpIfTruePop",
-                Jump(_) => "Jump",
-                TestIterable(_) => "TestIterable",
-                _ => unreachable!()
-            }, ip.add_offset(*offset + 1)),
+            JumpIfFalse(_) | JumpIfFalsePop(_) | JumpIfTrue(_) | JumpIfTruePop(_) | Jump(_) | TestIterable(_) => format!("{}({})", self.jump_name(), self.jump_target().unwrap()),
             Binary(op) => format!("{:?}", op),
             Unary(op) => format!("{:?}", op),
             NativeFunction(op) => format!("{:?}", op),
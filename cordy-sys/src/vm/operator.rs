@@ -1,10 +1,12 @@
+use std::cmp::Ordering;
 use std::collections::VecDeque;
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub};
 
 use crate::core;
 use crate::core::NativeFunction;
-use crate::vm::{ErrorResult, Type, ValuePtr, ValueResult};
+use crate::vm::{BitSetImpl, ErrorResult, MatrixImpl, MAX_INT, MIN_INT, Type, ValuePtr, ValueResult, VirtualInterface};
 use crate::vm::error::RuntimeError;
-use crate::vm::value::{C64, IntoIterableValue, IntoValue, Prefix};
+use crate::vm::value::{C64, IntoIterableValue, IntoValue, Prefix, R64};
 
 use RuntimeError::{*};
 use Type::{*};
@@ -62,11 +64,96 @@ impl BinaryOp {
 }
 
 
+/// Applies a binary operator, like `BinaryOp::apply()`, except comparison operators involving structs respect user defined comparisons via `compare()`.
+pub fn apply_binary<VM: VirtualInterface>(vm: &mut VM, op: BinaryOp, lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
+    if !lhs.is_struct() && !rhs.is_struct() {
+        return op.apply(lhs, rhs)
+    }
+    match op {
+        BinaryOp::LessThan => (compare(vm, &lhs, &rhs)? == Ordering::Less).to_value().ok(),
+        BinaryOp::GreaterThan => (compare(vm, &lhs, &rhs)? == Ordering::Greater).to_value().ok(),
+        BinaryOp::LessThanEqual => (compare(vm, &lhs, &rhs)? != Ordering::Greater).to_value().ok(),
+        BinaryOp::GreaterThanEqual => (compare(vm, &lhs, &rhs)? != Ordering::Less).to_value().ok(),
+        BinaryOp::Max => match compare(vm, &lhs, &rhs)? {
+            Ordering::Greater => lhs.ok(),
+            _ => rhs.ok(),
+        },
+        BinaryOp::Min => match compare(vm, &lhs, &rhs)? {
+            Ordering::Greater => rhs.ok(),
+            _ => lhs.ok(),
+        },
+        _ => op.apply(lhs, rhs),
+    }
+}
+
+/// A user defined comparison on a struct type, declared as a static member named either `__cmp__` or `__lt__`.
+enum Comparator {
+    /// `__cmp__(a, b)` returns an `int` which is negative, zero, or positive, if `a` is less than, equal to, or greater than `b`, respectively.
+    Cmp(ValuePtr),
+    /// `__lt__(a, b)` returns `true` if `a` is less than `b`.
+    Lt(ValuePtr),
+}
+
+impl Comparator {
+    fn of(value: &ValuePtr) -> Option<Comparator> {
+        match value.ty() {
+            Struct => {
+                let it = value.as_struct().borrow();
+                let type_impl = it.type_impl.get();
+                // A member which is `nil` has been replaced by a child struct declaring the other comparison member
+                let member = |name: &str| type_impl.get_member_by_name(name).filter(|f| !f.is_nil());
+                member("__cmp__").map(Comparator::Cmp)
+                    .or_else(|| member("__lt__").map(Comparator::Lt))
+            },
+            _ => Option::None, // `None` here refers to `Type::None`
+        }
+    }
+}
+
+/// Compares two values. If either value is a struct with a user defined comparison (`__cmp__` or `__lt__`), that is used to compare them.
+/// In that case, both values must be instances of the same struct, otherwise they are incomparable, and this raises an error.
+///
+/// Otherwise, this uses the natural ordering of values.
+pub fn compare<VM: VirtualInterface>(vm: &mut VM, lhs: &ValuePtr, rhs: &ValuePtr) -> ErrorResult<Ordering> {
+    let comparator: Comparator = match Comparator::of(lhs).or_else(|| Comparator::of(rhs)) {
+        Some(comparator) => comparator,
+        _ => return Ok(lhs.cmp(rhs)),
+    };
+    if !lhs.is_struct() || !rhs.is_struct() || lhs.as_struct().borrow().type_index != rhs.as_struct().borrow().type_index {
+        return TypeErrorCannotCompare(lhs.clone(), rhs.clone()).err()
+    }
+    match comparator {
+        Comparator::Cmp(f) => Ok(vm.call(f, [lhs.clone(), rhs.clone()])?.check_int()?.as_int().cmp(&0)),
+        Comparator::Lt(f) => Ok(if vm.call(f.clone(), [lhs.clone(), rhs.clone()])?.to_bool() {
+            Ordering::Less
+        } else if vm.call(f, [rhs.clone(), lhs.clone()])?.to_bool() {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        })
+    }
+}
+
+/// Updates the user-defined hash of a struct instance, if the struct type declares a `__hash__` member.
+/// This must be called whenever an instance is constructed, or any of its fields are modified, so that the hash stays consistent with the fields.
+///
+/// Structs without a `__hash__` member are hashed field-wise.
+pub fn update_hash<VM: VirtualInterface>(vm: &mut VM, instance: &ValuePtr) -> ErrorResult<()> {
+    let hash: Option<ValuePtr> = instance.as_struct().borrow().type_impl.get().get_member_by_name("__hash__");
+    if let Some(f) = hash {
+        let hash: i64 = vm.call(f, [instance.clone()])?.check_int()?.as_int();
+        instance.as_struct().borrow_mut().set_hash(hash);
+    }
+    Ok(())
+}
+
 pub fn unary_sub(a1: ValuePtr) -> ValueResult {
     match a1.ty() {
         Bool | Int => (-a1.as_int()).to_value().ok(),
         Complex => (-a1.as_complex()).to_value().ok(),
+        Fraction => checked_fraction(Some(-a1.as_fraction())),
         Vector => apply_vector_unary(a1, unary_sub),
+        Matrix => apply_matrix_unary(a1, |x| -x),
         _ => TypeErrorUnaryOp(UnaryOp::Neg, a1).err(),
     }
 }
@@ -77,6 +164,14 @@ pub fn unary_not(a1: ValuePtr) -> ValueResult {
         Int => (!a1.as_int()).to_value().ok(),
         Complex => a1.as_complex().conj().to_value().ok(),
         Vector => apply_vector_unary(a1, unary_not),
+        BitSet => {
+            let mut it = a1.as_bitset().borrow().clone();
+            for word in it.words.iter_mut() {
+                *word = !*word;
+            }
+            it.truncate();
+            it.to_value().ok()
+        },
         _ => TypeErrorUnaryOp(UnaryOp::Not, a1).err(),
     }
 }
@@ -85,11 +180,15 @@ pub fn unary_not(a1: ValuePtr) -> ValueResult {
 pub fn binary_mul(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
     match (lhs.ty(), rhs.ty()) {
         (Bool | Int, Bool | Int) => (lhs.as_int() * rhs.as_int()).to_value().ok(),
+        (Bool | Int | Fraction, Bool | Int | Fraction) => checked_fraction(lhs.as_fraction().checked_mul(&rhs.as_fraction())),
         (Bool | Int | Complex, Bool | Int | Complex) => (lhs.as_complex() * rhs.as_complex()).to_value().ok(),
         (Str, Int) => binary_str_repeat(lhs, rhs),
         (Int, Str) => binary_str_repeat(rhs, lhs),
         (List, Int) => binary_list_repeat(lhs, rhs),
         (Int, List) => binary_list_repeat(rhs, lhs),
+        (Matrix, Matrix) => apply_matrix_binary(lhs, rhs, |l, r| l * r),
+        (Matrix, Bool | Int) => apply_matrix_binary_scalar(lhs, rhs.as_int(), |l, r| l * r),
+        (Bool | Int, Matrix) => apply_matrix_binary_scalar(rhs, lhs.as_int(), |r, l| l * r),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_mul),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_mul),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_mul),
@@ -100,24 +199,40 @@ pub fn binary_mul(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
 fn binary_str_repeat(string: ValuePtr, repeat: ValuePtr) -> ValueResult {
     let i = repeat.as_int();
     if i < 0 {
-        ValueErrorValueMustBeNonNegative(i).err()
-    } else {
-        string.as_str().borrow_const().repeat(i as usize).to_value().ok()
+        return ValueErrorValueMustBeNonNegative(i).err()
+    }
+    let string = string.as_str().borrow_const();
+    let mut ret: String = String::new();
+    if !string.is_empty() {
+        check_repeat(string.len(), i, |len| ret.try_reserve_exact(len).is_ok())?;
+        for _ in 0..i {
+            ret.push_str(string);
+        }
     }
+    ret.to_value().ok()
 }
 
 fn binary_list_repeat(list: ValuePtr, repeat: ValuePtr) -> ValueResult {
     let i = repeat.as_int();
     if i < 0 {
-        ValueErrorValueMustBeNonNegative(i).err()
-    } else {
-        let list = list.as_list().borrow();
-        list.list.iter()
-            .cycle()
-            .take(i as usize * list.list.len())
-            .cloned()
-            .to_list()
-            .ok()
+        return ValueErrorValueMustBeNonNegative(i).err()
+    }
+    let list = list.as_list().borrow();
+    let mut ret: VecDeque<ValuePtr> = VecDeque::new();
+    if !list.list.is_empty() {
+        check_repeat(list.list.len(), i, |len| ret.try_reserve_exact(len).is_ok())?;
+        for _ in 0..i {
+            ret.extend(list.list.iter().cloned());
+        }
+    }
+    ret.to_value().ok()
+}
+
+/// Checks that `len` elements, repeated `repeat` times, can be allocated with `reserve`, and raises an error otherwise, rather than panicking or aborting.
+fn check_repeat(len: usize, repeat: i64, reserve: impl FnOnce(usize) -> bool) -> ErrorResult<()> {
+    match len.checked_mul(repeat as usize).is_some_and(reserve) {
+        true => Ok(()),
+        false => ValueErrorRepeatTooLarge(len, repeat).err(),
     }
 }
 
@@ -130,6 +245,15 @@ pub fn binary_div(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
                 num_integer::div_floor(lhs.as_int(), rhs.as_int()).to_value().ok()
             }
         },
+        (Bool | Int | Fraction, Bool | Int | Fraction) => {
+            // Division is only exact if either side is already a fraction, otherwise `int / int` keeps floor division semantics
+            let rhs: R64 = rhs.as_fraction();
+            if rhs == R64::from_integer(0) {
+                ValueErrorValueMustBeNonZero.err()
+            } else {
+                checked_fraction(lhs.as_fraction().checked_div(&rhs))
+            }
+        },
         (Bool | Int | Complex, Bool | Int | Complex) => {
             let lhs = lhs.as_complex();
             let rhs = rhs.as_complex();
@@ -146,6 +270,18 @@ pub fn binary_div(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
     }
 }
 
+/// Converts the result of a checked fraction operation to a value. This raises an error if the operation overflowed, or if either the numerator or denominator cannot be represented as an `int`.
+pub fn checked_fraction(value: Option<R64>) -> ValueResult {
+    match value {
+        Some(it) if is_int_in_range(*it.numer()) && is_int_in_range(*it.denom()) => it.to_value().ok(),
+        _ => ValueErrorFractionOverflow.err(),
+    }
+}
+
+fn is_int_in_range(value: i64) -> bool {
+    (MIN_INT..=MAX_INT).contains(&value)
+}
+
 /// The `C64` type provided by `num-complex` defines `div()` using regular rust division.
 /// This is a clone of that but using `floor_div` provided by `num-integer`, which keeps consistency with how we define division for `complex / int`
 #[inline]
@@ -158,7 +294,13 @@ fn c64_div_floor(lhs: C64, rhs: C64) -> C64 {
 
 pub fn binary_mod(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
     match (lhs.ty(), rhs.ty()) {
-        (Bool | Int, Bool | Int) => num_integer::mod_floor(lhs.as_int(), rhs.as_int()).to_value().ok(),
+        (Bool | Int, Bool | Int) => {
+            if rhs.as_int() == 0 {
+                ValueErrorValueMustBeNonZero.err()
+            } else {
+                num_integer::mod_floor(lhs.as_int(), rhs.as_int()).to_value().ok()
+            }
+        },
         (Str, _) => core::format_string(lhs.as_str().borrow_const(), rhs),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_mod),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_mod),
@@ -185,6 +327,19 @@ pub fn binary_pow(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
                 ValueErrorValueMustBeNonNegative(rhs).err()
             }
         },
+        (Fraction, Bool | Int) => match i32::try_from(rhs.as_int()) {
+            Ok(rhs) => {
+                // Fractions are never zero, so negative powers are safe, and are always reduced, so the powers of the numerator and denominator are as well
+                let (numer, denom) = lhs.as_fraction().into();
+                let (numer, denom) = if rhs < 0 { (denom, numer) } else { (numer, denom) };
+                let exp = rhs.unsigned_abs();
+                checked_fraction(numer.checked_pow(exp)
+                    .zip(denom.checked_pow(exp))
+                    .filter(|(n, d)| is_int_in_range(*n) && is_int_in_range(*d))
+                    .map(|(n, d)| R64::new(n, d)))
+            },
+            Err(_) => ValueErrorFractionOverflow.err(), // Fractions are also never `1` or `-1`, so any power this large cannot be represented
+        },
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_pow),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_pow),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_pow),
@@ -196,17 +351,34 @@ pub fn binary_pow(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
 pub fn binary_is(lhs: ValuePtr, rhs: ValuePtr, invert: bool) -> ValueResult {
     (match rhs.ty() {
         Nil => lhs.is_nil(),
-        StructType => lhs.is_struct() && lhs.as_struct().borrow().type_index == rhs.as_struct_type().borrow_const().type_index,
+        StructType => lhs.is_struct() && lhs.as_struct().borrow().type_impl.get().is_subtype_of(rhs.as_struct_type().borrow_const().type_index),
         NativeFunction => match rhs.as_native() {
             NativeFunction::Bool => lhs.is_bool(),
             NativeFunction::Int => lhs.is_int(),
             NativeFunction::Complex => lhs.is_complex(),
+            NativeFunction::Fraction => lhs.is_fraction(),
             NativeFunction::Str => lhs.is_str(),
             NativeFunction::Function => lhs.is_evaluable(),
             NativeFunction::List => lhs.is_list(),
             NativeFunction::Set => lhs.is_set(),
             NativeFunction::Dict => lhs.is_dict(),
+            NativeFunction::SortedSet => lhs.is_sorted_set(),
+            NativeFunction::SortedDict => lhs.is_sorted_dict(),
             NativeFunction::Vector => lhs.is_vector(),
+            NativeFunction::View => lhs.is_view(),
+            NativeFunction::PList => lhs.is_plist(),
+            NativeFunction::PDict => lhs.is_pdict(),
+            NativeFunction::Matrix => lhs.is_matrix(),
+            NativeFunction::BitSet => lhs.is_bitset(),
+            NativeFunction::Dsu => lhs.is_dsu(),
+            NativeFunction::Stopwatch => lhs.is_stopwatch(),
+            NativeFunction::TcpConnect => lhs.is_tcp_stream(),
+            NativeFunction::TcpListen => lhs.is_tcp_listener(),
+            NativeFunction::Coprocess => lhs.is_coprocess(),
+            NativeFunction::DbOpen => lhs.is_database(),
+            NativeFunction::Forall => lhs.is_generator(),
+            NativeFunction::Task => lhs.is_task(),
+            NativeFunction::Weak => lhs.is_weak(),
             NativeFunction::Iterable => lhs.is_iter(),
             NativeFunction::Heap => lhs.is_heap(),
             NativeFunction::Any => true,
@@ -221,11 +393,20 @@ pub fn binary_in(lhs: ValuePtr, rhs: ValuePtr, invert: bool) -> ValueResult {
     (match (lhs.ty(), rhs.ty()) {
         (Str, Str) => rhs.as_str().borrow_const().contains(lhs.as_str().borrow_const().as_str()),
         (Int | Bool, Range) => rhs.as_range().value.contains(lhs.as_int()),
+        (Int | Bool, BitSet) => {
+            let (it, index) = (rhs.as_bitset().borrow(), lhs.as_int());
+            0 <= index && (index as usize) < it.size && it.get(index as usize)
+        },
         (_, List) => rhs.as_list().borrow().list.contains(&lhs),
         (_, Set) => rhs.as_set().borrow().set.contains(&lhs),
         (_, Dict) => rhs.as_dict().borrow().dict.contains_key(&lhs),
+        (_, SortedSet) => rhs.as_sorted_set().borrow().contains(&lhs),
+        (_, SortedDict) => rhs.as_sorted_dict().borrow().contains_key(&lhs),
         (_, Heap) => rhs.as_heap().borrow().heap.iter().any(|v|v.0 == lhs),
         (_, Vector) => rhs.as_vector().borrow().vector.contains(&lhs),
+        (_, View) => rhs.as_view().borrow_const().as_slice().contains(&lhs),
+        (_, PList) => rhs.as_plist().borrow_const().iter().any(|v| *v == lhs),
+        (_, PDict) => rhs.as_pdict().borrow_const().contains_key(&lhs),
         _ => return TypeErrorBinaryOp(BinaryOp::In, lhs, rhs).err()
     } != invert).to_value().ok()
 }
@@ -233,6 +414,7 @@ pub fn binary_in(lhs: ValuePtr, rhs: ValuePtr, invert: bool) -> ValueResult {
 pub fn binary_add(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
     match (lhs.ty(), rhs.ty()) {
         (Bool | Int, Bool | Int) => (lhs.as_int() + rhs.as_int()).to_value().ok(),
+        (Bool | Int | Fraction, Bool | Int | Fraction) => checked_fraction(lhs.as_fraction().checked_add(&rhs.as_fraction())),
         (Bool | Int | Complex, Bool | Int | Complex) => (lhs.as_complex() + rhs.as_complex()).to_value().ok(),
         (List, List) => {
             let lhs = lhs.as_list().borrow();
@@ -244,6 +426,9 @@ pub fn binary_add(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
         }
         (Str, _) => format!("{}{}", lhs.as_str().borrow_const(), rhs.to_str()).to_value().ok(),
         (_, Str) => format!("{}{}", lhs.to_str(), rhs.as_str().borrow_const()).to_value().ok(),
+        (Matrix, Matrix) => apply_matrix_binary(lhs, rhs, |l, r| l + r),
+        (Matrix, Bool | Int) => apply_matrix_binary_scalar(lhs, rhs.as_int(), |l, r| l + r),
+        (Bool | Int, Matrix) => apply_matrix_binary_scalar(rhs, lhs.as_int(), |r, l| l + r),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_add),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_add),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_add),
@@ -254,12 +439,16 @@ pub fn binary_add(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
 pub fn binary_sub(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
     match (lhs.ty(), rhs.ty()) {
         (Bool | Int, Bool | Int) => (lhs.as_int() - rhs.as_int()).to_value().ok(),
+        (Bool | Int | Fraction, Bool | Int | Fraction) => checked_fraction(lhs.as_fraction().checked_sub(&rhs.as_fraction())),
         (Bool | Int | Complex, Bool | Int | Complex) => (lhs.as_complex() - rhs.as_complex()).to_value().ok(),
         (Set, Set) => {
             let lhs = lhs.as_set().borrow();
             let rhs = rhs.as_set().borrow();
             lhs.set.difference(&rhs.set).cloned().to_set().ok()
         },
+        (Matrix, Matrix) => apply_matrix_binary(lhs, rhs, |l, r| l - r),
+        (Matrix, Bool | Int) => apply_matrix_binary_scalar(lhs, rhs.as_int(), |l, r| l - r),
+        (Bool | Int, Matrix) => apply_matrix_binary_scalar(rhs, lhs.as_int(), |r, l| l - r),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_sub),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_sub),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_sub),
@@ -271,6 +460,7 @@ pub fn binary_sub(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
 pub fn binary_left_shift(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
     match (lhs.ty(), rhs.ty()) {
         (Bool | Int, Bool | Int) => i64_left_shift(lhs.as_int(), rhs.as_int()).to_value().ok(),
+        (BitSet, Bool | Int) => bitset_left_shift(lhs, rhs.as_int()),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_left_shift),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_left_shift),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_left_shift),
@@ -282,6 +472,7 @@ pub fn binary_left_shift(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
 pub fn binary_right_shift(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
     match (lhs.ty(), rhs.ty()) {
         (Bool | Int, Bool | Int) => i64_left_shift(lhs.as_int(), -rhs.as_int()).to_value().ok(),
+        (BitSet, Bool | Int) => bitset_left_shift(lhs, -rhs.as_int()),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_right_shift),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_right_shift),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_right_shift),
@@ -299,6 +490,34 @@ fn i64_left_shift(lhs: i64, rhs: i64) -> i64 {
     }
 }
 
+/// Shifts a bitset towards higher indices, with negative values shifting towards lower indices, in the same manner as `i64_left_shift()`.
+/// Bits that are shifted past either end of the bitset are discarded.
+fn bitset_left_shift(bitset: ValuePtr, rhs: i64) -> ValueResult {
+    const BITS: usize = u64::BITS as usize;
+
+    let it = bitset.as_bitset().borrow();
+    let mut ret = BitSetImpl::new(it.size);
+    let len: usize = it.words.len();
+    let shift: usize = rhs.unsigned_abs() as usize;
+    let (words, bits) = (shift / BITS, shift % BITS);
+    for i in 0..len {
+        // Bits of `ret.words[i]` come from at most two adjacent source words, `hi` and `lo`, where `lo` is shifted by an additional word
+        let (hi, lo) = if rhs >= 0 {
+            (i.checked_sub(words), i.checked_sub(words + 1))
+        } else {
+            (i.checked_add(words).filter(|j| *j < len), i.checked_add(words + 1).filter(|j| *j < len))
+        };
+        let hi: u64 = hi.map_or(0, |j| it.words[j]);
+        let lo: u64 = lo.map_or(0, |j| it.words[j]);
+        ret.words[i] = match (rhs >= 0, bits) {
+            (_, 0) => hi,
+            (true, _) => (hi << bits) | (lo >> (BITS - bits)),
+            (false, _) => (hi >> bits) | (lo << (BITS - bits)),
+        };
+    }
+    ret.truncate();
+    ret.to_value().ok()
+}
 
 pub fn binary_bitwise_and(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
     match (lhs.ty(), rhs.ty()) {
@@ -309,6 +528,7 @@ pub fn binary_bitwise_and(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
             let rhs = rhs.as_set().borrow();
             lhs.set.intersection(&rhs.set).cloned().to_set().ok()
         }
+        (BitSet, BitSet) => apply_bitset_binary(lhs, rhs, |l, r| l & r),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_bitwise_and),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_bitwise_and),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_bitwise_and),
@@ -325,6 +545,7 @@ pub fn binary_bitwise_or(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
             let rhs = rhs.as_set().borrow();
             lhs.set.union(&rhs.set).cloned().to_set().ok()
         },
+        (BitSet, BitSet) => apply_bitset_binary(lhs, rhs, |l, r| l | r),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_bitwise_or),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_bitwise_or),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_bitwise_or),
@@ -341,6 +562,7 @@ pub fn binary_bitwise_xor(lhs: ValuePtr, rhs: ValuePtr) -> ValueResult {
             let rhs = rhs.as_set().borrow();
             lhs.set.symmetric_difference(&rhs.set).cloned().to_set().ok()
         }
+        (BitSet, BitSet) => apply_bitset_binary(lhs, rhs, |l, r| l ^ r),
         (Vector, Vector) => apply_vector_binary(lhs, rhs, binary_bitwise_xor),
         (Vector, _) => apply_vector_binary_scalar_rhs(lhs, rhs, binary_bitwise_xor),
         (_, Vector) => apply_vector_binary_scalar_lhs(lhs, rhs, binary_bitwise_xor),
@@ -386,6 +608,44 @@ fn apply_vector_binary_scalar_rhs(lhs: ValuePtr, scalar_rhs: ValuePtr, binary_op
         .ok()
 }
 
+/// Helper for `BitSet` operations, where the result has the size of the larger of the two operands.
+fn apply_bitset_binary(lhs: ValuePtr, rhs: ValuePtr, binary_op: fn(u64, u64) -> u64) -> ValueResult {
+    let lhs = lhs.as_bitset().borrow();
+    let rhs = rhs.as_bitset().borrow();
+    let mut ret = BitSetImpl::new(lhs.size.max(rhs.size));
+    for (i, word) in ret.words.iter_mut().enumerate() {
+        *word = binary_op(lhs.words.get(i).copied().unwrap_or(0), rhs.words.get(i).copied().unwrap_or(0));
+    }
+    ret.to_value().ok()
+}
+
+/// Helpers for `Matrix` operations, which apply elementwise.
+/// Note that unlike `Vector`, these require both operands to have the same dimensions, as the elements are unboxed `int`s
+fn apply_matrix_unary(matrix: ValuePtr, unary_op: fn(i64) -> i64) -> ValueResult {
+    let it = matrix.as_matrix().borrow();
+    MatrixImpl::new(it.rows, it.cols, it.data.iter().map(|u| unary_op(*u)).collect())
+        .to_value()
+        .ok()
+}
+
+fn apply_matrix_binary(lhs: ValuePtr, rhs: ValuePtr, binary_op: fn(i64, i64) -> i64) -> ValueResult {
+    let lhs = lhs.as_matrix().borrow();
+    let rhs = rhs.as_matrix().borrow();
+    if lhs.rows != rhs.rows || lhs.cols != rhs.cols {
+        return ValueErrorMatrixDimensionMismatch(lhs.rows, lhs.cols, rhs.rows, rhs.cols).err()
+    }
+    MatrixImpl::new(lhs.rows, lhs.cols, lhs.data.iter().zip(rhs.data.iter()).map(|(l, r)| binary_op(*l, *r)).collect())
+        .to_value()
+        .ok()
+}
+
+fn apply_matrix_binary_scalar(matrix: ValuePtr, scalar: i64, binary_op: fn(i64, i64) -> i64) -> ValueResult {
+    let it = matrix.as_matrix().borrow();
+    MatrixImpl::new(it.rows, it.cols, it.data.iter().map(|u| binary_op(*u, scalar)).collect())
+        .to_value()
+        .ok()
+}
+
 
 #[cfg(test)]
 mod test {
@@ -0,0 +1,272 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::vm::{AnyResult, CallFrame, IntoValue, RuntimeError, ValuePtr, ValueResult, VirtualInterface, VirtualMachine};
+use crate::vm::value::{SuspendedTask, TaskImpl, TaskState, UpValue};
+
+use RuntimeError::{*};
+
+
+/// How long the event loop waits between polling asynchronous operations, when there are no timers which would expire sooner.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+
+/// The event loop, which schedules tasks created by `async fn`s and asynchronous natives.
+///
+/// The event loop is single threaded, and only runs when the program `await`s a task which has not completed. Then, it runs each task which is ready,
+/// in the order they became ready, until they complete or are suspended awaiting another task. Once no tasks are ready, it polls any which are
+/// waiting on a timer or IO, sleeping until the next one might complete.
+#[derive(Default)]
+pub struct EventLoop {
+    /// Tasks which are ready to start or resume
+    ready: VecDeque<ValuePtr>,
+    /// Tasks which are waiting on a timer or IO
+    waiting: Vec<ValuePtr>,
+    /// Tasks which are currently running, innermost last
+    running: Vec<RunningTask>,
+}
+
+/// A task which is currently running, along with the state of the VM when it was started or resumed. Everything above this belongs to the task.
+struct RunningTask {
+    task: ValuePtr,
+    stack: usize,
+    frames: usize,
+    literals: usize,
+    unrolls: usize,
+    contexts: usize,
+    /// The number of nested `run()` loops the task is running within. It can only be suspended if it is not within a callback invoked by a native function, which would run in a nested loop.
+    runs: usize,
+}
+
+impl EventLoop {
+    pub fn spawn(&mut self, task: ValuePtr) {
+        self.waiting.push(task);
+    }
+
+    /// Forgets any tasks which were running, when the VM is recovered after an error
+    pub fn clear(&mut self) {
+        self.running.clear();
+    }
+}
+
+
+impl<R, W, E> VirtualMachine<R, W, E> where
+    R: BufRead,
+    W: Write,
+    E: Write {
+
+    /// Pops a call to an async function with `nargs` arguments off the stack, and pushes a task which will run it, scheduled to start the next time the event loop runs.
+    pub(super) fn spawn_call(&mut self, nargs: u32) {
+        let args: Vec<ValuePtr> = self.popn(nargs);
+        let f: ValuePtr = self.pop();
+        let task: ValuePtr = TaskImpl::new(f.get_function().repr(), TaskState::Created(f, args)).to_value();
+        self.tasks.ready.push_back(task.clone());
+        self.push(task);
+    }
+
+    /// Awaits `task`, leaving its result on the stack once it has completed.
+    ///
+    /// If `task` has not completed, and this is running directly within a task, then that task is suspended, and this returns `RuntimeAwait`, which unwinds to the event loop.
+    /// Otherwise, this runs the event loop until `task` has completed.
+    pub(super) fn await_task(&mut self, task: ValuePtr) -> AnyResult {
+        let task: ValuePtr = task.check_task()?;
+        if let Some(ret) = task.as_task().borrow().result() {
+            self.push(ret?);
+            return Ok(())
+        }
+        if self.tasks.running.last().is_some_and(|running| running.runs == self.runs) {
+            self.suspend(task);
+            return RuntimeAwait.err()
+        }
+        loop {
+            if let Some(ret) = task.as_task().borrow().result() {
+                self.push(ret?);
+                return Ok(())
+            }
+            self.run_event_loop(&task)?;
+        }
+    }
+
+    /// Runs the next task which is ready, or if none are, polls the tasks waiting on timers or IO, waiting until at least one might have completed.
+    /// If no tasks are ready or waiting, then `awaiting` can never complete.
+    fn run_event_loop(&mut self, awaiting: &ValuePtr) -> AnyResult {
+        if let Some(task) = self.tasks.ready.pop_front() {
+            return self.resume(task)
+        }
+        if self.tasks.waiting.is_empty() {
+            return ValueErrorTaskNeverCompletes(awaiting.clone()).err()
+        }
+
+        let now: Instant = Instant::now();
+        let mut wait: Option<Duration> = None;
+        let mut any_completed: bool = false;
+        for task in std::mem::take(&mut self.tasks.waiting) {
+            let result: Option<ValueResult> = match &mut task.as_task().borrow_mut().state {
+                TaskState::Sleeping(deadline) if *deadline <= now => Some(ValuePtr::nil().ok()),
+                TaskState::Sleeping(deadline) => {
+                    wait = Some(wait.map_or(*deadline - now, |wait| wait.min(*deadline - now)));
+                    None
+                },
+                TaskState::Polling(operation) => {
+                    let result = operation.poll();
+                    wait = Some(wait.map_or(POLL_INTERVAL, |wait| wait.min(POLL_INTERVAL)));
+                    result
+                },
+                _ => unreachable!(),
+            };
+            match result {
+                Some(result) => {
+                    any_completed = true;
+                    self.complete(task, result)?;
+                },
+                None => self.tasks.waiting.push(task),
+            }
+        }
+        if !any_completed {
+            if let Some(wait) = wait {
+                thread::sleep(wait);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts or resumes `task`, and runs it until it completes, or is suspended again.
+    fn resume(&mut self, task: ValuePtr) -> AnyResult {
+        let state: TaskState = std::mem::replace(&mut task.as_task().borrow_mut().state, TaskState::Running);
+        let ip: usize = self.ip;
+        let frames: usize = self.call_stack.len();
+        self.tasks.running.push(RunningTask {
+            task: task.clone(),
+            stack: self.stack.len(),
+            frames,
+            literals: self.literal_stack.len(),
+            unrolls: self.unroll_stack.len(),
+            contexts: self.contexts.len(),
+            runs: self.runs + 1,
+        });
+
+        match state {
+            TaskState::Created(f, args) => {
+                let nargs: u32 = args.len() as u32;
+                let (head, num_var_args) = {
+                    let func = f.get_function();
+                    (func.jump_offset(nargs), func.num_var_args(nargs))
+                };
+                self.push(f);
+                for arg in args {
+                    self.push(arg);
+                }
+                self.call_function(head, nargs, num_var_args);
+            },
+            TaskState::Suspended(suspended) => self.restore(*suspended),
+            _ => unreachable!(),
+        }
+
+        let result: AnyResult = self.run_until(frames);
+        self.tasks.running.pop();
+        match result {
+            Ok(()) => {
+                self.ip = ip;
+                let ret: ValuePtr = self.pop();
+                self.complete(task, ret.ok())
+            },
+            Err(e) if matches!(e.value, RuntimeAwait) => {
+                self.ip = ip;
+                Ok(())
+            },
+            Err(e) => {
+                // The task's frames are left on the call stack, so they are included in the stack trace
+                task.as_task().borrow_mut().state = TaskState::Failed(e.value.clone());
+                task.as_task().borrow_mut().waiters.clear();
+                Err(e)
+            },
+        }
+    }
+
+    /// Completes `task` with `result`. If it was successful, any tasks awaiting it become ready, otherwise the error is raised from the event loop.
+    fn complete(&mut self, task: ValuePtr, result: ValueResult) -> AnyResult {
+        let mut it = task.as_task().borrow_mut();
+        let waiters: Vec<ValuePtr> = std::mem::take(&mut it.waiters);
+        match result.as_result() {
+            Ok(value) => {
+                it.state = TaskState::Done(value);
+                self.tasks.ready.extend(waiters);
+                Ok(())
+            },
+            Err(e) => {
+                it.state = TaskState::Failed(e.value.clone());
+                Err(e)
+            },
+        }
+    }
+
+    /// Suspends the innermost running task, which is awaiting `awaiting`, by moving everything it owns off of the VM.
+    fn suspend(&mut self, awaiting: ValuePtr) {
+        let running: &RunningTask = self.tasks.running.last().unwrap();
+        let base: usize = running.stack;
+
+        // Close any upvalues still open over the task's stack, as those stack slots are about to be moved
+        let mut upvalues: Vec<(usize, Rc<Cell<UpValue>>)> = Vec::new();
+        for index in self.open_upvalues.keys().copied().filter(|index| *index >= base).collect::<Vec<usize>>() {
+            let upvalue: Rc<Cell<UpValue>> = self.open_upvalues.remove(&index).unwrap();
+            upvalue.replace(UpValue::Closed(self.stack[index].clone()));
+            upvalues.push((index - base, upvalue));
+        }
+
+        let suspended: SuspendedTask = SuspendedTask {
+            ip: self.ip,
+            stack: self.stack.split_off(base),
+            frames: self.call_stack.split_off(running.frames)
+                .into_iter()
+                .map(|frame| CallFrame { frame_pointer: frame.frame_pointer - base, ..frame })
+                .collect(),
+            upvalues,
+            literals: self.literal_stack.split_off(running.literals),
+            unrolls: self.unroll_stack.split_off(running.unrolls),
+            contexts: self.contexts.split_off(running.contexts),
+            awaiting: awaiting.clone(),
+        };
+
+        let task: ValuePtr = running.task.clone();
+        awaiting.as_task().borrow_mut().waiters.push(task.clone());
+        task.as_task().borrow_mut().state = TaskState::Suspended(Box::new(suspended));
+    }
+
+    /// Moves a suspended task back onto the VM, at the current top of the stack, and pushes the result of the task it was awaiting.
+    fn restore(&mut self, suspended: SuspendedTask) {
+        let SuspendedTask { ip, mut stack, frames, upvalues, literals, unrolls, contexts, awaiting } = suspended;
+        let base: usize = self.stack.len();
+
+        // Re-open any upvalues, taking their current value, as they may have been modified by a closure while the task was suspended
+        for (offset, upvalue) in upvalues {
+            if let UpValue::Closed(value) = upvalue.replace(UpValue::Open(base + offset)) {
+                stack[offset] = value;
+            }
+            self.open_upvalues.insert(base + offset, upvalue);
+        }
+
+        let return_ip: usize = self.ip;
+        self.stack.extend(stack);
+        self.call_stack.extend(frames.into_iter()
+            .enumerate()
+            .map(|(i, frame)| CallFrame {
+                return_ip: if i == 0 { return_ip } else { frame.return_ip },
+                frame_pointer: frame.frame_pointer + base,
+                ..frame
+            }));
+        self.literal_stack.extend(literals);
+        self.unroll_stack.extend(unrolls);
+        self.contexts.extend(contexts);
+        self.ip = ip;
+
+        let ret: ValuePtr = match &awaiting.as_task().borrow().state {
+            TaskState::Done(value) => value.clone(),
+            _ => unreachable!(),
+        };
+        self.push(ret);
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::Write;
+
+use crate::vm::Opcode;
+
+
+/// Which events are emitted by a `Tracer`
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct TraceEvents {
+    /// Every instruction executed, along with its location and the depth of the stack
+    pub ops: bool,
+    /// The contents of the stack, after every instruction executed
+    pub stack: bool,
+    /// Every function invoked, and every return from a user function
+    pub calls: bool,
+}
+
+impl TraceEvents {
+    /// Parses a comma separated list of event kinds, i.e. `ops,stack,calls`
+    pub fn parse(text: &str) -> Option<TraceEvents> {
+        let mut events: TraceEvents = TraceEvents::default();
+        for kind in text.split(',') {
+            match kind.trim() {
+                "ops" => events.ops = true,
+                "stack" => events.stack = true,
+                "calls" => events.calls = true,
+                _ => return None,
+            }
+        }
+        Some(events)
+    }
+}
+
+
+/// Traces the execution of a VM, which can be provided by the host with `VirtualMachine::with_tracer()`.
+///
+/// Each event is written to the sink as a single line of JSON, i.e. `{"event":"op","ip":3,"line":1,"op":"Int(1)","depth":2}`, where `ip` is the index of the
+/// instruction, `line` is the line number of the instruction (or `null` if it has none), and `depth` is the number of values on the stack.
+pub struct Tracer {
+    events: TraceEvents,
+    sink: Box<dyn Write>,
+}
+
+impl Tracer {
+    pub fn new(events: TraceEvents, sink: Box<dyn Write>) -> Tracer {
+        Tracer { events, sink }
+    }
+
+    pub(super) fn op(&mut self, ip: usize, line: Option<usize>, op: Opcode, depth: usize) {
+        if self.events.ops {
+            self.emit(format!("{{\"event\":\"op\",\"ip\":{},\"line\":{},\"op\":{},\"depth\":{}}}", ip, json_line(line), json_str(&format!("{:?}", op)), depth));
+        }
+    }
+
+    /// Emits the current contents of the stack, where `stack` produces the `repr()` of each value, from the bottom of the stack upwards
+    pub(super) fn stack<F : FnOnce() -> Vec<String>>(&mut self, ip: usize, stack: F) {
+        if self.events.stack {
+            let values: Vec<String> = stack().iter().map(|v| json_str(v)).collect();
+            self.emit(format!("{{\"event\":\"stack\",\"ip\":{},\"stack\":[{}]}}", ip, values.join(",")));
+        }
+    }
+
+    /// Emits a call to `function`, where `frames` is the depth of the call stack before the call
+    pub(super) fn call<F : FnOnce() -> String>(&mut self, ip: usize, line: Option<usize>, function: F, nargs: u32, frames: usize) {
+        if self.events.calls {
+            self.emit(format!("{{\"event\":\"call\",\"ip\":{},\"line\":{},\"function\":{},\"nargs\":{},\"frames\":{}}}", ip, json_line(line), json_str(&function()), nargs, frames));
+        }
+    }
+
+    /// Emits a return from a user function, where `frames` is the depth of the call stack after returning
+    pub(super) fn ret(&mut self, ip: usize, line: Option<usize>, frames: usize) {
+        if self.events.calls {
+            self.emit(format!("{{\"event\":\"return\",\"ip\":{},\"line\":{},\"frames\":{}}}", ip, json_line(line), frames));
+        }
+    }
+
+    fn emit(&mut self, event: String) {
+        // Tracing is best-effort, and should never cause the program itself to fail
+        let _ = writeln!(self.sink, "{}", event);
+    }
+}
+
+
+fn json_line(line: Option<usize>) -> String {
+    match line {
+        Some(line) => line.to_string(),
+        None => String::from("null"),
+    }
+}
+
+fn json_str(text: &str) -> String {
+    let mut json: String = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(format!("\\u{:04x}", c as u32).as_str()),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tracer::{json_str, TraceEvents};
+
+    #[test]
+    fn test_parse_events() {
+        assert_eq!(TraceEvents::parse("ops,calls"), Some(TraceEvents { ops: true, stack: false, calls: true }));
+        assert_eq!(TraceEvents::parse("stack"), Some(TraceEvents { ops: false, stack: true, calls: false }));
+        assert_eq!(TraceEvents::parse("ops,jumps"), None);
+    }
+
+    #[test]
+    fn test_json_str() {
+        assert_eq!(json_str("fn f(a, b)"), "\"fn f(a, b)\"");
+        assert_eq!(json_str("'a\"b\\c\n'"), "\"'a\\\"b\\\\c\\n'\"");
+        assert_eq!(json_str("\x07"), "\"\\u0007\"");
+    }
+}
@@ -0,0 +1,62 @@
+use crate::vm::error::RuntimeError;
+use crate::vm::value::{ErrorResult, ValuePtr};
+use crate::vm::value::weak::WeakImpl;
+
+use RuntimeError::{*};
+
+
+/// A native resource, such as a socket, the standard input of a coprocess, or a database connection, which is held open by a value until it is closed.
+///
+/// Closing a handle drops the resource, after which any further use of it through `get()` or `get_mut()` raises an error. Closing a handle which is already closed does nothing.
+/// Any value holding a handle should be registered with the VM that opened it, via `VirtualInterface::register_handle()`, so that if it is still open when that VM is torn down, it can be closed then.
+#[derive(Debug)]
+pub struct Handle<T>(Option<T>);
+
+impl<T> Handle<T> {
+    pub fn new(resource: T) -> Handle<T> {
+        Handle(Some(resource))
+    }
+
+    /// Returns the resource, or raises an error if the handle is closed. `value` is the value holding this handle, which is reported in the error.
+    pub fn get(&self, value: &ValuePtr) -> ErrorResult<&T> {
+        match &self.0 {
+            Some(it) => Ok(it),
+            None => ValueErrorClosed(value.clone()).err(),
+        }
+    }
+
+    /// Like `get()`, but returns a mutable reference to the resource.
+    pub fn get_mut(&mut self, value: &ValuePtr) -> ErrorResult<&mut T> {
+        match &mut self.0 {
+            Some(it) => Ok(it),
+            None => ValueErrorClosed(value.clone()).err(),
+        }
+    }
+
+    /// Closes the handle, and returns the resource if it was still open, so it can be closed gracefully. Otherwise, it is simply dropped.
+    pub fn close(&mut self) -> Option<T> {
+        self.0.take()
+    }
+}
+
+
+/// Weak references to every value holding a handle which was opened by a single VM. Handles are usually few and long-lived, so values which are freed are only removed on the next `register()`.
+#[derive(Default)]
+pub struct Handles(Vec<WeakImpl>);
+
+impl Handles {
+    /// Registers `value`, which holds a handle, so that it is returned by `take()` if it is still alive when the VM is torn down. Returns `value`.
+    pub fn register(&mut self, value: ValuePtr) -> ValuePtr {
+        self.0.retain(|handle| handle.is_alive());
+        self.0.push(WeakImpl::new(&value));
+        value
+    }
+
+    /// Returns every registered value which is still alive, in the order they were registered, and clears the registry.
+    pub fn take(&mut self) -> Vec<ValuePtr> {
+        std::mem::take(&mut self.0)
+            .iter()
+            .filter_map(|handle| handle.get())
+            .collect()
+    }
+}
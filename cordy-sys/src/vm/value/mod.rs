@@ -1,25 +1,40 @@
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::cmp::{Ordering, Reverse};
 use std::collections::{BinaryHeap, HashMap, VecDeque};
 use std::convert::Infallible;
 use std::fmt::{Debug, Formatter};
 use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::BufReader;
 use std::iter::{FromIterator, FusedIterator};
-use std::ops::{ControlFlow, FromResidual, Try};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::ops::{ControlFlow, FromResidual, Residual, Try};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin};
 use std::rc::Rc;
 use std::str::Chars;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
 use fxhash::FxBuildHasher;
 use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
 
 use crate::compiler::Fields;
 use crate::core;
-use crate::core::{InvokeArg0, NativeFunction, PartialArgument};
+use crate::core::{InvokeArg0, NativeFunction, PartialArgument, ProgressBar};
+use crate::reporting::{Location, SourceView};
 use crate::util::impl_partial_ord;
 use crate::vm::error::RuntimeError;
 use crate::vm::value::ptr::{Ref, RefMut, SharedPrefix};
 
 pub use crate::vm::value::ptr::{MAX_INT, MIN_INT, ValuePtr, Field, Prefix};
+pub use crate::vm::value::weak::WeakImpl;
+pub use crate::vm::value::handle::Handle;
+pub use crate::vm::value::task::{Poll, SuspendedTask, TaskImpl, TaskState};
+pub use crate::vm::value::view::ViewImpl;
+pub use crate::vm::value::sorted::{SortedDictImpl, SortedSetImpl};
+pub use crate::vm::value::persistent::{PDictImpl, PListImpl};
+pub use crate::vm::value::stats::{live_stats, MemStat};
 
 use RuntimeError::{*};
 
@@ -27,6 +42,13 @@ pub type ErrorResult<T> = Result<T, Box<Prefix<RuntimeError>>>;
 pub type AnyResult = ErrorResult<()>;
 
 mod ptr;
+pub mod weak;
+pub mod handle;
+pub mod task;
+pub mod view;
+pub mod sorted;
+pub mod persistent;
+mod stats;
 
 
 /// `Type` is an enumeration of all the possible types (not including user-defined type variants such as `struct`s) possible in Cordy.
@@ -40,16 +62,33 @@ pub enum Type {
     NativeFunction,
     GetField,
     Complex,
+    Fraction,
     Str,
     List,
     Set,
     Dict,
     Heap,
+    SortedSet,
+    SortedDict,
     Vector,
+    View,
+    PList,
+    PDict,
+    Matrix,
+    BitSet,
+    Dsu,
+    Stopwatch,
+    Socket,
+    Coprocess,
+    Database,
+    Generator,
+    Task,
+    Weak,
     Struct,
     StructType,
     Range,
     Enumerate,
+    LazyIter,
     Slice,
     Iter,
     Memoized,
@@ -64,11 +103,11 @@ pub enum Type {
 
 impl Type {
     fn is_owned(&self) -> bool {
-        matches!(self, Type::Complex | Type::Range | Type::Enumerate | Type::PartialFunction | Type::PartialNativeFunction | Type::Slice | Type::Iter | Type::Error)
+        matches!(self, Type::Complex | Type::Fraction | Type::Range | Type::Enumerate | Type::LazyIter | Type::PartialFunction | Type::PartialNativeFunction | Type::Slice | Type::Iter | Type::Error)
     }
 
     fn is_shared(&self) -> bool {
-        matches!(self, Type::Str | Type::List | Type::Set | Type::Dict | Type::Heap | Type::Vector | Type::Function | Type::Closure | Type::Memoized | Type::Struct | Type::StructType)
+        matches!(self, Type::Str | Type::List | Type::Set | Type::Dict | Type::Heap | Type::SortedSet | Type::SortedDict | Type::Vector | Type::View | Type::PList | Type::PDict | Type::Matrix | Type::BitSet | Type::Dsu | Type::Stopwatch | Type::Socket | Type::Coprocess | Type::Database | Type::Generator | Type::Task | Type::Weak | Type::Function | Type::Closure | Type::Memoized | Type::Struct | Type::StructType)
     }
 }
 
@@ -241,6 +280,11 @@ impl Try for ValueResult {
     }
 }
 
+/// Associates the residual of a `ValueResult?` back with `ValueResult` itself, as required by `Try`
+impl Residual<ValuePtr> for Box<Prefix<RuntimeError>> {
+    type TryType = ValueResult;
+}
+
 /// Associated type for `Try`
 impl FromResidual for ValueResult {
     fn from_residual(residual: Box<Prefix<RuntimeError>>) -> ValueResult {
@@ -303,6 +347,7 @@ impl ValueStructType {
 }
 
 pub type C64 = num_complex::Complex<i64>;
+pub type R64 = num_rational::Ratio<i64>;
 
 
 impl ValuePtr {
@@ -326,13 +371,19 @@ impl ValuePtr {
             type_index: type_impl.get().type_index,
             type_impl,
             values,
+            hash: None,
         }.to_value()
     }
 
-    pub fn memoized(func: ValuePtr) -> ValuePtr {
+    pub fn memoized(func: ValuePtr, max_size: Option<usize>) -> ValuePtr {
         MemoizedImpl {
             func,
             cache: HashMap::with_hasher(FxBuildHasher::default()),
+            recent: VecDeque::new(),
+            max_size,
+            tick: 0,
+            hits: 0,
+            misses: 0,
         }.to_value()
     }
 
@@ -373,6 +424,7 @@ impl ValuePtr {
             Type::NativeFunction => self.as_native().name().to_string(),
             Type::PartialNativeFunction => self.as_partial_native_ref().func.name().to_string(),
             Type::Closure => self.as_closure().borrow().func.get().name.to_owned(),
+            Type::Fraction => self.as_precise_fraction_ref().inner.to_string(),
             _ => self.safe_to_repr_str(rc),
         }
     }
@@ -401,9 +453,14 @@ impl ValuePtr {
                     format!("{} + {}i", c.re, c.im)
                 }
             },
+            Type::Fraction => {
+                let r = &self.as_precise_fraction_ref().inner;
+                format!("fraction({}, {})", r.numer(), r.denom())
+            },
             Type::Str => {
+                // The debug representation escapes everything except `'`, which needs to be escaped in order to be read back as a string literal
                 let escaped = format!("{:?}", self.as_str().borrow_const());
-                format!("'{}'", &escaped[1..escaped.len() - 1])
+                format!("'{}'", escaped[1..escaped.len() - 1].replace('\'', "\\'"))
             },
 
             Type::List => recursive_guard!(
@@ -430,12 +487,78 @@ impl ValuePtr {
                     .map(|t| t.0.safe_to_repr_str(rc))
                     .join(", "))
             ),
+            Type::SortedSet => recursive_guard!(
+                String::from("sorted_set(...)"),
+                format!("sorted_set({{{}}})", self.as_sorted_set().borrow().iter()
+                    .map(|t| t.safe_to_repr_str(rc))
+                    .join(", "))
+            ),
+            Type::SortedDict => recursive_guard!(
+                String::from("sorted_dict(...)"),
+                format!("sorted_dict({{{}}})", self.as_sorted_dict().borrow().iter()
+                    .map(|(k, v)| format!("{}: {}", k.safe_to_repr_str(rc), v.safe_to_repr_str(rc)))
+                    .join(", "))
+            ),
             Type::Vector => recursive_guard!(
                 String::from("(...)"),
                 format!("({})", self.as_vector().borrow().vector.iter()
                     .map(|t| t.safe_to_repr_str(rc))
                     .join(", "))
             ),
+            Type::View => recursive_guard!(
+                String::from("view(...)"),
+                format!("view([{}])", self.as_view().borrow_const().as_slice().iter()
+                    .map(|t| t.safe_to_repr_str(rc))
+                    .join(", "))
+            ),
+            Type::PList => recursive_guard!(
+                String::from("plist(...)"),
+                format!("plist([{}])", self.as_plist().borrow_const().iter()
+                    .map(|t| t.safe_to_repr_str(rc))
+                    .join(", "))
+            ),
+            Type::PDict => recursive_guard!(
+                String::from("pdict(...)"),
+                format!("pdict({{{}}})", self.as_pdict().borrow_const().pairs().iter()
+                    .map(|(k, v)| format!("{}: {}", k.safe_to_repr_str(rc), v.safe_to_repr_str(rc)))
+                    .join(", "))
+            ),
+            Type::Matrix => {
+                let it = self.as_matrix().borrow();
+                if it.rows == 0 || it.cols == 0 {
+                    format!("matrix({}, {})", it.rows, it.cols)
+                } else {
+                    format!("matrix([{}])", it.data.chunks(it.cols)
+                        .map(|row| format!("[{}]", row.iter().join(", ")))
+                        .join(", "))
+                }
+            },
+
+            Type::BitSet => {
+                let it = self.as_bitset().borrow();
+                format!("bitset({}, {{{}}})", it.size, it.iter().join(", "))
+            },
+            Type::Dsu => format!("dsu({})", self.as_dsu().borrow().clone().groups().iter()
+                .map(|group| format!("[{}]", group.iter().join(", ")))
+                .join(", ")),
+
+            Type::Stopwatch => format!("stopwatch({:?})", self.as_stopwatch().borrow().start.elapsed()),
+            Type::Socket => match &*self.as_socket().borrow() {
+                SocketImpl::Stream(addr, _) => format!("tcp_stream({})", addr),
+                SocketImpl::Listener(addr, _) => format!("tcp_listener({})", addr),
+            },
+            Type::Coprocess => format!("coprocess({})", self.as_coprocess().borrow().cmd),
+            Type::Database => format!("database({})", self.as_database().borrow().path),
+            Type::Generator => match self.as_generator().borrow_const() {
+                GeneratorImpl::Int(lo, hi) => format!("gen_int({}, {})", lo, hi),
+                GeneratorImpl::List(g, n) => format!("gen_list({}, {})", g.safe_to_repr_str(rc), n),
+                GeneratorImpl::Str(alphabet, n) => format!("gen_str({}, {})", alphabet.iter().collect::<String>().to_value().safe_to_repr_str(rc), n),
+            },
+            Type::Task => format!("task({})", self.as_task().borrow().name),
+            Type::Weak => match self.as_weak().borrow_const().get() {
+                Some(it) => format!("weak({})", it.safe_to_repr_str(rc)),
+                None => String::from("weak(nil)"),
+            },
 
             Type::Struct => {
                 let it = self.as_struct().borrow();
@@ -447,10 +570,7 @@ impl ValuePtr {
                         .join(", "))
                 )
             },
-            Type::StructType => {
-                let it = self.as_struct_type().borrow_const();
-                format!("struct {}({})", it.name.clone(), it.field_names.join(", "))
-            },
+            Type::StructType => self.as_struct_type().borrow_const().as_str(),
 
             Type::Range => {
                 let r = self.as_range_ref();
@@ -461,6 +581,15 @@ impl ValuePtr {
                 }
             },
             Type::Enumerate => format!("enumerate({})", self.as_enumerate_ref().inner.safe_to_repr_str(rc)),
+            Type::LazyIter => match self.as_lazy_iter_ref() {
+                LazyIterImpl::Cycle(it) => format!("cycle({})", it.safe_to_repr_str(rc)),
+                LazyIterImpl::Repeat(it, None) => format!("repeat({})", it.safe_to_repr_str(rc)),
+                LazyIterImpl::Repeat(it, Some(n)) => format!("repeat({}, {})", it.safe_to_repr_str(rc), n),
+                LazyIterImpl::Chain(its) => format!("chain({})", its.iter().map(|u| u.safe_to_repr_str(rc)).join(", ")),
+                LazyIterImpl::Walk(it) => format!("walk({})", it.safe_to_repr_str(rc)),
+                LazyIterImpl::Progress(it, None) => format!("progress({})", it.safe_to_repr_str(rc)),
+                LazyIterImpl::Progress(it, Some(n)) => format!("progress({}, {})", it.safe_to_repr_str(rc), n),
+            },
             Type::Slice => {
                 #[inline]
                 fn to_str(i: &ValuePtr) -> String {
@@ -514,16 +643,33 @@ impl ValuePtr {
             Type::Bool => "bool",
             Type::Int => "int",
             Type::Complex => "complex",
+            Type::Fraction => "fraction",
             Type::Str => "str",
             Type::List => "list",
             Type::Set => "set",
             Type::Dict => "dict",
             Type::Heap => "heap",
+            Type::SortedSet => "sorted_set",
+            Type::SortedDict => "sorted_dict",
             Type::Vector => "vector",
+            Type::View => "view",
+            Type::PList => "plist",
+            Type::PDict => "pdict",
+            Type::Matrix => "matrix",
+            Type::BitSet => "bitset",
+            Type::Dsu => "dsu",
+            Type::Stopwatch => "stopwatch",
+            Type::Socket => "socket",
+            Type::Coprocess => "coprocess",
+            Type::Database => "database",
+            Type::Generator => "generator",
+            Type::Task => "task",
+            Type::Weak => "weak",
             Type::Struct => "struct",
             Type::StructType => "struct type",
             Type::Range => "range",
             Type::Enumerate => "enumerate",
+            Type::LazyIter => self.as_lazy_iter_ref().name(),
             Type::Slice => "slice",
             Type::Iter => "iter",
             Type::Memoized => "memoized",
@@ -554,10 +700,24 @@ impl ValuePtr {
             Type::Set => !self.as_set().borrow().set.is_empty(),
             Type::Dict => !self.as_dict().borrow().dict.is_empty(),
             Type::Heap => !self.as_heap().borrow().heap.is_empty(),
+            Type::SortedSet => !self.as_sorted_set().borrow().is_empty(),
+            Type::SortedDict => !self.as_sorted_dict().borrow().is_empty(),
             Type::Vector => !self.as_vector().borrow().vector.is_empty(),
+            Type::View => !self.as_view().borrow_const().is_empty(),
+            Type::PList => !self.as_plist().borrow_const().is_empty(),
+            Type::PDict => !self.as_pdict().borrow_const().is_empty(),
+            Type::Matrix => !self.as_matrix().borrow().data.is_empty(),
+            Type::BitSet => self.as_bitset().borrow().count_ones() > 0,
             Type::Range => !self.as_range_ref().is_empty(),
             Type::Enumerate => self.as_enumerate_ref().inner.to_bool(),
-            Type::Iter | Type::Memoized => panic!("{:?} is a synthetic type should not have as_bool() invoked on it", self),
+            Type::LazyIter => match self.as_lazy_iter_ref() {
+                LazyIterImpl::Cycle(it) => it.to_bool(),
+                LazyIterImpl::Repeat(_, n) => *n != Some(0),
+                LazyIterImpl::Chain(its) => its.iter().any(|u| u.to_bool()),
+                LazyIterImpl::Walk(_) => self.clone().to_iter().is_ok_and(|mut it| it.next().is_some()),
+                LazyIterImpl::Progress(it, _) => it.to_bool(),
+            },
+            Type::Iter => panic!("{:?} is a synthetic type should not have as_bool() invoked on it", self),
             _ => true,
         }
     }
@@ -575,7 +735,7 @@ impl ValuePtr {
                 };
                 Ok(Iterable::Str(string, chars))
             },
-            Type::List | Type::Set | Type::Dict | Type::Vector => Ok(Iterable::Collection(0, self)),
+            Type::List | Type::Set | Type::Dict | Type::Vector | Type::View | Type::PList => Ok(Iterable::Collection(0, self)),
 
             // Heaps completely unbox themselves to be iterated over
             Type::Heap => Ok(Iterable::RawVector(0, self.as_heap().borrow().heap
@@ -583,11 +743,25 @@ impl ValuePtr {
                 .cloned().map(|u| u.0)
                 .collect::<Vec<ValuePtr>>())),
 
+            // Sorted sets and dicts unbox themselves, in order, with each entry of a sorted dict as a key-value pair
+            Type::SortedSet => Ok(Iterable::RawVector(0, self.as_sorted_set().borrow().iter().cloned().collect())),
+            Type::SortedDict => Ok(Iterable::RawVector(0, self.as_sorted_dict().borrow().iter().map(|(k, v)| (k.clone(), v.clone()).to_value()).collect())),
+
+            // Persistent dicts are iterated as key-value pairs, in an order determined by the hash of each key
+            Type::PDict => Ok(Iterable::RawVector(0, self.as_pdict().borrow_const().pairs().into_iter().map(|u| u.to_value()).collect())),
+
+            // Matrices are iterated by row, with each row unboxed into a `Vector`
+            Type::Matrix => Ok(Iterable::RawVector(0, self.as_matrix().borrow().to_rows())),
+
+            // Bitsets are iterated as the indices of each set bit
+            Type::BitSet => Ok(Iterable::RawVector(0, self.as_bitset().borrow().iter().map(|u| u.to_value()).collect())),
+
             Type::Range => {
                 let it = self.as_range();
                 Ok(Iterable::Range(it.value.start, it.value))
             },
             Type::Enumerate => Ok(Iterable::Enumerate(0, Box::new(self.as_enumerate().value.inner.to_iter()?))),
+            Type::LazyIter => self.as_lazy_iter_ref().to_iter(),
 
             _ => TypeErrorArgMustBeIterable(self.clone()).err(),
         }
@@ -597,7 +771,7 @@ impl ValuePtr {
     /// Note that this takes a `str` to be a non-iterable primitive type, unlike `is_iter()` and `as_iter()`
     pub fn as_iter_or_unit(self) -> Iterable {
         match self.ty() {
-            Type::List | Type::Set | Type::Dict | Type::Vector => Iterable::Collection(0, self),
+            Type::List | Type::Set | Type::Dict | Type::Vector | Type::View | Type::PList => Iterable::Collection(0, self),
 
             // Heaps completely unbox themselves to be iterated over
             Type::Heap => Iterable::RawVector(0, self.as_heap().borrow().heap
@@ -605,12 +779,21 @@ impl ValuePtr {
                 .cloned()
                 .map(|u| u.0)
                 .collect::<Vec<ValuePtr>>()),
+            Type::SortedSet => Iterable::RawVector(0, self.as_sorted_set().borrow().iter().cloned().collect()),
+            Type::SortedDict => Iterable::RawVector(0, self.as_sorted_dict().borrow().iter().map(|(k, v)| (k.clone(), v.clone()).to_value()).collect()),
+            Type::PDict => Iterable::RawVector(0, self.as_pdict().borrow_const().pairs().into_iter().map(|u| u.to_value()).collect()),
+            Type::Matrix => Iterable::RawVector(0, self.as_matrix().borrow().to_rows()),
+            Type::BitSet => Iterable::RawVector(0, self.as_bitset().borrow().iter().map(|u| u.to_value()).collect()),
 
             Type::Range => {
                 let it = self.as_range();
                 Iterable::Range(it.value.start, it.value)
             },
             Type::Enumerate => Iterable::Enumerate(0, Box::new(self.as_enumerate().value.inner.as_iter_or_unit())),
+            Type::LazyIter => match self.as_lazy_iter_ref().to_iter() {
+                Ok(it) => it,
+                Err(_) => Iterable::Unit(ValueOption::some(self)),
+            },
 
             _ => Iterable::Unit(ValueOption::some(self)),
         }
@@ -622,6 +805,8 @@ impl ValuePtr {
             Type::Str => Ok(Indexable::Str(self.as_str())),
             Type::List => Ok(Indexable::List(self.as_list().borrow_mut())),
             Type::Vector => Ok(Indexable::Vector(self.as_vector().borrow_mut())),
+            Type::View => Ok(Indexable::View(self)),
+            Type::PList => Ok(Indexable::PList(self)),
             _ => TypeErrorArgMustBeIndexable(self.clone()).err()
         }
     }
@@ -632,6 +817,8 @@ impl ValuePtr {
             Type::Str => Ok(Sliceable::Str(self.as_str(), String::new())),
             Type::List => Ok(Sliceable::List(self.as_list().borrow(), VecDeque::new())),
             Type::Vector => Ok(Sliceable::Vector(self.as_vector().borrow(), Vec::new())),
+            Type::View => Ok(Sliceable::View(self.as_view().borrow_const(), Vec::new())),
+            Type::PList => Ok(Sliceable::PList(self.as_plist().borrow_const(), Vec::new())),
             _ => TypeErrorArgMustBeSliceable(self.clone()).err()
         }
     }
@@ -641,6 +828,7 @@ impl ValuePtr {
         match match self.ty() {
             Type::List => self.as_list().borrow().list.iter().cloned().collect_tuple(),
             Type::Vector => self.as_vector().borrow().vector.iter().cloned().collect_tuple(),
+            Type::View => self.as_view().borrow_const().as_slice().iter().cloned().collect_tuple(),
             _ => None
         } {
             Some(it) => Ok(it),
@@ -674,9 +862,24 @@ impl ValuePtr {
             Type::Set => Ok(self.as_set().borrow().set.len()),
             Type::Dict => Ok(self.as_dict().borrow().dict.len()),
             Type::Heap => Ok(self.as_heap().borrow().heap.len()),
+            Type::SortedSet => Ok(self.as_sorted_set().borrow().len()),
+            Type::SortedDict => Ok(self.as_sorted_dict().borrow().len()),
             Type::Vector => Ok(self.as_vector().borrow().vector.len()),
+            Type::View => Ok(self.as_view().borrow_const().len()),
+            Type::PList => Ok(self.as_plist().borrow_const().len()),
+            Type::PDict => Ok(self.as_pdict().borrow_const().len()),
+            Type::Matrix => Ok(self.as_matrix().borrow().rows),
+            Type::BitSet => Ok(self.as_bitset().borrow().count_ones()),
             Type::Range => Ok(self.as_range_ref().len()),
             Type::Enumerate => self.as_enumerate_ref().inner.len(),
+            Type::LazyIter => match self.as_lazy_iter_ref() {
+                _ if self.is_infinite() => ValueErrorIterableIsInfinite(self.clone()).err(),
+                LazyIterImpl::Cycle(_) => Ok(0),
+                LazyIterImpl::Repeat(_, n) => Ok(n.unwrap_or(0)),
+                LazyIterImpl::Chain(its) => its.iter().map(|u| u.len()).sum(),
+                LazyIterImpl::Walk(_) => Ok(self.clone().to_iter()?.count()),
+                LazyIterImpl::Progress(it, _) => it.len(),
+            },
             _ => TypeErrorArgMustBeIterable(self.clone()).err()
         }
     }
@@ -690,13 +893,47 @@ impl ValuePtr {
                     None => TypeErrorFieldNotPresentOnValue(it.type_impl.ptr.clone(), fields.get_field_name(field_index), true).err()
                 }
             },
+            Type::StructType => {
+                let it = self.as_struct_type().borrow_const();
+                match fields.get_member_offset(it.type_index, field_index) {
+                    Some(member_offset) => it.get_member(member_offset).ok(),
+                    None => TypeErrorFieldNotPresentOnValue(self.clone(), fields.get_field_name(field_index), false).err()
+                }
+            },
             _ => TypeErrorFieldNotPresentOnValue(self, fields.get_field_name(field_index), false).err()
         }
     }
 
+    /// If this value is a struct, and the field `field_index` is a computed field (property) of it's type, returns the getter and (optional) setter functions.
+    pub fn get_property(&self, fields: &Fields, field_index: u32) -> Option<(ValuePtr, Option<ValuePtr>)> {
+        match self.ty() {
+            Type::Struct => {
+                let it = self.as_struct().borrow();
+                let (getter, setter) = fields.get_property_offsets(it.type_index, field_index)?;
+                let type_impl = it.type_impl.get();
+                Some((type_impl.get_member(getter), setter.map(|setter| type_impl.get_member(setter))))
+            },
+            _ => None
+        }
+    }
+
+    /// Returns the value of the field named `name`, if this value is a struct with such a field.
+    /// Unlike `get_field()`, this looks up the field by name at runtime, rather than through the field offsets resolved by the compiler.
+    pub fn get_field_by_name(&self, name: &str) -> Option<ValuePtr> {
+        match self.ty() {
+            Type::Struct => {
+                let it = self.as_struct().borrow();
+                let index: usize = it.type_impl.get().field_names.iter().position(|f| f == name)?;
+                Some(it.values[index].clone())
+            },
+            _ => None
+        }
+    }
+
     pub fn set_field(self, fields: &Fields, field_index: u32, value: ValuePtr) -> ValueResult {
         match self.ty() {
             Type::Struct => {
+                self.check_mutable()?;
                 let mut it = self.as_struct().borrow_mut();
                 match fields.get_field_offset(it.type_index, field_index) {
                     Some(field_offset) => {
@@ -712,7 +949,22 @@ impl ValuePtr {
 
     /// Returns if the value is iterable.
     pub fn is_iter(&self) -> bool {
-        matches!(self.ty(), Type::Str | Type::List | Type::Set | Type::Dict | Type::Heap | Type::Vector | Type::Range | Type::Enumerate)
+        matches!(self.ty(), Type::Str | Type::List | Type::Set | Type::Dict | Type::Heap | Type::SortedSet | Type::SortedDict | Type::Vector | Type::View | Type::PList | Type::PDict | Type::Matrix | Type::BitSet | Type::Range | Type::Enumerate | Type::LazyIter)
+    }
+
+    /// Returns if the value is an infinite iterable, i.e. a non-empty `cycle()`, or a `repeat()` without a count.
+    pub fn is_infinite(&self) -> bool {
+        match self.ty() {
+            Type::Enumerate => self.as_enumerate_ref().inner.is_infinite(),
+            Type::LazyIter => match self.as_lazy_iter_ref() {
+                LazyIterImpl::Cycle(it) => it.to_bool(),
+                LazyIterImpl::Repeat(_, n) => n.is_none(),
+                LazyIterImpl::Chain(its) => its.iter().any(|u| u.is_infinite()),
+                LazyIterImpl::Walk(_) => false,
+                LazyIterImpl::Progress(it, _) => it.is_infinite(),
+            },
+            _ => false,
+        }
     }
 
     /// Returns if the value is function-evaluable. Note that single-element lists are not considered functions here.
@@ -720,6 +972,16 @@ impl ValuePtr {
         matches!(self.ty(), Type::Function | Type::PartialFunction | Type::NativeFunction | Type::PartialNativeFunction | Type::Closure | Type::StructType | Type::Slice)
     }
 
+    /// Returns if the value is a socket, which is a connection created by `tcp_connect()` or `accept()`
+    pub fn is_tcp_stream(&self) -> bool {
+        self.is_socket() && matches!(*self.as_socket().borrow(), SocketImpl::Stream(_, _))
+    }
+
+    /// Returns if the value is a socket, which is a listener created by `tcp_listen()`
+    pub fn is_tcp_listener(&self) -> bool {
+        self.is_socket() && matches!(*self.as_socket().borrow(), SocketImpl::Listener(_, _))
+    }
+
     pub fn as_iterable_mut(&mut self) -> &mut Iterable {
         debug_assert!(self.is_iterable());
         self.as_mut_ref()
@@ -743,6 +1005,98 @@ impl ValuePtr {
         }
     }
 
+    pub fn check_fraction(self) -> ValueResult {
+        match self.is_fraction() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeFraction(self).err(),
+        }
+    }
+
+    pub fn check_matrix(self) -> ValueResult {
+        match self.is_matrix() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeMatrix(self).err(),
+        }
+    }
+
+    pub fn check_bitset(self) -> ValueResult {
+        match self.is_bitset() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeBitSet(self).err(),
+        }
+    }
+
+    pub fn check_dsu(self) -> ValueResult {
+        match self.is_dsu() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeDsu(self).err(),
+        }
+    }
+
+    pub fn check_weak(self) -> ValueResult {
+        match self.is_weak() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeWeak(self).err(),
+        }
+    }
+
+    /// Checks that this value is a reference type, which can be weakly referenced or have finalizers, i.e. not an `int` or `nil`.
+    pub fn check_shared(self) -> ValueResult {
+        match self.ty().is_shared() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeShared(self).err(),
+        }
+    }
+
+    pub fn check_stopwatch(self) -> ValueResult {
+        match self.is_stopwatch() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeStopwatch(self).err(),
+        }
+    }
+
+    pub fn check_tcp_stream(self) -> ValueResult {
+        match self.is_tcp_stream() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeTcpStream(self).err(),
+        }
+    }
+
+    pub fn check_tcp_listener(self) -> ValueResult {
+        match self.is_tcp_listener() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeTcpListener(self).err(),
+        }
+    }
+
+    pub fn check_database(self) -> ValueResult {
+        match self.is_database() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeDatabase(self).err(),
+        }
+    }
+
+    pub fn check_generator(self) -> ValueResult {
+        match self.is_generator() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeGenerator(self).err(),
+        }
+    }
+
+    pub fn check_task(self) -> ValueResult {
+        match self.is_task() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeTask(self).err(),
+        }
+    }
+
+    pub fn check_iter(self) -> ValueResult {
+        match self.is_iter() {
+            true => self.ok(),
+            false => TypeErrorArgMustBeIterable(self).err(),
+        }
+    }
+
     pub fn check_str(self) -> ValueResult {
         match self.is_str() {
             true => self.ok(),
@@ -765,6 +1119,79 @@ impl ValuePtr {
     }
 }
 
+impl ValuePtr {
+    /// Returns `true` if this value contains itself, at any depth. Such values cannot be hashed, as hashing them would never terminate.
+    pub fn is_recursive(&self) -> bool { self.safe_is_recursive(&mut RecursionGuard::new()) }
+
+    fn safe_is_recursive(&self, rc: &mut RecursionGuard) -> bool {
+        macro_rules! recursive_guard {
+            ($recursive:expr) => {{
+                let ret = rc.enter(self) || $recursive;
+                rc.leave();
+                ret
+            }};
+        }
+
+        match self.ty() {
+            Type::List => recursive_guard!(self.as_list().borrow().list.iter().any(|v| v.safe_is_recursive(rc))),
+            Type::Set => recursive_guard!(self.as_set().borrow().set.iter().any(|v| v.safe_is_recursive(rc))),
+            Type::Dict => recursive_guard!(self.as_dict().borrow().dict.iter().any(|(k, v)| k.safe_is_recursive(rc) || v.safe_is_recursive(rc))),
+            Type::Heap => recursive_guard!(self.as_heap().borrow().heap.iter().any(|v| v.0.safe_is_recursive(rc))),
+            Type::SortedSet => recursive_guard!(self.as_sorted_set().borrow().iter().any(|v| v.safe_is_recursive(rc))),
+            Type::SortedDict => recursive_guard!(self.as_sorted_dict().borrow().iter().any(|(k, v)| k.safe_is_recursive(rc) || v.safe_is_recursive(rc))),
+            Type::Vector => recursive_guard!(self.as_vector().borrow().vector.iter().any(|v| v.safe_is_recursive(rc))),
+            Type::View => recursive_guard!(self.as_view().borrow_const().as_slice().iter().any(|v| v.safe_is_recursive(rc))),
+            Type::PList => recursive_guard!(self.as_plist().borrow_const().iter().any(|v| v.safe_is_recursive(rc))),
+            Type::PDict => recursive_guard!(self.as_pdict().borrow_const().pairs().iter().any(|(k, v)| k.safe_is_recursive(rc) || v.safe_is_recursive(rc))),
+            Type::Struct => recursive_guard!(self.as_struct().borrow().values.iter().any(|v| v.safe_is_recursive(rc))),
+            _ => false,
+        }
+    }
+
+    /// Freezes this value, and every mutable value it contains, so that any attempt to mutate them raises an error.
+    ///
+    /// This is done to every key inserted into a `dict` or `set`, as mutating a key would change its hash, and corrupt the collection. Once frozen, a value is never unfrozen, but copies of it, i.e. `list(x)`, are not frozen.
+    pub fn freeze(&self) {
+        if !matches!(self.ty(), Type::List | Type::Set | Type::Dict | Type::Heap | Type::SortedSet | Type::SortedDict | Type::Vector | Type::View | Type::PList | Type::PDict | Type::Struct | Type::Matrix | Type::BitSet) || !self.set_frozen() {
+            return // Either immutable, or already frozen, which also stops recursive values
+        }
+        match self.ty() {
+            Type::List => self.as_list().borrow().list.iter().for_each(|v| v.freeze()),
+            Type::Set => self.as_set().borrow().set.iter().for_each(|v| v.freeze()),
+            Type::Dict => self.as_dict().borrow().dict.iter().for_each(|(k, v)| {
+                k.freeze();
+                v.freeze();
+            }),
+            Type::Heap => self.as_heap().borrow().heap.iter().for_each(|v| v.0.freeze()),
+            Type::SortedSet => self.as_sorted_set().borrow().iter().for_each(|v| v.freeze()),
+            Type::SortedDict => self.as_sorted_dict().borrow().iter().for_each(|(k, v)| {
+                k.freeze();
+                v.freeze();
+            }),
+            Type::Vector => self.as_vector().borrow().vector.iter().for_each(|v| v.freeze()),
+            Type::View => self.as_view().borrow_const().as_slice().iter().for_each(|v| v.freeze()),
+            Type::PList => self.as_plist().borrow_const().iter().for_each(|v| v.freeze()),
+            Type::PDict => self.as_pdict().borrow_const().pairs().iter().for_each(|(k, v)| {
+                k.freeze();
+                v.freeze();
+            }),
+            Type::Struct => self.as_struct().borrow().values.iter().for_each(|v| v.freeze()),
+            _ => {},
+        }
+    }
+
+    /// Returns an error if this value is frozen, or is a view, and so cannot be mutated. This should be checked before any mutation of a collection.
+    pub fn check_mutable(&self) -> AnyResult {
+        if self.ty() == Type::View {
+            return ValueErrorViewIsImmutable(self.clone()).err()
+        }
+        match self.is_frozen() {
+            true => ValueErrorValueIsFrozen(self.clone()).err(),
+            false => Ok(()),
+        }
+    }
+}
+
 /// A type used to prevent recursive `repr()` and `str()` calls.
 struct RecursionGuard(Vec<ValueRef>);
 
@@ -840,6 +1267,7 @@ impl SharedValue for () {}
 // Cannot implement for `ComplexImpl` because we need a specialized to_value() which may convert to int
 impl_owned_value!(Type::Range, RangeImpl, as_range, as_range_ref, is_range);
 impl_owned_value!(Type::Enumerate, EnumerateImpl, as_enumerate, as_enumerate_ref, is_enumerate);
+impl_owned_value!(Type::LazyIter, LazyIterImpl, as_lazy_iter, as_lazy_iter_ref, is_lazy_iter);
 impl_owned_value!(Type::PartialFunction, PartialFunctionImpl, as_partial_function, as_partial_function_ref, is_partial_function);
 impl_owned_value!(Type::PartialNativeFunction, PartialNativeFunctionImpl, as_partial_native, as_partial_native_ref, is_partial_native);
 impl_owned_value!(Type::Slice, SliceImpl, as_slice, as_slice_ref, is_slice);
@@ -851,7 +1279,22 @@ impl_shared_value!(Type::List, ListImpl, MutValue, as_list, is_list);
 impl_shared_value!(Type::Set, SetImpl, MutValue, as_set, is_set);
 impl_shared_value!(Type::Dict, DictImpl, MutValue, as_dict, is_dict);
 impl_shared_value!(Type::Heap, HeapImpl, MutValue, as_heap, is_heap);
+impl_shared_value!(Type::SortedSet, SortedSetImpl, MutValue, as_sorted_set, is_sorted_set);
+impl_shared_value!(Type::SortedDict, SortedDictImpl, MutValue, as_sorted_dict, is_sorted_dict);
 impl_shared_value!(Type::Vector, VectorImpl, MutValue, as_vector, is_vector);
+impl_shared_value!(Type::View, ViewImpl, ConstValue, as_view, is_view);
+impl_shared_value!(Type::PList, PListImpl, ConstValue, as_plist, is_plist);
+impl_shared_value!(Type::PDict, PDictImpl, ConstValue, as_pdict, is_pdict);
+impl_shared_value!(Type::Matrix, MatrixImpl, MutValue, as_matrix, is_matrix);
+impl_shared_value!(Type::BitSet, BitSetImpl, MutValue, as_bitset, is_bitset);
+impl_shared_value!(Type::Dsu, DsuImpl, MutValue, as_dsu, is_dsu);
+impl_shared_value!(Type::Stopwatch, StopwatchImpl, MutValue, as_stopwatch, is_stopwatch);
+impl_shared_value!(Type::Socket, SocketImpl, MutValue, as_socket, is_socket);
+impl_shared_value!(Type::Coprocess, CoprocessImpl, MutValue, as_coprocess, is_coprocess);
+impl_shared_value!(Type::Database, DatabaseImpl, MutValue, as_database, is_database);
+impl_shared_value!(Type::Generator, GeneratorImpl, ConstValue, as_generator, is_generator);
+impl_shared_value!(Type::Task, TaskImpl, MutValue, as_task, is_task);
+impl_shared_value!(Type::Weak, WeakImpl, ConstValue, as_weak, is_weak);
 impl_shared_value!(Type::Function, FunctionImpl, ConstValue, as_function, is_function);
 impl_shared_value!(Type::Closure, ClosureImpl, MutValue, as_closure, is_closure);
 impl_shared_value!(Type::Memoized, MemoizedImpl, MutValue, as_memoized, is_memoized);
@@ -884,6 +1327,12 @@ impl_into!(ComplexImpl, self, if self.inner.im == 0 {
 } else {
     ValuePtr::from(Prefix::new(Type::Complex, self))
 });
+impl_into!(num_rational::Ratio<i64>, self, FractionImpl { inner: self }.to_value());
+impl_into!(FractionImpl, self, if self.inner.is_integer() {
+    ValuePtr::from(self.inner.to_integer())
+} else {
+    ValuePtr::from(Prefix::new(Type::Fraction, self))
+});
 impl_into!(bool, self, ValuePtr::from(self));
 impl_into!(char, self, String::from(self).to_value());
 impl_into!(&str, self, String::from(self).to_value());
@@ -891,13 +1340,21 @@ impl_into!(NativeFunction, self, ValuePtr::from(self));
 impl_into!(VecDeque<ValuePtr>, self, ListImpl { list: self }.to_value());
 impl_into!(Vec<ValuePtr>, self, VectorImpl { vector: self }.to_value());
 impl_into!((ValuePtr, ValuePtr), self, vec![self.0, self.1].to_value());
-impl_into!(IndexSet<ValuePtr, FxBuildHasher>, self, SetImpl { set: self }.to_value());
-impl_into!(IndexMap<ValuePtr, ValuePtr, FxBuildHasher>, self, DictImpl { dict: self, default: None }.to_value());
+impl_into!(IndexSet<ValuePtr, FxBuildHasher>, self, {
+    self.iter().for_each(|v| v.freeze());
+    SetImpl { set: self }.to_value()
+});
+impl_into!(IndexMap<ValuePtr, ValuePtr, FxBuildHasher>, self, {
+    self.keys().for_each(|k| k.freeze());
+    DictImpl { dict: self, default: None }.to_value()
+});
 impl_into!(BinaryHeap<Reverse<ValuePtr>>, self, HeapImpl { heap: self }.to_value());
 impl_into!(Sliceable<'_>, self, match self {
     Sliceable::Str(_, it) => it.to_value(),
     Sliceable::List(_, it) => it.to_value(),
     Sliceable::Vector(_, it) => it.to_value(),
+    Sliceable::View(_, it) => ViewImpl::new(it).to_value(),
+    Sliceable::PList(_, it) => it.into_iter().collect::<PListImpl>().to_value(),
 });
 
 
@@ -975,6 +1432,45 @@ impl Ord for ComplexImpl {
 }
 
 
+/// An exact rational number. Like `ComplexImpl`, this is always stored in a normalized form, and any fraction with a denominator of `1` is converted to an `int` instead.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct FractionImpl {
+    pub inner: num_rational::Ratio<i64>,
+}
+
+impl OwnedValue for FractionImpl {}
+
+impl ValuePtr {
+    pub fn as_precise_fraction(self) -> Box<Prefix<FractionImpl>> {
+        debug_assert!(self.ty() == Type::Fraction);
+        self.as_box()
+    }
+
+    pub fn as_precise_fraction_ref(&self) -> &FractionImpl {
+        debug_assert!(self.ty() == Type::Fraction);
+        self.as_ref()
+    }
+
+    pub fn is_precise_fraction(&self) -> bool {
+        self.ty() == Type::Fraction
+    }
+
+    pub fn is_fraction(&self) -> bool {
+        self.is_int() || self.is_precise_fraction()
+    }
+
+    /// If the current type is int-like, then automatically converts it to a fraction.
+    pub fn as_fraction(self) -> R64 {
+        debug_assert!(self.ty() == Type::Bool || self.ty() == Type::Int || self.ty() == Type::Fraction);
+        match self.ty() {
+            Type::Bool | Type::Int => R64::from_integer(self.as_int()),
+            Type::Fraction => self.as_precise_fraction().value.inner,
+            _ => unreachable!(),
+        }
+    }
+}
+
+
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct FunctionImpl {
@@ -985,11 +1481,28 @@ pub struct FunctionImpl {
     args: Vec<String>, // Names of the arguments
     default_args: Vec<usize>, // Jump offsets for each default argument
     var_arg: bool, // If the last argument in this function is variadic
+    is_async: bool, // If this is an `async fn`, which returns a task when called, instead of running immediately
+    doc: Option<String>, // The text of the `///` doc comment before this function's declaration, if present
+    upvalue_names: Vec<String>, // The name of each upvalue in the environment of a closure of this function, used for debugging
+    location: Location, // The location of the parameter list in the function's declaration, used to report where it was defined
+    arg_locations: Vec<Location>, // The location of each parameter as written, including any default value, used to show the full signature in errors
 }
 
 impl FunctionImpl {
-    pub fn new(head: usize, tail: usize, name: String, args: Vec<String>, default_args: Vec<usize>, var_arg: bool) -> FunctionImpl {
-        FunctionImpl { head, tail, name, args, default_args, var_arg, }
+    pub fn new(head: usize, tail: usize, name: String, args: Vec<String>, default_args: Vec<usize>, var_arg: bool, is_async: bool) -> FunctionImpl {
+        FunctionImpl { head, tail, name, args, default_args, var_arg, is_async, doc: None, upvalue_names: Vec::new(), location: Location::empty(), arg_locations: Vec::new() }
+    }
+
+    pub fn with_doc(self, doc: Option<String>) -> FunctionImpl {
+        FunctionImpl { doc, ..self }
+    }
+
+    pub fn with_upvalue_names(self, upvalue_names: Vec<String>) -> FunctionImpl {
+        FunctionImpl { upvalue_names, ..self }
+    }
+
+    pub fn with_signature(self, location: Location, arg_locations: Vec<Location>) -> FunctionImpl {
+        FunctionImpl { location, arg_locations, ..self }
     }
 
     /// The minimum number of required arguments, inclusive.
@@ -1028,8 +1541,61 @@ impl FunctionImpl {
         }
     }
 
+    /// Returns the names of each argument
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Returns the jump offsets for each default argument
+    pub fn default_args(&self) -> &[usize] {
+        &self.default_args
+    }
+
+    pub fn is_var_arg(&self) -> bool {
+        self.var_arg
+    }
+
+    pub fn is_async(&self) -> bool {
+        self.is_async
+    }
+
+    pub fn doc(&self) -> Option<&String> {
+        self.doc.as_ref()
+    }
+
+    /// Returns the location of the parameter list in this function's declaration, or an empty location if it is not known.
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
+    /// Returns the name of the upvalue at `index` in the environment of a closure of this function, if it is known.
+    pub fn upvalue_name(&self, index: usize) -> Option<&String> {
+        self.upvalue_names.get(index)
+    }
+
+    /// Returns the full signature of this function, with each parameter as written in `view`, including default values, i.e. `fn f(a, b = 3, *c)`.
+    /// If the source of any parameter is not known, this is the same as `repr()`.
+    pub fn signature(&self, view: &SourceView) -> String {
+        let args: Option<Vec<String>> = match self.arg_locations.len() == self.args.len() {
+            true => self.arg_locations.iter().map(|loc| view.text_of(*loc)).collect(),
+            false => None,
+        };
+        match args {
+            Some(args) => format!("{}fn {}({})", if self.is_async { "async " } else { "" }, self.name, args.join(", ")),
+            None => self.repr(),
+        }
+    }
+
     pub fn repr(&self) -> String {
-        format!("fn {}({})", self.name, self.args.join(", "))
+        let prefix: &str = if self.is_async { "async " } else { "" };
+        match self.var_arg {
+            true => format!("{}fn {}({}*{})", prefix, self.name, self.args[..self.args.len() - 1].iter().map(|arg| format!("{}, ", arg)).collect::<String>(), self.args.last().unwrap()),
+            false => format!("{}fn {}({})", prefix, self.name, self.args.join(", ")),
+        }
     }
 }
 
@@ -1109,6 +1675,15 @@ impl ClosureImpl {
     pub fn get(&self, index: usize) -> Rc<Cell<UpValue>> {
         self.environment[index].clone()
     }
+
+    /// Returns a copy of each upvalue in the environment of this closure, in order.
+    pub fn upvalues(&self) -> impl Iterator<Item=UpValue> + '_ {
+        self.environment.iter().map(|upvalue| {
+            let interior = upvalue.take();
+            upvalue.set(interior.clone()); // Replace back the original value
+            interior
+        })
+    }
 }
 
 #[derive(Clone)]
@@ -1154,6 +1729,253 @@ pub struct VectorImpl {
     pub vector: Vec<ValuePtr>
 }
 
+/// A dense, row-major, two dimensional array of `int`s.
+///
+/// This is much more compact than a nested `list`, since elements are stored unboxed, and supports native element-wise operators and matrix multiplication.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MatrixImpl {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<i64>,
+}
+
+impl MatrixImpl {
+    pub fn new(rows: usize, cols: usize, data: Vec<i64>) -> MatrixImpl {
+        debug_assert!(rows * cols == data.len());
+        MatrixImpl { rows, cols, data }
+    }
+
+    pub fn row(&self, row: usize) -> ValuePtr {
+        self.data[row * self.cols..(row + 1) * self.cols].iter()
+            .map(|u| (*u).to_value())
+            .to_vector()
+    }
+
+    pub fn to_rows(&self) -> Vec<ValuePtr> {
+        (0..self.rows).map(|row| self.row(row)).collect()
+    }
+}
+
+/// A fixed size set of `int`s in the range `[0, size)`, stored as a packed array of bits.
+///
+/// Any bits in `words` at or above `size` are always zero.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BitSetImpl {
+    pub size: usize,
+    pub words: Vec<u64>,
+}
+
+impl BitSetImpl {
+    const BITS: usize = u64::BITS as usize;
+
+    pub fn new(size: usize) -> BitSetImpl {
+        BitSetImpl { size, words: vec![0; size.div_ceil(BitSetImpl::BITS)] }
+    }
+
+    /// Like `new()`, but returns `None` if the bitset cannot be allocated, rather than aborting.
+    pub fn try_new(size: usize) -> Option<BitSetImpl> {
+        let len: usize = size.div_ceil(BitSetImpl::BITS);
+        let mut words: Vec<u64> = Vec::new();
+        words.try_reserve_exact(len).ok()?;
+        words.resize(len, 0);
+        Some(BitSetImpl { size, words })
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        (self.words[index / BitSetImpl::BITS] >> (index % BitSetImpl::BITS)) & 1 == 1
+    }
+
+    pub fn set(&mut self, index: usize, value: bool) {
+        let word: &mut u64 = &mut self.words[index / BitSetImpl::BITS];
+        let mask: u64 = 1 << (index % BitSetImpl::BITS);
+        if value {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|u| u.count_ones() as usize).sum()
+    }
+
+    /// Returns an iterator over the indices of all set bits, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        self.words.iter()
+            .enumerate()
+            .flat_map(|(i, word)| {
+                let mut word: u64 = *word;
+                std::iter::from_fn(move || if word == 0 {
+                    None
+                } else {
+                    let bit: usize = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(i * BitSetImpl::BITS + bit)
+                })
+            })
+    }
+
+    /// Clears any bits past the end of the bitset, which may be set by operations such as complement or shifts.
+    pub fn truncate(&mut self) {
+        let rem: usize = self.size % BitSetImpl::BITS;
+        if rem != 0 {
+            if let Some(last) = self.words.last_mut() {
+                *last &= (1 << rem) - 1;
+            }
+        }
+    }
+}
+
+/// A disjoint set union, or union-find, over the `int`s in the range `[0, size)`.
+///
+/// Sets are merged by size, and `find()` compresses the path to the root of each set, so operations take amortized near-constant time.
+#[derive(Debug, Clone)]
+pub struct DsuImpl {
+    parent: Vec<usize>,
+    set_size: Vec<usize>,
+}
+
+impl DsuImpl {
+    /// Returns a new disjoint set union of the given size, or `None` if it cannot be allocated, rather than aborting.
+    pub fn try_new(size: usize) -> Option<DsuImpl> {
+        let mut parent: Vec<usize> = Vec::new();
+        let mut set_size: Vec<usize> = Vec::new();
+        parent.try_reserve_exact(size).ok()?;
+        set_size.try_reserve_exact(size).ok()?;
+        parent.extend(0..size);
+        set_size.resize(size, 1);
+        Some(DsuImpl { parent, set_size })
+    }
+
+    pub fn size(&self) -> usize {
+        self.parent.len()
+    }
+
+    /// Returns the root of the set containing `index`, compressing the path to it.
+    pub fn find(&mut self, index: usize) -> usize {
+        let mut root: usize = index;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut index: usize = index;
+        while self.parent[index] != root {
+            index = std::mem::replace(&mut self.parent[index], root);
+        }
+        root
+    }
+
+    /// Merges the sets containing `a` and `b`, returning `false` if they were already the same set.
+    pub fn union(&mut self, a: usize, b: usize) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false
+        }
+        if self.set_size[a] < self.set_size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.set_size[a] += self.set_size[b];
+        true
+    }
+
+    /// Returns each set, in increasing order, ordered by their smallest element.
+    pub fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut group_of_root: Vec<Option<usize>> = vec![None; self.size()];
+        for index in 0..self.size() {
+            let root: usize = self.find(index);
+            match group_of_root[root] {
+                Some(group) => groups[group].push(index),
+                None => {
+                    group_of_root[root] = Some(groups.len());
+                    groups.push(vec![index]);
+                },
+            }
+        }
+        groups
+    }
+}
+
+/// A timer, which records when it was started and when the last lap ended.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StopwatchImpl {
+    pub start: Instant,
+    pub lap: Instant,
+}
+
+impl StopwatchImpl {
+    /// Creates a new stopwatch, started at the current instant.
+    pub fn now() -> StopwatchImpl {
+        let now: Instant = Instant::now();
+        StopwatchImpl { start: now, lap: now }
+    }
+
+    /// Returns the time since the last lap (or the start, if there were no previous laps), and starts a new lap.
+    pub fn lap(&mut self) -> Duration {
+        let now: Instant = Instant::now();
+        let lap: Duration = now - self.lap;
+        self.lap = now;
+        lap
+    }
+}
+
+/// A TCP socket, which is either a connection created by `tcp_connect()` or `accept()`, or a listener created by `tcp_listen()`.
+///
+/// Each holds the address it was created with (the remote address for a connection, and the local address for a listener), which is kept after the socket is closed.
+/// Closing a socket closes the handle to the underlying stream or listener, after which any further operations on it raise an error.
+/// Sockets are only equal to themselves, and are not ordered.
+#[derive(Debug)]
+pub enum SocketImpl {
+    Stream(SocketAddr, Handle<BufReader<TcpStream>>),
+    Listener(SocketAddr, Handle<TcpListener>),
+}
+
+/// An external process created by `coprocess()`, which is communicated with line by line, via its standard input and output.
+///
+/// Lines from the process's standard output are read by a separate thread, so that `recv_line()` can give up after `timeout`.
+/// The handle is to the process's standard input, which is closed when the process is closed. If the process is still running when it is dropped, it is killed, and then waited on so it does not become a zombie.
+/// Like sockets, coprocesses are only equal to themselves, and are not ordered.
+#[derive(Debug)]
+pub struct CoprocessImpl {
+    /// The `repr()` of the command used to start the process
+    pub cmd: String,
+    pub child: Child,
+    pub stdin: Handle<ChildStdin>,
+    pub stdout: Receiver<io::Result<String>>,
+    pub timeout: Option<Duration>,
+}
+
+impl Drop for CoprocessImpl {
+    fn drop(&mut self) {
+        if self.stdin.close().is_some() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// A connection to a SQLite database, created by `db_open()`.
+///
+/// Once the handle to the connection is closed, any further operations on it raise an error. Without the `sqlite` feature, no databases can be created.
+/// Like sockets, databases are only equal to themselves, and are not ordered.
+#[derive(Debug)]
+pub struct DatabaseImpl {
+    pub path: String,
+    #[cfg(feature = "sqlite")]
+    pub conn: Handle<rusqlite::Connection>,
+}
+
+/// A generator of random values, created by `gen_int()`, `gen_list()`, or `gen_str()`, which is used by `forall()` to test properties.
+///
+/// Generators are immutable, and besides generating values, also know how to shrink a generated value towards a simpler one.
+/// Lengths are the maximum length of the generated `list` or `str`, and the range of a `gen_int()` is inclusive.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum GeneratorImpl {
+    Int(i64, i64),
+    List(ValuePtr, usize),
+    Str(Vec<char>, usize),
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct SetImpl {
     pub set: IndexSet<ValuePtr, FxBuildHasher>
@@ -1295,9 +2117,17 @@ pub struct StructImpl {
     pub type_index: u32,
     pub type_impl: ValueStructType,
     values: Vec<ValuePtr>,
+
+    /// The result of the user-defined `__hash__` member, if the struct type declares one. This is recomputed whenever the instance is constructed, or a field is modified.
+    hash: Option<i64>,
 }
 
 impl StructImpl {
+    /// Returns the values of each field, in the same order as the struct type's field names
+    pub fn values(&self) -> &[ValuePtr] {
+        &self.values
+    }
+
     fn get_field(&mut self, field_offset: usize) -> ValuePtr {
         self.values[field_offset].clone()
     }
@@ -1305,6 +2135,10 @@ impl StructImpl {
     fn set_field(&mut self, field_offset: usize, value: ValuePtr) {
         self.values[field_offset] = value;
     }
+
+    pub fn set_hash(&mut self, hash: i64) {
+        self.hash = Some(hash);
+    }
 }
 
 impl Eq for StructImpl {}
@@ -1325,7 +2159,10 @@ impl Ord for StructImpl {
 impl Hash for StructImpl {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.type_index.hash(state);
-        self.values.hash(state);
+        match self.hash {
+            Some(hash) => hash.hash(state),
+            None => self.values.hash(state),
+        }
     }
 }
 
@@ -1336,15 +2173,74 @@ pub struct StructTypeImpl {
     pub field_names: Vec<String>,
 
     pub type_index: u32,
+
+    /// The type indices of every parent of this struct, ordered from the direct parent up to the root.
+    /// This is the order in which the hierarchy is searched, and a struct is considered an instance (via `is`) of any of these types.
+    pub parents: Vec<u32>,
+
+    pub kind: StructKind,
+
+    /// The names of each static member (function or constant) declared on this struct, ordered by member offset.
+    pub member_names: Vec<String>,
+
+    /// The values of each static member. These are initialized (via `InheritMembers` and `InitMember`) directly after the struct is declared, and never modified afterwards.
+    members: RefCell<Vec<ValuePtr>>,
+}
+
+/// Distinguishes between plain structs, and the types produced by an `enum` declaration.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StructKind {
+    /// A plain struct, declared with `struct`
+    Struct,
+    /// An enum, declared with `enum`. It cannot be constructed, but is the parent of each of its variants.
+    Enum,
+    /// A variant of an enum, with the given discriminant, which is the index of the variant within the enum.
+    Variant(u32),
 }
 
 impl StructTypeImpl {
-    pub fn new(name: String, field_names: Vec<String>, type_index: u32) -> StructTypeImpl {
-        StructTypeImpl { name, field_names, type_index }
+    pub fn new(name: String, field_names: Vec<String>, type_index: u32, parents: Vec<u32>, kind: StructKind, member_names: Vec<String>) -> StructTypeImpl {
+        let members: RefCell<Vec<ValuePtr>> = RefCell::new(vec![ValuePtr::nil(); member_names.len()]);
+        StructTypeImpl { name, field_names, type_index, parents, kind, member_names, members }
+    }
+
+    pub fn get_member(&self, member_offset: usize) -> ValuePtr {
+        self.members.borrow()[member_offset].clone()
+    }
+
+    /// Returns the static member with the name `name`, if present. Unlike field access, this looks up the member by name at runtime.
+    pub fn get_member_by_name(&self, name: &str) -> Option<ValuePtr> {
+        let offset: usize = self.member_names.iter().position(|m| m == name)?;
+        Some(self.get_member(offset))
+    }
+
+    pub fn init_member(&self, member_offset: usize, value: ValuePtr) {
+        self.members.borrow_mut()[member_offset] = value;
+    }
+
+    /// Initializes each member inherited from `parent`, which occupy the first member offsets of this type, with the values of the parent's members.
+    pub fn inherit_members(&self, parent: &StructTypeImpl) {
+        self.members.borrow_mut()[..parent.member_names.len()].clone_from_slice(&parent.members.borrow());
+    }
+
+    /// Returns the discriminant of this type, if it is a variant of an enum.
+    pub fn discriminant(&self) -> Option<u32> {
+        match self.kind {
+            StructKind::Variant(discriminant) => Some(discriminant),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if an instance of this type is an instance of the type `type_index`, either directly or via inheritance.
+    pub fn is_subtype_of(&self, type_index: u32) -> bool {
+        self.type_index == type_index || self.parents.contains(&type_index)
     }
 
     pub fn as_str(&self) -> String {
-        format!("struct {}({})", self.name, self.field_names.join(", "))
+        match self.kind {
+            StructKind::Enum => format!("enum {}", self.name),
+            _ => format!("struct {}({})", self.name, self.field_names.join(", ")),
+        }
     }
 }
 
@@ -1388,8 +2284,8 @@ impl RangeImpl {
     pub fn reverse(self) -> RangeImpl {
         match self.step.cmp(&0) {
             Ordering::Equal => self,
-            Ordering::Greater => RangeImpl { start: self.start + self.len() as i64 * self.step, stop: self.start + 1, step: -self.step },
-            Ordering::Less => RangeImpl { start: self.start + self.len() as i64 * self.step, stop: self.start - 1, step: -self.step }
+            // Empty ranges are all `range(0, 0, 0)`, so here `len() > 0`, and `stop` must be exactly one step past the original `start`
+            _ => RangeImpl { start: self.start + (self.len() as i64 - 1) * self.step, stop: self.start - self.step, step: -self.step },
         }
     }
 
@@ -1432,6 +2328,55 @@ pub struct EnumerateImpl {
 }
 
 
+/// The lazy iterables returned by `cycle()`, `repeat()`, `chain()`, `walk()` and `progress()`. Like `enumerate()`, these hold onto their source values, and only produce elements as they are iterated.
+/// For `walk()`, the source is the path of the directory, which is read again each time it is iterated.
+///
+/// Both `Cycle` and `Repeat` (without a count) are infinite, so they report a length of zero as an `Iterable`, and raise an error from `len()`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum LazyIterImpl {
+    Cycle(ValuePtr),
+    Repeat(ValuePtr, Option<usize>),
+    Chain(Vec<ValuePtr>),
+    Walk(ValuePtr),
+    Progress(ValuePtr, Option<usize>),
+}
+
+impl LazyIterImpl {
+    fn name(&self) -> &'static str {
+        match self {
+            LazyIterImpl::Cycle(_) => "cycle",
+            LazyIterImpl::Repeat(_, _) => "repeat",
+            LazyIterImpl::Chain(_) => "chain",
+            LazyIterImpl::Walk(_) => "walk",
+            LazyIterImpl::Progress(_, _) => "progress",
+        }
+    }
+
+    fn to_iter(&self) -> ErrorResult<Iterable> {
+        match self {
+            LazyIterImpl::Cycle(it) => Ok(Iterable::Cycle(it.clone(), Box::new(it.clone().to_iter()?))),
+            LazyIterImpl::Repeat(it, n) => Ok(Iterable::Repeat(it.clone(), *n)),
+            LazyIterImpl::Chain(its) => Ok(Iterable::Chain(its.iter()
+                .map(|u| u.clone().to_iter())
+                .collect::<ErrorResult<VecDeque<Iterable>>>()?)),
+            LazyIterImpl::Walk(it) => match core::read_dir(Path::new(it.as_str().borrow_const())) {
+                Ok(entries) => Ok(Iterable::Walk(vec![entries])),
+                Err(err) => IOError(err.to_string()).err(),
+            },
+            LazyIterImpl::Progress(it, total) => {
+                let iter: Iterable = it.clone().to_iter()?;
+                let total: Option<usize> = match total {
+                    Some(total) => Some(*total),
+                    None if it.is_infinite() => None,
+                    None => Some(it.len()?),
+                };
+                Ok(Iterable::Progress(Box::new(iter), ProgressBar::new(total)))
+            },
+        }
+    }
+}
+
+
 /// All arguments must either be `nil` (which will be treated as `None`), or an int-like type.
 #[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Clone)]
 pub struct SliceImpl {
@@ -1493,6 +2438,11 @@ pub enum Iterable {
     RawVector(usize, Vec<ValuePtr>),
     Range(i64, RangeImpl),
     Enumerate(usize, Box<Iterable>),
+    Cycle(ValuePtr, Box<Iterable>),
+    Repeat(ValuePtr, Option<usize>),
+    Chain(VecDeque<Iterable>),
+    Walk(Vec<VecDeque<(PathBuf, bool)>>),
+    Progress(Box<Iterable>, ProgressBar),
 }
 
 impl Iterable {
@@ -1506,6 +2456,11 @@ impl Iterable {
             Iterable::RawVector(_, it) => it.len(),
             Iterable::Range(_, it) => it.len(),
             Iterable::Enumerate(_, it) => it.len(),
+            Iterable::Cycle(_, _) => 0, // Infinite, so this is only usable as a size hint
+            Iterable::Repeat(_, n) => n.unwrap_or(0),
+            Iterable::Chain(its) => its.iter().map(|u| u.len()).sum(),
+            Iterable::Walk(_) => 0, // Unknown without reading the file system, so this is only usable as a size hint
+            Iterable::Progress(it, _) => it.len(),
         }
     }
 
@@ -1518,7 +2473,16 @@ impl Iterable {
             },
             Iterable::Collection(_, it) => IterableRev(Iterable::Collection(len, it)),
             Iterable::RawVector(_, it) => IterableRev(Iterable::RawVector(len, it)),
-            Iterable::Enumerate(_, it) => IterableRev(Iterable::Enumerate(len, Box::new(it.reverse().0))),
+            Iterable::Enumerate(_, it) => {
+                // The inner iterable may be collected when reversed, so take the length from the result
+                let it: Iterable = it.reverse().0;
+                IterableRev(Iterable::Enumerate(it.len(), Box::new(it)))
+            },
+            it @ (Iterable::Chain(_) | Iterable::Walk(_) | Iterable::Progress(_, _)) => {
+                // Each chained iterable would need to be reversed individually, walking a directory can only be done forwards, and progress is only reported forwards, so just collect them instead
+                let vec: Vec<ValuePtr> = it.collect();
+                IterableRev(Iterable::RawVector(vec.len(), vec))
+            },
             it => IterableRev(it)
         }
     }
@@ -1545,6 +2509,8 @@ impl Iterable {
             Type::Set => ptr.as_set().borrow().set.get_index(index).cloned(),
             Type::Dict => ptr.as_dict().borrow().dict.get_index(index).map(|(l, r)| (l.clone(), r.clone()).to_value()),
             Type::Vector => ptr.as_vector().borrow().vector.get(index).cloned(),
+            Type::View => ptr.as_view().borrow_const().as_slice().get(index).cloned(),
+            Type::PList => ptr.as_plist().borrow_const().get(index).cloned(),
             _ => unreachable!(),
         }
     }
@@ -1574,6 +2540,55 @@ impl Iterator for Iterable {
                 *index += 1;
                 ret
             },
+            Iterable::Cycle(source, it) => {
+                // Restart from the source on each pass. If a pass is empty, the source is empty, and so the cycle is finished.
+                let ret = it.next().or_else(|| {
+                    **it = source.clone().to_iter().ok()?;
+                    it.next()
+                });
+                if ret.is_none() {
+                    *self = Iterable::Unit(ValueOption::none());
+                }
+                ret
+            },
+            Iterable::Repeat(it, n) => match n {
+                Some(0) => None,
+                Some(n) => {
+                    *n -= 1;
+                    Some(it.clone())
+                },
+                None => Some(it.clone()),
+            },
+            Iterable::Chain(its) => loop {
+                match its.front_mut()?.next() {
+                    Some(ret) => break Some(ret),
+                    None => its.pop_front(),
+                };
+            },
+            Iterable::Walk(stack) => loop {
+                // Depth first, reading each directory as it is reached. Directories which cannot be read are still produced, but not entered.
+                match stack.last_mut()?.pop_front() {
+                    Some((path, is_dir)) => {
+                        if is_dir {
+                            if let Ok(entries) = core::read_dir(&path) {
+                                stack.push(entries);
+                            }
+                        }
+                        break Some(path.to_string_lossy().into_owned().to_value())
+                    },
+                    None => {
+                        stack.pop();
+                    },
+                }
+            },
+            Iterable::Progress(it, bar) => {
+                let ret = it.next();
+                match ret {
+                    Some(_) => bar.step(),
+                    None => bar.finish(),
+                }
+                ret
+            },
         }
     }
 }
@@ -1582,7 +2597,15 @@ impl Iterator for IterableRev {
     type Item = ValuePtr;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match &mut self.0 {
+        IterableRev::next_rev(&mut self.0)
+    }
+}
+
+impl IterableRev {
+    /// Advances an `Iterable` which has been prepared for reverse iteration by `Iterable.reverse()`.
+    /// This is separate from `next()` as `Enumerate` needs to reverse iterate its inner `Iterable`, which is not wrapped in an `IterableRev`.
+    fn next_rev(iter: &mut Iterable) -> Option<ValuePtr> {
+        match iter {
             Iterable::Str(_, chars) => chars.next_back().map(|u| u.to_value()),
             Iterable::Unit(it) => it.take().as_option(),
             Iterable::Collection(index, it) => {
@@ -1601,10 +2624,12 @@ impl Iterator for IterableRev {
             }
             Iterable::Range(it, range) => range.next(it),
             Iterable::Enumerate(index, it) => {
-                let ret = (*it).next().map(|u| (u, index.to_value()).to_value());
-                *index += 1;
-                ret
+                let ret = IterableRev::next_rev(it)?;
+                *index -= 1;
+                Some((index.to_value(), ret).to_value())
             },
+            // Infinite iterables have no end to reverse from, and `Chain`, `Walk` and `Progress` are collected by `reverse()`
+            it @ (Iterable::Cycle(_, _) | Iterable::Repeat(_, _) | Iterable::Chain(_) | Iterable::Walk(_) | Iterable::Progress(_, _)) => it.next(),
         }
     }
 }
@@ -1613,10 +2638,86 @@ impl FusedIterator for Iterable {}
 impl FusedIterator for IterableRev {}
 
 
+/// A function wrapper which caches the results of the function by its arguments.
+///
+/// If `max_size` is present, the cache evicts the least recently used entry when full. Recency is tracked by tagging each entry with the `tick` it was last used,
+/// and keeping a log of `(tick, key)` pairs in `recent`, in order of use. An entry in the log is stale if the tick does not match the cached entry's tick.
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct MemoizedImpl {
     pub func: ValuePtr,
-    pub cache: HashMap<Vec<ValuePtr>, ValuePtr, FxBuildHasher>
+    cache: HashMap<Vec<ValuePtr>, (ValuePtr, u64), FxBuildHasher>,
+    recent: VecDeque<(u64, Vec<ValuePtr>)>,
+    pub max_size: Option<usize>,
+    tick: u64,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl MemoizedImpl {
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Looks up a cached value, updating the hit and miss statistics.
+    pub fn get(&mut self, args: &Vec<ValuePtr>) -> Option<ValuePtr> {
+        let tick: u64 = self.tick;
+        match self.cache.get_mut(args) {
+            Some((value, used)) => {
+                *used = tick;
+                let value: ValuePtr = value.clone();
+                self.hits += 1;
+                self.touch(args);
+                Some(value)
+            },
+            None => {
+                self.misses += 1;
+                None
+            },
+        }
+    }
+
+    /// Inserts a new value into the cache, evicting the least recently used entry if needed, and returns the cached value.
+    ///
+    /// If the value was already inserted (which may happen during a recursive call to the same function), this returns the existing value.
+    pub fn insert(&mut self, args: Vec<ValuePtr>, value: ValuePtr) -> ValuePtr {
+        if let Some((existing, _)) = self.cache.get(&args) {
+            return existing.clone()
+        }
+        if let Some(max_size) = self.max_size {
+            while self.cache.len() >= max_size {
+                match self.recent.pop_front() {
+                    Some((used, key)) => if self.cache.get(&key).is_some_and(|(_, tick)| *tick == used) {
+                        self.cache.remove(&key);
+                    },
+                    None => break,
+                }
+            }
+        }
+        self.cache.insert(args.clone(), (value.clone(), self.tick));
+        self.touch(&args);
+        value
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.recent.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    fn touch(&mut self, args: &[ValuePtr]) {
+        if let Some(max_size) = self.max_size {
+            self.recent.push_back((self.tick, args.to_vec()));
+
+            // Periodically drop stale entries, so the log does not grow unbounded with repeated cache hits
+            if self.recent.len() > 2 * max_size + 16 {
+                let cache = &self.cache;
+                self.recent.retain(|(used, key)| cache.get(key).is_some_and(|(_, tick)| tick == used));
+            }
+        }
+        self.tick += 1;
+    }
 }
 
 impl Hash for MemoizedImpl {
@@ -1630,6 +2731,8 @@ pub enum Indexable<'a> {
     Str(&'a SharedPrefix<String>),
     List(RefMut<'a, ListImpl>),
     Vector(RefMut<'a, VectorImpl>),
+    View(&'a ValuePtr),
+    PList(&'a ValuePtr),
 }
 
 impl<'a> Indexable<'a> {
@@ -1639,6 +2742,8 @@ impl<'a> Indexable<'a> {
             Indexable::Str(it) => it.borrow_const().len(),
             Indexable::List(it) => it.list.len(),
             Indexable::Vector(it) => it.vector.len(),
+            Indexable::View(it) => it.as_view().borrow_const().len(),
+            Indexable::PList(it) => it.as_plist().borrow_const().len(),
         }
     }
 
@@ -1659,13 +2764,16 @@ impl<'a> Indexable<'a> {
             Indexable::Str(it) => it.borrow_const().chars().nth(index).unwrap().to_value(),
             Indexable::List(it) => it.list[index].clone(),
             Indexable::Vector(it) => it.vector[index].clone(),
+            Indexable::View(it) => it.as_view().borrow_const().as_slice()[index].clone(),
+            Indexable::PList(it) => it.as_plist().borrow_const().get(index).unwrap().clone(),
         }
     }
 
-    /// Setting indexes only works for immutable collections - so not strings
+    /// Setting indexes only works for mutable collections - so not strings, views, or persistent lists
     pub fn set_index(&mut self, index: usize, value: ValuePtr) -> AnyResult {
         match self {
             Indexable::Str(it) => TypeErrorArgMustBeIndexable(it.borrow_const().clone().to_value()).err(),
+            Indexable::View(it) | Indexable::PList(it) => TypeErrorArgMustBeIndexable((*it).clone()).err(),
             Indexable::List(it) => {
                 it.list[index] = value;
                 Ok(())
@@ -1683,6 +2791,8 @@ pub enum Sliceable<'a> {
     Str(&'a SharedPrefix<String>, String),
     List(Ref<'a, ListImpl>, VecDeque<ValuePtr>),
     Vector(Ref<'a, VectorImpl>, Vec<ValuePtr>),
+    View(&'a ViewImpl, Vec<ValuePtr>),
+    PList(&'a PListImpl, Vec<ValuePtr>),
 }
 
 impl<'a> Sliceable<'a> {
@@ -1692,6 +2802,8 @@ impl<'a> Sliceable<'a> {
             Sliceable::Str(it, _) => it.borrow_const().len(),
             Sliceable::List(it, _) => it.list.len(),
             Sliceable::Vector(it, _) => it.vector.len(),
+            Sliceable::View(it, _) => it.len(),
+            Sliceable::PList(it, _) => it.len(),
         }
     }
 
@@ -1702,6 +2814,8 @@ impl<'a> Sliceable<'a> {
                 Sliceable::Str(src, dest) => dest.push(src.borrow_const().chars().nth(index).unwrap()),
                 Sliceable::List(src, dest) => dest.push_back(src.list[index].clone()),
                 Sliceable::Vector(src, dest) => dest.push(src.vector[index].clone()),
+                Sliceable::View(src, dest) => dest.push(src.as_slice()[index].clone()),
+                Sliceable::PList(src, dest) => dest.push(src.get(index).unwrap().clone()),
             }
         }
     }
@@ -1743,6 +2857,16 @@ impl Literal {
         };
     }
 
+    /// Reserves capacity for `additional` more elements, as a hint. If the capacity cannot be reserved, this does nothing, and the literal will instead grow as elements are added.
+    pub fn reserve(&mut self, additional: usize) {
+        let _ = match self {
+            Literal::List(it) => it.try_reserve(additional).is_ok(),
+            Literal::Vector(it) => it.try_reserve(additional).is_ok(),
+            Literal::Set(it) => it.try_reserve(additional).is_ok(),
+            Literal::Dict(it) => it.try_reserve(additional).is_ok(),
+        };
+    }
+
     pub fn unroll<I : Iterator<Item=ValuePtr>>(&mut self, iter: I) -> AnyResult {
         match self {
             Literal::Dict(it) => for value in iter {
@@ -1824,18 +2948,18 @@ mod test {
     #[test]
     fn test_value_result() {
         let ok = ValuePtr::nil().ok();
-        let err = RuntimeError::RuntimeExit.err::<ValueResult>();
+        let err = RuntimeError::RuntimeExit(0).err::<ValueResult>();
 
         assert!(ok.is_ok());
         assert!(err.is_err());
 
         assert_eq!(ok.as_result(), Ok(ValuePtr::nil()));
-        assert_eq!(err.as_result(), RuntimeError::RuntimeExit.err())
+        assert_eq!(err.as_result(), RuntimeError::RuntimeExit(0).err())
     }
 
     #[test]
     #[should_panic]
     fn test_value_result_ok_of_err() {
-        let _ = ValueResult::ok(RuntimeError::RuntimeExit.to_value());
+        let _ = ValueResult::ok(RuntimeError::RuntimeExit(0).to_value());
     }
 }
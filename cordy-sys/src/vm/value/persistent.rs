@@ -0,0 +1,404 @@
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use fxhash::FxHasher;
+
+use crate::util::impl_partial_ord;
+use crate::vm::value::ValuePtr;
+
+
+const BITS: u32 = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+
+/// An immutable list, which is created by `plist()`.
+///
+/// This is a persistent vector, stored as a tree with a branching factor of 32, where each node is shared between every version of the list that
+/// contains it. So `conj()` and `assoc()` return a new list by copying only the path from the root to the changed element, in `O(log n)` time,
+/// and the original is unchanged. Lookups by index also take `O(log n)` time, which in practice is no more than a few levels.
+#[derive(Clone)]
+pub struct PListImpl {
+    len: usize,
+    /// The number of bits of the index which are used by all levels below the root
+    shift: u32,
+    root: PListNode,
+}
+
+#[derive(Clone)]
+enum PListNode {
+    Branch(Rc<Vec<PListNode>>),
+    Leaf(Rc<Vec<ValuePtr>>),
+}
+
+impl PListImpl {
+    pub fn new() -> PListImpl {
+        PListImpl { len: 0, shift: 0, root: PListNode::Leaf(Rc::new(Vec::new())) }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    pub fn get(&self, index: usize) -> Option<&ValuePtr> {
+        if index >= self.len {
+            return None
+        }
+        let mut node: &PListNode = &self.root;
+        let mut level: u32 = self.shift;
+        loop {
+            match node {
+                PListNode::Branch(children) => {
+                    node = &children[(index >> level) & MASK];
+                    level -= BITS;
+                },
+                PListNode::Leaf(values) => return Some(&values[index & MASK]),
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&ValuePtr> {
+        (0..self.len).map(|index| self.get(index).unwrap())
+    }
+
+    /// Returns a new list, with `value` appended to the end.
+    pub fn push(&self, value: ValuePtr) -> PListImpl {
+        if self.len == WIDTH << self.shift {
+            // The tree is full, so a new root is needed
+            let root = PListNode::Branch(Rc::new(vec![self.root.clone(), PListNode::path(self.shift, value)]));
+            return PListImpl { len: self.len + 1, shift: self.shift + BITS, root }
+        }
+        PListImpl { len: self.len + 1, shift: self.shift, root: self.root.push(self.shift, self.len, value) }
+    }
+
+    /// Returns a new list, with the value at `index` replaced by `value`. `index` must be in bounds.
+    pub fn set(&self, index: usize, value: ValuePtr) -> PListImpl {
+        debug_assert!(index < self.len);
+        PListImpl { len: self.len, shift: self.shift, root: self.root.set(self.shift, index, value) }
+    }
+}
+
+impl Default for PListImpl {
+    fn default() -> Self { PListImpl::new() }
+}
+
+impl FromIterator<ValuePtr> for PListImpl {
+    fn from_iter<T: IntoIterator<Item=ValuePtr>>(iter: T) -> Self {
+        iter.into_iter().fold(PListImpl::new(), |list, value| list.push(value))
+    }
+}
+
+impl PListNode {
+    /// Creates a new path from a node at `level`, down to a leaf containing only `value`
+    fn path(level: u32, value: ValuePtr) -> PListNode {
+        match level {
+            0 => PListNode::Leaf(Rc::new(vec![value])),
+            _ => PListNode::Branch(Rc::new(vec![PListNode::path(level - BITS, value)])),
+        }
+    }
+
+    fn push(&self, level: u32, index: usize, value: ValuePtr) -> PListNode {
+        match self {
+            PListNode::Leaf(values) => {
+                let mut values: Vec<ValuePtr> = (**values).clone();
+                values.push(value);
+                PListNode::Leaf(Rc::new(values))
+            },
+            PListNode::Branch(children) => {
+                let mut children: Vec<PListNode> = (**children).clone();
+                let sub: usize = (index >> level) & MASK;
+                match children.get(sub) {
+                    Some(child) => children[sub] = child.push(level - BITS, index, value),
+                    None => children.push(PListNode::path(level - BITS, value)),
+                }
+                PListNode::Branch(Rc::new(children))
+            },
+        }
+    }
+
+    fn set(&self, level: u32, index: usize, value: ValuePtr) -> PListNode {
+        match self {
+            PListNode::Leaf(values) => {
+                let mut values: Vec<ValuePtr> = (**values).clone();
+                values[index & MASK] = value;
+                PListNode::Leaf(Rc::new(values))
+            },
+            PListNode::Branch(children) => {
+                let mut children: Vec<PListNode> = (**children).clone();
+                let sub: usize = (index >> level) & MASK;
+                children[sub] = children[sub].set(level - BITS, index, value);
+                PListNode::Branch(Rc::new(children))
+            },
+        }
+    }
+}
+
+impl Eq for PListImpl {}
+impl PartialEq for PListImpl {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl Ord for PListImpl {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl_partial_ord!(PListImpl);
+
+impl Hash for PListImpl {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for v in self.iter() {
+            v.hash(state)
+        }
+    }
+}
+
+impl Debug for PListImpl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+
+/// An immutable dictionary, which is created by `pdict()`.
+///
+/// This is a hash array mapped trie, where each node has up to 32 children, selected by five bits of the hash of a key at each level. As with
+/// `PListImpl`, nodes are shared between versions, so `assoc()` and `dissoc()` return a new dictionary in `O(log n)` time. Unlike `dict`, entries
+/// are not kept in insertion order, but in an order determined by the hash of each key.
+#[derive(Clone)]
+pub struct PDictImpl {
+    len: usize,
+    root: Rc<PDictNode>,
+}
+
+#[derive(Clone, Default)]
+struct PDictNode {
+    /// Which of the 32 possible children are present, where `entries` holds the present children in order
+    bitmap: u32,
+    entries: Vec<PDictEntry>,
+}
+
+#[derive(Clone)]
+enum PDictEntry {
+    Pair(u64, ValuePtr, ValuePtr),
+    Node(Rc<PDictNode>),
+    /// Two or more keys which have exactly the same hash
+    Collision(u64, Rc<Vec<(ValuePtr, ValuePtr)>>),
+}
+
+fn hash_of(key: &ValuePtr) -> u64 {
+    let mut state = FxHasher::default();
+    key.hash(&mut state);
+    state.finish()
+}
+
+impl PDictImpl {
+    pub fn new() -> PDictImpl {
+        PDictImpl { len: 0, root: Rc::new(PDictNode::default()) }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    pub fn get(&self, key: &ValuePtr) -> Option<&ValuePtr> {
+        let hash: u64 = hash_of(key);
+        let mut node: &PDictNode = &self.root;
+        let mut shift: u32 = 0;
+        loop {
+            let bit: u32 = 1 << ((hash >> shift) as usize & MASK);
+            if node.bitmap & bit == 0 {
+                return None
+            }
+            match &node.entries[node.index_of(bit)] {
+                PDictEntry::Pair(_, k, v) => return if k == key { Some(v) } else { None },
+                PDictEntry::Collision(_, pairs) => return pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+                PDictEntry::Node(child) => {
+                    node = child;
+                    shift += BITS;
+                },
+            }
+        }
+    }
+
+    pub fn contains_key(&self, key: &ValuePtr) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns a new dictionary, with `key` set to `value`
+    pub fn insert(&self, key: ValuePtr, value: ValuePtr) -> PDictImpl {
+        let hash: u64 = hash_of(&key);
+        let (root, added) = self.root.insert(0, hash, key, value);
+        PDictImpl { len: self.len + added as usize, root: Rc::new(root) }
+    }
+
+    /// Returns a new dictionary without `key`, or `None` if `key` was not present
+    pub fn remove(&self, key: &ValuePtr) -> Option<PDictImpl> {
+        let hash: u64 = hash_of(key);
+        let root: PDictNode = self.root.remove(0, hash, key)?;
+        Some(PDictImpl { len: self.len - 1, root: Rc::new(root) })
+    }
+
+    /// Returns each key-value pair, in an order determined by the hash of each key.
+    pub fn pairs(&self) -> Vec<(ValuePtr, ValuePtr)> {
+        let mut pairs: Vec<(ValuePtr, ValuePtr)> = Vec::with_capacity(self.len);
+        self.root.collect(&mut pairs);
+        pairs
+    }
+}
+
+impl Default for PDictImpl {
+    fn default() -> Self { PDictImpl::new() }
+}
+
+impl FromIterator<(ValuePtr, ValuePtr)> for PDictImpl {
+    fn from_iter<T: IntoIterator<Item=(ValuePtr, ValuePtr)>>(iter: T) -> Self {
+        iter.into_iter().fold(PDictImpl::new(), |dict, (key, value)| dict.insert(key, value))
+    }
+}
+
+impl PDictNode {
+    /// Returns the index in `entries` of the child with the bit `bit`
+    fn index_of(&self, bit: u32) -> usize {
+        (self.bitmap & (bit - 1)).count_ones() as usize
+    }
+
+    /// Returns a copy of this node with `key` set to `value`, and if `key` was not already present
+    fn insert(&self, shift: u32, hash: u64, key: ValuePtr, value: ValuePtr) -> (PDictNode, bool) {
+        let bit: u32 = 1 << ((hash >> shift) as usize & MASK);
+        let index: usize = self.index_of(bit);
+        let mut node: PDictNode = self.clone();
+        if self.bitmap & bit == 0 {
+            node.bitmap |= bit;
+            node.entries.insert(index, PDictEntry::Pair(hash, key, value));
+            return (node, true)
+        }
+        let (entry, added) = match &self.entries[index] {
+            PDictEntry::Pair(h, k, _) if *k == key => (PDictEntry::Pair(*h, key, value), false),
+            PDictEntry::Pair(h, k, v) if *h == hash => (PDictEntry::Collision(hash, Rc::new(vec![(k.clone(), v.clone()), (key, value)])), true),
+            PDictEntry::Pair(h, k, v) => {
+                // Both keys share this slot, so they are moved into a new node one level below
+                let (child, _) = PDictNode::default().insert(shift + BITS, *h, k.clone(), v.clone());
+                let (child, _) = child.insert(shift + BITS, hash, key, value);
+                (PDictEntry::Node(Rc::new(child)), true)
+            },
+            PDictEntry::Collision(h, pairs) if *h == hash => {
+                let mut pairs: Vec<(ValuePtr, ValuePtr)> = (**pairs).clone();
+                let added: bool = match pairs.iter_mut().find(|(k, _)| *k == key) {
+                    Some(pair) => {
+                        pair.1 = value;
+                        false
+                    },
+                    None => {
+                        pairs.push((key, value));
+                        true
+                    },
+                };
+                (PDictEntry::Collision(hash, Rc::new(pairs)), added)
+            },
+            PDictEntry::Collision(h, pairs) => {
+                let child = PDictNode { bitmap: 1 << ((*h >> (shift + BITS)) as usize & MASK), entries: vec![PDictEntry::Collision(*h, pairs.clone())] };
+                let (child, _) = child.insert(shift + BITS, hash, key, value);
+                (PDictEntry::Node(Rc::new(child)), true)
+            },
+            PDictEntry::Node(child) => {
+                let (child, added) = child.insert(shift + BITS, hash, key, value);
+                (PDictEntry::Node(Rc::new(child)), added)
+            },
+        };
+        node.entries[index] = entry;
+        (node, added)
+    }
+
+    /// Returns a copy of this node without `key`, or `None` if `key` was not present
+    fn remove(&self, shift: u32, hash: u64, key: &ValuePtr) -> Option<PDictNode> {
+        let bit: u32 = 1 << ((hash >> shift) as usize & MASK);
+        if self.bitmap & bit == 0 {
+            return None
+        }
+        let index: usize = self.index_of(bit);
+        let entry: Option<PDictEntry> = match &self.entries[index] {
+            PDictEntry::Pair(_, k, _) if k == key => None,
+            PDictEntry::Pair(..) => return None,
+            PDictEntry::Collision(h, pairs) => {
+                let pos: usize = pairs.iter().position(|(k, _)| k == key)?;
+                let mut pairs: Vec<(ValuePtr, ValuePtr)> = (**pairs).clone();
+                pairs.remove(pos);
+                Some(match pairs.len() {
+                    1 => {
+                        let (k, v) = pairs.pop().unwrap();
+                        PDictEntry::Pair(*h, k, v)
+                    },
+                    _ => PDictEntry::Collision(*h, Rc::new(pairs)),
+                })
+            },
+            PDictEntry::Node(child) => {
+                let child: PDictNode = child.remove(shift + BITS, hash, key)?;
+                match (child.entries.len(), child.entries.first()) {
+                    (0, _) => None,
+                    // A single pair or collision can be lifted into this node, but a single node cannot, as its children depend on the level
+                    (1, Some(PDictEntry::Pair(..) | PDictEntry::Collision(..))) => child.entries.into_iter().next(),
+                    _ => Some(PDictEntry::Node(Rc::new(child))),
+                }
+            },
+        };
+        let mut node: PDictNode = self.clone();
+        match entry {
+            Some(entry) => node.entries[index] = entry,
+            None => {
+                node.bitmap &= !bit;
+                node.entries.remove(index);
+            },
+        }
+        Some(node)
+    }
+
+    fn collect(&self, pairs: &mut Vec<(ValuePtr, ValuePtr)>) {
+        for entry in &self.entries {
+            match entry {
+                PDictEntry::Pair(_, k, v) => pairs.push((k.clone(), v.clone())),
+                PDictEntry::Collision(_, it) => pairs.extend(it.iter().cloned()),
+                PDictEntry::Node(child) => child.collect(pairs),
+            }
+        }
+    }
+}
+
+impl Eq for PDictImpl {}
+impl PartialEq for PDictImpl {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.pairs().iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl Ord for PDictImpl {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            return Ordering::Equal // Keys with the same hash may be in a different order, but are still equal
+        }
+        self.pairs().cmp(&other.pairs())
+    }
+}
+
+impl_partial_ord!(PDictImpl);
+
+impl Hash for PDictImpl {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Combined with a commutative operation, as keys with the same hash may be in any order
+        let mut hash: u64 = 0;
+        for pair in self.pairs() {
+            let mut inner = FxHasher::default();
+            pair.hash(&mut inner);
+            hash = hash.wrapping_add(inner.finish());
+        }
+        state.write_u64(hash);
+    }
+}
+
+impl Debug for PDictImpl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.pairs()).finish()
+    }
+}
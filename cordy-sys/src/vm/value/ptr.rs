@@ -4,7 +4,7 @@ use std::fmt::{Debug, Formatter};
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::ptr::NonNull;
+use std::ptr::{self, NonNull};
 use num_complex::Complex;
 
 use crate::core::NativeFunction;
@@ -115,7 +115,9 @@ impl<T : OwnedValue> From<Prefix<T>> for ValuePtr {
 
 impl<T : SharedValue> From<SharedPrefix<T>> for ValuePtr {
     fn from(value: SharedPrefix<T>) -> Self {
-        ValuePtr { tag: TAG_PTR | (Box::into_raw(Box::new(value)) as usize) }
+        let ptr = ValuePtr { tag: TAG_PTR | (Box::into_raw(Box::new(value)) as usize) };
+        stats::on_alloc(ptr.ty(), ptr.shared_size::<T>());
+        ptr
     }
 }
 
@@ -166,7 +168,7 @@ impl ValuePtr {
 
     pub fn as_native(&self) -> NativeFunction {
         debug_assert!(self.is_native());
-        unsafe { std::mem::transmute((self.tag >> 6) as u8) }
+        unsafe { std::mem::transmute((self.tag >> 6) as u16) }
     }
 
     pub fn as_field(&self) -> u32 {
@@ -221,6 +223,50 @@ impl ValuePtr {
     fn is_owned(&self) -> bool { self.ty().is_owned() }
     fn is_shared(&self) -> bool { self.ty().is_shared() }
 
+    /// Returns `true` if this value has been frozen, see `freeze()`. Values which are not shared, such as `int`s, are never frozen.
+    pub fn is_frozen(&self) -> bool {
+        self.is_shared() && self.as_shared_ref::<()>().has_flag(FLAG_FROZEN)
+    }
+
+    /// Marks this shared value as frozen, returning `true` if it was not already frozen.
+    pub(super) fn set_frozen(&self) -> bool {
+        debug_assert!(self.is_shared());
+        self.as_shared_ref::<()>().set_flag(FLAG_FROZEN)
+    }
+
+    /// Marks this shared value as tracked, meaning it has weak references or finalizers, and so `weak::on_drop()` must be called when it is freed.
+    pub(super) fn set_tracked(&self) {
+        debug_assert!(self.is_shared());
+        self.as_shared_ref::<()>().set_flag(FLAG_TRACKED);
+    }
+
+    /// Returns the address of this shared value, which uniquely identifies it for as long as it is alive.
+    pub(super) fn as_address(&self) -> usize {
+        debug_assert!(self.is_shared());
+        unsafe { self.tag }
+    }
+
+    /// Returns the approximate number of bytes used by this shared value, which is reported by `memstats()`.
+    ///
+    /// This must not change over the lifetime of the value, so for mutable collections, only the size of the collection itself is counted, not its elements.
+    /// Strings are immutable, so their contents are included.
+    fn shared_size<T: SharedValue>(&self) -> usize {
+        size_of::<SharedPrefix<T>>() + match self.is_str() {
+            true => self.as_str().borrow_const().capacity(),
+            false => 0,
+        }
+    }
+
+    /// Creates a new strong reference to the shared value at `address`, which was previously returned by `as_address()`.
+    ///
+    /// **Safety:** The value must still be alive, which is only known if it is tracked, and `weak::on_drop()` has not yet been called for it.
+    pub(super) unsafe fn from_address(address: usize) -> ValuePtr {
+        let copy = ValuePtr { tag: address };
+        let ret = copy.clone();
+        std::mem::forget(copy);
+        ret
+    }
+
     pub fn as_value_ref(&self) -> ValueRef {
         ValueRef::new(unsafe { self.tag })
     }
@@ -315,6 +361,10 @@ impl ValuePtr {
         let shared: &SharedPrefix<()> = self.as_shared_ref::<()>();
         shared.dec_strong();
         if shared.refs.get() == 0 {
+            stats::on_free(self.ty(), self.shared_size::<T>());
+            if shared.has_flag(FLAG_TRACKED) {
+                weak::on_drop(self.as_address());
+            }
             unsafe {
                 drop(Box::from_raw(self.as_ptr() as *mut SharedPrefix<T>));
             }
@@ -338,8 +388,10 @@ impl PartialEq for ValuePtr {
             Type::GetField => unsafe { self.long_tag == other.long_tag },
             // Owned types check equality based on their ref
             Type::Complex => self.as_ref::<ComplexImpl>() == other.as_ref::<ComplexImpl>(),
+            Type::Fraction => self.as_ref::<FractionImpl>() == other.as_ref::<FractionImpl>(),
             Type::Range => self.as_ref::<RangeImpl>() == other.as_ref::<RangeImpl>(),
             Type::Enumerate => self.as_ref::<EnumerateImpl>() == other.as_ref::<EnumerateImpl>(),
+            Type::LazyIter => self.as_ref::<LazyIterImpl>() == other.as_ref::<LazyIterImpl>(),
             Type::PartialFunction => self.as_ref::<PartialFunctionImpl>() == other.as_ref::<PartialFunctionImpl>(),
             Type::PartialNativeFunction => self.as_ref::<PartialNativeFunctionImpl>() == other.as_ref::<PartialNativeFunctionImpl>(),
             Type::Slice => self.as_ref::<SliceImpl>() == other.as_ref::<SliceImpl>(),
@@ -350,7 +402,22 @@ impl PartialEq for ValuePtr {
             Type::Set => self.as_shared_ref::<SetImpl>() == other.as_shared_ref::<SetImpl>(),
             Type::Dict => self.as_shared_ref::<DictImpl>() == other.as_shared_ref::<DictImpl>(),
             Type::Heap => self.as_shared_ref::<HeapImpl>() == other.as_shared_ref::<HeapImpl>(),
+            Type::SortedSet => self.as_shared_ref::<SortedSetImpl>() == other.as_shared_ref::<SortedSetImpl>(),
+            Type::SortedDict => self.as_shared_ref::<SortedDictImpl>() == other.as_shared_ref::<SortedDictImpl>(),
             Type::Vector => self.as_shared_ref::<VectorImpl>() == other.as_shared_ref::<VectorImpl>(),
+            Type::View => self.as_shared_ref::<ViewImpl>() == other.as_shared_ref::<ViewImpl>(),
+            Type::PList => self.as_shared_ref::<PListImpl>() == other.as_shared_ref::<PListImpl>(),
+            Type::PDict => self.as_shared_ref::<PDictImpl>() == other.as_shared_ref::<PDictImpl>(),
+            Type::Matrix => self.as_shared_ref::<MatrixImpl>() == other.as_shared_ref::<MatrixImpl>(),
+            Type::BitSet => self.as_shared_ref::<BitSetImpl>() == other.as_shared_ref::<BitSetImpl>(),
+            Type::Dsu => ptr::eq(self.as_shared_ref::<DsuImpl>(), other.as_shared_ref::<DsuImpl>()),
+            Type::Stopwatch => self.as_shared_ref::<StopwatchImpl>() == other.as_shared_ref::<StopwatchImpl>(),
+            Type::Socket => ptr::eq(self.as_shared_ref::<SocketImpl>(), other.as_shared_ref::<SocketImpl>()),
+            Type::Coprocess => ptr::eq(self.as_shared_ref::<CoprocessImpl>(), other.as_shared_ref::<CoprocessImpl>()),
+            Type::Database => ptr::eq(self.as_shared_ref::<DatabaseImpl>(), other.as_shared_ref::<DatabaseImpl>()),
+            Type::Generator => self.as_shared_ref::<GeneratorImpl>() == other.as_shared_ref::<GeneratorImpl>(),
+            Type::Task => ptr::eq(self.as_shared_ref::<TaskImpl>(), other.as_shared_ref::<TaskImpl>()),
+            Type::Weak => ptr::eq(self.as_shared_ref::<WeakImpl>(), other.as_shared_ref::<WeakImpl>()),
             Type::Struct => self.as_shared_ref::<StructImpl>() == other.as_shared_ref::<StructImpl>(),
             Type::StructType => self.as_shared_ref::<StructTypeImpl>() == other.as_shared_ref::<StructTypeImpl>(),
             Type::Memoized => self.as_shared_ref::<MemoizedImpl>() == other.as_shared_ref::<MemoizedImpl>(),
@@ -370,7 +437,11 @@ impl Ord for ValuePtr {
     fn cmp(&self, other: &Self) -> Ordering {
         let ty: Type = self.ty();
         if ty != other.ty() {
-            return Ordering::Equal
+            // Fractions, and ints or bools, are all rational numbers, and so are comparable with each other
+            return match (ty, other.ty()) {
+                (Type::Bool | Type::Int, Type::Fraction) | (Type::Fraction, Type::Bool | Type::Int) => self.clone().as_fraction().cmp(&other.clone().as_fraction()),
+                _ => Ordering::Equal
+            }
         }
         match ty {
             // Inline types can directly compare the tag value. This works for all except ints
@@ -382,16 +453,34 @@ impl Ord for ValuePtr {
 
             // Owned types check equality based on their ref
             Type::Complex => self.as_ref::<ComplexImpl>().cmp(other.as_ref::<ComplexImpl>()),
+            Type::Fraction => self.as_ref::<FractionImpl>().cmp(other.as_ref::<FractionImpl>()),
             Type::Range => self.as_ref::<RangeImpl>().cmp(other.as_ref::<RangeImpl>()),
             Type::Enumerate => self.as_ref::<EnumerateImpl>().cmp(other.as_ref::<EnumerateImpl>()),
+            Type::LazyIter => self.as_ref::<LazyIterImpl>().cmp(other.as_ref::<LazyIterImpl>()),
             // Shared types check equality based on the shared ref
             Type::Str => self.as_shared_ref::<String>().cmp(other.as_shared_ref::<String>()),
             Type::List => self.as_shared_ref::<ListImpl>().cmp(other.as_shared_ref::<ListImpl>()),
             Type::Set => self.as_shared_ref::<SetImpl>().cmp(other.as_shared_ref::<SetImpl>()),
             Type::Dict => self.as_shared_ref::<DictImpl>().cmp(other.as_shared_ref::<DictImpl>()),
             Type::Heap => self.as_shared_ref::<HeapImpl>().cmp(other.as_shared_ref::<HeapImpl>()),
+            Type::SortedSet => self.as_shared_ref::<SortedSetImpl>().cmp(other.as_shared_ref::<SortedSetImpl>()),
+            Type::SortedDict => self.as_shared_ref::<SortedDictImpl>().cmp(other.as_shared_ref::<SortedDictImpl>()),
             Type::Vector => self.as_shared_ref::<VectorImpl>().cmp(other.as_shared_ref::<VectorImpl>()),
+            Type::View => self.as_shared_ref::<ViewImpl>().cmp(other.as_shared_ref::<ViewImpl>()),
+            Type::PList => self.as_shared_ref::<PListImpl>().cmp(other.as_shared_ref::<PListImpl>()),
+            Type::PDict => self.as_shared_ref::<PDictImpl>().cmp(other.as_shared_ref::<PDictImpl>()),
+            Type::Matrix => self.as_shared_ref::<MatrixImpl>().cmp(other.as_shared_ref::<MatrixImpl>()),
+            Type::BitSet => self.as_shared_ref::<BitSetImpl>().cmp(other.as_shared_ref::<BitSetImpl>()),
+            Type::Dsu => Ordering::Equal,
+            Type::Stopwatch => self.as_shared_ref::<StopwatchImpl>().cmp(other.as_shared_ref::<StopwatchImpl>()),
             Type::Struct => self.as_shared_ref::<StructImpl>().cmp(other.as_shared_ref::<StructImpl>()),
+            // Sockets, coprocesses, databases and weak references have identity, but no ordering
+            Type::Socket => Ordering::Equal,
+            Type::Coprocess => Ordering::Equal,
+            Type::Database => Ordering::Equal,
+            Type::Weak => Ordering::Equal,
+            Type::Generator => self.as_shared_ref::<GeneratorImpl>().cmp(other.as_shared_ref::<GeneratorImpl>()),
+            Type::Task => Ordering::Equal,
             // Function-like types are not checked for ordering
             Type::StructType |
             Type::Memoized |
@@ -425,8 +514,10 @@ impl Clone for ValuePtr {
                 Type::GetField => self.as_copy(),
                 // Owned types
                 Type::Complex => self.clone_owned::<ComplexImpl>(),
+                Type::Fraction => self.clone_owned::<FractionImpl>(),
                 Type::Range => self.clone_owned::<RangeImpl>(),
                 Type::Enumerate => self.clone_owned::<EnumerateImpl>(),
+                Type::LazyIter => self.clone_owned::<LazyIterImpl>(),
                 Type::PartialFunction => self.clone_owned::<PartialFunctionImpl>(),
                 Type::PartialNativeFunction => self.clone_owned::<PartialNativeFunctionImpl>(),
                 Type::Slice => self.clone_owned::<SliceImpl>(),
@@ -438,7 +529,22 @@ impl Clone for ValuePtr {
                 Type::Set => self.clone_shared::<SetImpl>(),
                 Type::Dict => self.clone_shared::<DictImpl>(),
                 Type::Heap => self.clone_shared::<HeapImpl>(),
+                Type::SortedSet => self.clone_shared::<SortedSetImpl>(),
+                Type::SortedDict => self.clone_shared::<SortedDictImpl>(),
                 Type::Vector => self.clone_shared::<VectorImpl>(),
+                Type::View => self.clone_shared::<ViewImpl>(),
+                Type::PList => self.clone_shared::<PListImpl>(),
+                Type::PDict => self.clone_shared::<PDictImpl>(),
+                Type::Matrix => self.clone_shared::<MatrixImpl>(),
+                Type::BitSet => self.clone_shared::<BitSetImpl>(),
+                Type::Dsu => self.clone_shared::<DsuImpl>(),
+                Type::Stopwatch => self.clone_shared::<StopwatchImpl>(),
+                Type::Socket => self.clone_shared::<SocketImpl>(),
+                Type::Coprocess => self.clone_shared::<CoprocessImpl>(),
+                Type::Database => self.clone_shared::<DatabaseImpl>(),
+                Type::Generator => self.clone_shared::<GeneratorImpl>(),
+                Type::Task => self.clone_shared::<TaskImpl>(),
+                Type::Weak => self.clone_shared::<WeakImpl>(),
                 Type::Struct => self.clone_shared::<StructImpl>(),
                 Type::StructType => self.clone_shared::<StructTypeImpl>(),
                 Type::Memoized => self.clone_shared::<MemoizedImpl>(),
@@ -468,8 +574,10 @@ impl Drop for ValuePtr {
                 Type::GetField => {},
                 // Owned types
                 Type::Complex => self.drop_owned::<ComplexImpl>(),
+                Type::Fraction => self.drop_owned::<FractionImpl>(),
                 Type::Range => self.drop_owned::<RangeImpl>(),
                 Type::Enumerate => self.drop_owned::<EnumerateImpl>(),
+                Type::LazyIter => self.drop_owned::<LazyIterImpl>(),
                 Type::PartialFunction => self.drop_owned::<PartialFunctionImpl>(),
                 Type::PartialNativeFunction => self.drop_owned::<PartialNativeFunctionImpl>(),
                 Type::Slice => self.drop_owned::<SliceImpl>(),
@@ -481,7 +589,22 @@ impl Drop for ValuePtr {
                 Type::Set => self.drop_shared::<SetImpl>(),
                 Type::Dict => self.drop_shared::<DictImpl>(),
                 Type::Heap => self.drop_shared::<HeapImpl>(),
+                Type::SortedSet => self.drop_shared::<SortedSetImpl>(),
+                Type::SortedDict => self.drop_shared::<SortedDictImpl>(),
                 Type::Vector => self.drop_shared::<VectorImpl>(),
+                Type::View => self.drop_shared::<ViewImpl>(),
+                Type::PList => self.drop_shared::<PListImpl>(),
+                Type::PDict => self.drop_shared::<PDictImpl>(),
+                Type::Matrix => self.drop_shared::<MatrixImpl>(),
+                Type::BitSet => self.drop_shared::<BitSetImpl>(),
+                Type::Dsu => self.drop_shared::<DsuImpl>(),
+                Type::Stopwatch => self.drop_shared::<StopwatchImpl>(),
+                Type::Socket => self.drop_shared::<SocketImpl>(),
+                Type::Coprocess => self.drop_shared::<CoprocessImpl>(),
+                Type::Database => self.drop_shared::<DatabaseImpl>(),
+                Type::Generator => self.drop_shared::<GeneratorImpl>(),
+                Type::Task => self.drop_shared::<TaskImpl>(),
+                Type::Weak => self.drop_shared::<WeakImpl>(),
                 Type::Struct => self.drop_shared::<StructImpl>(),
                 Type::StructType => self.drop_shared::<StructTypeImpl>(),
                 Type::Memoized => self.drop_shared::<MemoizedImpl>(),
@@ -507,8 +630,10 @@ impl Hash for ValuePtr {
             Type::GetField => unsafe { self.tag }.hash(state),
             // Owned types
             Type::Complex => self.as_ref::<ComplexImpl>().hash(state),
+            Type::Fraction => self.as_ref::<FractionImpl>().hash(state),
             Type::Range => self.as_ref::<RangeImpl>().hash(state),
             Type::Enumerate => self.as_ref::<EnumerateImpl>().hash(state),
+            Type::LazyIter => self.as_ref::<LazyIterImpl>().hash(state),
             Type::PartialFunction => self.as_ref::<PartialFunctionImpl>().hash(state),
             Type::PartialNativeFunction => self.as_ref::<PartialNativeFunctionImpl>().hash(state),
             Type::Slice => self.as_ref::<SliceImpl>().hash(state),
@@ -518,7 +643,22 @@ impl Hash for ValuePtr {
             Type::Set => self.as_shared_ref::<SetImpl>().hash(state),
             Type::Dict => self.as_shared_ref::<DictImpl>().hash(state),
             Type::Heap => self.as_shared_ref::<HeapImpl>().hash(state),
+            Type::SortedSet => self.as_shared_ref::<SortedSetImpl>().hash(state),
+            Type::SortedDict => self.as_shared_ref::<SortedDictImpl>().hash(state),
             Type::Vector => self.as_shared_ref::<VectorImpl>().hash(state),
+            Type::View => self.as_shared_ref::<ViewImpl>().hash(state),
+            Type::PList => self.as_shared_ref::<PListImpl>().hash(state),
+            Type::PDict => self.as_shared_ref::<PDictImpl>().hash(state),
+            Type::Matrix => self.as_shared_ref::<MatrixImpl>().hash(state),
+            Type::BitSet => self.as_shared_ref::<BitSetImpl>().hash(state),
+            Type::Dsu => ptr::hash(self.as_shared_ref::<DsuImpl>(), state),
+            Type::Stopwatch => self.as_shared_ref::<StopwatchImpl>().hash(state),
+            Type::Socket => ptr::hash(self.as_shared_ref::<SocketImpl>(), state),
+            Type::Coprocess => ptr::hash(self.as_shared_ref::<CoprocessImpl>(), state),
+            Type::Database => ptr::hash(self.as_shared_ref::<DatabaseImpl>(), state),
+            Type::Generator => self.as_shared_ref::<GeneratorImpl>().hash(state),
+            Type::Task => ptr::hash(self.as_shared_ref::<TaskImpl>(), state),
+            Type::Weak => ptr::hash(self.as_shared_ref::<WeakImpl>(), state),
             Type::Struct => self.as_shared_ref::<StructImpl>().hash(state),
             Type::StructType => self.as_shared_ref::<StructTypeImpl>().hash(state),
             Type::Memoized => self.as_shared_ref::<MemoizedImpl>().hash(state),
@@ -542,8 +682,10 @@ impl Debug for ValuePtr {
             Type::GetField => f.debug_struct("GetField").field("field_index", &self.as_field()).finish(),
             // Owned types
             Type::Complex => Debug::fmt(self.as_ref::<ComplexImpl>(), f),
+            Type::Fraction => Debug::fmt(self.as_ref::<FractionImpl>(), f),
             Type::Range => Debug::fmt(self.as_ref::<RangeImpl>(), f),
             Type::Enumerate => Debug::fmt(self.as_ref::<EnumerateImpl>(), f),
+            Type::LazyIter => Debug::fmt(self.as_ref::<LazyIterImpl>(), f),
             Type::PartialFunction => Debug::fmt(self.as_ref::<PartialFunctionImpl>(), f),
             Type::PartialNativeFunction => Debug::fmt(self.as_ref::<PartialNativeFunctionImpl>(), f),
             Type::Slice => Debug::fmt(self.as_ref::<SliceImpl>(), f),
@@ -554,7 +696,22 @@ impl Debug for ValuePtr {
             Type::Set => Debug::fmt(self.as_shared_ref::<SetImpl>(), f),
             Type::Dict => Debug::fmt(self.as_shared_ref::<DictImpl>(), f),
             Type::Heap => Debug::fmt(self.as_shared_ref::<HeapImpl>(), f),
+            Type::SortedSet => Debug::fmt(self.as_shared_ref::<SortedSetImpl>(), f),
+            Type::SortedDict => Debug::fmt(self.as_shared_ref::<SortedDictImpl>(), f),
             Type::Vector => Debug::fmt(self.as_shared_ref::<VectorImpl>(), f),
+            Type::View => Debug::fmt(self.as_shared_ref::<ViewImpl>(), f),
+            Type::PList => Debug::fmt(self.as_shared_ref::<PListImpl>(), f),
+            Type::PDict => Debug::fmt(self.as_shared_ref::<PDictImpl>(), f),
+            Type::Matrix => Debug::fmt(self.as_shared_ref::<MatrixImpl>(), f),
+            Type::BitSet => Debug::fmt(self.as_shared_ref::<BitSetImpl>(), f),
+            Type::Dsu => Debug::fmt(self.as_shared_ref::<DsuImpl>(), f),
+            Type::Stopwatch => Debug::fmt(self.as_shared_ref::<StopwatchImpl>(), f),
+            Type::Socket => Debug::fmt(self.as_shared_ref::<SocketImpl>(), f),
+            Type::Coprocess => Debug::fmt(self.as_shared_ref::<CoprocessImpl>(), f),
+            Type::Database => Debug::fmt(self.as_shared_ref::<DatabaseImpl>(), f),
+            Type::Generator => Debug::fmt(self.as_shared_ref::<GeneratorImpl>(), f),
+            Type::Task => Debug::fmt(self.as_shared_ref::<TaskImpl>(), f),
+            Type::Weak => Debug::fmt(self.as_shared_ref::<WeakImpl>(), f),
             Type::Struct => Debug::fmt(self.as_shared_ref::<StructImpl>(), f),
             Type::StructType => Debug::fmt(self.as_shared_ref::<StructTypeImpl>(), f),
             Type::Memoized => Debug::fmt(self.as_shared_ref::<MemoizedImpl>(), f),
@@ -631,19 +788,21 @@ impl<T : Debug + OwnedValue> Debug for Prefix<T> {
 /// 1. We fundamentally cannot use `Rc<RefCell<T>>` with `ValuePtr`, so the constructs have to be re-invented anyway.
 /// 2. Unlike `Rc<RefCell<T>>`, we optimize for memory overhead here: `Rc<RefCell<T>>` usually has 24 bytes of overhead: `usize` strong reference count,
 /// `usize` weak reference count, and `isize` borrow flag. We can improve on this, and pack with the `ty` field to only use 8 bytes total:
-///     - We skimp on the number of possible references, using a `Cell<u32>` instead of `Cell<usize>`, and don't need weak references (it is simply never used in Cordy).
+///     - We skimp on the number of possible references, using a `Cell<u32>` instead of `Cell<usize>`, and don't keep a weak reference count. Weak references created by `weak()` are rare, so are instead tracked in a side table (see `weak`), with a single flag in the header.
 ///     - We use a `Cell<u16>` instead of `Cell<usize>` for the borrow flag, in order to fit it into the remaining space.
 ///
 /// Both of the above mean we can fit all the header information in 8 bytes, conveniently aligned for other pointer-sized data.
 ///
 /// **Issues**
 ///
-/// - We don't use a weak reference in Cordy to break cycles, mostly because there's no mechanism in which is makes sense to use. So instead, we use
+/// - We don't use weak references internally in Cordy to break cycles, mostly because there's no mechanism in which is makes sense to use. So instead, we use
 /// a strategy called "not worrying about it" to deal with the potential to leak memory through cycles.
 /// - Overflow... may happen on the number of borrows, or the number of references, but both are so ridiculously infeasible scenarios that we employ the same strategy.
 #[repr(C)]
 pub struct SharedPrefix<T : SharedValue> {
     ty: Type,
+    /// A combination of `FLAG_FROZEN` and `FLAG_TRACKED`. This fits in the padding after `ty`, so costs no extra space.
+    flags: Cell<u8>,
     /// A `0` indicates a mutable borrow is currently taken, a `1` indicates no borrow, and `>1` indicates the number of current immutable borrows + 1
     lock: Cell<u16>,
     /// The number of (strong) references to this value. When it reaches zero, the memory is freed.
@@ -658,6 +817,7 @@ impl<T : SharedValue> SharedPrefix<T> {
     pub fn new(ty: Type, value: T) -> SharedPrefix<T> {
         SharedPrefix {
             ty,
+            flags: Cell::new(0),
             lock: Cell::new(BORROW_NONE),
             refs: Cell::new(1),
             value: UnsafeCell::new(value)
@@ -681,6 +841,17 @@ impl<T : SharedValue> SharedPrefix<T> {
         }
     }
 
+    fn has_flag(&self, flag: u8) -> bool {
+        self.flags.get() & flag != 0
+    }
+
+    /// Sets `flag`, returning `true` if it was not already set.
+    fn set_flag(&self, flag: u8) -> bool {
+        let flags: u8 = self.flags.get();
+        self.flags.set(flags | flag);
+        flags & flag == 0
+    }
+
     /// Copied from the implementation of `Rc`. Minus the core intrinsics, as they aren't stable.
     fn inc_strong(&self) {
         let strong: u32 = self.refs.get() + 1;
@@ -750,6 +921,11 @@ impl<T : Debug + SharedValue> Debug for SharedPrefix<T> {
     }
 }
 
+/// This value may not be mutated, as it has been used as a key in a `dict` or `set`.
+const FLAG_FROZEN: u8 = 0b01;
+/// This value has weak references, or finalizers, which need to be notified when it is freed.
+const FLAG_TRACKED: u8 = 0b10;
+
 const BORROW_MUT: u16 = 0;
 const BORROW_NONE: u16 = 1;
 
@@ -845,7 +1021,7 @@ mod tests {
     fn test_sizeof() {
         assert_eq!(std::mem::size_of::<ValuePtr>(), 8);
         assert_eq!(std::mem::size_of::<Prefix<()>>(), 1); // 1 byte, but usually followed by 7 bytes padding for most types, so 8 bytes in practice
-        assert_eq!(std::mem::size_of::<SharedPrefix<()>>(), 8); // 1 (Type) + 1 (bool) lock + 2 padding + 4 (u32 ref count)
+        assert_eq!(std::mem::size_of::<SharedPrefix<()>>(), 8); // 1 (Type) + 1 (u8 flags) + 2 (u16 lock) + 4 (u32 ref count)
     }
 
     #[test]
@@ -973,7 +1149,7 @@ mod tests {
     #[test]
     fn test_inline_native_function() {
         for f in 0..NativeFunction::total() {
-            let f: NativeFunction = unsafe { std::mem::transmute(f as u8) };
+            let f: NativeFunction = unsafe { std::mem::transmute(f as u16) };
             let ptr = f.to_value();
             assert!(!ptr.is_nil());
             assert!(!ptr.is_bool());
@@ -0,0 +1,154 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+use crate::util::impl_partial_ord;
+use crate::vm::value::{Type, ValuePtr};
+
+
+/// A set which keeps its elements in sorted order, which is created by `sorted_set()`.
+///
+/// Unlike `set`, which is backed by a hash set, and iterates in insertion order, this is backed by a B-tree. So it supports ordered queries,
+/// such as the elements within a range, or the nearest element to a given value, in `O(log n)` time. Elements are frozen on insertion, as with `set`.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SortedSetImpl {
+    set: BTreeSet<SortKey>
+}
+
+/// A dictionary which keeps its keys in sorted order, which is created by `sorted_dict()`. See `SortedSetImpl`.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SortedDictImpl {
+    dict: BTreeMap<SortKey, ValuePtr>
+}
+
+/// A value used as a key in a sorted collection.
+///
+/// `ValuePtr` considers values of different types to be equal, which would cause a sorted collection to treat i.e. `1` and `'1'` as the same key.
+/// So values of different types are instead ordered by their type, except for `int` and `fraction`, which are ordered by value.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct SortKey(ValuePtr);
+
+impl SortKey {
+    fn rank(&self) -> u8 {
+        match self.0.ty() {
+            Type::Fraction => Type::Int as u8,
+            ty => ty as u8,
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank()).then_with(|| self.0.cmp(&other.0))
+    }
+}
+
+impl_partial_ord!(SortKey);
+
+
+impl SortedSetImpl {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item=&ValuePtr> {
+        self.set.iter().map(|k| &k.0)
+    }
+
+    pub fn len(&self) -> usize { self.set.len() }
+    pub fn is_empty(&self) -> bool { self.set.is_empty() }
+    pub fn clear(&mut self) { self.set.clear() }
+
+    pub fn contains(&self, value: &ValuePtr) -> bool {
+        self.set.contains(&SortKey(value.clone()))
+    }
+
+    pub fn insert(&mut self, value: ValuePtr) -> bool {
+        self.set.insert(SortKey(value))
+    }
+
+    pub fn remove(&mut self, value: &ValuePtr) -> bool {
+        self.set.remove(&SortKey(value.clone()))
+    }
+
+    pub fn first(&self) -> Option<ValuePtr> { self.set.first().map(|k| k.0.clone()) }
+    pub fn last(&self) -> Option<ValuePtr> { self.set.last().map(|k| k.0.clone()) }
+    pub fn pop_last(&mut self) -> Option<ValuePtr> { self.set.pop_last().map(|k| k.0) }
+
+    /// Returns the elements in the range `[lo, hi)`, in order.
+    pub fn between(&self, lo: &ValuePtr, hi: &ValuePtr) -> Vec<ValuePtr> {
+        let (lo, hi) = (SortKey(lo.clone()), SortKey(hi.clone()));
+        match lo < hi {
+            true => self.set.range((Bound::Included(lo), Bound::Excluded(hi))).map(|k| k.0.clone()).collect(),
+            false => Vec::new(), // `BTreeSet::range()` panics if the range is decreasing
+        }
+    }
+
+    /// Returns the largest element which is less than or equal to `value`
+    pub fn floor(&self, value: &ValuePtr) -> Option<ValuePtr> {
+        self.set.range(..=SortKey(value.clone())).next_back().map(|k| k.0.clone())
+    }
+
+    /// Returns the smallest element which is greater than or equal to `value`
+    pub fn ceiling(&self, value: &ValuePtr) -> Option<ValuePtr> {
+        self.set.range(SortKey(value.clone())..).next().map(|k| k.0.clone())
+    }
+}
+
+impl FromIterator<ValuePtr> for SortedSetImpl {
+    fn from_iter<T: IntoIterator<Item=ValuePtr>>(iter: T) -> Self {
+        SortedSetImpl { set: iter.into_iter().map(SortKey).collect() }
+    }
+}
+
+
+impl SortedDictImpl {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item=(&ValuePtr, &ValuePtr)> {
+        self.dict.iter().map(|(k, v)| (&k.0, v))
+    }
+
+    pub fn len(&self) -> usize { self.dict.len() }
+    pub fn is_empty(&self) -> bool { self.dict.is_empty() }
+    pub fn clear(&mut self) { self.dict.clear() }
+
+    pub fn contains_key(&self, key: &ValuePtr) -> bool {
+        self.dict.contains_key(&SortKey(key.clone()))
+    }
+
+    pub fn get(&self, key: &ValuePtr) -> Option<&ValuePtr> {
+        self.dict.get(&SortKey(key.clone()))
+    }
+
+    pub fn insert(&mut self, key: ValuePtr, value: ValuePtr) {
+        self.dict.insert(SortKey(key), value);
+    }
+
+    pub fn remove(&mut self, key: &ValuePtr) -> bool {
+        self.dict.remove(&SortKey(key.clone())).is_some()
+    }
+
+    pub fn first(&self) -> Option<(ValuePtr, ValuePtr)> { self.dict.first_key_value().map(|(k, v)| (k.0.clone(), v.clone())) }
+    pub fn last(&self) -> Option<(ValuePtr, ValuePtr)> { self.dict.last_key_value().map(|(k, v)| (k.0.clone(), v.clone())) }
+    pub fn pop_last(&mut self) -> Option<(ValuePtr, ValuePtr)> { self.dict.pop_last().map(|(k, v)| (k.0, v)) }
+
+    /// Returns the key-value pairs whose keys are in the range `[lo, hi)`, in order.
+    pub fn between(&self, lo: &ValuePtr, hi: &ValuePtr) -> Vec<(ValuePtr, ValuePtr)> {
+        let (lo, hi) = (SortKey(lo.clone()), SortKey(hi.clone()));
+        match lo < hi {
+            true => self.dict.range((Bound::Included(lo), Bound::Excluded(hi))).map(|(k, v)| (k.0.clone(), v.clone())).collect(),
+            false => Vec::new(),
+        }
+    }
+
+    /// Returns the key-value pair with the largest key which is less than or equal to `key`
+    pub fn floor(&self, key: &ValuePtr) -> Option<(ValuePtr, ValuePtr)> {
+        self.dict.range(..=SortKey(key.clone())).next_back().map(|(k, v)| (k.0.clone(), v.clone()))
+    }
+
+    /// Returns the key-value pair with the smallest key which is greater than or equal to `key`
+    pub fn ceiling(&self, key: &ValuePtr) -> Option<(ValuePtr, ValuePtr)> {
+        self.dict.range(SortKey(key.clone())..).next().map(|(k, v)| (k.0.clone(), v.clone()))
+    }
+}
+
+impl FromIterator<(ValuePtr, ValuePtr)> for SortedDictImpl {
+    fn from_iter<T: IntoIterator<Item=(ValuePtr, ValuePtr)>>(iter: T) -> Self {
+        SortedDictImpl { dict: iter.into_iter().map(|(k, v)| (SortKey(k), v)).collect() }
+    }
+}
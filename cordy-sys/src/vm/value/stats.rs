@@ -0,0 +1,77 @@
+use std::cell::Cell;
+
+use crate::vm::value::Type;
+
+
+const TYPES: usize = Type::Never as usize + 1;
+
+// The number of live shared values, and the approximate bytes used by them, of each type, indexed by `Type`.
+// These are updated whenever a shared value is allocated or freed, and read by `memstats()`.
+thread_local! {
+    static COUNT: [Cell<u64>; TYPES] = const { [const { Cell::new(0) }; TYPES] };
+    static BYTES: [Cell<u64>; TYPES] = const { [const { Cell::new(0) }; TYPES] };
+}
+
+
+/// Each shared type, and the name it is reported as by `memstats()`, which matches the name used by `typeof`
+const SHARED: [(Type, &str); 26] = [
+    (Type::Str, "str"),
+    (Type::List, "list"),
+    (Type::Set, "set"),
+    (Type::Dict, "dict"),
+    (Type::Heap, "heap"),
+    (Type::SortedSet, "sorted_set"),
+    (Type::SortedDict, "sorted_dict"),
+    (Type::Vector, "vector"),
+    (Type::View, "view"),
+    (Type::PList, "plist"),
+    (Type::PDict, "pdict"),
+    (Type::Matrix, "matrix"),
+    (Type::BitSet, "bitset"),
+    (Type::Dsu, "dsu"),
+    (Type::Stopwatch, "stopwatch"),
+    (Type::Socket, "socket"),
+    (Type::Coprocess, "coprocess"),
+    (Type::Database, "database"),
+    (Type::Generator, "generator"),
+    (Type::Task, "task"),
+    (Type::Weak, "weak"),
+    (Type::Struct, "struct"),
+    (Type::StructType, "struct type"),
+    (Type::Memoized, "memoized"),
+    (Type::Function, "function"),
+    (Type::Closure, "closure"),
+];
+
+
+/// The number of live values of a single type, and the approximate number of bytes used by them.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MemStat {
+    pub name: &'static str,
+    pub count: u64,
+    pub bytes: u64,
+}
+
+/// Returns the statistics of each shared type which has at least one live value, in a fixed order.
+///
+/// Owned values, such as `complex`, and inline values, such as `int`, are not counted, as they are either small, or always owned by another value.
+pub fn live_stats() -> Vec<MemStat> {
+    COUNT.with(|count| BYTES.with(|bytes| SHARED.iter()
+        .map(|(ty, name)| MemStat { name, count: count[*ty as usize].get(), bytes: bytes[*ty as usize].get() })
+        .filter(|stat| stat.count > 0)
+        .collect()))
+}
+
+/// Called when a shared value of type `ty`, using `bytes`, is allocated.
+#[inline]
+pub(super) fn on_alloc(ty: Type, bytes: usize) {
+    let _ = COUNT.try_with(|count| count[ty as usize].set(count[ty as usize].get() + 1));
+    let _ = BYTES.try_with(|total| total[ty as usize].set(total[ty as usize].get() + bytes as u64));
+}
+
+/// Called when a shared value of type `ty`, using `bytes`, is freed. Note this may be called while thread locals are being destroyed, so we use `try_with()`
+#[inline]
+pub(super) fn on_free(ty: Type, bytes: usize) {
+    let _ = COUNT.try_with(|count| count[ty as usize].set(count[ty as usize].get() - 1));
+    let _ = BYTES.try_with(|total| total[ty as usize].set(total[ty as usize].get() - bytes as u64));
+}
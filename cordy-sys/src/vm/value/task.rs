@@ -0,0 +1,78 @@
+use std::cell::Cell;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::vm::CallFrame;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::{Literal, UpValue, ValuePtr, ValueResult};
+
+
+/// A task, which is created by calling an `async fn`, or by an asynchronous native function such as `sleep_async()`, and produces a value once it completes.
+///
+/// Tasks are run by the VM's event loop, which only runs when the program `await`s a task which has not completed yet. Like sockets, tasks are only equal to themselves, and are not ordered.
+pub struct TaskImpl {
+    /// A description of what this task is running, used for its `repr()`
+    pub name: String,
+    pub state: TaskState,
+    /// Tasks which are suspended awaiting this one, which are resumed once it completes
+    pub waiters: Vec<ValuePtr>,
+}
+
+pub enum TaskState {
+    /// A call to an `async fn`, with the function and its arguments, which has not started running yet
+    Created(ValuePtr, Vec<ValuePtr>),
+    /// An `async fn` which is currently running
+    Running,
+    /// An `async fn` which is suspended at an `await`
+    Suspended(Box<SuspendedTask>),
+    /// Waiting until the given instant, for `sleep_async()`
+    Sleeping(Instant),
+    /// Waiting on an asynchronous operation, such as a network request, which is polled by the event loop
+    Polling(Box<dyn Poll>),
+    Done(ValuePtr),
+    Failed(RuntimeError),
+}
+
+/// An asynchronous operation, such as a network request, which is polled by the event loop until it completes.
+pub trait Poll {
+    /// Makes progress on the operation, without blocking, and returns the result once it has completed.
+    fn poll(&mut self) -> Option<ValueResult>;
+}
+
+/// The state of an `async fn` which is suspended at an `await`. When a task is suspended, everything it owns on the VM is moved here, and is moved back when it is resumed.
+///
+/// Frame pointers and upvalues are stored relative to the bottom of the task's stack, as it may be resumed at a different depth. While suspended, any upvalues
+/// which are still open are closed, so that closures referencing them still see (and can modify) their values. They are re-opened when the task is resumed.
+pub struct SuspendedTask {
+    pub ip: usize,
+    pub stack: Vec<ValuePtr>,
+    pub frames: Vec<CallFrame>,
+    pub upvalues: Vec<(usize, Rc<Cell<UpValue>>)>,
+    pub literals: Vec<Literal>,
+    pub unrolls: Vec<i32>,
+    pub contexts: Vec<ValuePtr>,
+    /// The task being awaited
+    pub awaiting: ValuePtr,
+}
+
+impl TaskImpl {
+    pub fn new(name: String, state: TaskState) -> TaskImpl {
+        TaskImpl { name, state, waiters: Vec::new() }
+    }
+
+    /// Returns the result of this task, if it has completed. A task which failed raises its original error again.
+    pub fn result(&self) -> Option<ValueResult> {
+        match &self.state {
+            TaskState::Done(value) => Some(value.clone().ok()),
+            TaskState::Failed(error) => Some(error.clone().err()),
+            _ => None,
+        }
+    }
+}
+
+impl Debug for TaskImpl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "task({})", self.name)
+    }
+}
@@ -0,0 +1,73 @@
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::util::impl_partial_ord;
+use crate::vm::value::ValuePtr;
+
+
+/// An immutable view of a contiguous range of elements, which is created by `view()`.
+///
+/// Views share their backing storage, so slicing a view with a step of `1` takes constant time, where slicing a `list` copies each element of the slice.
+/// This makes recursive algorithms, which take the tail of a sequence on each call, linear rather than quadratic. The backing storage is a copy of the
+/// elements at the time the view was created, and is never mutated, so a view is unaffected by later changes to the original `list` or `vector`.
+///
+/// Views compare, order, and hash by their elements, like a `vector`.
+pub struct ViewImpl {
+    items: Rc<[ValuePtr]>,
+    start: usize,
+    end: usize,
+}
+
+impl ViewImpl {
+    pub fn new(items: Vec<ValuePtr>) -> ViewImpl {
+        let end: usize = items.len();
+        ViewImpl { items: items.into(), start: 0, end }
+    }
+
+    /// Returns a view of the elements `[start, end)` of this view, which shares the same backing storage.
+    pub fn sub_view(&self, start: usize, end: usize) -> ViewImpl {
+        debug_assert!(start <= end && end <= self.len());
+        ViewImpl { items: self.items.clone(), start: self.start + start, end: self.start + end }
+    }
+
+    pub fn as_slice(&self) -> &[ValuePtr] {
+        &self.items[self.start..self.end]
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl Eq for ViewImpl {}
+impl PartialEq for ViewImpl {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Ord for ViewImpl {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl_partial_ord!(ViewImpl);
+
+impl Hash for ViewImpl {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl Debug for ViewImpl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
@@ -0,0 +1,111 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use fxhash::FxBuildHasher;
+
+use crate::vm::value::ValuePtr;
+
+
+/// Shared values don't keep a weak reference count, as almost all values are never weakly referenced. Instead, values which have a weak reference or finalizer
+/// are marked as _tracked_ in their header, and their weak references and finalizers are stored here, by the address of the value.
+///
+/// When a tracked value is freed, `on_drop()` is called, which marks all weak references to it as dead, and queues its finalizers to be invoked by the VM which registered them.
+#[derive(Default)]
+struct Tracked {
+    /// Shared by every `WeakImpl` to this value, set to `false` when this value is freed.
+    alive: Option<Rc<Cell<bool>>>,
+    /// Each finalizer, along with the queue of the VM which registered it. If that VM has since been dropped, the finalizer is discarded.
+    finalizers: Vec<(Weak<Finalizers>, ValuePtr)>,
+}
+
+thread_local! {
+    static TRACKED: RefCell<HashMap<usize, Tracked, FxBuildHasher>> = RefCell::new(HashMap::with_hasher(FxBuildHasher::default()));
+}
+
+
+/// The finalizers of values which have been freed, which are waiting to be invoked by a single VM.
+#[derive(Default)]
+pub struct Finalizers {
+    pending: RefCell<Vec<ValuePtr>>,
+    flag: Cell<bool>,
+}
+
+impl Finalizers {
+    /// Returns `true` if there are any finalizers which need to be invoked, via `take()`
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        self.flag.get()
+    }
+
+    /// Returns all finalizers of values which have been freed since the last call, in the order they were freed.
+    pub fn take(&self) -> Vec<ValuePtr> {
+        self.flag.set(false);
+        self.pending.take()
+    }
+}
+
+
+/// A weak reference to a shared value, created by `weak()`, which does not keep the value alive.
+///
+/// Like sockets, weak references are only equal to themselves, and are not ordered.
+#[derive(Debug)]
+pub struct WeakImpl {
+    address: usize,
+    alive: Rc<Cell<bool>>,
+}
+
+impl WeakImpl {
+    /// Creates a new weak reference to `value`, which must be a shared value.
+    pub fn new(value: &ValuePtr) -> WeakImpl {
+        value.set_tracked();
+        let address: usize = value.as_address();
+        let alive: Rc<Cell<bool>> = TRACKED.with(|tracked| tracked.borrow_mut()
+            .entry(address)
+            .or_default()
+            .alive
+            .get_or_insert_with(|| Rc::new(Cell::new(true)))
+            .clone());
+        WeakImpl { address, alive }
+    }
+
+    /// Returns `true` if the value has not been freed yet.
+    pub fn is_alive(&self) -> bool {
+        self.alive.get()
+    }
+
+    /// Returns a new strong reference to the value, or `None` if it has been freed.
+    pub fn get(&self) -> Option<ValuePtr> {
+        match self.alive.get() {
+            true => Some(unsafe { ValuePtr::from_address(self.address) }),
+            false => None,
+        }
+    }
+}
+
+/// Registers `f` to be invoked, with no arguments, once `value` is freed. `value` must be a shared value. Once it is freed, `f` is queued in `owner`.
+pub fn add_finalizer(value: &ValuePtr, f: ValuePtr, owner: &Rc<Finalizers>) {
+    value.set_tracked();
+    TRACKED.with(|tracked| tracked.borrow_mut()
+        .entry(value.as_address())
+        .or_default()
+        .finalizers
+        .push((Rc::downgrade(owner), f)));
+}
+
+/// Called when a tracked value, at `address`, is about to be freed.
+///
+/// Note this may be called while thread locals are being destroyed, i.e. if a finalizer holds the last reference to another tracked value, so we use `try_with()` and ignore any errors.
+pub(super) fn on_drop(address: usize) {
+    let entry: Option<Tracked> = TRACKED.try_with(|tracked| tracked.borrow_mut().remove(&address)).ok().flatten();
+    if let Some(entry) = entry {
+        if let Some(alive) = entry.alive {
+            alive.set(false);
+        }
+        for (owner, f) in entry.finalizers {
+            if let Some(owner) = owner.upgrade() {
+                owner.pending.borrow_mut().push(f);
+                owner.flag.set(true);
+            }
+        }
+    }
+}
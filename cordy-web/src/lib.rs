@@ -195,7 +195,7 @@ impl Write for SharedBufWriter {
 
 
 struct Manager {
-    repl: Repl<SharedBufWriter>,
+    repl: Repl<io::Empty, SharedBufWriter, SharedBufWriter>,
     writer: SharedBufWriter
 }
 
@@ -203,7 +203,7 @@ impl Manager {
     fn new() -> Manager {
         let writer: SharedBufWriter = SharedBufWriter(Rc::new(RefCell::new(Vec::new())));
         Manager {
-            repl: Repl::new(writer.clone(), false),
+            repl: Repl::new(io::empty(), writer.clone(), writer.clone(), false),
             writer,
         }
     }
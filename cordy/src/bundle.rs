@@ -0,0 +1,94 @@
+use std::{env, fs};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+
+/// Marks the end of an executable which has a script appended to it
+const MAGIC: &[u8; 8] = b"CORDYBDL";
+
+/// The trailer is written after the script, and is made up of the length of the name, the length of the source, a flag for if optimizations are enabled, and then `MAGIC`
+const TRAILER: usize = 8 + 8 + 1 + MAGIC.len();
+
+
+/// A script which has been bundled into a copy of the interpreter, which is run in place of the usual command line interface.
+pub struct Bundle {
+    pub name: String,
+    pub text: String,
+    pub optimize: bool,
+}
+
+/// Writes a copy of the currently running executable to `output`, with `bundle` appended to it.
+pub fn write(bundle: &Bundle, output: &String) -> Result<(), String> {
+    let exe = env::current_exe().map_err(|e| format!("Unable to locate the cordy executable: {}", e))?;
+    let mut bytes: Vec<u8> = fs::read(&exe).map_err(|_| format!("Unable to read file '{}'", exe.display()))?;
+
+    // If this executable is itself a bundle, the existing script is replaced
+    if let Some(start) = script_start(&bytes) {
+        bytes.truncate(start);
+    }
+
+    bytes.extend_from_slice(bundle.name.as_bytes());
+    bytes.extend_from_slice(bundle.text.as_bytes());
+    bytes.extend_from_slice(&(bundle.name.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&(bundle.text.len() as u64).to_le_bytes());
+    bytes.push(bundle.optimize as u8);
+    bytes.extend_from_slice(MAGIC);
+
+    fs::write(output, bytes).map_err(|_| format!("Unable to write file '{}'", output))?;
+    set_executable(output)
+}
+
+/// Returns the script bundled into the currently running executable, if there is one.
+pub fn read() -> Option<Bundle> {
+    let mut file = File::open(env::current_exe().ok()?).ok()?;
+    let len: u64 = file.metadata().ok()?.len();
+    if len < TRAILER as u64 {
+        return None
+    }
+
+    // Only the end of the executable is read, so this is cheap for executables which are not bundles
+    let mut trailer: [u8; TRAILER] = [0; TRAILER];
+    file.seek(SeekFrom::End(-(TRAILER as i64))).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    let (name_len, text_len, optimize) = parse_trailer(&trailer)?;
+
+    let start: u64 = (len - TRAILER as u64).checked_sub(name_len + text_len)?;
+    let mut script: Vec<u8> = vec![0; (name_len + text_len) as usize];
+    file.seek(SeekFrom::Start(start)).ok()?;
+    file.read_exact(&mut script).ok()?;
+
+    let text: Vec<u8> = script.split_off(name_len as usize);
+    Some(Bundle {
+        name: String::from_utf8(script).ok()?,
+        text: String::from_utf8(text).ok()?,
+        optimize,
+    })
+}
+
+/// If `bytes` is a bundle, returns the index at which the script starts
+fn script_start(bytes: &[u8]) -> Option<usize> {
+    let end: usize = bytes.len().checked_sub(TRAILER)?;
+    let (name_len, text_len, _) = parse_trailer(&bytes[end..])?;
+    end.checked_sub((name_len + text_len) as usize)
+}
+
+/// Returns the length of the name, length of the text, and the optimize flag, if `trailer` is a valid trailer
+fn parse_trailer(trailer: &[u8]) -> Option<(u64, u64, bool)> {
+    if &trailer[17..] != MAGIC {
+        return None
+    }
+    let name_len: u64 = u64::from_le_bytes(trailer[0..8].try_into().ok()?);
+    let text_len: u64 = u64::from_le_bytes(trailer[8..16].try_into().ok()?);
+    Some((name_len, text_len, trailer[16] != 0))
+}
+
+#[cfg(unix)]
+fn set_executable(output: &String) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(output, fs::Permissions::from_mode(0o755)).map_err(|_| format!("Unable to make '{}' executable", output))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_: &String) -> Result<(), String> {
+    Ok(())
+}
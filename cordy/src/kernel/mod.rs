@@ -0,0 +1,434 @@
+use std::{fs, io, thread};
+use std::cell::RefCell;
+use std::io::Write;
+use std::net::TcpListener;
+use std::rc::Rc;
+use std::sync::{Arc, mpsc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use cordy_sys::{NativeFunction, SYS_VERSION};
+use cordy_sys::session::{Session, SessionError};
+
+use crate::kernel::zmtp::{Connection, Message, SocketType};
+
+mod zmtp;
+
+
+/// The version of the Jupyter messaging protocol which is implemented
+const PROTOCOL_VERSION: &str = "5.3";
+
+/// Separates the routing identities of a message from the message itself
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+
+/// Runs a Jupyter kernel, using the ports and key from the connection file at `path`, until the client requests it to shut down.
+///
+/// Each cell is executed in a single `Session`, so it can use anything declared by previously executed cells. Any output is sent to the client once the cell has finished executing.
+pub fn run(path: String) -> Result<(), String> {
+    let text: String = fs::read_to_string(&path).map_err(|_| format!("Unable to read file '{}'", path))?;
+    let config: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid connection file '{}': {}", path, e))?;
+
+    let address = |port: &str| -> Result<String, String> {
+        match (config["transport"].as_str(), config["ip"].as_str(), config[port].as_u64()) {
+            (Some("tcp"), Some(ip), Some(port)) => Ok(format!("{}:{}", ip, port)),
+            _ => Err(format!("Invalid connection file '{}': expected a tcp transport, ip, and '{}'", path, port)),
+        }
+    };
+    if !matches!(config["signature_scheme"].as_str(), None | Some("hmac-sha256")) {
+        return Err(format!("Invalid connection file '{}': only the 'hmac-sha256' signature scheme is supported", path))
+    }
+
+    let key: Vec<u8> = config["key"].as_str().unwrap_or("").as_bytes().to_vec();
+    let (sender, receiver) = mpsc::channel::<Request>();
+
+    listen(&address("shell_port")?, sender.clone())?;
+    listen(&address("control_port")?, sender)?;
+    listen_stdin(&address("stdin_port")?)?;
+    listen_heartbeat(&address("hb_port")?)?;
+    let iopub: Publisher = Publisher::bind(&address("iopub_port")?)?;
+
+    let output: SharedBuffer = SharedBuffer::default();
+    let mut kernel = Kernel {
+        key,
+        session: format!("{:032x}", now().as_nanos()),
+        execution_count: 0,
+        counter: 0,
+        iopub,
+        repl: Session::new(io::empty(), output.clone(), output.clone()),
+        output,
+    };
+
+    for request in receiver {
+        if !kernel.handle(request) {
+            break
+        }
+    }
+    Ok(())
+}
+
+
+/// A message received on the shell or control channel, along with the connection any reply should be sent to
+struct Request {
+    message: Message,
+    reply: Arc<Mutex<Connection>>,
+}
+
+/// A decoded Jupyter message
+struct Envelope {
+    identities: Vec<Vec<u8>>,
+    header: Value,
+    content: Value,
+}
+
+/// All output written by the session, which is taken and sent to the client once each cell has been executed
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> String {
+        String::from_utf8_lossy(&self.0.take()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The `iopub` channel, which broadcasts each message to every connected client
+struct Publisher {
+    subscribers: Arc<Mutex<Vec<Connection>>>,
+}
+
+impl Publisher {
+    fn bind(address: &String) -> Result<Publisher, String> {
+        let listener: TcpListener = bind(address)?;
+        let subscribers: Arc<Mutex<Vec<Connection>>> = Arc::new(Mutex::new(Vec::new()));
+        let accepted = subscribers.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Ok(connection) = Connection::accept(stream, SocketType::Pub) {
+                    accepted.lock().unwrap().push(connection);
+                }
+            }
+        });
+        Ok(Publisher { subscribers })
+    }
+
+    fn send(&self, message: &Message) {
+        // Subscribers which have disconnected are removed
+        self.subscribers.lock().unwrap().retain_mut(|connection| connection.send(message).is_ok());
+    }
+}
+
+struct Kernel {
+    key: Vec<u8>,
+    session: String,
+    execution_count: usize,
+    counter: usize,
+    iopub: Publisher,
+    output: SharedBuffer,
+    repl: Session<io::Empty, SharedBuffer, SharedBuffer>,
+}
+
+impl Kernel {
+
+    /// Handles a single request, returning `false` if the kernel should shut down
+    fn handle(&mut self, request: Request) -> bool {
+        let envelope: Envelope = match self.decode(request.message) {
+            Some(envelope) => envelope,
+            None => return true, // Messages with an invalid signature are ignored
+        };
+        let msg_type: String = envelope.header["msg_type"].as_str().unwrap_or("").to_string();
+
+        self.publish(&envelope, "status", json!({ "execution_state": "busy" }));
+        let mut running: bool = true;
+        let reply: Option<(&str, Value)> = match msg_type.as_str() {
+            "kernel_info_request" => Some(("kernel_info_reply", json!({
+                "status": "ok",
+                "protocol_version": PROTOCOL_VERSION,
+                "implementation": "cordy",
+                "implementation_version": SYS_VERSION,
+                "language_info": {
+                    "name": "cordy",
+                    "version": SYS_VERSION,
+                    "mimetype": "text/x-cordy",
+                    "file_extension": ".cor",
+                },
+                "banner": format!("Cordy v{}", SYS_VERSION),
+                "help_links": [],
+            }))),
+            "execute_request" => Some(("execute_reply", self.execute(&envelope))),
+            "is_complete_request" => Some(("is_complete_reply", json!({ "status": "unknown" }))),
+            "complete_request" => Some(("complete_reply", complete(&envelope.content))),
+            "inspect_request" => Some(("inspect_reply", json!({ "status": "ok", "found": false, "data": {}, "metadata": {} }))),
+            "history_request" => Some(("history_reply", json!({ "status": "ok", "history": [] }))),
+            "comm_info_request" => Some(("comm_info_reply", json!({ "status": "ok", "comms": {} }))),
+            "shutdown_request" => {
+                running = false;
+                Some(("shutdown_reply", json!({ "status": "ok", "restart": envelope.content["restart"].as_bool().unwrap_or(false) })))
+            },
+            _ => None, // Requests which are not supported are ignored, as the protocol allows
+        };
+        if let Some((reply_type, content)) = reply {
+            let message: Message = self.encode(&envelope, reply_type, content);
+            let _ = request.reply.lock().unwrap().send(&message);
+        }
+        self.publish(&envelope, "status", json!({ "execution_state": "idle" }));
+        running
+    }
+
+    /// Executes the code in an `execute_request`, publishes any output and the result, and returns the content of the reply
+    fn execute(&mut self, envelope: &Envelope) -> Value {
+        let code: &str = envelope.content["code"].as_str().unwrap_or("");
+        let silent: bool = envelope.content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.execution_count += 1;
+            self.publish(envelope, "execute_input", json!({ "code": code, "execution_count": self.execution_count }));
+        }
+
+        // Errors are returned as a name, i.e. `ValueError`, and the full error message
+        let result: Result<String, (String, String)> = match self.repl.push(code) {
+            Ok(value) => Ok(value.to_repr_str()),
+            Err(SessionError::Incomplete) => {
+                self.repl.discard();
+                Err((String::from("CompileError"), String::from("Unexpected end of input")))
+            },
+            Err(SessionError::Compile(errors)) => Err((String::from("CompileError"), errors.join("\n"))),
+            Err(SessionError::Runtime(error)) => Err((error_name(&error), error)),
+            Err(SessionError::Exit(code)) => Err((String::from("Exit"), format!("The program exited with code {}, restart the kernel to continue", code))),
+        };
+
+        let output: String = self.output.take();
+        if !output.is_empty() && !silent {
+            self.publish(envelope, "stream", json!({ "name": "stdout", "text": output }));
+        }
+
+        match result {
+            Ok(value) => {
+                if value != "nil" && !silent {
+                    self.publish(envelope, "execute_result", json!({
+                        "execution_count": self.execution_count,
+                        "data": { "text/plain": value },
+                        "metadata": {},
+                    }));
+                }
+                json!({ "status": "ok", "execution_count": self.execution_count, "payload": [], "user_expressions": {} })
+            },
+            Err((ename, error)) => {
+                let first: &str = error.lines().next().unwrap_or("");
+                let evalue: &str = first.strip_prefix(ename.as_str()).and_then(|e| e.strip_prefix(": ")).unwrap_or(first);
+                let traceback: Vec<&str> = error.lines().collect();
+                let content: Value = json!({ "ename": ename, "evalue": evalue, "traceback": traceback });
+                if !silent {
+                    self.publish(envelope, "error", content.clone());
+                }
+                let mut reply: Value = content;
+                reply["status"] = json!("error");
+                reply["execution_count"] = json!(self.execution_count);
+                reply
+            },
+        }
+    }
+
+    fn publish(&mut self, parent: &Envelope, msg_type: &str, content: Value) {
+        let mut message: Message = self.encode(parent, msg_type, content);
+        message.insert(0, msg_type.as_bytes().to_vec()); // The topic, which clients subscribe to
+        self.iopub.send(&message);
+    }
+
+    /// Decodes a message received from a client, returning `None` if it is malformed or its signature does not match
+    fn decode(&self, message: Message) -> Option<Envelope> {
+        let split: usize = message.iter().position(|frame| frame == DELIMITER)?;
+        let (identities, parts) = message.split_at(split);
+        if parts.len() < 6 {
+            return None
+        }
+        if !self.key.is_empty() {
+            let mut mac = self.mac();
+            for part in &parts[2..6] {
+                mac.update(part);
+            }
+            let signature: Vec<u8> = decode_hex(&parts[1])?;
+            mac.verify_slice(&signature).ok()?;
+        }
+        Some(Envelope {
+            identities: identities.to_vec(),
+            header: serde_json::from_slice(&parts[2]).ok()?,
+            content: serde_json::from_slice(&parts[5]).ok()?,
+        })
+    }
+
+    /// Encodes a new message of type `msg_type`, in response to `parent`
+    fn encode(&mut self, parent: &Envelope, msg_type: &str, content: Value) -> Message {
+        self.counter += 1;
+        let header: Value = json!({
+            "msg_id": format!("{}-{}", self.session, self.counter),
+            "session": self.session,
+            "username": "kernel",
+            "date": timestamp(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        });
+        let parts: [Vec<u8>; 4] = [header.to_string().into_bytes(), parent.header.to_string().into_bytes(), b"{}".to_vec(), content.to_string().into_bytes()];
+        let signature: String = match self.key.is_empty() {
+            true => String::new(),
+            false => {
+                let mut mac = self.mac();
+                for part in &parts {
+                    mac.update(part);
+                }
+                encode_hex(&mac.finalize().into_bytes())
+            },
+        };
+
+        let mut message: Message = parent.identities.clone();
+        message.push(DELIMITER.to_vec());
+        message.push(signature.into_bytes());
+        message.extend(parts);
+        message
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.key).unwrap()
+    }
+}
+
+
+fn bind(address: &String) -> Result<TcpListener, String> {
+    TcpListener::bind(address).map_err(|e| format!("Unable to bind to '{}': {}", address, e))
+}
+
+/// Accepts connections to the shell or control channel, and forwards each request to the kernel
+fn listen(address: &String, sender: mpsc::Sender<Request>) -> Result<(), String> {
+    let listener: TcpListener = bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            thread::spawn(move || -> io::Result<()> {
+                let mut connection: Connection = Connection::accept(stream, SocketType::Router)?;
+                let reply: Arc<Mutex<Connection>> = Arc::new(Mutex::new(connection.try_clone()?));
+                loop {
+                    let message: Message = connection.recv()?;
+                    if sender.send(Request { message, reply: reply.clone() }).is_err() {
+                        return Ok(()) // The kernel has shut down
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Accepts connections to the stdin channel. Reading input from the client is not supported, so no requests are ever sent on it.
+fn listen_stdin(address: &String) -> Result<(), String> {
+    let listener: TcpListener = bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || -> io::Result<()> {
+                let mut connection: Connection = Connection::accept(stream, SocketType::Router)?;
+                loop {
+                    connection.recv()?;
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Accepts connections to the heartbeat channel, which echoes back every message it receives
+fn listen_heartbeat(address: &String) -> Result<(), String> {
+    let listener: TcpListener = bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || -> io::Result<()> {
+                let mut connection: Connection = Connection::accept(stream, SocketType::Rep)?;
+                loop {
+                    let message: Message = connection.recv()?;
+                    connection.send(&message)?;
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+
+fn now() -> std::time::Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Returns the current time as an ISO 8601 timestamp, in UTC, i.e. `2023-01-31T12:30:00.000000Z`
+fn timestamp() -> String {
+    let now = now();
+    let seconds: u64 = now.as_secs();
+    let (hour, minute, second) = (seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+
+    // Converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z: i64 = (seconds / 86400) as i64 + 719468;
+    let era: i64 = z.div_euclid(146097);
+    let doe: i64 = z - era * 146097;
+    let yoe: i64 = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: i64 = (5 * doy + 2) / 153;
+    let day: i64 = doy - (153 * mp + 2) / 5 + 1;
+    let month: i64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: i64 = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z", year, month, day, hour, minute, second, now.subsec_micros())
+}
+
+/// Completes the identifier ending at the cursor of a `complete_request`, with the names of native functions, as listed by `help()`
+///
+/// Note that `cursor_pos` is measured in unicode code points, not bytes.
+fn complete(content: &Value) -> Value {
+    let code: Vec<char> = content["code"].as_str().unwrap_or("").chars().collect();
+    let cursor_end: usize = (content["cursor_pos"].as_u64().unwrap_or(0) as usize).min(code.len());
+    let cursor_start: usize = code[..cursor_end].iter().rposition(|c| !c.is_alphanumeric() && *c != '_').map_or(0, |i| i + 1);
+    let prefix: String = code[cursor_start..cursor_end].iter().collect();
+    let matches: Vec<&str> = match prefix.is_empty() {
+        true => Vec::new(),
+        false => NativeFunction::all()
+            .filter(|f| !f.is_hidden() && f.name().starts_with(&prefix))
+            .map(|f| f.name())
+            .collect(),
+    };
+    json!({
+        "status": "ok",
+        "matches": matches,
+        "cursor_start": cursor_start,
+        "cursor_end": cursor_end,
+        "metadata": {},
+    })
+}
+
+/// Runtime errors are formatted as `ValueError: message`, but may not have a name, in which case they are just an `Error`
+fn error_name(error: &str) -> String {
+    match error.split_once(':') {
+        Some((ename, _)) if !ename.is_empty() && !ename.contains(char::is_whitespace) => String::from(ename),
+        _ => String::from("Error"),
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(text: &[u8]) -> Option<Vec<u8>> {
+    let text: &str = std::str::from_utf8(text).ok()?;
+    if !text.len().is_multiple_of(2) {
+        return None
+    }
+    (0..text.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,141 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+
+/// A multipart message, where each element is a single frame
+pub type Message = Vec<Vec<u8>>;
+
+/// The socket types used by a kernel. Jupyter clients connect to each of these with the matching peer type, i.e. `DEALER` to a `ROUTER`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SocketType {
+    Router, Pub, Rep
+}
+
+impl SocketType {
+    fn name(self) -> &'static str {
+        match self {
+            SocketType::Router => "ROUTER",
+            SocketType::Pub => "PUB",
+            SocketType::Rep => "REP",
+        }
+    }
+}
+
+const FLAG_MORE: u8 = 0b001;
+const FLAG_LONG: u8 = 0b010;
+const FLAG_COMMAND: u8 = 0b100;
+
+/// A single connection from a peer, which speaks a minimal subset of ZMTP 3.0 (the ZeroMQ wire protocol), using the `NULL` security mechanism.
+///
+/// This is just enough to act as the server side of each of the sockets a Jupyter client connects to. Since every connection is handled separately,
+/// a `ROUTER` does not need to track peer identities, and replies are just written back to the connection the request was read from.
+pub struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+
+    /// Performs the ZMTP handshake over a newly accepted stream, as a socket of type `ty`.
+    pub fn accept(mut stream: TcpStream, ty: SocketType) -> io::Result<Connection> {
+        stream.set_nodelay(true)?;
+
+        // Greeting: signature, version 3.0, the `NULL` mechanism, and `as-server` = 0, padded to 64 bytes
+        let mut greeting: [u8; 64] = [0; 64];
+        greeting[0] = 0xFF;
+        greeting[9] = 0x7F;
+        greeting[10] = 3;
+        greeting[12..16].copy_from_slice(b"NULL");
+        stream.write_all(&greeting)?;
+
+        let mut peer: [u8; 64] = [0; 64];
+        stream.read_exact(&mut peer)?;
+        if peer[0] != 0xFF || peer[9] != 0x7F || peer[10] < 3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported ZMTP version"))
+        }
+        if &peer[12..16] != b"NULL" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported ZMTP security mechanism"))
+        }
+
+        let mut connection = Connection { stream };
+
+        // Each side sends a `READY` command, with the socket type as metadata. The peer's metadata is not needed.
+        let mut ready: Vec<u8> = Vec::new();
+        ready.push(5);
+        ready.extend_from_slice(b"READY");
+        ready.push(11);
+        ready.extend_from_slice(b"Socket-Type");
+        ready.extend_from_slice(&(ty.name().len() as u32).to_be_bytes());
+        ready.extend_from_slice(ty.name().as_bytes());
+        connection.write_frame(&ready, FLAG_COMMAND)?;
+        loop {
+            let (flags, _) = connection.read_frame()?;
+            if flags & FLAG_COMMAND != 0 {
+                break
+            }
+        }
+        Ok(connection)
+    }
+
+    pub fn try_clone(&self) -> io::Result<Connection> {
+        Ok(Connection { stream: self.stream.try_clone()? })
+    }
+
+    /// Reads the next message from the peer. Any commands, i.e. subscriptions from a `SUB` peer, are ignored.
+    pub fn recv(&mut self) -> io::Result<Message> {
+        let mut message: Message = Vec::new();
+        loop {
+            let (flags, frame) = self.read_frame()?;
+            if flags & FLAG_COMMAND != 0 {
+                continue
+            }
+            message.push(frame);
+            if flags & FLAG_MORE == 0 {
+                return Ok(message)
+            }
+        }
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        for (i, frame) in message.iter().enumerate() {
+            let more: u8 = if i + 1 < message.len() { FLAG_MORE } else { 0 };
+            encode_frame(&mut buffer, frame, more);
+        }
+        self.stream.write_all(&buffer)
+    }
+
+    fn read_frame(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut flags: [u8; 1] = [0];
+        self.stream.read_exact(&mut flags)?;
+        let size: usize = if flags[0] & FLAG_LONG != 0 {
+            let mut size: [u8; 8] = [0; 8];
+            self.stream.read_exact(&mut size)?;
+            u64::from_be_bytes(size) as usize
+        } else {
+            let mut size: [u8; 1] = [0];
+            self.stream.read_exact(&mut size)?;
+            size[0] as usize
+        };
+        let mut frame: Vec<u8> = vec![0; size];
+        self.stream.read_exact(&mut frame)?;
+        Ok((flags[0], frame))
+    }
+
+    fn write_frame(&mut self, frame: &[u8], flags: u8) -> io::Result<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        encode_frame(&mut buffer, frame, flags);
+        self.stream.write_all(&buffer)
+    }
+}
+
+fn encode_frame(buffer: &mut Vec<u8>, frame: &[u8], flags: u8) {
+    if frame.len() > u8::MAX as usize {
+        buffer.push(flags | FLAG_LONG);
+        buffer.extend_from_slice(&(frame.len() as u64).to_be_bytes());
+    } else {
+        buffer.push(flags);
+        buffer.push(frame.len() as u8);
+    }
+    buffer.extend_from_slice(frame);
+}
@@ -1,16 +1,38 @@
 use std::{fs, io};
-use std::io::Write;
+use std::io::{BufReader, IsTerminal, LineWriter, Write};
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
 use rustyline::{DefaultEditor, Editor};
 use rustyline::error::ReadlineError;
 
-use cordy_sys::{compiler, repl, SourceView, SYS_VERSION};
-use cordy_sys::compiler::CompileResult;
+use cordy_sys::{bench, compiler, repl, SourceView, SYS_VERSION};
+use cordy_sys::bench::{Baseline, Benchmark};
+use cordy_sys::compiler::{CompileFlags, CompileResult, DisassemblyOptions};
 use cordy_sys::repl::{Reader, ReadResult};
-use cordy_sys::vm::{ExitType, VirtualMachine};
+use cordy_sys::vm::{ExitType, Signal, Terminal, TraceEvents, TraceOptions, Tracer, VirtualInterface, VirtualMachine};
+
+
+/// In `--bench` mode, the number of times each benchmark is invoked before it is measured.
+const BENCH_WARMUP: usize = 3;
+/// In `--bench` mode, the number of times each benchmark is measured.
+const BENCH_SAMPLES: usize = 20;
+
+mod bundle;
+
+#[cfg(feature = "kernel")]
+mod kernel;
 
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
+
+    // A bundled executable runs its script, and passes every argument on to it
+    if let Some(bundle) = bundle::read() {
+        if let Err(e) = run_bundle(bundle, args) {
+            eprintln!("{}", e)
+        }
+        return
+    }
+
     let mut options: Options = match parse_args(args) {
         Some(args) => args,
         None => return
@@ -26,37 +48,56 @@ fn main() {
         },
         _ => {}
     }
-    let result = match options.file.take() {
-        Some(name) => run_main(name, options),
-        None => run_repl()
+    let result = match (options.kernel.take(), options.file.take()) {
+        (Some(path), _) => run_kernel(path),
+        (None, Some(name)) => run_main(name, options),
+        (None, None) => run_repl()
     };
-    match result {
-        Ok(()) => {},
-        Err(e) => eprintln!("{}", e)
+    if let Err(e) = result {
+        // Any error reaching this point was uncaught, so the process must exit with a non-zero status
+        eprintln!("{}", e);
+        io::stdout().flush().unwrap();
+        std::process::exit(1)
     }
 }
 
 fn parse_args(args: Vec<String>) -> Option<Options> {
     let mut iter = args.into_iter();
-    let mut options: Options = Options {
-        file: None,
-        args: Vec::new(),
-        mode: Mode::Default,
-        optimize: false,
-        no_line_numbers: false
-    };
+    let mut options: Options = Options::default();
 
     if iter.next().is_none() {
         panic!("Unexpected first argument");
     }
 
-    for arg in iter.by_ref() {
+    while let Some(arg) = iter.next() {
         match arg.as_str() {
             "-h" | "--help" => options.mode.set(Mode::Help).ok()?,
             "-v" | "--version" => options.mode.set(Mode::Version).ok()?,
             "-d" | "--disassembly" => options.mode.set(Mode::Disassembly).ok()?,
+            "-b" | "--bench" => options.mode.set(Mode::Bench).ok()?,
+            "--emit-asm" => options.mode.set(Mode::Assembly).ok()?,
+            "--run-asm" => options.run_asm = true,
+            "--baseline" => options.baseline = Some(iter.next()?),
+            "--save-baseline" => options.save_baseline = Some(iter.next()?),
+            "--coverage" => options.coverage = true,
+            "--lcov" => options.lcov = Some(iter.next()?),
+            "--trace-depth" => options.trace.depth = Some(iter.next()?.parse().ok()?),
+            "--trace-repeat" => options.trace.repeat_limit = iter.next()?.parse().ok()?,
+            "--trace-reverse" => options.trace.reverse = true,
+            "--verbose-traces" => options.trace.verbose = true,
+            "--trace-file" => options.trace_file = Some(iter.next()?),
+            a if a.starts_with("--trace=") => options.trace_events = Some(TraceEvents::parse(&a["--trace=".len()..])?),
+            "--kernel" => options.kernel = Some(iter.next()?),
+            "--bundle" => {
+                options.mode.set(Mode::Bundle).ok()?;
+                options.bundle = Some(iter.next()?)
+            },
             "-o" | "--optimize" => options.optimize = true,
-            "--no-line-numbers" => options.no_line_numbers = true,
+            "-p" | "--print-last" => options.print_last = true,
+            "--per-iteration-loops" => options.per_iteration_loops = true,
+            "--no-line-numbers" => options.disassembly.line_numbers = false,
+            "--annotate" => options.disassembly.annotate = true,
+            "--verbose-disassembly" => options.disassembly.verbose = true,
             a => {
                 options.file = Some(String::from(a));
                 break
@@ -76,7 +117,26 @@ fn print_help() {
     println!("  -v --version      : Print the version, then exit.");
     println!("  -d --disassembly  : Dump the disassembly view. Does nothing in REPL mode.");
     println!("  -o --optimize     : Enables compiler optimizations and transformations.");
+    println!("  -p --print-last   : Prints the value of the last expression in the program, if it is not nil, once it reaches the end.");
+    println!("  --per-iteration-loops : Binds the variables of a 'for' loop once per iteration, even when it ends with 'continue' or 'break'. This will become the default.");
     println!("  --no-line-numbers : In disassembly view, omits the leading '0001' style line numbers");
+    println!("  --annotate        : In disassembly view, shows the source code each instruction was emitted from.");
+    println!("  --verbose-disassembly : In disassembly view, also shows constants, fields, and functions, and labels jump targets.");
+    println!("  --emit-asm        : Prints the program in assembly form, which can be run with --run-asm.");
+    println!("  --run-asm         : Reads <file> as assembly, as produced by --emit-asm, instead of source code.");
+    println!("  -b --bench        : Runs each function named 'bench_*' repeatedly, and reports how long each takes.");
+    println!("  --baseline <file> : In bench mode, compares each benchmark against the results saved in <file>.");
+    println!("  --save-baseline <file> : In bench mode, saves the results to <file>, for use with --baseline.");
+    println!("  --coverage        : After the program exits, reports which lines were not executed.");
+    println!("  --lcov <file>     : Writes which lines were executed to <file>, in the lcov format.");
+    println!("  --trace-depth <n> : In stack traces, shows at most <n> frames.");
+    println!("  --trace-repeat <n> : In stack traces, shows a frame repeated by recursion at most <n> more times (default 3).");
+    println!("  --trace-reverse   : In stack traces, shows the outermost frame first.");
+    println!("  --verbose-traces  : In stack traces, shows the arguments of each function.");
+    println!("  --trace=<events>  : Traces execution to stderr, as one JSON object per line. <events> is any of 'ops', 'stack', and 'calls', i.e. --trace=ops,calls");
+    println!("  --trace-file <file> : Writes the trace from --trace to <file>, instead of stderr.");
+    println!("  --bundle <output> : Writes a standalone executable to <output>, which runs the program when invoked.");
+    println!("  --kernel <file>   : Runs as a Jupyter kernel, using the connection file <file>. Requires the 'kernel' feature.");
 }
 
 fn print_version() {
@@ -85,36 +145,146 @@ fn print_version() {
 
 fn run_main(name: String, options: Options) -> Result<(), String> {
     let text: String = fs::read_to_string(&name).map_err(|_| format!("Unable to read file '{}'", name))?;
+    run_source(name, text, options)
+}
+
+fn run_source(name: String, text: String, options: Options) -> Result<(), String> {
     let view: SourceView = SourceView::new(name, text);
-    let compiled: CompileResult = compiler::compile(options.optimize, &view).map_err(|e| e.join("\n"))?;
+    let flags: CompileFlags = CompileFlags { print_last: options.print_last, per_iteration_loops: options.per_iteration_loops, ..CompileFlags::default() };
+    let compiled: CompileResult = match options.mode {
+        _ if options.run_asm => compiler::assemble(&view),
+        Mode::Bench => compiler::compile_with_flags(options.optimize, CompileFlags { retain_globals: true, print_last: false, ..flags }, &view),
+        _ => compiler::compile_with_flags(options.optimize, flags, &view),
+    }.map_err(|e| e.join("\n"))?;
 
     match options.mode {
         Mode::Disassembly => {
-            for line in compiled.disassemble(&view, !options.no_line_numbers) {
+            for line in compiled.disassemble(&view, &options.disassembly) {
+                println!("{}", line);
+            }
+            Ok(())
+        },
+        Mode::Assembly => {
+            for line in compiled.assembly() {
                 println!("{}", line);
             }
             Ok(())
         },
-        Mode::Default => run_vm(compiled, options.args, view),
+        Mode::Default => run_vm(compiled, options, view),
+        Mode::Bench => run_bench(compiled, options, view),
+        Mode::Bundle if options.run_asm => Err(String::from("Cannot use --bundle with --run-asm")),
+        Mode::Bundle => {
+            let output: String = options.bundle.unwrap();
+            bundle::write(&bundle::Bundle { name: view.name().clone(), text: view.text().clone(), optimize: options.optimize }, &output)
+        },
         _ => panic!("Unsupported mode"),
     }
 }
 
-fn run_vm(compiled: CompileResult, program_args: Vec<String>, view: SourceView) -> Result<(), String> {
+fn run_vm(compiled: CompileResult, options: Options, view: SourceView) -> Result<(), String> {
 
     let stdin = io::stdin().lock();
     let stdout = io::stdout();
-    let mut vm = VirtualMachine::new(compiled, view, stdin, stdout, program_args);
+    let stderr = io::stderr();
+    let mut vm = VirtualMachine::new(compiled, view, stdin, stdout, stderr, options.args).with_trace_options(options.trace);
+    if let Some(terminal) = ConsoleTerminal::detect() {
+        vm = vm.with_terminal(terminal);
+    }
+    if options.coverage || options.lcov.is_some() {
+        vm = vm.with_coverage();
+    }
+    if options.trace_events.is_some() || options.trace_file.is_some() {
+        let events: TraceEvents = options.trace_events.unwrap_or(TraceEvents { ops: true, ..TraceEvents::default() });
+        let sink: Box<dyn Write> = match &options.trace_file {
+            // Each event is written as soon as it is complete, as the program may exit without flushing
+            Some(path) => Box::new(LineWriter::new(fs::File::create(path).map_err(|_| format!("Unable to write file '{}'", path))?)),
+            None => Box::new(io::stderr()),
+        };
+        vm = vm.with_tracer(Tracer::new(events, sink));
+    }
 
-    match vm.run_until_completion() {
+    // Signals are only intercepted once the program registers a handler, so until then they have their default behavior
+    let signals = vm.signals();
+    vm = vm.with_signal_hook(Box::new(move |signal| {
+        let signals = signals.clone();
+        let signum: i32 = match signal {
+            Signal::Int => signal_hook::consts::SIGINT,
+            Signal::Term => signal_hook::consts::SIGTERM,
+        };
+        // Safety: the handler only sets an atomic flag, which is async-signal-safe
+        let _ = unsafe { signal_hook::low_level::register(signum, move || signals.raise(signal)) };
+    }));
+
+    let exit: ExitType = vm.run_until_completion();
+    if let ExitType::Value(value) = &exit {
+        if !value.is_nil() {
+            vm.println(value.to_str());
+        }
+    }
+    let exit: ExitType = vm.run_exit_handlers(exit);
+    if let Some(coverage) = vm.coverage() {
+        if options.coverage {
+            for line in coverage.report() {
+                eprintln!("{}", line);
+            }
+        }
+        if let Some(path) = options.lcov {
+            fs::write(&path, coverage.to_lcov()).map_err(|_| format!("Unable to write file '{}'", path))?;
+        }
+    }
+    match exit {
         ExitType::Error(error) => Err(vm.view().format(&error)),
+        ExitType::Exit(code) if code != 0 => {
+            // `process::exit()` does not run destructors, so make sure any buffered output is written first
+            io::stdout().flush().unwrap();
+            std::process::exit(code)
+        },
         _ => Ok(())
     }
 }
 
+fn run_bench(compiled: CompileResult, options: Options, view: SourceView) -> Result<(), String> {
+    let baseline: Option<Baseline> = match &options.baseline {
+        Some(path) => {
+            let text: String = fs::read_to_string(path).map_err(|_| format!("Unable to read file '{}'", path))?;
+            Some(bench::parse_baseline(text.as_str()).map_err(|e| format!("Invalid baseline '{}': {}", path, e))?)
+        },
+        None => None,
+    };
+
+    let mut vm = VirtualMachine::new(compiled, view, io::stdin().lock(), io::stdout(), io::stderr(), options.args).with_trace_options(options.trace);
+    let benchmarks: Vec<Benchmark> = bench::run(&mut vm, BENCH_WARMUP, BENCH_SAMPLES)?;
+    for line in bench::report(&benchmarks, baseline.as_ref()) {
+        println!("{}", line);
+    }
+    if let Some(path) = options.save_baseline {
+        fs::write(&path, bench::to_json(&benchmarks)).map_err(|_| format!("Unable to write file '{}'", path))?;
+    }
+    Ok(())
+}
+
+fn run_bundle(bundle: bundle::Bundle, args: Vec<String>) -> Result<(), String> {
+    let options: Options = Options {
+        args: args.into_iter().skip(1).collect(),
+        optimize: bundle.optimize,
+        ..Options::default()
+    };
+    run_source(bundle.name, bundle.text, options)
+}
+
+#[cfg(feature = "kernel")]
+fn run_kernel(path: String) -> Result<(), String> {
+    kernel::run(path)
+}
+
+#[cfg(not(feature = "kernel"))]
+fn run_kernel(_: String) -> Result<(), String> {
+    Err(String::from("Unable to run as a Jupyter kernel, as cordy was built without the 'kernel' feature"))
+}
+
 pub fn run_repl() -> Result<(), String> {
     println!("Welcome to Cordy v{}! (exit with 'exit' or Ctrl-C)", SYS_VERSION);
-    repl::run(EditorRepl { editor: Editor::new().unwrap() }, io::stdout(), false)
+    repl::run(EditorRepl { editor: Editor::new().unwrap() }, BufReader::new(io::stdin()), io::stdout(), io::stderr(), ConsoleTerminal::detect(), false)
 }
 
 
@@ -136,21 +306,113 @@ impl Reader for EditorRepl {
     }
 }
 
+/// The terminal used for interactive natives such as `read_key()`, which is only available when both standard input and output are attached to a terminal.
+struct ConsoleTerminal;
+
+impl ConsoleTerminal {
+    fn detect() -> Option<Box<dyn Terminal>> {
+        match io::stdin().is_terminal() && io::stdout().is_terminal() {
+            true => Some(Box::new(ConsoleTerminal)),
+            false => None,
+        }
+    }
+}
+
+impl Terminal for ConsoleTerminal {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        crossterm::terminal::size()
+    }
+
+    fn read_key(&mut self) -> io::Result<String> {
+        io::stdout().flush()?;
+        crossterm::terminal::enable_raw_mode()?;
+        let key = loop {
+            match crossterm::event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => break Ok(key),
+                Ok(_) => {},
+                Err(e) => break Err(e),
+            }
+        };
+        crossterm::terminal::disable_raw_mode()?;
+        let key = key?;
+
+        let name: String = match key.code {
+            KeyCode::Char(c) => String::from(c),
+            KeyCode::F(n) => format!("f{}", n),
+            code => String::from(match code {
+                KeyCode::Up => "up",
+                KeyCode::Down => "down",
+                KeyCode::Left => "left",
+                KeyCode::Right => "right",
+                KeyCode::Enter => "enter",
+                KeyCode::Esc => "escape",
+                KeyCode::Backspace => "backspace",
+                KeyCode::Tab | KeyCode::BackTab => "tab",
+                KeyCode::Delete => "delete",
+                KeyCode::Home => "home",
+                KeyCode::End => "end",
+                KeyCode::PageUp => "page_up",
+                KeyCode::PageDown => "page_down",
+                _ => "unknown",
+            }),
+        };
+
+        // Shift is already applied to printable characters, so it is only reported for other keys
+        let mut prefix: String = String::new();
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            prefix.push_str("ctrl+");
+        }
+        if key.modifiers.contains(KeyModifiers::ALT) {
+            prefix.push_str("alt+");
+        }
+        if (key.modifiers.contains(KeyModifiers::SHIFT) && !matches!(key.code, KeyCode::Char(_))) || key.code == KeyCode::BackTab {
+            prefix.push_str("shift+");
+        }
+        Ok(prefix + name.as_str())
+    }
+}
+
+#[derive(Default)]
 struct Options {
     file: Option<String>,
     args: Vec<String>,
     mode: Mode,
     optimize: bool,
-    no_line_numbers: bool,
+    /// If true, the value of the last expression in the program is printed once it reaches the end
+    print_last: bool,
+    /// If true, the variables of a `for` loop are bound per iteration, including those which end with `continue` or `break`
+    per_iteration_loops: bool,
+    /// If true, the file is read as assembly, instead of source code
+    run_asm: bool,
+    /// How the disassembly view is rendered
+    disassembly: DisassemblyOptions,
+    /// In bench mode, the file to compare results against
+    baseline: Option<String>,
+    /// In bench mode, the file to save results to
+    save_baseline: Option<String>,
+    /// If true, a report of lines which were not executed is printed once the program exits
+    coverage: bool,
+    /// The file to write line coverage to, in the lcov format
+    lcov: Option<String>,
+    /// How stack traces of runtime errors are rendered
+    trace: TraceOptions,
+    /// If present, which events the execution of the program is traced with
+    trace_events: Option<TraceEvents>,
+    /// The file to write the execution trace to, instead of stderr
+    trace_file: Option<String>,
+    /// If present, runs as a Jupyter kernel with this connection file, instead of running a program
+    kernel: Option<String>,
+    /// In bundle mode, the path of the executable to write
+    bundle: Option<String>,
 }
 
-#[derive(Eq, PartialEq)]
-enum Mode { Default, Help, Version, Disassembly }
+#[derive(Eq, PartialEq, Default)]
+enum Mode { #[default] Default, Help, Version, Disassembly, Assembly, Bench, Bundle }
 
 impl Mode {
     fn set(&mut self, new: Mode) -> Result<(), String> {
         if *self != Mode::Default {
-            Err(String::from("Must only specify one of --help, --version, or --disassembly"))
+            Err(String::from("Must only specify one of --help, --version, --disassembly, --emit-asm, --bench, or --bundle"))
         } else {
             *self = new;
             Ok(())
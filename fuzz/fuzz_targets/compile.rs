@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use cordy_sys::{compiler, SourceView};
+
+
+// Compiles arbitrary source code, both with and without optimizations. Any panic is reported as a crash.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let view: SourceView = SourceView::new(String::from("<fuzz>"), String::from(text));
+        let _ = compiler::compile(false, &view);
+        let _ = compiler::compile(true, &view);
+    }
+});
@@ -0,0 +1,26 @@
+#![no_main]
+
+use std::io;
+use libfuzzer_sys::fuzz_target;
+
+use cordy_sys::{compiler, SourceView};
+use cordy_sys::vm::{Capability, VirtualMachine};
+
+
+/// The maximum number of instructions each program may execute, so that programs which never terminate are still fuzzed quickly.
+const EXECUTION_LIMIT: u64 = 100_000;
+
+// Compiles and runs arbitrary source code, where any panic is reported as a crash. Programs are denied any access to the file system, network, or other processes.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let view: SourceView = SourceView::new(String::from("<fuzz>"), String::from(text));
+        if let Ok(compiled) = compiler::compile(true, &view) {
+            let mut vm = VirtualMachine::new(compiled, view, &b""[..], io::sink(), io::sink(), vec![])
+                .with_execution_limit(EXECUTION_LIMIT)
+                .deny(Capability::Fs)
+                .deny(Capability::Net)
+                .deny(Capability::Process);
+            let _ = vm.run_until_completion();
+        }
+    }
+});